@@ -0,0 +1,92 @@
+#!/usr/bin/env node
+// Verify the WASI target: run `_start` of a command module under node:wasi and
+// check stdout, environment and preopened-file access, and the exit code.
+// Usage: node scripts/test-wasi.mjs <program.wasm>
+
+import { WASI } from "node:wasi";
+import { closeSync, mkdtempSync, openSync, readFileSync, rmSync, writeFileSync } from "node:fs";
+import { tmpdir } from "node:os";
+import { join } from "node:path";
+
+const wasmPath = process.argv[2] ?? "js-out/wasi/program.wasm";
+const mod = new WebAssembly.Module(readFileSync(wasmPath));
+
+let fail = 0;
+
+function checkContract(label, ok) {
+  if (ok) {
+    console.log(`  ${label}  OK`);
+  } else {
+    console.log(`  ${label}  FAIL`);
+    fail++;
+  }
+}
+
+function check(label, expected, got) {
+  if (got === expected) {
+    console.log(`  ${label} = ${JSON.stringify(got)}  OK`);
+  } else {
+    console.log(`  ${label} = ${JSON.stringify(got)}  FAIL (expected ${JSON.stringify(expected)})`);
+    fail++;
+  }
+}
+
+console.log("=== WASI target test (node:wasi) ===");
+
+const imports = WebAssembly.Module.imports(mod);
+checkContract(
+  "imports only wasi_snapshot_preview1",
+  imports.length > 0 && imports.every((item) => item.module === "wasi_snapshot_preview1")
+);
+const exports = WebAssembly.Module.exports(mod);
+checkContract(
+  "exports _start and memory",
+  exports.some((item) => item.kind === "function" && item.name === "_start") &&
+    exports.some((item) => item.kind === "memory" && item.name === "memory")
+);
+
+// The sandbox directory is the only preopen, so relative paths resolve inside it.
+const sandbox = mkdtempSync(join(tmpdir(), "calcit-wasi-"));
+writeFileSync(join(sandbox, "input.txt"), "file-body");
+const stdoutPath = join(sandbox, "stdout.txt");
+const stdoutFd = openSync(stdoutPath, "w");
+
+const wasi = new WASI({
+  version: "preview1",
+  args: ["program.wasm"],
+  env: { CALCIT_WASI_GREETING: "hi-from-host" },
+  preopens: { ".": sandbox },
+  stdout: stdoutFd,
+  returnOnExit: true,
+});
+const inst = new WebAssembly.Instance(mod, wasi.getImportObject());
+const exitCode = wasi.start(inst);
+closeSync(stdoutFd);
+const lines = readFileSync(stdoutPath, "utf-8").split("\n");
+rmSync(sandbox, { recursive: true, force: true });
+
+check("quit! exit code", 3, exitCode);
+const expected = [
+  ["println string", "hello-wasi"],
+  ["integer", "3"],
+  ["fraction", "0.25"],
+  ["negative fraction", "-1.5"],
+  ["pow 3 4", "81"],
+  ["pow 2 -1", "0.5"],
+  ["pow 2 0.5", "1.414213562"],
+  ["sin 0", "0"],
+  ["cos 0", "1"],
+  ["&get-env", "hi-from-host"],
+  ["&get-env default", "fallback"],
+  ["read-file", "file-body"],
+  ["cpu-time >= 0", "1"],
+];
+expected.forEach(([label, value], i) => check(label, value, lines[i]));
+check("nothing printed after quit!", "", lines.slice(expected.length).join("\n"));
+
+if (fail > 0) {
+  console.log(`WASI verification FAILED (${fail} failures)`);
+  process.exit(1);
+}
+
+console.log("=== All WASI checks passed ===");
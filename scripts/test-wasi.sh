@@ -0,0 +1,27 @@
+#!/usr/bin/env bash
+# Verify the WASI target: generate a command module and run it under Node's WASI runtime.
+# Usage: bash scripts/test-wasi.sh
+# Set CR_WASM_BIN to override the cr-wasm binary path (default: release then debug build).
+set -euo pipefail
+
+if [[ -n "${CR_WASM_BIN:-}" ]]; then
+  BIN="$CR_WASM_BIN"
+elif [[ -x ./target/release/cr-wasm ]]; then
+  BIN="./target/release/cr-wasm"
+elif [[ -x ./target/debug/cr-wasm ]]; then
+  BIN="./target/debug/cr-wasm"
+else
+  BIN=""
+fi
+ENTRY="calcit/test-wasi.cirru"
+OUT="js-out/wasi"
+
+# Step 1: generate the .wasm command module
+if [[ -n "$BIN" ]]; then
+  "$BIN" --target wasi --emit-path "$OUT" "$ENTRY" 2>&1
+else
+  bash scripts/cargo-with-sdk.sh run --bin cr-wasm -- --target wasi --emit-path "$OUT" "$ENTRY" 2>&1
+fi
+
+# Step 2: run `_start` with node:wasi and check its output
+node --no-warnings scripts/test-wasi.mjs "$OUT/program.wasm"
@@ -196,15 +196,19 @@ function findStaticStringTag() {
 }
 
 // Allocate a new heap string and return its pointer
-// String layout: [heap_ptr][ptr+0][ptr+8]
+// String layout: [ptr-8][ptr-4][ptr+0][ptr+8]
 //   [HEAP_MAGIC:i32][str_tag_id:i32][byte_len:f64][bytes:u8[]]
 // After allocation, caller writes byte_len at ptr and bytes at ptr+8
 function allocString(byteLen) {
-  const mem = new DataView(inst.exports.memory.buffer);
-  const heapPtr = inst.exports.__heap_ptr.value;
   if (!Number.isSafeInteger(byteLen) || byteLen < 0) {
     throw new RangeError(`invalid string byte length: ${byteLen}`);
   }
+  // Modules with a heap manager own the block layout; let them allocate.
+  if (inst.exports.__alloc) {
+    return inst.exports.__alloc(8 + byteLen, STRING_TAG);
+  }
+  const mem = new DataView(inst.exports.memory.buffer);
+  const heapPtr = inst.exports.__heap_ptr.value;
   // Total: 8-byte header plus the 8-byte length field and data, aligned to 8.
   const payloadSize = 8 + byteLen; // byte_len (8) + bytes
   const paddedPayload = (payloadSize + 7) & ~7;
@@ -317,6 +321,15 @@ check("test-call-spread-rest()", 15, e["test-call-spread-rest"]);
 check("test-enum-sum()", 30, e["test-enum-sum"]);
 check("test-enum-count()", 3, e["test-enum-count"]);
 
+// --- Closure tests ---
+check("test-closure-adder(3,4)", 7, e["test-closure-adder"], 3, 4);
+check("test-closure-apply(3,2)", 18, e["test-closure-apply"], 3, 2);
+check("test-closure-compose(5)", 16, e["test-closure-compose"], 5);
+check("test-closure-let-value(4)", 8, e["test-closure-let-value"], 4);
+check("test-closure-map(10)", 13, e["test-closure-map"], 10);
+check("test-closure-type()", 1, e["test-closure-type"]);
+check("test-closure-churn(200000)", 20000100000, e["test-closure-churn"], 200000);
+
 // --- Bitwise tests ---
 check("test-bit-and(0xFF,0x0F)", 0x0f, e["test-bit-and"], 0xff, 0x0f);
 check("test-bit-or(0xF0,0x0F)", 0xff, e["test-bit-or"], 0xf0, 0x0f);
@@ -523,14 +536,14 @@ check("test-display-by-bin()", 7, e["test-display-by-bin"]); // 17 in binary = "
 check("test-display-by-hex()", 4, e["test-display-by-hex"]); // 17 in hex = "0x11" (len 4)
 
 // --- __str_new FFI test (JS → WASM string passing) ---
-// Protocol: read heap top, write bytes at top+16 (zero-copy), call __str_new(top+16, len)
+// Protocol: read heap top, write bytes at top+24 (zero-copy), call __str_new(top+24, len)
 {
   const mem = inst.exports.memory.buffer;
   const heapTop = inst.exports.__heap_ptr.value;
   const encoder = new TextEncoder();
   const bytes = encoder.encode("world");
-  new Uint8Array(mem, heapTop + 16, bytes.length).set(bytes);
-  const strPtr = inst.exports.__str_new(heapTop + 16, bytes.length);
+  new Uint8Array(mem, heapTop + 24, bytes.length).set(bytes);
+  const strPtr = inst.exports.__str_new(heapTop + 24, bytes.length);
   // Decode: byte_len at strPtr, content at strPtr+8
   const view = new DataView(mem);
   const byteLen = view.getFloat64(strPtr | 0, true);
@@ -564,6 +577,104 @@ if (wasmLog[0] === 42) {
   fail++;
 }
 
+// --- Heap GC: millions of short-lived lists must not exhaust linear memory ---
+{
+  const before = e.__gc_collections();
+  check("test-gc-churn(2000000)", 2000000, e["test-gc-churn"], 2000000);
+  check("test-gc-grow()", 1000000, e["test-gc-grow"]);
+  checkStr("test-gc-keeps-live()", "kept", e["test-gc-keeps-live"], 300000);
+  const collections = e.__gc_collections() - before;
+  const memBytes = inst.exports.memory.buffer.byteLength;
+  if (collections > 0 && memBytes < 64 * 1024 * 1024) {
+    console.log(`  heap gc: ${collections} collections, ${memBytes >> 20}MB memory  OK`);
+  } else {
+    console.log(`  heap gc: ${collections} collections, ${memBytes >> 20}MB memory  FAIL`);
+    fail++;
+  }
+}
+{
+  // The loop runs below a non-tail call and after an export that trapped.
+  try {
+    e["test-gc-trap"]();
+  } catch {}
+  const before = e.__gc_collections();
+  checkStr("test-gc-nested-churn(2000000)", "kept", e["test-gc-nested-churn"], 2000000);
+  const collections = e.__gc_collections() - before;
+  if (collections > 0) {
+    console.log(`  nested heap gc: ${collections} collections  OK`);
+  } else {
+    console.log(`  nested heap gc: ${collections} collections  FAIL`);
+    fail++;
+  }
+}
+
+// --- `--wit`: WIT interface and canonical ABI adapters for defwasm-export ---
+{
+  const wit = readFileSync("js-out/program.wit", "utf-8");
+  checkModuleContract("program.wit declares package", wit.includes("package calcit:test-wasm-main;"));
+  checkModuleContract("program.wit declares record wasm-vec2", /record wasm-vec2 \{\s*x: f64,\s*y: f64,\s*\}/.test(wit));
+  checkModuleContract(
+    "program.wit declares wasm-wit-join",
+    wit.includes("wasm-wit-join: func(xs: list<string>, sep: string) -> string;")
+  );
+  checkModuleContract("program.wit declares wasm-wit-empty", wit.includes("wasm-wit-empty: func(xs: list<string>) -> bool;"));
+  checkModuleContract("exports cabi_realloc", moduleExports.has("cabi_realloc"));
+
+  const canon = (name) => e[`calcit:test-wasm-main/exports#${name}`];
+  const view = () => new DataView(inst.exports.memory.buffer);
+  // Lower a JS string into a guest buffer from cabi_realloc, as a component host would.
+  const lowerString = (text) => {
+    const bytes = new TextEncoder().encode(text);
+    const ptr = e.cabi_realloc(0, 0, 1, bytes.length);
+    new Uint8Array(inst.exports.memory.buffer, ptr, bytes.length).set(bytes);
+    return [ptr, bytes.length];
+  };
+  const lowerStringList = (items) => {
+    const ptr = e.cabi_realloc(0, 0, 4, items.length * 8);
+    items.forEach((item, i) => {
+      const [itemPtr, itemLen] = lowerString(item);
+      view().setUint32(ptr + i * 8, itemPtr, true);
+      view().setUint32(ptr + i * 8 + 4, itemLen, true);
+    });
+    return [ptr, items.length];
+  };
+  const liftString = (ptr, len) => new TextDecoder().decode(new Uint8Array(inst.exports.memory.buffer, ptr, len));
+  const checkValue = (label, expected, got) => {
+    if (JSON.stringify(got) === JSON.stringify(expected)) {
+      console.log(`  ${label} = ${JSON.stringify(got)}  OK`);
+    } else {
+      console.log(`  ${label} = ${JSON.stringify(got)}  FAIL (expected ${JSON.stringify(expected)})`);
+      fail++;
+    }
+  };
+
+  checkValue("canon wasm-ffi-add(20, 22)", 42, canon("wasm-ffi-add")(20, 22));
+  {
+    const area = canon("wasm-ffi-upcase")(...lowerString("wit"));
+    checkValue("canon wasm-ffi-upcase('wit')", "WIT", liftString(view().getUint32(area, true), view().getUint32(area + 4, true)));
+  }
+  {
+    const area = canon("wasm-wit-scale")(1.5, -2, 4);
+    checkValue("canon wasm-wit-scale({x: 1.5, y: -2}, 4)", { x: 6, y: -8 }, {
+      x: view().getFloat64(area, true),
+      y: view().getFloat64(area + 8, true),
+    });
+  }
+  {
+    const area = canon("wasm-wit-join")(...lowerStringList(["a", "bc", "def"]), ...lowerString("-"));
+    checkValue("canon wasm-wit-join(['a', 'bc', 'def'], '-')", "a-bc-def", liftString(view().getUint32(area, true), view().getUint32(area + 4, true)));
+  }
+  {
+    const area = canon("wasm-wit-lengths")(...lowerStringList(["a", "bc", "def"]));
+    const ptr = view().getUint32(area, true);
+    const len = view().getUint32(area + 4, true);
+    const lengths = Array.from({ length: len }, (_, i) => view().getFloat64(ptr + i * 8, true));
+    checkValue("canon wasm-wit-lengths(['a', 'bc', 'def'])", [1, 2, 3], lengths);
+  }
+  checkValue("canon wasm-wit-empty([])", 1, canon("wasm-wit-empty")(...lowerStringList([])));
+  checkValue("canon wasm-wit-empty(['x'])", 0, canon("wasm-wit-empty")(...lowerStringList(["x"])));
+}
+
 if (fail > 0) {
   console.log(`WASM verification FAILED (${fail} failures)`);
   process.exit(1);
@@ -15,12 +15,32 @@ else
 fi
 ENTRY="calcit/test-wasm.cirru"
 
+cr_wasm() {
+  if [[ -n "$BIN" ]]; then
+    "$BIN" "$@"
+  else
+    bash scripts/cargo-with-sdk.sh run --bin cr-wasm -- "$@"
+  fi
+}
+
 # Step 1: generate .wasm binary
-if [[ -n "$BIN" ]]; then
-  "$BIN" "$ENTRY" 2>&1
-else
-  bash scripts/cargo-with-sdk.sh run --bin cr-wasm -- "$ENTRY" 2>&1
-fi
+cr_wasm --wit "$ENTRY" 2>&1
 
 # Step 2: validate and run with Node.js
 node scripts/test-wasm.mjs
+
+# Step 3: the coverage report lists every unsupported construct reachable from the entries
+REPORT="$(NO_COLOR=1 cr_wasm --check-only calcit/test-wasm-coverage.cirru 2>&1)"
+for expected in "proc    &struct:assoc-at" "proc    generate-id!" "proc    get-os" "4/6 reachable definitions compile to WASM"; do
+  if ! grep -qF -- "$expected" <<<"$REPORT"; then
+    echo "$REPORT"
+    echo "WASM coverage report is missing: $expected"
+    exit 1
+  fi
+done
+if grep -qF "cov-unused" <<<"$REPORT"; then
+  echo "$REPORT"
+  echo "WASM coverage report lists an unreachable definition"
+  exit 1
+fi
+echo "=== WASM coverage report checks passed ==="
@@ -2,14 +2,20 @@
 //! packages are defined in `deps.cirru` file
 //!
 //! immutable source revisions are stored under `~/.config/calcit/module-caches/` and
-//! linked into each project's `.calcit/modules/` view.
+//! linked into each project's `.calcit/modules/` view. Resolved revisions are recorded in
+//! `deps.lock.cirru` next to `deps.cirru`. `caps vendor` copies them into `caps-vendor/`,
+//! which later runs use as a mirror.
 
 mod caps_graph;
+mod caps_lock;
+mod caps_mirror;
 mod git;
 
 use argh::{self, FromArgs};
 
 use caps_graph::*;
+use caps_lock::{LOCK_FILE, Lockfile};
+use caps_mirror::{Mirror, VENDOR_DIR, vendor_graph};
 use cirru_edn::Edn;
 use colored::*;
 use semver::Version;
@@ -141,6 +147,7 @@ pub fn main() -> Result<(), String> {
         dev_dependencies: Default::default(),
       },
       cli_args,
+      false,
     )?;
     return Ok(());
   }
@@ -182,7 +189,7 @@ pub fn main() -> Result<(), String> {
             format!("Failed to parse '{}'", cli_args.input)
           })?;
           let updated_deps: PackageDeps = parsed.try_into()?;
-          download_deps(updated_deps, cli_args)?;
+          download_deps(updated_deps, cli_args, true)?;
         }
       }
       Some(SubCommand::Upgrade(opts)) => {
@@ -197,7 +204,7 @@ pub fn main() -> Result<(), String> {
             format!("Failed to parse '{}'", cli_args.input)
           })?;
           let updated_deps: PackageDeps = parsed.try_into()?;
-          download_deps(updated_deps, cli_args)?;
+          download_deps(updated_deps, cli_args, true)?;
         }
       }
       Some(SubCommand::Add(opts)) => {
@@ -215,19 +222,24 @@ pub fn main() -> Result<(), String> {
             let (package, version) = raw.split_at(index);
             (package, version.strip_prefix('@').filter(|version| !version.is_empty()))
           });
-          let org_and_folder = normalize_package_name(package)?;
+          let (org_and_folder, remote) = parse_package_source(package)?;
           let target = if opts.dev {
             &mut updated_deps.dev_dependencies
           } else {
             &mut updated_deps.dependencies
           };
-          target.insert(org_and_folder.into(), inline_version.unwrap_or(&opts.version).to_owned().into());
+          let version = inline_version.unwrap_or(&opts.version);
+          let value = match remote {
+            Some(url) => format!("git+{url}#{version}"),
+            None => version.to_owned(),
+          };
+          target.insert(org_and_folder.into(), value.into());
         }
 
         updated_deps.root_dependencies()?;
         write_deps_file(&cli_args.input, &updated_deps)?;
         println!("updated {}", cli_args.input.green());
-        download_deps(updated_deps, cli_args)?;
+        download_deps(updated_deps, cli_args, true)?;
       }
       Some(SubCommand::Remove(opts)) => {
         if opts.packages.is_empty() {
@@ -247,7 +259,7 @@ pub fn main() -> Result<(), String> {
 
         write_deps_file(&cli_args.input, &updated_deps)?;
         println!("updated {}", cli_args.input.green());
-        download_deps(updated_deps, cli_args)?;
+        download_deps(updated_deps, cli_args, true)?;
       }
       Some(SubCommand::Status(_)) => {
         let graph = resolve_for_cli(&deps, &cli_args, false)?;
@@ -283,12 +295,24 @@ pub fn main() -> Result<(), String> {
         print_warnings(&graph);
         let graph_options = graph_options(&cli_args, true)?;
         install_project_view(&graph, &graph_options)?;
+        record_lock(&graph, &graph_options)?;
         println!(
           "restored {} module link(s) under {}",
           graph.modules.len(),
           graph_options.project_root.join(".calcit/modules").display()
         );
       }
+      Some(SubCommand::Vendor(_)) => {
+        let graph = resolve_for_cli(&deps, &cli_args, false)?;
+        print_warnings(&graph);
+        let graph_options = graph_options(&cli_args, false)?;
+        let count = vendor_graph(&graph, &graph_options.project_root)?;
+        record_lock(&graph, &graph_options)?;
+        println!(
+          "vendored {count} module(s) into {}",
+          graph_options.project_root.join(VENDOR_DIR).display()
+        );
+      }
       Some(SubCommand::Download(dep_names)) => {
         unreachable!("already handled: {:?}", dep_names);
       }
@@ -296,7 +320,7 @@ pub fn main() -> Result<(), String> {
         unreachable!("already handled before reading deps.cirru");
       }
       None => {
-        download_deps(deps, cli_args)?;
+        download_deps(deps, cli_args, true)?;
       }
     }
 
@@ -312,11 +336,15 @@ pub fn main() -> Result<(), String> {
   }
 }
 
-fn download_deps(deps: PackageDeps, options: TopLevelCaps) -> Result<(), String> {
+/// `lock` records the resolved revisions in `deps.lock.cirru`, ad-hoc downloads leave it alone
+fn download_deps(deps: PackageDeps, options: TopLevelCaps, lock: bool) -> Result<(), String> {
   let graph = resolve_for_cli(&deps, &options, !options.ci)?;
   print_warnings(&graph);
   let graph_options = graph_options(&options, !options.ci)?;
   install_project_view(&graph, &graph_options)?;
+  if lock {
+    record_lock(&graph, &graph_options)?;
+  }
   println!(
     "installed {} module(s) into {}",
     graph.modules.len(),
@@ -325,6 +353,16 @@ fn download_deps(deps: PackageDeps, options: TopLevelCaps) -> Result<(), String>
   Ok(())
 }
 
+fn record_lock(graph: &ResolvedGraph, options: &GraphOptions) -> Result<(), String> {
+  if options.frozen {
+    return Ok(());
+  }
+  if Lockfile::from_graph(graph).write(&options.project_root)? {
+    println!("updated {}", options.project_root.join(LOCK_FILE).display().to_string().green());
+  }
+  Ok(())
+}
+
 fn graph_options(options: &TopLevelCaps, build_native: bool) -> Result<GraphOptions, String> {
   let input = PathBuf::from(&options.input);
   let absolute_input = if input.is_absolute() {
@@ -336,15 +374,33 @@ fn graph_options(options: &TopLevelCaps, build_native: bool) -> Result<GraphOpti
     .parent()
     .ok_or_else(|| format!("cannot determine project directory from {}", absolute_input.display()))?
     .to_path_buf();
+  let lock = Lockfile::read(&project_root)?.map(Arc::new);
+  let mirror = mirror_path(options, &project_root).map(|path| Mirror::load(&path)).transpose()?;
   Ok(GraphOptions {
     project_root,
     modules_dir: modules_dir(options)?,
     ci: options.ci,
     strict: options.strict,
     build_native,
+    lock,
+    frozen: options.frozen,
+    mirror: mirror.map(Arc::new),
+    offline: options.offline,
   })
 }
 
+/// `--mirror`, then `CALCIT_CAPS_MIRROR`, then the project's `caps-vendor/` except while vendoring
+fn mirror_path(options: &TopLevelCaps, project_root: &Path) -> Option<PathBuf> {
+  if let Some(path) = &options.mirror {
+    return Some(PathBuf::from(path));
+  }
+  if let Some(path) = std::env::var_os("CALCIT_CAPS_MIRROR") {
+    return Some(PathBuf::from(path));
+  }
+  let vendored = project_root.join(VENDOR_DIR);
+  (vendored.is_dir() && !matches!(options.subcommand, Some(SubCommand::Vendor(_)))).then_some(vendored)
+}
+
 fn resolve_for_cli(deps: &PackageDeps, options: &TopLevelCaps, build_native: bool) -> Result<ResolvedGraph, String> {
   resolve_graph(deps, &graph_options(options, build_native)?)
 }
@@ -388,6 +444,18 @@ struct TopLevelCaps {
   #[argh(switch)]
   strict: bool,
 
+  /// install the revisions in deps.lock.cirru, fail if the lock is out of date
+  #[argh(switch)]
+  frozen: bool,
+
+  /// resolve only from the module cache, the lock and local mirrors, without network access
+  #[argh(switch)]
+  offline: bool,
+
+  /// mirror directory of bare repositories, or a .cirru map of URL prefix rewrites
+  #[argh(option)]
+  mirror: Option<String>,
+
   /// input file
   #[argh(positional, default = "\"deps.cirru\".to_owned()")]
   input: String,
@@ -416,6 +484,8 @@ enum SubCommand {
   Reset(ResetCaps),
   /// remove old immutable revisions from the global module cache
   Clean(CleanCaps),
+  /// copy the resolved revisions into caps-vendor/ for offline builds
+  Vendor(VendorCaps),
 }
 
 #[derive(FromArgs, PartialEq, Debug, Clone)]
@@ -438,6 +508,11 @@ struct ResetCaps {}
 #[argh(subcommand, name = "clean")]
 struct CleanCaps {}
 
+#[derive(FromArgs, PartialEq, Debug, Clone)]
+/// copy the resolved revisions into caps-vendor/ as bare repositories, used as the mirror of later runs
+#[argh(subcommand, name = "vendor")]
+struct VendorCaps {}
+
 #[derive(FromArgs, PartialEq, Debug, Clone)]
 /// show the resolved recursive dependency graph
 #[argh(subcommand, name = "tree")]
@@ -525,7 +600,7 @@ struct DownloadCaps {
 /// add dependencies to deps.cirru then run default download flow
 #[argh(subcommand, name = "add")]
 struct AddCaps {
-  /// packages in format `org/repo` or github URL
+  /// packages in format `org/repo` or a Git URL, other hosts are written as `git+<url>#<ref>`
   #[argh(positional)]
   packages: Vec<String>,
   /// version/branch written to deps.cirru
@@ -540,7 +615,7 @@ struct AddCaps {
 /// remove dependencies from deps.cirru then run default download flow
 #[argh(subcommand, name = "remove")]
 struct RemoveCaps {
-  /// packages in format `org/repo` or github URL
+  /// packages in format `org/repo` or a Git URL
   #[argh(positional)]
   packages: Vec<String>,
   /// remove packages from dev-dependencies
@@ -567,24 +642,45 @@ fn indent4(msg: &str) -> String {
 }
 
 fn normalize_package_name(raw: &str) -> Result<String, String> {
-  let mut s = raw.trim().to_string();
-
-  if let Some(rest) = s.strip_prefix("https://github.com/") {
-    s = rest.to_string();
-  } else if let Some(rest) = s.strip_prefix("http://github.com/") {
-    s = rest.to_string();
-  } else if let Some(rest) = s.strip_prefix("git@github.com:") {
-    s = rest.to_string();
-  }
+  parse_package_source(raw).map(|(name, _)| name)
+}
+
+/// Reads `org/repo` or a Git URL, returns the module name and the URL of a non-GitHub remote.
+/// The module name comes from the last two segments of the URL path.
+fn parse_package_source(raw: &str) -> Result<(String, Option<String>), String> {
+  let trimmed = raw.trim();
+  let mut remote = None;
+  let mut s = if let Some(rest) = trimmed.strip_prefix("https://github.com/") {
+    rest.to_string()
+  } else if let Some(rest) = trimmed.strip_prefix("http://github.com/") {
+    rest.to_string()
+  } else if let Some(rest) = trimmed.strip_prefix("git@github.com:") {
+    rest.to_string()
+  } else if let Some((_, rest)) = trimmed.split_once("://") {
+    remote = Some(trimmed.trim_end_matches('/').to_string());
+    rest.split_once('/').map_or("", |(_, path)| path).to_string()
+  } else if let Some((host, path)) = trimmed.split_once(':')
+    && host.contains('@')
+    && !host.contains('/')
+  {
+    remote = Some(trimmed.trim_end_matches('/').to_string());
+    path.to_string()
+  } else {
+    trimmed.to_string()
+  };
 
+  s = s.trim_end_matches('/').to_string();
   if s.ends_with(".git") {
     s.truncate(s.len() - 4);
   }
-  s = s.trim_end_matches('/').to_string();
+  if remote.is_some() {
+    let segments = s.rsplit('/').take(2).collect::<Vec<_>>();
+    s = segments.into_iter().rev().collect::<Vec<_>>().join("/");
+  }
 
   let (org, repo) =
-    validated_module_parts(&s).map_err(|_| format!("invalid package '{raw}', expected canonical org/repo or github URL"))?;
-  Ok(format!("{org}/{repo}"))
+    validated_module_parts(&s).map_err(|_| format!("invalid package '{raw}', expected canonical org/repo or Git URL"))?;
+  Ok((format!("{org}/{repo}"), remote))
 }
 
 fn module_folder(name: &str) -> Result<&str, String> {
@@ -737,15 +833,34 @@ fn outdated_tags(deps: PackageDeps, deps_file: &str, auto_yes: bool) -> Result<b
   Ok(false)
 }
 
-fn show_package_versions(org_and_folder: Arc<str>, version: Arc<str>) -> Result<Option<String>, String> {
-  let https_url = format!("https://github.com/{org_and_folder}.git");
-  let output = match list_remote_tags(&https_url) {
-    Ok(output) => output,
-    Err(https_error) => {
-      let ssh_url = format!("git@github.com:{org_and_folder}.git");
-      list_remote_tags(&ssh_url).map_err(|ssh_error| {
-        format!("failed to inspect tags for {org_and_folder} over HTTPS and SSH:\n  HTTPS: {https_error}\n  SSH: {ssh_error}")
-      })?
+/// Returns the updated `deps.cirru` value when a newer SemVer tag exists.
+fn show_package_versions(org_and_folder: Arc<str>, value: Arc<str>) -> Result<Option<String>, String> {
+  if value.starts_with("file:") || value.contains("#sha256=") {
+    let kind = if value.starts_with("file:") && !value.contains("#sha256=") {
+      "local directory"
+    } else {
+      "archive"
+    };
+    print_column(org_and_folder.dimmed(), value.dimmed(), kind.dimmed(), "-".dimmed());
+    return Ok(None);
+  }
+  let spec = DependencySpec::parse(&value, None)?;
+  let version = spec.reference.as_str();
+  let output = match &spec.origin {
+    ModuleOrigin::Git(url) => {
+      list_remote_tags(url).map_err(|e| format!("failed to inspect tags for {org_and_folder} at {url}: {e}"))?
+    }
+    _ => {
+      let https_url = format!("https://github.com/{org_and_folder}.git");
+      match list_remote_tags(&https_url) {
+        Ok(output) => output,
+        Err(https_error) => {
+          let ssh_url = format!("git@github.com:{org_and_folder}.git");
+          list_remote_tags(&ssh_url).map_err(|ssh_error| {
+            format!("failed to inspect tags for {org_and_folder} over HTTPS and SSH:\n  HTTPS: {https_error}\n  SSH: {ssh_error}")
+          })?
+        }
+      }
     }
   };
   let latest = String::from_utf8_lossy(&output.stdout)
@@ -758,16 +873,22 @@ fn show_package_versions(org_and_folder: Arc<str>, version: Arc<str>) -> Result<
     })
     .max_by(|left, right| left.1.cmp(&right.1).then_with(|| left.0.cmp(&right.0)));
   if let Some((latest_tag, latest_version)) = latest {
-    let current = Version::parse(version.strip_prefix('v').unwrap_or(&version));
+    let current = Version::parse(version.strip_prefix('v').unwrap_or(version));
     if current.as_ref().is_ok_and(|current| current < &latest_version) {
-      print_column(org_and_folder.yellow(), version.yellow(), latest_tag.yellow(), "Outdated".yellow());
-      Ok(Some(latest_tag))
+      print_column(org_and_folder.yellow(), value.yellow(), latest_tag.yellow(), "Outdated".yellow());
+      Ok(Some(
+        DependencySpec {
+          reference: latest_tag,
+          ..spec
+        }
+        .to_string(),
+      ))
     } else {
-      print_column(org_and_folder.dimmed(), version.dimmed(), latest_tag.dimmed(), "√".dimmed());
+      print_column(org_and_folder.dimmed(), value.dimmed(), latest_tag.dimmed(), "√".dimmed());
       Ok(None)
     }
   } else {
-    print_column(org_and_folder.yellow(), version.yellow(), "no SemVer tags".yellow(), "-".yellow());
+    print_column(org_and_folder.yellow(), value.yellow(), "no SemVer tags".yellow(), "-".yellow());
     Ok(None)
   }
 }
@@ -978,7 +1099,7 @@ fn print_column(pkg: ColoredString, expected: ColoredString, latest: ColoredStri
 mod tests {
   use super::{
     PackageDeps, VersionBumpCaps, VersionCaps, VersionGetCaps, VersionSubcommand, handle_version_command, module_folder,
-    normalize_package_name,
+    normalize_package_name, parse_package_source,
   };
   use cirru_edn::Edn;
   use std::collections::HashMap;
@@ -1001,6 +1122,23 @@ mod tests {
     assert!(normalize_package_name("calcit-lang/respo.calcit/extra").is_err());
   }
 
+  #[test]
+  fn package_sources_keep_non_github_remotes() {
+    assert_eq!(
+      parse_package_source("calcit-lang/lilac"),
+      Ok(("calcit-lang/lilac".to_owned(), None))
+    );
+    assert_eq!(
+      parse_package_source("https://gitlab.com/group/team/lib.git"),
+      Ok(("team/lib".to_owned(), Some("https://gitlab.com/group/team/lib.git".to_owned())))
+    );
+    assert_eq!(
+      parse_package_source("git@git.example.com:team/lib.git"),
+      Ok(("team/lib".to_owned(), Some("git@git.example.com:team/lib.git".to_owned())))
+    );
+    assert!(parse_package_source("https://gitlab.com/lib.git").is_err());
+  }
+
   #[test]
   fn missing_dependencies_field_is_an_empty_graph() {
     let deps: PackageDeps = Edn::Map(cirru_edn::EdnMapView::default()).try_into().unwrap();
@@ -1,9 +1,12 @@
+use crate::caps_lock::{LOCK_FILE, LockedModule, Lockfile};
+use crate::caps_mirror::Mirror;
 use crate::git::GitRepo;
 use crate::{CALCIT_VERSION, PackageDeps, call_build_script, module_folder};
 use cirru_edn::{Edn, EdnMapView};
 use colored::Colorize;
 use md5::{Digest, Md5};
-use semver::Version;
+use semver::{Version, VersionReq};
+use sha2::Sha256;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fs::{self, OpenOptions};
 use std::path::{Component, Path, PathBuf};
@@ -16,18 +19,150 @@ const MAX_PARALLEL_RESOLVES: usize = 6;
 const MODULE_CACHE_DIR: &str = "module-caches";
 const PROJECT_VIEW_REGISTRY_DIR: &str = "projects";
 const VERSION_STORE_METADATA: &str = "metadata.txt";
+const MAX_ARCHIVE_BYTES: u64 = 256 * 1024 * 1024;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DependencyRequest {
   pub reference: String,
+  pub origin: ModuleOrigin,
   pub requested_by: Option<String>,
 }
 
+impl DependencyRequest {
+  pub fn spec(&self) -> DependencySpec {
+    DependencySpec {
+      origin: self.origin.clone(),
+      reference: self.reference.clone(),
+    }
+  }
+}
+
+/// Where a module is fetched from, read from a `deps.cirru` value.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ModuleOrigin {
+  /// `<ref>`, the `org/repo` repository on GitHub
+  GitHub,
+  /// `git+<url>#<ref>`, any Git remote
+  Git(String),
+  /// `file:<dir>`, a local directory snapshotted into the store
+  Directory(PathBuf),
+  /// `<url>#sha256=<hex>`, a `.tar.gz` archive over HTTP(S) or `file:`
+  Archive(String),
+}
+
+/// A parsed `deps.cirru` value. Formatting gives back a value with absolute local paths.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DependencySpec {
+  pub origin: ModuleOrigin,
+  pub reference: String,
+}
+
+const DIRECTORY_REFERENCE: &str = "local";
+
+impl DependencySpec {
+  /// `base` resolves relative `file:` paths, they are rejected without one
+  pub fn parse(value: &str, base: Option<&Path>) -> Result<Self, String> {
+    if let Some(rest) = value.strip_prefix("git+") {
+      let (url, reference) = rest
+        .rsplit_once('#')
+        .filter(|(url, reference)| !url.is_empty() && !reference.is_empty())
+        .ok_or_else(|| format!("Git dependency {value} needs a #<ref> suffix"))?;
+      return Ok(DependencySpec {
+        origin: ModuleOrigin::Git(url.to_string()),
+        reference: reference.to_string(),
+      });
+    }
+    if let Some((location, digest)) = value.rsplit_once("#sha256=") {
+      if digest.len() != 64 || !digest.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(format!("archive dependency {value} needs a 64 digit hex SHA-256 digest"));
+      }
+      if !(location.ends_with(".tar.gz") || location.ends_with(".tgz")) {
+        return Err(format!("archive dependency {value} must be a .tar.gz or .tgz file"));
+      }
+      let url = if let Some(path) = location.strip_prefix("file:") {
+        format!("file:{}", local_dependency_path(value, path, base)?.display())
+      } else if location.starts_with("https://") || location.starts_with("http://") {
+        location.to_string()
+      } else {
+        return Err(format!("archive dependency {value} must use an http(s):// or file: location"));
+      };
+      return Ok(DependencySpec {
+        origin: ModuleOrigin::Archive(url),
+        reference: format!("sha256={}", digest.to_ascii_lowercase()),
+      });
+    }
+    if let Some(path) = value.strip_prefix("file:") {
+      let directory = local_dependency_path(value, path, base)?;
+      if !directory.is_dir() {
+        return Err(format!("local dependency {value} is not a directory"));
+      }
+      return Ok(DependencySpec {
+        origin: ModuleOrigin::Directory(directory),
+        reference: DIRECTORY_REFERENCE.to_string(),
+      });
+    }
+    Ok(DependencySpec {
+      origin: ModuleOrigin::GitHub,
+      reference: value.to_string(),
+    })
+  }
+}
+
+impl std::fmt::Display for DependencySpec {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self.origin {
+      ModuleOrigin::GitHub => write!(f, "{}", self.reference),
+      ModuleOrigin::Git(url) => write!(f, "git+{url}#{}", self.reference),
+      ModuleOrigin::Directory(directory) => write!(f, "file:{}", directory.display()),
+      ModuleOrigin::Archive(url) => write!(f, "{url}#{}", self.reference),
+    }
+  }
+}
+
+fn local_dependency_path(value: &str, path: &str, base: Option<&Path>) -> Result<PathBuf, String> {
+  let path = Path::new(path);
+  let joined = if path.is_absolute() {
+    path.to_path_buf()
+  } else {
+    base
+      .ok_or_else(|| format!("relative local dependency {value} is only allowed in a project or a local module"))?
+      .join(path)
+  };
+  fs::canonicalize(&joined).map_err(|e| format!("failed to resolve local dependency {value}: {e}"))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RefKind {
   Tag,
   Branch,
   Commit,
+  /// snapshot of a local directory
+  Directory,
+  /// snapshot of a checksummed archive
+  Archive,
+}
+
+impl RefKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Tag => "tag",
+      Self::Branch => "branch",
+      Self::Commit => "commit",
+      Self::Directory => "directory",
+      Self::Archive => "archive",
+    }
+  }
+
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "tag" => Some(Self::Tag),
+      "branch" => Some(Self::Branch),
+      "commit" => Some(Self::Commit),
+      "directory" => Some(Self::Directory),
+      "archive" => Some(Self::Archive),
+      _ => None,
+    }
+  }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -53,6 +188,7 @@ pub struct ResolvedModule {
   pub reference: String,
   pub commit: String,
   pub kind: RefKind,
+  pub origin: ModuleOrigin,
   pub source: PathBuf,
   pub link_target: PathBuf,
   pub dependencies: BTreeMap<String, String>,
@@ -73,6 +209,14 @@ pub struct GraphOptions {
   pub ci: bool,
   pub strict: bool,
   pub build_native: bool,
+  /// revisions recorded in `deps.lock.cirru`, preferred when SemVer ranges still match
+  pub lock: Option<Arc<Lockfile>>,
+  /// install only locked revisions and fail when the lock is out of date
+  pub frozen: bool,
+  /// replaces upstream fetch locations
+  pub mirror: Option<Arc<Mirror>>,
+  /// resolve from locked revisions in the module cache or a local mirror, never from the network
+  pub offline: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -323,14 +467,27 @@ fn metadata_value(content: &str, name: &str) -> Option<String> {
 pub fn resolve_graph(root_deps: &PackageDeps, options: &GraphOptions) -> Result<ResolvedGraph, String> {
   let root = sorted_root_dependencies(root_deps)?;
   validate_module_folders(root.keys())?;
+  let mut tags = BTreeMap::<(String, ModuleOrigin), Vec<String>>::new();
   let mut selections = BTreeMap::<String, String>::new();
+  for (repository, value) in &root {
+    let spec = DependencySpec::parse(value, Some(&options.project_root))?;
+    let request = BTreeSet::from([DependencyRequest {
+      reference: spec.reference,
+      origin: spec.origin,
+      requested_by: None,
+    }]);
+    selections.insert(
+      repository.clone(),
+      choose_reference(repository, &request, options, &mut tags)?.to_string(),
+    );
+  }
   let mut stable_result = None;
   let mut cache = BTreeMap::<(String, String), ResolvedModule>::new();
 
   for _ in 0..256 {
-    let pass = resolve_pass(&root, &selections, options, &mut cache)?;
+    let pass = resolve_pass(&root, &selections, options, &mut cache, &mut tags)?;
     if pass.selections == selections {
-      let final_pass = resolve_pass(&root, &pass.selections, options, &mut cache)?;
+      let final_pass = resolve_pass(&root, &pass.selections, options, &mut cache, &mut tags)?;
       stable_result = Some(final_pass);
       break;
     }
@@ -352,12 +509,26 @@ pub fn resolve_graph(root_deps: &PackageDeps, options: &GraphOptions) -> Result<
     return Err(format!("strict dependency resolution rejected warnings:\n{}", warnings.join("\n")));
   }
 
-  Ok(ResolvedGraph {
+  let graph = ResolvedGraph {
     root,
     modules,
     requests: pass.requests,
     warnings,
-  })
+  };
+  if options.frozen {
+    let lock = options
+      .lock
+      .as_ref()
+      .ok_or_else(|| format!("--frozen requires {LOCK_FILE}; run caps without --frozen to create it"))?;
+    let diff = lock.diff(&Lockfile::from_graph(&graph));
+    if !diff.is_empty() {
+      return Err(format!(
+        "{LOCK_FILE} is out of date; run caps without --frozen to update it:\n{}",
+        diff.join("\n")
+      ));
+    }
+  }
+  Ok(graph)
 }
 
 struct ResolvePass {
@@ -371,11 +542,14 @@ fn resolve_pass(
   previous: &BTreeMap<String, String>,
   options: &GraphOptions,
   cache: &mut BTreeMap<(String, String), ResolvedModule>,
+  tags: &mut BTreeMap<(String, ModuleOrigin), Vec<String>>,
 ) -> Result<ResolvePass, String> {
   let mut requests = BTreeMap::<String, BTreeSet<DependencyRequest>>::new();
-  for (repository, reference) in root {
+  for (repository, value) in root {
+    let spec = DependencySpec::parse(value, Some(&options.project_root))?;
     requests.entry(repository.clone()).or_default().insert(DependencyRequest {
-      reference: reference.clone(),
+      reference: spec.reference,
+      origin: spec.origin,
       requested_by: None,
     });
   }
@@ -409,10 +583,11 @@ fn resolve_pass(
         })
       })
       .collect::<Vec<_>>();
-    for handle in handles {
-      let (repository, selected, result) = handle
-        .join()
-        .map_err(|_| "dependency resolver worker panicked while inspecting a module".to_string())?;
+    // join the whole batch before reporting, so no worker keeps writing the store after an error
+    let results = handles.into_iter().map(|handle| handle.join()).collect::<Vec<_>>();
+    for joined in results {
+      let (repository, selected, result) =
+        joined.map_err(|_| "dependency resolver worker panicked while inspecting a module".to_string())?;
       let module = result?;
       cache.insert((repository.clone(), selected), module.clone());
       modules.insert(repository, module);
@@ -420,9 +595,15 @@ fn resolve_pass(
   }
 
   for (repository, module) in &modules {
-    for (dependency, reference) in &module.dependencies {
+    let base = match &module.origin {
+      ModuleOrigin::Directory(directory) => Some(directory.as_path()),
+      _ => None,
+    };
+    for (dependency, value) in &module.dependencies {
+      let spec = DependencySpec::parse(value, base).map_err(|e| format!("{e}, requested by {repository}@{}", module.reference))?;
       requests.entry(dependency.clone()).or_default().insert(DependencyRequest {
-        reference: reference.clone(),
+        reference: spec.reference,
+        origin: spec.origin,
         requested_by: Some(format!("{}@{}", repository, module.reference)),
       });
     }
@@ -432,7 +613,7 @@ fn resolve_pass(
   for (repository, module_requests) in &requests {
     selections.insert(
       repository.clone(),
-      select_reference(repository, module_requests, root.get(repository))?,
+      choose_reference(repository, module_requests, options, tags)?.to_string(),
     );
   }
 
@@ -443,6 +624,115 @@ fn resolve_pass(
   })
 }
 
+/// Picks the origin of a module, the root request wins when dependents disagree.
+/// Then resolves SemVer ranges to one tag and picks between the remaining refs.
+/// A locked tag is kept while it satisfies every range, remote tags are listed only otherwise.
+fn choose_reference(
+  repository: &str,
+  requests: &BTreeSet<DependencyRequest>,
+  options: &GraphOptions,
+  tags: &mut BTreeMap<(String, ModuleOrigin), Vec<String>>,
+) -> Result<DependencySpec, String> {
+  let root_request = requests.iter().find(|request| request.requested_by.is_none());
+  let origins = requests.iter().map(|request| &request.origin).collect::<BTreeSet<_>>();
+  let origin = match (origins.len(), root_request) {
+    (1, _) => origins.into_iter().next().expect("one dependency origin").clone(),
+    (_, Some(root_request)) => root_request.origin.clone(),
+    _ => {
+      let details = requests
+        .iter()
+        .map(|request| {
+          format!(
+            "  {} requested by {}",
+            request.spec(),
+            request.requested_by.as_deref().unwrap_or("root")
+          )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+      return Err(format!(
+        "conflicting sources for {repository}; add a direct root dependency to decide:\n{details}"
+      ));
+    }
+  };
+  let requests = requests
+    .iter()
+    .filter(|request| request.origin == origin)
+    .cloned()
+    .collect::<BTreeSet<_>>();
+  let reference = choose_origin_reference(repository, &origin, &requests, root_request, options, tags)?;
+  Ok(DependencySpec { origin, reference })
+}
+
+fn choose_origin_reference(
+  repository: &str,
+  origin: &ModuleOrigin,
+  requests: &BTreeSet<DependencyRequest>,
+  root_request: Option<&DependencyRequest>,
+  options: &GraphOptions,
+  tags: &mut BTreeMap<(String, ModuleOrigin), Vec<String>>,
+) -> Result<String, String> {
+  let root_reference = root_request.map(|request| &request.reference);
+  let mut ranges = vec![];
+  for request in requests.iter().filter(|request| is_version_range(&request.reference)) {
+    let range = VersionReq::parse(&request.reference).map_err(|e| {
+      format!(
+        "invalid SemVer range {repository}@{} requested by {}: {e}",
+        request.reference,
+        request.requested_by.as_deref().unwrap_or("root")
+      )
+    })?;
+    ranges.push(range);
+  }
+  if ranges.is_empty() {
+    return select_reference(repository, requests, root_reference);
+  }
+
+  let floor = requests.iter().filter_map(|request| parse_tag_version(&request.reference)).max();
+  let locked = options
+    .lock
+    .as_ref()
+    .and_then(|lock| lock.modules.get(repository))
+    .filter(|module| module.kind == RefKind::Tag)
+    .map(|module| module.reference.clone());
+  if let Some(tag) = pick_range_tag(&ranges, floor.as_ref(), locked.as_slice()) {
+    return Ok(tag);
+  }
+
+  let requirements = requests
+    .iter()
+    .map(|request| format!("{} by {}", request.reference, request.requested_by.as_deref().unwrap_or("root")))
+    .collect::<Vec<_>>()
+    .join(", ");
+  if options.frozen || options.offline {
+    return Err(format!(
+      "{LOCK_FILE} is out of date: no locked tag of {repository} matches {requirements}; {}",
+      pinned_hint(options)
+    ));
+  }
+  let key = (repository.to_string(), origin.clone());
+  if !tags.contains_key(&key) {
+    eprintln!("listing tags of {repository}");
+    tags.insert(key.clone(), resolve_remote_tags(repository, origin, options)?);
+  }
+  pick_range_tag(&ranges, floor.as_ref(), &tags[&key]).ok_or_else(|| format!("no tag of {repository} matches {requirements}"))
+}
+
+fn is_version_range(reference: &str) -> bool {
+  reference.starts_with(['^', '~', '>', '<', '=', '*'])
+}
+
+/// highest tag matching every range and not older than the highest exact version requested
+fn pick_range_tag(ranges: &[VersionReq], floor: Option<&Version>, candidates: &[String]) -> Option<String> {
+  candidates
+    .iter()
+    .filter_map(|tag| parse_tag_version(tag).map(|version| (tag, version)))
+    .filter(|(_, version)| ranges.iter().all(|range| range.matches(version)))
+    .filter(|(_, version)| floor.is_none_or(|floor| version >= floor))
+    .max_by(|left, right| left.1.cmp(&right.1).then_with(|| left.0.cmp(right.0)))
+    .map(|(tag, _)| tag.clone())
+}
+
 fn select_reference(
   repository: &str,
   requests: &BTreeSet<DependencyRequest>,
@@ -495,63 +785,98 @@ fn parse_tag_version(reference: &str) -> Option<Version> {
   Version::parse(reference.strip_prefix('v').unwrap_or(reference)).ok()
 }
 
-fn materialize_module(repository: &str, reference: &str, options: &GraphOptions) -> Result<ResolvedModule, String> {
-  let (owner, repo) = repository
-    .split_once('/')
-    .ok_or_else(|| format!("invalid repository {repository}"))?;
-  let temp_root = module_cache_root(&options.modules_dir).join("tmp");
-  fs::create_dir_all(&temp_root).map_err(|e| format!("failed to create {}: {e}", temp_root.display()))?;
-  let identity = hex::encode(Md5::digest(format!("{repository}\n{reference}").as_bytes()));
-  let temp_path = temp_root.join(format!(
-    "clone-{}-{}-{}-{identity}",
-    std::process::id(),
-    sanitize(repository),
-    sanitize(reference)
-  ));
-  if temp_path.exists() {
-    fs::remove_dir_all(&temp_path).map_err(|e| format!("failed to clean {}: {e}", temp_path.display()))?;
-  }
+fn materialize_module(repository: &str, selected: &str, options: &GraphOptions) -> Result<ResolvedModule, String> {
+  let spec = DependencySpec::parse(selected, None)?;
+  let reference = spec.reference.as_str();
+  let temp_path = store_temp_path(repository, reference, options)?;
+  let _lock;
+  let (kind, commit) = match &spec.origin {
+    ModuleOrigin::Directory(directory) => {
+      eprintln!("snapshotting {repository} from {}", directory.display());
+      copy_tree(directory, &temp_path)?;
+      _lock = CacheMetadataLock::acquire(&module_cache_root(&options.modules_dir))?;
+      (RefKind::Directory, GitRepo::snapshot(&temp_path)?)
+    }
+    ModuleOrigin::Archive(url) => {
+      // the snapshot commit of a locked archive is reused from the store or a directory mirror
+      let locked = locked_module(repository, &spec, options).ok().map(|module| module.commit.clone());
+      let mirrored = options
+        .mirror
+        .as_ref()
+        .and_then(|mirror| mirror.repository(repository))
+        .filter(|path| path.is_dir());
+      match (locked, mirrored) {
+        (Some(commit), _) if store_source_path(repository, &commit, options)?.exists() => {
+          _lock = CacheMetadataLock::acquire(&module_cache_root(&options.modules_dir))?;
+          (RefKind::Archive, commit)
+        }
+        (Some(commit), Some(mirrored)) => {
+          clone_locked_commit(
+            repository,
+            reference,
+            &format!("file://{}", mirrored.display()),
+            &commit,
+            &temp_path,
+          )?;
+          _lock = CacheMetadataLock::acquire(&module_cache_root(&options.modules_dir))?;
+          (RefKind::Archive, commit)
+        }
+        _ if options.offline && !url.starts_with("file:") => {
+          return Err(format!(
+            "{repository}@{reference} is not in the module cache; run caps without --offline or vendor it with caps vendor"
+          ));
+        }
+        _ => {
+          let url = options.mirror.as_ref().map_or_else(|| url.clone(), |mirror| mirror.rewrite(url));
+          eprintln!("fetching {repository} from {url}");
+          unpack_archive(&url, reference, &temp_path)?;
+          _lock = CacheMetadataLock::acquire(&module_cache_root(&options.modules_dir))?;
+          (RefKind::Archive, GitRepo::snapshot(&temp_path)?)
+        }
+      }
+    }
+    ModuleOrigin::GitHub | ModuleOrigin::Git(_) => {
+      let pinned = options.frozen || options.offline;
+      let (kind, commit, url) = if pinned {
+        let locked = locked_module(repository, &spec, options)?;
+        (locked.kind.clone(), locked.commit.clone(), None)
+      } else {
+        eprintln!("resolving {repository}@{reference}");
+        let remote = resolve_remote_ref(repository, &spec, options)?;
+        (remote.kind, remote.commit, Some(remote.url))
+      };
+      _lock = CacheMetadataLock::acquire(&module_cache_root(&options.modules_dir))?;
+      if !store_source_path(repository, &commit, options)?.exists() {
+        match url {
+          Some(url) => {
+            GitRepo::clone_to_path(&temp_path, &url, reference, &kind, true)
+              .map_err(|e| format!("failed to clone {repository}@{reference}: {e}"))?;
+            let cloned = GitRepo { dir: temp_path.clone() }.head_commit()?;
+            if cloned != commit {
+              return Err(format!(
+                "remote ref changed while cloning {repository}@{reference}: resolved {commit} but cloned {cloned}; retry the command"
+              ));
+            }
+          }
+          None => {
+            if options.offline && !options.mirror.as_ref().is_some_and(|mirror| mirror.is_local()) {
+              return Err(format!(
+                "{repository}@{reference} ({commit}) is not in the module cache; run caps without --offline or vendor it with caps vendor"
+              ));
+            }
+            let url = fetch_urls(repository, &spec.origin, options)?.remove(0);
+            clone_locked_commit(repository, reference, &url, &commit, &temp_path)?;
+          }
+        }
+      }
+      (kind, commit)
+    }
+  };
 
-  eprintln!("resolving {repository}@{reference}");
-  let remote = resolve_remote_ref(repository, reference, options.ci)?;
-  let source = module_cache_root(&options.modules_dir)
-    .join("git")
-    .join(owner)
-    .join(repo)
-    .join(&remote.commit)
-    .join("source");
-  let _lock = CacheMetadataLock::acquire(&module_cache_root(&options.modules_dir))?;
-  if source.exists() {
-    validate_store_source(repository, &source, &remote.commit)?;
-    ensure_store_metadata_unlocked(&source, repository, reference, &remote.commit)?;
-    let dependencies = read_module_dependencies(&source)?;
-    return Ok(ResolvedModule {
-      repository: repository.to_string(),
-      reference: reference.to_string(),
-      commit: remote.commit,
-      kind: remote.kind,
-      link_target: source.clone(),
-      source,
-      dependencies,
-    });
-  }
-  GitRepo::clone_to_path(&temp_path, &remote.url, reference, &remote.kind, true)
-    .map_err(|e| format!("failed to clone {repository}@{reference}: {e}"))?;
-  let temp_repo = GitRepo { dir: temp_path.clone() };
-  let commit = temp_repo.head_commit()?;
-  if commit != remote.commit {
-    return Err(format!(
-      "remote ref changed while cloning {repository}@{reference}: resolved {} but cloned {commit}; retry the command",
-      remote.commit
-    ));
-  }
-  let source = module_cache_root(&options.modules_dir)
-    .join("git")
-    .join(owner)
-    .join(repo)
-    .join(&commit)
-    .join("source");
-  if source.exists() {
+  let source = store_source_path(repository, &commit, options)?;
+  if !temp_path.exists() {
+    validate_store_source(repository, &source, &commit)?;
+  } else if source.exists() {
     fs::remove_dir_all(&temp_path).map_err(|e| format!("failed to clean {}: {e}", temp_path.display()))?;
     validate_store_source(repository, &source, &commit)?;
   } else {
@@ -578,13 +903,104 @@ fn materialize_module(repository: &str, reference: &str, options: &GraphOptions)
     repository: repository.to_string(),
     reference: reference.to_string(),
     commit,
-    kind: remote.kind,
+    kind,
+    origin: spec.origin,
     link_target: source.clone(),
     source,
     dependencies,
   })
 }
 
+fn store_temp_path(repository: &str, reference: &str, options: &GraphOptions) -> Result<PathBuf, String> {
+  let temp_root = module_cache_root(&options.modules_dir).join("tmp");
+  fs::create_dir_all(&temp_root).map_err(|e| format!("failed to create {}: {e}", temp_root.display()))?;
+  let identity = hex::encode(Md5::digest(format!("{repository}\n{reference}").as_bytes()));
+  let temp_path = temp_root.join(format!(
+    "clone-{}-{}-{}-{identity}",
+    std::process::id(),
+    sanitize(repository),
+    sanitize(reference)
+  ));
+  if temp_path.exists() {
+    fs::remove_dir_all(&temp_path).map_err(|e| format!("failed to clean {}: {e}", temp_path.display()))?;
+  }
+  Ok(temp_path)
+}
+
+fn store_source_path(repository: &str, commit: &str, options: &GraphOptions) -> Result<PathBuf, String> {
+  let (owner, repo) = repository
+    .split_once('/')
+    .ok_or_else(|| format!("invalid repository {repository}"))?;
+  Ok(
+    module_cache_root(&options.modules_dir)
+      .join("git")
+      .join(owner)
+      .join(repo)
+      .join(commit)
+      .join("source"),
+  )
+}
+
+/// Reads a `.tar.gz` archive, checks its SHA-256 digest and unpacks it into `target`.
+/// An archive holding a single top-level directory is unpacked from inside that directory.
+fn unpack_archive(url: &str, reference: &str, target: &Path) -> Result<(), String> {
+  let bytes = if let Some(path) = url.strip_prefix("file:") {
+    fs::read(path).map_err(|e| format!("failed to read archive {path}: {e}"))?
+  } else {
+    ureq::get(url)
+      .call()
+      .map_err(|e| format!("failed to download archive {url}: {e}"))?
+      .into_body()
+      .with_config()
+      .limit(MAX_ARCHIVE_BYTES)
+      .read_to_vec()
+      .map_err(|e| format!("failed to download archive {url}: {e}"))?
+  };
+  let expected = reference.strip_prefix("sha256=").unwrap_or(reference);
+  let actual = hex::encode(Sha256::digest(&bytes));
+  if actual != expected {
+    return Err(format!(
+      "checksum mismatch for archive {url}: expected sha256={expected}, got sha256={actual}"
+    ));
+  }
+
+  let unpacked = target.with_extension("unpack");
+  if unpacked.exists() {
+    fs::remove_dir_all(&unpacked).map_err(|e| format!("failed to clean {}: {e}", unpacked.display()))?;
+  }
+  fs::create_dir_all(&unpacked).map_err(|e| format!("failed to create {}: {e}", unpacked.display()))?;
+  let archive = target.with_extension("tar.gz");
+  fs::write(&archive, &bytes).map_err(|e| format!("failed to write {}: {e}", archive.display()))?;
+  let output = Command::new("tar")
+    .arg("-xzf")
+    .arg(&archive)
+    .arg("-C")
+    .arg(&unpacked)
+    .output()
+    .map_err(|e| format!("failed to run tar for {url}: {e}"))?;
+  let _ = fs::remove_file(&archive);
+  if !output.status.success() {
+    let _ = fs::remove_dir_all(&unpacked);
+    return Err(format!(
+      "failed to unpack archive {url}: {}",
+      String::from_utf8_lossy(&output.stderr).trim()
+    ));
+  }
+  let entries = fs::read_dir(&unpacked)
+    .map_err(|e| format!("failed to read {}: {e}", unpacked.display()))?
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| format!("failed to read {}: {e}", unpacked.display()))?;
+  let root = match entries.as_slice() {
+    [entry] if entry.file_type().is_ok_and(|kind| kind.is_dir()) => entry.path(),
+    _ => unpacked.clone(),
+  };
+  fs::rename(&root, target).map_err(|e| format!("failed to move {} to {}: {e}", root.display(), target.display()))?;
+  if unpacked.exists() {
+    fs::remove_dir_all(&unpacked).map_err(|e| format!("failed to clean {}: {e}", unpacked.display()))?;
+  }
+  Ok(())
+}
+
 #[cfg(test)]
 fn ensure_store_metadata(source: &Path, repository: &str, reference: &str, commit: &str) -> Result<(), String> {
   let _lock = CacheMetadataLock::acquire(&metadata_cache_root(source))?;
@@ -649,25 +1065,108 @@ struct RemoteRef {
   url: String,
 }
 
-fn resolve_remote_ref(repository: &str, reference: &str, ci: bool) -> Result<RemoteRef, String> {
+fn resolve_remote_ref(repository: &str, spec: &DependencySpec, options: &GraphOptions) -> Result<RemoteRef, String> {
+  let reference = spec.reference.as_str();
+  let urls = fetch_urls(repository, &spec.origin, options)?;
   if is_full_commit_hash(reference) {
     return Ok(RemoteRef {
       kind: RefKind::Commit,
       commit: reference.to_ascii_lowercase(),
-      url: format!("https://github.com/{repository}.git"),
+      url: urls[0].clone(),
     });
   }
-  let https_url = format!("https://github.com/{repository}.git");
-  match inspect_remote_ref(&https_url, reference) {
-    Ok(remote) => Ok(remote),
-    Err(https_error) if !ci => {
-      let ssh_url = format!("git@github.com:{repository}.git");
-      inspect_remote_ref(&ssh_url, reference).map_err(|ssh_error| {
-        format!("failed to resolve {repository}@{reference} over HTTPS and SSH:\n  HTTPS: {https_error}\n  SSH: {ssh_error}")
-      })
+  first_reachable(&urls, |url| inspect_remote_ref(url, reference))
+    .map_err(|e| format!("failed to resolve {repository}@{reference}:\n{e}"))
+}
+
+/// the locked revision of `repository@reference`, used by `--frozen` and `--offline` without asking the remote
+fn locked_module<'a>(repository: &str, spec: &DependencySpec, options: &'a GraphOptions) -> Result<&'a LockedModule, String> {
+  options
+    .lock
+    .as_ref()
+    .and_then(|lock| lock.modules.get(repository))
+    .filter(|module| module.reference == spec.reference)
+    .ok_or_else(|| {
+      format!(
+        "{LOCK_FILE} is out of date: {repository}@{} is not locked; {}",
+        spec.reference,
+        pinned_hint(options)
+      )
+    })
+}
+
+fn pinned_hint(options: &GraphOptions) -> &'static str {
+  if options.offline {
+    "run caps without --offline to update it"
+  } else {
+    "run caps without --frozen to update it"
+  }
+}
+
+fn clone_locked_commit(repository: &str, reference: &str, url: &str, commit: &str, target: &Path) -> Result<(), String> {
+  GitRepo::clone_to_path(target, url, commit, &RefKind::Commit, true)
+    .map_err(|e| format!("failed to fetch {repository}@{reference} ({commit}) from {url}: {e}"))?;
+  let cloned = GitRepo { dir: target.to_path_buf() }.head_commit()?;
+  if cloned != commit {
+    return Err(format!(
+      "fetched {cloned} for {repository}@{reference} from {url}, expected {commit}"
+    ));
+  }
+  Ok(())
+}
+
+/// fetch URLs of a Git origin in the order they are tried, after applying the mirror.
+/// GitHub modules fall back from HTTPS to SSH outside CI.
+fn fetch_urls(repository: &str, origin: &ModuleOrigin, options: &GraphOptions) -> Result<Vec<String>, String> {
+  let upstream = match origin {
+    ModuleOrigin::Git(url) => vec![url.clone()],
+    _ if options.ci => vec![format!("https://github.com/{repository}.git")],
+    _ => vec![
+      format!("https://github.com/{repository}.git"),
+      format!("git@github.com:{repository}.git"),
+    ],
+  };
+  match &options.mirror {
+    Some(mirror) => mirror.git_urls(repository, upstream),
+    None => Ok(upstream),
+  }
+}
+
+/// result from the first URL that answers, or every error when none does
+fn first_reachable<T>(urls: &[String], inspect: impl Fn(&str) -> Result<T, String>) -> Result<T, String> {
+  let mut errors = vec![];
+  for url in urls {
+    match inspect(url) {
+      Ok(value) => return Ok(value),
+      Err(error) => errors.push(format!("  {url}: {error}")),
     }
-    Err(error) => Err(error),
   }
+  Err(errors.join("\n"))
+}
+
+fn resolve_remote_tags(repository: &str, origin: &ModuleOrigin, options: &GraphOptions) -> Result<Vec<String>, String> {
+  let urls = fetch_urls(repository, origin, options)?;
+  first_reachable(&urls, inspect_remote_tags).map_err(|e| format!("failed to list tags of {repository}:\n{e}"))
+}
+
+fn inspect_remote_tags(url: &str) -> Result<Vec<String>, String> {
+  let output = Command::new("git")
+    .env("GIT_TERMINAL_PROMPT", "0")
+    .args(["ls-remote", "--tags", "--refs", url])
+    .output()
+    .map_err(|e| format!("failed to list tags of {url}: {e}"))?;
+  if !output.status.success() {
+    return Err(format!(
+      "failed to list tags of {url}: {}",
+      String::from_utf8_lossy(&output.stderr).trim()
+    ));
+  }
+  Ok(
+    String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .filter_map(|line| line.split_once("\trefs/tags/").map(|(_, tag)| tag.to_string()))
+      .collect(),
+  )
 }
 
 fn is_full_commit_hash(reference: &str) -> bool {
@@ -772,22 +1271,30 @@ fn collect_warnings(
         "warning: {repository}@{} is a pinned commit without SemVer release metadata",
         module.reference
       )),
-      RefKind::Tag => {}
+      RefKind::Directory => warnings.push(format!(
+        "warning: {repository} is a local directory and currently snapshots to {}; run caps again after editing it",
+        module.commit
+      )),
+      RefKind::Tag | RefKind::Archive => {}
     }
     if let Some(module_requests) = requests.get(repository) {
       let distinct = module_requests.iter().map(|request| &request.reference).collect::<BTreeSet<_>>();
-      if distinct.len() > 1 {
-        let reason =
-          if root.get(repository) == Some(&module.reference) && distinct.iter().any(|value| parse_tag_version(value).is_none()) {
-            "root override"
-          } else if distinct.iter().any(|value| parse_tag_version(value).is_none()) {
-            "published SemVer preferred over mutable ref"
-          } else {
-            "highest requested SemVer"
-          };
+      let origins = module_requests.iter().map(|request| &request.origin).collect::<BTreeSet<_>>();
+      if distinct.len() > 1 || origins.len() > 1 {
+        let reason = if origins.len() > 1 {
+          "root source override"
+        } else if distinct.iter().any(|value| is_version_range(value)) {
+          "highest tag matching SemVer ranges"
+        } else if root.get(repository) == Some(&module.reference) && distinct.iter().any(|value| parse_tag_version(value).is_none()) {
+          "root override"
+        } else if distinct.iter().any(|value| parse_tag_version(value).is_none()) {
+          "published SemVer preferred over mutable ref"
+        } else {
+          "highest requested SemVer"
+        };
         let sources = module_requests
           .iter()
-          .map(|request| format!("{} by {}", request.reference, request.requested_by.as_deref().unwrap_or("root")))
+          .map(|request| format!("{} by {}", request.spec(), request.requested_by.as_deref().unwrap_or("root")))
           .collect::<Vec<_>>()
           .join(", ");
         warnings.push(format!(
@@ -1061,11 +1568,12 @@ pub fn verify_native_library(path: &Path) -> Result<(), String> {
   let abi: libloading::Symbol<VersionFn> =
     unsafe { library.get(b"abi_version") }.map_err(|e| format!("failed to read abi_version from {}: {e}", path.display()))?;
   let actual_abi = abi();
-  if actual_abi != calcit::FFI_ABI_VERSION {
+  if !calcit::is_compatible_ffi_abi(&actual_abi) {
     return Err(format!(
-      "FFI ABI mismatch in {}: found {actual_abi}, expected {}",
+      "FFI ABI mismatch in {}: found {actual_abi}, expected {} or {}",
       path.display(),
-      calcit::FFI_ABI_VERSION
+      calcit::FFI_ABI_VERSION,
+      calcit::FFI_LEGACY_ABI_VERSION
     ));
   }
   let edn: libloading::Symbol<VersionFn> =
@@ -1397,7 +1905,7 @@ fn copy_tree(source: &Path, target: &Path) -> Result<(), String> {
   fs::create_dir_all(target).map_err(|e| format!("failed to create {}: {e}", target.display()))?;
   for entry in fs::read_dir(source).map_err(|e| format!("failed to read {}: {e}", source.display()))? {
     let entry = entry.map_err(|e| e.to_string())?;
-    if entry.file_name() == ".git" || entry.file_name() == "target" {
+    if entry.file_name() == ".git" || entry.file_name() == ".calcit" || entry.file_name() == "target" {
       continue;
     }
     let source_path = entry.path();
@@ -1410,14 +1918,11 @@ fn copy_tree(source: &Path, target: &Path) -> Result<(), String> {
         .map_err(|e| format!("failed to copy {} to {}: {e}", source_path.display(), target_path.display()))?;
     } else if kind.is_symlink() {
       return Err(format!(
-        "native module source contains unsupported symlink {}; replace it with a regular file or directory",
+        "module source contains unsupported symlink {}; replace it with a regular file or directory",
         source_path.display()
       ));
     } else {
-      return Err(format!(
-        "native module source contains unsupported file type at {}",
-        source_path.display()
-      ));
+      return Err(format!("module source contains unsupported file type at {}", source_path.display()));
     }
   }
   Ok(())
@@ -1516,22 +2021,47 @@ fn sanitize(value: &str) -> String {
 #[cfg(test)]
 mod tests {
   use super::{
-    DependencyRequest, GraphOptions, RefKind, ResolvedGraph, ResolvedModule, clean_version_store, ensure_store_metadata,
-    install_project_view_with, is_full_commit_hash, parse_tag_version, read_module_dependencies, select_reference,
-    sorted_root_dependencies, verify_native_receipt, write_modules_agents, write_native_receipt,
+    DependencyRequest, DependencySpec, GraphOptions, ModuleOrigin, RefKind, ResolvedGraph, ResolvedModule, choose_reference,
+    clean_version_store, ensure_store_metadata, install_project_view_with, is_full_commit_hash, parse_tag_version, pick_range_tag,
+    read_module_dependencies, resolve_graph, select_reference, sorted_root_dependencies, verify_native_receipt, write_modules_agents,
+    write_native_receipt,
   };
   use crate::PackageDeps;
-  use std::collections::{BTreeSet, HashMap};
+  use crate::caps_lock::{LockedModule, Lockfile};
+  use crate::caps_mirror::{Mirror, VENDOR_DIR, vendor_graph};
+  use semver::{Version, VersionReq};
+  use sha2::{Digest, Sha256};
+  use std::collections::{BTreeMap, BTreeSet, HashMap};
   use std::fs;
+  use std::path::Path;
+  use std::process::Command;
   use std::sync::{Arc, Barrier};
 
   fn request(reference: &str, requested_by: Option<&str>) -> DependencyRequest {
     DependencyRequest {
       reference: reference.to_string(),
+      origin: ModuleOrigin::GitHub,
       requested_by: requested_by.map(str::to_string),
     }
   }
 
+  fn git(dir: &Path, args: &[&str]) {
+    let output = Command::new("git")
+      .current_dir(dir)
+      .args([
+        "-c",
+        "user.name=test",
+        "-c",
+        "user.email=test@example.com",
+        "-c",
+        "commit.gpgsign=false",
+      ])
+      .args(args)
+      .output()
+      .unwrap();
+    assert!(output.status.success(), "git {args:?}: {}", String::from_utf8_lossy(&output.stderr));
+  }
+
   #[test]
   fn selects_highest_requested_semver() {
     let requests = BTreeSet::from([request("0.9.2", Some("a@1.0.0")), request("0.10.0", None)]);
@@ -1542,6 +2072,258 @@ mod tests {
     assert!(parse_tag_version("v1.2.3").is_some());
   }
 
+  #[test]
+  fn ranges_pick_highest_matching_tag() {
+    let tags = ["0.3.9", "v0.4.1", "0.4.3", "0.5.0", "0.4.4-a1", "main"].map(str::to_string);
+    let caret = VersionReq::parse("^0.4").unwrap();
+    assert_eq!(pick_range_tag(std::slice::from_ref(&caret), None, &tags), Some("0.4.3".to_string()));
+    let tilde = VersionReq::parse("~0.4.1").unwrap();
+    assert_eq!(
+      pick_range_tag(&[caret.clone(), tilde], Some(&Version::new(0, 4, 1)), &tags),
+      Some("0.4.3".to_string())
+    );
+    assert_eq!(pick_range_tag(&[caret], Some(&Version::new(0, 5, 0)), &tags), None);
+  }
+
+  #[test]
+  fn ranges_keep_matching_locked_tag() {
+    let root = std::env::temp_dir().join(format!("calcit-caps-range-{}", std::process::id()));
+    let mut options = GraphOptions {
+      project_root: root.clone(),
+      modules_dir: root.join("modules"),
+      ci: true,
+      strict: false,
+      build_native: false,
+      lock: Some(Arc::new(Lockfile {
+        modules: BTreeMap::from([(
+          "org/repo".to_string(),
+          LockedModule {
+            reference: "0.4.1".to_string(),
+            kind: RefKind::Tag,
+            commit: "a".repeat(40),
+          },
+        )]),
+      })),
+      frozen: true,
+      mirror: None,
+      offline: false,
+    };
+    let mut tags = BTreeMap::new();
+    let requests = BTreeSet::from([request("^0.4", None), request("~0.4.0", Some("lib@1.0.0"))]);
+    assert_eq!(
+      choose_reference("org/repo", &requests, &options, &mut tags).map(|spec| spec.to_string()),
+      Ok("0.4.1".to_string())
+    );
+    assert!(tags.is_empty());
+
+    let requests = BTreeSet::from([request("^0.5", None)]);
+    let error = choose_reference("org/repo", &requests, &options, &mut tags).unwrap_err();
+    assert!(error.contains("deps.lock.cirru is out of date"), "{error}");
+    options.frozen = false;
+    let requests = BTreeSet::from([request("^zero", None)]);
+    assert!(choose_reference("org/repo", &requests, &options, &mut tags).is_err());
+  }
+
+  #[test]
+  fn dependency_specs_parse_sources() {
+    let root = std::env::temp_dir().join(format!("calcit-caps-specs-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("lib")).unwrap();
+    let root = fs::canonicalize(root).unwrap();
+
+    let git = DependencySpec::parse("git+https://gitlab.com/team/lib.git#^1.2", None).unwrap();
+    assert_eq!(git.origin, ModuleOrigin::Git("https://gitlab.com/team/lib.git".to_string()));
+    assert_eq!(git.reference, "^1.2");
+    assert!(DependencySpec::parse("git+https://gitlab.com/team/lib.git", None).is_err());
+
+    let directory = DependencySpec::parse("file:lib", Some(&root)).unwrap();
+    assert_eq!(directory.origin, ModuleOrigin::Directory(root.join("lib")));
+    assert_eq!(DependencySpec::parse(&directory.to_string(), None), Ok(directory));
+    assert!(DependencySpec::parse("file:lib", None).is_err());
+
+    let digest = "ab".repeat(32);
+    let archive = DependencySpec::parse(&format!("https://example.com/lib-1.0.0.tar.gz#sha256={digest}"), None).unwrap();
+    assert_eq!(archive.reference, format!("sha256={digest}"));
+    assert!(DependencySpec::parse("https://example.com/lib.zip#sha256=00", None).is_err());
+    assert_eq!(DependencySpec::parse("0.4.3", None).unwrap().origin, ModuleOrigin::GitHub);
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn resolves_git_urls_local_directories_and_archives() {
+    let root = std::env::temp_dir().join(format!("calcit-caps-sources-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let root = fs::canonicalize(root).unwrap();
+    let empty_deps = "{} (:dependencies $ {})\n";
+
+    let work = root.join("work");
+    fs::create_dir_all(&work).unwrap();
+    git(&work, &["init", "-q"]);
+    fs::write(work.join("deps.cirru"), empty_deps).unwrap();
+    git(&work, &["add", "-A"]);
+    git(&work, &["commit", "-qm", "first"]);
+    git(&work, &["tag", "1.0.0"]);
+    fs::write(work.join("calcit.cirru"), "{}\n").unwrap();
+    git(&work, &["add", "-A"]);
+    git(&work, &["commit", "-qm", "second"]);
+    git(&work, &["tag", "1.1.0"]);
+    let remote = root.join("lib.git");
+    git(&root, &["clone", "-q", "--bare", work.to_str().unwrap(), remote.to_str().unwrap()]);
+
+    let shared = root.join("shared");
+    fs::create_dir_all(shared.join(".calcit")).unwrap();
+    fs::write(shared.join(".calcit/state"), "ignored").unwrap();
+    fs::write(
+      shared.join("deps.cirru"),
+      format!("{{}} $ :dependencies $ {{}} (|team/lib |git+file://{}#^1.0)\n", remote.display()),
+    )
+    .unwrap();
+
+    fs::create_dir_all(root.join("pack/pack-1.0.0")).unwrap();
+    fs::write(root.join("pack/pack-1.0.0/deps.cirru"), empty_deps).unwrap();
+    let status = Command::new("tar")
+      .args(["-czf", "pack.tar.gz", "-C", "pack", "pack-1.0.0"])
+      .current_dir(&root)
+      .status()
+      .unwrap();
+    assert!(status.success());
+    let digest = hex::encode(Sha256::digest(fs::read(root.join("pack.tar.gz")).unwrap()));
+
+    let project_root = root.join("project");
+    fs::create_dir_all(&project_root).unwrap();
+    let deps = |archive_digest: &str| PackageDeps {
+      version: None,
+      calcit_version: None,
+      dependencies: HashMap::from([
+        (Arc::from("team/shared"), Arc::from("file:../shared")),
+        (
+          Arc::from("team/pack"),
+          Arc::from(format!("file:../pack.tar.gz#sha256={archive_digest}").as_str()),
+        ),
+      ]),
+      dev_dependencies: HashMap::new(),
+    };
+    let mut options = GraphOptions {
+      project_root: project_root.clone(),
+      modules_dir: root.join("cache/modules"),
+      ci: true,
+      strict: false,
+      build_native: false,
+      lock: None,
+      frozen: false,
+      mirror: None,
+      offline: false,
+    };
+
+    let graph = resolve_graph(&deps(&digest), &options).unwrap();
+    let lib = &graph.modules["team/lib"];
+    assert_eq!((lib.reference.as_str(), &lib.kind), ("1.1.0", &RefKind::Tag));
+    assert!(lib.source.join("calcit.cirru").exists());
+    let shared_module = &graph.modules["team/shared"];
+    assert_eq!(shared_module.kind, RefKind::Directory);
+    assert!(!shared_module.source.join(".calcit").exists());
+    let pack = &graph.modules["team/pack"];
+    assert_eq!(pack.kind, RefKind::Archive);
+    assert!(pack.source.join("deps.cirru").exists());
+    assert!(pack.source.starts_with(root.join("cache/module-caches/git/team/pack")));
+    assert!(
+      graph
+        .warnings
+        .iter()
+        .any(|warning| warning.contains("team/shared is a local directory"))
+    );
+
+    install_project_view_with(&graph, &options, |_| Ok(())).unwrap();
+    assert!(project_root.join(".calcit/modules/lib/calcit.cirru").exists());
+    assert!(project_root.join(".calcit/modules/pack/deps.cirru").exists());
+
+    options.lock = Some(Arc::new(Lockfile::from_graph(&graph)));
+    options.frozen = true;
+    let again = resolve_graph(&deps(&digest), &options).unwrap();
+    assert_eq!(again.modules["team/shared"].commit, shared_module.commit);
+    fs::write(shared.join("calcit.cirru"), "{}\n").unwrap();
+    let error = resolve_graph(&deps(&digest), &options).unwrap_err();
+    assert!(error.contains("team/shared is locked at local@"), "{error}");
+
+    options.frozen = false;
+    let error = resolve_graph(&deps(&"0".repeat(64)), &options).unwrap_err();
+    assert!(error.contains("checksum mismatch"), "{error}");
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn vendored_modules_resolve_offline() {
+    let root = std::env::temp_dir().join(format!("calcit-caps-vendor-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let root = fs::canonicalize(root).unwrap();
+
+    let work = root.join("work");
+    fs::create_dir_all(&work).unwrap();
+    git(&work, &["init", "-q"]);
+    fs::write(work.join("deps.cirru"), "{} (:dependencies $ {})\n").unwrap();
+    git(&work, &["add", "-A"]);
+    git(&work, &["commit", "-qm", "first"]);
+    git(&work, &["tag", "1.0.0"]);
+    fs::write(work.join("calcit.cirru"), "{}\n").unwrap();
+    git(&work, &["add", "-A"]);
+    git(&work, &["commit", "-qm", "second"]);
+    git(&work, &["tag", "1.1.0"]);
+    let remote = root.join("lib.git");
+    git(&root, &["clone", "-q", "--bare", work.to_str().unwrap(), remote.to_str().unwrap()]);
+
+    let project_root = root.join("project");
+    fs::create_dir_all(&project_root).unwrap();
+    let deps = PackageDeps {
+      version: None,
+      calcit_version: None,
+      dependencies: HashMap::from([(
+        Arc::from("team/lib"),
+        Arc::from(format!("git+file://{}#^1.0", remote.display()).as_str()),
+      )]),
+      dev_dependencies: HashMap::new(),
+    };
+    let mut options = GraphOptions {
+      project_root: project_root.clone(),
+      modules_dir: root.join("online/modules"),
+      ci: true,
+      strict: false,
+      build_native: false,
+      lock: None,
+      frozen: false,
+      mirror: None,
+      offline: false,
+    };
+    let graph = resolve_graph(&deps, &options).unwrap();
+    assert_eq!(vendor_graph(&graph, &project_root).unwrap(), 1);
+    assert!(project_root.join(VENDOR_DIR).join("team/lib.git").is_dir());
+    fs::remove_dir_all(&remote).unwrap();
+
+    options.modules_dir = root.join("offline/modules");
+    options.offline = true;
+    let error = resolve_graph(&deps, &options).unwrap_err();
+    assert!(error.contains("run caps without --offline"), "{error}");
+
+    options.lock = Some(Arc::new(Lockfile::from_graph(&graph)));
+    let error = resolve_graph(&deps, &options).unwrap_err();
+    assert!(error.contains("is not in the module cache"), "{error}");
+
+    options.mirror = Some(Arc::new(Mirror::load(&project_root.join(VENDOR_DIR)).unwrap()));
+    let offline = resolve_graph(&deps, &options).unwrap();
+    let lib = &offline.modules["team/lib"];
+    assert_eq!(lib.commit, graph.modules["team/lib"].commit);
+    assert!(lib.source.starts_with(root.join("offline/module-caches")));
+    assert!(lib.source.join("calcit.cirru").exists());
+
+    options.modules_dir = root.join("mirrored/modules");
+    options.offline = false;
+    options.lock = None;
+    let mirrored = resolve_graph(&deps, &options).unwrap();
+    assert_eq!(mirrored.modules["team/lib"].reference, "1.1.0");
+    fs::remove_dir_all(root).unwrap();
+  }
+
   #[test]
   fn incomparable_refs_need_root_decision() {
     let requests = BTreeSet::from([request("main", Some("a@1.0.0")), request("next", Some("b@1.0.0"))]);
@@ -1632,6 +2414,7 @@ mod tests {
       reference: "0.1.0".to_string(),
       commit: "0123456789abcdef0123456789abcdef01234567".to_string(),
       kind: RefKind::Tag,
+      origin: ModuleOrigin::GitHub,
       source: root.join("source"),
       link_target: realization.clone(),
       dependencies: Default::default(),
@@ -1767,6 +2550,10 @@ mod tests {
       ci: false,
       strict: false,
       build_native: false,
+      lock: None,
+      frozen: false,
+      mirror: None,
+      offline: false,
     };
     let result = install_project_view_with(&graph, &options, |_| Err("registration failed".to_string()));
     assert_eq!(result.unwrap_err(), "registration failed");
@@ -0,0 +1,201 @@
+//! `deps.lock.cirru`, the committed record of resolved module revisions.
+//!
+//! ```cirru.no-check
+//! {} (:lock-version 1)
+//!   :modules $ {}
+//!     |calcit-lang/lilac $ {} (:commit |4f0c...) (:kind :tag) (:ref |0.4.3)
+//! ```
+//!
+//! Ranges in `deps.cirru` prefer the locked tag while it still matches, and `caps --frozen`
+//! installs exactly the locked commits, failing when the lock does not match the graph.
+
+use crate::caps_graph::{RefKind, ResolvedGraph};
+use cirru_edn::{Edn, EdnMapView};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const LOCK_FILE: &str = "deps.lock.cirru";
+const LOCK_VERSION: f64 = 1.0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedModule {
+  pub reference: String,
+  pub kind: RefKind,
+  pub commit: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lockfile {
+  pub modules: BTreeMap<String, LockedModule>,
+}
+
+fn string_field(info: &EdnMapView, field: &str, repository: &str) -> Result<String, String> {
+  match info.get_or_nil(field) {
+    Edn::Str(s) => Ok((*s).to_owned()),
+    Edn::Tag(t) => Ok(t.ref_str().to_owned()),
+    v => Err(format!("invalid :{field} of {repository} in {LOCK_FILE}: {v}")),
+  }
+}
+
+impl Lockfile {
+  pub fn from_graph(graph: &ResolvedGraph) -> Self {
+    let modules = graph
+      .modules
+      .iter()
+      .map(|(repository, module)| {
+        (
+          repository.clone(),
+          LockedModule {
+            reference: module.reference.clone(),
+            kind: module.kind.clone(),
+            commit: module.commit.clone(),
+          },
+        )
+      })
+      .collect();
+    Lockfile { modules }
+  }
+
+  pub fn parse(content: &str) -> Result<Self, String> {
+    let data = cirru_edn::parse(content).map_err(|e| format!("failed to parse {LOCK_FILE}: {e}"))?;
+    let info = data.view_map()?;
+    match info.get_or_nil("lock-version") {
+      Edn::Number(n) if n == LOCK_VERSION => {}
+      v => return Err(format!("unsupported :lock-version in {LOCK_FILE}: {v}")),
+    }
+    let mut lock = Lockfile::default();
+    #[allow(clippy::mutable_key_type)]
+    let modules = match info.get_or_nil("modules") {
+      Edn::Nil => EdnMapView::default().0,
+      value => value.view_map()?.0,
+    };
+    for (key, value) in &modules {
+      let Edn::Str(repository) = key else {
+        return Err(format!("invalid module name in {LOCK_FILE}: {key}"));
+      };
+      let info = value.view_map()?;
+      let kind = string_field(&info, "kind", repository)?;
+      lock.modules.insert(
+        (**repository).to_owned(),
+        LockedModule {
+          reference: string_field(&info, "ref", repository)?,
+          kind: RefKind::parse(&kind).ok_or_else(|| format!("invalid :kind of {repository} in {LOCK_FILE}: {kind}"))?,
+          commit: string_field(&info, "commit", repository)?,
+        },
+      );
+    }
+    Ok(lock)
+  }
+
+  /// read the lock next to `deps.cirru`, `None` when the project has no lock yet
+  pub fn read(project_root: &Path) -> Result<Option<Self>, String> {
+    let path = project_root.join(LOCK_FILE);
+    if !path.exists() {
+      return Ok(None);
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    Self::parse(&content).map(Some)
+  }
+
+  pub fn format(&self) -> Result<String, String> {
+    let mut modules = EdnMapView::default();
+    for (repository, module) in &self.modules {
+      let mut info = EdnMapView::default();
+      info.insert(Edn::tag("ref"), Edn::str(module.reference.as_str()));
+      info.insert(Edn::tag("kind"), Edn::tag(module.kind.as_str()));
+      info.insert(Edn::tag("commit"), Edn::str(module.commit.as_str()));
+      modules.insert(Edn::str(repository.as_str()), Edn::Map(info));
+    }
+    let mut root = EdnMapView::default();
+    root.insert(Edn::tag("lock-version"), Edn::Number(LOCK_VERSION));
+    root.insert(Edn::tag("modules"), Edn::Map(modules));
+    let mut content = cirru_edn::format(&Edn::Map(root), false)?.trim_start().to_owned();
+    if !content.ends_with('\n') {
+      content.push('\n');
+    }
+    Ok(content)
+  }
+
+  /// write the lock when its content changed, returns whether the file was written
+  pub fn write(&self, project_root: &Path) -> Result<bool, String> {
+    let path = project_root.join(LOCK_FILE);
+    if Self::read(project_root).ok().flatten().as_ref() == Some(self) {
+      return Ok(false);
+    }
+    let temp = project_root.join(format!("{LOCK_FILE}.{}.tmp", std::process::id()));
+    fs::write(&temp, self.format()?).map_err(|e| format!("failed to write {}: {e}", temp.display()))?;
+    fs::rename(&temp, &path).map_err(|e| format!("failed to activate {}: {e}", path.display()))?;
+    Ok(true)
+  }
+
+  /// differences from `expected`, one line per module
+  pub fn diff(&self, expected: &Lockfile) -> Vec<String> {
+    let mut lines = vec![];
+    for (repository, module) in &expected.modules {
+      match self.modules.get(repository) {
+        None => lines.push(format!("  {repository}@{} is not locked", module.reference)),
+        Some(locked) if locked != module => lines.push(format!(
+          "  {repository} is locked at {}@{} but resolves to {}@{}",
+          locked.reference, locked.commit, module.reference, module.commit
+        )),
+        Some(_) => {}
+      }
+    }
+    for (repository, locked) in &self.modules {
+      if !expected.modules.contains_key(repository) {
+        lines.push(format!("  {repository}@{} is locked but no longer required", locked.reference));
+      }
+    }
+    lines
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sample() -> Lockfile {
+    Lockfile {
+      modules: BTreeMap::from([
+        (
+          "org/lib".to_owned(),
+          LockedModule {
+            reference: "0.4.3".to_owned(),
+            kind: RefKind::Tag,
+            commit: "0123456789abcdef0123456789abcdef01234567".to_owned(),
+          },
+        ),
+        (
+          "org/tool".to_owned(),
+          LockedModule {
+            reference: "main".to_owned(),
+            kind: RefKind::Branch,
+            commit: "89abcdef0123456789abcdef0123456789abcdef".to_owned(),
+          },
+        ),
+      ]),
+    }
+  }
+
+  #[test]
+  fn lockfile_round_trips_and_reports_drift() {
+    let lock = sample();
+    let parsed = Lockfile::parse(&lock.format().unwrap()).unwrap();
+    assert_eq!(parsed, lock);
+    assert!(lock.diff(&parsed).is_empty());
+
+    let mut moved = lock.clone();
+    moved.modules.get_mut("org/tool").unwrap().commit = "f".repeat(40);
+    moved.modules.remove("org/lib");
+    let diff = lock.diff(&moved);
+    assert_eq!(diff.len(), 2);
+    assert!(diff[0].contains("org/tool is locked at main@89abcdef"));
+    assert!(diff[1].contains("org/lib@0.4.3 is locked but no longer required"));
+  }
+
+  #[test]
+  fn rejects_unknown_lock_versions() {
+    assert!(Lockfile::parse("{} (:lock-version 2) (:modules $ {})").is_err());
+  }
+}
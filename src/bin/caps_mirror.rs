@@ -0,0 +1,168 @@
+//! Mirrors replace the upstream locations `caps` fetches from.
+//!
+//! A directory mirror holds bare repositories at `<dir>/<owner>/<repo>.git`, the layout written
+//! by `caps vendor`. A rewrite mirror is a `.cirru` map of URL prefixes:
+//!
+//! ```cirru.no-check
+//! {}
+//!   |https://github.com/ |https://git.example.com/github/
+//!   |https://example.com/archives/ |file:/srv/archives/
+//! ```
+//!
+//! The longest matching prefix wins, URLs without a match are fetched from upstream.
+
+use crate::caps_graph::{ModuleOrigin, RefKind, ResolvedGraph};
+use crate::git::GitRepo;
+use cirru_edn::Edn;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const VENDOR_DIR: &str = "caps-vendor";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mirror {
+  Directory(PathBuf),
+  Rewrite(Vec<(String, String)>),
+}
+
+impl Mirror {
+  /// a directory becomes a directory mirror, a file is read as a rewrite map
+  pub fn load(path: &Path) -> Result<Self, String> {
+    if path.is_dir() {
+      let dir = fs::canonicalize(path).map_err(|e| format!("failed to resolve mirror {}: {e}", path.display()))?;
+      return Ok(Mirror::Directory(dir));
+    }
+    let content = fs::read_to_string(path).map_err(|e| format!("failed to read mirror {}: {e}", path.display()))?;
+    let data = cirru_edn::parse(&content).map_err(|e| format!("failed to parse mirror {}: {e}", path.display()))?;
+    let mut rules = vec![];
+    for (key, value) in &data.view_map()?.0 {
+      match (key, value) {
+        (Edn::Str(from), Edn::Str(to)) => rules.push(((**from).to_owned(), (**to).to_owned())),
+        _ => return Err(format!("invalid mirror rule in {}: {key} {value}", path.display())),
+      }
+    }
+    rules.sort_by(|left, right| right.0.len().cmp(&left.0.len()).then_with(|| left.0.cmp(&right.0)));
+    Ok(Mirror::Rewrite(rules))
+  }
+
+  /// bare repository of `owner/repo` in a directory mirror
+  pub fn repository(&self, repository: &str) -> Option<PathBuf> {
+    match self {
+      Mirror::Directory(dir) => Some(dir.join(format!("{repository}.git"))),
+      Mirror::Rewrite(_) => None,
+    }
+  }
+
+  /// fetch URLs replacing `upstream`, an error when a directory mirror lacks the repository
+  pub fn git_urls(&self, repository: &str, upstream: Vec<String>) -> Result<Vec<String>, String> {
+    match self {
+      Mirror::Directory(dir) => {
+        let path = dir.join(format!("{repository}.git"));
+        if path.is_dir() {
+          Ok(vec![format!("file://{}", path.display())])
+        } else {
+          Err(format!(
+            "{repository} is not in mirror {}; run caps vendor or add it to the mirror",
+            dir.display()
+          ))
+        }
+      }
+      Mirror::Rewrite(_) => {
+        let mut urls = vec![];
+        for url in upstream.iter().map(|url| self.rewrite(url)) {
+          if !urls.contains(&url) {
+            urls.push(url);
+          }
+        }
+        Ok(urls)
+      }
+    }
+  }
+
+  pub fn rewrite(&self, url: &str) -> String {
+    match self {
+      Mirror::Rewrite(rules) => rules
+        .iter()
+        .find_map(|(from, to)| url.strip_prefix(from.as_str()).map(|rest| format!("{to}{rest}")))
+        .unwrap_or_else(|| url.to_string()),
+      Mirror::Directory(_) => url.to_string(),
+    }
+  }
+
+  pub fn is_local(&self) -> bool {
+    matches!(self, Mirror::Directory(_))
+  }
+}
+
+/// Write a bare repository for every fetched module of `graph` into `<project_root>/caps-vendor`,
+/// replacing the previous contents. Local directory modules are skipped.
+/// Returns the number of vendored modules.
+pub fn vendor_graph(graph: &ResolvedGraph, project_root: &Path) -> Result<usize, String> {
+  let target = project_root.join(VENDOR_DIR);
+  let temp = project_root.join(format!("{VENDOR_DIR}.{}.tmp", std::process::id()));
+  let previous = project_root.join(format!("{VENDOR_DIR}.{}.old", std::process::id()));
+  for path in [&temp, &previous] {
+    if path.exists() {
+      fs::remove_dir_all(path).map_err(|e| format!("failed to remove {}: {e}", path.display()))?;
+    }
+  }
+  fs::create_dir_all(&temp).map_err(|e| format!("failed to create {}: {e}", temp.display()))?;
+  let mut count = 0;
+  for (repository, module) in &graph.modules {
+    if matches!(module.origin, ModuleOrigin::Directory(_)) {
+      continue;
+    }
+    let target_ref = match module.kind {
+      RefKind::Tag => format!("refs/tags/{}", module.reference),
+      RefKind::Branch => format!("refs/heads/{}", module.reference),
+      RefKind::Commit | RefKind::Directory | RefKind::Archive => format!("refs/caps/{}", module.commit),
+    };
+    let bare = temp.join(format!("{repository}.git"));
+    GitRepo::init_bare(&bare)?;
+    GitRepo {
+      dir: module.source.clone(),
+    }
+    .push_to_path(&bare, &module.commit, &target_ref)
+    .map_err(|e| format!("failed to vendor {repository}@{}: {e}", module.reference))?;
+    count += 1;
+  }
+  if target.exists() {
+    fs::rename(&target, &previous).map_err(|e| format!("failed to move {}: {e}", target.display()))?;
+  }
+  fs::rename(&temp, &target).map_err(|e| format!("failed to activate {}: {e}", target.display()))?;
+  if previous.exists() {
+    fs::remove_dir_all(&previous).map_err(|e| format!("failed to remove {}: {e}", previous.display()))?;
+  }
+  Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rewrite_mirrors_use_the_longest_prefix() {
+    let root = std::env::temp_dir().join(format!("calcit-caps-mirror-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("dir/org/lib.git")).unwrap();
+    let rules = root.join("mirror.cirru");
+    fs::write(
+      &rules,
+      "{} (|https://github.com/ |https://git.example.com/gh/) (|https://github.com/org/ |file:///srv/org/)\n",
+    )
+    .unwrap();
+
+    let mirror = Mirror::load(&rules).unwrap();
+    assert_eq!(mirror.rewrite("https://github.com/org/lib.git"), "file:///srv/org/lib.git");
+    assert_eq!(
+      mirror.rewrite("https://github.com/other/lib.git"),
+      "https://git.example.com/gh/other/lib.git"
+    );
+    assert_eq!(mirror.rewrite("https://gitlab.com/team/lib.git"), "https://gitlab.com/team/lib.git");
+
+    let directory = Mirror::load(&root.join("dir")).unwrap();
+    assert!(directory.git_urls("org/lib", vec![]).unwrap()[0].ends_with("/dir/org/lib.git"));
+    assert!(directory.git_urls("org/missing", vec![]).is_err());
+    fs::remove_dir_all(root).unwrap();
+  }
+}
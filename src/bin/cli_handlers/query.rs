@@ -800,12 +800,16 @@ mod type_query_tests {
   use crate::cli_handlers::test_support::TestProject;
 
   fn on_cli_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let context = calcit::runtime_context::current();
     std::thread::Builder::new()
       .name("calcit-query-test".into())
       // Core preprocessing and context rendering are recursive; mirror the
       // CLI worker instead of relying on Rust's smaller test-thread stack.
       .stack_size(16 * 1024 * 1024)
-      .spawn(f)
+      .spawn(move || {
+        let _entered = context.enter();
+        f()
+      })
       .expect("query test thread should start")
       .join()
       .expect("query test thread should finish")
@@ -1011,7 +1015,7 @@ mod type_query_tests {
 
   #[test]
   fn number_type_query_uses_static_dispatch_metadata() {
-    let _guard = crate::GLOBAL_TEST_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let _context = calcit::runtime_context::RuntimeContext::new().enter();
     let snapshot = load_core_snapshot().expect("core snapshot should load");
     prepare_program_for_type_query_on_cli_stack(snapshot);
 
@@ -1042,6 +1046,10 @@ mod type_query_tests {
         ".eq?",
         ".add",
         ".multiply",
+        // trait defaults follow every explicit method
+        ".not-eq?",
+        ".max",
+        ".min",
       ]
     );
   }
@@ -1070,7 +1078,7 @@ mod type_query_tests {
 
   #[test]
   fn special_builtin_context_preserves_examples_and_intent() {
-    let _guard = crate::GLOBAL_TEST_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let _context = calcit::runtime_context::RuntimeContext::new().enter();
     let snapshot = load_core_snapshot().expect("core snapshot should load");
     let envelope = on_cli_stack(move || {
       let meta = lookup_special_builtin_query_meta("calcit.core", "to-js-data")
@@ -1107,7 +1115,7 @@ mod type_query_tests {
 
   #[test]
   fn regular_context_carries_snapshot_revision_and_tree_location() {
-    let _guard = crate::GLOBAL_TEST_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let _context = calcit::runtime_context::RuntimeContext::new().enter();
     let snapshot = load_core_snapshot().expect("core snapshot should load");
     let (envelope, expected_revision) = on_cli_stack(move || {
       let entry = snapshot
@@ -1130,7 +1138,7 @@ mod type_query_tests {
 
   #[test]
   fn project_function_schema_argument_resolves_source_backed_struct() {
-    let _guard = crate::GLOBAL_TEST_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let _context = calcit::runtime_context::RuntimeContext::new().enter();
     let snapshot = load_snapshot("calcit/test.cirru").expect("test snapshot should load");
     prepare_program_for_type_query_on_cli_stack(snapshot.clone());
     let schema = program::lookup_def_schema("test-struct.main", "sum-point");
@@ -20,8 +20,8 @@ mod quality_gate;
 #[path = "../type_coverage.rs"]
 mod type_coverage;
 
-#[cfg(test)]
-static GLOBAL_TEST_LOCK: std::sync::LazyLock<std::sync::Mutex<()>> = std::sync::LazyLock::new(|| std::sync::Mutex::new(()));
+#[global_allocator]
+static ALLOCATOR: calcit::runner::sandbox::CountingAlloc = calcit::runner::sandbox::CountingAlloc;
 
 #[cfg(test)]
 #[path = "cr_tests/type_fail.rs"]
@@ -44,8 +44,12 @@ use notify::RecursiveMode;
 use notify_debouncer_mini::new_debouncer;
 
 use calcit::{
-  ProgramEntries, builtins, call_stack, cli_args, codegen, codegen::COMPILE_ERRORS_FILE, codegen::emit_js::gen_stack, program, runner,
-  snapshot, util,
+  ProgramEntries, builtins, call_stack, cli_args, codegen,
+  codegen::COMPILE_ERRORS_FILE,
+  codegen::emit_js::bundle::{self, BundleFormat, BundleOptions},
+  codegen::emit_js::gen_stack,
+  codegen::gen_ir::IrFormat,
+  program, runner, snapshot, util,
 };
 use cirru_edn::EdnTag;
 use cirru_parser::Cirru;
@@ -121,8 +125,39 @@ fn main() -> Result<(), String> {
   }
 }
 
+fn sandbox_policy(cli_args: &ToplevelCalcit) -> Result<Option<runner::sandbox::SandboxPolicy>, String> {
+  let uses_allowances = !cli_args.allow_read.is_empty()
+    || !cli_args.allow_write.is_empty()
+    || cli_args.allow_env
+    || cli_args.allow_ffi
+    || cli_args.allow_net
+    || cli_args.sandbox_timeout_ms.is_some()
+    || cli_args.sandbox_memory_mb.is_some()
+    || cli_args.sandbox_steps.is_some();
+  if !cli_args.sandbox {
+    return if uses_allowances {
+      Err(String::from("`--allow-*` and `--sandbox-*` options require `--sandbox`"))
+    } else {
+      Ok(None)
+    };
+  }
+  Ok(Some(runner::sandbox::SandboxPolicy {
+    fs_read: cli_args.allow_read.iter().map(PathBuf::from).collect(),
+    fs_write: cli_args.allow_write.iter().map(PathBuf::from).collect(),
+    env: cli_args.allow_env,
+    ffi: cli_args.allow_ffi,
+    net: cli_args.allow_net,
+    timeout: cli_args.sandbox_timeout_ms.map(Duration::from_millis),
+    memory_budget: cli_args.sandbox_memory_mb.map(|mb| mb * 1024 * 1024),
+    step_budget: cli_args.sandbox_steps,
+  }))
+}
+
 fn run_cli() -> Result<(), String> {
-  let cli_args: ToplevelCalcit = argh::from_env();
+  run_cli_with(argh::from_env())
+}
+
+fn run_cli_with(cli_args: ToplevelCalcit) -> Result<(), String> {
   cli_handlers::warn_on_global_temp_snapshot_path(&cli_args.input);
   calcit::project_state::set_active_project_directory_from_snapshot(&cli_args.input);
 
@@ -143,6 +178,7 @@ fn run_cli() -> Result<(), String> {
 
   // Query/analyze commands may run preprocessing before the normal program-loading path.
   runner::preprocess::set_warn_dyn_method(cli_args.warn_dyn_method);
+  runner::preprocess::set_optimize(cli_args.optimize);
   runner::preprocess::set_verbose_preprocess(cli_args.verbose);
 
   if cli_handlers::should_echo_command(&cli_args) {
@@ -156,6 +192,10 @@ fn run_cli() -> Result<(), String> {
   #[cfg(not(target_arch = "wasm32"))]
   injection::inject_platform_apis();
 
+  if let Some(policy) = sandbox_policy(&cli_args)? {
+    runner::sandbox::install(policy)?;
+  }
+
   // Handle standalone commands that don't need full program loading
   match &cli_args.subcommand {
     Some(CalcitCommand::Query(query_cmd)) => {
@@ -182,6 +222,9 @@ fn run_cli() -> Result<(), String> {
     Some(CalcitCommand::Config(config_cmd)) => {
       return cli_handlers::handle_config_command(config_cmd, &cli_args.input);
     }
+    Some(CalcitCommand::RunIr(run_ir_cmd)) => {
+      return run_from_ir(run_ir_cmd, &cli_args);
+    }
     Some(CalcitCommand::Analyze(analyze_cmd)) => match &analyze_cmd.subcommand {
       AnalyzeSubcommand::ProgramDiff(diff_cmd) => {
         return cli_handlers::handle_program_diff_command(diff_cmd, &cli_args.input);
@@ -382,13 +425,25 @@ fn run_cli() -> Result<(), String> {
     if cli_args.skip_arity_check {
       codegen::set_code_gen_skip_arity_check(true);
     }
-    run_codegen_with_timeout(&entries, &cli_args.emit_path, false, cli_args.timeout, cli_args.verbose)
+    run_codegen_with_timeout(
+      &entries,
+      &cli_args.emit_path,
+      js_codegen_target(&cli_args.subcommand),
+      cli_args.timeout,
+      cli_args.verbose,
+    )
   } else if let Some(CalcitCommand::EmitIr(ir_options)) = &cli_args.subcommand {
     if !ir_options.watch {
       // `calcit ir` defaults to once mode; use --watch/-w to keep watching
       eval_once = true;
     }
-    run_codegen_with_timeout(&entries, &cli_args.emit_path, true, cli_args.timeout, cli_args.verbose)
+    run_codegen_with_timeout(
+      &entries,
+      &cli_args.emit_path,
+      CodegenTarget::Ir(ir_options.format),
+      cli_args.timeout,
+      cli_args.verbose,
+    )
   } else if let Some(CalcitCommand::Analyze(analyze_cmd)) = &cli_args.subcommand {
     eval_once = true;
     match &analyze_cmd.subcommand {
@@ -443,12 +498,44 @@ fn run_cli() -> Result<(), String> {
   if !eval_once {
     runner::track::track_task_add();
     let args = cli_args.clone();
-    std::thread::spawn(move || watch_files(entries, args, assets_watch, configured_run_mode));
+    let context = calcit::runtime_context::current();
+    std::thread::spawn(move || {
+      let _entered = context.enter();
+      watch_files(entries, args, assets_watch, configured_run_mode)
+    });
   }
   runner::track::exit_when_cleared();
   Ok(())
 }
 
+/// Run `init-fn` of an IR file; the compiled program comes from the file, so
+/// nothing is read from the source snapshot or preprocessed again.
+fn run_from_ir(command: &cli_args::RunIrCommand, cli_args: &ToplevelCalcit) -> Result<(), String> {
+  if cli_args.disable_stack {
+    call_stack::set_using_stack(false);
+  }
+  let loaded = codegen::gen_ir::load_ir(Path::new(&command.path))?;
+  for (def, reason) in &loaded.skipped {
+    eprintln!("{} {def}: {reason}", "[run-ir] not loaded".yellow());
+  }
+  let init_fn = cli_args.init_fn.as_deref().unwrap_or(&loaded.init_fn);
+  let (init_ns, init_def) = util::string::extract_ns_def(init_fn)?;
+  if program::lookup_compiled_def(&init_ns, &init_def).is_none() {
+    return Err(format!("{init_fn} is not defined in {}", command.path));
+  }
+
+  let started_time = Instant::now();
+  let v = calcit::run_program_with_docs(init_ns.into(), init_def.into(), &[]).map_err(|e| {
+    LocatedWarning::print_list(&e.warnings);
+    e.msg
+  })?;
+  let duration = Instant::now().duration_since(started_time);
+  println!("{}{}", format!("took {}ms: ", duration.as_micros() as f64 / 1000.0).dimmed(), v);
+
+  runner::track::exit_when_cleared();
+  Ok(())
+}
+
 #[derive(Debug, Clone)]
 struct RunnableTest {
   namespace: String,
@@ -1065,9 +1152,21 @@ fn recall_program(
   // In practice, this could be enhanced to maintain documentation state
 
   let task = if should_emit_js(&settings.subcommand, configured_run_mode) {
-    run_codegen_with_timeout(entries, &settings.emit_path, false, settings.timeout, settings.verbose)
-  } else if let Some(CalcitCommand::EmitIr(_)) = settings.subcommand {
-    run_codegen_with_timeout(entries, &settings.emit_path, true, settings.timeout, settings.verbose)
+    run_codegen_with_timeout(
+      entries,
+      &settings.emit_path,
+      js_codegen_target(&settings.subcommand),
+      settings.timeout,
+      settings.verbose,
+    )
+  } else if let Some(CalcitCommand::EmitIr(ir_options)) = &settings.subcommand {
+    run_codegen_with_timeout(
+      entries,
+      &settings.emit_path,
+      CodegenTarget::Ir(ir_options.format),
+      settings.timeout,
+      settings.verbose,
+    )
   } else {
     // run from `reload_fn` after reload
     let started_time = Instant::now();
@@ -1155,19 +1254,47 @@ fn run_check_only(entries: &ProgramEntries) -> Result<(), String> {
   Ok(())
 }
 
+/// Output produced by `run_codegen`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CodegenTarget {
+  Js {
+    only_reachable: bool,
+    dts: bool,
+    /// output path of a single-file bundle, written after the modules
+    bundle: Option<(String, BundleFormat)>,
+  },
+  Ir(IrFormat),
+}
+
+fn js_codegen_target(subcommand: &Option<CalcitCommand>) -> CodegenTarget {
+  match subcommand {
+    Some(CalcitCommand::EmitJs(options)) => CodegenTarget::Js {
+      only_reachable: options.only_reachable,
+      dts: options.dts,
+      bundle: options.bundle.to_owned().map(|path| (path, options.bundle_format)),
+    },
+    _ => CodegenTarget::Js {
+      only_reachable: false,
+      dts: false,
+      bundle: None,
+    },
+  }
+}
+
 fn run_codegen_with_timeout(
   entries: &ProgramEntries,
   emit_path: &str,
-  ir_mode: bool,
+  target: CodegenTarget,
   timeout_secs: u64,
   verbose: bool,
 ) -> Result<(), String> {
   if timeout_secs == 0 {
-    return run_codegen(entries, emit_path, ir_mode, verbose);
+    return run_codegen(entries, emit_path, target, verbose);
   }
   let entries = entries.clone();
   let emit_path = emit_path.to_owned();
   let (tx, rx) = channel();
+  let context = calcit::runtime_context::current();
   std::thread::Builder::new()
     .name("calcit-codegen".into())
     // Macro/type preprocessing follows transitive definition dependencies and
@@ -1176,7 +1303,8 @@ fn run_codegen_with_timeout(
     // thread default so the CLI returns diagnostics instead of aborting.
     .stack_size(64 * 1024 * 1024)
     .spawn(move || {
-      let result = run_codegen(&entries, &emit_path, ir_mode, verbose);
+      let _entered = context.enter();
+      let result = run_codegen(&entries, &emit_path, target, verbose);
       let _ = tx.send(result);
     })
     .map_err(|err| format!("failed to start codegen thread: {err}"))?;
@@ -1189,7 +1317,7 @@ fn run_codegen_with_timeout(
   }
 }
 
-fn run_codegen(entries: &ProgramEntries, emit_path: &str, ir_mode: bool, verbose: bool) -> Result<(), String> {
+fn run_codegen(entries: &ProgramEntries, emit_path: &str, target: CodegenTarget, verbose: bool) -> Result<(), String> {
   let started_time = Instant::now();
   let phase = |name: &str| {
     if verbose {
@@ -1199,7 +1327,7 @@ fn run_codegen(entries: &ProgramEntries, emit_path: &str, ir_mode: bool, verbose
   phase("codegen started");
   codegen::set_codegen_mode(true);
 
-  if ir_mode {
+  if matches!(target, CodegenTarget::Ir(_)) {
     builtins::effects::modify_cli_running_mode(builtins::effects::CliRunningMode::Ir)?;
   } else {
     builtins::effects::modify_cli_running_mode(builtins::effects::CliRunningMode::Js)?;
@@ -1253,19 +1381,38 @@ fn run_codegen(entries: &ProgramEntries, emit_path: &str, ir_mode: bool, verbose
     let _ = fs::write(&js_file_path, no_error_code);
   }
 
-  if ir_mode {
-    phase("emitting IR");
-    match codegen::gen_ir::emit_ir(&entries.init_fn, &entries.reload_fn, emit_path) {
+  if let CodegenTarget::Js {
+    only_reachable,
+    dts,
+    bundle,
+  } = target
+  {
+    // TODO entry ns
+    phase("emitting JavaScript");
+    let roots = [(&*entries.init_ns, &*entries.init_def), (&*entries.reload_ns, &*entries.reload_def)];
+    let options = codegen::emit_js::JsEmitOptions {
+      reachable_roots: only_reachable.then_some(&roots[..]),
+      dts,
+    };
+    match codegen::emit_js::emit_js(&entries.init_ns, emit_path, options) {
       Ok(_) => (),
       Err(failure) => {
         call_stack::display_stack_with_docs(&failure, &gen_stack::get_gen_stack(), None, None)?;
         return Err(failure);
       }
     }
-  } else {
-    // TODO entry ns
-    phase("emitting JavaScript");
-    match codegen::emit_js::emit_js(&entries.init_ns, emit_path) {
+    if let Some((bundle_path, format)) = bundle {
+      phase("writing bundle");
+      let options = BundleOptions {
+        output: Path::new(&bundle_path),
+        format,
+      };
+      let count = bundle::write_bundle(code_emit_path, roots[0], roots[1], options)?;
+      println!("bundled {count} modules into {bundle_path}");
+    }
+  } else if let CodegenTarget::Ir(ir_format) = target {
+    phase("emitting IR");
+    match codegen::gen_ir::emit_ir(&entries.init_fn, &entries.reload_fn, emit_path, ir_format) {
       Ok(_) => (),
       Err(failure) => {
         call_stack::display_stack_with_docs(&failure, &gen_stack::get_gen_stack(), None, None)?;
@@ -1508,7 +1655,16 @@ fn run_js_examples(target_ns: &str, check_fn_name: &str, emit_path: &str) -> Res
     reload_ns: Arc::from(target_ns),
     reload_def: Arc::from(check_fn_name),
   };
-  run_codegen(&entries, emit_path, false, false)?;
+  run_codegen(
+    &entries,
+    emit_path,
+    CodegenTarget::Js {
+      only_reachable: false,
+      dts: false,
+      bundle: None,
+    },
+    false,
+  )?;
 
   let runner_path = Path::new(emit_path).join(format!(".calcit-check-examples-{}.mjs", std::process::id()));
   fs::write(&runner_path, js_examples_runner_source(target_ns, check_fn_name))
@@ -1649,6 +1805,47 @@ mod tests {
   use std::collections::BTreeSet;
   use std::fs;
 
+  /// run `calcit <args>` in a context of its own, the way `main` runs it
+  fn run_cli_in_own_context(args: &[&str]) -> Result<(), String> {
+    let args: Vec<String> = args.iter().map(|arg| (*arg).to_owned()).collect();
+    std::thread::Builder::new()
+      .stack_size(CLI_STACK_SIZE)
+      .spawn(move || {
+        let context = calcit::runtime_context::RuntimeContext::new();
+        let _entered = context.enter();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let cli_args = <ToplevelCalcit as argh::FromArgs>::from_args(&["calcit"], &args).expect("parse cli args");
+        run_cli_with(cli_args)
+      })
+      .expect("spawn cli thread")
+      .join()
+      .expect("cli thread panicked")
+  }
+
+  #[test]
+  fn sandboxed_eval_denies_files_ffi_and_quit() {
+    let dir = std::env::temp_dir().join(format!("calcit-cli-sandbox-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("create dir");
+    let secret = dir.join("secret.txt");
+    fs::write(&secret, "secret").expect("write secret");
+    let written = dir.join("written.txt");
+    let snippets = [
+      format!("read-file |{}", secret.display()),
+      format!("write-file |{} |leaked", written.display()),
+      String::from("&call-dylib-edn |libmissing.so |run"),
+      String::from("quit! 3"),
+    ];
+    for snippet in &snippets {
+      let error = run_cli_in_own_context(&["--sandbox", "eval", snippet]).expect_err("sandbox should deny the snippet");
+      assert!(error.contains("sandbox denies"), "snippet `{snippet}` failed with: {error}");
+    }
+    assert!(!written.exists(), "sandboxed write-file should not create a file");
+
+    let allowed = dir.to_string_lossy().to_string();
+    run_cli_in_own_context(&["--sandbox", "--allow-read", &allowed, "eval", &snippets[0]]).expect("allowed read should run");
+    fs::remove_dir_all(&dir).expect("clean dir");
+  }
+
   #[test]
   fn attaching_core_preserves_source_namespaces_and_fills_missing_ones() {
     let mut project = snapshot::Snapshot::default();
@@ -2788,7 +2985,7 @@ mod tests {
 
   #[test]
   fn affected_test_selection_uses_transitive_compiled_dependencies() {
-    let _guard = GLOBAL_TEST_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let _context = calcit::runtime_context::RuntimeContext::new().enter();
     builtins::effects::init_effects_states();
     injection::inject_platform_apis();
 
@@ -2847,7 +3044,7 @@ mod tests {
 
   #[test]
   fn default_test_scope_excludes_core_and_dependency_namespaces() {
-    let _guard = GLOBAL_TEST_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let _context = calcit::runtime_context::RuntimeContext::new().enter();
     builtins::effects::init_effects_states();
     injection::inject_platform_apis();
 
@@ -2917,7 +3114,7 @@ mod tests {
 
   #[test]
   fn test_filters_can_exclude_tags_and_require_a_match() {
-    let _guard = GLOBAL_TEST_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let _context = calcit::runtime_context::RuntimeContext::new().enter();
     builtins::effects::init_effects_states();
     injection::inject_platform_apis();
 
@@ -1,23 +1,24 @@
 use super::*;
+use calcit::calcit::{Calcit, CalcitErr, CalcitErrKind};
 use std::cell::RefCell;
 use std::fs;
 use std::path::Path;
-use std::sync::Once;
 
-static PLATFORM_INIT: Once = Once::new();
-
-fn lock_suite() -> std::sync::MutexGuard<'static, ()> {
-  super::GLOBAL_TEST_LOCK.lock().unwrap_or_else(|err| err.into_inner())
+/// Run `f` inside a fresh runtime context with platform procs registered, so suite
+/// runs see none of the program data, refs or switches of other tests.
+fn in_own_context<T>(f: impl FnOnce(&calcit::runtime_context::RuntimeContext) -> T) -> T {
+  let context = calcit::runtime_context::RuntimeContext::new();
+  let _entered = context.enter();
+  #[cfg(not(target_arch = "wasm32"))]
+  super::injection::inject_platform_apis();
+  f(&context)
 }
 
-fn load_and_run_cirru(path: &str) {
+/// Load the snapshot at `path` with its modules and core into program data,
+/// returning the init and reload entries as `(ns, def)` pairs.
+fn load_cirru_program(path: &str) -> ((String, String), (String, String)) {
   builtins::effects::init_effects_states();
 
-  PLATFORM_INIT.call_once(|| {
-    #[cfg(not(target_arch = "wasm32"))]
-    super::injection::inject_platform_apis();
-  });
-
   let content = fs::read_to_string(path).unwrap_or_else(|_| panic!("Failed to read: {path}"));
   let data = cirru_edn::parse(&content).unwrap_or_else(|e| panic!("Failed to parse {path}: {e}"));
   let mut snapshot = snapshot::load_snapshot_data(&data, path).unwrap_or_else(|e| panic!("Failed to load {path}: {e}"));
@@ -48,8 +49,12 @@ fn load_and_run_cirru(path: &str) {
   let selected_entry = snapshot.active_entry().expect("default entry");
   let config_init = selected_entry.init_fn.to_string();
   let config_reload = selected_entry.reload_fn.to_string();
-  let (init_ns, init_def) = util::string::extract_ns_def(&config_init).expect("extract init ns/def");
-  let (reload_ns, _reload_def) = util::string::extract_ns_def(&config_reload).expect("extract reload ns/def");
+  let reload = util::string::extract_ns_def(&config_reload).expect("extract reload ns/def");
+  (util::string::extract_ns_def(&config_init).expect("extract init ns/def"), reload)
+}
+
+fn load_and_run_cirru(path: &str) {
+  let ((init_ns, init_def), (reload_ns, _reload_def)) = load_cirru_program(path);
 
   program::clear_runtime_caches_for_reload(init_ns.clone().into(), reload_ns.clone().into(), true).expect("clear runtime caches");
 
@@ -74,17 +79,13 @@ fn load_and_run_cirru(path: &str) {
 }
 
 /// Run a test in a dedicated thread with a large stack to avoid stack overflows
-/// in deeply recursive Calcit evaluation.  The suite lock is acquired inside the
-/// spawned thread so it is held for the duration of the run and dropped before
-/// the thread exits.
+/// in deeply recursive Calcit evaluation.  The thread enters a context of its own,
+/// so suite runs go in parallel with other tests.
 fn run_with_large_stack(path: &'static str) {
   const STACK_SIZE: usize = 32 * 1024 * 1024; // 32 MiB
   std::thread::Builder::new()
     .stack_size(STACK_SIZE)
-    .spawn(move || {
-      let _guard = lock_suite();
-      load_and_run_cirru(path);
-    })
+    .spawn(move || in_own_context(|_| load_and_run_cirru(path)))
     .expect("spawn test thread")
     .join()
     .expect("test thread panicked");
@@ -101,3 +102,196 @@ fn cirru_test_suite() {
 fn cirru_test_recur_arity() {
   run_with_large_stack("calcit/test-recur-arity.cirru");
 }
+
+/// Emit the IR of a program the way `calcit ir` does, drop all program state,
+/// then run it from the IR alone.
+fn run_from_ir(path: &'static str, ir_format: codegen::gen_ir::IrFormat) {
+  std::thread::Builder::new()
+    .stack_size(32 * 1024 * 1024)
+    .spawn(move || {
+      in_own_context(|context| run_from_ir_in(context, path, ir_format));
+    })
+    .expect("spawn test thread")
+    .join()
+    .expect("test thread panicked");
+}
+
+fn run_from_ir_in(context: &calcit::runtime_context::RuntimeContext, path: &str, ir_format: codegen::gen_ir::IrFormat) {
+  let ((init_ns, init_def), (reload_ns, reload_def)) = load_cirru_program(path);
+  program::clear_runtime_caches_for_reload(init_ns.clone().into(), reload_ns.clone().into(), true).expect("clear runtime caches");
+  // like the CLI, touch builtin impls before codegen
+  runner::preprocess::ensure_ns_def_compiled(
+    calcit::calcit::CORE_NS,
+    calcit::calcit::BUILTIN_IMPLS_ENTRY,
+    &RefCell::new(vec![]),
+    &CallStackList::default(),
+  )
+  .expect("preprocess builtin impls");
+  let entries = ProgramEntries {
+    init_fn: format!("{init_ns}/{init_def}").into(),
+    init_ns: init_ns.clone().into(),
+    init_def: init_def.clone().into(),
+    reload_fn: format!("{reload_ns}/{reload_def}").into(),
+    reload_ns: reload_ns.into(),
+    reload_def: reload_def.into(),
+  };
+  let file_name = match ir_format {
+    codegen::gen_ir::IrFormat::Cirru => "program-ir.cirru",
+    codegen::gen_ir::IrFormat::Msgpack => "program-ir.msgpack",
+  };
+  let emit_dir = std::env::temp_dir().join(format!("calcit-run-ir-{}-{}-{file_name}", std::process::id(), init_ns));
+  let emitted = super::run_codegen(&entries, &emit_dir.to_string_lossy(), CodegenTarget::Ir(ir_format), false);
+  // codegen switched this thread's context into IR mode; running happens in eval mode
+  codegen::set_codegen_mode(false);
+  builtins::effects::modify_cli_running_mode(builtins::effects::CliRunningMode::Eval).expect("reset running mode");
+  emitted.expect("emit IR");
+
+  context.clear();
+  super::injection::inject_platform_apis();
+
+  let loaded = codegen::gen_ir::load_ir(&emit_dir.join(file_name)).expect("load IR");
+  let _ = fs::remove_dir_all(&emit_dir);
+  assert_eq!(&*loaded.init_fn, &*entries.init_fn);
+  assert!(loaded.skipped.is_empty(), "unexpected unloadable defs: {:?}", loaded.skipped);
+  if let Err(e) = calcit::run_program_with_docs(init_ns.into(), init_def.into(), &[]) {
+    panic!("running {path} from IR failed: {}", e.msg);
+  }
+}
+
+#[test]
+fn cirru_test_run_from_ir() {
+  run_from_ir("calcit/test-run-ir.cirru", codegen::gen_ir::IrFormat::Cirru);
+}
+
+#[test]
+fn cirru_test_run_from_binary_ir() {
+  run_from_ir("calcit/test-run-ir.cirru", codegen::gen_ir::IrFormat::Msgpack);
+}
+
+/// The full suite also passes from IR, skipping its `inside-eval:` and `inside-js:` blocks.
+#[test]
+fn cirru_test_suite_run_from_ir() {
+  run_from_ir("calcit/test.cirru", codegen::gen_ir::IrFormat::Cirru);
+}
+
+fn host_double(xs: Vec<Calcit>, _call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Number(n)) => Ok(Calcit::Number(n * 2.0)),
+    _ => CalcitErr::err_str(CalcitErrKind::Type, "host-scale expected a number"),
+  }
+}
+
+fn host_triple(xs: Vec<Calcit>, _call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Number(n)) => Ok(Calcit::Number(n * 3.0)),
+    _ => CalcitErr::err_str(CalcitErrKind::Type, "host-scale expected a number"),
+  }
+}
+
+fn embedded_snapshot(label: &str) -> String {
+  format!(
+    r#"{{}} (:package |app) (:version |0.0.0)
+  :entries $ {{}}
+    :default $ {{}} (:init-fn |app.main/main!) (:reload-fn |app.main/reload!) (:mode :native) (:modules $ [])
+  :files $ {{}}
+    |app.main $ %{{}} 'FileEntry
+      :defs $ {{}}
+        |label $ %{{}} 'CodeEntry (:doc |) (:schema nil)
+          :code $ quote (def label |{label})
+        |scale $ %{{}} 'CodeEntry (:doc |) (:schema nil)
+          :code $ quote
+            defn scale (x) (host-scale x)
+        |point $ %{{}} 'CodeEntry (:doc |) (:schema nil)
+          :code $ quote
+            defn point (x) ({{}} (:x x) (:label label))
+        |main! $ %{{}} 'CodeEntry (:doc |) (:schema nil)
+          :code $ quote
+            defn main! () (scale 1)
+        |reload! $ %{{}} 'CodeEntry (:doc |) (:schema nil)
+          :code $ quote
+            defn reload! () nil
+      :ns $ %{{}} 'NsEntry (:doc |)
+        :code $ quote
+          ns app.main
+"#
+  )
+}
+
+fn build_engine(label: &str, f: builtins::FnType) -> calcit::Engine {
+  calcit::Engine::builder()
+    .snapshot_str(embedded_snapshot(label))
+    .proc(
+      "host-scale",
+      f,
+      builtins::RegisteredProcDescriptor {
+        platforms: vec![builtins::RegisteredProcPlatform::Native],
+        ..Default::default()
+      },
+    )
+    .build()
+    .expect("build engine")
+}
+
+#[derive(Debug, PartialEq, serde::Deserialize)]
+struct EmbeddedPoint {
+  x: f64,
+  label: String,
+}
+
+#[test]
+fn cirru_test_embedded_engines() {
+  std::thread::Builder::new()
+    .stack_size(32 * 1024 * 1024)
+    .spawn(|| {
+      let doubling = build_engine("double", host_double);
+      let tripling = build_engine("triple", host_triple);
+
+      assert_eq!(doubling.run_init().expect("run init"), Calcit::Number(2.0));
+      assert_eq!(tripling.run_init().expect("run init"), Calcit::Number(3.0));
+      assert_eq!(
+        doubling.call("app.main", "scale", &[Calcit::Number(5.0)]).expect("call"),
+        Calcit::Number(10.0)
+      );
+      assert_eq!(tripling.eval_def("app.main", "label").expect("eval"), Calcit::Str("triple".into()));
+      assert!(!builtins::is_registered_proc("host-scale"));
+
+      let point: EmbeddedPoint = tripling
+        .call_with("app.main", "point", &[calcit::engine::to_calcit(&4.0).expect("to calcit")])
+        .expect("call with serde");
+      assert_eq!(
+        point,
+        EmbeddedPoint {
+          x: 4.0,
+          label: "triple".to_owned()
+        }
+      );
+    })
+    .expect("spawn test thread")
+    .join()
+    .expect("test thread panicked");
+}
+
+#[test]
+fn cirru_test_engines_run_in_parallel() {
+  let workers: Vec<_> = [("double", host_double as builtins::FnType, 2.0), ("triple", host_triple, 3.0)]
+    .into_iter()
+    .map(|(label, f, factor)| {
+      std::thread::Builder::new()
+        .stack_size(32 * 1024 * 1024)
+        .spawn(move || {
+          let engine = build_engine(label, f);
+          for i in 0..20 {
+            let x = f64::from(i);
+            assert_eq!(
+              engine.call("app.main", "scale", &[Calcit::Number(x)]).expect("call"),
+              Calcit::Number(x * factor)
+            );
+          }
+          engine.eval_def("app.main", "label").expect("eval")
+        })
+        .expect("spawn engine thread")
+    })
+    .collect();
+  let labels: Vec<Calcit> = workers.into_iter().map(|w| w.join().expect("engine thread panicked")).collect();
+  assert_eq!(labels, vec![Calcit::Str("double".into()), Calcit::Str("triple".into())]);
+}
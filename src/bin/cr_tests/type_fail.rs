@@ -3,19 +3,16 @@ use calcit::calcit::{Calcit, CalcitProc, CalcitTypeAnnotation};
 use std::cell::RefCell;
 use std::fs;
 
-fn lock_fixture_tests() -> std::sync::MutexGuard<'static, ()> {
-  super::GLOBAL_TEST_LOCK.lock().unwrap_or_else(|err| err.into_inner())
-}
-
 /// Run a test body in a dedicated thread with a 32 MiB stack.
-/// The suite lock is acquired inside the spawned thread so it is held for the
-/// duration of the run and dropped before the thread exits.
+/// The thread enters a runtime context of its own, so fixtures loaded by other
+/// tests are not seen.
 fn run_with_large_stack(f: impl FnOnce() + Send + 'static) {
   const STACK_SIZE: usize = 32 * 1024 * 1024;
   std::thread::Builder::new()
     .stack_size(STACK_SIZE)
     .spawn(move || {
-      let _guard = lock_fixture_tests();
+      let context = calcit::runtime_context::RuntimeContext::new();
+      let _entered = context.enter();
       f();
     })
     .expect("spawn test thread")
@@ -339,6 +336,90 @@ fn type_fail_trait_method_generic_receiver_fixture_reports_warning_code() {
   });
 }
 
+#[test]
+fn type_fail_trait_impl_fixture_reports_conformance_warnings() {
+  run_with_large_stack(|| {
+    let entries = load_fixture_entries("calcit/type-fail/trait-impl-conformance.cirru");
+    let warnings: RefCell<Vec<LocatedWarning>> = RefCell::new(vec![]);
+
+    runner::preprocess::ensure_ns_def_compiled(&entries.init_ns, &entries.init_def, &warnings, &CallStackList::default())
+      .expect("trait impl fixture should preprocess with warnings, not hard errors");
+
+    let warnings = warnings.borrow();
+    let find = |code: &str| {
+      let matched: Vec<&LocatedWarning> = warnings.iter().filter(|warning| warning.code() == Some(code)).collect();
+      assert_eq!(matched.len(), 1, "expected exactly one {code} warning, got: {warnings:?}");
+      matched[0]
+    };
+
+    let missing = find("W_TRAIT_IMPL_MISSING_METHOD");
+    assert!(
+      missing.message().contains(".farewell"),
+      "warning message was: {}",
+      missing.message()
+    );
+
+    let unknown = find("W_TRAIT_IMPL_UNKNOWN_METHOD");
+    assert!(unknown.message().contains("`.wave`"), "warning message was: {}", unknown.message());
+
+    let mismatch = find("W_TRAIT_IMPL_SIGNATURE_MISMATCH");
+    assert!(
+      mismatch.message().contains("`.greet`")
+        && mismatch
+          .message()
+          .contains("returns type `:string`, but the implementation returns `:number`"),
+      "warning message was: {}",
+      mismatch.message()
+    );
+    // Member warnings point into the `defimpl` source rather than only naming the definition.
+    for warning in [mismatch, unknown] {
+      let location = &warning.as_json()["location"];
+      assert_eq!(location["def"], "BadGreeter");
+      assert!(
+        location["coord"].as_array().is_some_and(|coord| !coord.is_empty()),
+        "expected a source coordinate, got: {warning}"
+      );
+    }
+  });
+}
+
+#[test]
+fn type_fail_nil_narrowing_fixture_only_flags_unguarded_uses() {
+  run_with_large_stack(|| {
+    let entries = load_fixture_entries("calcit/type-fail/nil-narrowing-guards.cirru");
+    let warnings: RefCell<Vec<LocatedWarning>> = RefCell::new(vec![]);
+
+    runner::preprocess::ensure_ns_def_compiled(&entries.init_ns, &entries.init_def, &warnings, &CallStackList::default())
+      .expect("nil narrowing fixture should preprocess with warnings, not hard errors");
+
+    let warnings = warnings.borrow();
+    let mut flagged: Vec<(&str, String)> = warnings
+      .iter()
+      .map(|warning| (warning.code().unwrap_or_default(), warning.message().to_owned()))
+      .collect();
+    flagged.sort();
+    assert_eq!(flagged.len(), 3, "expected warnings only for unguarded uses, got: {warnings:?}");
+
+    // Without `non-nil!`, the nullable local still reaches the number parameter.
+    assert_eq!(flagged[0].0, "W_FN_ARG_TYPE_MISMATCH");
+    assert!(
+      flagged[0].1.contains("but got `:number?`") && flagged[0].1.contains("main/unguarded"),
+      "warning message was: {}",
+      flagged[0].1
+    );
+    // `if-let` types `item` with the Option payload, so the string parameter rejects a number.
+    assert_eq!(flagged[1].0, "W_FN_ARG_TYPE_MISMATCH");
+    assert!(
+      flagged[1].1.contains("expects type `:string`, but got `:number`") && flagged[1].1.contains("if-let-payload"),
+      "warning message was: {}",
+      flagged[1].1
+    );
+    // The raising `js-nullish?` guard in `host-guarded` silences the dereference warning there.
+    assert_eq!(flagged[2].0, "W_JS_FFI_NULLABLE_DEREF");
+    assert!(flagged[2].1.contains("host-unguarded"), "warning message was: {}", flagged[2].1);
+  });
+}
+
 #[test]
 fn type_fail_generic_where_bound_fixture_reports_warning_code() {
   run_with_large_stack(|| {
@@ -459,6 +540,52 @@ fn data_definition_rejects_extra_parentheses_around_indented_forms() {
   });
 }
 
+#[test]
+fn data_definition_rejects_traits_that_cannot_be_derived() {
+  run_with_large_stack(|| {
+    let entries = load_snippet_entries("defn main! ()\n  defstruct Point (:x 'Number) :derive ('Show 'Add)");
+    let warnings: RefCell<Vec<LocatedWarning>> = RefCell::new(vec![]);
+
+    let err = runner::preprocess::ensure_ns_def_compiled(&entries.init_ns, &entries.init_def, &warnings, &CallStackList::default())
+      .expect_err("deriving Add should fail at macro expansion");
+    let message = err.to_string();
+    assert!(
+      message.contains("can only derive 'Show, 'Eq, 'Serialize and 'Deserialize") && message.contains(":Add"),
+      "unexpected derive error: {message}"
+    );
+  });
+}
+
+#[test]
+fn derived_impl_methods_compile_to_invoke_method_in_js() {
+  run_with_large_stack(|| {
+    super::injection::inject_platform_apis();
+    let entries = load_snippet_entries(
+      "def Point $ defstruct Point (:x 'Number) (:y 'Number) :derive ('Show 'Deserialize)\ndefn main! ()\n  let\n      p $ %{} Point (:x 1) (:y 2)\n    println $ p .show\n    println $ p .deserialize \"|{} (:x 3) (:y 4)\"\ndefn reload! ()",
+    );
+    let emit_path = std::env::temp_dir().join(format!("calcit-derive-js-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&emit_path);
+    let target = CodegenTarget::Js {
+      only_reachable: false,
+      dts: false,
+      bundle: None,
+    };
+    let result = run_codegen(&entries, emit_path.to_str().expect("emit path"), target, false);
+    let code = fs::read_to_string(emit_path.join("app.main.mjs"));
+    let _ = fs::remove_dir_all(&emit_path);
+    result.expect("emit js");
+    let code = code.expect("read app.main.mjs");
+
+    // typed receivers keep the method call instead of calling `p` with `.show` as an argument;
+    // the impl is still picked at runtime, there is no direct call to the derived impl function yet
+    assert!(code.contains("$clt.invoke_method(\"show\",p,)"), "{code}");
+    assert!(
+      code.contains("$clt.invoke_method(\"deserialize\",p,\"{} (:x 3) (:y 4)\")"),
+      "{code}"
+    );
+  });
+}
+
 fn contains_with_type_slot(value: &Calcit) -> bool {
   match value {
     Calcit::Proc(CalcitProc::WithTypeSlot) => true,
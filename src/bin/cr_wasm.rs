@@ -14,6 +14,7 @@ mod type_coverage;
 use argh::FromArgs;
 use calcit::calcit::LocatedWarning;
 use calcit::call_stack::CallStackList;
+use calcit::codegen::emit_wasm::{WasmCoverage, WasmGapKind, WasmOptions, WasmTarget};
 use calcit::util::string::strip_shebang;
 use calcit::{ProgramEntries, builtins, call_stack, codegen, program, runner, snapshot, util};
 use colored::Colorize;
@@ -36,6 +37,13 @@ struct WasmArgs {
   /// check-only mode: validate without codegen
   #[argh(switch)]
   check_only: bool,
+  /// host environment of the module: "js" (default) imports host functions from the JS loader,
+  /// "wasi" builds a command module for WASI runtimes with a `_start` export
+  #[argh(option, default = "WasmTarget::Js")]
+  target: WasmTarget,
+  /// also write `program.wit` for `defwasm-export` definitions and export canonical ABI adapters
+  #[argh(switch)]
+  wit: bool,
   /// print version only
   #[argh(switch, short = 'v')]
   version: bool,
@@ -127,7 +135,11 @@ fn main() -> Result<(), String> {
     return Ok(());
   }
 
-  run_wasm_codegen(&entries, &cli_args.emit_path)
+  let options = WasmOptions {
+    target: cli_args.target,
+    wit: cli_args.wit,
+  };
+  run_wasm_codegen(&entries, &cli_args.emit_path, &options)
 }
 
 fn run_check_only(entries: &ProgramEntries) -> Result<(), String> {
@@ -167,6 +179,11 @@ fn run_check_only(entries: &ProgramEntries) -> Result<(), String> {
     return Err(format!("Found {} warnings during preprocessing", warnings.len()));
   }
 
+  // Exports live next to the init function; warnings there were not asked for.
+  preprocess_init_ns(entries, &RefCell::new(vec![]));
+  let coverage = codegen::emit_wasm::check_wasm_coverage(&entries.init_ns, &entries.init_def, &[&entries.reload_fn])?;
+  print_wasm_coverage(&coverage);
+
   let duration = Instant::now().duration_since(started_time);
   println!(
     "\n{} {}",
@@ -177,13 +194,22 @@ fn run_check_only(entries: &ProgramEntries) -> Result<(), String> {
   Ok(())
 }
 
-fn run_wasm_codegen(entries: &ProgramEntries, emit_path: &str) -> Result<(), String> {
+fn run_wasm_codegen(entries: &ProgramEntries, emit_path: &str, options: &WasmOptions) -> Result<(), String> {
   let started_time = Instant::now();
   codegen::set_codegen_mode(true);
 
   let check_warnings: &RefCell<Vec<LocatedWarning>> = &RefCell::new(vec![]);
+  preprocess_init_ns(entries, check_warnings);
+
+  codegen::emit_wasm::emit_wasm(&entries.init_ns, &entries.init_def, emit_path, options)?;
+
+  let duration = Instant::now().duration_since(started_time);
+  println!("{}", format!("took {}ms", duration.as_micros() as f64 / 1000.0).dimmed());
+  Ok(())
+}
 
-  // WASM codegen exports every compilable function, so preprocess all defs in target namespace.
+/// WASM codegen exports every compilable function, so preprocess all defs in the init namespace.
+fn preprocess_init_ns(entries: &ProgramEntries, check_warnings: &RefCell<Vec<LocatedWarning>>) {
   let all_defs = program::list_source_def_names(&entries.init_ns);
   for def_name in &all_defs {
     match runner::preprocess::ensure_ns_def_compiled(&entries.init_ns, def_name, check_warnings, &CallStackList::default()) {
@@ -198,10 +224,63 @@ fn run_wasm_codegen(entries: &ProgramEntries, emit_path: &str) -> Result<(), Str
       }
     }
   }
+}
 
-  codegen::emit_wasm::emit_wasm(&entries.init_ns, emit_path)?;
+/// Print unsupported constructs per definition, then totals per construct.
+fn print_wasm_coverage(coverage: &WasmCoverage) {
+  println!(
+    "\n{} {}",
+    "WASM coverage".bold(),
+    format!("(from {})", coverage.roots.join(", ")).dimmed()
+  );
+  for def in &coverage.defs {
+    let gaps: Vec<_> = coverage.gaps.iter().filter(|gap| &gap.def == def).collect();
+    let failure = coverage.failures.iter().find(|(failed, _)| failed == def);
+    if gaps.is_empty() && failure.is_none() {
+      continue;
+    }
+    println!("  {} {def}", "✗".red());
+    for gap in gaps {
+      println!("      {:<7} {}", gap.kind.to_string(), gap.name);
+    }
+    if let Some((_, error)) = failure {
+      println!("      {:<7} {}", "error", error.dimmed());
+    }
+  }
 
-  let duration = Instant::now().duration_since(started_time);
-  println!("{}", format!("took {}ms", duration.as_micros() as f64 / 1000.0).dimmed());
-  Ok(())
+  let mut totals: Vec<((WasmGapKind, &str), usize)> = vec![];
+  for gap in &coverage.gaps {
+    match totals.iter_mut().find(|(key, _)| *key == (gap.kind, gap.name.as_str())) {
+      Some((_, count)) => *count += 1,
+      None => totals.push(((gap.kind, &gap.name), 1)),
+    }
+  }
+  totals.sort();
+  if !totals.is_empty() {
+    println!("\n  {}", "Unsupported constructs:".yellow());
+    for ((kind, name), count) in totals {
+      let defs = if count == 1 { "def" } else { "defs" };
+      println!("      {:<7} {name} {}", kind.to_string(), format!("(in {count} {defs})").dimmed());
+    }
+  }
+
+  let total = coverage.defs.len();
+  let supported = coverage.supported_count();
+  let percent = if total == 0 {
+    100.0
+  } else {
+    supported as f64 * 100.0 / total as f64
+  };
+  let summary = format!("{supported}/{total} reachable definitions compile to WASM ({percent:.1}%)");
+  if coverage.is_complete() {
+    println!("\n  {} {}", "✓".green(), summary);
+  } else {
+    println!(
+      "\n  {} {}, {} unsupported constructs, {} failed definitions",
+      "✗".yellow(),
+      summary,
+      coverage.gaps.len(),
+      coverage.failures.len()
+    );
+  }
 }
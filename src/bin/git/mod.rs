@@ -55,6 +55,55 @@ impl GitRepo {
     Ok(())
   }
 
+  /// Commit the files of `dir` as a fresh repository with fixed author and dates,
+  /// so the same contents always give the same commit. Ignored files are removed.
+  pub fn snapshot(dir: &Path) -> Result<String, String> {
+    let repo = GitRepo { dir: dir.to_path_buf() };
+    repo.run_command(&["init", "-q"])?;
+    repo.run_command(&["add", "-A"])?;
+    let mut commit = Command::new("git");
+    commit
+      .current_dir(dir)
+      .env("GIT_AUTHOR_DATE", "1970-01-01T00:00:00+0000")
+      .env("GIT_COMMITTER_DATE", "1970-01-01T00:00:00+0000")
+      .args([
+        "-c",
+        "user.name=caps",
+        "-c",
+        "user.email=caps@calcit-lang.org",
+        "-c",
+        "commit.gpgsign=false",
+        "commit",
+        "-q",
+        "--allow-empty",
+        "-m",
+        "caps snapshot",
+      ]);
+    run_noninteractive(&mut commit)?;
+    repo.run_command(&["clean", "-fdxq"])?;
+    repo.head_commit()
+  }
+
+  /// Create an empty bare repository that serves any pushed commit to shallow fetches.
+  pub fn init_bare(dir: &Path) -> Result<GitRepo, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    let repo = GitRepo { dir: dir.to_path_buf() };
+    repo.run_command(&["init", "-q", "--bare", "--template="])?;
+    repo.run_command(&["config", "receive.shallowUpdate", "true"])?;
+    repo.run_command(&["config", "uploadpack.allowAnySHA1InWant", "true"])?;
+    Ok(repo)
+  }
+
+  /// Push `commit` to `target_ref` of a local repository, shallow history included.
+  /// ```bash
+  /// git push --force <TARGET> <COMMIT>:<REF>
+  /// ```
+  pub fn push_to_path(&self, target: &Path, commit: &str, target_ref: &str) -> Result<(), String> {
+    let target = target.to_str().ok_or_else(|| format!("invalid push target {}", target.display()))?;
+    self.run_command(&["push", "-q", "--force", target, &format!("{commit}:{target_ref}")])?;
+    Ok(())
+  }
+
   /// get SHA of a tag or ref
   /// ```bash
   /// git rev-parse <REF>
@@ -16,7 +16,10 @@ use calcit::{
   calcit::{Calcit, CalcitErr, CalcitErrKind},
   call_stack::{CallStackList, display_stack},
   data::edn::{calcit_to_edn, edn_to_calcit, sanitize_edn_for_format},
+  data::ffi_manifest::FfiManifest,
+  data::ffi_raw::{EdnFfiRaw, FfiRawArgs},
   runner::track,
+  runtime_context,
 };
 
 /// FFI protocol types
@@ -29,6 +32,8 @@ type EdnFfiFn = fn(
 
 /// lazily cache dylibs, in case Linux drops memory of libraries
 static DYLIBS: LazyLock<Mutex<HashMap<String, Arc<libloading::Library>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+/// manifests exported by dylibs through `ffi_manifest`, `None` for libraries without one
+static DYLIB_MANIFESTS: LazyLock<Mutex<HashMap<String, Option<Arc<FfiManifest>>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
 static TRACE_FFI: AtomicBool = AtomicBool::new(false);
 static STDOUT_TO_STDERR: AtomicBool = AtomicBool::new(false);
 static SILENCE_PROGRAM_OUTPUT: AtomicBool = AtomicBool::new(false);
@@ -124,7 +129,14 @@ fn load_dylib(lib_name: &str) -> Result<Arc<libloading::Library>, CalcitErr> {
   Ok(lib)
 }
 
-fn ensure_abi_compatible(lib: &libloading::Library, lib_name: &str) -> Result<(), CalcitErr> {
+/// negotiated ABI version and optional typed manifest of a loaded dylib
+struct DylibContract {
+  abi_version: String,
+  manifest: Option<Arc<FfiManifest>>,
+}
+
+/// check versions of a dylib, and return its typed manifest when it exports one
+fn ensure_abi_compatible(lib: &libloading::Library, lib_name: &str) -> Result<DylibContract, CalcitErr> {
   let expected_edn_version = cirru_edn::version();
   trace_ffi_event("lookup-abi", format!("lib={lib_name}"));
   let lookup_version: libloading::Symbol<fn() -> String> = unsafe { lib.get("abi_version".as_bytes()) }.map_err(|e| {
@@ -138,12 +150,15 @@ fn ensure_abi_compatible(lib: &libloading::Library, lib_name: &str) -> Result<()
     "abi-version",
     format!("lib={lib_name} current={current} expected={}", calcit::FFI_ABI_VERSION),
   );
-  if current != calcit::FFI_ABI_VERSION {
-    return CalcitErr::err_str(
+  if !calcit::is_compatible_ffi_abi(&current) {
+    return Err(CalcitErr::use_str(
       CalcitErrKind::Unexpected,
-      format!("ABI versions mismatch: {current} {}", calcit::FFI_ABI_VERSION),
-    )
-    .map(|_| ());
+      format!(
+        "ABI versions mismatch: {current}, expected {} or {}",
+        calcit::FFI_ABI_VERSION,
+        calcit::FFI_LEGACY_ABI_VERSION
+      ),
+    ));
   }
 
   trace_ffi_event("lookup-edn-version", format!("lib={lib_name}"));
@@ -159,20 +174,47 @@ fn ensure_abi_compatible(lib: &libloading::Library, lib_name: &str) -> Result<()
     format!("lib={lib_name} current={current_edn} expected={expected_edn_version}"),
   );
   if current_edn != expected_edn_version {
-    return CalcitErr::err_str(
+    return Err(CalcitErr::use_str(
       CalcitErrKind::Unexpected,
       format!("cirru_edn versions mismatch: {current_edn} {expected_edn_version}"),
-    )
-    .map(|_| ());
+    ));
   }
-  Ok(())
+  Ok(DylibContract {
+    abi_version: current,
+    manifest: load_dylib_manifest(lib, lib_name)?,
+  })
 }
 
-static PLATFORM_APIS_INJECTED: AtomicBool = AtomicBool::new(false);
+fn load_dylib_manifest(lib: &libloading::Library, lib_name: &str) -> Result<Option<Arc<FfiManifest>>, CalcitErr> {
+  let mut manifests = DYLIB_MANIFESTS
+    .lock()
+    .map_err(|_| CalcitErr::use_str(CalcitErrKind::Unexpected, "failed to lock dylib manifest cache"))?;
+  if let Some(manifest) = manifests.get(lib_name) {
+    return Ok(manifest.to_owned());
+  }
+  trace_ffi_event("lookup-manifest", format!("lib={lib_name}"));
+  // manifest is optional, libraries built before it stay callable without checks
+  let manifest = match unsafe { lib.get::<fn() -> Edn>("ffi_manifest".as_bytes()) } {
+    Ok(lookup_manifest) => {
+      let data = lookup_manifest();
+      let manifest = FfiManifest::parse(&data)
+        .map_err(|e| CalcitErr::use_str(CalcitErrKind::Unexpected, format!("invalid FFI manifest in `{lib_name}`: {e}")))?;
+      trace_ffi_event("manifest", format!("lib={lib_name} methods={}", manifest.methods.len()));
+      Some(Arc::new(manifest))
+    }
+    Err(_) => {
+      trace_ffi_event("manifest", format!("lib={lib_name} missing"));
+      None
+    }
+  };
+  manifests.insert(lib_name.to_owned(), manifest.to_owned());
+  Ok(manifest)
+}
 
+/// Register platform procs into the current runtime context, once per context.
 #[allow(dead_code)]
 pub fn inject_platform_apis() {
-  if PLATFORM_APIS_INJECTED.swap(true, Ordering::Relaxed) {
+  if builtins::is_registered_proc("&call-dylib-edn") {
     return;
   }
   builtins::register_import_proc_with_descriptor(
@@ -213,6 +255,21 @@ pub fn inject_platform_apis() {
       ..Default::default()
     },
   );
+  builtins::register_import_proc_with_descriptor(
+    "&call-dylib-raw",
+    call_dylib_raw,
+    RegisteredProcDescriptor {
+      arity_min: 2,
+      arity_max: None,
+      platforms: vec![RegisteredProcPlatform::Native],
+      stability: RegisteredProcStability::Public,
+      docs_hint: Some(Arc::from(
+        "Fix: use native runtime and a dylib built for the raw calling convention, pass (lib-name method ...args).",
+      )),
+      callback_last: false,
+      tags: proc_tags(["interop", "io"]),
+    },
+  );
   builtins::register_import_proc_with_descriptor(
     "&call-dylib-edn-fn",
     call_dylib_edn_fn,
@@ -281,8 +338,8 @@ pub fn call_dylib_edn(xs: Vec<Calcit>, _call_stack: &CallStackList) -> Result<Ca
     );
   };
   let mut ys: Vec<Edn> = Vec::with_capacity(xs.len());
-  for v in xs.into_iter().skip(2) {
-    ys.push(calcit_to_edn(&v)?);
+  for v in xs.iter().skip(2) {
+    ys.push(calcit_to_edn(v)?);
   }
 
   trace_ffi_event(
@@ -296,7 +353,13 @@ pub fn call_dylib_edn(xs: Vec<Calcit>, _call_stack: &CallStackList) -> Result<Ca
   );
 
   let lib = load_dylib(&lib_name)?;
-  ensure_abi_compatible(&lib, &lib_name)?;
+  let manifest = ensure_abi_compatible(&lib, &lib_name)?.manifest;
+  if let Some(manifest) = &manifest {
+    manifest.check_args(&method, &xs[2..]).map_err(|e| {
+      trace_ffi_event("reject", format!("lib={lib_name} symbol={method} {e}"));
+      CalcitErr::use_str(CalcitErrKind::Type, format!("&call-dylib-edn `{lib_name}`: {e}"))
+    })?;
+  }
   trace_ffi_event("lookup-symbol", format!("lib={lib_name} symbol={method}"));
   let func: libloading::Symbol<EdnFfi> = unsafe { lib.get(method.as_bytes()) }.map_err(|e| {
     CalcitErr::use_str(
@@ -315,7 +378,85 @@ pub fn call_dylib_edn(xs: Vec<Calcit>, _call_stack: &CallStackList) -> Result<Ca
       format_edn_args_for_trace(std::slice::from_ref(&ret))
     ),
   );
-  Ok(edn_to_calcit(&ret, &Calcit::Nil))
+  let ret = edn_to_calcit(&ret, &Calcit::Nil);
+  if let Some(manifest) = &manifest {
+    manifest
+      .check_return(&method, &ret)
+      .map_err(|e| CalcitErr::use_str(CalcitErrKind::Type, format!("&call-dylib-edn `{lib_name}`: {e}")))?;
+  }
+  Ok(ret)
+}
+
+/// raw calling convention, Buffers and number lists are passed as borrowed slices
+pub fn call_dylib_raw(xs: Vec<Calcit>, _call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  if xs.len() < 2 {
+    return CalcitErr::err_str(CalcitErrKind::Arity, format!("&call-dylib-raw expected >2 arguments, got: {xs:?}"));
+  }
+  let Calcit::Str(lib_name) = &xs[0] else {
+    return CalcitErr::err_str(CalcitErrKind::Type, format!("&call-dylib-raw expected a lib_name, got: {}", xs[0]));
+  };
+  let Calcit::Str(method) = &xs[1] else {
+    return CalcitErr::err_str(
+      CalcitErrKind::Type,
+      format!("&call-dylib-raw expected a method name, got: {}", xs[1]),
+    );
+  };
+  let args = FfiRawArgs::from_calcit(&xs[2..]).map_err(|e| CalcitErr::use_str(CalcitErrKind::Type, e))?;
+
+  trace_ffi_event(
+    "call-raw",
+    format!(
+      "lib={lib_name} resolved={} symbol={method} argc={} args=[{}]",
+      resolve_trace_path(lib_name),
+      xs.len() - 2,
+      args.describe()
+    ),
+  );
+
+  let lib = load_dylib(lib_name)?;
+  let contract = ensure_abi_compatible(&lib, lib_name)?;
+  if !calcit::ffi_abi_supports_raw(&contract.abi_version) {
+    return CalcitErr::err_str(
+      CalcitErrKind::Unexpected,
+      format!(
+        "&call-dylib-raw requires ABI {} in `{lib_name}`, got {}, use &call-dylib-edn instead",
+        calcit::FFI_ABI_VERSION,
+        contract.abi_version
+      ),
+    );
+  }
+  if let Some(manifest) = &contract.manifest {
+    manifest.check_args(method, &xs[2..]).map_err(|e| {
+      trace_ffi_event("reject", format!("lib={lib_name} symbol={method} {e}"));
+      CalcitErr::use_str(CalcitErrKind::Type, format!("&call-dylib-raw `{lib_name}`: {e}"))
+    })?;
+  }
+  trace_ffi_event("lookup-symbol", format!("lib={lib_name} symbol={method}"));
+  let func: libloading::Symbol<EdnFfiRaw> = unsafe { lib.get(method.as_bytes()) }.map_err(|e| {
+    CalcitErr::use_str(
+      CalcitErrKind::Unexpected,
+      format!("failed to load FFI symbol `{method}` in `{lib_name}`: {e}"),
+    )
+  })?;
+  let raw_args = args.to_raw();
+  let ret = func(&raw_args).map_err(|e| {
+    trace_ffi_event("error", format!("lib={lib_name} symbol={method} {e}"));
+    e
+  })?;
+  trace_ffi_event(
+    "return",
+    format!(
+      "lib={lib_name} symbol={method} ret={}",
+      format_edn_args_for_trace(std::slice::from_ref(&ret))
+    ),
+  );
+  let ret = edn_to_calcit(&ret, &Calcit::Nil);
+  if let Some(manifest) = &contract.manifest {
+    manifest
+      .check_return(method, &ret)
+      .map_err(|e| CalcitErr::use_str(CalcitErrKind::Type, format!("&call-dylib-raw `{lib_name}`: {e}")))?;
+  }
+  Ok(ret)
 }
 
 pub fn stdout_println(xs: Vec<Calcit>, _call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
@@ -412,8 +553,10 @@ pub fn call_dylib_edn_fn(xs: Vec<Calcit>, call_stack: &CallStackList) -> Result<
   let copied_stack_1 = Arc::new(call_stack.to_owned());
   let method_name = method.clone();
   let lib_name_for_thread = lib_name.clone();
+  let context = runtime_context::current();
 
   let _handle = thread::spawn(move || {
+    let _entered = context.enter();
     trace_ffi_event(
       "thread-start",
       format!(
@@ -436,9 +579,12 @@ pub fn call_dylib_edn_fn(xs: Vec<Calcit>, call_stack: &CallStackList) -> Result<
       }
     };
     let copied_stack = copied_stack_1.to_owned();
+    let callback_context = context.clone();
     match func(
       ys.to_owned(),
       Arc::new(move |ps: Vec<Edn>| -> Result<Edn, String> {
+        // the library may call back from threads of its own
+        let _entered = callback_context.enter();
         trace_ffi_event(
           "callback-in",
           format!(
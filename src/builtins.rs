@@ -5,17 +5,18 @@ mod logics;
 mod maps;
 mod math;
 pub mod meta;
-mod refs;
+pub(crate) mod refs;
 mod sets;
 mod strings;
 pub(crate) mod structs;
 pub mod syntax;
 
 use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, LazyLock, RwLock};
+use std::sync::Arc;
 
 use crate::calcit::{Calcit, CalcitErr, CalcitErrKind, CalcitList, CalcitProc, CalcitScope, CalcitSyntax};
 use crate::call_stack::{CallStackList, using_stack};
+use crate::runtime_context::{ContextRwLock, ContextSlot};
 use cirru_edn::EdnTag;
 
 use im_ternary_tree::TernaryTreeList;
@@ -92,10 +93,11 @@ pub fn proc_tags<'a>(names: impl IntoIterator<Item = &'a str>) -> HashSet<EdnTag
     .collect()
 }
 
-pub(crate) static IMPORTED_PROCS: LazyLock<RwLock<HashMap<Arc<str>, FnType>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
-pub(crate) static IMPORTED_PROC_DESCRIPTORS: LazyLock<RwLock<HashMap<Arc<str>, RegisteredProcDescriptor>>> =
-  LazyLock::new(|| RwLock::new(HashMap::new()));
-pub(crate) static WARNED_REGISTERED_PROCS: LazyLock<RwLock<HashSet<Arc<str>>>> = LazyLock::new(|| RwLock::new(HashSet::new()));
+pub(crate) static IMPORTED_PROCS: ContextSlot<ContextRwLock<HashMap<Arc<str>, FnType>>> = ContextSlot::new(|ctx| &ctx.imported_procs);
+pub(crate) static IMPORTED_PROC_DESCRIPTORS: ContextSlot<ContextRwLock<HashMap<Arc<str>, RegisteredProcDescriptor>>> =
+  ContextSlot::new(|ctx| &ctx.imported_proc_descriptors);
+pub(crate) static WARNED_REGISTERED_PROCS: ContextSlot<ContextRwLock<HashSet<Arc<str>>>> =
+  ContextSlot::new(|ctx| &ctx.warned_registered_procs);
 
 pub(crate) fn err_arity<T: Into<String>>(msg: T, xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   CalcitErr::err_nodes(CalcitErrKind::Arity, msg, xs)
@@ -156,6 +158,7 @@ fn validate_registered_proc_call(alias: &str, args: &[Calcit]) -> Result<(), Cal
   let Some(descriptor) = descriptors.get(alias) else {
     return Ok(());
   };
+  crate::runner::sandbox::check_registered_proc(alias, descriptor)?;
 
   let current = detect_current_platform();
   if !descriptor.platforms.is_empty() && !descriptor.platforms.contains(&current) {
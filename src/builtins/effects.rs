@@ -5,9 +5,11 @@ use std::sync::LazyLock;
 use std::sync::RwLock;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use crate::runtime_context::{ContextRwLock, ContextSlot};
 use crate::{
   builtins::meta::type_of,
   calcit::{Calcit, CalcitErr, CalcitErrKind, CalcitProc, format_proc_examples_hint},
+  runner::sandbox,
   util::number::f64_to_i32,
 };
 
@@ -18,8 +20,19 @@ pub enum CliRunningMode {
   Ir,
 }
 
+impl CliRunningMode {
+  /// name of the tag returned by `&get-calcit-running-mode`
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Self::Eval => "eval",
+      Self::Js => "js",
+      Self::Ir => "ir",
+    }
+  }
+}
+
 static STARTED_INSTANT: LazyLock<RwLock<Instant>> = LazyLock::new(|| RwLock::new(Instant::now()));
-static CLI_RUNNING_MODE: LazyLock<RwLock<CliRunningMode>> = LazyLock::new(|| RwLock::new(CliRunningMode::Eval));
+static CLI_RUNNING_MODE: ContextSlot<ContextRwLock<CliRunningMode>> = ContextSlot::new(|ctx| &ctx.running_mode);
 
 pub fn raise(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   let mut s = String::from("");
@@ -70,13 +83,12 @@ pub fn modify_cli_running_mode(mode: CliRunningMode) -> Result<(), String> {
   Ok(())
 }
 
+pub fn cli_running_mode() -> CliRunningMode {
+  CLI_RUNNING_MODE.read().expect("read mode").to_owned()
+}
+
 pub fn calcit_running_mode(_xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
-  let mode = CLI_RUNNING_MODE.read().expect("read mode").to_owned();
-  match mode {
-    CliRunningMode::Eval => Ok(Calcit::tag("eval")),
-    CliRunningMode::Js => Ok(Calcit::tag("js")),
-    CliRunningMode::Ir => Ok(Calcit::tag("ir")),
-  }
+  Ok(Calcit::tag(cli_running_mode().as_str()))
 }
 
 /// is evaluating in Rust, not for js
@@ -90,6 +102,7 @@ pub fn call_get_calcit_backend(_xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
 }
 
 pub fn quit(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  sandbox::check_quit()?;
   match xs.first() {
     Some(Calcit::Number(n)) => match f64_to_i32(*n) {
       Ok(code) => exit(code),
@@ -111,6 +124,9 @@ pub fn get_env(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   if xs.len() > 2 {
     return CalcitErr::err_str(CalcitErrKind::Arity, "get-env get 1~2 arguments");
   }
+  if let Some(Calcit::Str(s)) = xs.first() {
+    sandbox::check_env(s)?;
+  }
   match xs.first() {
     Some(Calcit::Str(s)) => match env::var(&**s) {
       Ok(v) => {
@@ -155,6 +171,9 @@ pub fn unix_time_ms(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
 }
 
 pub fn read_file(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  if let Some(Calcit::Str(s)) = xs.first() {
+    sandbox::check_read(s)?;
+  }
   match xs.first() {
     Some(Calcit::Str(s)) => match fs::read_to_string(&**s) {
       Ok(content) => Ok(Calcit::Str(content.into())),
@@ -207,6 +226,7 @@ pub fn read_dir(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     Ok(())
   }
 
+  sandbox::check_read(path)?;
   let mut paths = vec![];
   collect_paths(std::path::Path::new(&**path), recursive, &mut paths)
     .map_err(|e| CalcitErr::use_str(CalcitErrKind::Effect, format!("read-dir failed at {}: {e}", &**path)))?;
@@ -217,6 +237,9 @@ pub fn read_dir(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
 }
 
 pub fn write_file(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  if let Some(Calcit::Str(path)) = xs.first() {
+    sandbox::check_write(path)?;
+  }
   match (xs.first(), xs.get(1)) {
     (Some(Calcit::Str(path)), Some(Calcit::Str(content))) => match fs::write(&**path, &**content) {
       Ok(_) => Ok(Calcit::Nil),
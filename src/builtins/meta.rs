@@ -1,10 +1,12 @@
 use crate::calcit::type_annotation::{collect_runtime_type_bindings, validate_runtime_generic_where_bounds};
+use crate::runtime_context::{self, ContextMutex, ContextSlot};
 use crate::{
   builtins,
   calcit::{
-    self, Calcit, CalcitEnumDef, CalcitEnumValue, CalcitErr, CalcitErrKind, CalcitImpl, CalcitImport, CalcitList, CalcitLocal,
-    CalcitProc, CalcitStructDef, CalcitStructValue, CalcitSymbolInfo, CalcitSyntax, CalcitTrait, CalcitTypeAnnotation, GEN_NS,
-    GENERATED_DEF, brief_type_of_value, format_proc_examples_hint, gen_core_id, register_type_slot, value_matches_type_annotation,
+    self, Calcit, CalcitEnumDef, CalcitEnumValue, CalcitErr, CalcitErrKind, CalcitFn, CalcitImpl, CalcitImport, CalcitList,
+    CalcitLocal, CalcitProc, CalcitStructDef, CalcitStructValue, CalcitSymbolInfo, CalcitSyntax, CalcitTrait, CalcitTypeAnnotation,
+    GEN_NS, GENERATED_DEF, brief_type_of_value, format_proc_examples_hint, gen_core_id, register_type_slot,
+    value_matches_type_annotation,
   },
   call_stack::{self, CallStackList},
   codegen::gen_ir::dump_code,
@@ -21,18 +23,15 @@ use crate::{
 use cirru_edn::EdnTag;
 use cirru_parser::Cirru;
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, atomic};
 use std::{cmp::Ordering, collections::HashMap};
-use std::{collections::hash_map::DefaultHasher, sync::Mutex};
-use std::{
-  hash::{Hash, Hasher},
-  sync::LazyLock,
-};
 
-static JS_SYMBOL_INDEX: AtomicUsize = AtomicUsize::new(0);
+static JS_SYMBOL_INDEX: ContextSlot<AtomicUsize> = ContextSlot::new(|ctx| &ctx.js_symbol_index);
 
-pub(crate) static NS_SYMBOL_DICT: LazyLock<Mutex<HashMap<Arc<str>, usize>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+pub(crate) static NS_SYMBOL_DICT: ContextSlot<ContextMutex<HashMap<Arc<str>, usize>>> = ContextSlot::new(|ctx| &ctx.ns_symbols);
 
 // Tracks the current top-level def being preprocessed, keyed as "ns/def".
 // This makes gensym counters per-definition rather than per-namespace,
@@ -621,8 +620,8 @@ pub fn enum_definition(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
 }
 
 pub fn trait_new(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
-  if xs.len() != 2 {
-    return CalcitErr::err_nodes(CalcitErrKind::Arity, "&trait::new expected 2 arguments, but received:", xs);
+  if xs.len() != 2 && xs.len() != 3 {
+    return CalcitErr::err_nodes(CalcitErrKind::Arity, "&trait::new expected 2 or 3 arguments, but received:", xs);
   }
   fn normalize_type_form(form: &Calcit) -> Calcit {
     match form {
@@ -673,17 +672,18 @@ pub fn trait_new(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     }
   }
 
-  let (methods, method_types) = match &xs[1] {
+  let (methods, method_types, defaults) = match &xs[1] {
     Calcit::List(list) => {
       let mut items = Vec::with_capacity(list.len());
       let mut types = Vec::with_capacity(list.len());
+      let mut defaults = Vec::with_capacity(list.len());
       for item in list.iter() {
         match item {
           Calcit::List(entry) => {
-            if entry.len() != 2 {
+            if entry.len() != 2 && entry.len() != 3 {
               return CalcitErr::err_str(
                 CalcitErrKind::Type,
-                format!("&trait::new expects (method type) pairs, but received: {item}"),
+                format!("&trait::new expects (method type) pairs or (method type default) triples, but received: {item}"),
               );
             }
             let name = match entry.first().unwrap() {
@@ -726,8 +726,22 @@ pub fn trait_new(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
                 format!("&trait::new does not allow Dynamic inside method signatures: {type_form_value}"),
               );
             }
+            let default_impl = match entry.get(2) {
+              None => None,
+              Some(Calcit::Fn { info, .. }) => Some(info.to_owned()),
+              Some(other) => {
+                return CalcitErr::err_str(
+                  CalcitErrKind::Type,
+                  format!(
+                    "&trait::new expects default of .{} to be a function, but received: {other}",
+                    name.ref_str()
+                  ),
+                );
+              }
+            };
             items.push(name);
             types.push(method_type);
+            defaults.push(default_impl);
           }
           Calcit::Tag(_) | Calcit::Symbol { .. } => {
             return CalcitErr::err_str(
@@ -743,7 +757,7 @@ pub fn trait_new(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
           }
         }
       }
-      (items, types)
+      (items, types, defaults)
     }
     other => {
       return CalcitErr::err_str(
@@ -753,7 +767,36 @@ pub fn trait_new(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     }
   };
 
-  Ok(Calcit::Trait(CalcitTrait::new_runtime(name, methods, method_types)))
+  let requires = match xs.get(2) {
+    None | Some(Calcit::Nil) => vec![],
+    Some(Calcit::List(list)) => {
+      let mut requires = Vec::with_capacity(list.len());
+      for item in list.iter() {
+        match item {
+          Calcit::Trait(required) => requires.push(Arc::new(required.to_owned())),
+          other => {
+            return CalcitErr::err_str(
+              CalcitErrKind::Type,
+              format!("&trait::new expects required traits, but received: {other}"),
+            );
+          }
+        }
+      }
+      requires
+    }
+    Some(other) => {
+      return CalcitErr::err_str(
+        CalcitErrKind::Type,
+        format!("&trait::new expects a list of required traits, but received: {other}"),
+      );
+    }
+  };
+
+  Ok(Calcit::Trait(
+    CalcitTrait::new_runtime(name, methods, method_types)
+      .with_defaults(defaults)
+      .with_requires(requires),
+  ))
 }
 
 fn collect_trait_impls(xs: &[Calcit], proc_name: &str) -> Result<Vec<Arc<CalcitImpl>>, CalcitErr> {
@@ -772,6 +815,30 @@ fn collect_trait_impls(xs: &[Calcit], proc_name: &str) -> Result<Vec<Arc<CalcitI
   Ok(traits)
 }
 
+/// Every trait impl attached to a type must find impls for the traits its
+/// origin requires, either earlier on the type or in the same attachment.
+fn check_required_traits(impls: &[Arc<CalcitImpl>], proc_name: &str) -> Result<(), CalcitErr> {
+  let implemented = impls
+    .iter()
+    .filter_map(|imp| imp.origin())
+    .map(|origin| origin.as_ref())
+    .collect::<Vec<_>>();
+  for origin in implemented.iter() {
+    let missing = origin.missing_requires(&implemented);
+    if !missing.is_empty() {
+      let names = missing.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ");
+      return Err(CalcitErr::use_str(
+        CalcitErrKind::Type,
+        format!(
+          "{proc_name}: trait {} requires impls of {names}, attach them together or before it",
+          origin.name
+        ),
+      ));
+    }
+  }
+  Ok(())
+}
+
 pub fn record_impl_traits(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   if xs.len() < 2 {
     return CalcitErr::err_nodes(CalcitErrKind::Arity, "&struct:impl-traits expected 2+ arguments, but received:", xs);
@@ -780,6 +847,7 @@ pub fn record_impl_traits(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     Calcit::Struct(struct_value) => {
       let mut impls = struct_value.struct_ref.impls.clone();
       impls.extend(collect_trait_impls(&xs[1..], "&struct:impl-traits")?);
+      check_required_traits(&impls, "&struct:impl-traits")?;
       let mut next_struct = (*struct_value.struct_ref).clone();
       next_struct.impls = impls;
 
@@ -825,6 +893,7 @@ pub fn tuple_impl_traits(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
         }
       };
       next_sum_type.impls.extend(collect_trait_impls(&xs[1..], "&enum:impl-traits")?);
+      check_required_traits(&next_sum_type.impls, "&enum:impl-traits")?;
       Ok(Calcit::Enum(CalcitEnumValue {
         tag: enum_value.tag.to_owned(),
         extra: enum_value.extra.to_owned(),
@@ -851,6 +920,7 @@ pub fn struct_impl_traits(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
       let mut next = struct_def.to_owned();
       let mut next_impls = next.impls.clone();
       next_impls.extend(collect_trait_impls(&xs[1..], "&struct-def:impl-traits")?);
+      check_required_traits(&next_impls, "&struct-def:impl-traits")?;
       next.impls = next_impls;
       Ok(Calcit::StructDef(next))
     }
@@ -873,6 +943,7 @@ pub fn enum_impl_traits(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     Calcit::EnumDef(enum_def) => {
       let mut next = enum_def.to_owned();
       next.impls.extend(collect_trait_impls(&xs[1..], "&enum-def:impl-traits")?);
+      check_required_traits(&next.impls, "&enum-def:impl-traits")?;
       Ok(Calcit::EnumDef(next))
     }
     other => CalcitErr::err_str(
@@ -1094,7 +1165,7 @@ pub fn invoke_method(name: &str, method_args: &[Calcit], call_stack: &CallStackL
     // user-defined values: impl-traits appends, so later impls override earlier ones
     Enum(enum_value) => {
       let user_impls = enum_value.impls();
-      let has_user_method = user_impls.iter().any(|imp| imp.get(name).is_some());
+      let has_user_method = user_impls.iter().any(|imp| impl_provides_method(imp, name));
       if has_user_method {
         method_call_impls(user_impls, v0, name, method_args, call_stack, true)
       } else {
@@ -1104,7 +1175,7 @@ pub fn invoke_method(name: &str, method_args: &[Calcit], call_stack: &CallStackL
     }
     Struct(struct_value) => {
       let user_impls = &struct_value.struct_ref.impls;
-      let has_user_method = user_impls.iter().any(|imp| imp.get(name).is_some());
+      let has_user_method = user_impls.iter().any(|imp| impl_provides_method(imp, name));
       if has_user_method {
         method_call_impls(user_impls, v0, name, method_args, call_stack, true)
       } else {
@@ -1215,6 +1286,15 @@ fn method_call_impls(
       }
     }
   }
+  // explicit impl entries always win, trait defaults only fill the gaps
+  let default_impl = if last_wins {
+    impls.iter().rev().find_map(|imp| trait_default_of(imp, name))
+  } else {
+    impls.iter().find_map(|imp| trait_default_of(imp, name))
+  };
+  if let Some(default_impl) = default_impl {
+    return runner::run_fn(method_args, default_impl, call_stack);
+  }
   let mut fields: Vec<String> = vec![];
   for imp in impls {
     for field in imp.fields().iter() {
@@ -1229,6 +1309,16 @@ fn method_call_impls(
   ))
 }
 
+/// Default body declared by the trait an impl was created from, used when the
+/// impl itself leaves the method out.
+fn trait_default_of<'a>(imp: &'a CalcitImpl, name: &str) -> Option<&'a Arc<CalcitFn>> {
+  imp.origin().and_then(|origin| origin.get_default(name))
+}
+
+fn impl_provides_method(imp: &CalcitImpl, name: &str) -> bool {
+  imp.get(name).is_some() || trait_default_of(imp, name).is_some()
+}
+
 fn invoke_impl_method(
   impl_value: &CalcitImpl,
   v0: &Calcit,
@@ -1495,6 +1585,17 @@ fn collect_method_names(value: &Calcit, impls: &[Arc<CalcitImpl>]) -> Vec<String
       }
     }
   }
+  // trait defaults come after every explicit entry, matching dispatch
+  for imp in iter_impls_in_precedence_order(value, impls) {
+    let Some(origin) = imp.origin() else { continue };
+    for (method, default_impl) in origin.methods.iter().zip(origin.defaults.iter()) {
+      let name = format!(".{}", method.ref_str());
+      if default_impl.is_some() && !seen.contains_key(&name) {
+        seen.insert(name.clone(), ());
+        methods.push(name);
+      }
+    }
+  }
 
   methods
 }
@@ -1574,6 +1675,11 @@ pub fn trait_call(xs: &[Calcit], call_stack: &CallStackList) -> Result<Calcit, C
   method_args.extend_from_slice(&xs[3..]);
 
   if let Some(impl_value) = selected_impl {
+    if impl_value.get(&method_name).is_none()
+      && let Some(default_impl) = trait_def.get_default(&method_name)
+    {
+      return runner::run_fn(&method_args, default_impl, call_stack);
+    }
     return invoke_impl_method(impl_value.as_ref(), receiver, &method_name, &method_args, call_stack);
   }
 
@@ -1718,7 +1824,7 @@ pub fn assert_traits(xs: &[Calcit], call_stack: &CallStackList) -> Result<Calcit
   let missing = trait_def
     .methods
     .iter()
-    .filter(|method| selected_impl.get(method.ref_str()).is_none())
+    .filter(|method| !impl_provides_method(selected_impl, method.ref_str()))
     .map(ToString::to_string)
     .collect::<Vec<_>>();
   if !missing.is_empty() {
@@ -1808,7 +1914,9 @@ pub fn async_sleep(xs: Vec<Calcit>, call_stack: &CallStackList) -> Result<Calcit
 
   runner::track::track_task_add();
 
+  let context = runtime_context::current();
   let _handle = thread::spawn(move || {
+    let _entered = context.enter();
     let ten_secs = time::Duration::from_secs(sec.round() as u64);
     // let _now = time::Instant::now();
     thread::sleep(ten_secs);
@@ -4,20 +4,21 @@
 
 use std::collections::HashMap;
 use std::sync::atomic::AtomicUsize;
-use std::sync::{Arc, LazyLock, Mutex};
+use std::sync::{Arc, Mutex};
 
 use cirru_edn::EdnTag;
 
 use crate::builtins::meta::type_of;
+use crate::runtime_context::{ContextMutex, ContextSlot};
 
 use crate::calcit::{Calcit, CalcitErr, CalcitErrKind, CalcitImport, CalcitList, CalcitScope};
 use crate::{call_stack::CallStackList, runner};
 
 pub(crate) type ValueAndListeners = (Calcit, HashMap<EdnTag, Calcit>);
 
-type RefListeners = HashMap<Arc<str>, Arc<Mutex<ValueAndListeners>>>;
+pub(crate) type RefListeners = HashMap<Arc<str>, Arc<Mutex<ValueAndListeners>>>;
 
-static REFS_DICT: LazyLock<Mutex<RefListeners>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static REFS_DICT: ContextSlot<ContextMutex<RefListeners>> = ContextSlot::new(|ctx| &ctx.refs);
 
 fn modify_ref(locked_pair: Arc<Mutex<ValueAndListeners>>, v: Calcit, call_stack: &CallStackList) -> Result<(), CalcitErr> {
   let (listeners, prev) = {
@@ -122,7 +123,7 @@ pub fn defatom(expr: &CalcitList, scope: &CalcitScope, file_ns: &str, call_stack
 }
 
 /// dead simple counter for ID generator, better use nanoid in business
-static ATOM_ID_GEN: AtomicUsize = AtomicUsize::new(0);
+static ATOM_ID_GEN: ContextSlot<AtomicUsize> = ContextSlot::new(|ctx| &ctx.atom_ids);
 
 /// proc
 pub fn atom(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
@@ -40,7 +40,7 @@ fn validate_trait_impl_entries(trait_def: &crate::calcit::CalcitTrait, entries:
   let missing = trait_def
     .methods
     .iter()
-    .filter(|method| !entries.iter().any(|(name, _)| name == *method))
+    .filter(|method| !entries.iter().any(|(name, _)| name == *method) && trait_def.get_default(method.ref_str()).is_none())
     .map(ToString::to_string)
     .collect::<Vec<_>>();
   let unexpected = entries
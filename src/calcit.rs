@@ -49,9 +49,9 @@ pub use symbol::{CalcitImport, CalcitSymbolInfo, ImportInfo};
 pub use syntax_name::{CalcitSyntax, SyntaxTypeSignature};
 pub use thunk::{CalcitThunk, CalcitThunkInfo};
 pub use type_annotation::{
-  CalcitFnTypeAnnotation, CalcitGenericBound, CalcitTypeAnnotation, DYNAMIC_TYPE, SchemaKind, brief_type_of_value, clear_type_slots,
-  configure_entry_type_slots, pop_type_slot_override, push_type_slot_override, register_program_lookups, register_type_slot,
-  resolve_type_slot, value_matches_type_annotation, with_type_annotation_warning_context,
+  CalcitFnTypeAnnotation, CalcitGenericBound, CalcitMapShape, CalcitTypeAnnotation, DYNAMIC_TYPE, SchemaKind, brief_type_of_value,
+  clear_type_slots, configure_entry_type_slots, pop_type_slot_override, push_type_slot_override, register_program_lookups,
+  register_type_slot, resolve_type_slot, value_matches_type_annotation, with_type_annotation_warning_context,
 };
 
 use compare::{
@@ -61,9 +61,10 @@ use compare::{
 
 use crate::builtins::ValueAndListeners;
 use crate::call_stack::CallStackList;
+use crate::runtime_context::ContextSlot;
 
 /// dead simple counter for ID generator, better use nanoid in business
-static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+static ID_GEN: ContextSlot<AtomicUsize> = ContextSlot::new(|ctx| &ctx.fn_ids);
 
 /// dynamic data defined in Calcit
 #[derive(Debug, Clone)]
@@ -968,6 +969,8 @@ pub enum CalcitErrKind {
   Arity,
   Var,
   Effect,
+  /// denied by a sandbox capability or budget
+  Sandbox,
   Unexpected,
   Unimplemented,
 }
@@ -982,6 +985,7 @@ impl fmt::Display for CalcitErrKind {
       Arity => "Arity",
       Var => "Var",
       Effect => "Effect",
+      Sandbox => "Sandbox",
       Unexpected => "Unexpected",
       Unimplemented => "Unimplemented",
     })
@@ -2,7 +2,7 @@ use std::{cmp::Ordering, sync::Arc};
 
 use cirru_edn::EdnTag;
 
-use super::{Calcit, CalcitTrait};
+use super::{Calcit, CalcitTrait, CalcitTraitMemberKind};
 
 #[derive(Debug, Clone)]
 pub struct CalcitImpl {
@@ -22,6 +22,28 @@ impl CalcitImpl {
     }
   }
 
+  /// Static stand-in for an impl generated by `defstruct ... :derive`. The
+  /// method table mirrors the trait's required methods; values stay `nil`
+  /// since only the shape is known before the definition is evaluated.
+  pub fn derived_stand_in(origin: Arc<CalcitTrait>) -> Self {
+    let mut fields: Vec<EdnTag> = origin
+      .methods
+      .iter()
+      .zip(origin.member_kinds.iter())
+      .zip(origin.defaults.iter())
+      .filter(|((_, kind), default_impl)| **kind == CalcitTraitMemberKind::Method && default_impl.is_none())
+      .map(|((method, _), _)| method.to_owned())
+      .collect();
+    fields.sort_by(|a, b| a.ref_str().cmp(b.ref_str()));
+    let values = vec![Calcit::Nil; fields.len()];
+    CalcitImpl {
+      name: origin.name.to_owned(),
+      origin: Some(origin),
+      fields: Arc::new(fields),
+      values: Arc::new(values),
+    }
+  }
+
   pub fn name(&self) -> &EdnTag {
     &self.name
   }
@@ -5,8 +5,9 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use cirru_edn::EdnTag;
 
 use super::{CalcitFn, CalcitTypeAnnotation};
+use crate::runtime_context::ContextSlot;
 
-static NEXT_TRAIT_RUNTIME_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_TRAIT_RUNTIME_ID: ContextSlot<AtomicU64> = ContextSlot::new(|ctx| &ctx.trait_ids);
 
 /// Source member shape retained for traits. Ordinary traits use method
 /// members; external-object traits may additionally expose typed fields.
@@ -173,6 +174,22 @@ impl CalcitTrait {
     self
   }
 
+  /// Attach default method bodies, parallel to `methods`.
+  pub fn with_defaults(mut self, defaults: Vec<Option<Arc<CalcitFn>>>) -> Self {
+    assert!(
+      defaults.len() == self.methods.len(),
+      "CalcitTrait::with_defaults expects defaults to match methods length"
+    );
+    self.defaults = Arc::new(defaults);
+    self
+  }
+
+  /// Attach supertraits that every type implementing this trait must also implement.
+  pub fn with_requires(mut self, requires: Vec<Arc<CalcitTrait>>) -> Self {
+    self.requires = Arc::new(requires);
+    self
+  }
+
   /// Build an unresolved trait reference while retaining a qualified source
   /// path when one is present in the schema.
   pub fn new_reference(name: &str) -> Self {
@@ -236,6 +253,17 @@ impl CalcitTrait {
       .method_index(name)
       .and_then(|idx| self.defaults.get(idx).and_then(|d| d.as_ref()))
   }
+
+  /// Names of required traits that `implemented` does not satisfy. Each impl
+  /// checks its own direct supertraits, so chains are enforced transitively.
+  pub fn missing_requires(&self, implemented: &[&CalcitTrait]) -> Vec<EdnTag> {
+    self
+      .requires
+      .iter()
+      .filter(|required| !implemented.iter().any(|candidate| candidate.matches_reference(required)))
+      .map(|required| required.name.to_owned())
+      .collect()
+  }
 }
 
 impl Hash for CalcitTrait {
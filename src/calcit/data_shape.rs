@@ -514,6 +514,7 @@ impl GraphBuilder {
       CalcitTypeAnnotation::JsNullish(_) => Err(unsupported_type("JsNullish is an opaque JavaScript boundary value")),
       CalcitTypeAnnotation::Custom(value) => Err(unsupported_type(&format!("custom type `{value}` has no data shape"))),
       CalcitTypeAnnotation::Variadic(_) => Err(unsupported_type("Variadic is a function parameter constraint")),
+      CalcitTypeAnnotation::MapShape(_) => Err(unsupported_type("map shapes describe legacy maps; declare a Struct for decoding")),
     }
   }
 
@@ -657,7 +658,7 @@ fn qualify_type_ref(name: &str, default_ns: &str) -> (Arc<str>, Arc<str>) {
   let stripped = name.trim_start_matches('\'').trim_start_matches(':');
   if let Some((ns, def)) = stripped.rsplit_once('/') {
     (Arc::from(ns), Arc::from(def))
-  } else if program::has_def_code(default_ns, stripped) {
+  } else if program::has_def(default_ns, stripped) {
     (Arc::from(default_ns), Arc::from(stripped))
   } else if let Some(target_ns) = program::lookup_def_target_in_import(default_ns, stripped) {
     (target_ns, Arc::from(stripped))
@@ -667,9 +668,9 @@ fn qualify_type_ref(name: &str, default_ns: &str) -> (Arc<str>, Arc<str>) {
 }
 
 fn infer_nominal_path(default_ns: &str, name: &str) -> Option<(Arc<str>, Arc<str>)> {
-  if program::has_def_code(default_ns, name) {
+  if program::has_def(default_ns, name) {
     Some((Arc::from(default_ns), Arc::from(name)))
-  } else if program::has_def_code(super::CORE_NS, name) {
+  } else if program::has_def(super::CORE_NS, name) {
     Some((Arc::from(super::CORE_NS), Arc::from(name)))
   } else {
     None
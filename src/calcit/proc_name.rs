@@ -1167,7 +1167,7 @@ impl CalcitProc {
       }),
       NativeTraitNew => Some(ProcTypeSignature {
         return_type: some_tag("trait"),
-        arg_types: vec![dynamic_tag(), some_tag("list")],
+        arg_types: vec![dynamic_tag(), some_tag("list"), some_tag("list")],
       }),
       NativeImplNew => Some(ProcTypeSignature {
         return_type: some_tag("impl"),
@@ -1443,7 +1443,7 @@ impl CalcitProc {
     match self {
       GenerateId | Range | NativeListRange => 2,
       NativeInspectMethods | NativeInspectType | Trim | NativeStrSlice | Sort | NativeListSort | NativeListSlice | NativeStructNth
-      | ReadDir | GetEnv | ParseCirruEdn | FormatCirru | FormatCirruEdn | Todo => 1,
+      | ReadDir | GetEnv | ParseCirruEdn | FormatCirru | FormatCirruEdn | Todo | NativeTraitNew => 1,
       _ => 0,
     }
   }
@@ -1527,6 +1527,7 @@ mod tests {
       (CalcitProc::ParseCirruEdn, ProcArity { min: 1, max: Some(2) }),
       (CalcitProc::FormatCirru, ProcArity { min: 1, max: Some(2) }),
       (CalcitProc::FormatCirruEdn, ProcArity { min: 1, max: Some(2) }),
+      (CalcitProc::NativeTraitNew, ProcArity { min: 2, max: Some(3) }),
     ] {
       assert_eq!(proc.arity(), Some(expected), "{proc} arity");
     }
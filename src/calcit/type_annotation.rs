@@ -272,6 +272,9 @@ pub enum CalcitTypeAnnotation {
   List(Arc<CalcitTypeAnnotation>),
   /// Map type with key and value type annotations
   Map(Arc<CalcitTypeAnnotation>, Arc<CalcitTypeAnnotation>),
+  /// Record-like map with known tag keys and per-key value types, e.g.
+  /// `:: :map-shape $ {} (:required $ {} (:name :string)) (:rest :dynamic)`.
+  MapShape(Arc<CalcitMapShape>),
   /// A struct value's inferred type, identified by its struct definition.
   StructValue(Arc<CalcitStructDef>),
   /// An enum value's inferred type, identified by its enum definition.
@@ -375,6 +378,12 @@ impl CalcitTypeAnnotation {
         key.validate_applied_type_args()?;
         value.validate_applied_type_args()
       }
+      Self::MapShape(shape) => {
+        for ty in shape.all_value_types() {
+          ty.validate_applied_type_args()?;
+        }
+        Ok(())
+      }
       Self::Fn(signature) => signature.validate_applied_type_args(),
       Self::Struct(base, args) => {
         for arg in args.iter() {
@@ -524,6 +533,7 @@ impl CalcitTypeAnnotation {
       "tag" | "Tag" => Some("Tag"),
       "list" | "List" => Some("List"),
       "map" | "Map" => Some("Map"),
+      "map-shape" | "MapShape" => Some("MapShape"),
       "set" | "Set" => Some("Set"),
       "fn" | "Fn" => Some("Fn"),
       "macro" | "Macro" => Some("Macro"),
@@ -557,6 +567,12 @@ impl CalcitTypeAnnotation {
       "Tag" => Some(Self::Tag),
       "List" => Some(Self::List(DYNAMIC_TYPE.clone())),
       "Map" => Some(Self::Map(DYNAMIC_TYPE.clone(), DYNAMIC_TYPE.clone())),
+      // A bare shape without fields is an open record accepting any tag keys.
+      "MapShape" => Some(Self::MapShape(Arc::new(CalcitMapShape::new(
+        vec![],
+        vec![],
+        Some(DYNAMIC_TYPE.clone()),
+      )))),
       "Set" => Some(Self::Set(DYNAMIC_TYPE.clone())),
       "Fn" | "Macro" => Some(Self::DynFn),
       "Enum" => Some(Self::AnonymousEnum),
@@ -740,6 +756,71 @@ impl CalcitTypeAnnotation {
     }
   }
 
+  /// Key/value pairs of a schema map, accepting both evaluated maps and `{}` literal forms.
+  fn schema_map_entries(form: &Calcit) -> Option<Vec<(&Calcit, &Calcit)>> {
+    match form {
+      Calcit::Map(xs) => Some(xs.iter().collect()),
+      Calcit::List(xs) => {
+        if !matches!(xs.first(), Some(head) if Self::is_schema_map_literal_head(head)) {
+          return None;
+        }
+        let entries = xs
+          .iter()
+          .skip(1)
+          .filter_map(|entry| match entry {
+            Calcit::List(pair) if pair.len() >= 2 => Some((pair.get(0)?, pair.get(1)?)),
+            _ => None,
+          })
+          .collect();
+        Some(entries)
+      }
+      _ => None,
+    }
+  }
+
+  /// Parse the `{}` payload of `:: :map-shape $ {} (:required ...) (:optional ...) (:rest ...)`.
+  fn parse_map_shape_from_schema_form(
+    form: &Calcit,
+    generics: &[Arc<str>],
+    strict_named_refs: bool,
+  ) -> Option<Arc<CalcitTypeAnnotation>> {
+    let entries = Self::schema_map_entries(form)?;
+    let parse_fields = |fields_form: &Calcit, section: &str| {
+      let Some(pairs) = Self::schema_map_entries(fields_form) else {
+        eprintln!("[Warn] :map-shape expects a map for :{section}, got {fields_form}");
+        return vec![];
+      };
+      pairs
+        .into_iter()
+        .filter_map(|(key, value)| match key {
+          Calcit::Tag(tag) => Some((
+            tag.to_owned(),
+            Self::parse_type_annotation_form_inner(value, generics, strict_named_refs),
+          )),
+          _ => {
+            eprintln!("[Warn] :map-shape only supports tag keys, got {key}");
+            None
+          }
+        })
+        .collect::<Vec<_>>()
+    };
+
+    let mut required = vec![];
+    let mut optional = vec![];
+    let mut rest = None;
+    for (key, value) in entries {
+      match Self::schema_key_name(key) {
+        Some("required") => required = parse_fields(value, "required"),
+        Some("optional") => optional = parse_fields(value, "optional"),
+        Some("rest") => rest = Some(Self::parse_type_annotation_form_inner(value, generics, strict_named_refs)),
+        _ => eprintln!("[Warn] :map-shape expects :required, :optional or :rest, got {key}"),
+      }
+    }
+    Some(Arc::new(CalcitTypeAnnotation::MapShape(Arc::new(CalcitMapShape::new(
+      required, optional, rest,
+    )))))
+  }
+
   fn collect_fn_schema_fields<'a>(form: &'a Calcit) -> FnSchemaFields<'a> {
     let mut fields = FnSchemaFields::default();
 
@@ -1865,6 +1946,11 @@ impl CalcitTypeAnnotation {
               return parsed;
             }
           }
+          "MapShape" if let Some(schema_form) = enum_value.extra.first() => {
+            if let Some(parsed) = Self::parse_map_shape_from_schema_form(schema_form, generics, strict_named_refs) {
+              return parsed;
+            }
+          }
           _ => {}
         }
       }
@@ -1928,6 +2014,12 @@ impl CalcitTypeAnnotation {
           }
           return Arc::new(CalcitTypeAnnotation::Ref(Arc::new(Self::Dynamic)));
         }
+        if tag_name == "map-shape"
+          && let Some(schema_form) = xs.get(1)
+          && let Some(parsed) = Self::parse_map_shape_from_schema_form(schema_form, generics, strict_named_refs)
+        {
+          return parsed;
+        }
         if tag_name == "fn" {
           if let Some(schema_form) = xs.get(1)
             && let Some(parsed) = Self::parse_fn_annotation_from_schema_form(schema_form, generics, strict_named_refs)
@@ -2087,6 +2179,11 @@ impl CalcitTypeAnnotation {
                   return parsed;
                 }
               }
+              "MapShape" if let Some(schema_form) = xs.get(2) => {
+                if let Some(parsed) = Self::parse_map_shape_from_schema_form(schema_form, generics, strict_named_refs) {
+                  return parsed;
+                }
+              }
               _ => {}
             }
           }
@@ -2154,6 +2251,7 @@ impl CalcitTypeAnnotation {
       Self::Variadic(inner) => format!("&{}", inner.to_brief_string()),
       Self::List(inner) => format!("list<{}>", inner.to_brief_string()),
       Self::Map(k, v) => format!("map<{},{}>", k.to_brief_string(), v.to_brief_string()),
+      Self::MapShape(shape) => shape.render(|ty| ty.to_brief_string()),
       Self::Set(inner) => format!("set<{}>", inner.to_brief_string()),
       Self::Ref(inner) => format!("ref<{}>", inner.to_brief_string()),
       Self::Custom(inner) => format!("{inner}"),
@@ -2210,6 +2308,7 @@ impl CalcitTypeAnnotation {
       }
       Self::List(inner) => Arc::new(Self::List(inner.substitute_type_vars(bindings))),
       Self::Map(k, v) => Arc::new(Self::Map(k.substitute_type_vars(bindings), v.substitute_type_vars(bindings))),
+      Self::MapShape(shape) => Arc::new(Self::MapShape(Arc::new(shape.map_types(|ty| ty.substitute_type_vars(bindings))))),
       Self::Set(inner) => Arc::new(Self::Set(inner.substitute_type_vars(bindings))),
       Self::Ref(inner) => Arc::new(Self::Ref(inner.substitute_type_vars(bindings))),
       Self::Optional(inner) => Arc::new(Self::Optional(inner.substitute_type_vars(bindings))),
@@ -2254,6 +2353,7 @@ impl CalcitTypeAnnotation {
       | Self::JsNullish(inner)
       | Self::Variadic(inner) => inner.contains_type_var(),
       Self::Map(k, v) => k.contains_type_var() || v.contains_type_var(),
+      Self::MapShape(shape) => shape.all_value_types().any(|ty| ty.contains_type_var()),
       Self::Fn(sig) => sig.arg_types.iter().any(|a| a.contains_type_var()) || sig.return_type.contains_type_var(),
       Self::Struct(_, args) | Self::Enum(_, args) => args.iter().any(|a| a.contains_type_var()),
       _ => false,
@@ -2342,7 +2442,7 @@ impl CalcitTypeAnnotation {
     match self {
       Self::List(_) => Some("&core-list-impls"),
       Self::String => Some("&core-string-impls"),
-      Self::Map(_, _) => Some("&core-map-impls"),
+      Self::Map(_, _) | Self::MapShape(_) => Some("&core-map-impls"),
       Self::Set(_) => Some("&core-set-impls"),
       Self::Number => Some("&core-number-impls"),
       Self::DynFn | Self::Fn(_) => Some("&core-fn-impls"),
@@ -2511,6 +2611,58 @@ impl CalcitTypeAnnotation {
       .all(|trait_def| self.satisfies_trait_bound(trait_def.as_ref()))
   }
 
+  /// Width subtyping between map shapes: `actual` must provide every required key of `expected`,
+  /// listed keys must agree on value types, and extra keys need an open row on the expected side.
+  fn map_shape_matches(actual: &CalcitMapShape, expected: &CalcitMapShape, bindings: &mut TypeBindings) -> bool {
+    for (key, expected_ty) in expected.required.iter() {
+      match actual.field(key.ref_str()) {
+        Some((actual_ty, true)) if actual_ty.matches_with_bindings(expected_ty, bindings) => {}
+        _ => return false,
+      }
+    }
+    for (key, expected_ty) in expected.optional.iter() {
+      let actual_ty = actual.field(key.ref_str()).map(|(ty, _)| ty).or(actual.rest.as_ref());
+      if let Some(actual_ty) = actual_ty
+        && !actual_ty.matches_with_bindings(expected_ty, bindings)
+      {
+        return false;
+      }
+    }
+    for (key, actual_ty) in actual.fields() {
+      if expected.field(key.ref_str()).is_some() {
+        continue;
+      }
+      match &expected.rest {
+        Some(rest) if actual_ty.matches_with_bindings(rest, bindings) => {}
+        _ => return false,
+      }
+    }
+    match (&actual.rest, &expected.rest) {
+      (None, _) => true,
+      (Some(_), None) => false,
+      (Some(actual_rest), Some(expected_rest)) => actual_rest.matches_with_bindings(expected_rest, bindings),
+    }
+  }
+
+  /// Whether a map key type admits every key, binding a free type variable to `:dynamic`.
+  fn accepts_any_key(key: &CalcitTypeAnnotation, bindings: &mut TypeBindings) -> bool {
+    match key {
+      Self::Dynamic => true,
+      Self::Custom(name) => Self::custom_keyword_matches(name, "any"),
+      Self::TypeVar(var) => match bindings.get(var) {
+        Some(bound) => {
+          let bound = bound.clone();
+          Self::accepts_any_key(bound.as_ref(), bindings)
+        }
+        None => {
+          bindings.insert(var.to_owned(), DYNAMIC_TYPE.clone());
+          true
+        }
+      },
+      _ => false,
+    }
+  }
+
   pub fn matches_annotation(&self, expected: &CalcitTypeAnnotation) -> bool {
     let mut bindings = TypeBindings::new();
     self.matches_with_bindings(expected, &mut bindings)
@@ -2581,6 +2733,22 @@ impl CalcitTypeAnnotation {
       }
       (Self::List(a), Self::List(b)) => a.matches_with_bindings(b, bindings),
       (Self::Map(ak, av), Self::Map(bk, bv)) => ak.matches_with_bindings(bk, bindings) && av.matches_with_bindings(bv, bindings),
+      (Self::MapShape(actual), Self::MapShape(expected)) => Self::map_shape_matches(actual, expected, bindings),
+      // An open row may hold keys of any kind at runtime, so only a map with unconstrained keys covers it.
+      (Self::MapShape(actual), Self::Map(key, value)) => {
+        let key_matches = if actual.rest.is_some() {
+          Self::accepts_any_key(key, bindings)
+        } else {
+          Self::Tag.matches_with_bindings(key, bindings)
+        };
+        key_matches && actual.all_value_types().all(|ty| ty.matches_with_bindings(value, bindings))
+      }
+      // Gradual typing: a plain map does not prove which keys are present, but its values must
+      // still be compatible with every listed field so legacy code can adopt shapes incrementally.
+      (Self::Map(key, value), Self::MapShape(expected)) => {
+        key.matches_with_bindings(&Self::Tag, bindings)
+          && expected.all_value_types().all(|ty| value.matches_with_bindings(ty, bindings))
+      }
       (Self::Set(a), Self::Set(b)) => a.matches_with_bindings(b, bindings),
       (Self::Ref(a), Self::Ref(b)) => a.matches_with_bindings(b, bindings),
       (Self::TypeRef(name, args), Self::Struct(base, other_args)) | (Self::Struct(base, other_args), Self::TypeRef(name, args)) => {
@@ -2939,6 +3107,11 @@ impl CalcitTypeAnnotation {
         extra: vec![inner.to_calcit()],
         sum_type: None,
       }),
+      Self::MapShape(shape) => Calcit::Enum(CalcitEnumValue {
+        tag: Arc::new(Calcit::Tag(EdnTag::from("map-shape"))),
+        extra: vec![shape.to_schema_calcit()],
+        sum_type: None,
+      }),
       Self::Struct(struct_def, args) => {
         if args.is_empty() {
           Calcit::StructDef((**struct_def).clone())
@@ -3018,6 +3191,7 @@ impl CalcitTypeAnnotation {
           Edn::enum_value("Map", vec![k.to_type_edn(), v.to_type_edn()])
         }
       }
+      Self::MapShape(shape) => Edn::enum_value("MapShape", vec![shape.to_schema_edn()]),
       Self::Set(inner) => {
         if matches!(inner.as_ref(), Self::Dynamic) {
           Edn::Symbol(Arc::from("Set"))
@@ -3125,6 +3299,7 @@ impl CalcitTypeAnnotation {
         }
         return format!("map<{}, {}>", k.describe(), v.describe());
       }
+      Self::MapShape(shape) => return shape.render(|ty| ty.describe()),
       Self::Set(inner) => {
         if matches!(inner.as_ref(), Self::Dynamic) {
           return "set".to_string();
@@ -3217,6 +3392,7 @@ impl CalcitTypeAnnotation {
       Self::TypeSlot(_) => 30,
       Self::StructDef(_) => 31,
       Self::EnumDef(_) => 32,
+      Self::MapShape(_) => 33,
     }
   }
 }
@@ -3336,6 +3512,12 @@ fn calcit_type_to_edn(form: &Calcit) -> Edn {
   }
 }
 
+/// Resolve a definition's source code form to the StructDef or EnumDef it
+/// declares, without evaluating the definition.
+pub(crate) fn resolve_nominal_type_def_from_code(code: &Calcit) -> Option<Calcit> {
+  resolve_type_def_from_code(code).filter(|resolved| matches!(resolved, Calcit::StructDef(_) | Calcit::EnumDef(_)))
+}
+
 /// Check whether a definition's source code form resolves to a concrete
 /// StructDef or EnumDef. Used to restrict direct `assert-type` type resolution
 /// to nominal type definitions, so visible function or value names are never
@@ -3433,7 +3615,8 @@ fn is_list_literal_head(head: &Calcit) -> bool {
 }
 
 fn parse_defstruct_code(items: &CalcitList) -> Option<CalcitStructDef> {
-  let forms = normalized_data_definition_forms(items);
+  let (items, derived_impls) = split_derive_clause(items);
+  let forms = normalized_data_definition_forms(&items);
   let name_form = forms.get(1)?;
   let name = parse_type_name(name_form)?;
   let mut generics: Vec<Arc<str>> = vec![];
@@ -3491,12 +3674,13 @@ fn parse_defstruct_code(items: &CalcitList) -> Option<CalcitStructDef> {
     field_types: Arc::new(field_types),
     generics: Arc::new(generics),
     where_bounds: Arc::new(where_bounds),
-    impls: vec![],
+    impls: derived_impls,
   })
 }
 
 fn parse_defenum_code(items: &CalcitList) -> Option<CalcitEnumDef> {
-  let forms = normalized_data_definition_forms(items);
+  let (items, derived_impls) = split_derive_clause(items);
+  let forms = normalized_data_definition_forms(&items);
   let name_form = forms.get(1)?;
   let name = parse_type_name(name_form)?;
   let mut generics: Vec<Arc<str>> = vec![];
@@ -3554,21 +3738,50 @@ fn parse_defenum_code(items: &CalcitList) -> Option<CalcitEnumDef> {
     struct_ref: Arc::new(struct_ref),
     values: Arc::new(values),
   };
-  CalcitEnumDef::from_struct(struct_value).ok()
+  let mut enum_def = CalcitEnumDef::from_struct(struct_value).ok()?;
+  enum_def.set_impls(derived_impls);
+  Some(enum_def)
+}
+
+/// Split a trailing `:derive (Trait ...)` clause off a `defstruct`/`defenum`
+/// form. The macro attaches one impl per derived core trait, so static
+/// resolution mirrors that with stand-ins carrying the same method table.
+fn split_derive_clause(items: &CalcitList) -> (Vec<&Calcit>, Vec<Arc<CalcitImpl>>) {
+  let forms: Vec<&Calcit> = items.iter().collect();
+  let size = forms.len();
+  if size < 4 || !matches!(forms[size - 2], Calcit::Tag(tag) if tag.ref_str() == "derive") {
+    return (forms, vec![]);
+  }
+  let Calcit::List(traits) = forms[size - 1] else {
+    return (forms, vec![]);
+  };
+  let derived_impls = traits
+    .iter()
+    .filter(|item| !is_list_literal_head(item))
+    .filter_map(|item| match item {
+      Calcit::List(quoted) if quoted.len() == 2 => quoted.get(1).and_then(parse_type_name),
+      _ => parse_type_name(item),
+    })
+    .filter_map(|name| match lookup_runtime_ready_registered(CORE_NS, name.ref_str()) {
+      Some(Calcit::Trait(trait_def)) => Some(Arc::new(CalcitImpl::derived_stand_in(Arc::new(trait_def)))),
+      _ => None,
+    })
+    .collect();
+  (forms[..size - 2].to_vec(), derived_impls)
 }
 
 /// `defstruct` and `defenum` accept a map-headed wrapper (`$ {} ...`) so data
 /// definitions can be supplied as one macro argument. Runtime macros normalize
 /// that form before parsing generics and `:where`; do the same for static type
 /// resolution so a named TypeRef observes identical fields or variants.
-fn normalized_data_definition_forms(items: &CalcitList) -> Vec<&Calcit> {
+fn normalized_data_definition_forms<'a>(items: &[&'a Calcit]) -> Vec<&'a Calcit> {
   let Some(Calcit::List(wrapper)) = items.get(2) else {
-    return items.iter().collect();
+    return items.to_vec();
   };
   if items.len() != 3 || !wrapper.first().is_some_and(CalcitTypeAnnotation::is_schema_map_literal_head) {
-    return items.iter().collect();
+    return items.to_vec();
   }
-  let mut forms = vec![items.first().expect("definition head"), items.get(1).expect("definition name")];
+  let mut forms = vec![items[0], items[1]];
   forms.extend(wrapper.iter().skip(1));
   forms
 }
@@ -4661,6 +4874,23 @@ mod tests {
     assert!(matches!(resolve_type_def_from_code(&wrapped), Some(Calcit::EnumDef(_))));
   }
 
+  #[test]
+  fn resolves_struct_definition_with_trailing_derive_clause() {
+    let struct_form = Calcit::from(vec![
+      symbol("defstruct"),
+      symbol("Point"),
+      Calcit::from(vec![Calcit::tag("y"), symbol("Number")]),
+      Calcit::from(vec![Calcit::tag("x"), symbol("Number")]),
+      Calcit::tag("derive"),
+      Calcit::from(vec![Calcit::from(vec![symbol("quote"), symbol("Show")])]),
+    ]);
+
+    let Some(Calcit::StructDef(struct_def)) = resolve_type_def_from_code(&struct_form) else {
+      panic!("defstruct with :derive should resolve to a StructDef");
+    };
+    assert_eq!(struct_def.fields.as_ref(), &vec![EdnTag::new("x"), EdnTag::new("y")]);
+  }
+
   #[test]
   fn variadic_function_satisfies_fixed_arity_callback_contract() {
     let number = Arc::new(CalcitTypeAnnotation::Number);
@@ -4739,6 +4969,153 @@ mod tests {
 
     assert!(actual.matches_annotation(&expected));
   }
+
+  fn map_shape_form(required: &[(&str, &str)], optional: &[(&str, &str)], rest: Option<&str>) -> Calcit {
+    let list = |items: Vec<Calcit>| Calcit::List(Arc::new(CalcitList::from(items.as_slice())));
+    let fields = |pairs: &[(&str, &str)]| {
+      let mut items = vec![symbol("{}")];
+      items.extend(pairs.iter().map(|(key, ty)| list(vec![Calcit::tag(key), Calcit::tag(ty)])));
+      list(items)
+    };
+    let mut payload = vec![
+      symbol("{}"),
+      list(vec![Calcit::tag("required"), fields(required)]),
+      list(vec![Calcit::tag("optional"), fields(optional)]),
+    ];
+    if let Some(rest) = rest {
+      payload.push(list(vec![Calcit::tag("rest"), Calcit::tag(rest)]));
+    }
+    list(vec![symbol("::"), Calcit::tag("map-shape"), list(payload)])
+  }
+
+  #[test]
+  fn parses_map_shape_and_round_trips_through_edn() {
+    let form = map_shape_form(&[("name", "string"), ("age", "number")], &[("email", "string")], Some("dynamic"));
+    let parsed = CalcitTypeAnnotation::parse_type_annotation_form(&form);
+    let CalcitTypeAnnotation::MapShape(shape) = parsed.as_ref() else {
+      panic!("expected map shape, got {parsed:?}");
+    };
+    assert_eq!(
+      shape.required.iter().map(|(k, _)| k.ref_str()).collect::<Vec<_>>(),
+      vec!["age", "name"]
+    );
+    assert!(matches!(shape.field("email"), Some((ty, false)) if ty.as_ref() == &CalcitTypeAnnotation::String));
+    assert!(matches!(shape.rest.as_deref(), Some(CalcitTypeAnnotation::Dynamic)));
+    assert_eq!(
+      parsed.to_brief_string(),
+      "map{:age :number, :name :string, :email? :string, ..dynamic}"
+    );
+
+    let reparsed = CalcitTypeAnnotation::parse_type_annotation_from_edn(&parsed.to_type_edn());
+    assert_eq!(reparsed, parsed);
+  }
+
+  #[test]
+  fn closed_and_open_map_shapes_round_trip_with_one_rule() {
+    let closed = CalcitTypeAnnotation::parse_type_annotation_form(&map_shape_form(&[("name", "string")], &[], None));
+    let open =
+      CalcitTypeAnnotation::parse_type_annotation_form(&map_shape_form(&[("name", "string")], &[("email", "string")], Some("number")));
+    for parsed in [&closed, &open] {
+      let CalcitTypeAnnotation::MapShape(shape) = parsed.as_ref() else {
+        panic!("expected map shape, got {parsed:?}");
+      };
+      let edn = parsed.to_type_edn();
+      assert_eq!(&CalcitTypeAnnotation::parse_type_annotation_from_edn(&edn), parsed);
+      let calcit_form = Calcit::List(Arc::new(CalcitList::from(
+        [symbol("::"), Calcit::tag("map-shape"), shape.to_schema_calcit()].as_slice(),
+      )));
+      assert_eq!(&CalcitTypeAnnotation::parse_type_annotation_form(&calcit_form), parsed);
+      let Calcit::Map(calcit_schema) = shape.to_schema_calcit() else {
+        panic!("expected schema map");
+      };
+      let Edn::Map(edn_schema) = shape.to_schema_edn() else {
+        panic!("expected schema map");
+      };
+      assert_eq!(calcit_schema.size(), edn_schema.0.len(), "both schemas omit empty sections");
+    }
+
+    let CalcitTypeAnnotation::MapShape(closed_shape) = closed.as_ref() else {
+      unreachable!()
+    };
+    let CalcitTypeAnnotation::MapShape(open_shape) = open.as_ref() else {
+      unreachable!()
+    };
+    assert_eq!(closed_shape.key_type("age").as_ref(), &CalcitTypeAnnotation::Unit);
+    assert_eq!(open_shape.key_type("age").as_ref(), &CalcitTypeAnnotation::Number);
+    assert_eq!(
+      closed_shape.as_plain_map().as_ref(),
+      &CalcitTypeAnnotation::Map(Arc::new(CalcitTypeAnnotation::Tag), Arc::new(CalcitTypeAnnotation::String))
+    );
+    assert_eq!(
+      open_shape.as_plain_map().as_ref(),
+      &CalcitTypeAnnotation::Map(DYNAMIC_TYPE.clone(), DYNAMIC_TYPE.clone())
+    );
+  }
+
+  #[test]
+  fn map_shape_matching_uses_width_subtyping() {
+    let parse = |form: Calcit| CalcitTypeAnnotation::parse_type_annotation_form(&form);
+    let person = parse(map_shape_form(&[("name", "string")], &[("email", "string")], None));
+    let with_age = parse(map_shape_form(&[("name", "string"), ("age", "number")], &[], None));
+    let open_person = parse(map_shape_form(&[("name", "string")], &[], Some("dynamic")));
+    let numbered_name = parse(map_shape_form(&[("name", "number")], &[], None));
+
+    assert!(parse(map_shape_form(&[("name", "string"), ("email", "string")], &[], None)).matches_annotation(&person));
+    assert!(!with_age.matches_annotation(&person), "closed shapes reject unlisted keys");
+    assert!(with_age.matches_annotation(&open_person));
+    assert!(!person.matches_annotation(&with_age), "optional keys do not satisfy required ones");
+    assert!(!numbered_name.matches_annotation(&person));
+
+    let dynamic = crate::calcit::DYNAMIC_TYPE.clone();
+    let plain_map = CalcitTypeAnnotation::Map(dynamic.clone(), dynamic);
+    assert!(person.matches_annotation(&plain_map));
+    assert!(plain_map.matches_annotation(&person), "legacy maps adopt shapes gradually");
+    let number_map = CalcitTypeAnnotation::Map(Arc::new(CalcitTypeAnnotation::Tag), Arc::new(CalcitTypeAnnotation::Number));
+    assert!(!number_map.matches_annotation(&person));
+
+    let any_string_map = CalcitTypeAnnotation::Map(crate::calcit::DYNAMIC_TYPE.clone(), Arc::new(CalcitTypeAnnotation::String));
+    let tag_string_map = CalcitTypeAnnotation::Map(Arc::new(CalcitTypeAnnotation::Tag), Arc::new(CalcitTypeAnnotation::String));
+    let open_strings = parse(map_shape_form(&[("name", "string")], &[], Some("string")));
+    assert!(person.matches_annotation(&tag_string_map), "closed shapes only hold tag keys");
+    assert!(
+      !open_strings.matches_annotation(&tag_string_map),
+      "open rows may hold non-tag keys, so they do not fit a tag-keyed map"
+    );
+    assert!(open_strings.matches_annotation(&any_string_map));
+  }
+
+  #[test]
+  fn runtime_map_values_are_checked_against_map_shape() {
+    let person = CalcitTypeAnnotation::parse_type_annotation_form(&map_shape_form(&[("name", "string")], &[("age", "number")], None));
+    let map = |pairs: &[(&str, Calcit)]| {
+      let mut xs = rpds::HashTrieMap::new_sync();
+      for (key, value) in pairs {
+        xs.insert_mut(Calcit::tag(key), value.to_owned());
+      }
+      Calcit::Map(xs)
+    };
+
+    assert!(value_matches_type_annotation(&map(&[("name", Calcit::Str("Ada".into()))]), &person));
+    assert!(value_matches_type_annotation(
+      &map(&[("name", Calcit::Str("Ada".into())), ("age", Calcit::Number(36.0))]),
+      &person
+    ));
+    assert!(!value_matches_type_annotation(&map(&[("age", Calcit::Number(36.0))]), &person));
+    assert!(!value_matches_type_annotation(
+      &map(&[("name", Calcit::Str("Ada".into())), ("role", Calcit::Str("admin".into()))]),
+      &person
+    ));
+
+    let open_strings = CalcitTypeAnnotation::parse_type_annotation_form(&map_shape_form(&[("name", "string")], &[], Some("string")));
+    let Calcit::Map(with_string_key) = map(&[("name", Calcit::Str("Ada".into()))]) else {
+      unreachable!()
+    };
+    let with_string_key = Calcit::Map(with_string_key.insert(Calcit::Str("nick".into()), Calcit::Str("ada".into())));
+    assert!(
+      value_matches_type_annotation(&with_string_key, &open_strings),
+      "the open row covers non-tag keys, matching the static rule"
+    );
+  }
 }
 
 impl fmt::Display for CalcitTypeAnnotation {
@@ -4764,6 +5141,10 @@ impl Hash for CalcitTypeAnnotation {
         k.hash(state);
         v.hash(state);
       }
+      Self::MapShape(shape) => {
+        "map-shape".hash(state);
+        shape.hash(state);
+      }
       Self::StructValue(struct_def) => {
         "record".hash(state);
         struct_def.name.hash(state);
@@ -4885,6 +5266,7 @@ impl Ord for CalcitTypeAnnotation {
       | (Self::CirruQuote, Self::CirruQuote) => Ordering::Equal,
       (Self::List(a), Self::List(b)) => a.cmp(b),
       (Self::Map(ak, av), Self::Map(bk, bv)) => ak.cmp(bk).then_with(|| av.cmp(bv)),
+      (Self::MapShape(a), Self::MapShape(b)) => a.cmp(b),
       (Self::StructValue(a), Self::StructValue(b)) => a.name.cmp(&b.name).then_with(|| a.fields.cmp(&b.fields)),
       (Self::EnumValue(a), Self::EnumValue(b)) => a.name().cmp(b.name()),
       (Self::Fn(a), Self::Fn(b)) => a
@@ -5204,6 +5586,160 @@ impl CalcitFnTypeAnnotation {
   }
 }
 
+/// Record-like map type: known tag keys with per-key value types plus an
+/// optional open "rest" row for every other key.
+///
+/// Written in source as
+/// `:: :map-shape $ {} (:required $ {} (:name :string)) (:optional $ {} (:email :string)) (:rest :dynamic)`.
+/// Omitting `:rest` closes the shape, so unlisted keys are rejected.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CalcitMapShape {
+  /// Keys that are always present, sorted by tag.
+  pub required: Vec<(EdnTag, Arc<CalcitTypeAnnotation>)>,
+  /// Keys that may be absent, sorted by tag.
+  pub optional: Vec<(EdnTag, Arc<CalcitTypeAnnotation>)>,
+  /// Value type of unlisted keys; `None` marks a closed shape.
+  pub rest: Option<Arc<CalcitTypeAnnotation>>,
+}
+
+impl CalcitMapShape {
+  /// Build a shape with sorted fields. A key listed as both required and optional stays required.
+  pub fn new(
+    mut required: Vec<(EdnTag, Arc<CalcitTypeAnnotation>)>,
+    mut optional: Vec<(EdnTag, Arc<CalcitTypeAnnotation>)>,
+    rest: Option<Arc<CalcitTypeAnnotation>>,
+  ) -> Self {
+    required.sort_by(|a, b| a.0.cmp(&b.0));
+    required.dedup_by(|a, b| a.0 == b.0);
+    optional.retain(|(key, _)| !required.iter().any(|(k, _)| k == key));
+    optional.sort_by(|a, b| a.0.cmp(&b.0));
+    optional.dedup_by(|a, b| a.0 == b.0);
+    Self { required, optional, rest }
+  }
+
+  /// Declared type of `key`, with `true` when the key is required.
+  pub fn field(&self, key: &str) -> Option<(&Arc<CalcitTypeAnnotation>, bool)> {
+    if let Some((_, ty)) = self.required.iter().find(|(k, _)| k.ref_str() == key) {
+      return Some((ty, true));
+    }
+    self.optional.iter().find(|(k, _)| k.ref_str() == key).map(|(_, ty)| (ty, false))
+  }
+
+  /// Shape produced by `assoc`-ing `key` with a value of type `ty`: the key becomes required.
+  pub fn with_field(&self, key: EdnTag, ty: Arc<CalcitTypeAnnotation>) -> Self {
+    let mut required = self.required.iter().filter(|(k, _)| k != &key).cloned().collect::<Vec<_>>();
+    required.push((key.clone(), ty));
+    let optional = self.optional.iter().filter(|(k, _)| k != &key).cloned().collect();
+    Self::new(required, optional, self.rest.clone())
+  }
+
+  /// Shape produced by `dissoc`-ing `key`.
+  pub fn without_field(&self, key: &EdnTag) -> Self {
+    Self {
+      required: self.required.iter().filter(|(k, _)| k != key).cloned().collect(),
+      optional: self.optional.iter().filter(|(k, _)| k != key).cloned().collect(),
+      rest: self.rest.clone(),
+    }
+  }
+
+  /// Value type read for `key`. Unlisted keys read from the open row, and are never present on a
+  /// closed shape, which gives `Unit`. Optional-ness is left to the caller, see [Self::field].
+  pub fn key_type(&self, key: &str) -> Arc<CalcitTypeAnnotation> {
+    match (self.field(key), &self.rest) {
+      (Some((ty, _)), _) => ty.clone(),
+      (None, Some(rest)) => rest.clone(),
+      (None, None) => Arc::new(CalcitTypeAnnotation::Unit),
+    }
+  }
+
+  /// Plain `Map` view used where only the map-ness of a value matters, such as proc dispatch.
+  /// Keys are tags unless the row is open, values keep a type shared by every field and the row.
+  pub fn as_plain_map(&self) -> Arc<CalcitTypeAnnotation> {
+    let key_type = if self.rest.is_some() {
+      DYNAMIC_TYPE.clone()
+    } else {
+      Arc::new(CalcitTypeAnnotation::Tag)
+    };
+    let mut value_types = self.all_value_types();
+    let value_type = match value_types.next() {
+      Some(first) if value_types.all(|ty| ty == first) => first.clone(),
+      _ => DYNAMIC_TYPE.clone(),
+    };
+    Arc::new(CalcitTypeAnnotation::Map(key_type, value_type))
+  }
+
+  fn fields(&self) -> impl Iterator<Item = &(EdnTag, Arc<CalcitTypeAnnotation>)> {
+    self.required.iter().chain(self.optional.iter())
+  }
+
+  pub fn all_value_types(&self) -> impl Iterator<Item = &Arc<CalcitTypeAnnotation>> {
+    self.fields().map(|(_, ty)| ty).chain(self.rest.iter())
+  }
+
+  pub fn map_types(&self, f: impl Fn(&Arc<CalcitTypeAnnotation>) -> Arc<CalcitTypeAnnotation>) -> Self {
+    Self {
+      required: self.required.iter().map(|(k, ty)| (k.clone(), f(ty))).collect(),
+      optional: self.optional.iter().map(|(k, ty)| (k.clone(), f(ty))).collect(),
+      rest: self.rest.as_ref().map(&f),
+    }
+  }
+
+  fn render(&self, render_type: impl Fn(&CalcitTypeAnnotation) -> String) -> String {
+    let mut parts = self
+      .required
+      .iter()
+      .map(|(k, ty)| format!(":{k} {}", render_type(ty)))
+      .collect::<Vec<_>>();
+    parts.extend(self.optional.iter().map(|(k, ty)| format!(":{k}? {}", render_type(ty))));
+    if let Some(rest) = &self.rest {
+      parts.push(format!("..{}", render_type(rest)));
+    }
+    format!("map{{{}}}", parts.join(", "))
+  }
+
+  fn to_schema_edn(&self) -> Edn {
+    let fields_edn = |fields: &[(EdnTag, Arc<CalcitTypeAnnotation>)]| {
+      let mut map = EdnMapView::default();
+      for (key, ty) in fields {
+        map.insert(Edn::Tag(key.clone()), ty.to_type_edn());
+      }
+      Edn::Map(map)
+    };
+    let mut map = EdnMapView::default();
+    if !self.required.is_empty() {
+      map.insert_key("required", fields_edn(&self.required));
+    }
+    if !self.optional.is_empty() {
+      map.insert_key("optional", fields_edn(&self.optional));
+    }
+    if let Some(rest) = &self.rest {
+      map.insert_key("rest", rest.to_type_edn());
+    }
+    Edn::Map(map)
+  }
+
+  fn to_schema_calcit(&self) -> Calcit {
+    let fields_calcit = |fields: &[(EdnTag, Arc<CalcitTypeAnnotation>)]| {
+      let mut map = rpds::HashTrieMap::new_sync();
+      for (key, ty) in fields {
+        map.insert_mut(Calcit::Tag(key.clone()), ty.to_calcit());
+      }
+      Calcit::Map(map)
+    };
+    let mut map = rpds::HashTrieMap::new_sync();
+    if !self.required.is_empty() {
+      map.insert_mut(Calcit::tag("required"), fields_calcit(&self.required));
+    }
+    if !self.optional.is_empty() {
+      map.insert_mut(Calcit::tag("optional"), fields_calcit(&self.optional));
+    }
+    if let Some(rest) = &self.rest {
+      map.insert_mut(Calcit::tag("rest"), rest.to_calcit());
+    }
+    Calcit::Map(map)
+  }
+}
+
 /// Check if a runtime `Calcit` value matches the expected `CalcitTypeAnnotation`.
 /// Used for runtime type validation when creating structs (`%{}`) and enums (`%::`).
 /// Returns `true` if the value is compatible with the declared type.
@@ -5221,6 +5757,20 @@ pub fn value_matches_type_annotation(value: &Calcit, expected: &CalcitTypeAnnota
     CalcitTypeAnnotation::Tag => matches!(value, Calcit::Tag(_)),
     CalcitTypeAnnotation::List(_) => matches!(value, Calcit::List(_)),
     CalcitTypeAnnotation::Map(_, _) => matches!(value, Calcit::Map(_)),
+    CalcitTypeAnnotation::MapShape(shape) => match value {
+      Calcit::Map(xs) => {
+        let fields_match = shape.fields().all(|(key, ty)| match xs.get(&Calcit::Tag(key.to_owned())) {
+          Some(v) => value_matches_type_annotation(v, ty),
+          None => shape.field(key.ref_str()).is_some_and(|(_, required)| !required),
+        });
+        let rest_match = xs.iter().all(|(k, v)| match k {
+          Calcit::Tag(tag) if shape.field(tag.ref_str()).is_some() => true,
+          _ => shape.rest.as_ref().is_some_and(|rest| value_matches_type_annotation(v, rest)),
+        });
+        fields_match && rest_match
+      }
+      _ => false,
+    },
     CalcitTypeAnnotation::Set(_) => matches!(value, Calcit::Set(_)),
     CalcitTypeAnnotation::Ref(_) => matches!(value, Calcit::Ref(..)),
     CalcitTypeAnnotation::Buffer => matches!(value, Calcit::Buffer(_)),
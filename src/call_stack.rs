@@ -10,7 +10,9 @@ use std::hash::Hash;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 
-static TRACK_STACK: AtomicBool = AtomicBool::new(true);
+use crate::runtime_context::ContextSlot;
+
+static TRACK_STACK: ContextSlot<AtomicBool> = ContextSlot::new(|ctx| &ctx.track_stack);
 
 /// control global stack usage
 pub fn set_using_stack(b: bool) {
@@ -5,9 +5,10 @@
 //! and identify unused definitions.
 
 use crate::calcit::Calcit;
-use crate::program::{PROGRAM_CODE_DATA, ProgramCodeData};
+use crate::program::{CompiledProgram, DefId, PROGRAM_CODE_DATA, ProgramCodeData};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 /// Represents a node in the call tree
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -92,6 +93,10 @@ pub struct CallTreeConfig {
   pub ns_prefix: Option<String>,
 }
 
+/// Definitions reachable from some roots, grouped by namespace so that
+/// lookups borrow the names instead of formatting `ns/def` keys.
+pub type ReachableDefs = HashMap<Arc<str>, HashSet<Arc<str>>>;
+
 /// Call tree analyzer
 pub struct CallTreeAnalyzer {
   config: CallTreeConfig,
@@ -180,6 +185,47 @@ impl CallTreeAnalyzer {
     })
   }
 
+  /// Collect definitions reachable from `roots` by walking compiled dependencies.
+  ///
+  /// Unlike `analyze`, this follows the preprocessed program, so references
+  /// introduced by macro expansion (mostly into `calcit.core`) are kept as well.
+  pub fn collect_compiled_reachable(&self, program: &CompiledProgram, roots: &[(&str, &str)]) -> ReachableDefs {
+    let mut names_by_id: HashMap<DefId, (&Arc<str>, &Arc<str>)> = HashMap::new();
+    for (ns, file) in program {
+      for (def, compiled) in &file.defs {
+        names_by_id.insert(compiled.def_id, (ns, def));
+      }
+    }
+
+    let mut pending: Vec<(&Arc<str>, &Arc<str>)> = roots
+      .iter()
+      .filter_map(|(ns, def)| {
+        let (ns, file) = program.get_key_value(*ns)?;
+        let (def, _) = file.defs.get_key_value(*def)?;
+        Some((ns, def))
+      })
+      .collect();
+    let mut reachable = ReachableDefs::new();
+    while let Some((ns, def)) = pending.pop() {
+      if !self.config.include_core && self.is_core_ns(ns) {
+        continue;
+      }
+      let Some(compiled) = program.get(ns).and_then(|file| file.defs.get(def)) else {
+        continue;
+      };
+      if !reachable.entry(ns.to_owned()).or_default().insert(def.to_owned()) {
+        continue;
+      }
+      for dep_id in &compiled.deps {
+        if let Some(target) = names_by_id.get(dep_id) {
+          pending.push(*target);
+        }
+      }
+    }
+
+    reachable
+  }
+
   fn build_tree(&mut self, ns: &str, def: &str, depth: usize) -> Result<CallTreeNode, String> {
     let fqn = format!("{ns}/{def}");
 
@@ -891,6 +937,7 @@ pub fn format_count_as_json(result: &CallCountResult) -> Result<String, String>
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::program::tests::compiled_file_for_test;
 
   #[test]
   fn unused_report_explains_entry_relative_limit() {
@@ -925,4 +972,42 @@ mod tests {
     assert!(output.contains("Definitions Unreachable from This Entry"));
     assert!(output.contains("may be dead code, or entry points called externally"));
   }
+
+  #[test]
+  fn compiled_reachability_follows_deps_across_namespaces() {
+    let program: CompiledProgram = HashMap::from([
+      (
+        std::sync::Arc::from("app.main"),
+        compiled_file_for_test(&[("main!", 9001, &[9002, 9010]), ("reload!", 9003, &[]), ("unused", 9004, &[9011])]),
+      ),
+      (
+        std::sync::Arc::from("app.lib"),
+        compiled_file_for_test(&[("helper", 9002, &[9001])]),
+      ),
+      (
+        std::sync::Arc::from("calcit.core"),
+        compiled_file_for_test(&[("map", 9010, &[]), ("filter", 9011, &[])]),
+      ),
+    ]);
+
+    let analyzer = CallTreeAnalyzer::new(CallTreeConfig {
+      include_core: true,
+      ..CallTreeConfig::default()
+    });
+    let reachable = analyzer.collect_compiled_reachable(&program, &[("app.main", "main!"), ("app.main", "reload!")]);
+    let mut names = reachable
+      .iter()
+      .flat_map(|(ns, defs)| defs.iter().map(move |def| format!("{ns}/{def}")))
+      .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+      names,
+      vec!["app.lib/helper", "app.main/main!", "app.main/reload!", "calcit.core/map"]
+    );
+
+    let project_only = CallTreeAnalyzer::new(CallTreeConfig::default());
+    let reachable = project_only.collect_compiled_reachable(&program, &[("app.main", "main!")]);
+    assert!(!reachable.contains_key("calcit.core"));
+    assert!(reachable["app.lib"].contains("helper"));
+  }
 }
@@ -1,5 +1,8 @@
 use argh::FromArgs;
 
+use crate::codegen::emit_js::bundle::BundleFormat;
+use crate::codegen::gen_ir::IrFormat;
+
 pub const CALCIT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(FromArgs, PartialEq, Debug, Clone)]
@@ -22,9 +25,39 @@ pub struct ToplevelCalcit {
   /// warn on dynamic method calls that cannot be monomorphized
   #[argh(switch)]
   pub warn_dyn_method: bool,
+  /// run constant folding, inlining and dead-branch elimination on preprocessed code
+  #[argh(switch)]
+  pub optimize: bool,
   /// print FFI dylib calls and callbacks for debugging native crashes
   #[argh(switch)]
   pub trace_ffi: bool,
+  /// run with effects denied unless allowed by `--allow-*` options
+  #[argh(switch)]
+  pub sandbox: bool,
+  /// sandbox: allow reading files under this path, repeatable
+  #[argh(option)]
+  pub allow_read: Vec<String>,
+  /// sandbox: allow writing files under this path, repeatable
+  #[argh(option)]
+  pub allow_write: Vec<String>,
+  /// sandbox: allow reading environment variables
+  #[argh(switch)]
+  pub allow_env: bool,
+  /// sandbox: allow registered procs tagged `:interop`, such as dylib calls
+  #[argh(switch)]
+  pub allow_ffi: bool,
+  /// sandbox: allow registered procs tagged `:net`
+  #[argh(switch)]
+  pub allow_net: bool,
+  /// sandbox: wall-clock limit in milliseconds
+  #[argh(option)]
+  pub sandbox_timeout_ms: Option<u64>,
+  /// sandbox: limit in MiB of net heap bytes allocated inside the sandbox
+  #[argh(option)]
+  pub sandbox_memory_mb: Option<usize>,
+  /// sandbox: limit of evaluated call expressions
+  #[argh(option)]
+  pub sandbox_steps: Option<u64>,
   /// entry file path, defaults to "js-out/"
   #[argh(option, default = "String::from(\"js-out/\")")]
   pub emit_path: String,
@@ -73,6 +106,8 @@ pub enum CalcitCommand {
   EmitJs(EmitJsCommand),
   /// emit Cirru EDN representation of program to program-ir.cirru
   EmitIr(EmitIrCommand),
+  /// run a program from its program-ir.cirru, without the source snapshot
+  RunIr(RunIrCommand),
   /// evaluate snippet
   Eval(EvalCommand),
   /// discover and run definition-attached tests
@@ -145,6 +180,18 @@ pub struct EmitJsCommand {
   /// check-only mode for JS emit
   #[argh(switch)]
   pub check_only: bool,
+  /// emit only definitions reachable from init-fn and reload-fn, and report the dropped ones
+  #[argh(switch)]
+  pub only_reachable: bool,
+  /// also write TypeScript declarations (.d.mts) for each emitted namespace
+  #[argh(switch)]
+  pub dts: bool,
+  /// write a single-file bundle with the procs runtime inlined to this path
+  #[argh(option)]
+  pub bundle: Option<String>,
+  /// bundle format: esm, iife or cjs (default: esm)
+  #[argh(option, default = "BundleFormat::Esm")]
+  pub bundle_format: BundleFormat,
 }
 
 /// emit Cirru EDN representation of program to program-ir.cirru
@@ -154,6 +201,18 @@ pub struct EmitIrCommand {
   /// enable watch mode (default behavior is run once)
   #[argh(switch, short = 'w')]
   pub watch: bool,
+  /// output format: "cirru" (default) writes program-ir.cirru, "msgpack" writes the compact program-ir.msgpack
+  #[argh(option, default = "IrFormat::Cirru")]
+  pub format: IrFormat,
+}
+
+/// run program from IR emitted by `calcit ir`
+#[derive(FromArgs, PartialEq, Debug, Clone)]
+#[argh(subcommand, name = "run-ir")]
+pub struct RunIrCommand {
+  /// IR file to run, `program-ir.cirru` or `program-ir.msgpack`, defaults to "js-out/program-ir.cirru"
+  #[argh(positional, default = "String::from(\"js-out/program-ir.cirru\")")]
+  pub path: String,
 }
 
 /// run program
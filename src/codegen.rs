@@ -1,13 +1,15 @@
 use std::sync::atomic::AtomicBool;
 
+use crate::runtime_context::ContextSlot;
+
 pub mod emit_js;
 pub mod emit_wasm;
 pub mod gen_ir;
 
 /// switch whether in codegen mode
-static CODEGEN_MODE: AtomicBool = AtomicBool::new(true);
+static CODEGEN_MODE: ContextSlot<AtomicBool> = ContextSlot::new(|ctx| &ctx.codegen_mode);
 
-static CODEGEN_SKIP_ARITY_CHECK: AtomicBool = AtomicBool::new(false);
+static CODEGEN_SKIP_ARITY_CHECK: ContextSlot<AtomicBool> = ContextSlot::new(|ctx| &ctx.skip_arity_check);
 
 pub const COMPILE_ERRORS_FILE: &str = "calcit.build-errors";
 
@@ -1,5 +1,8 @@
 mod args;
+pub mod bundle;
+mod def_cache;
 mod deps;
+mod dts;
 pub mod gen_stack;
 mod helpers;
 mod internal_states;
@@ -12,8 +15,9 @@ mod tags;
 
 use im_ternary_tree::TernaryTreeList;
 
+use std::borrow::Cow;
 use std::cell::{Cell, RefCell};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
@@ -27,14 +31,17 @@ use crate::calcit::data_shape::{DataShapeGraph, DataShapeNode};
 use crate::calcit::{self, CalcitArgLabel, CalcitFnArgs, CalcitImport, CalcitList, CalcitLocal, CalcitProc, MethodKind};
 use crate::calcit::{Calcit, CalcitSyntax, ImportInfo};
 use crate::call_stack::StackKind;
+use crate::call_tree::{CallTreeAnalyzer, CallTreeConfig};
 use crate::codegen::skip_arity_check;
 use crate::program;
 use crate::util::string::{has_ns_part, matches_js_var, wrap_js_str};
 use args::{gen_args_code, gen_call_args_with_temps};
+pub(crate) use def_cache::DefCodeCache;
+use def_cache::{CachedDefCode, DefKeys};
 use deps::{contains_symbol, sort_compiled_defs_by_deps};
-use helpers::{cirru_to_js, is_js_unavailable_procs, write_file_if_changed};
+use helpers::{cirru_to_js, is_js_unavailable_procs, remove_ns_outputs, write_file_if_changed};
 use paths::{to_js_import_name, to_mjs_filename};
-use runtime::{get_proc_prefix, is_cirru_string};
+use runtime::{ProcsRefs, get_proc_prefix, is_cirru_string};
 use symbols::{escape_cirru_str, escape_var};
 
 pub fn escape_symbol_for_js(name: &str) -> String {
@@ -1866,7 +1873,46 @@ fn extract_preprocessed_fn_parts(code: &Calcit) -> Result<PreprocessedFnParts, S
   }
 }
 
-pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
+/// Whether a compiled definition would produce any JavaScript in `emit_js`.
+fn emits_js_def(ns: &str, def: &str, compiled_def: &program::CompiledDef) -> bool {
+  if ns == calcit::CORE_NS && (should_skip_core_def_codegen(def, compiled_def) || is_js_unavailable_procs(def)) {
+    return false;
+  }
+  matches!(
+    compiled_def.kind,
+    program::CompiledDefKind::Proc | program::CompiledDefKind::Fn | program::CompiledDefKind::LazyValue
+  )
+}
+
+/// Names `calcit.core.mjs` exports from its own definitions.
+fn core_js_exports(file: &program::CompiledFileData) -> HashSet<String> {
+  file
+    .defs
+    .iter()
+    .filter(|(def, compiled_def)| {
+      emits_js_def(calcit::CORE_NS, def, compiled_def)
+        && !is_preferred_js_proc(def)
+        && matches!(
+          compiled_def.kind,
+          program::CompiledDefKind::Fn | program::CompiledDefKind::LazyValue
+        )
+    })
+    .map(|(def, _)| escape_var(def))
+    .collect()
+}
+
+/// Filters and extra outputs for `emit_js`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsEmitOptions<'a> {
+  /// Only emit definitions reachable from these `(ns, def)` roots, plus the
+  /// builtin impls registered by `calcit.core`, then report the dropped ones.
+  pub reachable_roots: Option<&'a [(&'a str, &'a str)]>,
+  /// Also write a `.d.mts` declaration file next to each emitted `.mjs`.
+  pub dts: bool,
+}
+
+/// Emit one `.mjs` file per namespace.
+pub fn emit_js(entry_ns: &str, emit_path: &str, options: JsEmitOptions) -> Result<(), String> {
   let code_emit_path = Path::new(emit_path);
   if !code_emit_path.exists() {
     let _ = fs::create_dir(code_emit_path);
@@ -1875,10 +1921,73 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
   let mut unchanged_ns: HashSet<Arc<str>> = HashSet::new();
 
   let program = program::clone_compiled_program_snapshot()?;
-  for (ns, file) in program.iter() {
+  let def_keys = DefKeys::new(&program);
+  let mut def_cache = DefCodeCache::open(code_emit_path, options.reachable_roots.is_some());
+  let mut generated_count = 0;
+
+  let reachable = options.reachable_roots.map(|roots| {
+    let mut roots = roots.to_vec();
+    roots.push((calcit::CORE_NS, calcit::BUILTIN_IMPLS_ENTRY));
+    let analyzer = CallTreeAnalyzer::new(CallTreeConfig {
+      include_core: true,
+      ..CallTreeConfig::default()
+    });
+    analyzer.collect_compiled_reachable(&program, &roots)
+  });
+  let dts_types = if options.dts {
+    dts::DtsTypeIndex::collect(&program, |ns, def| {
+      reachable
+        .as_ref()
+        .is_none_or(|reachable| reachable.get(ns).is_some_and(|defs| defs.contains(def)))
+    })
+  } else {
+    dts::DtsTypeIndex::default()
+  };
+  let mut dropped_defs: Vec<(Arc<str>, Arc<str>)> = vec![];
+  let mut kept_count = 0;
+  let mut procs_refs = ProcsRefs::default();
+  let mut procs_imported = 0;
+
+  // `calcit.core` goes last when filtering, so it knows which procs the other modules read
+  let mut namespaces = program.iter().collect::<Vec<_>>();
+  if reachable.is_some() {
+    namespaces.sort_by_key(|(ns, _)| &***ns == calcit::CORE_NS);
+  }
+
+  for (ns, full_file) in namespaces {
     // println!("\nstart handling: {}\n", ns);
     // side-effects, reset tracking state
 
+    let file: Cow<program::CompiledFileData> = match &reachable {
+      Some(reachable) => {
+        let reachable_defs = reachable.get(ns);
+        let mut defs = HashMap::new();
+        for (def, compiled_def) in &full_file.defs {
+          if reachable_defs.is_some_and(|reachable_defs| reachable_defs.contains(def)) {
+            if emits_js_def(ns, def, compiled_def) {
+              kept_count += 1;
+            }
+            defs.insert(def.to_owned(), compiled_def.to_owned());
+          } else if emits_js_def(ns, def, compiled_def) {
+            dropped_defs.push((ns.to_owned(), def.to_owned()));
+          }
+        }
+        Cow::Owned(program::CompiledFileData { defs })
+      }
+      None => Cow::Borrowed(full_file),
+    };
+    let file = file.as_ref();
+
+    // namespaces left without definitions are not imported by anything emitted,
+    // and their outputs from an earlier full emit would be picked up by bundlers
+    if reachable.is_some() && file.defs.is_empty() && &**ns != calcit::CORE_NS && &**ns != entry_ns {
+      for path in remove_ns_outputs(code_emit_path, ns)? {
+        println!("removed: {}", path.to_str().expect("exptract path"));
+      }
+      internal_states::forget_ns_cache(ns);
+      continue;
+    }
+
     let file_imports: RefCell<ImportsDict> = RefCell::new(ImportsDict::new());
     let collected_tags: RefCell<HashSet<EdnTag>> = RefCell::new(HashSet::new());
 
@@ -1887,7 +1996,8 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
       defs_in_current.insert(k.to_owned());
     }
 
-    if !internal_states::is_first_compilation() {
+    // skipped modules would not be scanned for the procs they read
+    if reachable.is_none() && !internal_states::is_first_compilation() {
       let app_pkg_name = entry_ns.split('.').collect::<Vec<&str>>()[0];
       let pkg_name = ns.split('.').collect::<Vec<&str>>()[0]; // TODO simpler
       if app_pkg_name != pkg_name {
@@ -1914,7 +2024,12 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
     let mut tags_code = String::new();
 
     let mut import_code = if &**ns == "calcit.core" {
-      snippets::tmpl_import_procs(wrap_js_str("@calcit/procs"))
+      if reachable.is_some() {
+        // written once the code shows which procs it reads
+        String::new()
+      } else {
+        snippets::tmpl_import_procs(wrap_js_str("@calcit/procs"))
+      }
     } else {
       format!("\nimport * as $clt from {core_lib};")
     };
@@ -1927,8 +2042,18 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
     }
 
     let deps_in_order = sort_compiled_defs_by_deps(file);
+    let file_key_part = def_keys.file_part(ns, &def_names);
     // println!("deps order: {:?}", deps_in_order);
 
+    if options.dts {
+      let dts_file_path = code_emit_path.join(format!("{ns}.d.mts"));
+      let procs_exports = (reachable.is_some() && &**ns == calcit::CORE_NS).then(|| procs_refs.exports(&core_js_exports(file)));
+      let dts_code = dts::gen_dts_code(ns, file, &deps_in_order, &dts_types, procs_exports.as_deref())?;
+      if write_file_if_changed(&dts_file_path, &dts_code)? {
+        println!("emitted: {}", dts_file_path.to_str().expect("exptract path"));
+      }
+    }
+
     for def in deps_in_order {
       let compiled_def = file.get(&def).expect("compiled def for codegen");
 
@@ -1941,15 +2066,57 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
           continue;
         }
         if is_preferred_js_proc(&def) {
-          writeln!(defs_code, "\nvar {} = $procs.{};", escape_var(&def), escape_var(&def)).expect("write");
+          if reachable.is_some() {
+            // imported by name instead
+            procs_refs.add_alias(escape_var(&def));
+          } else {
+            writeln!(defs_code, "\nvar {} = $procs.{};", escape_var(&def), escape_var(&def)).expect("write");
+          }
           continue;
         }
       }
 
-      match &compiled_def.kind {
+      let cache_key = match &compiled_def.kind {
+        program::CompiledDefKind::Fn | program::CompiledDefKind::LazyValue => {
+          file_key_part.as_ref().and_then(|file_part| def_keys.key(file_part, compiled_def))
+        }
+        _ => None,
+      };
+      if let Some(cached) = cache_key.as_ref().and_then(|key| def_cache.get(ns, &def, key)) {
+        let target = if compiled_def.kind == program::CompiledDefKind::Fn {
+          if !defs_code.is_empty() {
+            defs_code.push('\n');
+          }
+          &mut defs_code
+        } else {
+          &mut vals_code
+        };
+        target.push_str(&cached.code);
+        let mut imports = file_imports.borrow_mut();
+        for item in &cached.imports {
+          imports.insert(item.into());
+        }
+        collected_tags
+          .borrow_mut()
+          .extend(cached.tags.iter().map(|tag| EdnTag::from(tag.as_str())));
+        continue;
+      }
+
+      // imports and tags of each def are tracked apart for the def cache,
+      // and gensym restarts so its code does not depend on the defs before it
+      let def_imports: RefCell<ImportsDict> = RefCell::new(ImportsDict::new());
+      let def_tags: RefCell<HashSet<EdnTag>> = RefCell::new(HashSet::new());
+      reset_js_gensym_index();
+
+      let def_code = match &compiled_def.kind {
         // probably not work here
         program::CompiledDefKind::Proc => {
-          writeln!(defs_code, "\nvar {} = $procs.{};", escape_var(&def), escape_var(&def)).expect("write");
+          if reachable.is_some() && &**ns == calcit::CORE_NS {
+            procs_refs.add_alias(escape_var(&def));
+          } else {
+            writeln!(defs_code, "\nvar {} = $procs.{};", escape_var(&def), escape_var(&def)).expect("write");
+          }
+          None
         }
         program::CompiledDefKind::Fn => {
           let fn_parts = extract_preprocessed_fn_parts(&compiled_def.preprocessed_code)?;
@@ -1957,13 +2124,9 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
           let passed_defs = PassedDefs {
             ns,
             local_defs: &def_names,
-            file_imports: &file_imports,
+            file_imports: &def_imports,
           };
-          // Blank line between generated functions is a cheap, no-cost readability win.
-          if !defs_code.is_empty() {
-            defs_code.push('\n');
-          }
-          defs_code.push_str(&gen_js_func(
+          let code = gen_js_func(
             &def,
             JsFnParams {
               args: &fn_parts.args,
@@ -1972,39 +2135,62 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
             &fn_parts.body,
             &passed_defs,
             true,
-            &collected_tags,
+            &def_tags,
             ns,
-          )?);
+          )?;
+          // Blank line between generated functions is a cheap, no-cost readability win.
+          if !defs_code.is_empty() {
+            defs_code.push('\n');
+          }
+          defs_code.push_str(&code);
           gen_stack::pop_call_stack();
+          Some(code)
         }
         program::CompiledDefKind::LazyValue => {
           // TODO need topological sorting for accuracy
           // values are called directly, put them after fns
           gen_stack::push_call_stack(ns, &def, StackKind::Codegen, compiled_def.codegen_form.to_owned(), &[]);
-          writeln!(
-            vals_code,
-            "\nexport var {} = {};",
+          let code = format!(
+            "\nexport var {} = {};\n",
             escape_var(&def),
-            to_js_code(&compiled_def.codegen_form, ns, &def_names, &file_imports, &collected_tags, None)?
-          )
-          .expect("write");
-          gen_stack::pop_call_stack()
+            to_js_code(&compiled_def.codegen_form, ns, &def_names, &def_imports, &def_tags, None)?
+          );
+          vals_code.push_str(&code);
+          gen_stack::pop_call_stack();
+          Some(code)
         }
         // macro are not traced in codegen since already expanded
-        program::CompiledDefKind::Macro => {}
+        program::CompiledDefKind::Macro => None,
         program::CompiledDefKind::Syntax => {
           // should he handled inside compiler
+          None
         }
         program::CompiledDefKind::Value if matches!(&compiled_def.codegen_form, Calcit::Bool(_) | Calcit::Number(_)) => {
           eprintln!(
             "[Warn] expected thunk, got macro. skipped `{ns}/{def} {}`",
             compiled_def.codegen_form
-          )
+          );
+          None
         }
         program::CompiledDefKind::Value => {
-          eprintln!("[Warn] expected thunk for js, skipped `{ns}/{def} {}`", compiled_def.codegen_form)
+          eprintln!("[Warn] expected thunk for js, skipped `{ns}/{def} {}`", compiled_def.codegen_form);
+          None
         }
+      };
+
+      let def_imports = def_imports.into_inner();
+      let def_tags = def_tags.into_inner();
+      if let Some(code) = def_code {
+        generated_count += 1;
+        if let Some(key) = cache_key {
+          def_cache.insert(ns, &def, CachedDefCode::new(key, code, &def_imports.0, &def_tags));
+        }
+      }
+      let mut imports = file_imports.borrow_mut();
+      for item in def_imports.0 {
+        imports.insert(item);
       }
+      collected_tags.borrow_mut().extend(def_tags);
     }
     if &**ns == calcit::CORE_NS {
       // add at end of file to register builtin classes
@@ -2046,6 +2232,7 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
             if at_ns == &item.ns {
               continue;
             }
+            procs_refs.add_core_import(escape_var(&item.def));
             write!(import_code, "\nimport {{ {} }} from {core_lib};", escape_var(&item.def)).expect("write");
           }
           ImportInfo::SameFile { .. } => {
@@ -2071,11 +2258,26 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
     tag_arr.push(']');
     tags_code.push_str(&snippets::tmpl_tags_init(&tag_arr, tag_prefix));
 
+    let mut js_code = format!("{import_code}{tags_code}\n{defs_code}\n\n{vals_code}\n{direct_code}");
+    if reachable.is_some() {
+      if &**ns == calcit::CORE_NS {
+        procs_refs.scan_core(&js_code);
+        let core_exports = core_js_exports(file);
+        let imports = procs_refs.imports(&core_exports);
+        procs_imported = imports.len();
+        let procs_import = snippets::tmpl_import_used_procs(
+          wrap_js_str("@calcit/procs"),
+          &imports,
+          &procs_refs.members(),
+          &procs_refs.exports(&core_exports),
+        );
+        js_code.insert_str(0, &procs_import);
+      } else {
+        procs_refs.scan_module(&js_code);
+      }
+    }
     let js_file_path = code_emit_path.join(to_mjs_filename(ns));
-    let wrote_new = write_file_if_changed(
-      &js_file_path,
-      &format!("{import_code}{tags_code}\n{defs_code}\n\n{vals_code}\n{direct_code}"),
-    )?;
+    let wrote_new = write_file_if_changed(&js_file_path, &js_code)?;
     if wrote_new {
       println!("emitted: {}", js_file_path.to_str().expect("exptract path"));
     } else {
@@ -2086,12 +2288,49 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
   if !unchanged_ns.is_empty() {
     println!("\n... and {} files not changed.", unchanged_ns.len());
   }
+  if def_cache.hits > 0 {
+    println!(
+      "... reused cached code of {} definitions, generated {generated_count}.",
+      def_cache.hits
+    );
+  }
+  // the cache only saves time, failing to persist it should not fail codegen
+  if let Err(e) = def_cache.finish(&program) {
+    eprintln!("[Warn] {e}");
+  }
+
+  if reachable.is_some() {
+    print!("{}", format_dropped_defs_report(kept_count, &mut dropped_defs, procs_imported));
+  }
 
   let _ = internal_states::finish_compilation();
 
   Ok(())
 }
 
+/// Summarize `--only-reachable` output. Library and app definitions are listed
+/// per namespace, while `calcit.core` is only counted since it is always large.
+fn format_dropped_defs_report(kept_count: usize, dropped_defs: &mut [(Arc<str>, Arc<str>)], procs_imported: usize) -> String {
+  dropped_defs.sort();
+  let mut report = format!(
+    "\nonly-reachable: kept {kept_count} definitions, dropped {} unreachable\n",
+    dropped_defs.len()
+  );
+  let core_count = dropped_defs.iter().filter(|(ns, _)| &**ns == calcit::CORE_NS).count();
+  if core_count > 0 {
+    writeln!(report, "  {}: {core_count} definitions", calcit::CORE_NS).expect("write");
+  }
+  for chunk in dropped_defs
+    .chunk_by(|a, b| a.0 == b.0)
+    .filter(|chunk| &*chunk[0].0 != calcit::CORE_NS)
+  {
+    let names = chunk.iter().map(|(_, def)| &**def).collect::<Vec<_>>();
+    writeln!(report, "  {}: {}", chunk[0].0, names.join(" ")).expect("write");
+  }
+  writeln!(report, "  @calcit/procs: {procs_imported} names imported by calcit.core.mjs").expect("write");
+  report
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -2170,6 +2409,21 @@ mod tests {
     }
   }
 
+  #[test]
+  fn dropped_defs_report_counts_core_and_lists_others() {
+    let mut dropped: Vec<(Arc<str>, Arc<str>)> = vec![
+      (Arc::from("app.lib"), Arc::from("unused-b")),
+      (Arc::from(calcit::CORE_NS), Arc::from("zipmap")),
+      (Arc::from("app.lib"), Arc::from("unused-a")),
+      (Arc::from(calcit::CORE_NS), Arc::from("interleave")),
+    ];
+    let report = format_dropped_defs_report(12, &mut dropped, 40);
+    assert_eq!(
+      report,
+      "\nonly-reachable: kept 12 definitions, dropped 4 unreachable\n  calcit.core: 2 definitions\n  app.lib: unused-a unused-b\n  @calcit/procs: 40 names imported by calcit.core.mjs\n"
+    );
+  }
+
   #[test]
   fn hinted_async_accepts_schema_map_literal() {
     let schema = Calcit::List(Arc::new(CalcitList::from(&[
@@ -0,0 +1,939 @@
+//! Single-file bundles built from the emitted `.mjs` modules.
+//!
+//! Import and export statements are located with `oxc_parser`; the rest of
+//! each module is copied as written. The parser comes with the default `bundle`
+//! feature, builds without it report an error from [write_bundle].
+//!
+//! Modules are wrapped into factories of a small registry instead of being
+//! scope-hoisted. Named imports become `__import` callbacks, so bindings from a
+//! module that is still evaluating (import cycles inside `@calcit/procs`) are
+//! filled in when it finishes, following ES module evaluation order.
+//!
+//! Modules from `node_modules` are cached on `globalThis`. Evaluating the bundle
+//! a second time re-runs the app namespaces against the same runtime (atoms are
+//! kept) and calls `reload-fn` instead of `init-fn`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[cfg(feature = "bundle")]
+use oxc_allocator::Allocator;
+#[cfg(feature = "bundle")]
+use oxc_ast::ast::{Declaration, ExportDefaultDeclarationKind, ExportSpecifier, ImportDeclarationSpecifier, Statement as AstStatement};
+#[cfg(feature = "bundle")]
+use oxc_parser::Parser;
+#[cfg(feature = "bundle")]
+use oxc_span::{GetSpan, SourceType};
+
+use super::paths::to_mjs_filename;
+use super::symbols::escape_var;
+
+/// Shape of the generated bundle file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleFormat {
+  /// an ES module without imports, other than Node built-ins
+  Esm,
+  /// a classic script wrapped in a function, for userscripts and `<script>` tags
+  Iife,
+  /// a CommonJS module exporting the `init-fn` namespace
+  Cjs,
+}
+
+impl FromStr for BundleFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "esm" => Ok(Self::Esm),
+      "iife" => Ok(Self::Iife),
+      "cjs" => Ok(Self::Cjs),
+      _ => Err(format!("unknown bundle format `{s}`, expected one of: esm, iife, cjs")),
+    }
+  }
+}
+
+/// Where to write a bundle and in which format.
+#[derive(Debug, Clone, Copy)]
+pub struct BundleOptions<'a> {
+  pub output: &'a Path,
+  pub format: BundleFormat,
+}
+
+/// Node built-ins stay external. IIFE bundles have no way to load them.
+const NODE_BUILTINS: &[&str] = &[
+  "assert",
+  "buffer",
+  "child_process",
+  "crypto",
+  "events",
+  "fs",
+  "fs/promises",
+  "http",
+  "https",
+  "module",
+  "net",
+  "os",
+  "path",
+  "process",
+  "readline",
+  "stream",
+  "url",
+  "util",
+  "worker_threads",
+  "zlib",
+];
+
+fn is_node_builtin(specifier: &str) -> bool {
+  specifier.starts_with("node:") || NODE_BUILTINS.contains(&specifier)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+// only the parser builds statements
+#[cfg_attr(not(feature = "bundle"), allow(dead_code))]
+enum Statement {
+  /// source text kept as written
+  Code(String),
+  /// an `import.meta` expression, which only ESM bundles keep
+  ImportMeta,
+  ImportAll {
+    local: String,
+    specifier: String,
+  },
+  ImportNamed {
+    names: Vec<(String, String)>,
+    specifier: String,
+  },
+  ImportBare {
+    specifier: String,
+  },
+  ExportAll {
+    specifier: String,
+  },
+  ExportFrom {
+    names: Vec<(String, String)>,
+    specifier: String,
+  },
+}
+
+/// Top-level statements of one ES module, with exports collected separately.
+#[derive(Debug, Default)]
+struct ParsedModule {
+  statements: Vec<Statement>,
+  /// `(local, exported)` pairs for bindings declared in this module
+  local_exports: Vec<(String, String)>,
+}
+
+impl ParsedModule {
+  fn specifiers(&self) -> impl Iterator<Item = &str> {
+    self.statements.iter().filter_map(|statement| match statement {
+      Statement::Code(_) | Statement::ImportMeta => None,
+      Statement::ImportAll { specifier, .. }
+      | Statement::ImportNamed { specifier, .. }
+      | Statement::ImportBare { specifier }
+      | Statement::ExportAll { specifier }
+      | Statement::ExportFrom { specifier, .. } => Some(specifier.as_str()),
+    })
+  }
+}
+
+/// A source range replaced by bundle statements; the text between edits is kept.
+#[cfg(feature = "bundle")]
+struct Edit {
+  start: u32,
+  end: u32,
+  replacement: Vec<Statement>,
+}
+
+#[cfg(feature = "bundle")]
+impl Edit {
+  fn new(start: u32, end: u32, replacement: Vec<Statement>) -> Self {
+    Self { start, end, replacement }
+  }
+}
+
+#[cfg(feature = "bundle")]
+fn export_pairs(specifiers: &[ExportSpecifier]) -> Vec<(String, String)> {
+  specifiers
+    .iter()
+    .map(|specifier| (specifier.local.name().to_string(), specifier.exported.name().to_string()))
+    .collect()
+}
+
+/// Split an ES module into its import/export statements and the code around
+/// them. Export keywords are cut off declarations in place, so everything
+/// else keeps its original text.
+#[cfg(feature = "bundle")]
+fn parse_module(source: &str) -> Result<ParsedModule, String> {
+  let allocator = Allocator::default();
+  let ret = Parser::new(&allocator, source, SourceType::mjs()).parse();
+  if let Some(error) = ret.errors.first() {
+    return Err(format!("failed to parse module: {error}"));
+  }
+
+  let mut parsed = ParsedModule::default();
+  let mut edits: Vec<Edit> = vec![];
+  for statement in &ret.program.body {
+    let span = statement.span();
+    match statement {
+      AstStatement::ImportDeclaration(decl) => {
+        let specifier = decl.source.value.to_string();
+        let replacement = match &decl.specifiers {
+          None => vec![Statement::ImportBare { specifier }],
+          Some(specifiers) => {
+            let mut names = vec![];
+            let mut replacement = vec![];
+            for item in specifiers {
+              match item {
+                ImportDeclarationSpecifier::ImportNamespaceSpecifier(item) => replacement.push(Statement::ImportAll {
+                  local: item.local.name.to_string(),
+                  specifier: specifier.to_owned(),
+                }),
+                ImportDeclarationSpecifier::ImportDefaultSpecifier(item) => {
+                  names.push(("default".to_owned(), item.local.name.to_string()))
+                }
+                ImportDeclarationSpecifier::ImportSpecifier(item) => {
+                  names.push((item.imported.name().to_string(), item.local.name.to_string()))
+                }
+              }
+            }
+            if !names.is_empty() || replacement.is_empty() {
+              replacement.push(Statement::ImportNamed { names, specifier });
+            }
+            replacement
+          }
+        };
+        edits.push(Edit::new(span.start, span.end, replacement));
+      }
+      AstStatement::ExportAllDeclaration(decl) => {
+        let specifier = decl.source.value.to_string();
+        let replacement = match &decl.exported {
+          None => Statement::ExportAll { specifier },
+          Some(exported) => {
+            let local = format!("__star_{}", exported.name());
+            parsed.local_exports.push((local.to_owned(), exported.name().to_string()));
+            Statement::ImportAll { local, specifier }
+          }
+        };
+        edits.push(Edit::new(span.start, span.end, vec![replacement]));
+      }
+      AstStatement::ExportNamedDeclaration(decl) => match (&decl.declaration, &decl.source) {
+        (Some(declaration), _) => {
+          let names = match declaration {
+            Declaration::VariableDeclaration(vars) => vars
+              .declarations
+              .iter()
+              .flat_map(|item| item.id.get_binding_identifiers())
+              .map(|ident| ident.name.to_string())
+              .collect(),
+            _ => declaration.id().map(|ident| ident.name.to_string()).into_iter().collect::<Vec<_>>(),
+          };
+          parsed.local_exports.extend(names.into_iter().map(|name| (name.to_owned(), name)));
+          edits.push(Edit::new(span.start, declaration.span().start, vec![]));
+        }
+        (None, Some(source)) => edits.push(Edit::new(
+          span.start,
+          span.end,
+          vec![Statement::ExportFrom {
+            names: export_pairs(&decl.specifiers),
+            specifier: source.value.to_string(),
+          }],
+        )),
+        (None, None) => {
+          parsed.local_exports.extend(export_pairs(&decl.specifiers));
+          edits.push(Edit::new(span.start, span.end, vec![]));
+        }
+      },
+      AstStatement::ExportDefaultDeclaration(decl) => {
+        let named = match &decl.declaration {
+          ExportDefaultDeclarationKind::FunctionDeclaration(f) => f.id.as_ref(),
+          ExportDefaultDeclarationKind::ClassDeclaration(c) => c.id.as_ref(),
+          _ => None,
+        };
+        let value_start = decl.declaration.span().start;
+        match named {
+          Some(ident) => {
+            parsed.local_exports.push((ident.name.to_string(), "default".to_owned()));
+            edits.push(Edit::new(span.start, value_start, vec![]));
+          }
+          None => {
+            parsed.local_exports.push(("__default__".to_owned(), "default".to_owned()));
+            let replacement = vec![Statement::Code("var __default__ = ".to_owned())];
+            edits.push(Edit::new(span.start, value_start, replacement));
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+  for span in &ret.module_record.import_metas {
+    edits.push(Edit::new(span.start, span.end, vec![Statement::ImportMeta]));
+  }
+  for comment in &ret.program.comments {
+    let span = comment.span;
+    if source[span.start as usize..span.end as usize].starts_with("//# sourceMappingURL=") {
+      edits.push(Edit::new(span.start, span.end, vec![]));
+    }
+  }
+
+  edits.sort_by_key(|edit| edit.start);
+  let mut cursor = 0;
+  for edit in edits {
+    if edit.start as usize > cursor {
+      parsed
+        .statements
+        .push(Statement::Code(source[cursor..edit.start as usize].to_owned()));
+    }
+    parsed.statements.extend(edit.replacement);
+    cursor = edit.end as usize;
+  }
+  if cursor < source.len() {
+    parsed.statements.push(Statement::Code(source[cursor..].to_owned()));
+  }
+  Ok(parsed)
+}
+
+#[cfg(not(feature = "bundle"))]
+fn parse_module(_source: &str) -> Result<ParsedModule, String> {
+  Err(String::from("bundles need calcit built with the `bundle` feature"))
+}
+
+fn js_str(s: &str) -> String {
+  serde_json::to_string(s).expect("encode js string")
+}
+
+fn js_getters(pairs: &[(String, String)]) -> String {
+  pairs
+    .iter()
+    .map(|(exported, target)| format!("{}: () => {target}", js_str(exported)))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// Render the body of a registry factory for a parsed module. `ids` maps each
+/// specifier to the registry id it resolved to.
+fn render_module_factory(parsed: &ParsedModule, ids: &HashMap<String, String>, import_meta: &str) -> String {
+  let mut code = String::new();
+  let mut getters: Vec<(String, String)> = parsed
+    .local_exports
+    .iter()
+    .map(|(local, exported)| (exported.to_owned(), local.to_owned()))
+    .collect();
+  for statement in &parsed.statements {
+    if let Statement::ExportFrom { names, specifier } = statement {
+      for (imported, exported) in names {
+        getters.push((exported.to_owned(), format!("__require({}).{imported}", js_str(&ids[specifier]))));
+      }
+    }
+  }
+  // getters go first so that exports are visible while an import cycle is evaluating
+  if !getters.is_empty() {
+    writeln!(code, "__export(__exports, {{ {} }});", js_getters(&getters)).expect("write");
+  }
+
+  for statement in &parsed.statements {
+    match statement {
+      Statement::Code(text) => code.push_str(text),
+      Statement::ImportMeta => code.push_str(import_meta),
+      Statement::ImportAll { local, specifier } => {
+        write!(code, "const {local} = __require({});", js_str(&ids[specifier])).expect("write");
+      }
+      Statement::ImportNamed { names, specifier } => {
+        let locals = names.iter().map(|(_, local)| local.as_str()).collect::<Vec<_>>();
+        let assigns = names
+          .iter()
+          .map(|(imported, local)| format!("{local} = m[{}];", js_str(imported)))
+          .collect::<Vec<_>>();
+        let declare = if locals.is_empty() {
+          String::new()
+        } else {
+          format!("let {}; ", locals.join(", "))
+        };
+        write!(
+          code,
+          "{declare}__import({}, (m) => {{ {} }});",
+          js_str(&ids[specifier]),
+          assigns.join(" ")
+        )
+        .expect("write");
+      }
+      Statement::ImportBare { specifier } => {
+        write!(code, "__require({});", js_str(&ids[specifier])).expect("write");
+      }
+      Statement::ExportAll { specifier } => {
+        write!(code, "__exportAll(__exports, {});", js_str(&ids[specifier])).expect("write");
+      }
+      Statement::ExportFrom { .. } => {}
+    }
+  }
+  if !code.ends_with('\n') {
+    code.push('\n');
+  }
+  code
+}
+
+enum ModuleBody {
+  Esm(String),
+  Json(String),
+  External(String),
+}
+
+struct BundledModule {
+  id: String,
+  runtime: bool,
+  body: ModuleBody,
+}
+
+struct Bundler {
+  format: BundleFormat,
+  base: PathBuf,
+  modules: Vec<BundledModule>,
+  ids: HashMap<PathBuf, String>,
+  uses_import_meta: bool,
+}
+
+/// Pick the ESM entry file of a package from its `package.json`.
+fn package_entry(package_dir: &Path, subpath: &str) -> Result<PathBuf, String> {
+  let manifest_path = package_dir.join("package.json");
+  let manifest: serde_json::Value = match fs::read_to_string(&manifest_path) {
+    Ok(content) => serde_json::from_str(&content).map_err(|e| format!("failed to parse {}: {e}", manifest_path.display()))?,
+    Err(_) => serde_json::Value::Null,
+  };
+
+  fn pick_condition(target: &serde_json::Value) -> Option<&str> {
+    match target {
+      serde_json::Value::String(s) => Some(s),
+      serde_json::Value::Object(conditions) => ["import", "module", "default", "node"]
+        .iter()
+        .find_map(|key| conditions.get(*key).and_then(pick_condition)),
+      _ => None,
+    }
+  }
+
+  let export_key = if subpath.is_empty() {
+    ".".to_owned()
+  } else {
+    format!("./{subpath}")
+  };
+  let from_exports = match manifest.get("exports") {
+    Some(serde_json::Value::Object(map)) if map.keys().any(|k| k.starts_with('.')) => map.get(&export_key).and_then(pick_condition),
+    Some(target) if subpath.is_empty() => pick_condition(target),
+    _ => None,
+  };
+  let relative = match from_exports {
+    Some(target) => target.to_owned(),
+    None if !subpath.is_empty() => subpath.to_owned(),
+    None => ["module", "main"]
+      .iter()
+      .find_map(|key| manifest.get(*key).and_then(|v| v.as_str()).map(str::to_owned))
+      .unwrap_or_else(|| "index.js".to_owned()),
+  };
+
+  let candidate = package_dir.join(relative);
+  for path in [
+    candidate.to_owned(),
+    candidate.with_extension("mjs"),
+    candidate.with_extension("js"),
+    candidate.join("index.mjs"),
+    candidate.join("index.js"),
+  ] {
+    if path.is_file() {
+      return Ok(path);
+    }
+  }
+  Err(format!(
+    "no entry file found for {} in {}",
+    candidate.display(),
+    package_dir.display()
+  ))
+}
+
+/// Node-style lookup of a bare specifier from `node_modules` folders above `from_dir`.
+fn resolve_package(specifier: &str, from_dir: &Path) -> Result<PathBuf, String> {
+  let mut parts = specifier.splitn(if specifier.starts_with('@') { 3 } else { 2 }, '/');
+  let package_name = if specifier.starts_with('@') {
+    format!("{}/{}", parts.next().unwrap_or_default(), parts.next().unwrap_or_default())
+  } else {
+    parts.next().unwrap_or_default().to_owned()
+  };
+  let subpath = parts.next().unwrap_or_default();
+
+  for dir in from_dir.ancestors() {
+    let package_dir = dir.join("node_modules").join(&package_name);
+    if package_dir.is_dir() {
+      return package_entry(&package_dir, subpath);
+    }
+  }
+  Err(format!(
+    "cannot inline `{specifier}`: package `{package_name}` not found in node_modules above {}",
+    from_dir.display()
+  ))
+}
+
+impl Bundler {
+  /// Ids do not depend on where the project lives, so the runtime cache on
+  /// `globalThis` keeps matching across rebuilt bundles.
+  fn module_id(&self, path: &Path) -> String {
+    let components = path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>();
+    match components.iter().rposition(|c| c == "node_modules") {
+      Some(idx) => components[idx..].join("/"),
+      None => {
+        let shown = path.strip_prefix(&self.base).unwrap_or(path);
+        shown.to_string_lossy().replace('\\', "/")
+      }
+    }
+  }
+
+  fn add_external(&mut self, specifier: &str) -> Result<String, String> {
+    if self.format == BundleFormat::Iife {
+      return Err(format!(
+        "IIFE bundles cannot load the Node built-in `{specifier}`, use --bundle-format esm or cjs"
+      ));
+    }
+    let id = format!("external:{specifier}");
+    if !self.modules.iter().any(|module| module.id == id) {
+      self.modules.push(BundledModule {
+        id: id.to_owned(),
+        runtime: true,
+        body: ModuleBody::External(specifier.to_owned()),
+      });
+    }
+    Ok(id)
+  }
+
+  /// Add `path` and everything it imports, dependencies first.
+  fn add_file(&mut self, path: &Path, runtime: bool) -> Result<String, String> {
+    let path = path
+      .canonicalize()
+      .map_err(|e| format!("failed to locate module {}: {e}", path.display()))?;
+    if let Some(id) = self.ids.get(&path) {
+      return Ok(id.to_owned());
+    }
+    let id = self.module_id(&path);
+    self.ids.insert(path.to_owned(), id.to_owned());
+    let runtime = runtime || path.components().any(|c| c.as_os_str() == "node_modules");
+
+    let source = fs::read_to_string(&path).map_err(|e| format!("failed to read module {}: {e}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+      self.modules.push(BundledModule {
+        id: id.to_owned(),
+        runtime,
+        body: ModuleBody::Json(source),
+      });
+      return Ok(id);
+    }
+
+    let parsed = parse_module(&source).map_err(|e| format!("{}: {e}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut ids: HashMap<String, String> = HashMap::new();
+    for specifier in parsed.specifiers() {
+      if ids.contains_key(specifier) {
+        continue;
+      }
+      let dep_id = if is_node_builtin(specifier) {
+        self.add_external(specifier)?
+      } else if specifier.starts_with("./") || specifier.starts_with("../") || specifier.starts_with('/') {
+        self.add_file(&dir.join(specifier), runtime)?
+      } else {
+        self.add_file(&resolve_package(specifier, dir)?, true)?
+      };
+      ids.insert(specifier.to_owned(), dep_id);
+    }
+
+    let import_meta = match self.format {
+      BundleFormat::Esm => "import.meta",
+      BundleFormat::Iife | BundleFormat::Cjs => "__import_meta",
+    };
+    self.uses_import_meta = self.uses_import_meta || parsed.statements.contains(&Statement::ImportMeta);
+    self.modules.push(BundledModule {
+      id: id.to_owned(),
+      runtime,
+      body: ModuleBody::Esm(render_module_factory(&parsed, &ids, import_meta)),
+    });
+    Ok(id)
+  }
+
+  /// `init` and `reload` are `(module id, def name)` pairs.
+  fn render(&self, init: (&str, &str), reload: (&str, &str)) -> String {
+    let mut code = String::new();
+    let mut externals = String::new();
+    let mut factories = String::new();
+    for (idx, module) in self.modules.iter().enumerate() {
+      let factory = match &module.body {
+        ModuleBody::Esm(body) => format!("function (__exports) {{\n{body}}}"),
+        ModuleBody::Json(json) => {
+          format!("function (__exports) {{\n__export(__exports, {{ default: () => json }});\nconst json = {json};\n}}")
+        }
+        ModuleBody::External(specifier) => {
+          let local = format!("__external_{idx}");
+          match self.format {
+            BundleFormat::Esm => writeln!(externals, "import * as {local} from {};", js_str(specifier)).expect("write"),
+            _ => writeln!(externals, "const {local} = require({});", js_str(specifier)).expect("write"),
+          }
+          let default_value = if self.format == BundleFormat::Esm {
+            format!("{local}.default")
+          } else {
+            local.to_owned()
+          };
+          format!(
+            "function (__exports) {{\n__exportAll(__exports, {local}, true);\n__export(__exports, {{ default: () => {default_value} }});\n}}"
+          )
+        }
+      };
+      writeln!(
+        factories,
+        "\n// {}\n__define({}, {}, {factory});",
+        module.id,
+        js_str(&module.id),
+        module.runtime
+      )
+      .expect("write");
+    }
+
+    if self.format == BundleFormat::Iife {
+      code.push_str("(function () {\n");
+    }
+    if self.format != BundleFormat::Esm {
+      code.push_str("\"use strict\";\n");
+    }
+    code.push_str(&externals);
+    code.push_str(BUNDLE_PRELUDE);
+    if self.uses_import_meta {
+      match self.format {
+        BundleFormat::Esm => {}
+        BundleFormat::Cjs => code.push_str("const __import_meta = { url: require(\"url\").pathToFileURL(__filename).href };\n"),
+        BundleFormat::Iife => code.push_str(
+          "const __import_meta = { url: (typeof document !== \"undefined\" && document.currentScript && document.currentScript.src) || \"\" };\n",
+        ),
+      }
+    }
+    code.push_str(&factories);
+
+    writeln!(
+      code,
+      "\nconst __init = __require({});\nconst __reload = __require({});\nif (__calcit_bundle.loaded) {{\n  __reload.{}();\n}} else {{\n  __calcit_bundle.loaded = true;\n  __init.{}();\n}}",
+      js_str(init.0),
+      js_str(reload.0),
+      escape_var(reload.1),
+      escape_var(init.1)
+    )
+    .expect("write");
+    match self.format {
+      BundleFormat::Esm => code.push_str("export default __init;\n"),
+      BundleFormat::Cjs => code.push_str("module.exports = __init;\n"),
+      BundleFormat::Iife => code.push_str("})();\n"),
+    }
+    code
+  }
+}
+
+const BUNDLE_PRELUDE: &str = r#"const __calcit_bundle = globalThis.__calcit_bundle__ || (globalThis.__calcit_bundle__ = { runtime: new Map(), loaded: false });
+const __factories = new Map();
+const __modules = new Map();
+function __define(id, runtime, factory) {
+  __factories.set(id, { runtime, factory });
+}
+function __load(id) {
+  const { runtime, factory } = __factories.get(id);
+  const cache = runtime ? __calcit_bundle.runtime : __modules;
+  let record = cache.get(id);
+  if (record == null) {
+    record = { exports: {}, done: false, pending: [] };
+    cache.set(id, record);
+    factory(record.exports);
+    record.done = true;
+    for (const f of record.pending) f(record.exports);
+    record.pending = [];
+  }
+  return record;
+}
+function __require(id) {
+  return __load(id).exports;
+}
+function __import(id, f) {
+  const record = __load(id);
+  if (record.done) f(record.exports);
+  else record.pending.push(f);
+}
+function __export(exports, getters) {
+  for (const k of Object.keys(getters)) {
+    if (!Object.prototype.hasOwnProperty.call(exports, k)) Object.defineProperty(exports, k, { enumerable: true, get: getters[k] });
+  }
+}
+function __exportAll(exports, source, isNamespace) {
+  const copy = (m) => {
+    for (const k of Object.keys(m)) {
+      if (k !== "default" && !Object.prototype.hasOwnProperty.call(exports, k)) Object.defineProperty(exports, k, { enumerable: true, get: () => m[k] });
+    }
+  };
+  if (isNamespace) return copy(source);
+  const record = __load(source);
+  copy(record.exports);
+  if (!record.done) record.pending.push(copy);
+}
+"#;
+
+/// Bundle the emitted modules reachable from the `init-fn` and `reload-fn`
+/// namespaces into one file. Returns the number of bundled modules.
+pub fn write_bundle(emit_path: &Path, init: (&str, &str), reload: (&str, &str), options: BundleOptions) -> Result<usize, String> {
+  let base = emit_path
+    .canonicalize()
+    .map_err(|e| format!("failed to locate emit path {}: {e}", emit_path.display()))?;
+  let mut bundler = Bundler {
+    format: options.format,
+    base,
+    modules: vec![],
+    ids: HashMap::new(),
+    uses_import_meta: false,
+  };
+  let init_id = bundler.add_file(&emit_path.join(to_mjs_filename(init.0)), false)?;
+  let reload_id = bundler.add_file(&emit_path.join(to_mjs_filename(reload.0)), false)?;
+  let code = bundler.render((&init_id, init.1), (&reload_id, reload.1));
+
+  if let Some(dir) = options.output.parent()
+    && !dir.as_os_str().is_empty()
+  {
+    fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+  }
+  fs::write(options.output, code).map_err(|e| format!("failed to write bundle {}: {e}", options.output.display()))?;
+  Ok(bundler.modules.len())
+}
+
+#[cfg(all(test, feature = "bundle"))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_module_statements_from_codegen_and_tsc() {
+    let source = "import * as $clt from \"./calcit.core.mjs\";\nimport { a, b as c } from \"./app.lib.mjs\";\nimport {\n  d,\n  e,\n} from \"@calcit/procs\";\nimport pkg from \"./package.json\" with { type: \"json\" };\nexport * from \"./js-map.mjs\";\nexport { f as g } from \"./js-ref.mjs\";\nexport function main_$x_() {\n  return 1;\n}\nexport var x = 1, y = import.meta.url;\nexport default null;\n//# sourceMappingURL=app.mjs.map\n";
+    let parsed = parse_module(source).expect("parse module");
+    let module_statements = parsed
+      .statements
+      .iter()
+      .filter(|statement| !matches!(statement, Statement::Code(_)))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      module_statements[1],
+      &Statement::ImportNamed {
+        names: vec![("a".to_owned(), "a".to_owned()), ("b".to_owned(), "c".to_owned())],
+        specifier: "./app.lib.mjs".to_owned(),
+      }
+    );
+    assert_eq!(
+      module_statements[2],
+      &Statement::ImportNamed {
+        names: vec![("d".to_owned(), "d".to_owned()), ("e".to_owned(), "e".to_owned())],
+        specifier: "@calcit/procs".to_owned(),
+      }
+    );
+    assert_eq!(
+      module_statements[3],
+      &Statement::ImportNamed {
+        names: vec![("default".to_owned(), "pkg".to_owned())],
+        specifier: "./package.json".to_owned(),
+      }
+    );
+    assert_eq!(module_statements[6], &Statement::ImportMeta);
+    assert_eq!(
+      parsed.local_exports,
+      vec![
+        ("main_$x_".to_owned(), "main_$x_".to_owned()),
+        ("x".to_owned(), "x".to_owned()),
+        ("y".to_owned(), "y".to_owned()),
+        ("__default__".to_owned(), "default".to_owned()),
+      ]
+    );
+    assert_eq!(
+      parsed.specifiers().collect::<Vec<_>>(),
+      vec![
+        "./calcit.core.mjs",
+        "./app.lib.mjs",
+        "@calcit/procs",
+        "./package.json",
+        "./js-map.mjs",
+        "./js-ref.mjs"
+      ]
+    );
+    let code = parsed
+      .statements
+      .iter()
+      .filter_map(|statement| match statement {
+        Statement::Code(text) => Some(text.as_str()),
+        _ => None,
+      })
+      .collect::<String>();
+    assert_eq!(
+      code,
+      "\n\n\n\n\n\nfunction main_$x_() {\n  return 1;\n}\nvar x = 1, y = .url;\nvar __default__ = null;\n\n"
+    );
+  }
+
+  #[test]
+  fn module_keywords_inside_strings_stay_code() {
+    let source = "export var doc = `\nexport default 1\nimport x from \"y\"\n`;\nexport var s = \"import.meta\";\n";
+    let parsed = parse_module(source).expect("parse module");
+    assert_eq!(parsed.specifiers().count(), 0);
+    assert!(!parsed.statements.contains(&Statement::ImportMeta));
+    assert_eq!(
+      parsed.local_exports,
+      vec![("doc".to_owned(), "doc".to_owned()), ("s".to_owned(), "s".to_owned())]
+    );
+    assert_eq!(
+      parsed.statements,
+      vec![
+        Statement::Code("var doc = `\nexport default 1\nimport x from \"y\"\n`;\n".to_owned()),
+        Statement::Code("var s = \"import.meta\";\n".to_owned()),
+      ]
+    );
+  }
+
+  #[test]
+  fn renders_named_imports_as_deferred_bindings() {
+    let parsed = parse_module("import { a } from \"./lib.mjs\";\nexport var b = () => a;\n").expect("parse module");
+    let ids = HashMap::from([("./lib.mjs".to_owned(), "js-out/lib.mjs".to_owned())]);
+    assert_eq!(
+      render_module_factory(&parsed, &ids, "import.meta"),
+      "__export(__exports, { \"b\": () => b });\nlet a; __import(\"js-out/lib.mjs\", (m) => { a = m[\"a\"]; });\nvar b = () => a;\n"
+    );
+  }
+
+  #[test]
+  fn bundle_formats_parse_from_cli_names() {
+    assert_eq!("iife".parse::<BundleFormat>(), Ok(BundleFormat::Iife));
+    assert_eq!("cjs".parse::<BundleFormat>(), Ok(BundleFormat::Cjs));
+    assert!("umd".parse::<BundleFormat>().is_err());
+  }
+
+  /// Emitted modules plus a `@calcit/procs` package with a JSON import and an import cycle.
+  fn write_bundle_fixture(root: &Path) {
+    let _ = fs::remove_dir_all(root);
+    let files = [
+      (
+        "js-out/app.main.mjs",
+        "import * as $clt from \"./calcit.core.mjs\";\nimport { helper, metaType } from \"./app.lib.mjs\";\nexport function main_$x_() { console.log(\"init\", helper(), $clt.tick(), metaType); }\nexport function reload_$x_() { console.log(\"reload\", helper(), $clt.tick(), metaType); }\n",
+      ),
+      (
+        "js-out/app.lib.mjs",
+        "import { calcit_version } from \"./calcit.core.mjs\";\nexport var helper = () => \"v\" + calcit_version;\nexport var metaType = typeof import.meta.url;\n",
+      ),
+      (
+        "js-out/calcit.core.mjs",
+        "import * as $procs from \"@calcit/procs\";\nexport * from \"@calcit/procs\";\nexport function tick() {\n  let n = ($procs._$n_deref(\"count\") ?? 0) + 1;\n  $procs._$n_reset_$x_(\"count\", n);\n  return n + $procs.checkCycle();\n}\n",
+      ),
+      (
+        "node_modules/@calcit/procs/package.json",
+        "{\"name\": \"@calcit/procs\", \"version\": \"0.1.0\", \"main\": \"./lib/calcit.procs.mjs\"}",
+      ),
+      (
+        "node_modules/@calcit/procs/lib/calcit.procs.mjs",
+        "import pkg from \"../package.json\" with { type: \"json\" };\nimport {\n  cycleA,\n} from \"./a.mjs\";\nexport * from \"./a.mjs\";\nexport const calcit_version = pkg.version;\nvar refs = new Map();\nexport function _$n_reset_$x_(k, v) { refs.set(k, v); return null; }\nexport function _$n_deref(k) { return refs.get(k); }\nexport let checkCycle = () => cycleA();\n",
+      ),
+      (
+        "node_modules/@calcit/procs/lib/a.mjs",
+        "import { fromB } from \"./b.mjs\";\nexport const aValue = \"a\";\nexport function cycleA() { return fromB(); }\n",
+      ),
+      (
+        "node_modules/@calcit/procs/lib/b.mjs",
+        "import { aValue } from \"./a.mjs\";\nexport function fromB() { return aValue + \"b\"; }\n",
+      ),
+    ];
+    for (path, content) in files {
+      let path = root.join(path);
+      fs::create_dir_all(path.parent().expect("parent")).expect("create fixture dir");
+      fs::write(path, content).expect("write fixture");
+    }
+  }
+
+  #[test]
+  fn bundles_emitted_modules_with_package_runtime() {
+    let root = std::env::temp_dir().join(format!("calcit-js-bundle-{}", std::process::id()));
+    write_bundle_fixture(&root);
+
+    let output = root.join("out.mjs");
+    let options = BundleOptions {
+      output: &output,
+      format: BundleFormat::Esm,
+    };
+    let count = write_bundle(&root.join("js-out"), ("app.main", "main!"), ("app.main", "reload!"), options).expect("bundle");
+    let code = fs::read_to_string(&output).expect("read bundle");
+    let _ = fs::remove_dir_all(&root);
+
+    assert_eq!(count, 7);
+    let defined = code
+      .lines()
+      .filter_map(|line| line.strip_prefix("__define(\""))
+      .map(|line| line.split('"').next().unwrap_or_default())
+      .collect::<Vec<_>>();
+    // dependencies first, package modules keyed independently of the project location
+    assert_eq!(
+      defined,
+      vec![
+        "node_modules/@calcit/procs/package.json",
+        "node_modules/@calcit/procs/lib/b.mjs",
+        "node_modules/@calcit/procs/lib/a.mjs",
+        "node_modules/@calcit/procs/lib/calcit.procs.mjs",
+        "calcit.core.mjs",
+        "app.lib.mjs",
+        "app.main.mjs",
+      ]
+    );
+    assert!(code.contains("__reload.reload_$x_();"));
+    assert!(code.contains("__init.main_$x_();"));
+    assert!(!code.contains("\nimport "));
+  }
+
+  /// Each bundle is evaluated twice in one node process: the first run calls
+  /// `init-fn`, the second calls `reload-fn` against the runtime kept on `globalThis`.
+  #[test]
+  fn iife_and_cjs_bundles_run_init_then_reload_under_node() {
+    if std::process::Command::new("node").arg("--version").output().is_err() {
+      eprintln!("node not found, skipping bundle run");
+      return;
+    }
+    let root = std::env::temp_dir().join(format!("calcit-js-bundle-run-{}", std::process::id()));
+    write_bundle_fixture(&root);
+
+    let runs = [
+      (
+        BundleFormat::Iife,
+        "out.js",
+        "const vm = require(\"vm\"); const code = require(\"fs\").readFileSync(process.argv[1], \"utf8\"); vm.runInThisContext(code); vm.runInThisContext(code);",
+      ),
+      (
+        BundleFormat::Cjs,
+        "out.cjs",
+        "const file = process.argv[1]; require(file); delete require.cache[require.resolve(file)]; require(file);",
+      ),
+    ];
+    let mut outputs = vec![];
+    for (format, file, loader) in runs {
+      let output = root.join(file);
+      let options = BundleOptions { output: &output, format };
+      write_bundle(&root.join("js-out"), ("app.main", "main!"), ("app.main", "reload!"), options).expect("bundle");
+      let result = std::process::Command::new("node")
+        .arg("-e")
+        .arg(loader)
+        .arg(&output)
+        .output()
+        .expect("run node");
+      outputs.push((
+        format,
+        result.status.success(),
+        String::from_utf8_lossy(&result.stdout).into_owned(),
+        String::from_utf8_lossy(&result.stderr).into_owned(),
+      ));
+    }
+    let _ = fs::remove_dir_all(&root);
+
+    for (format, success, stdout, stderr) in outputs {
+      assert!(success, "{format:?} bundle failed under node: {stderr}");
+      // the tick counter lives in the runtime, so reload sees the state left by init
+      assert_eq!(
+        stdout, "init v0.1.0 1ab string\nreload v0.1.0 2ab string\n",
+        "{format:?} bundle output"
+      );
+    }
+  }
+}
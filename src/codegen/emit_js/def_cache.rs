@@ -0,0 +1,507 @@
+//! Generated JavaScript per definition, reused across watch iterations and
+//! process restarts.
+//!
+//! An entry is keyed by the `snapshot::definition_revision` of the definition
+//! and of everything it depends on transitively. Macro expansion is not
+//! recorded in `deps`, so the revisions of all macros are part of every key as
+//! well. The cache file lives in the emit path, removing that folder also
+//! drops the cache.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cirru_edn::EdnTag;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+
+use crate::calcit::{CalcitImport, ImportInfo};
+use crate::codegen::{codegen_mode, skip_arity_check};
+use crate::program::{self, CompiledDef, CompiledDefKind, CompiledProgram, DefId};
+use crate::runner::preprocess;
+use crate::runtime_context::{ContextMutex, ContextSlot};
+
+const DEF_CACHE_FILE: &str = ".calcit-js-cache.json";
+
+/// kept between `emit_js` calls of the same context, loaded from disk otherwise
+static LOADED_DEF_CACHE: ContextSlot<ContextMutex<Option<DefCodeCache>>> = ContextSlot::new(|ctx| &ctx.js_def_cache);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+enum CachedImportInfo {
+  NsAs { at_ns: String, at_def: String, alias: String },
+  NsReferDef { at_ns: String, at_def: String },
+  Core { at_ns: String },
+  JsDefault { alias: String, at_ns: String, at_def: String },
+  SameFile { at_def: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct CachedImport {
+  ns: String,
+  def: String,
+  info: CachedImportInfo,
+}
+
+impl From<&CalcitImport> for CachedImport {
+  fn from(item: &CalcitImport) -> Self {
+    let info = match &*item.info {
+      ImportInfo::NsAs { at_ns, at_def, alias } => CachedImportInfo::NsAs {
+        at_ns: at_ns.to_string(),
+        at_def: at_def.to_string(),
+        alias: alias.to_string(),
+      },
+      ImportInfo::NsReferDef { at_ns, at_def } => CachedImportInfo::NsReferDef {
+        at_ns: at_ns.to_string(),
+        at_def: at_def.to_string(),
+      },
+      ImportInfo::Core { at_ns } => CachedImportInfo::Core { at_ns: at_ns.to_string() },
+      ImportInfo::JsDefault { alias, at_ns, at_def } => CachedImportInfo::JsDefault {
+        alias: alias.to_string(),
+        at_ns: at_ns.to_string(),
+        at_def: at_def.to_string(),
+      },
+      ImportInfo::SameFile { at_def } => CachedImportInfo::SameFile {
+        at_def: at_def.to_string(),
+      },
+    };
+    CachedImport {
+      ns: item.ns.to_string(),
+      def: item.def.to_string(),
+      info,
+    }
+  }
+}
+
+impl From<&CachedImport> for CalcitImport {
+  fn from(item: &CachedImport) -> Self {
+    let info = match &item.info {
+      CachedImportInfo::NsAs { at_ns, at_def, alias } => ImportInfo::NsAs {
+        at_ns: at_ns.as_str().into(),
+        at_def: at_def.as_str().into(),
+        alias: alias.as_str().into(),
+      },
+      CachedImportInfo::NsReferDef { at_ns, at_def } => ImportInfo::NsReferDef {
+        at_ns: at_ns.as_str().into(),
+        at_def: at_def.as_str().into(),
+      },
+      CachedImportInfo::Core { at_ns } => ImportInfo::Core {
+        at_ns: at_ns.as_str().into(),
+      },
+      CachedImportInfo::JsDefault { alias, at_ns, at_def } => ImportInfo::JsDefault {
+        alias: alias.as_str().into(),
+        at_ns: at_ns.as_str().into(),
+        at_def: at_def.as_str().into(),
+      },
+      CachedImportInfo::SameFile { at_def } => ImportInfo::SameFile {
+        at_def: at_def.as_str().into(),
+      },
+    };
+    CalcitImport {
+      ns: item.ns.as_str().into(),
+      def: item.def.as_str().into(),
+      info: Arc::new(info),
+      // runtime ids are not stable across processes and unused by codegen
+      def_id: None,
+    }
+  }
+}
+
+/// Output of one definition, with the imports and tags it collected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct CachedDefCode {
+  key: String,
+  pub code: String,
+  pub imports: Vec<CachedImport>,
+  pub tags: Vec<String>,
+}
+
+impl CachedDefCode {
+  pub fn new(key: String, code: String, imports: &HashSet<CalcitImport>, tags: &HashSet<EdnTag>) -> Self {
+    CachedDefCode {
+      key,
+      code,
+      imports: imports.iter().map(CachedImport::from).collect(),
+      tags: tags.iter().map(|tag| tag.ref_str().to_owned()).collect(),
+    }
+  }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DefCacheFile {
+  /// compiler version and options that change generated code
+  compiler: String,
+  defs: HashMap<String, CachedDefCode>,
+}
+
+/// every switch that changes the generated code of a definition belongs here
+fn compiler_fingerprint(only_reachable: bool) -> String {
+  format!(
+    "calcit {} codegen-mode={} skip-arity-check={} optimize={} only-reachable={only_reachable}",
+    env!("CARGO_PKG_VERSION"),
+    codegen_mode(),
+    skip_arity_check(),
+    preprocess::optimize_enabled()
+  )
+}
+
+#[derive(Debug)]
+pub(crate) struct DefCodeCache {
+  emit_path: PathBuf,
+  data: DefCacheFile,
+  changed: bool,
+  pub hits: usize,
+}
+
+impl DefCodeCache {
+  /// Take the cache of a previous `emit_js` call, or load it from `emit_path`.
+  /// A missing or unreadable file, or one from another compiler or another
+  /// `--only-reachable` mode, starts empty.
+  pub(super) fn open(emit_path: &Path, only_reachable: bool) -> Self {
+    let compiler = compiler_fingerprint(only_reachable);
+    let previous = LOADED_DEF_CACHE.lock().expect("lock js def cache").take();
+    if let Some(mut cache) = previous
+      && cache.emit_path == emit_path
+      && cache.data.compiler == compiler
+    {
+      cache.hits = 0;
+      return cache;
+    }
+    let data = fs::read_to_string(emit_path.join(DEF_CACHE_FILE))
+      .ok()
+      .and_then(|content| serde_json::from_str::<DefCacheFile>(&content).ok())
+      .filter(|data| data.compiler == compiler)
+      .unwrap_or_else(|| DefCacheFile {
+        compiler,
+        defs: HashMap::new(),
+      });
+    DefCodeCache {
+      emit_path: emit_path.to_owned(),
+      data,
+      changed: false,
+      hits: 0,
+    }
+  }
+
+  pub(super) fn get(&mut self, ns: &str, def: &str, key: &str) -> Option<&CachedDefCode> {
+    let entry = self.data.defs.get(&format!("{ns}/{def}")).filter(|entry| entry.key == key)?;
+    self.hits += 1;
+    Some(entry)
+  }
+
+  pub(super) fn insert(&mut self, ns: &str, def: &str, entry: CachedDefCode) {
+    self.data.defs.insert(format!("{ns}/{def}"), entry);
+    self.changed = true;
+  }
+
+  /// Drop entries of definitions that no longer exist, write the cache file
+  /// when it changed, and keep the cache for the next call.
+  pub(super) fn finish(mut self, program: &CompiledProgram) -> Result<(), String> {
+    let before = self.data.defs.len();
+    self.data.defs.retain(|path, _| {
+      path
+        .split_once('/')
+        .is_some_and(|(ns, def)| program.get(ns).is_some_and(|file| file.defs.contains_key(def)))
+    });
+    if self.changed || self.data.defs.len() != before {
+      let content = serde_json::to_string(&self.data).map_err(|e| format!("failed to encode js def cache: {e}"))?;
+      fs::write(self.emit_path.join(DEF_CACHE_FILE), content).map_err(|e| format!("failed to write js def cache: {e}"))?;
+      self.changed = false;
+    }
+    *LOADED_DEF_CACHE.lock().expect("lock js def cache") = Some(self);
+    Ok(())
+  }
+}
+
+/// Computes cache keys from definition revisions of a compiled program.
+pub(super) struct DefKeys {
+  /// hash over the revisions of a definition and its transitive deps, `None`
+  /// when one of them has no source revision
+  closures: HashMap<DefId, Option<String>>,
+  macro_epoch: Option<String>,
+}
+
+/// Tarjan's strongly connected components over `deps`. Definitions of one
+/// cycle share a hash, and each component hashes the components it depends
+/// on, which are always finished first. The walk keeps its own stack, so long
+/// dependency chains do not overflow the thread stack.
+struct ClosureHasher<'a> {
+  defs: HashMap<DefId, (String, &'a CompiledDef)>,
+  revisions: HashMap<DefId, Arc<str>>,
+  index: HashMap<DefId, usize>,
+  low_link: HashMap<DefId, usize>,
+  stack: Vec<DefId>,
+  on_stack: HashSet<DefId>,
+  closures: HashMap<DefId, Option<String>>,
+}
+
+impl ClosureHasher<'_> {
+  fn enter(&mut self, def_id: DefId) {
+    let idx = self.index.len();
+    self.index.insert(def_id, idx);
+    self.low_link.insert(def_id, idx);
+    self.stack.push(def_id);
+    self.on_stack.insert(def_id);
+  }
+
+  fn lower_link(&mut self, def_id: DefId, link: usize) {
+    let low = self.low_link[&def_id].min(link);
+    self.low_link.insert(def_id, low);
+  }
+
+  fn visit(&mut self, root: DefId) {
+    // definitions being visited, with the position of the next dep to follow
+    let mut frames: Vec<(DefId, usize)> = vec![(root, 0)];
+    self.enter(root);
+
+    while let Some(frame) = frames.last_mut() {
+      let (def_id, next) = *frame;
+      let deps = &self.defs[&def_id].1.deps;
+      if let Some(&dep) = deps.get(next) {
+        frame.1 += 1;
+        // deps outside the program are JavaScript modules, covered by the ns form
+        if !self.defs.contains_key(&dep) {
+          continue;
+        }
+        if !self.index.contains_key(&dep) {
+          self.enter(dep);
+          frames.push((dep, 0));
+        } else if self.on_stack.contains(&dep) {
+          self.lower_link(def_id, self.index[&dep]);
+        }
+        continue;
+      }
+
+      frames.pop();
+      if let Some(&(parent, _)) = frames.last() {
+        self.lower_link(parent, self.low_link[&def_id]);
+      }
+      if self.low_link[&def_id] == self.index[&def_id] {
+        let mut members = vec![];
+        while let Some(member) = self.stack.pop() {
+          self.on_stack.remove(&member);
+          members.push(member);
+          if member == def_id {
+            break;
+          }
+        }
+        let closure = self.hash_component(&members);
+        for member in members {
+          self.closures.insert(member, closure.to_owned());
+        }
+      }
+    }
+  }
+
+  fn hash_component(&self, members: &[DefId]) -> Option<String> {
+    let mut parts: Vec<(&str, Arc<str>)> = vec![];
+    let mut dep_closures: Vec<&str> = vec![];
+    for member in members {
+      let (name, compiled_def) = &self.defs[member];
+      parts.push((name, self.revisions.get(member)?.to_owned()));
+      for dep in &compiled_def.deps {
+        if let Some(closure) = self.closures.get(dep) {
+          dep_closures.push(closure.as_deref()?);
+        }
+      }
+    }
+    parts.sort_unstable();
+    dep_closures.sort_unstable();
+    dep_closures.dedup();
+
+    let mut hasher = Md5::new();
+    for (name, revision) in parts {
+      hasher.update(name.as_bytes());
+      hasher.update([0]);
+      hasher.update(revision.as_bytes());
+      hasher.update([0]);
+    }
+    for closure in dep_closures {
+      hasher.update(closure.as_bytes());
+    }
+    Some(hex::encode(hasher.finalize()))
+  }
+}
+
+/// Closure hashes of every definition in `program`.
+fn hash_closures(program: &CompiledProgram, revision_of: impl Fn(&str, &str) -> Option<Arc<str>>) -> HashMap<DefId, Option<String>> {
+  let mut hasher = ClosureHasher {
+    defs: HashMap::new(),
+    revisions: HashMap::new(),
+    index: HashMap::new(),
+    low_link: HashMap::new(),
+    stack: vec![],
+    on_stack: HashSet::new(),
+    closures: HashMap::new(),
+  };
+  for (ns, file) in program {
+    for (def, compiled_def) in &file.defs {
+      hasher.defs.insert(compiled_def.def_id, (format!("{ns}/{def}"), compiled_def));
+      if let Some(revision) = revision_of(ns, def) {
+        hasher.revisions.insert(compiled_def.def_id, revision);
+      }
+    }
+  }
+  let mut def_ids = hasher.defs.keys().copied().collect::<Vec<_>>();
+  def_ids.sort();
+  for def_id in def_ids {
+    if !hasher.index.contains_key(&def_id) {
+      hasher.visit(def_id);
+    }
+  }
+  hasher.closures
+}
+
+impl DefKeys {
+  pub fn new(program: &CompiledProgram) -> Self {
+    let closures = hash_closures(program, program::lookup_def_revision);
+    let mut macro_closures = program
+      .values()
+      .flat_map(|file| file.defs.values())
+      .filter(|compiled_def| compiled_def.kind == CompiledDefKind::Macro)
+      .map(|compiled_def| closures[&compiled_def.def_id].as_deref())
+      .collect::<Option<Vec<_>>>();
+    let macro_epoch = macro_closures.as_mut().map(|closures| {
+      closures.sort_unstable();
+      closures.dedup();
+      hex::encode(Md5::digest(closures.concat().as_bytes()))
+    });
+    DefKeys { closures, macro_epoch }
+  }
+
+  /// Key part shared by the definitions of one emitted file. The `ns` form
+  /// decides where imports point to, and `local_defs` decide whether a symbol
+  /// is referenced locally or through an import.
+  pub fn file_part(&self, ns: &str, local_defs: &HashSet<Arc<str>>) -> Option<String> {
+    let macro_epoch = self.macro_epoch.as_ref()?;
+    let ns_revision = program::lookup_ns_revision(ns)?;
+    let mut local_names = local_defs.iter().map(|name| &**name).collect::<Vec<_>>();
+    local_names.sort_unstable();
+
+    let mut hasher = Md5::new();
+    hasher.update(macro_epoch.as_bytes());
+    hasher.update(ns_revision.as_bytes());
+    for name in local_names {
+      hasher.update(name.as_bytes());
+      hasher.update([0]);
+    }
+    Some(hex::encode(hasher.finalize()))
+  }
+
+  pub fn key(&self, file_part: &str, compiled_def: &CompiledDef) -> Option<String> {
+    let closure = self.closures.get(&compiled_def.def_id)?.as_ref()?;
+    Some(hex::encode(Md5::digest(format!("{closure}{file_part}").as_bytes())))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::program::tests::compiled_file_for_test;
+
+  #[test]
+  fn closure_hashes_follow_transitive_deps_and_cycles() {
+    // a -> b <-> c -> d, with e alone and f depending on a js module (99)
+    let program: CompiledProgram = HashMap::from([(
+      Arc::from("app.main"),
+      compiled_file_for_test(&[
+        ("a", 1, &[2]),
+        ("b", 2, &[3]),
+        ("c", 3, &[2, 4]),
+        ("d", 4, &[]),
+        ("e", 5, &[]),
+        ("f", 6, &[99]),
+      ]),
+    )]);
+    let hashes = |changed: Option<&str>| {
+      hash_closures(&program, |_ns, def| {
+        Some(Arc::from(if changed == Some(def) {
+          format!("{def}-v2")
+        } else {
+          def.to_owned()
+        }))
+      })
+    };
+    let base = hashes(None);
+    let changed_d = hashes(Some("d"));
+
+    assert_eq!(base[&DefId(2)], base[&DefId(3)], "a cycle shares one hash");
+    for id in [1, 2, 3, 4] {
+      assert_ne!(base[&DefId(id)], changed_d[&DefId(id)], "def {id} depends on d");
+    }
+    assert_eq!(base[&DefId(5)], changed_d[&DefId(5)]);
+    assert!(base[&DefId(6)].is_some(), "deps outside the program are skipped");
+
+    let missing = hash_closures(&program, |_ns, def| (def != "d").then(|| Arc::from(def)));
+    assert_eq!(missing[&DefId(1)], None);
+    assert!(missing[&DefId(5)].is_some());
+  }
+
+  #[test]
+  fn closure_hashes_walk_long_chains_without_recursion() {
+    // each def depends on the next one, deeper than a recursive walk could go
+    let size: u32 = 50_000;
+    let deps = (0..size)
+      .map(|idx| if idx + 1 < size { vec![idx + 1] } else { vec![] })
+      .collect::<Vec<_>>();
+    let names = (0..size).map(|idx| format!("f{idx}")).collect::<Vec<_>>();
+    let entries = (0..size as usize)
+      .map(|idx| (names[idx].as_str(), idx as u32, deps[idx].as_slice()))
+      .collect::<Vec<_>>();
+    let program: CompiledProgram = HashMap::from([(Arc::from("app.main"), compiled_file_for_test(&entries))]);
+    let hashes = hash_closures(&program, |_ns, def| Some(Arc::from(def)));
+    assert_eq!(hashes.len(), size as usize);
+    assert!(hashes.values().all(Option::is_some));
+  }
+
+  #[test]
+  fn codegen_switches_miss_the_cache() {
+    let _context = crate::runtime_context::RuntimeContext::new().enter();
+    let dir = std::env::temp_dir().join(format!("calcit-js-def-cache-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("create emit dir");
+    let program: CompiledProgram = HashMap::from([(Arc::from("app.main"), compiled_file_for_test(&[("f", 1, &[])]))]);
+    let entry = || CachedDefCode::new(String::from("key"), String::from("code"), &HashSet::new(), &HashSet::new());
+
+    let mut cache = DefCodeCache::open(&dir, false);
+    cache.insert("app.main", "f", entry());
+    cache.finish(&program).expect("write cache");
+    let mut reopened = DefCodeCache::open(&dir, false);
+    assert!(reopened.get("app.main", "f", "key").is_some());
+    reopened.finish(&program).expect("keep cache");
+
+    let mut reachable = DefCodeCache::open(&dir, true);
+    assert!(
+      reachable.get("app.main", "f", "key").is_none(),
+      "--only-reachable output must not be reused"
+    );
+    reachable.insert("app.main", "f", entry());
+    reachable.finish(&program).expect("write cache");
+
+    preprocess::set_optimize(!preprocess::optimize_enabled());
+    let mut flipped = DefCodeCache::open(&dir, true);
+    assert!(flipped.get("app.main", "f", "key").is_none(), "optimized code must not be reused");
+    fs::remove_dir_all(&dir).expect("clean emit dir");
+  }
+
+  #[test]
+  fn cached_imports_round_trip() {
+    let item = CalcitImport {
+      ns: Arc::from("app.lib"),
+      def: Arc::from("f"),
+      info: Arc::new(ImportInfo::NsAs {
+        at_ns: Arc::from("app.main"),
+        at_def: Arc::from("main!"),
+        alias: Arc::from("lib"),
+      }),
+      def_id: Some(3),
+    };
+    let cached = CachedImport::from(&item);
+    let json = serde_json::to_string(&cached).expect("encode");
+    let decoded: CachedImport = serde_json::from_str(&json).expect("decode");
+    let restored = CalcitImport::from(&decoded);
+    assert_eq!(restored, item);
+    assert_eq!(restored.info, item.info);
+    assert_eq!(restored.def_id, None);
+  }
+}
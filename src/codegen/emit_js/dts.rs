@@ -0,0 +1,580 @@
+//! TypeScript declarations (`.d.mts`) for emitted namespaces, derived from
+//! definition schemas. Runtime classes come from `@calcit/procs`; anything the
+//! schema leaves open is declared as `unknown`.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::calcit::type_annotation::resolve_nominal_type_def_from_code;
+use crate::calcit::{self, Calcit, CalcitArgLabel, CalcitFnArgs, CalcitLocal, CalcitSyntax, CalcitTypeAnnotation};
+use crate::program::{self, CompiledDefKind, CompiledFileData};
+
+use super::paths::to_js_import_name;
+use super::symbols::escape_var;
+use super::{emits_js_def, escape_ns, extract_preprocessed_fn_parts, hinted_async, is_preferred_js_proc};
+
+/// A struct or enum definition that gets a TypeScript type in its namespace's `.d.mts`.
+#[derive(Debug, Clone)]
+struct TypeSite {
+  ns: Arc<str>,
+  ident: String,
+  generics: Vec<Arc<str>>,
+}
+
+/// Struct and enum types of every namespace `emit_js` writes, so a declaration
+/// can refer to a type defined in another file.
+#[derive(Debug, Default)]
+pub(super) struct DtsTypeIndex {
+  by_name: HashMap<Arc<str>, Vec<TypeSite>>,
+  by_path: HashMap<(Arc<str>, Arc<str>), TypeSite>,
+}
+
+impl DtsTypeIndex {
+  /// Index the emitted struct and enum definitions. `keep` filters out
+  /// definitions that are not written, e.g. under `--only-reachable`.
+  pub(super) fn collect<'a>(
+    files: impl IntoIterator<Item = (&'a Arc<str>, &'a CompiledFileData)>,
+    keep: impl Fn(&str, &str) -> bool,
+  ) -> Self {
+    let mut index = Self::default();
+    for (ns, file) in files {
+      let mut defs = file.defs.iter().collect::<Vec<_>>();
+      defs.sort_by(|a, b| a.0.cmp(b.0));
+      // one site per type name and namespace, like the local lookup in `gen_dts_code`
+      let mut sites: HashMap<Arc<str>, TypeSite> = HashMap::new();
+      for (def, compiled_def) in defs {
+        if !keep(ns, def) || !emits_js_def(ns, def, compiled_def) {
+          continue;
+        }
+        let Some((name, generics, _)) = nominal_type_def(compiled_def) else {
+          continue;
+        };
+        let site = TypeSite {
+          ns: ns.to_owned(),
+          ident: escape_var(def),
+          generics,
+        };
+        index.by_path.insert((ns.to_owned(), def.to_owned()), site.to_owned());
+        sites.insert(name, site);
+      }
+      for (name, site) in sites {
+        index.by_name.entry(name).or_default().push(site);
+      }
+    }
+    index
+  }
+
+  /// A type name defined by more than one namespace is ambiguous and not resolved.
+  fn lookup(&self, name: &str, path: Option<(Arc<str>, Arc<str>)>) -> Option<&TypeSite> {
+    if let Some(site) = path.and_then(|path| self.by_path.get(&path)) {
+      return Some(site);
+    }
+    match self.by_name.get(name).map(Vec::as_slice) {
+      Some([site]) => Some(site),
+      _ => None,
+    }
+  }
+}
+
+/// The struct or enum a lazy value defines, with its type name and generics.
+fn nominal_type_def(compiled_def: &program::CompiledDef) -> Option<(Arc<str>, Vec<Arc<str>>, Calcit)> {
+  if compiled_def.kind != CompiledDefKind::LazyValue {
+    return None;
+  }
+  let type_def = compiled_def.source_code.as_ref().and_then(resolve_nominal_type_def_from_code)?;
+  let (name, generics) = match &type_def {
+    Calcit::StructDef(struct_def) => (struct_def.name.ref_str(), struct_def.generics.to_vec()),
+    Calcit::EnumDef(enum_def) => (enum_def.name().ref_str(), enum_def.generics().to_vec()),
+    _ => return None,
+  };
+  Some((Arc::from(name), generics, type_def))
+}
+
+struct DtsContext<'a> {
+  ns: &'a str,
+  types: &'a DtsTypeIndex,
+  /// types declared in the current namespace, keyed by type name
+  local_types: HashMap<Arc<str>, TypeSite>,
+  runtime_types: BTreeSet<&'static str>,
+  imported_ns: BTreeSet<Arc<str>>,
+}
+
+impl DtsContext<'_> {
+  fn runtime(&mut self, name: &'static str) -> String {
+    self.runtime_types.insert(name);
+    name.to_owned()
+  }
+
+  /// Refer to a struct or enum type, from the current namespace or through an
+  /// `import type` of the namespace defining it. `path` is the `(ns, def)` of a
+  /// resolved type reference, when known.
+  fn nominal_type_ref(
+    &mut self,
+    name: &str,
+    path: Option<(Arc<str>, Arc<str>)>,
+    args: &[Arc<CalcitTypeAnnotation>],
+    generics: &[Arc<str>],
+    fallback: &'static str,
+  ) -> String {
+    let site = match self.local_types.get(name) {
+      Some(site) => site.to_owned(),
+      None => match self.types.lookup(name, path) {
+        Some(site) => site.to_owned(),
+        None => return self.runtime(fallback),
+      },
+    };
+    let ident = if &*site.ns == self.ns {
+      site.ident
+    } else {
+      let ident = format!("{}.{}", escape_ns(&site.ns), site.ident);
+      self.imported_ns.insert(site.ns);
+      ident
+    };
+    if args.is_empty() || args.len() != site.generics.len() {
+      return ident;
+    }
+    let args = args.iter().map(|arg| self.ts_type(arg, generics)).collect::<Vec<_>>();
+    format!("{ident}<{}>", args.join(", "))
+  }
+
+  /// Translate a schema type into TypeScript. `generics` lists the type
+  /// variables in scope, other type variables become `unknown`.
+  fn ts_type(&mut self, t: &CalcitTypeAnnotation, generics: &[Arc<str>]) -> String {
+    match t {
+      CalcitTypeAnnotation::Bool => "boolean".to_owned(),
+      CalcitTypeAnnotation::Number => "number".to_owned(),
+      CalcitTypeAnnotation::String => "string".to_owned(),
+      CalcitTypeAnnotation::Unit => "null".to_owned(),
+      CalcitTypeAnnotation::Symbol => self.runtime("CalcitSymbol"),
+      CalcitTypeAnnotation::Tag => self.runtime("CalcitTag"),
+      CalcitTypeAnnotation::List(_) => format!("{} | {}", self.runtime("CalcitList"), self.runtime("CalcitSliceList")),
+      CalcitTypeAnnotation::Map(..) | CalcitTypeAnnotation::MapShape(_) => {
+        format!("{} | {}", self.runtime("CalcitMap"), self.runtime("CalcitSliceMap"))
+      }
+      CalcitTypeAnnotation::Set(_) => self.runtime("CalcitSet"),
+      CalcitTypeAnnotation::Ref(_) => self.runtime("CalcitRef"),
+      CalcitTypeAnnotation::Buffer => "Uint8Array".to_owned(),
+      CalcitTypeAnnotation::CirruQuote => self.runtime("CalcitCirruQuote"),
+      CalcitTypeAnnotation::DynFn => self.runtime("CalcitFn"),
+      CalcitTypeAnnotation::Fn(signature) => {
+        let mut params = signature
+          .arg_types
+          .iter()
+          .enumerate()
+          .map(|(idx, arg)| format!("arg{idx}: {}", self.ts_type(arg, generics)))
+          .collect::<Vec<_>>();
+        if let Some(rest) = &signature.rest_type {
+          params.push(format!("...rest: {}[]", self.union_member(rest, generics)));
+        }
+        format!("({}) => {}", params.join(", "), self.ts_type(&signature.return_type, generics))
+      }
+      CalcitTypeAnnotation::Variadic(inner) => format!("{}[]", self.union_member(inner, generics)),
+      CalcitTypeAnnotation::Optional(inner) => format!("{} | null", self.union_member(inner, generics)),
+      CalcitTypeAnnotation::JsNullish(inner) => format!("{} | null | undefined", self.union_member(inner, generics)),
+      CalcitTypeAnnotation::Struct(def, args) => self.nominal_type_ref(def.name.ref_str(), None, args, generics, "CalcitStructValue"),
+      CalcitTypeAnnotation::StructValue(def) => self.nominal_type_ref(def.name.ref_str(), None, &[], generics, "CalcitStructValue"),
+      CalcitTypeAnnotation::Enum(def, args) => self.nominal_type_ref(def.name().ref_str(), None, args, generics, "CalcitEnumValue"),
+      CalcitTypeAnnotation::EnumValue(def) => self.nominal_type_ref(def.name().ref_str(), None, &[], generics, "CalcitEnumValue"),
+      CalcitTypeAnnotation::AnonymousEnum => self.runtime("CalcitEnumValue"),
+      CalcitTypeAnnotation::StructDef(_) => self.runtime("CalcitStructDef"),
+      CalcitTypeAnnotation::EnumDef(_) => self.runtime("CalcitEnumDef"),
+      CalcitTypeAnnotation::TypeVar(name) if generics.contains(name) => escape_var(name),
+      CalcitTypeAnnotation::TypeRef(_, args) => {
+        if let Some((struct_def, path)) = t.resolve_to_struct_with_ref() {
+          self.nominal_type_ref(struct_def.name.ref_str(), path, args, generics, "CalcitStructValue")
+        } else if let Some((enum_def, path)) = t.resolve_to_enum_with_ref() {
+          self.nominal_type_ref(enum_def.name().ref_str(), path, args, generics, "CalcitEnumValue")
+        } else {
+          "unknown".to_owned()
+        }
+      }
+      CalcitTypeAnnotation::TypeVar(_)
+      | CalcitTypeAnnotation::Custom(_)
+      | CalcitTypeAnnotation::Dynamic
+      | CalcitTypeAnnotation::Trait(_)
+      | CalcitTypeAnnotation::TraitSet(_)
+      | CalcitTypeAnnotation::JsObject
+      | CalcitTypeAnnotation::TypeSlot(_) => "unknown".to_owned(),
+    }
+  }
+
+  /// Like `ts_type`, parenthesized where needed to sit inside a union or array type.
+  fn union_member(&mut self, t: &CalcitTypeAnnotation, generics: &[Arc<str>]) -> String {
+    let code = self.ts_type(t, generics);
+    if code.contains("=>") || code.contains(" | ") {
+      format!("({code})")
+    } else {
+      code
+    }
+  }
+}
+
+fn generics_code(generics: &[Arc<str>], with_defaults: bool) -> String {
+  if generics.is_empty() {
+    return String::new();
+  }
+  let names = generics
+    .iter()
+    .map(|name| {
+      if with_defaults {
+        format!("{} = unknown", escape_var(name))
+      } else {
+        escape_var(name)
+      }
+    })
+    .collect::<Vec<_>>();
+  format!("<{}>", names.join(", "))
+}
+
+fn is_async_fn_body(body: &[Calcit]) -> bool {
+  body.iter().any(|line| match line {
+    Calcit::List(xs) => {
+      let is_hint = match xs.first() {
+        Some(Calcit::Syntax(sym, _ns)) => sym == &CalcitSyntax::HintFn,
+        Some(Calcit::Symbol { sym, .. }) => sym.as_ref() == "hint-fn",
+        _ => false,
+      };
+      is_hint && hinted_async(xs)
+    }
+    _ => false,
+  })
+}
+
+fn gen_fn_decl(ctx: &mut DtsContext, def: &str, compiled_def: &program::CompiledDef) -> Result<String, String> {
+  let parts = extract_preprocessed_fn_parts(&compiled_def.preprocessed_code)?;
+  let signature = match compiled_def.schema.as_ref() {
+    CalcitTypeAnnotation::Fn(signature) => Some(signature.to_owned()),
+    _ => None,
+  };
+  let generics: Vec<Arc<str>> = signature.as_ref().map(|s| s.generics.to_vec()).unwrap_or_default();
+  let arg_type = |idx: usize| -> Arc<CalcitTypeAnnotation> {
+    let local = parts.arg_types.get(idx).cloned().unwrap_or_else(|| calcit::DYNAMIC_TYPE.clone());
+    match signature.as_ref().and_then(|s| s.arg_types.get(idx)) {
+      Some(declared) if matches!(local.as_ref(), CalcitTypeAnnotation::Dynamic) => declared.to_owned(),
+      _ => local,
+    }
+  };
+
+  let mut params: Vec<String> = vec![];
+  match &parts.args {
+    CalcitFnArgs::Args(idxs) => {
+      let optional_from = idxs.len() - calcit::trailing_option_arg_count(&parts.arg_types, idxs.len());
+      for (position, idx) in idxs.iter().enumerate() {
+        let mark = if position >= optional_from { "?" } else { "" };
+        let t = ctx.ts_type(&arg_type(position), &generics);
+        params.push(format!("{}{mark}: {t}", escape_var(&CalcitLocal::read_name(*idx))));
+      }
+    }
+    CalcitFnArgs::MarkedArgs(labels) => {
+      let mut optional = false;
+      let mut spreading = false;
+      let mut position = 0;
+      for label in labels {
+        match label {
+          CalcitArgLabel::OptionalMark => optional = true,
+          CalcitArgLabel::RestMark => spreading = true,
+          CalcitArgLabel::Idx(idx) => {
+            let name = escape_var(&CalcitLocal::read_name(*idx));
+            if spreading {
+              let item_type = match signature.as_ref().and_then(|s| s.rest_type.to_owned()) {
+                Some(rest) => rest,
+                None => match arg_type(position).as_ref() {
+                  CalcitTypeAnnotation::Variadic(inner) | CalcitTypeAnnotation::List(inner) => inner.to_owned(),
+                  _ => calcit::DYNAMIC_TYPE.clone(),
+                },
+              };
+              params.push(format!("...{name}: {}[]", ctx.union_member(&item_type, &generics)));
+            } else {
+              let mark = if optional { "?" } else { "" };
+              params.push(format!("{name}{mark}: {}", ctx.ts_type(&arg_type(position), &generics)));
+            }
+            position += 1;
+          }
+        }
+      }
+    }
+  }
+
+  let return_type = match &signature {
+    Some(signature) => ctx.ts_type(&signature.return_type, &generics),
+    None => "unknown".to_owned(),
+  };
+  let return_type = if is_async_fn_body(&parts.body) {
+    format!("Promise<{return_type}>")
+  } else {
+    return_type
+  };
+  Ok(format!(
+    "export declare function {}{}({}): {return_type};\n",
+    escape_var(def),
+    generics_code(&generics, false),
+    params.join(", ")
+  ))
+}
+
+fn gen_type_def_decl(ctx: &mut DtsContext, def: &str, type_def: &Calcit) -> String {
+  let ident = escape_var(def);
+  let mut code = String::new();
+  match type_def {
+    Calcit::StructDef(struct_def) => {
+      let generics = struct_def.generics.to_vec();
+      let fields = struct_def
+        .fields
+        .iter()
+        .zip(struct_def.field_types.iter())
+        .map(|(field, t)| (field.ref_str().to_owned(), ctx.ts_type(t, &generics)))
+        .collect::<Vec<_>>();
+      let summary = fields.iter().map(|(field, t)| format!("{field}: {t}")).collect::<Vec<_>>();
+      let values = fields.into_iter().map(|(_, t)| t).collect::<Vec<_>>();
+      writeln!(code, "export declare var {ident}: {};", ctx.runtime("CalcitStructDef")).expect("write");
+      writeln!(code, "/** struct {} {{{}}} */", struct_def.name.ref_str(), summary.join(", ")).expect("write");
+      writeln!(
+        code,
+        "export type {ident}{} = {} & {{ values: [{}] }};",
+        generics_code(&generics, true),
+        ctx.runtime("CalcitStructValue"),
+        values.join(", ")
+      )
+      .expect("write");
+    }
+    Calcit::EnumDef(enum_def) => {
+      let generics = enum_def.generics().to_vec();
+      let mut summary: Vec<String> = vec![];
+      let mut payloads: Vec<String> = vec![];
+      for variant in enum_def.variants() {
+        let types = variant.payload_types.iter().map(|t| ctx.ts_type(t, &generics)).collect::<Vec<_>>();
+        if types.is_empty() {
+          summary.push(format!(":{}", variant.tag.ref_str()));
+        } else {
+          summary.push(format!(":{}({})", variant.tag.ref_str(), types.join(", ")));
+        }
+        let payload = format!("[{}]", types.join(", "));
+        if !payloads.contains(&payload) {
+          payloads.push(payload);
+        }
+      }
+      if payloads.is_empty() {
+        payloads.push("[]".to_owned());
+      }
+      writeln!(code, "export declare var {ident}: {};", ctx.runtime("CalcitEnumDef")).expect("write");
+      writeln!(code, "/** enum {} {} */", enum_def.name().ref_str(), summary.join(" | ")).expect("write");
+      writeln!(
+        code,
+        "export type {ident}{} = {} & {{ extra: {} }};",
+        generics_code(&generics, true),
+        ctx.runtime("CalcitEnumValue"),
+        payloads.join(" | ")
+      )
+      .expect("write");
+    }
+    _ => unreachable!("expected struct or enum definition, got: {type_def}"),
+  }
+  code
+}
+
+/// Generate the `.d.mts` content matching what `emit_js` writes for `ns`.
+/// `defs` are the definitions in emit order, `types` the struct and enum
+/// types of all emitted namespaces.
+pub(super) fn gen_dts_code(
+  ns: &str,
+  file: &CompiledFileData,
+  defs: &[Arc<str>],
+  types: &DtsTypeIndex,
+  procs_exports: Option<&[&str]>,
+) -> Result<String, String> {
+  let mut ctx = DtsContext {
+    ns,
+    types,
+    local_types: HashMap::new(),
+    runtime_types: BTreeSet::new(),
+    imported_ns: BTreeSet::new(),
+  };
+
+  let mut type_defs: HashMap<Arc<str>, Calcit> = HashMap::new();
+  for def in defs {
+    let compiled_def = file.defs.get(def).expect("compiled def for dts");
+    let Some((name, generics, type_def)) = nominal_type_def(compiled_def) else {
+      continue;
+    };
+    ctx.local_types.insert(
+      name,
+      TypeSite {
+        ns: Arc::from(ns),
+        ident: escape_var(def),
+        generics,
+      },
+    );
+    type_defs.insert(def.to_owned(), type_def);
+  }
+
+  let mut decls = String::new();
+  for def in defs {
+    let compiled_def = file.defs.get(def).expect("compiled def for dts");
+    if !emits_js_def(ns, def, compiled_def) || (ns == calcit::CORE_NS && is_preferred_js_proc(def)) {
+      continue;
+    }
+    match compiled_def.kind {
+      CompiledDefKind::Fn => decls.push_str(&gen_fn_decl(&mut ctx, def, compiled_def)?),
+      CompiledDefKind::LazyValue => match type_defs.get(def) {
+        Some(type_def) => decls.push_str(&gen_type_def_decl(&mut ctx, def, type_def)),
+        None => {
+          let t = ctx.ts_type(&compiled_def.schema, &[]);
+          writeln!(decls, "export declare var {}: {t};", escape_var(def)).expect("write");
+        }
+      },
+      _ => {}
+    }
+  }
+
+  let mut code = String::new();
+  if !ctx.runtime_types.is_empty() {
+    let names = ctx.runtime_types.iter().copied().collect::<Vec<_>>();
+    writeln!(code, "import type {{ {} }} from \"@calcit/procs\";", names.join(", ")).expect("write");
+  }
+  for imported in &ctx.imported_ns {
+    writeln!(
+      code,
+      "import type * as {} from {};",
+      escape_ns(imported),
+      to_js_import_name(imported, true)
+    )
+    .expect("write");
+  }
+  if ns == calcit::CORE_NS {
+    match procs_exports {
+      Some([]) => {}
+      Some(names) => writeln!(code, "export {{ {} }} from \"@calcit/procs\";", names.join(", ")).expect("write"),
+      None => code.push_str("export * from \"@calcit/procs\";\n"),
+    }
+  }
+  if !code.is_empty() {
+    code.push('\n');
+  }
+  code.push_str(&decls);
+  Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::calcit::{CalcitFnTypeAnnotation, CalcitStructDef, SchemaKind};
+  use cirru_edn::EdnTag;
+  use std::collections::HashSet;
+
+  fn empty_ctx<'a>(ns: &'a str, types: &'a DtsTypeIndex) -> DtsContext<'a> {
+    DtsContext {
+      ns,
+      types,
+      local_types: HashMap::new(),
+      runtime_types: BTreeSet::new(),
+      imported_ns: BTreeSet::new(),
+    }
+  }
+
+  #[test]
+  fn schema_types_map_to_runtime_classes() {
+    let types = DtsTypeIndex::default();
+    let mut ctx = empty_ctx("app.main", &types);
+    let generics: Vec<Arc<str>> = vec![Arc::from("T")];
+    let signature = CalcitTypeAnnotation::Fn(Arc::new(CalcitFnTypeAnnotation {
+      generics: Arc::new(vec![]),
+      where_bounds: Arc::new(vec![]),
+      arg_types: vec![Arc::new(CalcitTypeAnnotation::TypeVar(Arc::from("T")))],
+      return_type: Arc::new(CalcitTypeAnnotation::Optional(Arc::new(CalcitTypeAnnotation::Number))),
+      fn_kind: SchemaKind::Fn,
+      rest_type: Some(Arc::new(CalcitTypeAnnotation::Dynamic)),
+      features: Arc::new(HashSet::new()),
+    }));
+    assert_eq!(ctx.ts_type(&signature, &generics), "(arg0: T, ...rest: unknown[]) => number | null");
+    assert_eq!(ctx.ts_type(&signature, &[]), "(arg0: unknown, ...rest: unknown[]) => number | null");
+    assert_eq!(
+      ctx.ts_type(&CalcitTypeAnnotation::Optional(Arc::new(signature.to_owned())), &[]),
+      "((arg0: unknown, ...rest: unknown[]) => number | null) | null"
+    );
+    assert_eq!(
+      ctx.ts_type(&CalcitTypeAnnotation::List(Arc::new(CalcitTypeAnnotation::Tag)), &[]),
+      "CalcitList | CalcitSliceList"
+    );
+    assert_eq!(ctx.ts_type(&CalcitTypeAnnotation::Dynamic, &[]), "unknown");
+    assert_eq!(
+      ctx.runtime_types.iter().copied().collect::<Vec<_>>(),
+      vec!["CalcitList", "CalcitSliceList"]
+    );
+  }
+
+  #[test]
+  fn struct_defs_become_struct_value_types() {
+    let types = DtsTypeIndex::default();
+    let mut ctx = empty_ctx("app.main", &types);
+    let point = CalcitStructDef {
+      name: EdnTag::new("Point"),
+      fields: Arc::new(vec![EdnTag::new("x"), EdnTag::new("y")]),
+      field_types: Arc::new(vec![
+        Arc::new(CalcitTypeAnnotation::Number),
+        Arc::new(CalcitTypeAnnotation::TypeVar(Arc::from("T"))),
+      ]),
+      generics: Arc::new(vec![Arc::from("T")]),
+      where_bounds: Arc::new(vec![]),
+      impls: vec![],
+    };
+    ctx.local_types.insert(
+      Arc::from("Point"),
+      TypeSite {
+        ns: Arc::from("app.main"),
+        ident: "Point".to_owned(),
+        generics: vec![Arc::from("T")],
+      },
+    );
+    let code = gen_type_def_decl(&mut ctx, "Point", &Calcit::StructDef(point.to_owned()));
+    assert_eq!(
+      code,
+      "export declare var Point: CalcitStructDef;\n/** struct Point {x: number, y: T} */\nexport type Point<T = unknown> = CalcitStructValue & { values: [number, T] };\n"
+    );
+    let applied = CalcitTypeAnnotation::Struct(Arc::new(point), Arc::new(vec![Arc::new(CalcitTypeAnnotation::String)]));
+    assert_eq!(ctx.ts_type(&applied, &[]), "Point<string>");
+  }
+
+  #[test]
+  fn types_from_other_namespaces_are_imported() {
+    let mut types = DtsTypeIndex::default();
+    let site = TypeSite {
+      ns: Arc::from("app.lib"),
+      ident: "Point".to_owned(),
+      generics: vec![],
+    };
+    types.by_name.insert(Arc::from("Point"), vec![site.to_owned()]);
+    types.by_path.insert((Arc::from("app.lib"), Arc::from("Point")), site);
+    for ns in ["app.a", "app.b"] {
+      let shape = TypeSite {
+        ns: Arc::from(ns),
+        ident: "Shape".to_owned(),
+        generics: vec![],
+      };
+      types.by_name.entry(Arc::from("Shape")).or_default().push(shape);
+    }
+
+    let mut ctx = empty_ctx("app.main", &types);
+    assert_eq!(
+      ctx.nominal_type_ref("Point", None, &[], &[], "CalcitStructValue"),
+      "$app_DOT_lib.Point"
+    );
+    assert_eq!(
+      ctx.nominal_type_ref(
+        "Other",
+        Some((Arc::from("app.lib"), Arc::from("Point"))),
+        &[],
+        &[],
+        "CalcitStructValue"
+      ),
+      "$app_DOT_lib.Point"
+    );
+    // defined by two namespaces, so the name alone can't pick one
+    assert_eq!(ctx.nominal_type_ref("Shape", None, &[], &[], "CalcitEnumValue"), "CalcitEnumValue");
+    assert_eq!(ctx.imported_ns.iter().map(|ns| &**ns).collect::<Vec<_>>(), vec!["app.lib"]);
+
+    let mut lib_ctx = empty_ctx("app.lib", &types);
+    assert_eq!(lib_ctx.nominal_type_ref("Point", None, &[], &[], "CalcitStructValue"), "Point");
+    assert!(lib_ctx.imported_ns.is_empty());
+  }
+}
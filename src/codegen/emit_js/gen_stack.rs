@@ -1,9 +1,8 @@
-use std::sync::{LazyLock, Mutex};
-
 use crate::calcit::Calcit;
 use crate::call_stack::{CalcitStack, CallStackList, StackKind};
+use crate::runtime_context::{ContextMutex, ContextSlot};
 
-static CALL_STACK: LazyLock<Mutex<rpds::ListSync<CalcitStack>>> = LazyLock::new(|| Mutex::new(rpds::List::new_sync()));
+static CALL_STACK: ContextSlot<ContextMutex<rpds::ListSync<CalcitStack>>> = ContextSlot::new(|ctx| &ctx.js_gen_stack);
 
 pub fn push_call_stack(ns: &str, def: &str, kind: StackKind, code: Calcit, args: &[Calcit]) {
   let mut stack = CALL_STACK.lock().expect("open call stack");
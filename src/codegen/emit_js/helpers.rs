@@ -1,8 +1,10 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use cirru_parser::Cirru;
 
+use super::paths::to_mjs_filename;
+
 pub(super) fn write_file_if_changed(filename: &Path, content: &str) -> Result<bool, String> {
   if filename.exists() && fs::read_to_string(filename).map_err(|e| e.to_string())? == content {
     return Ok(false);
@@ -11,6 +13,19 @@ pub(super) fn write_file_if_changed(filename: &Path, content: &str) -> Result<bo
   Ok(true)
 }
 
+/// Remove the `.mjs` and `.d.mts` outputs of a namespace that is no longer
+/// emitted, returning the files that existed.
+pub(super) fn remove_ns_outputs(emit_path: &Path, ns: &str) -> Result<Vec<PathBuf>, String> {
+  let mut removed = vec![];
+  for path in [emit_path.join(to_mjs_filename(ns)), emit_path.join(format!("{ns}.d.mts"))] {
+    if path.exists() {
+      fs::remove_file(&path).map_err(|e| format!("failed to remove stale {}: {e}", path.display()))?;
+      removed.push(path);
+    }
+  }
+  Ok(removed)
+}
+
 pub(super) fn is_js_unavailable_procs(name: &str) -> bool {
   matches!(
     name,
@@ -58,4 +73,20 @@ mod tests {
     let code = Cirru::List(vec![Cirru::Leaf("a".into()), Cirru::Leaf("b".into())]);
     assert_eq!(cirru_to_js(&code).expect("cirru js"), "[\"a\",\"b\"]");
   }
+
+  #[test]
+  fn removes_stale_outputs_of_one_namespace() {
+    let root = std::env::temp_dir().join(format!("calcit-js-stale-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).expect("create emit dir");
+    for name in ["app.lib.mjs", "app.lib.d.mts", "app.main.mjs"] {
+      fs::write(root.join(name), "").expect("write output");
+    }
+
+    let removed = remove_ns_outputs(&root, "app.lib").expect("remove outputs");
+    assert_eq!(removed, vec![root.join("app.lib.mjs"), root.join("app.lib.d.mts")]);
+    assert!(root.join("app.main.mjs").exists());
+    assert!(remove_ns_outputs(&root, "app.lib").expect("remove again").is_empty());
+    let _ = fs::remove_dir_all(&root);
+  }
 }
@@ -2,16 +2,18 @@
 
 use std::collections::{HashMap, HashSet};
 
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, LazyLock, RwLock};
+
+use crate::runtime_context::{ContextRwLock, ContextSlot};
 
 type ProgramCache = HashMap<Arc<str>, HashSet<Arc<str>>>;
 
 // track if it's the first compilation
-static FIRST_COMPILATION: AtomicBool = AtomicBool::new(true);
+static FIRST_COMPILATION: ContextSlot<AtomicBool> = ContextSlot::new(|ctx| &ctx.js_first_compilation);
 
 // caches program data for detecting incremental changes of libs
-static GLOBAL_PREVIOUS_PROGRAM_CACHES: LazyLock<RwLock<ProgramCache>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+static GLOBAL_PREVIOUS_PROGRAM_CACHES: ContextSlot<ContextRwLock<ProgramCache>> = ContextSlot::new(|ctx| &ctx.js_previous_program);
 
 pub fn lookup_prev_ns_cache(ns: &str) -> Option<HashSet<Arc<str>>> {
   let previous_program_caches = &GLOBAL_PREVIOUS_PROGRAM_CACHES.read().expect("load cache");
@@ -23,6 +25,13 @@ pub fn write_as_ns_cache(ns: &str, v: HashSet<Arc<str>>) {
   (*previous_program_caches).insert(ns.to_owned().into(), v);
 }
 
+/// Drop the cached defs of a namespace whose output was removed, so the next
+/// compilation writes it again.
+pub fn forget_ns_cache(ns: &str) {
+  let mut previous_program_caches = GLOBAL_PREVIOUS_PROGRAM_CACHES.write().expect("write cache");
+  (*previous_program_caches).remove(ns);
+}
+
 pub fn is_first_compilation() -> bool {
   FIRST_COMPILATION.load(Ordering::Relaxed)
 }
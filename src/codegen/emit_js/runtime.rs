@@ -1,5 +1,9 @@
+use std::collections::{BTreeSet, HashSet};
+
 use crate::calcit;
 
+use super::snippets::PROCS_HELPERS;
+
 #[inline(always)]
 pub(super) fn is_cirru_string(s: &str) -> bool {
   s.starts_with('|') || s.starts_with('"')
@@ -10,6 +14,72 @@ pub(super) fn get_proc_prefix(ns: &str) -> &str {
   if ns == calcit::CORE_NS { "$procs." } else { "$clt." }
 }
 
+/// Names of `@calcit/procs` read by emitted modules. With `--only-reachable`,
+/// `calcit.core.mjs` is emitted last and imports and re-exports only these.
+#[derive(Debug, Default)]
+pub(super) struct ProcsRefs {
+  /// read as `$procs.x` in `calcit.core.mjs`
+  members: BTreeSet<String>,
+  /// `calcit.core` definitions that forward to the proc of the same name
+  aliases: BTreeSet<String>,
+  /// read from `calcit.core.mjs` by other modules, as `$clt.x` or a named import
+  from_core: BTreeSet<String>,
+}
+
+impl ProcsRefs {
+  pub fn add_alias(&mut self, name: String) {
+    self.aliases.insert(name);
+  }
+
+  pub fn add_core_import(&mut self, name: String) {
+    self.from_core.insert(name);
+  }
+
+  /// Record what a non-core module reads through `$clt`.
+  pub fn scan_module(&mut self, code: &str) {
+    self.from_core.extend(prefixed_names(code, "$clt."));
+  }
+
+  /// Record what `calcit.core.mjs` reads through `$procs`.
+  pub fn scan_core(&mut self, code: &str) {
+    self.members.extend(prefixed_names(code, "$procs."));
+  }
+
+  /// Names other modules need from `calcit.core.mjs` that it does not define.
+  pub fn exports<'a>(&'a self, core_exports: &HashSet<String>) -> Vec<&'a str> {
+    self
+      .from_core
+      .iter()
+      .filter(|name| !core_exports.contains(*name))
+      .map(|name| name.as_str())
+      .collect()
+  }
+
+  /// Every name `calcit.core.mjs` imports from `@calcit/procs`.
+  pub fn imports<'a>(&'a self, core_exports: &HashSet<String>) -> Vec<&'a str> {
+    let mut names: BTreeSet<&str> = PROCS_HELPERS.into_iter().collect();
+    names.extend(self.members.iter().map(|name| name.as_str()));
+    names.extend(self.aliases.iter().map(|name| name.as_str()));
+    names.extend(self.exports(core_exports));
+    names.into_iter().collect()
+  }
+
+  pub fn members(&self) -> Vec<&str> {
+    self.members.iter().map(|name| name.as_str()).collect()
+  }
+}
+
+/// JS identifiers that directly follow `prefix` in generated code.
+fn prefixed_names<'a>(code: &'a str, prefix: &'a str) -> impl Iterator<Item = String> + 'a {
+  code.match_indices(prefix).filter_map(move |(at, _)| {
+    let rest = &code[at + prefix.len()..];
+    let end = rest
+      .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+      .unwrap_or(rest.len());
+    (end > 0).then(|| rest[..end].to_owned())
+  })
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -20,6 +90,31 @@ mod tests {
     assert_eq!(get_proc_prefix("app.main"), "$clt.");
   }
 
+  #[test]
+  fn procs_refs_leave_out_core_definitions() {
+    let mut refs = ProcsRefs::default();
+    refs.scan_module("import * as $clt from \"./calcit.core.mjs\";\n$clt.map_$x_($clt.number_$q_, $clt.arrayToList(xs));");
+    refs.scan_core("export function map_$x_(f, xs) { return $procs.type_of(xs) + $procs._$n_map(f, xs); }");
+    refs.add_alias("number_$q_".to_owned());
+    let core_exports = HashSet::from(["map_$x_".to_owned()]);
+    assert_eq!(refs.exports(&core_exports), vec!["arrayToList", "number_$q_"]);
+    assert_eq!(refs.members(), vec!["_$n_map", "type_of"]);
+    assert_eq!(
+      refs.imports(&core_exports),
+      vec![
+        "CalcitRecur",
+        "CalcitSliceList",
+        "CalcitSymbol",
+        "_$n_map",
+        "arrayToList",
+        "init_tags",
+        "listToArray",
+        "number_$q_",
+        "type_of"
+      ]
+    );
+  }
+
   #[test]
   fn cirru_string_detection() {
     assert!(is_cirru_string("|abc"));
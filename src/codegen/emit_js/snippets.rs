@@ -1,3 +1,5 @@
+use std::fmt::Write;
+
 use crate::builtins::meta::js_gensym;
 
 use super::runtime::get_proc_prefix;
@@ -95,16 +97,39 @@ pub fn tmpl_tail_recursion(
   )
 }
 
+/// Runtime helpers `calcit.core.mjs` uses without the `$procs.` prefix.
+pub const PROCS_HELPERS: [&str; 6] = [
+  "init_tags",
+  "arrayToList",
+  "listToArray",
+  "CalcitSliceList",
+  "CalcitSymbol",
+  "CalcitRecur",
+];
+
 pub fn tmpl_import_procs(name: String) -> String {
   format!(
     "
-import {{init_tags, arrayToList, listToArray, CalcitSliceList, CalcitSymbol, CalcitRecur}} from {name};
+import {{{}}} from {name};
 import * as $procs from {name};
 export * from {name};
 ",
+    PROCS_HELPERS.join(", ")
   )
 }
 
+/// Imports of `calcit.core.mjs` for `--only-reachable`: named imports of the
+/// procs in use, `$procs` rebuilt from the ones read as members, and named
+/// re-exports of the ones other modules read through `$clt`.
+pub fn tmpl_import_used_procs(name: String, imports: &[&str], members: &[&str], exports: &[&str]) -> String {
+  let mut code = format!("\nimport {{{}}} from {name};\n", imports.join(", "));
+  writeln!(code, "const $procs = {{{}}};", members.join(", ")).expect("write");
+  if !exports.is_empty() {
+    writeln!(code, "export {{{}}};", exports.join(", ")).expect("write");
+  }
+  code
+}
+
 pub fn tmpl_classes_registering() -> String {
   format!(
     "
@@ -144,6 +169,20 @@ mod tests {
     assert!(code.contains("init_tags"));
   }
 
+  #[test]
+  fn import_used_procs_names_every_binding() {
+    let code = tmpl_import_used_procs(
+      "\"@calcit/procs\"".to_owned(),
+      &["init_tags", "number_$q_", "type_of"],
+      &["type_of"],
+      &["number_$q_"],
+    );
+    assert_eq!(
+      code,
+      "\nimport {init_tags, number_$q_, type_of} from \"@calcit/procs\";\nconst $procs = {type_of};\nexport {number_$q_};\n"
+    );
+  }
+
   #[test]
   fn tags_init_uses_runtime_helper() {
     let code = tmpl_tags_init("[\"a\",\"b\"]", "$clt.");
@@ -11,6 +11,7 @@
 //! - Tag values (compiled to f64 integer constants)
 //! - Struct creation (`&%{}`) and field access (`&struct:nth`, `&struct:get`)
 //! - Tuple creation (`::`) and field access (`&enum:nth`)
+//! - Closures: `fn` values capturing locals, called via `call_indirect`
 //!
 //! All values are represented as f64 (matching Calcit's single numeric type).
 //! Booleans: true → 1.0, false/nil → 0.0.
@@ -21,6 +22,7 @@
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::rc::Rc;
 
 use wasm_encoder::{
   CodeSection, ConstExpr, ElementSection, Elements, ExportKind, ExportSection, Function, FunctionSection, GlobalSection, GlobalType,
@@ -49,11 +51,11 @@ use structs::{
   emit_struct_to_map, resolve_struct_ref, try_parse_defrecord_form,
 };
 
-/// Base offset — reserve first 16 bytes for bookkeeping.
-/// The actual heap start will be shifted when string literals occupy the
-/// initial segment (see `build_string_pool`).
-const HEAP_BASE: i32 = 16;
-/// Global index for the heap pointer (bump allocator).
+/// Base offset — the first bytes hold the nil guard and the GC bookkeeping
+/// area (see `gc.rs`). The actual heap start will be shifted when string
+/// literals occupy the initial segment (see `build_string_pool`).
+const HEAP_BASE: i32 = gc::GC_AREA_END;
+/// Global index for the heap pointer (top of the allocated heap).
 const HEAP_PTR_GLOBAL: u32 = 0;
 /// Magic marker written at `raw_base` of every heap allocation. Used by
 /// `type-of` to distinguish real pointers from raw f64 numbers that happen to
@@ -95,6 +97,12 @@ fn mem_arg_byte(offset: u64) -> wasm_encoder::MemArg {
 
 /// Emit a WASM binary module from the compiled program.
 /// Processes functions from all namespaces in the program.
+#[path = "emit_wasm/closures.rs"]
+mod closures;
+#[path = "emit_wasm/coverage.rs"]
+mod coverage;
+#[path = "emit_wasm/gc.rs"]
+mod gc;
 #[path = "emit_wasm/heap.rs"]
 mod heap;
 #[path = "emit_wasm/hof.rs"]
@@ -107,7 +115,17 @@ mod maps;
 mod sets;
 #[path = "emit_wasm/strings.rs"]
 mod strings;
+#[path = "emit_wasm/wasi.rs"]
+mod wasi;
+#[path = "emit_wasm/wit.rs"]
+mod wit;
 
+use closures::{
+  LambdaTable, build_closure_of_fn, compile_lifted_lambdas, emit_args_to_locals, emit_closure, emit_fn_value_call, lambda_used_as_value,
+};
+use coverage::{GapLog, record_call_edges};
+pub use coverage::{WasmCoverage, WasmGap, WasmGapKind, check_wasm_coverage};
+use gc::{build_export_entry, emit_direct_call, emit_gc_enter, emit_gc_leave, emit_gc_safepoint};
 #[allow(unused_imports)]
 pub(super) use heap::*; // makes heap fns available to sibling submodules via `use super::*`
 use hof::*;
@@ -115,8 +133,74 @@ use lists::*;
 use maps::*;
 use sets::*;
 use strings::*;
+use wasi::{build_start_fn, build_wasi_host_fns, wasi_imports};
+use wit::collect_wit_interface;
+
+/// Host environment the generated module is built for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WasmTarget {
+  /// Imports `math`/`io` host functions, provided by the JS loader.
+  #[default]
+  Js,
+  /// A command module for WASI runtimes: imports only
+  /// `wasi_snapshot_preview1` and exports `_start`, which runs the init function.
+  Wasi,
+}
+
+impl std::str::FromStr for WasmTarget {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "js" => Ok(WasmTarget::Js),
+      "wasi" => Ok(WasmTarget::Wasi),
+      _ => Err(format!("unknown WASM target `{s}`, expected `js` or `wasi`")),
+    }
+  }
+}
+
+/// Options for [`emit_wasm`], set from `cr-wasm` flags.
+#[derive(Clone, Debug, Default)]
+pub struct WasmOptions {
+  pub target: WasmTarget,
+  /// Also write `program.wit` describing the `defwasm-export` definitions,
+  /// and export canonical ABI adapters for them.
+  pub wit: bool,
+}
+
+pub fn emit_wasm(init_ns: &str, init_def: &str, emit_path: &str, options: &WasmOptions) -> Result<(), String> {
+  let module = compile_module(init_ns, init_def, options, None)?;
+
+  // Write output
+  let out_path = Path::new(emit_path);
+  if !out_path.exists() {
+    fs::create_dir_all(out_path).map_err(|e| format!("failed to create dir: {e}"))?;
+  }
+  let wasm_file = out_path.join("program.wasm");
+  fs::write(&wasm_file, &module.wasm_bytes).map_err(|e| format!("failed to write WASM: {e}"))?;
+  println!("wrote WASM to: {}", wasm_file.display());
+  if let Some(interface) = &module.wit_interface {
+    if interface.is_empty() {
+      eprintln!("[wasm] --wit: no `defwasm-export` definitions, writing an empty interface");
+    }
+    let wit_file = out_path.join("program.wit");
+    fs::write(&wit_file, interface.render()).map_err(|e| format!("failed to write WIT: {e}"))?;
+    println!("wrote WIT to: {}", wit_file.display());
+  }
+
+  Ok(())
+}
+
+struct CompiledModule {
+  wasm_bytes: Vec<u8>,
+  wit_interface: Option<wit::WitInterface>,
+}
 
-pub fn emit_wasm(init_ns: &str, emit_path: &str) -> Result<(), String> {
+/// Compile the whole program into a module. With `gaps`, unsupported
+/// constructs and failing definitions are logged there instead of aborting
+/// (see `coverage.rs`).
+fn compile_module(init_ns: &str, init_def: &str, options: &WasmOptions, gaps: Option<GapLog>) -> Result<CompiledModule, String> {
+  let target = options.target;
   let program_data = program::clone_compiled_program_snapshot()?;
 
   // First pass: extract all function signatures from all namespaces
@@ -164,8 +248,12 @@ pub fn emit_wasm(init_ns: &str, emit_path: &str) -> Result<(), String> {
 
   // Build the import table before assigning user function indices. Built-in imports
   // stay first so internal lowering keeps its stable indices; user declarations
-  // append after them.
-  let mut host_imports = HOST_IMPORTS.to_vec();
+  // append after them. Under WASI the built-in host functions become in-module
+  // shims and only the WASI system calls are imported.
+  let mut host_imports = match target {
+    WasmTarget::Js => HOST_IMPORTS.to_vec(),
+    WasmTarget::Wasi => wasi_imports(),
+  };
   let mut wasm_import_names: HashMap<String, u32> = HashMap::new();
   let mut wasm_import_arities: HashMap<String, u32> = HashMap::new();
   for &ns in &ns_order {
@@ -181,11 +269,7 @@ pub fn emit_wasm(init_ns: &str, emit_path: &str) -> Result<(), String> {
       })?;
       let arity = wasm_import_arity(&args).map_err(|reason| format!("[wasm] invalid import declaration {ns}/{def_name}: {reason}"))?;
       let index = host_imports.len() as u32;
-      host_imports.push(HostImport {
-        module,
-        name,
-        arity: arity as usize,
-      });
+      host_imports.push(HostImport::calcit(&module, &name, arity as usize));
       let qualified = format!("{ns}/{def_name}");
       wasm_import_names.insert(qualified.clone(), index);
       wasm_import_names.insert(def_name.to_string(), index);
@@ -195,32 +279,107 @@ pub fn emit_wasm(init_ns: &str, emit_path: &str) -> Result<(), String> {
   }
   let num_imports = host_imports.len() as u32;
 
+  // Scan for defatom definitions — each gets a mutable WASM global (f64).
+  let mut atom_initial_values: Vec<f64> = Vec::new();
+  let mut atom_globals: HashMap<String, u32> = HashMap::new();
+  // Collect top-level value defs so imported constants can be emitted as expressions.
+  let mut value_imports: HashMap<String, Calcit> = HashMap::new();
+  for &ns in &ns_order {
+    let Some(file_info) = program_data.get(ns) else {
+      continue;
+    };
+    for (def_name, compiled) in &file_info.defs {
+      let qualified = format!("{ns}/{def_name}");
+      if matches!(compiled.kind, program::CompiledDefKind::Value | program::CompiledDefKind::LazyValue) {
+        value_imports.insert(qualified.clone(), compiled.preprocessed_code.to_owned());
+      }
+      if let crate::calcit::Calcit::List(xs) = &compiled.preprocessed_code
+        && matches!(
+          xs.first(),
+          Some(crate::calcit::Calcit::Syntax(crate::calcit::CalcitSyntax::Defatom, _))
+        )
+      {
+        // Global 0 is the heap pointer; atoms follow it.
+        let global_idx = 1 + atom_initial_values.len() as u32;
+        atom_globals.insert(qualified, global_idx);
+        // Determine initial value from 3rd node (index 2)
+        let init_val = match xs.get(2) {
+          Some(crate::calcit::Calcit::Bool(true)) => 1.0,
+          Some(crate::calcit::Calcit::Number(n)) => *n,
+          _ => 0.0, // false / nil / complex init → default 0.0
+        };
+        atom_initial_values.push(init_val);
+      }
+    }
+  }
+
   // Collect tags early — needed to embed the string type tag in the __str_new helper.
-  let tag_index = collect_all_tags_from(&fn_defs);
+  let mut tag_index = collect_all_tags_from(&fn_defs);
+  // WIT adapters build structs for records, so record struct names need tags
+  // even when no compiled code mentions them.
+  let wit_interface = if options.wit {
+    Some(collect_wit_interface(&program_data, &ns_order, init_ns)?)
+  } else {
+    None
+  };
+  for struct_name in wit_interface.iter().flat_map(|interface| interface.struct_names()) {
+    if !tag_index.contains_key(struct_name) {
+      let next_id = tag_index.values().max().copied().unwrap_or(0) + 1;
+      tag_index.insert(struct_name.to_string(), next_id);
+    }
+  }
   eprintln!("[wasm] tag index: {tag_index:?}");
 
+  // Atoms are GC roots, so the heap manager needs their global indices.
+  let atom_global_indices: Vec<u32> = (1..=atom_initial_values.len() as u32).collect();
+
   let (mut compiled_fns, mut runtime_fn_index) = build_runtime_fns(
     num_imports,
     *tag_index.get("map").expect("map tag must exist") as i32,
     *tag_index.get("list").expect("list tag must exist") as i32,
     *tag_index.get("string").expect("string tag must exist") as i32,
+    &atom_global_indices,
   );
+  let alloc_idx = *runtime_fn_index.get("__rt_alloc").expect("alloc helper");
 
   // Emit __str_new(src_ptr: i32, byte_len: i32) → f64 now that we know the string tag id.
   // This is a runtime helper exported for JS FFI: copies bytes into a tagged heap string.
   let str_tag_id = *tag_index.get("string").expect("string tag must exist") as i32;
   let str_new_idx = num_imports + compiled_fns.len() as u32;
   runtime_fn_index.insert("__str_new".to_string(), str_new_idx);
-  compiled_fns.push(build_str_new_fn(str_tag_id));
+  compiled_fns.push(build_str_new_fn(
+    str_tag_id,
+    *runtime_fn_index.get("__rt_alloc_uninit").expect("alloc helper"),
+  ));
 
   // Pad helpers (need str_tag_id for heap allocation).
   let str_pad_left_idx = num_imports + compiled_fns.len() as u32;
   runtime_fn_index.insert("__rt_str_pad_left".to_string(), str_pad_left_idx);
-  compiled_fns.push(build_str_pad_left_fn(str_tag_id));
+  compiled_fns.push(build_str_pad_left_fn(str_tag_id, alloc_idx));
 
   let str_pad_right_idx = num_imports + compiled_fns.len() as u32;
   runtime_fn_index.insert("__rt_str_pad_right".to_string(), str_pad_right_idx);
-  compiled_fns.push(build_str_pad_right_fn(str_tag_id));
+  compiled_fns.push(build_str_pad_right_fn(str_tag_id, alloc_idx));
+
+  let closure_of_idx = num_imports + compiled_fns.len() as u32;
+  runtime_fn_index.insert("__rt_closure_of".to_string(), closure_of_idx);
+  compiled_fns.push(build_closure_of_fn(*tag_index.get("fn").expect("fn tag must exist") as i32));
+
+  // Built-in host functions are called by key, e.g. `io/log_value`.
+  match target {
+    WasmTarget::Js => {
+      for (i, imp) in HOST_IMPORTS.iter().enumerate() {
+        runtime_fn_index.insert(imp.key(), i as u32);
+      }
+    }
+    WasmTarget::Wasi => {
+      let base = num_imports + compiled_fns.len() as u32;
+      for (i, (key, func)) in build_wasi_host_fns(base, alloc_idx, str_tag_id).into_iter().enumerate() {
+        runtime_fn_index.insert(key, base + i as u32);
+        compiled_fns.push(func);
+      }
+    }
+  }
 
   let runtime_fn_count = compiled_fns.len() as u32;
   let mut export_name_counts: HashMap<String, usize> = HashMap::new();
@@ -264,39 +423,6 @@ pub fn emit_wasm(init_ns: &str, emit_path: &str) -> Result<(), String> {
   // Build string literal pool: assigns each unique string a memory offset.
   let (string_pool, string_data_segment, heap_start) = build_string_pool(&fn_defs, &tag_index);
 
-  // Scan for defatom definitions — each gets a mutable WASM global (f64).
-  let mut atom_initial_values: Vec<f64> = Vec::new();
-  let mut atom_globals: HashMap<String, u32> = HashMap::new();
-  // Collect top-level value defs so imported constants can be emitted as expressions.
-  let mut value_imports: HashMap<String, Calcit> = HashMap::new();
-  for &ns in &ns_order {
-    let Some(file_info) = program_data.get(ns) else {
-      continue;
-    };
-    for (def_name, compiled) in &file_info.defs {
-      let qualified = format!("{ns}/{def_name}");
-      if matches!(compiled.kind, program::CompiledDefKind::Value | program::CompiledDefKind::LazyValue) {
-        value_imports.insert(qualified.clone(), compiled.preprocessed_code.to_owned());
-      }
-      if let crate::calcit::Calcit::List(xs) = &compiled.preprocessed_code
-        && matches!(
-          xs.first(),
-          Some(crate::calcit::Calcit::Syntax(crate::calcit::CalcitSyntax::Defatom, _))
-        )
-      {
-        let global_idx = atom_initial_values.len() as u32;
-        atom_globals.insert(qualified, global_idx);
-        // Determine initial value from 3rd node (index 2)
-        let init_val = match xs.get(2) {
-          Some(crate::calcit::Calcit::Bool(true)) => 1.0,
-          Some(crate::calcit::Calcit::Number(n)) => *n,
-          _ => 0.0, // false / nil / complex init → default 0.0
-        };
-        atom_initial_values.push(init_val);
-      }
-    }
-  }
-
   let env = WasmCompileEnv {
     fn_index,
     fn_arity,
@@ -308,6 +434,8 @@ pub fn emit_wasm(init_ns: &str, emit_path: &str) -> Result<(), String> {
     atom_globals,
     value_imports,
     fn_table_index,
+    lambdas: LambdaTable::new(fn_defs.len() as u32),
+    gaps,
   };
 
   // Second pass: compile. If a function fails, we still reserve its slot
@@ -322,16 +450,20 @@ pub fn emit_wasm(init_ns: &str, emit_path: &str) -> Result<(), String> {
     } else {
       def_name.clone()
     };
+    let qualified = format!("{ns}/{def_name}");
     // Try custom implementation (avoids skip for known-good WASM rewrites).
     let result = try_custom_def_impl(ns, def_name, &export_name, args, &env)
-      .unwrap_or_else(|| compile_fn(def_name, &export_name, args, body, &env));
+      .unwrap_or_else(|| compile_fn(&qualified, &export_name, args, body, &env));
     match result {
       Ok(func) => compiled_fns.push(func),
       Err(e) => {
-        if explicit_export {
+        if let Some(log) = &env.gaps {
+          log.record_failure(&qualified, &e);
+        } else if explicit_export {
           return Err(format!("[wasm] exported function {ns}/{def_name} is not compilable: {e}"));
+        } else {
+          eprintln!("[wasm] skipping {ns}/{def_name}: {e}");
         }
-        eprintln!("[wasm] skipping {ns}/{def_name}: {e}");
         let (arity, _) = compute_fn_arity(args);
         compiled_fns.push(CompiledFn {
           export_name: Some(export_name),
@@ -344,10 +476,59 @@ pub fn emit_wasm(init_ns: &str, emit_path: &str) -> Result<(), String> {
     }
   }
 
+  // Lambdas used as values take the table slots after the top-level functions.
+  compile_lifted_lambdas(&env, &mut compiled_fns);
+
+  if let Some(log) = &env.gaps {
+    let mut owners: Vec<Rc<str>> = fn_defs.iter().map(|(ns, name, _, _)| Rc::from(format!("{ns}/{name}"))).collect();
+    owners.extend(env.lambdas.owners());
+    record_call_edges(
+      log,
+      &compiled_fns[runtime_fn_count as usize..],
+      &owners,
+      fn_defs.len(),
+      num_imports + runtime_fn_count,
+    );
+  }
+
+  if target == WasmTarget::Wasi {
+    let init_fn = format!("{init_ns}/{init_def}");
+    let init_idx = *env
+      .fn_index
+      .get(&init_fn)
+      .ok_or_else(|| format!("[wasm] init function {init_fn} not found for the WASI entry"))?;
+    if env.fn_arity.get(&init_fn).copied().unwrap_or(0) != 0 || env.fn_has_rest.contains_key(&init_fn) {
+      return Err(format!(
+        "[wasm] init function {init_fn} must take no arguments to be the WASI entry"
+      ));
+    }
+    compiled_fns.push(build_start_fn(init_idx, env.runtime_fn_index["__rt_gc_reset"]));
+  }
+
+  if let Some(interface) = &wit_interface {
+    compiled_fns.extend(interface.build_adapters(&env)?);
+  }
+
+  // Hosts call top-level functions through entry wrappers that reset the GC
+  // frame bookkeeping; calls inside the module keep the original indices.
+  let gc_reset_idx = env.runtime_fn_index["__rt_gc_reset"];
+  let user_defs = runtime_fn_count as usize..runtime_fn_count as usize + fn_defs.len();
+  let entries: Vec<CompiledFn> = compiled_fns[user_defs.clone()]
+    .iter_mut()
+    .zip(user_defs)
+    .filter_map(|(func, i)| build_export_entry(func, num_imports + i as u32, gc_reset_idx))
+    .collect();
+  compiled_fns.extend(entries);
+
   if compiled_fns.is_empty() {
     return Err("no functions could be compiled to WASM".into());
   }
 
+  // Keep every heap pointer above the largest table slot, so `__rt_closure_of`
+  // never mistakes a bare slot for a closure object.
+  let table_len = compiled_fns.len() as i32 - runtime_fn_count as i32;
+  let heap_start = heap_start.max((table_len + 7) & !7);
+
   // Build module using wasm-encoder
   let wasm_bytes = build_wasm_module(
     &compiled_fns,
@@ -359,16 +540,7 @@ pub fn emit_wasm(init_ns: &str, emit_path: &str) -> Result<(), String> {
     runtime_fn_count,
   )?;
 
-  // Write output
-  let out_path = Path::new(emit_path);
-  if !out_path.exists() {
-    fs::create_dir_all(out_path).map_err(|e| format!("failed to create dir: {e}"))?;
-  }
-  let wasm_file = out_path.join("program.wasm");
-  fs::write(&wasm_file, &wasm_bytes).map_err(|e| format!("failed to write WASM: {e}"))?;
-  println!("wrote WASM to: {}", wasm_file.display());
-
-  Ok(())
+  Ok(CompiledModule { wasm_bytes, wit_interface })
 }
 
 /// Intermediate representation of a compiled function before encoding.
@@ -397,6 +569,10 @@ struct WasmCompileEnv {
   value_imports: HashMap<String, Calcit>,
   /// qualified function name → funcref table slot index (0-based, for call_indirect)
   fn_table_index: HashMap<String, u32>,
+  /// Lambdas lifted into their own table slots (see `closures.rs`).
+  lambdas: LambdaTable,
+  /// Set while collecting a coverage report (see `coverage.rs`).
+  gaps: Option<GapLog>,
 }
 
 fn extract_fn_parts(code: &Calcit) -> Result<(CalcitFnArgs, Vec<Calcit>), String> {
@@ -508,10 +684,27 @@ struct WasmGenCtx {
   /// inline lambda locals: local name → (params, body)
   /// When a let-binding holds a `fn`/`defn` form, it's stored here for inlining at call sites.
   lambda_locals: HashMap<String, (Vec<String>, Vec<Calcit>)>,
+  /// Whether the expression about to be emitted is in tail position of the
+  /// function, i.e. nothing stays on the operand stack around it. Consumed by
+  /// `emit_expr`; only `if`/`let`/bodies/calls hand it down to a sub-expression.
+  tail_position: bool,
+  /// Whether nothing of this frame stays on the operand stack below the
+  /// expression about to be emitted, so a direct call there may spill the
+  /// frame's locals (see `gc.rs`). Handed down like `tail_position`, and
+  /// additionally to body statements, `if` conditions and `let` initializers.
+  stack_empty: bool,
+  /// i32 local holding the shadow frame of a spilling call, once needed.
+  spill_frame: Option<u32>,
+  /// Lambdas lifted into their own table slots, shared by all functions.
+  lambdas: LambdaTable,
+  /// Qualified `ns/def` being compiled; lifted lambdas keep their enclosing def.
+  owner: Rc<str>,
+  /// Set while collecting a coverage report (see `coverage.rs`).
+  gaps: Option<GapLog>,
 }
 
 impl WasmGenCtx {
-  fn new(num_params: u32, env: WasmCompileEnv) -> Self {
+  fn new(owner: Rc<str>, num_params: u32, env: WasmCompileEnv) -> Self {
     WasmGenCtx {
       locals: HashMap::new(),
       extra_locals: Vec::new(),
@@ -531,6 +724,12 @@ impl WasmGenCtx {
       value_imports: env.value_imports,
       fn_table_index: env.fn_table_index,
       lambda_locals: HashMap::new(),
+      tail_position: false,
+      stack_empty: false,
+      spill_frame: None,
+      lambdas: env.lambdas,
+      owner,
+      gaps: env.gaps,
     }
   }
 
@@ -788,13 +987,15 @@ fn try_custom_def_impl(
   type BodyFn = fn(&mut WasmGenCtx) -> Result<(), String>;
   let arity: u32 = 2;
   let build = |body_fn: BodyFn, en: &str, env: &WasmCompileEnv| -> Result<CompiledFn, String> {
-    let mut ctx = WasmGenCtx::new(arity, env.clone());
+    let mut ctx = WasmGenCtx::new(Rc::from(format!("{ns}/{def_name}")), arity, env.clone());
     // Register param locals 0 and 1
     ctx.locals.insert("__p0__".into(), 0);
     ctx.locals.insert("__p1__".into(), 1);
     ctx.arg_indices.push(0);
     ctx.arg_indices.push(1);
+    emit_gc_enter(&mut ctx);
     body_fn(&mut ctx)?;
+    emit_gc_leave(&mut ctx);
     Ok(CompiledFn {
       export_name: Some(en.to_owned()),
       params: vec![ValType::F64; arity as usize],
@@ -814,7 +1015,7 @@ fn try_custom_def_impl(
 }
 
 fn compile_fn(
-  _name: &str,
+  owner: &str,
   export_name: &str,
   args: &CalcitFnArgs,
   body: &[Calcit],
@@ -854,7 +1055,7 @@ fn compile_fn(
   }
 
   let arity = param_names.len();
-  let mut ctx = WasmGenCtx::new(arity as u32, env.clone());
+  let mut ctx = WasmGenCtx::new(Rc::from(owner), arity as u32, env.clone());
 
   // Register parameter locals
   for (i, pname) in param_names.iter().enumerate() {
@@ -862,17 +1063,7 @@ fn compile_fn(
     ctx.arg_indices.push(i as u32);
   }
 
-  // Check if body uses recur
-  ctx.uses_recur = body.iter().any(check_uses_recur);
-
-  if ctx.uses_recur {
-    // loop $recur (result f64) ... end
-    ctx.emit(Instruction::Loop(wasm_encoder::BlockType::Result(ValType::F64)));
-    emit_body(&mut ctx, body)?;
-    ctx.emit(Instruction::End); // end loop
-  } else {
-    emit_body(&mut ctx, body)?;
-  }
+  emit_fn_body(&mut ctx, body)?;
 
   Ok(CompiledFn {
     export_name: Some(export_name.to_owned()),
@@ -883,6 +1074,26 @@ fn compile_fn(
   })
 }
 
+/// Emit a function body with its GC prologue/epilogue, wrapped in the `recur`
+/// loop when needed. Parameters must already be registered in `ctx`.
+fn emit_fn_body(ctx: &mut WasmGenCtx, body: &[Calcit]) -> Result<(), String> {
+  ctx.uses_recur = body.iter().any(check_uses_recur);
+
+  emit_gc_enter(ctx);
+  ctx.tail_position = true;
+  ctx.stack_empty = true;
+  if ctx.uses_recur {
+    // loop $recur (result f64) ... end
+    ctx.emit(Instruction::Loop(wasm_encoder::BlockType::Result(ValType::F64)));
+    emit_body(ctx, body)?;
+    ctx.emit(Instruction::End); // end loop
+  } else {
+    emit_body(ctx, body)?;
+  }
+  emit_gc_leave(ctx);
+  Ok(())
+}
+
 fn check_uses_recur(expr: &Calcit) -> bool {
   match expr {
     Calcit::Proc(CalcitProc::Recur) => true,
@@ -899,11 +1110,15 @@ fn check_uses_recur(expr: &Calcit) -> bool {
 
 /// Emit instructions for a sequence of expressions (last is the return value).
 fn emit_body(ctx: &mut WasmGenCtx, exprs: &[Calcit]) -> Result<(), String> {
+  let tail = std::mem::take(&mut ctx.tail_position);
+  let stack_empty = std::mem::take(&mut ctx.stack_empty);
   if exprs.is_empty() {
     ctx.emit(f64_const(0.0));
     return Ok(());
   }
   for (i, expr) in exprs.iter().enumerate() {
+    ctx.tail_position = tail && i == exprs.len() - 1;
+    ctx.stack_empty = stack_empty;
     emit_expr(ctx, expr)?;
     if i < exprs.len() - 1 {
       ctx.emit(Instruction::Drop);
@@ -919,11 +1134,14 @@ fn emit_body(ctx: &mut WasmGenCtx, exprs: &[Calcit]) -> Result<(), String> {
 ///   so that `recur` correctly jumps back to the loop start.
 /// - Outer params of the same name are shadowed and restored after.
 fn emit_inline_iife(ctx: &mut WasmGenCtx, params: &[String], body: &[Calcit], init_args: &[Calcit]) -> Result<(), String> {
+  let tail = std::mem::take(&mut ctx.tail_position);
+  let stack_empty = std::mem::take(&mut ctx.stack_empty);
   // Evaluate all init args and store in fresh locals (before modifying ctx.locals).
   let mut param_locals: Vec<u32> = Vec::new();
   for (i, _param_name) in params.iter().enumerate() {
     let tmp = ctx.alloc_local();
     if i < init_args.len() {
+      ctx.stack_empty = stack_empty;
       emit_expr(ctx, &init_args[i])?;
     } else {
       ctx.emit(f64_const(0.0)); // nil for missing args
@@ -947,12 +1165,16 @@ fn emit_inline_iife(ctx: &mut WasmGenCtx, params: &[String], body: &[Calcit], in
     ctx.block_depth = 0;
 
     ctx.emit(Instruction::Loop(wasm_encoder::BlockType::Result(ValType::F64)));
+    ctx.tail_position = tail;
+    ctx.stack_empty = stack_empty;
     emit_body(ctx, body)?;
     ctx.emit(Instruction::End); // end loop
 
     ctx.block_depth = old_block_depth;
     ctx.arg_indices = old_arg_indices;
   } else {
+    ctx.tail_position = tail;
+    ctx.stack_empty = stack_empty;
     emit_body(ctx, body)?;
   }
 
@@ -972,6 +1194,8 @@ fn emit_inline_iife(ctx: &mut WasmGenCtx, params: &[String], body: &[Calcit], in
 
 /// Emit instructions for a single Calcit expression.
 fn emit_expr(ctx: &mut WasmGenCtx, expr: &Calcit) -> Result<(), String> {
+  let tail = std::mem::take(&mut ctx.tail_position);
+  let stack_empty = std::mem::take(&mut ctx.stack_empty);
   match expr {
     Calcit::Number(n) => {
       ctx.emit(f64_const(*n));
@@ -1003,10 +1227,22 @@ fn emit_expr(ctx: &mut WasmGenCtx, expr: &Calcit) -> Result<(), String> {
     }
     Calcit::Local(local) => {
       let name = &*local.sym;
-      let idx = *ctx.locals.get(name).ok_or_else(|| format!("undefined local variable: {name}"))?;
-      ctx.emit(Instruction::LocalGet(idx));
+      match ctx.locals.get(name) {
+        Some(&idx) => ctx.emit(Instruction::LocalGet(idx)),
+        None => {
+          // An inline lambda handed on as an argument has no local of its own.
+          let (params, body) = ctx
+            .lambda_locals
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("undefined local variable: {name}"))?;
+          emit_closure(ctx, params, body)?;
+        }
+      }
     }
     Calcit::List(xs) if !xs.is_empty() => {
+      ctx.tail_position = tail;
+      ctx.stack_empty = stack_empty;
       emit_call_expr(ctx, xs)?;
     }
     // `do` appears as a bare (non-call) expression when used as a body sequencer in defn.
@@ -1038,6 +1274,7 @@ fn emit_expr(ctx: &mut WasmGenCtx, expr: &Calcit) -> Result<(), String> {
         .or_else(|| ctx.fn_table_index.get(import.def.as_ref()))
       {
         // Function reference used as a value — encode as f64 table slot index.
+        ctx.note_fn_ref(&qualified);
         ctx.emit(f64_const(slot as f64));
       } else if let Some(value_expr) = ctx.value_imports.get(&qualified).cloned() {
         // Imported top-level def value (e.g. a string constant). Inline its expression.
@@ -1057,8 +1294,16 @@ fn emit_expr(ctx: &mut WasmGenCtx, expr: &Calcit) -> Result<(), String> {
         .ok_or_else(|| format!("string literal not found in pool: {s}"))?;
       ctx.emit(f64_const(*ptr as f64));
     }
-    Calcit::Struct(_) => return Err("Struct literals not supported in WASM codegen (use constructor)".into()),
-    Calcit::Enum(_) => return Err("Enum literals not supported in WASM codegen (use constructor)".into()),
+    Calcit::Struct(_) => ctx.unsupported(
+      WasmGapKind::Value,
+      "struct literal",
+      "Struct literals not supported in WASM codegen (use constructor)".into(),
+    )?,
+    Calcit::Enum(_) => ctx.unsupported(
+      WasmGapKind::Value,
+      "enum literal",
+      "Enum literals not supported in WASM codegen (use constructor)".into(),
+    )?,
     // Function value (Fn with def_ref) — encode as f64 table slot index for call_indirect.
     Calcit::Fn { info, .. } => {
       if let Some(def_ref) = &info.def_ref {
@@ -1069,11 +1314,15 @@ fn emit_expr(ctx: &mut WasmGenCtx, expr: &Calcit) -> Result<(), String> {
           .or_else(|| ctx.fn_table_index.get(def_ref.def_name.as_ref()))
           .copied()
           .ok_or_else(|| format!("fn value not in table: {qualified}"))?;
+        ctx.note_fn_ref(&qualified);
         ctx.emit(f64_const(slot as f64));
       } else {
-        // Anonymous inline closure (no def_ref) — captured upvalues not representable.
-        eprintln!("[wasm] anonymous closure (no def_ref): emitting nil placeholder");
-        ctx.emit(f64_const(0.0));
+        let (params, body) = try_extract_inline_lambda(expr).ok_or("empty lambda used as a value")?;
+        if matches!(info.args.as_ref(), CalcitFnArgs::MarkedArgs(labels) if labels.iter().any(|l| !matches!(l, CalcitArgLabel::Idx(_))))
+        {
+          return Err("lambdas with optional or rest params are not supported as WASM values".into());
+        }
+        emit_closure(ctx, params, body)?;
       }
     }
     // `[]` used as a bare expression (not in call position) evaluates to an empty list.
@@ -1085,21 +1334,37 @@ fn emit_expr(ctx: &mut WasmGenCtx, expr: &Calcit) -> Result<(), String> {
     Calcit::Proc(CalcitProc::NativeMap) => {
       emit_map_new(ctx, &[])?;
     }
-    _ => return Err(format!("unsupported WASM expression: {expr}")),
+    _ => {
+      let kind = match crate::builtins::meta::type_of(std::slice::from_ref(expr)) {
+        Ok(Calcit::Tag(tag)) => tag.ref_str().to_string(),
+        _ => "unknown".to_string(),
+      };
+      ctx.unsupported(WasmGapKind::Value, &kind, format!("unsupported WASM expression: {expr}"))?
+    }
   }
   Ok(())
 }
 
 /// Emit instructions for a call expression.
 fn emit_call_expr(ctx: &mut WasmGenCtx, xs: &crate::calcit::CalcitList) -> Result<(), String> {
+  let tail = std::mem::take(&mut ctx.tail_position);
+  let stack_empty = std::mem::take(&mut ctx.stack_empty);
   let head = &xs[0];
   let args_list: Vec<Calcit> = xs.drop_left().to_vec();
 
   match head {
     Calcit::Syntax(syn, _) => match syn {
       CalcitSyntax::CallSpread => emit_call_spread(ctx, &args_list),
-      CalcitSyntax::If => emit_if(ctx, &args_list),
-      CalcitSyntax::CoreLet => emit_let(ctx, &args_list),
+      CalcitSyntax::If => {
+        ctx.tail_position = tail;
+        ctx.stack_empty = stack_empty;
+        emit_if(ctx, &args_list)
+      }
+      CalcitSyntax::CoreLet => {
+        ctx.tail_position = tail;
+        ctx.stack_empty = stack_empty;
+        emit_let(ctx, &args_list)
+      }
       CalcitSyntax::Match => emit_match(ctx, &args_list),
       CalcitSyntax::HintFn => {
         // hint-fn is metadata-only; emit nothing (0.0 placeholder)
@@ -1119,15 +1384,16 @@ fn emit_call_expr(ctx: &mut WasmGenCtx, xs: &crate::calcit::CalcitList) -> Resul
         }
         emit_expr(ctx, &args_list[0])
       }
-      CalcitSyntax::ParseCirruEdnAs | CalcitSyntax::DecodeMapAs => Err(format!("{syn} is not yet supported in WASM codegen")),
+      CalcitSyntax::ParseCirruEdnAs | CalcitSyntax::DecodeMapAs => ctx.unsupported(
+        WasmGapKind::Syntax,
+        syn.as_ref(),
+        format!("{syn} is not yet supported in WASM codegen"),
+      ),
       CalcitSyntax::Defn => {
         // A `fn`/`defn` form in value position creates a closure capturing outer variables.
-        // Closures with captured upvalues can't be represented as static WASM function
-        // indices in the current codegen. Emit nil as a placeholder so the outer function
-        // still compiles; callers that invoke the returned "closure" will receive nil.
-        eprintln!("[wasm] closure-as-value: emitting nil placeholder for nested fn/defn");
-        ctx.emit(f64_const(0.0));
-        Ok(())
+        let (params, body) =
+          try_extract_inline_lambda(&Calcit::List(std::sync::Arc::new(xs.clone()))).ok_or("empty lambda used as a value")?;
+        emit_closure(ctx, params, body)
       }
       CalcitSyntax::Quote | CalcitSyntax::Quasiquote => {
         // Quote creates a runtime value (quoted symbol/expression).
@@ -1159,16 +1425,25 @@ fn emit_call_expr(ctx: &mut WasmGenCtx, xs: &crate::calcit::CalcitList) -> Resul
         ctx.emit(Instruction::LocalGet(tmp));
         Ok(())
       }
-      _ => Err(format!("unsupported syntax in WASM: {syn}")),
+      _ => ctx.unsupported(WasmGapKind::Syntax, syn.as_ref(), format!("unsupported syntax in WASM: {syn}")),
     },
-    Calcit::Proc(proc) => emit_proc_call(ctx, proc, &args_list),
+    Calcit::Proc(proc) => {
+      ctx.tail_position = tail && matches!(proc, CalcitProc::Recur);
+      emit_proc_call(ctx, proc, &args_list)
+    }
     Calcit::Method(name, kind) => match kind {
       MethodKind::Invoke(_) => emit_method_invoke(ctx, name.as_ref(), &args_list),
-      _ => Err(format!("unsupported method in WASM: .{name}")),
+      _ => ctx.unsupported(
+        WasmGapKind::Method,
+        &format!(".{name}"),
+        format!("unsupported method in WASM: .{name}"),
+      ),
     },
     Calcit::Import(import) => {
       // `do` is a sequencing form in calcit.core — emit all args, return last
       if import.def.as_ref() == "do" {
+        ctx.tail_position = tail;
+        ctx.stack_empty = stack_empty;
         return emit_body(ctx, &args_list);
       }
       // High-level set wrappers (union/difference/include) use reduce+lambda which can't be
@@ -1256,20 +1531,16 @@ fn emit_call_expr(ctx: &mut WasmGenCtx, xs: &crate::calcit::CalcitList) -> Resul
         .or_else(|| ctx.fn_has_rest.get(import.def.as_ref()))
         .copied();
       emit_call_args(ctx, &args_list, target_arity, rest_fixed)?;
-      ctx.emit(Instruction::Call(fn_idx));
+      emit_direct_call(ctx, fn_idx, tail, stack_empty);
       Ok(())
     }
     Calcit::Symbol { sym, .. } => {
       let name = sym.as_ref();
       // IO functions: call host log_value for each arg, return nil
       if matches!(name, "println" | "eprintln" | "echo") {
-        let log_idx = HOST_IMPORTS
-          .iter()
-          .position(|imp| imp.module == "io" && imp.name == "log_value")
-          .expect("log_value host import") as u32;
         for arg in &args_list {
           emit_expr(ctx, arg)?;
-          ctx.emit(Instruction::Call(log_idx));
+          ctx.call_rt("io/log_value");
           ctx.emit(Instruction::Drop); // drop log_value's return
         }
         ctx.emit(f64_const(0.0)); // nil
@@ -1314,35 +1585,33 @@ fn emit_call_expr(ctx: &mut WasmGenCtx, xs: &crate::calcit::CalcitList) -> Resul
             ctx.emit(Instruction::LocalSet(idx));
           }
         }
+        ctx.tail_position = tail;
+        ctx.stack_empty = stack_empty;
         return emit_body(ctx, &body);
       }
-      let fn_idx = *ctx
-        .fn_index
-        .get(name)
-        .ok_or_else(|| format!("unknown function symbol in direct call: {sym:?}"))?;
+      let Some(&fn_idx) = ctx.fn_index.get(name) else {
+        // e.g. platform procs registered by the host binary
+        return ctx.unsupported(WasmGapKind::Proc, name, format!("unknown function symbol in direct call: {sym:?}"));
+      };
       let target_arity = ctx.fn_arity.get(name).copied().unwrap_or(args_list.len() as u32);
       let rest_fixed = ctx.fn_has_rest.get(name).copied();
       emit_call_args(ctx, &args_list, target_arity, rest_fixed)?;
-      ctx.emit(Instruction::Call(fn_idx));
+      emit_direct_call(ctx, fn_idx, tail, stack_empty);
       Ok(())
     }
     Calcit::Registered(name) => {
       // Registered procs (eprintln, println, echo, etc.)
       let name = name.as_ref();
       if matches!(name, "println" | "eprintln" | "echo") {
-        let log_idx = HOST_IMPORTS
-          .iter()
-          .position(|imp| imp.module == "io" && imp.name == "log_value")
-          .expect("log_value host import") as u32;
         for arg in &args_list {
           emit_expr(ctx, arg)?;
-          ctx.emit(Instruction::Call(log_idx));
+          ctx.call_rt("io/log_value");
           ctx.emit(Instruction::Drop);
         }
         ctx.emit(f64_const(0.0)); // nil
         return Ok(());
       }
-      Err(format!("unsupported registered proc in WASM: {name}"))
+      ctx.unsupported(WasmGapKind::Proc, name, format!("unsupported registered proc in WASM: {name}"))
     }
     Calcit::Fn { info, .. } => {
       let def_ref = info.def_ref.as_ref().ok_or_else(|| {
@@ -1390,7 +1659,7 @@ fn emit_call_expr(ctx: &mut WasmGenCtx, xs: &crate::calcit::CalcitList) -> Resul
         .or_else(|| ctx.fn_has_rest.get(def_ref.def_name.as_ref()))
         .copied();
       emit_call_args(ctx, &args_list, target_arity, rest_fixed)?;
-      ctx.emit(Instruction::Call(fn_idx));
+      emit_direct_call(ctx, fn_idx, tail, stack_empty);
       Ok(())
     }
     // Inline IIFE: call head is `(defn name (params...) body...)`
@@ -1408,10 +1677,12 @@ fn emit_call_expr(ctx: &mut WasmGenCtx, xs: &crate::calcit::CalcitList) -> Resul
         other => return Err(format!("IIFE defn: params list expected, got: {other:?}")),
       };
       let body: Vec<Calcit> = iife_items.iter().skip(3).cloned().collect();
+      ctx.tail_position = tail;
+      ctx.stack_empty = stack_empty;
       emit_inline_iife(ctx, &params, &body, &args_list)
     }
-    // Dynamic call via a local variable holding a function table index (f64).
-    // Implements Rust-style `dyn Fn` dispatch via WASM call_indirect.
+    // Dynamic call via a local variable holding a function value (table slot
+    // or closure), dispatched through WASM call_indirect.
     Calcit::Local(local) => {
       let local_name = local.sym.as_ref().to_string();
       // If this local is an inline lambda, inline the call directly.
@@ -1434,25 +1705,24 @@ fn emit_call_expr(ctx: &mut WasmGenCtx, xs: &crate::calcit::CalcitList) -> Resul
             ctx.emit(Instruction::LocalSet(idx));
           }
         }
+        ctx.tail_position = tail;
+        ctx.stack_empty = stack_empty;
         return emit_body(ctx, &body);
       }
       let local_idx = *ctx
         .locals
         .get(&local_name)
         .ok_or_else(|| format!("undefined local used as function: {}", local.sym))?;
-      // Emit all arguments
-      for arg in &args_list {
-        emit_expr(ctx, arg)?;
-      }
-      // Load function table index (f64) and truncate to i32 for call_indirect
-      ctx.emit(Instruction::LocalGet(local_idx));
-      ctx.emit(Instruction::I32TruncF64S);
-      // Canonical type index = number of args (type N = (f64×N) → f64)
-      ctx.emit(Instruction::CallIndirect {
-        type_index: args_list.len() as u32,
-        table_index: 0,
-      });
-      Ok(())
+      let arg_locals = emit_args_to_locals(ctx, &args_list)?;
+      emit_fn_value_call(ctx, local_idx, &arg_locals)
+    }
+    // Call head computed at runtime, e.g. `((make-adder 1) 2)`.
+    Calcit::List(_) => {
+      emit_expr(ctx, head)?;
+      let callee = ctx.alloc_local();
+      ctx.emit(Instruction::LocalSet(callee));
+      let arg_locals = emit_args_to_locals(ctx, &args_list)?;
+      emit_fn_value_call(ctx, callee, &arg_locals)
     }
     _ => Err(format!("unsupported call head in WASM: {head}")),
   }
@@ -1817,6 +2087,7 @@ fn emit_proc_call(ctx: &mut WasmGenCtx, proc: &CalcitProc, args: &[Calcit]) -> R
 
     // Recur
     CalcitProc::Recur => {
+      let tail = std::mem::take(&mut ctx.tail_position);
       // Check for spread: args may be [a1, ..., ak, ArgSpread, rest_list]
       let spread_pos = args.iter().position(|a| matches!(a, Calcit::Syntax(CalcitSyntax::ArgSpread, _)));
 
@@ -1875,6 +2146,9 @@ fn emit_proc_call(ctx: &mut WasmGenCtx, proc: &CalcitProc, args: &[Calcit]) -> R
           ctx.emit(Instruction::LocalGet(tmp));
           ctx.emit(Instruction::LocalSet(ctx.arg_indices[i]));
         }
+        if tail {
+          emit_gc_safepoint(ctx);
+        }
         ctx.emit(Instruction::Br(ctx.block_depth));
         ctx.emit(Instruction::Unreachable);
         Ok(())
@@ -1900,6 +2174,9 @@ fn emit_proc_call(ctx: &mut WasmGenCtx, proc: &CalcitProc, args: &[Calcit]) -> R
           ctx.emit(Instruction::LocalGet(tmp));
           ctx.emit(Instruction::LocalSet(ctx.arg_indices[i]));
         }
+        if tail {
+          emit_gc_safepoint(ctx);
+        }
         ctx.emit(Instruction::Br(ctx.block_depth)); // br to the recur loop
         // After unconditional br, mark as unreachable for the type checker
         ctx.emit(Instruction::Unreachable);
@@ -1916,15 +2193,21 @@ fn emit_proc_call(ctx: &mut WasmGenCtx, proc: &CalcitProc, args: &[Calcit]) -> R
     CalcitProc::NativeStructDefinition => emit_struct_def(ctx, args),
     CalcitProc::NativeStructGetName => emit_struct_get_name(ctx, args),
     CalcitProc::NativeStructToMap => emit_struct_to_map(ctx, args),
-    CalcitProc::NativeStructAssoc | CalcitProc::NativeStructAssocAt | CalcitProc::NativeStructWith => {
-      Err(format!("{proc} not yet supported in WASM codegen"))
-    }
+    CalcitProc::NativeStructAssoc | CalcitProc::NativeStructAssocAt | CalcitProc::NativeStructWith => ctx.unsupported(
+      WasmGapKind::Proc,
+      proc.as_ref(),
+      format!("{proc} not yet supported in WASM codegen"),
+    ),
     CalcitProc::NativeStructFromMap
     | CalcitProc::NativeStructExtendAs
     | CalcitProc::NativeStructPartial
     | CalcitProc::NativeStructImpls
     | CalcitProc::NativeStructWithAt
-    | CalcitProc::NativeLooseStruct => Err(format!("Struct operation {proc} not yet supported in WASM codegen")),
+    | CalcitProc::NativeLooseStruct => ctx.unsupported(
+      WasmGapKind::Proc,
+      proc.as_ref(),
+      format!("Struct operation {proc} not yet supported in WASM codegen"),
+    ),
     CalcitProc::NativeStructContains => emit_struct_contains(ctx, args),
     CalcitProc::NativeStructMatches => emit_struct_matches(ctx, args),
 
@@ -1940,7 +2223,11 @@ fn emit_proc_call(ctx: &mut WasmGenCtx, proc: &CalcitProc, args: &[Calcit]) -> R
     | CalcitProc::NativeEnumDefinition
     | CalcitProc::NativeEnumValueImplTraits
     | CalcitProc::NativeEnumDefHasVariant
-    | CalcitProc::NativeEnumDefVariantArity => Err(format!("Enum operation {proc} not yet supported in WASM codegen")),
+    | CalcitProc::NativeEnumDefVariantArity => ctx.unsupported(
+      WasmGapKind::Proc,
+      proc.as_ref(),
+      format!("Enum operation {proc} not yet supported in WASM codegen"),
+    ),
     CalcitProc::NativeEnumAssoc => emit_enum_assoc(ctx, args),
 
     // Bitwise operations — convert to i32, operate, convert back to f64
@@ -2104,8 +2391,10 @@ fn emit_proc_call(ctx: &mut WasmGenCtx, proc: &CalcitProc, args: &[Calcit]) -> R
     CalcitProc::FormatToLisp => emit_format_to_lisp(ctx, args),
     // to-lispy-string — stub, only used in raise/error message paths
     CalcitProc::PrStr => ctx.stub_proc(args),
-    // get-env — returns nil in WASM (env vars not available)
-    CalcitProc::GetEnv => ctx.stub_proc(args),
+    // &get-env — host environment lookup, falling back to the optional default
+    CalcitProc::GetEnv => emit_get_env(ctx, args),
+    CalcitProc::ReadFile => emit_host_call(ctx, "read_file_str", args),
+    CalcitProc::CpuTime => emit_host_call(ctx, "current_time", args),
 
     // @atom deref: just emit the argument (which should already be a GlobalGet)
     CalcitProc::AtomDeref => {
@@ -2113,8 +2402,16 @@ fn emit_proc_call(ctx: &mut WasmGenCtx, proc: &CalcitProc, args: &[Calcit]) -> R
       emit_expr(ctx, &args[0])
     }
 
-    // quit! — trap (abort) the WASM instance
+    // quit! — exit the process under WASI, otherwise trap (abort) the WASM instance
     CalcitProc::Quit => {
+      if ctx.runtime_fn_index.contains_key("__wasi_exit") {
+        match args.first() {
+          Some(code) => emit_expr(ctx, code)?,
+          None => ctx.emit(f64_const(0.0)),
+        }
+        ctx.call_rt("__wasi_exit");
+        return Ok(());
+      }
       ctx.emit(Instruction::Unreachable);
       ctx.emit(f64_const(0.0)); // unreachable, but keeps type stack valid
       Ok(())
@@ -2185,7 +2482,7 @@ fn emit_proc_call(ctx: &mut WasmGenCtx, proc: &CalcitProc, args: &[Calcit]) -> R
     }
 
     // Not yet supported
-    _ => Err(format!("unsupported proc in WASM: {proc}")),
+    _ => ctx.unsupported(WasmGapKind::Proc, proc.as_ref(), format!("unsupported proc in WASM: {proc}")),
   }
 }
 
@@ -2290,19 +2587,41 @@ fn emit_type_predicate(ctx: &mut WasmGenCtx, type_name: &str, args: &[Calcit]) -
 }
 
 /// Emit a call to a host-imported function by name.
+/// Under WASI the call goes to the in-module shim registered under the same key.
 fn emit_host_call(ctx: &mut WasmGenCtx, name: &str, args: &[Calcit]) -> Result<(), String> {
-  let import_idx = HOST_IMPORTS
+  let import = HOST_IMPORTS
     .iter()
-    .position(|imp| imp.name == name)
+    .find(|imp| imp.name == name)
     .ok_or_else(|| format!("unknown host import: {name}"))?;
-  let expected_arity = HOST_IMPORTS[import_idx].arity;
+  let expected_arity = import.arity();
   if args.len() != expected_arity {
     return Err(format!("{name} expects {expected_arity} args, got {}", args.len()));
   }
   for arg in args {
     emit_expr(ctx, arg)?;
   }
-  ctx.emit(Instruction::Call(import_idx as u32));
+  ctx.call_rt(&import.key());
+  Ok(())
+}
+
+/// `&get-env name ?default` — the host `get_env` result, or `default` when it is nil.
+fn emit_get_env(ctx: &mut WasmGenCtx, args: &[Calcit]) -> Result<(), String> {
+  let Some((name, rest)) = args.split_first() else {
+    return Err("&get-env expects 1 or 2 args, got 0".into());
+  };
+  emit_host_call(ctx, "get_env", std::slice::from_ref(name))?;
+  let Some(default) = rest.first() else {
+    return Ok(());
+  };
+  let value = ctx.alloc_local_typed(ValType::F64);
+  ctx.emit(Instruction::LocalTee(value));
+  ctx.emit(f64_const(0.0));
+  ctx.emit(Instruction::F64Eq);
+  ctx.emit(Instruction::If(wasm_encoder::BlockType::Result(ValType::F64)));
+  emit_expr(ctx, default)?;
+  ctx.emit(Instruction::Else);
+  ctx.emit(Instruction::LocalGet(value));
+  ctx.emit(Instruction::End);
   Ok(())
 }
 
@@ -2790,16 +3109,23 @@ fn emit_if(ctx: &mut WasmGenCtx, args: &[Calcit]) -> Result<(), String> {
   if args.len() < 2 || args.len() > 3 {
     return Err(format!("if expects 2-3 args, got {}", args.len()));
   }
+  let tail = std::mem::take(&mut ctx.tail_position);
+  let stack_empty = std::mem::take(&mut ctx.stack_empty);
   // condition → i32
+  ctx.stack_empty = stack_empty;
   emit_expr(ctx, &args[0])?;
   ctx.emit(f64_const(0.0));
   ctx.emit(Instruction::F64Ne); // nonzero is truthy → i32
 
   ctx.emit(Instruction::If(wasm_encoder::BlockType::Result(ValType::F64)));
   ctx.block_depth += 1;
+  ctx.tail_position = tail;
+  ctx.stack_empty = stack_empty;
   emit_expr(ctx, &args[1])?;
   ctx.emit(Instruction::Else);
   if args.len() == 3 {
+    ctx.tail_position = tail;
+    ctx.stack_empty = stack_empty;
     emit_expr(ctx, &args[2])?;
   } else {
     ctx.emit(f64_const(0.0));
@@ -2847,6 +3173,8 @@ fn emit_let_pairs(ctx: &mut WasmGenCtx, pairs: &[Calcit], body: &[Calcit]) -> Re
 
 /// Emit WASM for `let` expression.
 fn emit_let(ctx: &mut WasmGenCtx, body: &[Calcit]) -> Result<(), String> {
+  let tail = std::mem::take(&mut ctx.tail_position);
+  let stack_empty = std::mem::take(&mut ctx.stack_empty);
   if body.is_empty() {
     ctx.emit(f64_const(0.0));
     return Ok(());
@@ -2856,8 +3184,16 @@ fn emit_let(ctx: &mut WasmGenCtx, body: &[Calcit]) -> Result<(), String> {
   let rest = &body[1..];
 
   match pair {
-    Calcit::Nil => emit_body(ctx, rest),
-    Calcit::List(xs) if xs.is_empty() => emit_body(ctx, rest),
+    Calcit::Nil => {
+      ctx.tail_position = tail;
+      ctx.stack_empty = stack_empty;
+      emit_body(ctx, rest)
+    }
+    Calcit::List(xs) if xs.is_empty() => {
+      ctx.tail_position = tail;
+      ctx.stack_empty = stack_empty;
+      emit_body(ctx, rest)
+    }
     Calcit::List(xs) if xs.len() == 2 => {
       let var_name = match &xs[0] {
         Calcit::Local(CalcitLocal { sym, .. }) => sym.to_string(),
@@ -2868,20 +3204,32 @@ fn emit_let(ctx: &mut WasmGenCtx, body: &[Calcit]) -> Result<(), String> {
       // Check if the binding value is an inline lambda.
       // If so, store it for inlining at call sites instead of emitting as runtime value.
       if let Some((params, body)) = try_extract_inline_lambda(&xs[1]) {
-        ctx.lambda_locals.insert(var_name.clone(), (params, body));
-        // Allocate a local slot (unused at runtime) so shadowing cleanup works.
-        ctx.declare_local(&var_name);
+        ctx.lambda_locals.insert(var_name.clone(), (params.clone(), body.clone()));
+        // The local slot also keeps shadowing cleanup working; it only holds
+        // the closure when the lambda escapes direct calls.
+        if lambda_used_as_value(&var_name, rest) {
+          emit_closure(ctx, params, body)?;
+          let idx = ctx.declare_local(&var_name);
+          ctx.emit(Instruction::LocalSet(idx));
+        } else {
+          ctx.declare_local(&var_name);
+        }
         // Flatten nested lets
         if rest.len() == 1
           && let Calcit::List(inner) = &rest[0]
           && let Some(Calcit::Syntax(CalcitSyntax::CoreLet, _)) = inner.first()
         {
           let inner_body: Vec<Calcit> = inner.drop_left().to_vec();
+          ctx.tail_position = tail;
+          ctx.stack_empty = stack_empty;
           return emit_let(ctx, &inner_body);
         }
+        ctx.tail_position = tail;
+        ctx.stack_empty = stack_empty;
         return emit_body(ctx, rest);
       }
 
+      ctx.stack_empty = stack_empty;
       emit_expr(ctx, &xs[1])?;
       let idx = ctx.declare_local(&var_name);
       ctx.emit(Instruction::LocalSet(idx));
@@ -2892,9 +3240,14 @@ fn emit_let(ctx: &mut WasmGenCtx, body: &[Calcit]) -> Result<(), String> {
         && let Some(Calcit::Syntax(CalcitSyntax::CoreLet, _)) = inner.first()
       {
         let inner_body: Vec<Calcit> = inner.drop_left().to_vec();
+        ctx.tail_position = tail;
+        ctx.stack_empty = stack_empty;
         return emit_let(ctx, &inner_body);
       }
 
+      ctx.tail_position = tail;
+
+      ctx.stack_empty = stack_empty;
       emit_body(ctx, rest)
     }
     _ => Err(format!("unsupported let binding form: {pair}")),
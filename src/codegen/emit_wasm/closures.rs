@@ -0,0 +1,345 @@
+use super::gc::emit_load_first_object;
+use super::runtime::{MAX_CANONICAL_ARITY, RuntimeFnBuilder};
+use super::*;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+use wasm_encoder::BlockType;
+
+// ---------------------------------------------------------------------------
+// Closure conversion
+// ---------------------------------------------------------------------------
+//
+// A `fn` that is used as a value (returned, passed to a user function, stored
+// in a data structure) is lifted into its own WASM function, appended to the
+// funcref table after the top-level functions. Locals of the enclosing
+// function that the lambda mentions become its captures.
+//
+// Function values keep two encodings:
+//
+// - a bare table slot (f64), for top-level functions and capture-free lambdas;
+//   the callee has exactly the call's arity,
+// - a heap object tagged `fn` for lambdas with captures:
+//
+//   ```text
+//   logical_ptr + 0:      table slot (f64)
+//   logical_ptr + 8 * i:  capture i - 1 (f64)
+//   ```
+//
+//   the lifted function takes the closure itself as an extra trailing `env`
+//   parameter and loads its captures from there.
+//
+// Callers go through `emit_fn_value_call`, which asks `__rt_closure_of` which
+// encoding it holds. The module raises the heap start above the table size so
+// that a bare slot can never look like a heap pointer.
+
+/// A lambda lifted out of its enclosing function, compiled after all defs.
+pub(super) struct LiftedLambda {
+  params: Vec<String>,
+  body: Vec<Calcit>,
+  /// Names of enclosing locals copied into the closure object, in slot order.
+  captures: Vec<String>,
+  /// Definition the lambda was written in.
+  owner: Rc<str>,
+}
+
+/// Shared registry of lifted lambdas. Cloned into every function's context;
+/// all clones append to the same list, so slots are assigned in lift order.
+#[derive(Clone)]
+pub(super) struct LambdaTable {
+  slot_base: u32,
+  lambdas: Rc<RefCell<Vec<Rc<LiftedLambda>>>>,
+}
+
+impl LambdaTable {
+  /// `slot_base` is the first free table slot, i.e. the number of top-level functions.
+  pub(super) fn new(slot_base: u32) -> Self {
+    LambdaTable {
+      slot_base,
+      lambdas: Rc::new(RefCell::new(Vec::new())),
+    }
+  }
+
+  fn lift(&self, lambda: LiftedLambda) -> u32 {
+    let mut lambdas = self.lambdas.borrow_mut();
+    lambdas.push(Rc::new(lambda));
+    self.slot_base + lambdas.len() as u32 - 1
+  }
+
+  fn get(&self, i: usize) -> Option<Rc<LiftedLambda>> {
+    self.lambdas.borrow().get(i).cloned()
+  }
+
+  /// Enclosing definition of each lambda, in slot order.
+  pub(super) fn owners(&self) -> Vec<Rc<str>> {
+    self.lambdas.borrow().iter().map(|lambda| lambda.owner.clone()).collect()
+  }
+}
+
+/// Push a function value for the lambda `params → body`, capturing the
+/// enclosing locals it mentions.
+pub(super) fn emit_closure(ctx: &mut WasmGenCtx, params: Vec<String>, body: Vec<Calcit>) -> Result<(), String> {
+  if params.iter().any(|p| p == "&" || p == "?") {
+    return Err("lambdas with optional or rest params are not supported as WASM values".into());
+  }
+  let mut mentioned = BTreeSet::new();
+  for expr in &body {
+    collect_mentioned_names(expr, &mut mentioned);
+  }
+  let captures: Vec<(String, u32)> = mentioned
+    .into_iter()
+    .filter(|name| !params.contains(name))
+    .filter_map(|name| ctx.locals.get(&name).map(|&idx| (name, idx)))
+    .collect();
+  let wasm_arity = params.len() as u32 + u32::from(!captures.is_empty());
+  if wasm_arity >= MAX_CANONICAL_ARITY {
+    return Err(format!(
+      "lambda takes {wasm_arity} WASM params, at most {} supported",
+      MAX_CANONICAL_ARITY - 1
+    ));
+  }
+
+  let slot = ctx.lambdas.lift(LiftedLambda {
+    params,
+    body,
+    captures: captures.iter().map(|(name, _)| name.clone()).collect(),
+    owner: ctx.owner.clone(),
+  });
+  if captures.is_empty() {
+    ctx.emit(f64_const(slot as f64));
+    return Ok(());
+  }
+
+  let ptr = ctx.alloc_local_typed(ValType::I32);
+  emit_bump_alloc(ctx, 8 * (1 + captures.len() as i32), ptr, "fn");
+  ctx.emit(Instruction::LocalGet(ptr));
+  ctx.emit(f64_const(slot as f64));
+  ctx.emit(Instruction::F64Store(mem_arg_f64(0)));
+  for (i, (_, local)) in captures.iter().enumerate() {
+    ctx.emit(Instruction::LocalGet(ptr));
+    ctx.emit(Instruction::LocalGet(*local));
+    // Inline HOF loops may bind a param to their i32 index counter.
+    if local_val_type(ctx, *local) == ValType::I32 {
+      ctx.emit(Instruction::F64ConvertI32U);
+    }
+    ctx.emit(Instruction::F64Store(mem_arg_f64(8 * (1 + i as u64))));
+  }
+  ctx.ptr_to_f64(ptr);
+  Ok(())
+}
+
+fn local_val_type(ctx: &WasmGenCtx, idx: u32) -> ValType {
+  let param_count = ctx.next_local - ctx.extra_locals.len() as u32;
+  if idx < param_count {
+    ValType::F64
+  } else {
+    ctx.extra_locals[(idx - param_count) as usize]
+  }
+}
+
+/// Every symbol or local name appearing in `expr`, nested lambdas included.
+fn collect_mentioned_names(expr: &Calcit, out: &mut BTreeSet<String>) {
+  match expr {
+    Calcit::Local(CalcitLocal { sym, .. }) | Calcit::Symbol { sym, .. } => {
+      out.insert(sym.to_string());
+    }
+    Calcit::List(xs) => {
+      for x in xs.iter() {
+        collect_mentioned_names(x, out);
+      }
+    }
+    Calcit::Fn { info, .. } if info.def_ref.is_none() => {
+      for x in info.body.iter() {
+        collect_mentioned_names(x, out);
+      }
+    }
+    _ => {}
+  }
+}
+
+/// Whether the let-bound lambda `name` is needed as a runtime value in
+/// `exprs`, rather than only being called directly (which inlines it).
+/// Any mention inside a nested lambda counts, since that lambda captures it.
+pub(super) fn lambda_used_as_value(name: &str, exprs: &[Calcit]) -> bool {
+  exprs.iter().any(|expr| used_as_value(name, expr))
+}
+
+fn used_as_value(name: &str, expr: &Calcit) -> bool {
+  match expr {
+    Calcit::Local(CalcitLocal { sym, .. }) | Calcit::Symbol { sym, .. } => sym.as_ref() == name,
+    Calcit::List(xs) => match xs.first() {
+      Some(Calcit::Syntax(CalcitSyntax::Defn, _)) => mentions(name, expr),
+      Some(Calcit::Local(CalcitLocal { sym, .. }) | Calcit::Symbol { sym, .. }) if sym.as_ref() == name => {
+        xs.iter().skip(1).any(|x| used_as_value(name, x))
+      }
+      _ => xs.iter().any(|x| used_as_value(name, x)),
+    },
+    Calcit::Fn { .. } => mentions(name, expr),
+    _ => false,
+  }
+}
+
+fn mentions(name: &str, expr: &Calcit) -> bool {
+  let mut names = BTreeSet::new();
+  collect_mentioned_names(expr, &mut names);
+  names.contains(name)
+}
+
+/// Evaluate `args` left to right into fresh f64 locals.
+pub(super) fn emit_args_to_locals(ctx: &mut WasmGenCtx, args: &[Calcit]) -> Result<Vec<u32>, String> {
+  let mut locals = Vec::with_capacity(args.len());
+  for arg in args {
+    emit_expr(ctx, arg)?;
+    let local = ctx.alloc_local();
+    ctx.emit(Instruction::LocalSet(local));
+    locals.push(local);
+  }
+  Ok(locals)
+}
+
+/// Call the function value held in the f64 local `callee` with f64 `args`,
+/// dispatching on its encoding (closure object or bare table slot).
+pub(super) fn emit_fn_value_call(ctx: &mut WasmGenCtx, callee: u32, args: &[u32]) -> Result<(), String> {
+  let arity = args.len() as u32;
+  if arity + 1 >= MAX_CANONICAL_ARITY {
+    return Err(format!(
+      "dynamic call with {arity} args, at most {} supported",
+      MAX_CANONICAL_ARITY - 2
+    ));
+  }
+  let closure = ctx.alloc_local_typed(ValType::I32);
+  ctx.emit(Instruction::LocalGet(callee));
+  ctx.call_rt("__rt_closure_of");
+  ctx.emit(Instruction::LocalTee(closure));
+  ctx.emit(Instruction::If(BlockType::Result(ValType::F64)));
+  for &arg in args {
+    ctx.emit(Instruction::LocalGet(arg));
+  }
+  ctx.emit(Instruction::LocalGet(callee));
+  ctx.emit(Instruction::LocalGet(closure));
+  ctx.emit(Instruction::F64Load(mem_arg_f64(0)));
+  ctx.emit(Instruction::I32TruncF64U);
+  ctx.emit(Instruction::CallIndirect {
+    type_index: arity + 1,
+    table_index: 0,
+  });
+  ctx.emit(Instruction::Else);
+  for &arg in args {
+    ctx.emit(Instruction::LocalGet(arg));
+  }
+  ctx.emit(Instruction::LocalGet(callee));
+  ctx.emit(Instruction::I32TruncF64S);
+  ctx.emit(Instruction::CallIndirect {
+    type_index: arity,
+    table_index: 0,
+  });
+  ctx.emit(Instruction::End);
+  Ok(())
+}
+
+/// `__rt_closure_of(v: f64) → i32` — the logical pointer of `v` when it is a
+/// closure object, otherwise 0.
+pub(super) fn build_closure_of_fn(fn_tag: i32) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // v
+  let ptr = b.alloc_i32();
+
+  // integer, first object <= v < heap_ptr
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::F64Trunc);
+  b.emit(Instruction::F64Eq);
+  b.emit(Instruction::LocalGet(0));
+  emit_load_first_object(&mut b);
+  b.emit(Instruction::F64ConvertI32U);
+  b.emit(Instruction::F64Ge);
+  b.emit(Instruction::I32And);
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
+  b.emit(Instruction::F64ConvertI32U);
+  b.emit(Instruction::F64Lt);
+  b.emit(Instruction::I32And);
+  b.emit(Instruction::I32Eqz);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::Return);
+  b.emit(Instruction::End);
+
+  // magic and `fn` tag in the object header
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::I32TruncF64U);
+  b.emit(Instruction::LocalTee(ptr));
+  b.emit(Instruction::I32Const(8));
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::I32Load(mem_arg_i32(0)));
+  b.emit(Instruction::I32Const(HEAP_MAGIC));
+  b.emit(Instruction::I32Eq);
+  b.emit(Instruction::LocalGet(ptr));
+  b.emit(Instruction::I32Const(4));
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::I32Load(mem_arg_i32(0)));
+  b.emit(Instruction::I32Const(fn_tag));
+  b.emit(Instruction::I32Eq);
+  b.emit(Instruction::I32And);
+  b.emit(Instruction::If(BlockType::Result(ValType::I32)));
+  b.emit(Instruction::LocalGet(ptr));
+  b.emit(Instruction::Else);
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::End);
+  b.finish(vec![ValType::F64], vec![ValType::I32])
+}
+
+/// Compile every lifted lambda in slot order, including lambdas lifted while
+/// compiling other lambdas. A lambda that fails to compile keeps its slot with
+/// a body returning nil.
+pub(super) fn compile_lifted_lambdas(env: &WasmCompileEnv, out: &mut Vec<CompiledFn>) {
+  let mut next = 0;
+  while let Some(lambda) = env.lambdas.get(next) {
+    let arity = lambda.params.len() + usize::from(!lambda.captures.is_empty());
+    let func = compile_lambda(&lambda, env).unwrap_or_else(|e| {
+      if let Some(log) = &env.gaps {
+        log.record_failure(&lambda.owner, &format!("lambda: {e}"));
+      } else {
+        eprintln!(
+          "[wasm] skipping lambda in table slot {}: {e}",
+          env.lambdas.slot_base as usize + next
+        );
+      }
+      CompiledFn {
+        export_name: None,
+        params: vec![ValType::F64; arity],
+        results: vec![ValType::F64],
+        locals: vec![],
+        instructions: vec![f64_const(0.0)],
+      }
+    });
+    out.push(func);
+    next += 1;
+  }
+}
+
+fn compile_lambda(lambda: &LiftedLambda, env: &WasmCompileEnv) -> Result<CompiledFn, String> {
+  let param_count = lambda.params.len() as u32;
+  let arity = param_count + u32::from(!lambda.captures.is_empty());
+  let mut ctx = WasmGenCtx::new(lambda.owner.clone(), arity, env.clone());
+  for (i, name) in lambda.params.iter().enumerate() {
+    ctx.locals.insert(name.clone(), i as u32);
+    ctx.arg_indices.push(i as u32);
+  }
+  // The trailing `env` param is the closure object; unpack its captures.
+  for (i, name) in lambda.captures.iter().enumerate() {
+    ctx.emit(Instruction::LocalGet(param_count));
+    ctx.emit(Instruction::I32TruncF64U);
+    ctx.emit(Instruction::F64Load(mem_arg_f64(8 * (1 + i as u64))));
+    let idx = ctx.declare_local(name);
+    ctx.emit(Instruction::LocalSet(idx));
+  }
+  emit_fn_body(&mut ctx, &lambda.body)?;
+
+  Ok(CompiledFn {
+    export_name: None,
+    params: vec![ValType::F64; arity as usize],
+    results: vec![ValType::F64],
+    locals: ctx.extra_locals,
+    instructions: ctx.instructions,
+  })
+}
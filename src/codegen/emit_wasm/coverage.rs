@@ -0,0 +1,209 @@
+use super::*;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+
+// ---------------------------------------------------------------------------
+// Coverage report for `cr-wasm --check-only`
+// ---------------------------------------------------------------------------
+//
+// Codegen stops at the first construct it cannot lower. For the report the
+// module is compiled with a `GapLog` in the env instead: each unsupported
+// proc, syntax, method or value is recorded against the definition being
+// compiled and replaced with a nil placeholder, so compilation carries on and
+// finds the rest. Other compile errors are recorded as failures of the whole
+// definition.
+//
+// The call graph comes from the compiled code rather than from `deps`: many
+// core functions (`map`, `join-str`, ...) are lowered inline and never called,
+// so only `call` instructions into user functions and function values count
+// as edges. Lifted lambdas belong to the definition they were written in.
+
+/// Kind of construct the WASM backend cannot lower yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WasmGapKind {
+  Proc,
+  Syntax,
+  Method,
+  Value,
+}
+
+impl fmt::Display for WasmGapKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      WasmGapKind::Proc => "proc",
+      WasmGapKind::Syntax => "syntax",
+      WasmGapKind::Method => "method",
+      WasmGapKind::Value => "value",
+    })
+  }
+}
+
+/// An unsupported construct met while compiling a definition.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WasmGap {
+  /// Qualified `ns/def` the construct appears in.
+  pub def: String,
+  pub kind: WasmGapKind,
+  /// Proc or syntax name, `.method`, or the kind of value.
+  pub name: String,
+}
+
+/// How much of the code reachable from the entry points compiles to WASM.
+#[derive(Debug, Default)]
+pub struct WasmCoverage {
+  /// Entry definitions the call graph is walked from.
+  pub roots: Vec<String>,
+  /// Function definitions reachable from the roots, sorted.
+  pub defs: Vec<String>,
+  /// Unsupported constructs in reachable definitions, sorted by definition.
+  pub gaps: Vec<WasmGap>,
+  /// Reachable definitions that failed to compile for other reasons.
+  pub failures: Vec<(String, String)>,
+}
+
+impl WasmCoverage {
+  /// Number of reachable definitions that compile without gaps or failures.
+  pub fn supported_count(&self) -> usize {
+    let blocked: HashSet<&str> = self
+      .gaps
+      .iter()
+      .map(|gap| gap.def.as_str())
+      .chain(self.failures.iter().map(|(def, _)| def.as_str()))
+      .collect();
+    self.defs.iter().filter(|def| !blocked.contains(def.as_str())).count()
+  }
+
+  pub fn is_complete(&self) -> bool {
+    self.gaps.is_empty() && self.failures.is_empty()
+  }
+}
+
+#[derive(Default)]
+struct GapLogEntries {
+  gaps: BTreeSet<WasmGap>,
+  failures: Vec<(String, String)>,
+  /// caller `ns/def` → callee `ns/def`
+  edges: HashSet<(String, String)>,
+}
+
+/// Shared sink for coverage findings. Cloned into every function's context,
+/// like `LambdaTable`; all clones append to the same log.
+#[derive(Clone, Default)]
+pub(super) struct GapLog(Rc<RefCell<GapLogEntries>>);
+
+impl GapLog {
+  pub(super) fn record_gap(&self, def: &str, kind: WasmGapKind, name: &str) {
+    self.0.borrow_mut().gaps.insert(WasmGap {
+      def: def.to_string(),
+      kind,
+      name: name.to_string(),
+    });
+  }
+
+  pub(super) fn record_failure(&self, def: &str, error: &str) {
+    self.0.borrow_mut().failures.push((def.to_string(), error.to_string()));
+  }
+
+  pub(super) fn record_edge(&self, caller: &str, callee: &str) {
+    if caller != callee {
+      self.0.borrow_mut().edges.insert((caller.to_string(), callee.to_string()));
+    }
+  }
+}
+
+impl WasmGenCtx {
+  /// Report a construct the backend cannot lower. Normally an error; while
+  /// collecting coverage it is logged and compiled as nil so the rest of the
+  /// definition is still checked.
+  pub(super) fn unsupported(&mut self, kind: WasmGapKind, name: &str, message: String) -> Result<(), String> {
+    match &self.gaps {
+      Some(log) => {
+        log.record_gap(&self.owner, kind, name);
+        self.emit(f64_const(0.0));
+        Ok(())
+      }
+      None => Err(message),
+    }
+  }
+
+  /// Note that this function references the definition `callee` as a function value.
+  pub(super) fn note_fn_ref(&self, callee: &str) {
+    if let Some(log) = &self.gaps {
+      log.record_edge(&self.owner, callee);
+    }
+  }
+}
+
+/// Record `call` edges between compiled definitions. `fns` are the compiled
+/// user functions followed by the lifted lambdas, `owners` the definition
+/// each one belongs to; user function `i` has function index `first_user_idx + i`.
+pub(super) fn record_call_edges(log: &GapLog, fns: &[CompiledFn], owners: &[Rc<str>], user_fn_count: usize, first_user_idx: u32) {
+  let callees = &owners[..user_fn_count];
+  for (func, owner) in fns.iter().zip(owners) {
+    for instr in &func.instructions {
+      if let Instruction::Call(idx) = instr
+        && let Some(callee) = idx.checked_sub(first_user_idx).and_then(|i| callees.get(i as usize))
+      {
+        log.record_edge(owner, callee);
+      }
+    }
+  }
+}
+
+/// Compile the program reachable from `init_ns`/`init_def` in coverage mode
+/// and report what does not compile yet. Roots are the init function, every
+/// `defwasm-export` and `extra_roots` (qualified `ns/def`).
+pub fn check_wasm_coverage(init_ns: &str, init_def: &str, extra_roots: &[&str]) -> Result<WasmCoverage, String> {
+  let log = GapLog::default();
+  let program_data = program::clone_compiled_program_snapshot()?;
+  compile_module(init_ns, init_def, &WasmOptions::default(), Some(log.clone()))?;
+  let entries = log.0.take();
+
+  let mut roots = vec![format!("{init_ns}/{init_def}")];
+  roots.extend(extra_roots.iter().map(|root| root.to_string()));
+  let mut ns_list: Vec<_> = program_data.keys().collect();
+  ns_list.sort();
+  for ns in ns_list {
+    let mut defs: Vec<_> = program_data[ns]
+      .defs
+      .iter()
+      .filter(|(_, compiled)| is_wasm_export_def(&compiled.preprocessed_code))
+      .map(|(def, _)| format!("{ns}/{def}"))
+      .collect();
+    defs.sort();
+    roots.extend(defs);
+  }
+  roots.dedup();
+
+  let is_fn = |qualified: &str| {
+    qualified.rsplit_once('/').is_some_and(|(ns, def)| {
+      program_data
+        .get(ns)
+        .and_then(|file| file.defs.get(def))
+        .is_some_and(|compiled| compiled.kind == program::CompiledDefKind::Fn && !is_wasm_import_def(&compiled.preprocessed_code))
+    })
+  };
+  let mut reachable: BTreeSet<String> = BTreeSet::new();
+  let mut pending: VecDeque<String> = roots.iter().filter(|root| is_fn(root)).cloned().collect();
+  while let Some(def) = pending.pop_front() {
+    if !reachable.insert(def.clone()) {
+      continue;
+    }
+    for (caller, callee) in &entries.edges {
+      if *caller == def && !reachable.contains(callee) && is_fn(callee) {
+        pending.push_back(callee.clone());
+      }
+    }
+  }
+
+  let mut failures: Vec<_> = entries.failures.into_iter().filter(|(def, _)| reachable.contains(def)).collect();
+  failures.sort();
+  Ok(WasmCoverage {
+    roots,
+    gaps: entries.gaps.into_iter().filter(|gap| reachable.contains(&gap.def)).collect(),
+    failures,
+    defs: reachable.into_iter().collect(),
+  })
+}
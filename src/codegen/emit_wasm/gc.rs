@@ -0,0 +1,1051 @@
+use super::runtime::RuntimeFnBuilder;
+use super::*;
+use wasm_encoder::BlockType;
+
+// ---------------------------------------------------------------------------
+// Heap manager: size-class free lists + conservative mark-sweep collection
+// ---------------------------------------------------------------------------
+//
+// Every heap object lives in a block with a 16-byte header:
+//
+// ```text
+// block + 0:  block size in bytes (i32, header included, multiple of 8)
+// block + 4:  free-list link while the block is free (i32)
+// block + 8:  HEAP_MAGIC (i32)        == logical_ptr - 8
+// block + 12: type tag id (i32)       == logical_ptr - 4
+// block + 16: payload                 == logical_ptr
+// ```
+//
+// so the `magic`/`tag` words keep their historical offsets relative to the
+// logical pointer. Blocks between `HEAP_START` and the bump pointer tile the
+// heap without gaps; a free block has its magic cleared.
+//
+// Values carry no static type, so collection is conservative: any f64 that
+// is the logical pointer of a block start counts as a reference. Payload
+// slots are f64 for every object except strings, which are skipped while
+// scanning. Roots are the atom globals, the shadow stack and the locals of
+// the one frame that may still hold heap values, which is why collection
+// only happens at safepoints where the operand stack is known to be empty:
+//
+// - a `recur` in tail position of the innermost counted function,
+// - the return of a function when no other frame is counted (its result is
+//   then the only root besides the shadow stack).
+//
+// A per-instance call depth counter (`GC_DEPTH`) tracks how many frames may
+// hold heap values. Tail calls hand their frame over to the callee. A
+// non-tail direct call made while the caller's operand stack is empty spills
+// the caller's locals into a shadow frame (a heap block tagged
+// `GC_FRAME_TAG`, linked from `GC_SHADOW_TOP`) and stops counting the
+// caller, so a loop nested under ordinary calls still reaches a safepoint
+// that collects. Calls that may leave values on the operand stack (arguments
+// being evaluated, closures called from runtime helpers) keep counting the
+// caller, which only delays collection. Every export entry resets the
+// counter and the shadow stack, so an export that trapped does not keep the
+// instance from collecting.
+
+/// GC bookkeeping words, stored in linear memory right after the nil guard.
+pub(super) const GC_STATE_ADDR: i32 = 16;
+const GC_HEAP_START: i32 = 0;
+const GC_ALLOCATED: i32 = 4;
+const GC_PENDING: i32 = 8;
+const GC_DEPTH: i32 = 12;
+const GC_THRESHOLD: i32 = 16;
+const GC_COLLECTIONS: i32 = 20;
+const GC_LARGE_FREE: i32 = 24;
+const GC_LIVE: i32 = 28;
+const GC_START_MAP: i32 = 32;
+const GC_MARK_MAP: i32 = 36;
+const GC_STACK_BASE: i32 = 40;
+const GC_STACK_TOP: i32 = 44;
+const GC_SHADOW_TOP: i32 = 48;
+/// One spare word keeps the free lists and static data 8-byte aligned.
+const GC_STATE_BYTES: i32 = 56;
+
+/// Heads of the exact-size free lists, one i32 per 8-byte size class.
+const GC_FREE_LISTS_ADDR: i32 = GC_STATE_ADDR + GC_STATE_BYTES;
+const GC_SMALL_CLASSES: i32 = 64;
+/// Largest block size served by the exact-size lists; bigger blocks use a
+/// first-fit list with splitting.
+const GC_SMALL_MAX_BLOCK: i32 = (GC_SMALL_CLASSES - 1) * 8;
+/// First byte after the GC bookkeeping area; static data starts here.
+pub(super) const GC_AREA_END: i32 = GC_FREE_LISTS_ADDR + GC_SMALL_CLASSES * 4;
+
+/// Size of the block header in front of the logical pointer.
+const HEAP_BLOCK_HEADER: i32 = 16;
+/// Bytes allocated between collections before a safepoint collects, at minimum.
+const GC_MIN_THRESHOLD: i32 = 1 << 20;
+/// Initial room reserved for the mark stack above the bitmaps.
+const GC_MARK_STACK_RESERVE: i32 = 4096;
+/// Type tag of shadow frames. Never a value tag, so frames stay invisible to
+/// calcit code; payload slot 0 links the previous frame.
+const GC_FRAME_TAG: i32 = -1;
+
+/// Initial contents of the GC bookkeeping words (written by a data segment).
+pub(super) fn gc_initial_state(heap_start: i32) -> Vec<u8> {
+  let mut words = [0i32; (GC_STATE_BYTES / 4) as usize];
+  words[(GC_HEAP_START / 4) as usize] = heap_start;
+  words[(GC_THRESHOLD / 4) as usize] = GC_MIN_THRESHOLD;
+  words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+fn load_state(b: &mut RuntimeFnBuilder, field: i32) {
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::I32Load(mem_arg_i32((GC_STATE_ADDR + field) as u64)));
+}
+
+/// Push the lowest address a heap object's logical pointer can take.
+pub(super) fn emit_load_first_object(b: &mut RuntimeFnBuilder) {
+  load_state(b, GC_HEAP_START);
+  b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER));
+  b.emit(Instruction::I32Add);
+}
+
+fn store_state(b: &mut RuntimeFnBuilder, field: i32, value: impl FnOnce(&mut RuntimeFnBuilder)) {
+  b.emit(Instruction::I32Const(0));
+  value(b);
+  b.emit(Instruction::I32Store(mem_arg_i32((GC_STATE_ADDR + field) as u64)));
+}
+
+/// Push the byte address holding bit `i` of the bitmap stored in `map_field`.
+fn emit_bit_addr(b: &mut RuntimeFnBuilder, map_field: i32, i: u32) {
+  load_state(b, map_field);
+  b.emit(Instruction::LocalGet(i));
+  b.emit(Instruction::I32Const(3));
+  b.emit(Instruction::I32ShrU);
+  b.emit(Instruction::I32Add);
+}
+
+/// Push `1 << (i & 7)`.
+fn emit_bit_mask(b: &mut RuntimeFnBuilder, i: u32) {
+  b.emit(Instruction::I32Const(1));
+  b.emit(Instruction::LocalGet(i));
+  b.emit(Instruction::I32Const(7));
+  b.emit(Instruction::I32And);
+  b.emit(Instruction::I32Shl);
+}
+
+/// Push a non-zero i32 when bit `i` of the bitmap is set.
+fn emit_test_bit(b: &mut RuntimeFnBuilder, map_field: i32, i: u32) {
+  emit_bit_addr(b, map_field, i);
+  b.emit(Instruction::I32Load8U(mem_arg_byte(0)));
+  emit_bit_mask(b, i);
+  b.emit(Instruction::I32And);
+}
+
+fn emit_set_bit(b: &mut RuntimeFnBuilder, map_field: i32, i: u32, addr: u32) {
+  emit_bit_addr(b, map_field, i);
+  b.emit(Instruction::LocalTee(addr));
+  b.emit(Instruction::LocalGet(addr));
+  b.emit(Instruction::I32Load8U(mem_arg_byte(0)));
+  emit_bit_mask(b, i);
+  b.emit(Instruction::I32Or);
+  b.emit(Instruction::I32Store8(mem_arg_byte(0)));
+}
+
+/// Build the heap manager helpers in index order starting at `base_index`,
+/// paired with their runtime names. `__rt_alloc` is exported as `__alloc`.
+pub(super) fn build_gc_fns(base_index: u32, string_tag: i32, atom_globals: &[u32]) -> Vec<(&'static str, CompiledFn)> {
+  let reserve = base_index;
+  let free_push = base_index + 1;
+  let alloc = base_index + 2;
+  let mark_value = base_index + 4;
+  let try_begin = base_index + 5;
+  let finish = base_index + 6;
+
+  let mut alloc_fn = build_rt_alloc(reserve, free_push, true);
+  alloc_fn.export_name = Some("__alloc".to_string());
+  let mut collect_fn = build_gc_collect(try_begin, finish);
+  collect_fn.export_name = Some("__gc_collect".to_string());
+  let mut collections_fn = build_gc_collections();
+  collections_fn.export_name = Some("__gc_collections".to_string());
+
+  vec![
+    ("__rt_gc_reserve", build_rt_gc_reserve()),
+    ("__rt_gc_free_push", build_rt_gc_free_push()),
+    ("__rt_alloc", alloc_fn),
+    ("__rt_alloc_uninit", build_rt_alloc(reserve, free_push, false)),
+    ("__rt_gc_mark_value", build_rt_gc_mark_value(reserve)),
+    ("__rt_gc_try_begin", build_rt_gc_try_begin(reserve, mark_value, atom_globals)),
+    ("__rt_gc_finish", build_rt_gc_finish(mark_value, free_push, string_tag)),
+    ("__rt_gc_enter", build_rt_gc_shift_depth(1)),
+    ("__rt_gc_exit", build_rt_gc_shift_depth(-1)),
+    ("__rt_gc_leave", build_rt_gc_leave(try_begin, mark_value, finish)),
+    ("__gc_collect", collect_fn),
+    ("__gc_collections", collections_fn),
+    ("__rt_gc_frame_push", build_rt_gc_frame_push(alloc)),
+    ("__rt_gc_frame_pop", build_rt_gc_frame_pop(free_push)),
+    ("__rt_gc_reset", build_rt_gc_reset()),
+  ]
+}
+
+/// `__rt_gc_reserve(end: i32)` — grow linear memory until `end` is addressable.
+/// Grows by at least half the current size to amortize `memory.grow`.
+fn build_rt_gc_reserve() -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // end
+  let have = b.alloc_i32();
+  let need = b.alloc_i32();
+  let grow = b.alloc_i32();
+  b.emit(Instruction::MemorySize(0));
+  b.emit(Instruction::I32Const(16));
+  b.emit(Instruction::I32Shl);
+  b.emit(Instruction::LocalSet(have));
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::LocalGet(have));
+  b.emit(Instruction::I32LeU);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::Return);
+  b.emit(Instruction::End);
+  // need = ceil((end - have) / 64KiB)
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::LocalGet(have));
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::I32Const(0xffff));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::I32Const(16));
+  b.emit(Instruction::I32ShrU);
+  b.emit(Instruction::LocalSet(need));
+  // grow = max(need, pages / 2)
+  b.emit(Instruction::MemorySize(0));
+  b.emit(Instruction::I32Const(1));
+  b.emit(Instruction::I32ShrU);
+  b.emit(Instruction::LocalTee(grow));
+  b.emit(Instruction::LocalGet(need));
+  b.emit(Instruction::I32LtU);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::LocalGet(need));
+  b.emit(Instruction::LocalSet(grow));
+  b.emit(Instruction::End);
+  b.emit(Instruction::LocalGet(grow));
+  b.emit(Instruction::MemoryGrow(0));
+  b.emit(Instruction::I32Const(-1));
+  b.emit(Instruction::I32Eq);
+  b.emit(Instruction::If(BlockType::Empty));
+  // Retry with the exact amount before giving up.
+  b.emit(Instruction::LocalGet(need));
+  b.emit(Instruction::MemoryGrow(0));
+  b.emit(Instruction::I32Const(-1));
+  b.emit(Instruction::I32Eq);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::Unreachable);
+  b.emit(Instruction::End);
+  b.emit(Instruction::End);
+  b.finish(vec![ValType::I32], vec![])
+}
+
+/// `__rt_gc_free_push(block: i32)` — put a block (size already written) on
+/// its free list and clear its magic so it no longer looks like an object.
+fn build_rt_gc_free_push() -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // block
+  let size = b.alloc_i32();
+  let head = b.alloc_i32();
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::I32Store(mem_arg_i32(8)));
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::I32Load(mem_arg_i32(0)));
+  b.emit(Instruction::LocalTee(size));
+  b.emit(Instruction::I32Const(GC_SMALL_MAX_BLOCK));
+  b.emit(Instruction::I32LeU);
+  b.emit(Instruction::If(BlockType::Empty));
+  {
+    // head = FREE_LISTS + (size / 8) * 4
+    b.emit(Instruction::LocalGet(size));
+    b.emit(Instruction::I32Const(1));
+    b.emit(Instruction::I32ShrU);
+    b.emit(Instruction::I32Const(GC_FREE_LISTS_ADDR));
+    b.emit(Instruction::I32Add);
+    b.emit(Instruction::LocalSet(head));
+    b.emit(Instruction::LocalGet(0));
+    b.emit(Instruction::LocalGet(head));
+    b.emit(Instruction::I32Load(mem_arg_i32(0)));
+    b.emit(Instruction::I32Store(mem_arg_i32(4)));
+    b.emit(Instruction::LocalGet(head));
+    b.emit(Instruction::LocalGet(0));
+    b.emit(Instruction::I32Store(mem_arg_i32(0)));
+  }
+  b.emit(Instruction::Else);
+  {
+    b.emit(Instruction::LocalGet(0));
+    load_state(&mut b, GC_LARGE_FREE);
+    b.emit(Instruction::I32Store(mem_arg_i32(4)));
+    store_state(&mut b, GC_LARGE_FREE, |b| b.emit(Instruction::LocalGet(0)));
+  }
+  b.emit(Instruction::End);
+  b.finish(vec![ValType::I32], vec![])
+}
+
+/// `__rt_alloc(payload: i32, tag: i32) → i32` — allocate a zeroed object and
+/// return its logical pointer. Exported to hosts as `__alloc`.
+///
+/// Tries the exact-size free list, then first fit in the large list, then
+/// bumps the heap pointer (growing memory when needed). Never collects by
+/// itself: it only raises `GC_PENDING` for the next safepoint.
+///
+/// `__rt_alloc_uninit` skips zeroing the payload. `__str_new` relies on that:
+/// hosts may stage bytes right above the heap pointer, exactly where a bump
+/// allocation puts the new string's content.
+fn build_rt_alloc(reserve_idx: u32, free_push_idx: u32, zero_fill: bool) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(2); // payload, tag
+  let total = b.alloc_i32();
+  let block = b.alloc_i32();
+  let prev = b.alloc_i32();
+  let cur = b.alloc_i32();
+  let cur_size = b.alloc_i32();
+  let tmp = b.alloc_i32();
+
+  // total = (payload + header + 7) & -8
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER + 7));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::I32Const(-8));
+  b.emit(Instruction::I32And);
+  b.emit(Instruction::LocalSet(total));
+
+  // Accounting: request a collection once enough bytes were handed out.
+  store_state(&mut b, GC_ALLOCATED, |b| {
+    load_state(b, GC_ALLOCATED);
+    b.emit(Instruction::LocalGet(total));
+    b.emit(Instruction::I32Add);
+  });
+  load_state(&mut b, GC_ALLOCATED);
+  load_state(&mut b, GC_THRESHOLD);
+  b.emit(Instruction::I32GeU);
+  b.emit(Instruction::If(BlockType::Empty));
+  store_state(&mut b, GC_PENDING, |b| b.emit(Instruction::I32Const(1)));
+  b.emit(Instruction::End);
+
+  // 1) exact-size free list
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::LocalSet(block));
+  b.emit(Instruction::LocalGet(total));
+  b.emit(Instruction::I32Const(GC_SMALL_MAX_BLOCK));
+  b.emit(Instruction::I32LeU);
+  b.emit(Instruction::If(BlockType::Empty));
+  {
+    b.emit(Instruction::LocalGet(total));
+    b.emit(Instruction::I32Const(1));
+    b.emit(Instruction::I32ShrU);
+    b.emit(Instruction::I32Const(GC_FREE_LISTS_ADDR));
+    b.emit(Instruction::I32Add);
+    b.emit(Instruction::LocalTee(tmp));
+    b.emit(Instruction::I32Load(mem_arg_i32(0)));
+    b.emit(Instruction::LocalTee(block));
+    b.emit(Instruction::If(BlockType::Empty));
+    b.emit(Instruction::LocalGet(tmp));
+    b.emit(Instruction::LocalGet(block));
+    b.emit(Instruction::I32Load(mem_arg_i32(4)));
+    b.emit(Instruction::I32Store(mem_arg_i32(0)));
+    b.emit(Instruction::End);
+  }
+  b.emit(Instruction::End);
+
+  // 2) first fit in the large list, splitting off the unused tail
+  b.emit(Instruction::LocalGet(block));
+  b.emit(Instruction::I32Eqz);
+  b.emit(Instruction::If(BlockType::Empty));
+  {
+    b.emit(Instruction::I32Const(0));
+    b.emit(Instruction::LocalSet(prev));
+    load_state(&mut b, GC_LARGE_FREE);
+    b.emit(Instruction::LocalSet(cur));
+    b.emit(Instruction::Block(BlockType::Empty));
+    b.emit(Instruction::Loop(BlockType::Empty));
+    b.emit(Instruction::LocalGet(cur));
+    b.emit(Instruction::I32Eqz);
+    b.emit(Instruction::BrIf(1));
+    b.emit(Instruction::LocalGet(cur));
+    b.emit(Instruction::I32Load(mem_arg_i32(0)));
+    b.emit(Instruction::LocalTee(cur_size));
+    b.emit(Instruction::LocalGet(total));
+    b.emit(Instruction::I32GeU);
+    b.emit(Instruction::If(BlockType::Empty));
+    {
+      // unlink cur
+      b.emit(Instruction::LocalGet(prev));
+      b.emit(Instruction::I32Eqz);
+      b.emit(Instruction::If(BlockType::Empty));
+      store_state(&mut b, GC_LARGE_FREE, |b| {
+        b.emit(Instruction::LocalGet(cur));
+        b.emit(Instruction::I32Load(mem_arg_i32(4)));
+      });
+      b.emit(Instruction::Else);
+      b.emit(Instruction::LocalGet(prev));
+      b.emit(Instruction::LocalGet(cur));
+      b.emit(Instruction::I32Load(mem_arg_i32(4)));
+      b.emit(Instruction::I32Store(mem_arg_i32(4)));
+      b.emit(Instruction::End);
+      b.emit(Instruction::LocalGet(cur));
+      b.emit(Instruction::LocalSet(block));
+      // split when the rest can hold at least a header
+      b.emit(Instruction::LocalGet(cur_size));
+      b.emit(Instruction::LocalGet(total));
+      b.emit(Instruction::I32Sub);
+      b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER));
+      b.emit(Instruction::I32GeU);
+      b.emit(Instruction::If(BlockType::Empty));
+      b.emit(Instruction::LocalGet(cur));
+      b.emit(Instruction::LocalGet(total));
+      b.emit(Instruction::I32Add);
+      b.emit(Instruction::LocalTee(tmp));
+      b.emit(Instruction::LocalGet(cur_size));
+      b.emit(Instruction::LocalGet(total));
+      b.emit(Instruction::I32Sub);
+      b.emit(Instruction::I32Store(mem_arg_i32(0)));
+      b.emit(Instruction::LocalGet(tmp));
+      b.emit(Instruction::Call(free_push_idx));
+      b.emit(Instruction::Else);
+      b.emit(Instruction::LocalGet(cur_size));
+      b.emit(Instruction::LocalSet(total));
+      b.emit(Instruction::End);
+      b.emit(Instruction::Br(2));
+    }
+    b.emit(Instruction::End);
+    b.emit(Instruction::LocalGet(cur));
+    b.emit(Instruction::LocalSet(prev));
+    b.emit(Instruction::LocalGet(cur));
+    b.emit(Instruction::I32Load(mem_arg_i32(4)));
+    b.emit(Instruction::LocalSet(cur));
+    b.emit(Instruction::Br(0));
+    b.emit(Instruction::End);
+    b.emit(Instruction::End);
+  }
+  b.emit(Instruction::End);
+
+  // 3) bump allocation
+  b.emit(Instruction::LocalGet(block));
+  b.emit(Instruction::I32Eqz);
+  b.emit(Instruction::If(BlockType::Empty));
+  {
+    b.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
+    b.emit(Instruction::LocalTee(block));
+    b.emit(Instruction::LocalGet(total));
+    b.emit(Instruction::I32Add);
+    b.emit(Instruction::Call(reserve_idx));
+    b.emit(Instruction::LocalGet(block));
+    b.emit(Instruction::LocalGet(total));
+    b.emit(Instruction::I32Add);
+    b.emit(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
+  }
+  b.emit(Instruction::End);
+
+  // header + zeroed payload
+  b.emit(Instruction::LocalGet(block));
+  b.emit(Instruction::LocalGet(total));
+  b.emit(Instruction::I32Store(mem_arg_i32(0)));
+  b.emit(Instruction::LocalGet(block));
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::I32Store(mem_arg_i32(4)));
+  b.emit(Instruction::LocalGet(block));
+  b.emit(Instruction::I32Const(HEAP_MAGIC));
+  b.emit(Instruction::I32Store(mem_arg_i32(8)));
+  b.emit(Instruction::LocalGet(block));
+  b.emit(Instruction::LocalGet(1));
+  b.emit(Instruction::I32Store(mem_arg_i32(12)));
+  b.emit(Instruction::LocalGet(block));
+  b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::LocalSet(tmp));
+  if zero_fill {
+    b.emit(Instruction::LocalGet(tmp));
+    b.emit(Instruction::I32Const(0));
+    b.emit(Instruction::LocalGet(total));
+    b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER));
+    b.emit(Instruction::I32Sub);
+    b.emit(Instruction::MemoryFill(0));
+  }
+  b.emit(Instruction::LocalGet(tmp));
+  b.finish(vec![ValType::I32, ValType::I32], vec![ValType::I32])
+}
+
+/// `__rt_gc_mark_value(v: f64)` — if `v` is the logical pointer of a live
+/// block that is not yet marked, mark it and push it on the mark stack.
+fn build_rt_gc_mark_value(reserve_idx: u32) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // v
+  let ptr = b.alloc_i32();
+  let i = b.alloc_i32();
+  let addr = b.alloc_i32();
+  let top = b.alloc_i32();
+
+  // HEAP_START + header <= v < heap_ptr, and v is an integer
+  b.emit(Instruction::LocalGet(0));
+  load_state(&mut b, GC_HEAP_START);
+  b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::F64ConvertI32U);
+  b.emit(Instruction::F64Ge);
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
+  b.emit(Instruction::F64ConvertI32U);
+  b.emit(Instruction::F64Lt);
+  b.emit(Instruction::I32And);
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::F64Trunc);
+  b.emit(Instruction::F64Eq);
+  b.emit(Instruction::I32And);
+  b.emit(Instruction::I32Eqz);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::Return);
+  b.emit(Instruction::End);
+
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::I32TruncF64U);
+  b.emit(Instruction::LocalTee(ptr));
+  b.emit(Instruction::I32Const(7));
+  b.emit(Instruction::I32And);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::Return);
+  b.emit(Instruction::End);
+
+  // i = (block - HEAP_START) / 8
+  b.emit(Instruction::LocalGet(ptr));
+  b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER));
+  b.emit(Instruction::I32Sub);
+  load_state(&mut b, GC_HEAP_START);
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::I32Const(3));
+  b.emit(Instruction::I32ShrU);
+  b.emit(Instruction::LocalSet(i));
+
+  emit_test_bit(&mut b, GC_START_MAP, i);
+  b.emit(Instruction::I32Eqz);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::Return);
+  b.emit(Instruction::End);
+  emit_test_bit(&mut b, GC_MARK_MAP, i);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::Return);
+  b.emit(Instruction::End);
+  emit_set_bit(&mut b, GC_MARK_MAP, i, addr);
+
+  // push the block on the mark stack (it lives above the bitmaps)
+  load_state(&mut b, GC_STACK_TOP);
+  b.emit(Instruction::LocalTee(top));
+  b.emit(Instruction::I32Const(4));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::Call(reserve_idx));
+  b.emit(Instruction::LocalGet(top));
+  b.emit(Instruction::LocalGet(ptr));
+  b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER));
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::I32Store(mem_arg_i32(0)));
+  store_state(&mut b, GC_STACK_TOP, |b| {
+    b.emit(Instruction::LocalGet(top));
+    b.emit(Instruction::I32Const(4));
+    b.emit(Instruction::I32Add);
+  });
+  b.finish(vec![ValType::F64], vec![])
+}
+
+/// `__rt_gc_try_begin(depth: i32) → i32` — start a collection when one is
+/// pending and exactly `depth` frames may hold heap values. Lays out the
+/// block-start bitmap, the mark bitmap and the mark stack in the free space
+/// above the heap pointer, then marks the atom globals. Returns 1 when the
+/// caller must mark its roots and call `__rt_gc_finish`.
+fn build_rt_gc_try_begin(reserve_idx: u32, mark_value_idx: u32, atom_globals: &[u32]) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // depth
+  let h0 = b.alloc_i32();
+  let h1 = b.alloc_i32();
+  let map_bytes = b.alloc_i32();
+  let p = b.alloc_i32();
+  let i = b.alloc_i32();
+  let addr = b.alloc_i32();
+
+  load_state(&mut b, GC_PENDING);
+  b.emit(Instruction::I32Eqz);
+  load_state(&mut b, GC_DEPTH);
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::I32Ne);
+  b.emit(Instruction::I32Or);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::Return);
+  b.emit(Instruction::End);
+
+  load_state(&mut b, GC_HEAP_START);
+  b.emit(Instruction::LocalSet(h0));
+  b.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
+  b.emit(Instruction::LocalSet(h1));
+  // one bit per 8 bytes of heap, rounded up to whole 8-byte words
+  b.emit(Instruction::LocalGet(h1));
+  b.emit(Instruction::LocalGet(h0));
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::I32Const(3));
+  b.emit(Instruction::I32ShrU);
+  b.emit(Instruction::I32Const(7));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::I32Const(3));
+  b.emit(Instruction::I32ShrU);
+  b.emit(Instruction::I32Const(7));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::I32Const(-8));
+  b.emit(Instruction::I32And);
+  b.emit(Instruction::LocalSet(map_bytes));
+
+  store_state(&mut b, GC_START_MAP, |b| b.emit(Instruction::LocalGet(h1)));
+  store_state(&mut b, GC_MARK_MAP, |b| {
+    b.emit(Instruction::LocalGet(h1));
+    b.emit(Instruction::LocalGet(map_bytes));
+    b.emit(Instruction::I32Add);
+  });
+  store_state(&mut b, GC_STACK_BASE, |b| {
+    b.emit(Instruction::LocalGet(h1));
+    b.emit(Instruction::LocalGet(map_bytes));
+    b.emit(Instruction::I32Const(1));
+    b.emit(Instruction::I32Shl);
+    b.emit(Instruction::I32Add);
+  });
+  store_state(&mut b, GC_STACK_TOP, |b| load_state(b, GC_STACK_BASE));
+  load_state(&mut b, GC_STACK_BASE);
+  b.emit(Instruction::I32Const(GC_MARK_STACK_RESERVE));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::Call(reserve_idx));
+  b.emit(Instruction::LocalGet(h1));
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::LocalGet(map_bytes));
+  b.emit(Instruction::I32Const(1));
+  b.emit(Instruction::I32Shl);
+  b.emit(Instruction::MemoryFill(0));
+
+  // Walk the heap once to record where live blocks start.
+  b.emit(Instruction::LocalGet(h0));
+  b.emit(Instruction::LocalSet(p));
+  b.emit(Instruction::Block(BlockType::Empty));
+  b.emit(Instruction::Loop(BlockType::Empty));
+  b.emit(Instruction::LocalGet(p));
+  b.emit(Instruction::LocalGet(h1));
+  b.emit(Instruction::I32GeU);
+  b.emit(Instruction::BrIf(1));
+  b.emit(Instruction::LocalGet(p));
+  b.emit(Instruction::I32Load(mem_arg_i32(8)));
+  b.emit(Instruction::I32Const(HEAP_MAGIC));
+  b.emit(Instruction::I32Eq);
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::LocalGet(p));
+  b.emit(Instruction::LocalGet(h0));
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::I32Const(3));
+  b.emit(Instruction::I32ShrU);
+  b.emit(Instruction::LocalSet(i));
+  emit_set_bit(&mut b, GC_START_MAP, i, addr);
+  b.emit(Instruction::End);
+  b.emit(Instruction::LocalGet(p));
+  b.emit(Instruction::LocalGet(p));
+  b.emit(Instruction::I32Load(mem_arg_i32(0)));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::LocalSet(p));
+  b.emit(Instruction::Br(0));
+  b.emit(Instruction::End);
+  b.emit(Instruction::End);
+
+  for &global_idx in atom_globals {
+    b.emit(Instruction::GlobalGet(global_idx));
+    b.emit(Instruction::Call(mark_value_idx));
+  }
+  // The newest shadow frame; older ones are reached through its link slot.
+  load_state(&mut b, GC_SHADOW_TOP);
+  b.emit(Instruction::F64ConvertI32U);
+  b.emit(Instruction::Call(mark_value_idx));
+  b.emit(Instruction::I32Const(1));
+  b.finish(vec![ValType::I32], vec![ValType::I32])
+}
+
+/// `__rt_gc_finish()` — drain the mark stack, then sweep: coalesce runs of
+/// unmarked blocks onto the free lists, return a trailing run to the bump
+/// area and size the next collection threshold after the live bytes.
+fn build_rt_gc_finish(mark_value_idx: u32, free_push_idx: u32, string_tag: i32) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(0);
+  let top = b.alloc_i32();
+  let block = b.alloc_i32();
+  let slot = b.alloc_i32();
+  let end = b.alloc_i32();
+  let p = b.alloc_i32();
+  let size = b.alloc_i32();
+  let run = b.alloc_i32();
+  let live = b.alloc_i32();
+  let h0 = b.alloc_i32();
+  let h1 = b.alloc_i32();
+  let i = b.alloc_i32();
+
+  // --- mark: scan f64 slots of every reachable non-string block ---
+  b.emit(Instruction::Block(BlockType::Empty));
+  b.emit(Instruction::Loop(BlockType::Empty));
+  load_state(&mut b, GC_STACK_TOP);
+  b.emit(Instruction::LocalTee(top));
+  load_state(&mut b, GC_STACK_BASE);
+  b.emit(Instruction::I32Eq);
+  b.emit(Instruction::BrIf(1));
+  b.emit(Instruction::LocalGet(top));
+  b.emit(Instruction::I32Const(4));
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::LocalSet(top));
+  store_state(&mut b, GC_STACK_TOP, |b| b.emit(Instruction::LocalGet(top)));
+  b.emit(Instruction::LocalGet(top));
+  b.emit(Instruction::I32Load(mem_arg_i32(0)));
+  b.emit(Instruction::LocalTee(block));
+  b.emit(Instruction::I32Load(mem_arg_i32(12)));
+  b.emit(Instruction::I32Const(string_tag));
+  b.emit(Instruction::I32Ne);
+  b.emit(Instruction::If(BlockType::Empty));
+  {
+    b.emit(Instruction::LocalGet(block));
+    b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER));
+    b.emit(Instruction::I32Add);
+    b.emit(Instruction::LocalSet(slot));
+    b.emit(Instruction::LocalGet(block));
+    b.emit(Instruction::LocalGet(block));
+    b.emit(Instruction::I32Load(mem_arg_i32(0)));
+    b.emit(Instruction::I32Add);
+    b.emit(Instruction::LocalSet(end));
+    b.emit(Instruction::Block(BlockType::Empty));
+    b.emit(Instruction::Loop(BlockType::Empty));
+    b.emit(Instruction::LocalGet(slot));
+    b.emit(Instruction::LocalGet(end));
+    b.emit(Instruction::I32GeU);
+    b.emit(Instruction::BrIf(1));
+    b.emit(Instruction::LocalGet(slot));
+    b.emit(Instruction::F64Load(mem_arg_f64(0)));
+    b.emit(Instruction::Call(mark_value_idx));
+    b.emit(Instruction::LocalGet(slot));
+    b.emit(Instruction::I32Const(8));
+    b.emit(Instruction::I32Add);
+    b.emit(Instruction::LocalSet(slot));
+    b.emit(Instruction::Br(0));
+    b.emit(Instruction::End);
+    b.emit(Instruction::End);
+  }
+  b.emit(Instruction::End);
+  b.emit(Instruction::Br(0));
+  b.emit(Instruction::End);
+  b.emit(Instruction::End);
+
+  // --- sweep: rebuild the free lists from scratch ---
+  b.emit(Instruction::I32Const(GC_FREE_LISTS_ADDR));
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::I32Const(GC_SMALL_CLASSES * 4));
+  b.emit(Instruction::MemoryFill(0));
+  store_state(&mut b, GC_LARGE_FREE, |b| b.emit(Instruction::I32Const(0)));
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::LocalSet(run));
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::LocalSet(live));
+  load_state(&mut b, GC_HEAP_START);
+  b.emit(Instruction::LocalTee(h0));
+  b.emit(Instruction::LocalSet(p));
+  b.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
+  b.emit(Instruction::LocalSet(h1));
+  b.emit(Instruction::Block(BlockType::Empty));
+  b.emit(Instruction::Loop(BlockType::Empty));
+  b.emit(Instruction::LocalGet(p));
+  b.emit(Instruction::LocalGet(h1));
+  b.emit(Instruction::I32GeU);
+  b.emit(Instruction::BrIf(1));
+  b.emit(Instruction::LocalGet(p));
+  b.emit(Instruction::I32Load(mem_arg_i32(0)));
+  b.emit(Instruction::LocalSet(size));
+  b.emit(Instruction::LocalGet(p));
+  b.emit(Instruction::LocalGet(h0));
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::I32Const(3));
+  b.emit(Instruction::I32ShrU);
+  b.emit(Instruction::LocalSet(i));
+  emit_test_bit(&mut b, GC_MARK_MAP, i);
+  b.emit(Instruction::If(BlockType::Empty));
+  {
+    // live block: close the pending free run
+    b.emit(Instruction::LocalGet(run));
+    b.emit(Instruction::If(BlockType::Empty));
+    b.emit(Instruction::LocalGet(run));
+    b.emit(Instruction::LocalGet(p));
+    b.emit(Instruction::LocalGet(run));
+    b.emit(Instruction::I32Sub);
+    b.emit(Instruction::I32Store(mem_arg_i32(0)));
+    b.emit(Instruction::LocalGet(run));
+    b.emit(Instruction::Call(free_push_idx));
+    b.emit(Instruction::I32Const(0));
+    b.emit(Instruction::LocalSet(run));
+    b.emit(Instruction::End);
+    b.emit(Instruction::LocalGet(live));
+    b.emit(Instruction::LocalGet(size));
+    b.emit(Instruction::I32Add);
+    b.emit(Instruction::LocalSet(live));
+  }
+  b.emit(Instruction::Else);
+  {
+    b.emit(Instruction::LocalGet(run));
+    b.emit(Instruction::I32Eqz);
+    b.emit(Instruction::If(BlockType::Empty));
+    b.emit(Instruction::LocalGet(p));
+    b.emit(Instruction::LocalSet(run));
+    b.emit(Instruction::End);
+  }
+  b.emit(Instruction::End);
+  b.emit(Instruction::LocalGet(p));
+  b.emit(Instruction::LocalGet(size));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::LocalSet(p));
+  b.emit(Instruction::Br(0));
+  b.emit(Instruction::End);
+  b.emit(Instruction::End);
+
+  // A free run reaching the top goes back to the bump area.
+  b.emit(Instruction::LocalGet(run));
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::LocalGet(run));
+  b.emit(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
+  b.emit(Instruction::End);
+
+  store_state(&mut b, GC_LIVE, |b| b.emit(Instruction::LocalGet(live)));
+  store_state(&mut b, GC_ALLOCATED, |b| b.emit(Instruction::I32Const(0)));
+  store_state(&mut b, GC_PENDING, |b| b.emit(Instruction::I32Const(0)));
+  store_state(&mut b, GC_COLLECTIONS, |b| {
+    load_state(b, GC_COLLECTIONS);
+    b.emit(Instruction::I32Const(1));
+    b.emit(Instruction::I32Add);
+  });
+  // next threshold = max(GC_MIN_THRESHOLD, live)
+  store_state(&mut b, GC_THRESHOLD, |b| {
+    b.emit(Instruction::I32Const(GC_MIN_THRESHOLD));
+    b.emit(Instruction::LocalGet(live));
+    b.emit(Instruction::I32Const(GC_MIN_THRESHOLD));
+    b.emit(Instruction::LocalGet(live));
+    b.emit(Instruction::I32GtU);
+    b.emit(Instruction::Select);
+  });
+  b.finish(vec![], vec![])
+}
+
+/// `__rt_gc_enter()` / `__rt_gc_exit()` — adjust the count of frames that may
+/// hold heap values.
+fn build_rt_gc_shift_depth(delta: i32) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(0);
+  store_state(&mut b, GC_DEPTH, |b| {
+    load_state(b, GC_DEPTH);
+    b.emit(Instruction::I32Const(delta));
+    b.emit(Instruction::I32Add);
+  });
+  b.finish(vec![], vec![])
+}
+
+/// `__rt_gc_leave(result: f64) → f64` — function epilogue. When the
+/// outermost frame returns, its result is the only root besides the atoms.
+fn build_rt_gc_leave(try_begin_idx: u32, mark_value_idx: u32, finish_idx: u32) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // result
+  store_state(&mut b, GC_DEPTH, |b| {
+    load_state(b, GC_DEPTH);
+    b.emit(Instruction::I32Const(1));
+    b.emit(Instruction::I32Sub);
+  });
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::Call(try_begin_idx));
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::Call(mark_value_idx));
+  b.emit(Instruction::Call(finish_idx));
+  b.emit(Instruction::End);
+  b.emit(Instruction::LocalGet(0));
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
+
+/// `__rt_gc_frame_push(slots: i32) → i32` — open a shadow frame with room for
+/// `slots` spilled locals and stop counting the caller's frame. Returns the
+/// frame's logical pointer; local `k` goes to payload slot `k + 1`.
+fn build_rt_gc_frame_push(alloc_idx: u32) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // slots
+  let frame = b.alloc_i32();
+  b.emit(Instruction::LocalGet(0));
+  b.emit(Instruction::I32Const(1));
+  b.emit(Instruction::I32Add);
+  b.emit(Instruction::I32Const(3));
+  b.emit(Instruction::I32Shl);
+  b.emit(Instruction::I32Const(GC_FRAME_TAG));
+  b.emit(Instruction::Call(alloc_idx));
+  b.emit(Instruction::LocalTee(frame));
+  load_state(&mut b, GC_SHADOW_TOP);
+  b.emit(Instruction::F64ConvertI32U);
+  b.emit(Instruction::F64Store(mem_arg_f64(0)));
+  store_state(&mut b, GC_SHADOW_TOP, |b| b.emit(Instruction::LocalGet(frame)));
+  store_state(&mut b, GC_DEPTH, |b| {
+    load_state(b, GC_DEPTH);
+    b.emit(Instruction::I32Const(1));
+    b.emit(Instruction::I32Sub);
+  });
+  b.emit(Instruction::LocalGet(frame));
+  b.finish(vec![ValType::I32], vec![ValType::I32])
+}
+
+/// `__rt_gc_frame_pop()` — close the newest shadow frame once the call
+/// returned, count the caller again and give the block straight back to its
+/// free list, so calls do not drive the collection threshold.
+fn build_rt_gc_frame_pop(free_push_idx: u32) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(0);
+  let block = b.alloc_i32();
+  let size = b.alloc_i32();
+  load_state(&mut b, GC_SHADOW_TOP);
+  b.emit(Instruction::I32Const(HEAP_BLOCK_HEADER));
+  b.emit(Instruction::I32Sub);
+  b.emit(Instruction::LocalSet(block));
+  store_state(&mut b, GC_SHADOW_TOP, |b| {
+    b.emit(Instruction::LocalGet(block));
+    b.emit(Instruction::F64Load(mem_arg_f64(HEAP_BLOCK_HEADER as u64)));
+    b.emit(Instruction::I32TruncF64U);
+  });
+  // allocated = allocated >= size ? allocated - size : 0
+  b.emit(Instruction::LocalGet(block));
+  b.emit(Instruction::I32Load(mem_arg_i32(0)));
+  b.emit(Instruction::LocalSet(size));
+  store_state(&mut b, GC_ALLOCATED, |b| {
+    load_state(b, GC_ALLOCATED);
+    b.emit(Instruction::LocalGet(size));
+    b.emit(Instruction::I32Sub);
+    b.emit(Instruction::I32Const(0));
+    load_state(b, GC_ALLOCATED);
+    b.emit(Instruction::LocalGet(size));
+    b.emit(Instruction::I32GeU);
+    b.emit(Instruction::Select);
+  });
+  b.emit(Instruction::LocalGet(block));
+  b.emit(Instruction::Call(free_push_idx));
+  store_state(&mut b, GC_DEPTH, |b| {
+    load_state(b, GC_DEPTH);
+    b.emit(Instruction::I32Const(1));
+    b.emit(Instruction::I32Add);
+  });
+  b.finish(vec![], vec![])
+}
+
+/// `__rt_gc_reset()` — export entry: no frame of this instance is running, so
+/// drop whatever depth and shadow frames a trapped export left behind.
+fn build_rt_gc_reset() -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(0);
+  store_state(&mut b, GC_DEPTH, |b| b.emit(Instruction::I32Const(0)));
+  store_state(&mut b, GC_SHADOW_TOP, |b| b.emit(Instruction::I32Const(0)));
+  b.finish(vec![], vec![])
+}
+
+/// `__gc_collect() → f64` — host-triggered collection between calls; only
+/// atoms survive as roots. Does nothing while an export is running, nor
+/// after an export trapped until the next export entry resets the depth.
+fn build_gc_collect(try_begin_idx: u32, finish_idx: u32) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(0);
+  store_state(&mut b, GC_PENDING, |b| b.emit(Instruction::I32Const(1)));
+  b.emit(Instruction::I32Const(0));
+  b.emit(Instruction::Call(try_begin_idx));
+  b.emit(Instruction::If(BlockType::Empty));
+  b.emit(Instruction::Call(finish_idx));
+  b.emit(Instruction::End);
+  b.emit(f64_const(0.0));
+  b.finish(vec![], vec![ValType::F64])
+}
+
+/// Move `func`'s export onto a wrapper that calls `__rt_gc_reset` and then
+/// forwards to `func` (at `func_idx`). `None` for functions not exported.
+pub(super) fn build_export_entry(func: &mut CompiledFn, func_idx: u32, gc_reset_idx: u32) -> Option<CompiledFn> {
+  let export_name = func.export_name.take()?;
+  let mut instructions = vec![Instruction::Call(gc_reset_idx)];
+  instructions.extend((0..func.params.len() as u32).map(Instruction::LocalGet));
+  instructions.push(Instruction::Call(func_idx));
+  Some(CompiledFn {
+    export_name: Some(export_name),
+    params: func.params.clone(),
+    results: func.results.clone(),
+    locals: vec![],
+    instructions,
+  })
+}
+
+/// `__gc_collections() → f64` — number of completed collections.
+fn build_gc_collections() -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(0);
+  load_state(&mut b, GC_COLLECTIONS);
+  b.emit(Instruction::F64ConvertI32U);
+  b.finish(vec![], vec![ValType::F64])
+}
+
+// ---------------------------------------------------------------------------
+// Hooks emitted into compiled calcit functions
+// ---------------------------------------------------------------------------
+
+/// Function prologue: one more frame may hold heap values.
+pub(super) fn emit_gc_enter(ctx: &mut WasmGenCtx) {
+  ctx.call_rt("__rt_gc_enter");
+}
+
+/// Function epilogue, with the f64 result on the stack.
+pub(super) fn emit_gc_leave(ctx: &mut WasmGenCtx) {
+  ctx.call_rt("__rt_gc_leave");
+}
+
+/// Locals of the function being compiled that may hold heap values: f64
+/// locals and the i32 ones (raw pointers), in declaration order.
+fn root_locals(ctx: &WasmGenCtx) -> Vec<(u32, ValType)> {
+  let param_count = ctx.next_local - ctx.extra_locals.len() as u32;
+  (0..ctx.next_local)
+    .map(|idx| {
+      let ty = if idx < param_count {
+        ValType::F64
+      } else {
+        ctx.extra_locals[(idx - param_count) as usize]
+      };
+      (idx, ty)
+    })
+    .filter(|(_, ty)| matches!(ty, ValType::F64 | ValType::I32))
+    .collect()
+}
+
+/// Push a root local as the f64 a collection scans.
+fn emit_root_local(ctx: &mut WasmGenCtx, idx: u32, ty: ValType) {
+  ctx.emit(Instruction::LocalGet(idx));
+  if ty == ValType::I32 {
+    ctx.emit(Instruction::F64ConvertI32U);
+  }
+}
+
+/// Safepoint for a `recur` in tail position: when this is the only frame that
+/// may hold heap values, every local of the function is a root.
+pub(super) fn emit_gc_safepoint(ctx: &mut WasmGenCtx) {
+  ctx.emit(Instruction::I32Const(1));
+  ctx.call_rt("__rt_gc_try_begin");
+  ctx.begin_block_if();
+  for (idx, ty) in root_locals(ctx) {
+    emit_root_local(ctx, idx, ty);
+    ctx.call_rt("__rt_gc_mark_value");
+  }
+  ctx.call_rt("__rt_gc_finish");
+  ctx.end_one();
+}
+
+/// Direct call to a compiled function. In tail position the caller's frame
+/// holds nothing live while the callee runs, so it is handed over instead of
+/// counted. With an empty operand stack the caller's locals are spilled into
+/// a shadow frame for the duration of the call; otherwise the caller stays
+/// counted.
+pub(super) fn emit_direct_call(ctx: &mut WasmGenCtx, fn_idx: u32, tail: bool, stack_empty: bool) {
+  if tail {
+    ctx.call_rt("__rt_gc_exit");
+    ctx.emit(Instruction::Call(fn_idx));
+    ctx.call_rt("__rt_gc_enter");
+  } else if stack_empty {
+    let frame = match ctx.spill_frame {
+      Some(local) => local,
+      None => {
+        let local = ctx.alloc_local_typed(ValType::I32);
+        ctx.spill_frame = Some(local);
+        local
+      }
+    };
+    let roots = root_locals(ctx);
+    ctx.emit(Instruction::I32Const(roots.len() as i32));
+    ctx.call_rt("__rt_gc_frame_push");
+    ctx.emit(Instruction::LocalSet(frame));
+    for (slot, (idx, ty)) in roots.into_iter().enumerate() {
+      ctx.emit(Instruction::LocalGet(frame));
+      emit_root_local(ctx, idx, ty);
+      ctx.emit(Instruction::F64Store(mem_arg_f64(8 * (slot as u64 + 1))));
+    }
+    ctx.emit(Instruction::Call(fn_idx));
+    ctx.call_rt("__rt_gc_frame_pop");
+  } else {
+    ctx.emit(Instruction::Call(fn_idx));
+  }
+}
@@ -84,63 +84,35 @@ pub(super) fn emit_hash_proc(ctx: &mut WasmGenCtx, args: &[Calcit]) -> Result<()
   Ok(())
 }
 
-/// Bump-allocator emitter with heap type header.
+/// Heap allocation emitter with heap type header.
 ///
-/// Allocates `byte_size + 8` bytes: first 8 bytes store the type tag (f64),
-/// the remaining `byte_size` bytes are the logical payload. Returns the LOGICAL
-/// pointer (= raw_base + 8) in `ptr_local`, so all existing offset math works
-/// unchanged. `type-of` can recover the type by loading at `(ptr - 8)`.
+/// Calls `__rt_alloc(byte_size, tag)`, which reserves a block with the
+/// magic/tag header in front of a zeroed `byte_size`-byte payload. Returns the
+/// LOGICAL pointer in `ptr_local`, so all existing offset math works unchanged.
+/// `type-of` can recover the type by loading at `(ptr - 4)`.
 ///
 /// WAT sketch:
 /// ```wasm
-/// global.get $heap_ptr
-/// f64.const <type_tag>
-/// f64.store offset=0
-/// global.get $heap_ptr
-/// i32.const 8
-/// i32.add
-/// local.tee $ptr_local
 /// i32.const <byte_size>
-/// i32.add
-/// global.set $heap_ptr
+/// i32.const <type_tag>
+/// call $__rt_alloc
+/// local.set $ptr_local
 /// ```
 pub(super) fn emit_bump_alloc(ctx: &mut WasmGenCtx, byte_size: i32, ptr_local: u32, type_tag: &str) {
   let tag_val = get_type_tag(ctx, type_tag) as i32;
-  // Write magic at raw_base+0.
-  ctx.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  ctx.emit(Instruction::I32Const(HEAP_MAGIC));
-  ctx.emit(Instruction::I32Store(mem_arg_i32(0)));
-  // Write tag id at raw_base+4.
-  ctx.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  ctx.emit(Instruction::I32Const(tag_val));
-  ctx.emit(Instruction::I32Store(mem_arg_i32(4)));
-  // Compute logical ptr = base + 8 and save.
-  ctx.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  ctx.emit(Instruction::I32Const(8));
-  ctx.emit(Instruction::I32Add);
-  ctx.emit(Instruction::LocalTee(ptr_local));
-  // Bump by byte_size, yielding new heap_ptr = old_base + 8 + byte_size.
   ctx.emit(Instruction::I32Const(byte_size));
-  ctx.emit(Instruction::I32Add);
-  ctx.emit(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
+  ctx.emit(Instruction::I32Const(tag_val));
+  ctx.call_rt("__rt_alloc");
+  ctx.emit(Instruction::LocalSet(ptr_local));
 }
 
-/// Bump-allocator with dynamic size (i32 local) and heap type header.
+/// Heap allocation with dynamic size (i32 local) and heap type header.
 pub(super) fn emit_bump_alloc_dynamic(ctx: &mut WasmGenCtx, size_local: u32, ptr_local: u32, type_tag: &str) {
   let tag_val = get_type_tag(ctx, type_tag) as i32;
-  ctx.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  ctx.emit(Instruction::I32Const(HEAP_MAGIC));
-  ctx.emit(Instruction::I32Store(mem_arg_i32(0)));
-  ctx.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  ctx.emit(Instruction::I32Const(tag_val));
-  ctx.emit(Instruction::I32Store(mem_arg_i32(4)));
-  ctx.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  ctx.emit(Instruction::I32Const(8));
-  ctx.emit(Instruction::I32Add);
-  ctx.emit(Instruction::LocalTee(ptr_local));
   ctx.emit(Instruction::LocalGet(size_local));
-  ctx.emit(Instruction::I32Add);
-  ctx.emit(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
+  ctx.emit(Instruction::I32Const(tag_val));
+  ctx.call_rt("__rt_alloc");
+  ctx.emit(Instruction::LocalSet(ptr_local));
 }
 
 /// Allocate a nominal `Option` enum using the same memory layout as `%::`.
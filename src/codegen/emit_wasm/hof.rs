@@ -167,7 +167,7 @@ pub(super) enum FoldlCallKind {
   Inline(Vec<String>, Vec<Calcit>),
   /// A native builtin proc (e.g. `&+`, `&merge`).
   Proc(CalcitProc),
-  /// A local variable holding a function table index (f64) — uses call_indirect.
+  /// A local variable holding a function value (table slot or closure) — uses call_indirect.
   /// Value is the WASM local index of the variable.
   Dynamic(u32),
 }
@@ -276,18 +276,8 @@ pub(super) fn emit_foldl_step(ctx: &mut WasmGenCtx, fn_call_kind: &FoldlCallKind
       Ok(())
     }
     FoldlCallKind::Proc(proc) => emit_foldl_proc_call(ctx, proc, acc, elem),
-    // Dynamic dispatch via call_indirect: (acc_f64, elem_f64) → f64. Canonical type 2.
-    FoldlCallKind::Dynamic(fn_local_idx) => {
-      ctx.emit(Instruction::LocalGet(acc));
-      ctx.emit(Instruction::LocalGet(elem));
-      ctx.emit(Instruction::LocalGet(*fn_local_idx));
-      ctx.emit(Instruction::I32TruncF64S);
-      ctx.emit(Instruction::CallIndirect {
-        type_index: 2,
-        table_index: 0,
-      });
-      Ok(())
-    }
+    // Dynamic dispatch via call_indirect: (acc_f64, elem_f64) → f64.
+    FoldlCallKind::Dynamic(fn_local_idx) => emit_fn_value_call(ctx, *fn_local_idx, &[acc, elem]),
   }
 }
 
@@ -547,18 +537,8 @@ fn emit_unary_step(ctx: &mut WasmGenCtx, kind: &FoldlCallKind, arg: u32) -> Resu
       }
       result
     }
-    // Dynamic dispatch via call_indirect: local holds f64 table slot index.
-    // Canonical type 1 = (f64) → f64.
-    FoldlCallKind::Dynamic(fn_local_idx) => {
-      ctx.emit(Instruction::LocalGet(arg));
-      ctx.emit(Instruction::LocalGet(*fn_local_idx));
-      ctx.emit(Instruction::I32TruncF64S);
-      ctx.emit(Instruction::CallIndirect {
-        type_index: 1,
-        table_index: 0,
-      });
-      Ok(())
-    }
+    // Dynamic dispatch via call_indirect: local holds a function value.
+    FoldlCallKind::Dynamic(fn_local_idx) => emit_fn_value_call(ctx, *fn_local_idx, &[arg]),
   }
 }
 
@@ -597,17 +577,12 @@ fn emit_binary_step_ei(ctx: &mut WasmGenCtx, kind: &FoldlCallKind, elem: u32, id
       Ok(())
     }
     FoldlCallKind::Proc(_) => Err("map-indexed proc callee not supported".into()),
-    // Dynamic dispatch: (elem_f64, idx_as_f64) → f64. Canonical type 2.
+    // Dynamic dispatch: (elem_f64, idx_as_f64) → f64.
     FoldlCallKind::Dynamic(fn_local_idx) => {
-      ctx.emit(Instruction::LocalGet(elem));
+      let idx_f64 = ctx.alloc_local();
       ctx.ptr_to_f64(idx);
-      ctx.emit(Instruction::LocalGet(*fn_local_idx));
-      ctx.emit(Instruction::I32TruncF64S);
-      ctx.emit(Instruction::CallIndirect {
-        type_index: 2,
-        table_index: 0,
-      });
-      Ok(())
+      ctx.emit(Instruction::LocalSet(idx_f64));
+      emit_fn_value_call(ctx, *fn_local_idx, &[elem, idx_f64])
     }
   }
 }
@@ -351,7 +351,11 @@ pub(super) fn emit_method_invoke(ctx: &mut WasmGenCtx, name: &str, args: &[Calci
       ctx.emit(Instruction::F64ConvertI32U);
       Ok(())
     }
-    _ => Err(format!("unsupported invoke method in WASM: .{name}")),
+    _ => ctx.unsupported(
+      WasmGapKind::Method,
+      &format!(".{name}"),
+      format!("unsupported invoke method in WASM: .{name}"),
+    ),
   }
 }
 
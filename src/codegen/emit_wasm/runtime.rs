@@ -1,3 +1,4 @@
+use super::gc::{GC_STATE_ADDR, build_gc_fns, gc_initial_state};
 use super::*;
 use std::sync::LazyLock;
 use wasm_encoder::{BlockType, Ieee64};
@@ -6,70 +7,64 @@ use wasm_encoder::{BlockType, Ieee64};
 pub(super) struct HostImport {
   pub(super) module: String,
   pub(super) name: String,
-  pub(super) arity: usize,
+  pub(super) params: Vec<ValType>,
+  pub(super) results: Vec<ValType>,
+}
+
+impl HostImport {
+  /// An import following the calcit value ABI: `(f64 × arity) → f64`.
+  pub(super) fn calcit(module: &str, name: &str, arity: usize) -> Self {
+    HostImport {
+      module: module.into(),
+      name: name.into(),
+      params: vec![ValType::F64; arity],
+      results: vec![ValType::F64],
+    }
+  }
+
+  /// An import with a raw WASM signature (used for WASI system calls).
+  pub(super) fn raw(module: &str, name: &str, params: Vec<ValType>, results: Vec<ValType>) -> Self {
+    HostImport {
+      module: module.into(),
+      name: name.into(),
+      params,
+      results,
+    }
+  }
+
+  pub(super) fn arity(&self) -> usize {
+    self.params.len()
+  }
+
+  /// Key under which the callable for this import is registered in
+  /// `runtime_fn_index`, e.g. `io/log_value`.
+  pub(super) fn key(&self) -> String {
+    format!("{}/{}", self.module, self.name)
+  }
 }
 
 /// List of host-imported functions.
 /// These are provided by the JS environment and indexed before user functions.
+/// With `--target wasi` they are implemented inside the module instead (see `wasi.rs`).
 pub(super) static HOST_IMPORTS: LazyLock<Vec<HostImport>> = LazyLock::new(|| {
   vec![
-    HostImport {
-      module: "math".into(),
-      name: "pow".into(),
-      arity: 2,
-    },
-    HostImport {
-      module: "math".into(),
-      name: "sin".into(),
-      arity: 1,
-    },
-    HostImport {
-      module: "math".into(),
-      name: "cos".into(),
-      arity: 1,
-    },
+    HostImport::calcit("math", "pow", 2),
+    HostImport::calcit("math", "sin", 1),
+    HostImport::calcit("math", "cos", 1),
     // IO: log a single value (f64) — host reads memory to decode type
-    HostImport {
-      module: "io".into(),
-      name: "log_value".into(),
-      arity: 1,
-    },
+    HostImport::calcit("io", "log_value", 1),
     // IO: log a string directly (ptr to heap string) — more efficient than log_value for strings
-    HostImport {
-      module: "io".into(),
-      name: "log_str".into(),
-      arity: 1,
-    },
+    HostImport::calcit("io", "log_str", 1),
     // IO: read file contents as string (ptr to path in heap)
-    HostImport {
-      module: "io".into(),
-      name: "read_file_str".into(),
-      arity: 1,
-    },
+    HostImport::calcit("io", "read_file_str", 1),
     // IO: check if file exists (ptr to path in heap) — returns 1.0 if exists, 0.0 otherwise
-    HostImport {
-      module: "io".into(),
-      name: "file_exists".into(),
-      arity: 1,
-    },
+    HostImport::calcit("io", "file_exists", 1),
     // IO: parse JSON string (ptr to JSON string in heap) — returns parsed value or nil on error
-    HostImport {
-      module: "io".into(),
-      name: "parse_json".into(),
-      arity: 1,
-    },
+    HostImport::calcit("io", "parse_json", 1),
     // IO: get current time in milliseconds
-    HostImport {
-      module: "io".into(),
-      name: "current_time".into(),
-      arity: 0,
-    },
+    HostImport::calcit("io", "current_time", 0),
     // IO: get environment variable (ptr to key in heap) — returns value string or nil
-    HostImport {
-      module: "io".into(),
-      name: "get_env".into(),
-      arity: 1,
-    },
+    HostImport::calcit("io", "get_env", 1),
   ]
 });
 
@@ -106,8 +101,7 @@ pub(super) fn build_wasm_module(
     types.ty().function(vec![ValType::F64; arity as usize], vec![ValType::F64]);
   }
   for imp in host_imports {
-    let params: Vec<ValType> = vec![ValType::F64; imp.arity];
-    types.ty().function(params, vec![ValType::F64]);
+    types.ty().function(imp.params.clone(), imp.results.clone());
   }
   for f in fns {
     types.ty().function(f.params.clone(), f.results.clone());
@@ -239,12 +233,13 @@ pub(super) fn build_wasm_module(
   }
   module.section(&codes);
 
-  // Data section: string literals pre-allocated before the heap
+  // Data section: GC bookkeeping, then string literals pre-allocated before the heap
+  let mut data = wasm_encoder::DataSection::new();
+  data.active(0, &ConstExpr::i32_const(GC_STATE_ADDR), gc_initial_state(heap_start));
   if !string_data.is_empty() {
-    let mut data = wasm_encoder::DataSection::new();
     data.active(0, &ConstExpr::i32_const(HEAP_BASE), string_data.iter().copied());
-    module.section(&data);
   }
+  module.section(&data);
 
   Ok(module.finish())
 }
@@ -254,10 +249,18 @@ pub(super) fn build_runtime_fns(
   map_tag: i32,
   list_tag: i32,
   string_tag: i32,
+  atom_globals: &[u32],
 ) -> (Vec<CompiledFn>, HashMap<String, u32>) {
   let mut fn_index = HashMap::new();
   let mut fns = Vec::new();
 
+  // Heap manager first: every allocating helper below calls `__rt_alloc`.
+  for (name, f) in build_gc_fns(base_index, string_tag, atom_globals) {
+    fn_index.insert(String::from(name), base_index + fns.len() as u32);
+    fns.push(f);
+  }
+  let alloc_idx = *fn_index.get("__rt_alloc").expect("alloc helper");
+
   let copy_name = String::from("__rt_copy_f64_slots");
   fn_index.insert(copy_name.clone(), base_index + fns.len() as u32);
 
@@ -482,7 +485,10 @@ pub(super) fn build_runtime_fns(
 
   let map_root_assoc_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_map_root_assoc"), map_root_assoc_idx);
-  fns.push(build_rt_map_root_assoc(*fn_index.get("__rt_copy_f64_slots").expect("copy helper")));
+  fns.push(build_rt_map_root_assoc(
+    *fn_index.get("__rt_copy_f64_slots").expect("copy helper"),
+    alloc_idx,
+  ));
 
   let map_root_lookup_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_map_root_lookup"), map_root_lookup_idx);
@@ -498,7 +504,7 @@ pub(super) fn build_runtime_fns(
 
   let map_make_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_map_make"), map_make_idx);
-  fns.push(build_rt_map_make(map_tag));
+  fns.push(build_rt_map_make(map_tag, alloc_idx));
 
   let map_from_flat_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_map_from_flat"), map_from_flat_idx);
@@ -510,7 +516,7 @@ pub(super) fn build_runtime_fns(
 
   let map_linearize_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_map_linearize"), map_linearize_idx);
-  fns.push(build_rt_map_linearize(map_root_write_pairs_idx, list_tag));
+  fns.push(build_rt_map_linearize(map_root_write_pairs_idx, list_tag, alloc_idx));
 
   let map_assoc_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_map_assoc"), map_assoc_idx);
@@ -534,6 +540,7 @@ pub(super) fn build_runtime_fns(
     *fn_index.get("__rt_copy_f64_slots").expect("copy helper"),
     map_linearize_idx,
     map_from_flat_idx,
+    alloc_idx,
   ));
 
   // String comparison helper: __rt_str_compare(ptr_a: i32, ptr_b: i32) → f64
@@ -563,22 +570,22 @@ pub(super) fn build_runtime_fns(
   // Number-to-string: __rt_f64_to_str(value: f64) → i32 (string logical ptr)
   let f64_to_str_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_f64_to_str"), f64_to_str_idx);
-  fns.push(build_rt_f64_to_str(string_tag));
+  fns.push(build_rt_f64_to_str(string_tag, alloc_idx));
 
   // Radix display: __rt_display_by(value: f64, radix: f64) → f64 (string logical ptr as f64)
   let display_by_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_display_by"), display_by_idx);
-  fns.push(build_rt_display_by(string_tag));
+  fns.push(build_rt_display_by(string_tag, alloc_idx));
 
   // Trim whitespace: __rt_trim_ws(s: f64) → f64 (trimmed string ptr)
   let trim_ws_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_trim_ws"), trim_ws_idx);
-  fns.push(build_rt_trim_ws(string_tag));
+  fns.push(build_rt_trim_ws(string_tag, alloc_idx));
 
   // Trim char: __rt_trim_char(s: f64, c: f64) → f64 (trimmed string ptr)
   let trim_char_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_trim_char"), trim_char_idx);
-  fns.push(build_rt_trim_char(string_tag));
+  fns.push(build_rt_trim_char(string_tag, alloc_idx));
 
   // blank?: __rt_blank(s: f64) → f64 (1.0 if blank, 0.0 otherwise)
   let blank_idx = base_index + fns.len() as u32;
@@ -593,12 +600,12 @@ pub(super) fn build_runtime_fns(
   // char-from-code: __rt_char_from_code(cp: f64) → f64 (single-char string ptr)
   let char_from_code_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_char_from_code"), char_from_code_idx);
-  fns.push(build_rt_char_from_code(string_tag));
+  fns.push(build_rt_char_from_code(string_tag, alloc_idx));
 
   // str-replace: __rt_str_replace(s:f64, pat:f64, rep:f64) → f64 (new string ptr)
   let str_replace_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_str_replace"), str_replace_idx);
-  fns.push(build_rt_str_replace(string_tag));
+  fns.push(build_rt_str_replace(string_tag, alloc_idx));
 
   // str-escape: __rt_str_escape(s:f64) → f64 (new string ptr with escaped chars)
   let str_escape_idx = base_index + fns.len() as u32;
@@ -625,7 +632,13 @@ pub(super) fn build_runtime_fns(
   // str-split: __rt_str_split(s_ptr: i32, pat_ptr: i32) → i32 (list ptr)
   let str_split_idx = base_index + fns.len() as u32;
   fn_index.insert(String::from("__rt_str_split"), str_split_idx);
-  fns.push(build_rt_str_split(string_tag, list_tag, str_find_from_idx, utf8_char_len_idx));
+  fns.push(build_rt_str_split(
+    string_tag,
+    list_tag,
+    str_find_from_idx,
+    utf8_char_len_idx,
+    alloc_idx,
+  ));
 
   // value-equal: __rt_value_equal(a: f64, b: f64) → i32 (1=equal, 0=not)
   // Deep equality for all heap types (strings, lists). For other types: f64 equality.
@@ -642,14 +655,14 @@ const RT_MAP_TABLE_CHILDREN: i32 = 32;
 const RT_MAP_TABLE_SLOTS: i32 = 33; // kind + 32 child pointers
 const RT_MAP_TABLE_BYTES: i32 = RT_MAP_TABLE_SLOTS * 8;
 
-struct RuntimeFnBuilder {
+pub(super) struct RuntimeFnBuilder {
   locals: Vec<ValType>,
   next_local: u32,
   instructions: Vec<Instruction<'static>>,
 }
 
 impl RuntimeFnBuilder {
-  fn new(param_count: u32) -> Self {
+  pub(super) fn new(param_count: u32) -> Self {
     Self {
       locals: Vec::new(),
       next_local: param_count,
@@ -657,25 +670,36 @@ impl RuntimeFnBuilder {
     }
   }
 
-  fn alloc_i32(&mut self) -> u32 {
+  pub(super) fn alloc_i32(&mut self) -> u32 {
     let idx = self.next_local;
     self.next_local += 1;
     self.locals.push(ValType::I32);
     idx
   }
 
-  fn alloc_f64(&mut self) -> u32 {
+  pub(super) fn alloc_f64(&mut self) -> u32 {
     let idx = self.next_local;
     self.next_local += 1;
     self.locals.push(ValType::F64);
     idx
   }
 
-  fn emit(&mut self, instr: Instruction<'static>) {
+  pub(super) fn alloc_i64(&mut self) -> u32 {
+    let idx = self.next_local;
+    self.next_local += 1;
+    self.locals.push(ValType::I64);
+    idx
+  }
+
+  pub(super) fn emit(&mut self, instr: Instruction<'static>) {
     self.instructions.push(instr);
   }
 
-  fn finish(self, params: Vec<ValType>, results: Vec<ValType>) -> CompiledFn {
+  pub(super) fn emit_all<const N: usize>(&mut self, instrs: [Instruction<'static>; N]) {
+    self.instructions.extend(instrs);
+  }
+
+  pub(super) fn finish(self, params: Vec<ValType>, results: Vec<ValType>) -> CompiledFn {
     CompiledFn {
       export_name: None,
       params,
@@ -686,48 +710,20 @@ impl RuntimeFnBuilder {
   }
 }
 
-fn rt_emit_alloc_const(builder: &mut RuntimeFnBuilder, byte_size: i32, dst_local: u32, type_tag: i32) {
-  let raw = builder.alloc_i32();
-  builder.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  builder.emit(Instruction::LocalTee(raw));
-  builder.emit(Instruction::I32Const(HEAP_MAGIC));
-  builder.emit(Instruction::I32Store(mem_arg_i32(0)));
-  builder.emit(Instruction::LocalGet(raw));
-  builder.emit(Instruction::I32Const(4));
-  builder.emit(Instruction::I32Add);
+/// Allocate `byte_size` payload bytes through `__rt_alloc` and store the logical
+/// pointer into `dst_local`.
+fn rt_emit_alloc_const(builder: &mut RuntimeFnBuilder, alloc_idx: u32, byte_size: i32, dst_local: u32, type_tag: i32) {
+  builder.emit(Instruction::I32Const(byte_size));
   builder.emit(Instruction::I32Const(type_tag));
-  builder.emit(Instruction::I32Store(mem_arg_i32(0)));
-  builder.emit(Instruction::LocalGet(raw));
-  builder.emit(Instruction::I32Const(8));
-  builder.emit(Instruction::I32Add);
+  builder.emit(Instruction::Call(alloc_idx));
   builder.emit(Instruction::LocalSet(dst_local));
-  builder.emit(Instruction::LocalGet(raw));
-  builder.emit(Instruction::I32Const(byte_size + 8));
-  builder.emit(Instruction::I32Add);
-  builder.emit(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
 }
 
-fn rt_emit_alloc_dynamic(builder: &mut RuntimeFnBuilder, size_local: u32, dst_local: u32, type_tag: i32) {
-  let raw = builder.alloc_i32();
-  builder.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  builder.emit(Instruction::LocalTee(raw));
-  builder.emit(Instruction::I32Const(HEAP_MAGIC));
-  builder.emit(Instruction::I32Store(mem_arg_i32(0)));
-  builder.emit(Instruction::LocalGet(raw));
-  builder.emit(Instruction::I32Const(4));
-  builder.emit(Instruction::I32Add);
+fn rt_emit_alloc_dynamic(builder: &mut RuntimeFnBuilder, alloc_idx: u32, size_local: u32, dst_local: u32, type_tag: i32) {
+  builder.emit(Instruction::LocalGet(size_local));
   builder.emit(Instruction::I32Const(type_tag));
-  builder.emit(Instruction::I32Store(mem_arg_i32(0)));
-  builder.emit(Instruction::LocalGet(raw));
-  builder.emit(Instruction::I32Const(8));
-  builder.emit(Instruction::I32Add);
+  builder.emit(Instruction::Call(alloc_idx));
   builder.emit(Instruction::LocalSet(dst_local));
-  builder.emit(Instruction::LocalGet(raw));
-  builder.emit(Instruction::I32Const(8));
-  builder.emit(Instruction::I32Add);
-  builder.emit(Instruction::LocalGet(size_local));
-  builder.emit(Instruction::I32Add);
-  builder.emit(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
 }
 
 fn rt_emit_table_child_addr(builder: &mut RuntimeFnBuilder, table_local: u32, child_idx_local: u32, dst_local: u32) {
@@ -754,8 +750,8 @@ fn rt_emit_load_table_child(builder: &mut RuntimeFnBuilder, table_local: u32, ch
   builder.emit(Instruction::LocalSet(dst_local));
 }
 
-fn rt_emit_alloc_empty_table(builder: &mut RuntimeFnBuilder, dst_local: u32) {
-  rt_emit_alloc_const(builder, RT_MAP_TABLE_BYTES, dst_local, 0);
+fn rt_emit_alloc_empty_table(builder: &mut RuntimeFnBuilder, alloc_idx: u32, dst_local: u32) {
+  rt_emit_alloc_const(builder, alloc_idx, RT_MAP_TABLE_BYTES, dst_local, 0);
   builder.emit(Instruction::LocalGet(dst_local));
   builder.emit(f64_const(RT_MAP_TABLE_KIND));
   builder.emit(Instruction::F64Store(mem_arg_f64(0)));
@@ -783,7 +779,7 @@ fn rt_emit_alloc_empty_table(builder: &mut RuntimeFnBuilder, dst_local: u32) {
   builder.emit(Instruction::End);
 }
 
-fn rt_emit_alloc_bucket(builder: &mut RuntimeFnBuilder, count_local: u32, dst_local: u32) {
+fn rt_emit_alloc_bucket(builder: &mut RuntimeFnBuilder, alloc_idx: u32, count_local: u32, dst_local: u32) {
   let slots = builder.alloc_i32();
   let size = builder.alloc_i32();
   builder.emit(Instruction::LocalGet(count_local));
@@ -796,7 +792,7 @@ fn rt_emit_alloc_bucket(builder: &mut RuntimeFnBuilder, count_local: u32, dst_lo
   builder.emit(Instruction::I32Const(8));
   builder.emit(Instruction::I32Mul);
   builder.emit(Instruction::LocalSet(size));
-  rt_emit_alloc_dynamic(builder, size, dst_local, 0);
+  rt_emit_alloc_dynamic(builder, alloc_idx, size, dst_local, 0);
   builder.emit(Instruction::LocalGet(dst_local));
   builder.emit(f64_const(RT_MAP_BUCKET_KIND));
   builder.emit(Instruction::F64Store(mem_arg_f64(0)));
@@ -883,10 +879,10 @@ fn build_rt_hash_f64() -> CompiledFn {
   b.finish(vec![ValType::F64], vec![ValType::I32])
 }
 
-fn build_rt_map_make(map_tag: i32) -> CompiledFn {
+fn build_rt_map_make(map_tag: i32, alloc_idx: u32) -> CompiledFn {
   let mut b = RuntimeFnBuilder::new(2);
   let dst = b.alloc_i32();
-  rt_emit_alloc_const(&mut b, 16, dst, map_tag);
+  rt_emit_alloc_const(&mut b, alloc_idx, 16, dst, map_tag);
   b.emit(Instruction::LocalGet(dst));
   b.emit(Instruction::LocalGet(0));
   b.emit(Instruction::F64ConvertI32U);
@@ -899,7 +895,7 @@ fn build_rt_map_make(map_tag: i32) -> CompiledFn {
   b.finish(vec![ValType::I32, ValType::I32], vec![ValType::I32])
 }
 
-fn build_rt_map_root_assoc(copy_fn_idx: u32) -> CompiledFn {
+fn build_rt_map_root_assoc(copy_fn_idx: u32, alloc_idx: u32) -> CompiledFn {
   let mut b = RuntimeFnBuilder::new(4); // root, key, value, hash
   let idx0 = b.alloc_i32();
   let idx1 = b.alloc_i32();
@@ -930,11 +926,11 @@ fn build_rt_map_root_assoc(copy_fn_idx: u32) -> CompiledFn {
   b.emit(Instruction::LocalGet(0));
   b.emit(Instruction::I32Eqz);
   b.emit(Instruction::If(wasm_encoder::BlockType::Empty));
-  rt_emit_alloc_empty_table(&mut b, new_root);
-  rt_emit_alloc_empty_table(&mut b, new_table1);
+  rt_emit_alloc_empty_table(&mut b, alloc_idx, new_root);
+  rt_emit_alloc_empty_table(&mut b, alloc_idx, new_table1);
   b.emit(Instruction::I32Const(1));
   b.emit(Instruction::LocalSet(bucket_count));
-  rt_emit_alloc_bucket(&mut b, bucket_count, new_bucket);
+  rt_emit_alloc_bucket(&mut b, alloc_idx, bucket_count, new_bucket);
   b.emit(Instruction::LocalGet(new_bucket));
   b.emit(Instruction::LocalGet(1));
   b.emit(Instruction::F64Store(mem_arg_f64(16)));
@@ -959,14 +955,14 @@ fn build_rt_map_root_assoc(copy_fn_idx: u32) -> CompiledFn {
   b.emit(Instruction::LocalGet(table1));
   b.emit(Instruction::I32Eqz);
   b.emit(Instruction::If(wasm_encoder::BlockType::Empty));
-  rt_emit_alloc_const(&mut b, RT_MAP_TABLE_BYTES, new_root, 0);
+  rt_emit_alloc_const(&mut b, alloc_idx, RT_MAP_TABLE_BYTES, new_root, 0);
   b.emit(Instruction::I32Const(RT_MAP_TABLE_SLOTS));
   b.emit(Instruction::LocalSet(slots));
   rt_emit_copy_slots(&mut b, copy_fn_idx, new_root, 0, slots);
-  rt_emit_alloc_empty_table(&mut b, new_table1);
+  rt_emit_alloc_empty_table(&mut b, alloc_idx, new_table1);
   b.emit(Instruction::I32Const(1));
   b.emit(Instruction::LocalSet(bucket_count));
-  rt_emit_alloc_bucket(&mut b, bucket_count, new_bucket);
+  rt_emit_alloc_bucket(&mut b, alloc_idx, bucket_count, new_bucket);
   b.emit(Instruction::LocalGet(new_bucket));
   b.emit(Instruction::LocalGet(1));
   b.emit(Instruction::F64Store(mem_arg_f64(16)));
@@ -991,17 +987,17 @@ fn build_rt_map_root_assoc(copy_fn_idx: u32) -> CompiledFn {
   b.emit(Instruction::LocalGet(bucket));
   b.emit(Instruction::I32Eqz);
   b.emit(Instruction::If(wasm_encoder::BlockType::Empty));
-  rt_emit_alloc_const(&mut b, RT_MAP_TABLE_BYTES, new_root, 0);
+  rt_emit_alloc_const(&mut b, alloc_idx, RT_MAP_TABLE_BYTES, new_root, 0);
   b.emit(Instruction::I32Const(RT_MAP_TABLE_SLOTS));
   b.emit(Instruction::LocalSet(slots));
   rt_emit_copy_slots(&mut b, copy_fn_idx, new_root, 0, slots);
-  rt_emit_alloc_const(&mut b, RT_MAP_TABLE_BYTES, new_table1, 0);
+  rt_emit_alloc_const(&mut b, alloc_idx, RT_MAP_TABLE_BYTES, new_table1, 0);
   b.emit(Instruction::I32Const(RT_MAP_TABLE_SLOTS));
   b.emit(Instruction::LocalSet(slots));
   rt_emit_copy_slots(&mut b, copy_fn_idx, new_table1, table1, slots);
   b.emit(Instruction::I32Const(1));
   b.emit(Instruction::LocalSet(bucket_count));
-  rt_emit_alloc_bucket(&mut b, bucket_count, new_bucket);
+  rt_emit_alloc_bucket(&mut b, alloc_idx, bucket_count, new_bucket);
   b.emit(Instruction::LocalGet(new_bucket));
   b.emit(Instruction::LocalGet(1));
   b.emit(Instruction::F64Store(mem_arg_f64(16)));
@@ -1068,7 +1064,7 @@ fn build_rt_map_root_assoc(copy_fn_idx: u32) -> CompiledFn {
   b.emit(Instruction::I32Const(1));
   b.emit(Instruction::I32Add);
   b.emit(Instruction::LocalSet(slots));
-  rt_emit_alloc_bucket(&mut b, slots, new_bucket);
+  rt_emit_alloc_bucket(&mut b, alloc_idx, slots, new_bucket);
   b.emit(Instruction::LocalGet(bucket_count));
   b.emit(Instruction::I32Const(2));
   b.emit(Instruction::I32Mul);
@@ -1098,7 +1094,7 @@ fn build_rt_map_root_assoc(copy_fn_idx: u32) -> CompiledFn {
   b.emit(Instruction::I32Const(1));
   b.emit(Instruction::LocalSet(added));
   b.emit(Instruction::Else);
-  rt_emit_alloc_bucket(&mut b, bucket_count, new_bucket);
+  rt_emit_alloc_bucket(&mut b, alloc_idx, bucket_count, new_bucket);
   b.emit(Instruction::LocalGet(bucket_count));
   b.emit(Instruction::I32Const(2));
   b.emit(Instruction::I32Mul);
@@ -1119,11 +1115,11 @@ fn build_rt_map_root_assoc(copy_fn_idx: u32) -> CompiledFn {
   b.emit(Instruction::LocalSet(added));
   b.emit(Instruction::End);
 
-  rt_emit_alloc_const(&mut b, RT_MAP_TABLE_BYTES, new_root, 0);
+  rt_emit_alloc_const(&mut b, alloc_idx, RT_MAP_TABLE_BYTES, new_root, 0);
   b.emit(Instruction::I32Const(RT_MAP_TABLE_SLOTS));
   b.emit(Instruction::LocalSet(slots));
   rt_emit_copy_slots(&mut b, copy_fn_idx, new_root, 0, slots);
-  rt_emit_alloc_const(&mut b, RT_MAP_TABLE_BYTES, new_table1, 0);
+  rt_emit_alloc_const(&mut b, alloc_idx, RT_MAP_TABLE_BYTES, new_table1, 0);
   b.emit(Instruction::I32Const(RT_MAP_TABLE_SLOTS));
   b.emit(Instruction::LocalSet(slots));
   rt_emit_copy_slots(&mut b, copy_fn_idx, new_table1, table1, slots);
@@ -1594,7 +1590,7 @@ fn build_rt_map_root_from_flat(hash_idx: u32, root_assoc_idx: u32) -> CompiledFn
   b.finish(vec![ValType::I32], vec![ValType::I32])
 }
 
-fn build_rt_map_linearize(root_write_pairs_idx: u32, list_tag: i32) -> CompiledFn {
+fn build_rt_map_linearize(root_write_pairs_idx: u32, list_tag: i32, alloc_idx: u32) -> CompiledFn {
   let mut b = RuntimeFnBuilder::new(1);
   let count = b.alloc_i32();
   let root = b.alloc_i32();
@@ -1621,7 +1617,7 @@ fn build_rt_map_linearize(root_write_pairs_idx: u32, list_tag: i32) -> CompiledF
   b.emit(Instruction::I32Const(8));
   b.emit(Instruction::I32Mul);
   b.emit(Instruction::LocalSet(size));
-  rt_emit_alloc_dynamic(&mut b, size, dst, list_tag);
+  rt_emit_alloc_dynamic(&mut b, alloc_idx, size, dst, list_tag);
   b.emit(Instruction::LocalGet(dst));
   b.emit(Instruction::LocalGet(count));
   b.emit(Instruction::F64ConvertI32U);
@@ -1734,7 +1730,7 @@ fn build_rt_map_contains_value(root_contains_value_idx: u32) -> CompiledFn {
   b.finish(vec![ValType::I32, ValType::F64], vec![ValType::I32])
 }
 
-fn build_rt_map_dissoc(copy_fn_idx: u32, map_linearize_idx: u32, map_from_flat_idx: u32) -> CompiledFn {
+fn build_rt_map_dissoc(copy_fn_idx: u32, map_linearize_idx: u32, map_from_flat_idx: u32, alloc_idx: u32) -> CompiledFn {
   let mut b = RuntimeFnBuilder::new(2);
   let count = b.alloc_i32();
   let flat = b.alloc_i32();
@@ -1815,7 +1811,7 @@ fn build_rt_map_dissoc(copy_fn_idx: u32, map_linearize_idx: u32, map_from_flat_i
   b.emit(Instruction::I32Const(8));
   b.emit(Instruction::I32Mul);
   b.emit(Instruction::LocalSet(size));
-  rt_emit_alloc_dynamic(&mut b, size, dst, 0);
+  rt_emit_alloc_dynamic(&mut b, alloc_idx, size, dst, 0);
   b.emit(Instruction::LocalGet(dst));
   b.emit(Instruction::LocalGet(new_count));
   b.emit(Instruction::F64ConvertI32U);
@@ -2357,7 +2353,7 @@ fn build_rt_str_ends_with() -> CompiledFn {
 /// `__rt_f64_to_str(value: f64) → i32`
 /// Converts a number to a heap-allocated string.
 /// Handles integers (positive, negative, zero). Non-integers get "number".
-fn build_rt_f64_to_str(string_tag: i32) -> CompiledFn {
+fn build_rt_f64_to_str(string_tag: i32, alloc_idx: u32) -> CompiledFn {
   // param 0: value (f64)
   // locals: 1=raw_i64(i64), 2=neg(i32), 3=abs_i64(i64), 4=ndigits(i32),
   //          5=tmp_i64(i64), 6=payload(i32), 7=str_ptr(i32), 8=content(i32),
@@ -2437,35 +2433,16 @@ fn build_rt_f64_to_str(string_tag: i32) -> CompiledFn {
   b.push(Instruction::I32Add); // payload = 8 + padded_total_len
   b.push(Instruction::LocalSet(6)); // payload
 
-  // Allocate string: raw_base = HEAP_PTR_GLOBAL
-  b.push(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  b.push(Instruction::LocalTee(11)); // raw_base
-  // Write HEAP_MAGIC at raw_base
-  b.push(Instruction::I32Const(HEAP_MAGIC));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  // Write string_tag at raw_base+4
-  b.push(Instruction::LocalGet(11));
-  b.push(Instruction::I32Const(4));
-  b.push(Instruction::I32Add);
+  // Allocate string: str_ptr = __rt_alloc(payload, string_tag)
+  b.push(Instruction::LocalGet(6));
   b.push(Instruction::I32Const(string_tag));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  // str_ptr = raw_base + 8
-  b.push(Instruction::LocalGet(11));
-  b.push(Instruction::I32Const(8));
-  b.push(Instruction::I32Add);
+  b.push(Instruction::Call(alloc_idx));
   b.push(Instruction::LocalSet(7)); // str_ptr (logical)
   // content = str_ptr + 8 (after byte_len slot)
   b.push(Instruction::LocalGet(7));
   b.push(Instruction::I32Const(8));
   b.push(Instruction::I32Add);
   b.push(Instruction::LocalSet(8)); // content base
-  // Advance heap_ptr by payload
-  b.push(Instruction::LocalGet(11));
-  b.push(Instruction::I32Const(8));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::LocalGet(6));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
 
   // Write byte_len = total_len (ndigits + neg) at str_ptr+0
   b.push(Instruction::LocalGet(7));
@@ -2539,24 +2516,10 @@ fn build_rt_f64_to_str(string_tag: i32) -> CompiledFn {
   // --- non-integer branch: allocate string "number" ---
   // "number" = 6 bytes: 110 117 109 98 101 114 (0x6e,0x75,0x6d,0x62,0x65,0x72)
   // payload = 8 (byte_len) + 8 (padded 6 bytes to 8)
-  b.push(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  b.push(Instruction::LocalTee(11));
-  b.push(Instruction::I32Const(HEAP_MAGIC));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  b.push(Instruction::LocalGet(11));
-  b.push(Instruction::I32Const(4));
-  b.push(Instruction::I32Add);
+  b.push(Instruction::I32Const(16)); // 8 byte_len + 8 padded bytes
   b.push(Instruction::I32Const(string_tag));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  b.push(Instruction::LocalGet(11));
-  b.push(Instruction::I32Const(8));
-  b.push(Instruction::I32Add);
+  b.push(Instruction::Call(alloc_idx));
   b.push(Instruction::LocalSet(7)); // str_ptr
-  // Advance heap_ptr by 16 (8 byte_len + 8 padded content)
-  b.push(Instruction::LocalGet(11));
-  b.push(Instruction::I32Const(24)); // 8 header + 8 byte_len + 8 padded bytes
-  b.push(Instruction::I32Add);
-  b.push(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
   // byte_len = 6
   b.push(Instruction::LocalGet(7));
   b.push(Instruction::F64Const(Ieee64::from(6.0f64)));
@@ -2617,7 +2580,7 @@ fn build_rt_f64_to_str(string_tag: i32) -> CompiledFn {
 /// Prefixes: radix 2 → "0b", radix 8 → "0o", radix 16 → "0x", else no prefix.
 /// Negative values get a "-" prefix.
 #[allow(clippy::vec_init_then_push)]
-fn build_rt_display_by(string_tag: i32) -> CompiledFn {
+fn build_rt_display_by(string_tag: i32, alloc_idx: u32) -> CompiledFn {
   // params: 0=value(f64), 1=radix(f64)
   // locals (allocated manually to match flat vec):
   //   2=radix_i64(i64), 3=is_neg(i32), 4=abs_i64(i64), 5=tmp_i64(i64),
@@ -2718,33 +2681,16 @@ fn build_rt_display_by(string_tag: i32) -> CompiledFn {
   b.push(Instruction::I32Add);
   b.push(Instruction::LocalSet(16)); // payload
 
-  // Allocate string: raw_base = HEAP_PTR_GLOBAL
-  b.push(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  b.push(Instruction::LocalTee(14)); // raw_base
-  b.push(Instruction::I32Const(HEAP_MAGIC));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  b.push(Instruction::LocalGet(14));
-  b.push(Instruction::I32Const(4));
-  b.push(Instruction::I32Add);
+  // Allocate string: str_ptr = __rt_alloc(payload, string_tag)
+  b.push(Instruction::LocalGet(16));
   b.push(Instruction::I32Const(string_tag));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  // str_ptr = raw_base + 8
-  b.push(Instruction::LocalGet(14));
-  b.push(Instruction::I32Const(8));
-  b.push(Instruction::I32Add);
+  b.push(Instruction::Call(alloc_idx));
   b.push(Instruction::LocalSet(10)); // str_ptr (logical)
   // content = str_ptr + 8
   b.push(Instruction::LocalGet(10));
   b.push(Instruction::I32Const(8));
   b.push(Instruction::I32Add);
   b.push(Instruction::LocalSet(11)); // content
-  // Advance heap_ptr: raw_base + 8 + payload
-  b.push(Instruction::LocalGet(14));
-  b.push(Instruction::I32Const(8));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::LocalGet(16));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
 
   // Write byte_len at str_ptr+0
   b.push(Instruction::LocalGet(10));
@@ -2950,7 +2896,7 @@ fn emit_is_ws_check(b: &mut Vec<Instruction<'static>>, loc: u32) {
 /// Strips leading and trailing ASCII whitespace from a heap string.
 /// Returns a new heap-allocated string.
 #[allow(clippy::vec_init_then_push)]
-fn build_rt_trim_ws(str_tag: i32) -> CompiledFn {
+fn build_rt_trim_ws(str_tag: i32, alloc_idx: u32) -> CompiledFn {
   // params: 0=s(f64)
   // locals: 1=ptr(i32), 2=byte_len(i32), 3=content(i32), 4=start(i32),
   //         5=end(i32), 6=new_len(i32), 7=raw_base(i32), 8=new_ptr(i32),
@@ -3044,33 +2990,18 @@ fn build_rt_trim_ws(str_tag: i32) -> CompiledFn {
   b.push(Instruction::I32And);
   b.push(Instruction::LocalSet(9));
 
-  // Allocate heap string
-  b.push(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  b.push(Instruction::LocalTee(7)); // raw_base
-  b.push(Instruction::I32Const(HEAP_MAGIC));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  b.push(Instruction::LocalGet(7));
-  b.push(Instruction::I32Const(4));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::I32Const(str_tag));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  // new_ptr = raw_base + 8
-  b.push(Instruction::LocalGet(7));
+  // Allocate heap string: new_ptr = __rt_alloc(8 + padded, str_tag)
   b.push(Instruction::I32Const(8));
+  b.push(Instruction::LocalGet(9));
   b.push(Instruction::I32Add);
+  b.push(Instruction::I32Const(str_tag));
+  b.push(Instruction::Call(alloc_idx));
   b.push(Instruction::LocalSet(8));
   // byte_len at new_ptr+0
   b.push(Instruction::LocalGet(8));
   b.push(Instruction::LocalGet(6));
   b.push(Instruction::F64ConvertI32U);
   b.push(Instruction::F64Store(mem_arg_f64(0)));
-  // advance heap: raw_base + 16 + padded
-  b.push(Instruction::LocalGet(7));
-  b.push(Instruction::I32Const(16));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::LocalGet(9));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
   // memory.copy(new_ptr+8, content+start, new_len)
   b.push(Instruction::LocalGet(8));
   b.push(Instruction::I32Const(8));
@@ -3108,7 +3039,7 @@ fn build_rt_trim_ws(str_tag: i32) -> CompiledFn {
 ///
 /// Strips the first byte of the `c` string from both ends of `s`.
 #[allow(clippy::vec_init_then_push)]
-fn build_rt_trim_char(str_tag: i32) -> CompiledFn {
+fn build_rt_trim_char(str_tag: i32, alloc_idx: u32) -> CompiledFn {
   // params: 0=s(f64), 1=c(f64) (char string ptr)
   // locals: 2=ptr(i32), 3=byte_len(i32), 4=content(i32), 5=start(i32),
   //         6=end(i32), 7=new_len(i32), 8=raw_base(i32), 9=new_ptr(i32),
@@ -3212,30 +3143,17 @@ fn build_rt_trim_char(str_tag: i32) -> CompiledFn {
   b.push(Instruction::I32And);
   b.push(Instruction::LocalSet(10));
 
-  // Allocate heap string
-  b.push(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  b.push(Instruction::LocalTee(8));
-  b.push(Instruction::I32Const(HEAP_MAGIC));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  b.push(Instruction::LocalGet(8));
-  b.push(Instruction::I32Const(4));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::I32Const(str_tag));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  b.push(Instruction::LocalGet(8));
+  // Allocate heap string: __rt_alloc(8 + padded, str_tag)
   b.push(Instruction::I32Const(8));
+  b.push(Instruction::LocalGet(10));
   b.push(Instruction::I32Add);
+  b.push(Instruction::I32Const(str_tag));
+  b.push(Instruction::Call(alloc_idx));
   b.push(Instruction::LocalSet(9));
   b.push(Instruction::LocalGet(9));
   b.push(Instruction::LocalGet(7));
   b.push(Instruction::F64ConvertI32U);
   b.push(Instruction::F64Store(mem_arg_f64(0)));
-  b.push(Instruction::LocalGet(8));
-  b.push(Instruction::I32Const(16));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::LocalGet(10));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
   b.push(Instruction::LocalGet(9));
   b.push(Instruction::I32Const(8));
   b.push(Instruction::I32Add);
@@ -3574,7 +3492,7 @@ fn build_rt_parse_float() -> CompiledFn {
 /// Encodes a Unicode codepoint (u32) as a UTF-8 string and allocates it on the heap.
 /// Returns the logical string pointer as f64.
 #[allow(clippy::vec_init_then_push)]
-fn build_rt_char_from_code(str_tag: i32) -> CompiledFn {
+fn build_rt_char_from_code(str_tag: i32, alloc_idx: u32) -> CompiledFn {
   // params: 0=cp(f64)
   // locals: 1=code(i32), 2=raw_base(i32), 3=new_ptr(i32), 4=byte_len(i32)
   let mut b: Vec<Instruction> = Vec::new();
@@ -3616,37 +3534,16 @@ fn build_rt_char_from_code(str_tag: i32) -> CompiledFn {
   b.push(Instruction::LocalSet(4));
   b.push(Instruction::End);
 
-  // Allocate: raw_base = HEAP_PTR; write magic+tag; new_ptr = raw_base+8
-  b.push(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  b.push(Instruction::LocalTee(2)); // raw_base
-  b.push(Instruction::I32Const(HEAP_MAGIC));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  b.push(Instruction::LocalGet(2));
-  b.push(Instruction::I32Const(4));
-  b.push(Instruction::I32Add);
+  // Allocate: new_ptr = __rt_alloc(16, str_tag) — at most 4 content bytes
+  b.push(Instruction::I32Const(16));
   b.push(Instruction::I32Const(str_tag));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  // new_ptr = raw_base + 8
-  b.push(Instruction::LocalGet(2));
-  b.push(Instruction::I32Const(8));
-  b.push(Instruction::I32Add);
+  b.push(Instruction::Call(alloc_idx));
   b.push(Instruction::LocalSet(3));
   // store byte_len as f64 at new_ptr+0
   b.push(Instruction::LocalGet(3));
   b.push(Instruction::LocalGet(4));
   b.push(Instruction::F64ConvertI32U);
   b.push(Instruction::F64Store(mem_arg_f64(0)));
-  // advance heap: raw_base + 16 + ((byte_len+7)&-8)
-  b.push(Instruction::LocalGet(2));
-  b.push(Instruction::I32Const(16));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::LocalGet(4));
-  b.push(Instruction::I32Const(7));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::I32Const(-8i32));
-  b.push(Instruction::I32And);
-  b.push(Instruction::I32Add);
-  b.push(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
 
   // Write UTF-8 bytes at new_ptr+8 based on byte_len
   // content_base = new_ptr + 8
@@ -3784,7 +3681,7 @@ fn build_rt_char_from_code(str_tag: i32) -> CompiledFn {
 ///
 /// Layout of a string logical ptr P: [byte_len:f64][utf8_bytes...]
 #[allow(clippy::vec_init_then_push)]
-fn build_rt_str_replace(str_tag: i32) -> CompiledFn {
+fn build_rt_str_replace(str_tag: i32, alloc_idx: u32) -> CompiledFn {
   // params: 0=s(f64), 1=pat(f64), 2=rep(f64)
   // locals: 3=s_ptr(i32), 4=s_len(i32), 5=s_cont(i32)
   //         6=pat_ptr(i32), 7=pat_len(i32), 8=pat_cont(i32)
@@ -3854,16 +3751,6 @@ fn build_rt_str_replace(str_tag: i32) -> CompiledFn {
   b.push(Instruction::I32Add);
   b.push(Instruction::LocalSet(20));
 
-  // raw_base = HEAP_PTR (save before allocating)
-  b.push(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-  b.push(Instruction::LocalSet(12));
-
-  // content_base = raw_base + 16 (after 8-byte header + 8-byte byte_len)
-  b.push(Instruction::LocalGet(12));
-  b.push(Instruction::I32Const(16));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::LocalSet(13));
-
   // padded = (max_out + 7) & -8
   b.push(Instruction::LocalGet(20));
   b.push(Instruction::I32Const(7));
@@ -3872,13 +3759,20 @@ fn build_rt_str_replace(str_tag: i32) -> CompiledFn {
   b.push(Instruction::I32And);
   b.push(Instruction::LocalSet(19));
 
-  // Pessimistic bump: HEAP_PTR = raw_base + 16 + padded
-  b.push(Instruction::LocalGet(12));
-  b.push(Instruction::I32Const(16));
-  b.push(Instruction::I32Add);
+  // Pessimistic allocation: new_ptr = __rt_alloc(8 + padded, str_tag).
+  // The unused tail stays inside the block until it is collected.
+  b.push(Instruction::I32Const(8));
   b.push(Instruction::LocalGet(19));
   b.push(Instruction::I32Add);
-  b.push(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
+  b.push(Instruction::I32Const(str_tag));
+  b.push(Instruction::Call(alloc_idx));
+  b.push(Instruction::LocalSet(18));
+
+  // content_base = new_ptr + 8 (after the byte_len slot)
+  b.push(Instruction::LocalGet(18));
+  b.push(Instruction::I32Const(8));
+  b.push(Instruction::I32Add);
+  b.push(Instruction::LocalSet(13));
 
   // out_pos = 0, si = 0
   b.push(Instruction::I32Const(0));
@@ -4001,40 +3895,12 @@ fn build_rt_str_replace(str_tag: i32) -> CompiledFn {
   b.push(Instruction::LocalSet(14));
   b.push(Instruction::End);
 
-  // Write header: magic, str_tag, byte_len at raw_base
-  b.push(Instruction::LocalGet(12));
-  b.push(Instruction::I32Const(HEAP_MAGIC));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  b.push(Instruction::LocalGet(12));
-  b.push(Instruction::I32Const(4));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::I32Const(str_tag));
-  b.push(Instruction::I32Store(mem_arg_i32(0)));
-  // new_ptr = raw_base + 8
-  b.push(Instruction::LocalGet(12));
-  b.push(Instruction::I32Const(8));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::LocalSet(18));
   // byte_len = out_pos
   b.push(Instruction::LocalGet(18));
   b.push(Instruction::LocalGet(14));
   b.push(Instruction::F64ConvertI32U);
   b.push(Instruction::F64Store(mem_arg_f64(0)));
 
-  // Correct HEAP_PTR: raw_base + 16 + ((out_pos + 7) & -8)
-  b.push(Instruction::LocalGet(14));
-  b.push(Instruction::I32Const(7));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::I32Const(-8i32));
-  b.push(Instruction::I32And);
-  b.push(Instruction::LocalSet(19));
-  b.push(Instruction::LocalGet(12));
-  b.push(Instruction::I32Const(16));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::LocalGet(19));
-  b.push(Instruction::I32Add);
-  b.push(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
-
   // return f64(new_ptr)
   b.push(Instruction::LocalGet(18));
   b.push(Instruction::F64ConvertI32U);
@@ -4351,7 +4217,7 @@ fn build_rt_utf8_char_len() -> CompiledFn {
 
 /// `__rt_str_split(s_ptr: i32, pat_ptr: i32) -> i32` (list logical ptr)
 /// Splits string s by pat. Empty pieces are dropped. Empty pat = char-split.
-fn build_rt_str_split(string_tag: i32, list_tag: i32, find_from_idx: u32, utf8_char_len_idx: u32) -> CompiledFn {
+fn build_rt_str_split(string_tag: i32, list_tag: i32, find_from_idx: u32, utf8_char_len_idx: u32, alloc_idx: u32) -> CompiledFn {
   let mut b = RuntimeFnBuilder::new(2); // s_ptr=0, pat_ptr=1
   let s_len = b.alloc_i32();
   let p_len = b.alloc_i32();
@@ -4478,7 +4344,7 @@ fn build_rt_str_split(string_tag: i32, list_tag: i32, find_from_idx: u32, utf8_c
   b.emit(Instruction::I32Const(8));
   b.emit(Instruction::I32Add);
   b.emit(Instruction::LocalSet(list_size));
-  rt_emit_alloc_dynamic(&mut b, list_size, list_ptr, list_tag);
+  rt_emit_alloc_dynamic(&mut b, alloc_idx, list_size, list_ptr, list_tag);
   b.emit(Instruction::LocalGet(list_ptr));
   b.emit(Instruction::LocalGet(count));
   b.emit(Instruction::F64ConvertI32U);
@@ -4519,7 +4385,7 @@ fn build_rt_str_split(string_tag: i32, list_tag: i32, find_from_idx: u32, utf8_c
   b.emit(Instruction::I32Const(8));
   b.emit(Instruction::I32Add);
   b.emit(Instruction::LocalSet(size));
-  rt_emit_alloc_dynamic(&mut b, size, str_ptr, string_tag);
+  rt_emit_alloc_dynamic(&mut b, alloc_idx, size, str_ptr, string_tag);
   b.emit(Instruction::LocalGet(str_ptr));
   b.emit(Instruction::LocalGet(char_len));
   b.emit(Instruction::F64ConvertI32U);
@@ -4612,7 +4478,7 @@ fn build_rt_str_split(string_tag: i32, list_tag: i32, find_from_idx: u32, utf8_c
   b.emit(Instruction::I32Const(8));
   b.emit(Instruction::I32Add);
   b.emit(Instruction::LocalSet(size));
-  rt_emit_alloc_dynamic(&mut b, size, str_ptr, string_tag);
+  rt_emit_alloc_dynamic(&mut b, alloc_idx, size, str_ptr, string_tag);
   b.emit(Instruction::LocalGet(str_ptr));
   b.emit(Instruction::LocalGet(piece_len));
   b.emit(Instruction::F64ConvertI32U);
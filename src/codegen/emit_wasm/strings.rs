@@ -465,29 +465,10 @@ pub(super) fn emit_turn_string(ctx: &mut WasmGenCtx, args: &[Calcit]) -> Result<
     ctx.emit(Instruction::F64Eq);
     ctx.begin_block_if();
     {
-      let raw = ctx.alloc_local_typed(ValType::I32);
-      ctx.emit(Instruction::GlobalGet(HEAP_PTR_GLOBAL));
-      ctx.emit(Instruction::LocalTee(raw));
-      ctx.emit(Instruction::I32Const(HEAP_MAGIC));
-      ctx.emit(Instruction::I32Store(mem_arg_i32(0)));
-      ctx.emit(Instruction::LocalGet(raw));
-      ctx.emit(Instruction::I32Const(string_type_tag));
-      ctx.emit(Instruction::I32Store(mem_arg_i32(4)));
-      // write byte_len = 0.0 at raw+8
-      ctx.emit(Instruction::LocalGet(raw));
-      ctx.emit(Instruction::I32Const(8));
-      ctx.emit(Instruction::I32Add);
-      ctx.emit(Instruction::F64Const(wasm_encoder::Ieee64::from(0.0f64)));
-      ctx.emit(Instruction::F64Store(mem_arg_f64(0)));
-      // advance heap_ptr by 16 (header=8 + byte_len_slot=8)
-      ctx.emit(Instruction::LocalGet(raw));
-      ctx.emit(Instruction::I32Const(16));
-      ctx.emit(Instruction::I32Add);
-      ctx.emit(Instruction::GlobalSet(HEAP_PTR_GLOBAL));
-      // result = (raw + 8) as f64
-      ctx.emit(Instruction::LocalGet(raw));
-      ctx.emit(Instruction::I32Const(8));
-      ctx.emit(Instruction::I32Add);
+      // byte_len slot only; the allocator zeroes it, so byte_len = 0
+      let ptr = ctx.alloc_local_typed(ValType::I32);
+      emit_bump_alloc(ctx, 8, ptr, "string");
+      ctx.emit(Instruction::LocalGet(ptr));
       ctx.emit(Instruction::F64ConvertI32U);
       ctx.emit(Instruction::LocalSet(result));
     }
@@ -719,16 +700,18 @@ pub(super) fn emit_str_spaced(ctx: &mut WasmGenCtx, args: &[Calcit]) -> Result<(
 //
 // JS protocol:
 //   1. Read heap top: `const top = inst.exports.__heap_ptr.value`
-//   2. Write UTF-8 bytes at `top + 16` (after the 8-byte header and 8-byte byte_len)
-//   3. Call `inst.exports.__str_new(top + 16, byteLen)` → returns f64 logical pointer
+//   2. Write UTF-8 bytes at `top + 24` (after the 16-byte block header and 8-byte byte_len)
+//   3. Call `inst.exports.__str_new(top + 24, byteLen)` → returns f64 logical pointer
 //
-// The zero-copy path: when src_ptr == logical_ptr + 8, memory.copy is a no-op.
+// The zero-copy path: when the allocation bumps the heap, src_ptr == logical_ptr + 8
+// and memory.copy is a no-op. Hosts can also call `__alloc(8 + byteLen, tag)` and
+// write the byte_len slot and bytes themselves.
 // JS may also write to any other scratch address and pass that as src_ptr.
 // ===========================================================================
 
 /// Build the `__str_new(src_ptr: i32, byte_len: i32) → f64` runtime function.
 /// Copies `byte_len` bytes from `src_ptr` into a new heap-allocated tagged string.
-pub(super) fn build_str_new_fn(str_tag_id: i32) -> CompiledFn {
+pub(super) fn build_str_new_fn(str_tag_id: i32, alloc_uninit_idx: u32) -> CompiledFn {
   // params: 0 = src_ptr (i32), 1 = byte_len (i32)
   // locals: 2 = padded (i32), 3 = payload (i32), 4 = ptr (i32)
   let instructions = vec![
@@ -744,22 +727,11 @@ pub(super) fn build_str_new_fn(str_tag_id: i32) -> CompiledFn {
     Instruction::LocalGet(2),
     Instruction::I32Add,
     Instruction::LocalSet(3), // payload
-    // Write HEAP_MAGIC at heap_ptr (raw_base + 0)
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
-    Instruction::I32Const(HEAP_MAGIC),
-    Instruction::I32Store(mem_arg_i32(0)),
-    // Write str_tag_id at heap_ptr + 4
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
-    Instruction::I32Const(str_tag_id),
-    Instruction::I32Store(mem_arg_i32(4)),
-    // ptr = heap_ptr + 8; advance heap_ptr += payload
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
-    Instruction::I32Const(8),
-    Instruction::I32Add,
-    Instruction::LocalTee(4), // ptr = logical_ptr
+    // ptr = __rt_alloc_uninit(payload, str_tag_id) — leaves staged bytes intact
     Instruction::LocalGet(3), // payload
-    Instruction::I32Add,
-    Instruction::GlobalSet(HEAP_PTR_GLOBAL),
+    Instruction::I32Const(str_tag_id),
+    Instruction::Call(alloc_uninit_idx),
+    Instruction::LocalSet(4), // ptr = logical_ptr
     // Store byte_len as f64 at ptr+0
     Instruction::LocalGet(4),
     Instruction::LocalGet(1), // byte_len
@@ -789,7 +761,7 @@ pub(super) fn build_str_new_fn(str_tag_id: i32) -> CompiledFn {
 /// Build `__rt_str_pad_left(str_ptr: i32, target_size: i32, pattern_ptr: i32) → f64`.
 /// Pads `str` on the left with repeating `pattern` bytes until `target_size` total bytes.
 /// If `str` is already >= `target_size`, returns the original pointer unchanged.
-pub(super) fn build_str_pad_left_fn(str_tag_id: i32) -> CompiledFn {
+pub(super) fn build_str_pad_left_fn(str_tag_id: i32, alloc_idx: u32) -> CompiledFn {
   // params: 0=str_ptr(i32), 1=target_size(i32), 2=pattern_ptr(i32)
   // locals: 3=str_len, 4=pad_size, 5=pat_len, 6=padded, 7=payload,
   //         8=new_ptr, 9=dst_base, 10=i, 11=j, 12=byte_val  (all i32)
@@ -840,25 +812,11 @@ pub(super) fn build_str_pad_left_fn(str_tag_id: i32) -> CompiledFn {
     Instruction::LocalGet(6),
     Instruction::I32Add,
     Instruction::LocalSet(7),
-    // Write HEAP_MAGIC at heap_ptr
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
-    Instruction::I32Const(HEAP_MAGIC),
-    Instruction::I32Store(mem_arg_i32(0)),
-    // Write str_tag_id at heap_ptr+4
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
+    // new_ptr = __rt_alloc(payload, str_tag_id)
+    Instruction::LocalGet(7),
     Instruction::I32Const(str_tag_id),
-    Instruction::I32Store(mem_arg_i32(4)),
-    // new_ptr = heap_ptr + 8; advance heap by 8 + payload
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
-    Instruction::I32Const(8),
-    Instruction::I32Add,
+    Instruction::Call(alloc_idx),
     Instruction::LocalSet(8), // new_ptr
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
-    Instruction::I32Const(8),
-    Instruction::LocalGet(7),
-    Instruction::I32Add,
-    Instruction::I32Add,
-    Instruction::GlobalSet(HEAP_PTR_GLOBAL),
     // Write byte_len (target_size as f64) at new_ptr+0
     Instruction::LocalGet(8),
     Instruction::LocalGet(1),
@@ -953,7 +911,7 @@ pub(super) fn build_str_pad_left_fn(str_tag_id: i32) -> CompiledFn {
 
 /// Build `__rt_str_pad_right(str_ptr: i32, target_size: i32, pattern_ptr: i32) → f64`.
 /// Pads `str` on the right with repeating `pattern` bytes until `target_size` total bytes.
-pub(super) fn build_str_pad_right_fn(str_tag_id: i32) -> CompiledFn {
+pub(super) fn build_str_pad_right_fn(str_tag_id: i32, alloc_idx: u32) -> CompiledFn {
   // params: 0=str_ptr(i32), 1=target_size(i32), 2=pattern_ptr(i32)
   // Same locals layout as pad_left.
   let instructions = vec![
@@ -1003,25 +961,11 @@ pub(super) fn build_str_pad_right_fn(str_tag_id: i32) -> CompiledFn {
     Instruction::LocalGet(6),
     Instruction::I32Add,
     Instruction::LocalSet(7),
-    // Write HEAP_MAGIC at heap_ptr
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
-    Instruction::I32Const(HEAP_MAGIC),
-    Instruction::I32Store(mem_arg_i32(0)),
-    // Write str_tag_id at heap_ptr+4
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
+    // new_ptr = __rt_alloc(payload, str_tag_id)
+    Instruction::LocalGet(7),
     Instruction::I32Const(str_tag_id),
-    Instruction::I32Store(mem_arg_i32(4)),
-    // new_ptr = heap_ptr + 8; advance heap
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
-    Instruction::I32Const(8),
-    Instruction::I32Add,
+    Instruction::Call(alloc_idx),
     Instruction::LocalSet(8), // new_ptr
-    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
-    Instruction::I32Const(8),
-    Instruction::LocalGet(7),
-    Instruction::I32Add,
-    Instruction::I32Add,
-    Instruction::GlobalSet(HEAP_PTR_GLOBAL),
     // Write byte_len (target_size as f64) at new_ptr+0
     Instruction::LocalGet(8),
     Instruction::LocalGet(1),
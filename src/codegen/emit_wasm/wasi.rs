@@ -0,0 +1,1328 @@
+use super::runtime::{HOST_IMPORTS, HostImport, RuntimeFnBuilder};
+use super::*;
+use wasm_encoder::BlockType;
+
+// ---------------------------------------------------------------------------
+// `--target wasi`: command modules for WASI runtimes
+// ---------------------------------------------------------------------------
+//
+// The JS target imports the `math`/`io` host functions listed in
+// `HOST_IMPORTS`. A WASI runtime only offers `wasi_snapshot_preview1`, so for
+// this target every entry of `HOST_IMPORTS` is implemented inside the module
+// with the same `(f64 × arity) → f64` signature, and generated code calls the
+// shims exactly like it calls the imports:
+//
+// | host function   | WASI implementation                                   |
+// | --------------- | ----------------------------------------------------- |
+// | `pow/sin/cos`   | in-module polynomial approximations                   |
+// | `log_value/str` | `fd_write` to stdout, one line per value              |
+// | `read_file_str` | `path_open` on the first preopened dir + `fd_read`     |
+// | `file_exists`   | `path_filestat_get` on the first preopened dir        |
+// | `current_time`  | `clock_time_get` on the monotonic clock, in ms        |
+// | `get_env`       | `environ_sizes_get` + `environ_get`                   |
+// | `parse_json`    | not available, always nil                             |
+//
+// Scratch buffers for system calls are plain heap strings: they are never
+// scanned by the collector and become garbage when the shim returns. Shims
+// allocate without reaching a safepoint, so nothing is collected under them.
+
+const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+// Import indices, matching the order of `wasi_imports`.
+const WASI_FD_WRITE: u32 = 0;
+const WASI_FD_READ: u32 = 1;
+const WASI_FD_CLOSE: u32 = 2;
+const WASI_FD_FILESTAT_GET: u32 = 3;
+const WASI_PATH_OPEN: u32 = 4;
+const WASI_PATH_FILESTAT_GET: u32 = 5;
+const WASI_ENVIRON_SIZES_GET: u32 = 6;
+const WASI_ENVIRON_GET: u32 = 7;
+const WASI_CLOCK_TIME_GET: u32 = 8;
+const WASI_PROC_EXIT: u32 = 9;
+
+const WASI_STDOUT: i32 = 1;
+/// Relative paths resolve against the first preopened directory.
+const WASI_PREOPEN_FD: i32 = 3;
+const WASI_LOOKUP_SYMLINK_FOLLOW: i32 = 1;
+const WASI_RIGHT_FD_READ: i64 = 1 << 1;
+const WASI_RIGHT_FD_FILESTAT_GET: i64 = 1 << 21;
+const WASI_CLOCK_MONOTONIC: i32 = 1;
+/// Byte offset of `size` in a `filestat` record (64 bytes in total).
+const WASI_FILESTAT_SIZE: u64 = 32;
+const WASI_FILESTAT_BYTES: i32 = 64;
+
+/// Numbers at or above this magnitude are printed in exponent form.
+const EXPONENT_FORM_MIN: f64 = 1e18;
+/// Fractional digits printed for non-integer numbers (trailing zeros trimmed).
+const FRACTION_DIGITS: i32 = 9;
+
+/// WASI system calls imported by `--target wasi`, in import index order.
+pub(super) fn wasi_imports() -> Vec<HostImport> {
+  use ValType::{I32, I64};
+  vec![
+    HostImport::raw(WASI_MODULE, "fd_write", vec![I32; 4], vec![I32]),
+    HostImport::raw(WASI_MODULE, "fd_read", vec![I32; 4], vec![I32]),
+    HostImport::raw(WASI_MODULE, "fd_close", vec![I32], vec![I32]),
+    HostImport::raw(WASI_MODULE, "fd_filestat_get", vec![I32, I32], vec![I32]),
+    HostImport::raw(
+      WASI_MODULE,
+      "path_open",
+      vec![I32, I32, I32, I32, I32, I64, I64, I32, I32],
+      vec![I32],
+    ),
+    HostImport::raw(WASI_MODULE, "path_filestat_get", vec![I32; 5], vec![I32]),
+    HostImport::raw(WASI_MODULE, "environ_sizes_get", vec![I32, I32], vec![I32]),
+    HostImport::raw(WASI_MODULE, "environ_get", vec![I32, I32], vec![I32]),
+    HostImport::raw(WASI_MODULE, "clock_time_get", vec![I32, I64, I32], vec![I32]),
+    HostImport::raw(WASI_MODULE, "proc_exit", vec![I32], vec![]),
+  ]
+}
+
+/// Function indices of the internal helpers, laid out from `base` in the
+/// order `build_wasi_host_fns` emits them.
+struct WasiHelpers {
+  alloc: u32,
+  string_tag: i32,
+  string_of: u32,
+  write: u32,
+  write_u64: u32,
+  write_f64: u32,
+  ln: u32,
+  exp: u32,
+  sin_kernel: u32,
+  env_match: u32,
+}
+
+const WASI_HELPER_COUNT: u32 = 9;
+
+/// Build the WASI shims, starting at function index `base`.
+///
+/// Returns `(runtime_fn_index key, fn)` pairs in index order: internal
+/// helpers (`__wasi_*`, including `__wasi_exit` used by `quit!`), then one
+/// shim per `HOST_IMPORTS` entry keyed by `HostImport::key`.
+pub(super) fn build_wasi_host_fns(base: u32, alloc_idx: u32, string_tag: i32) -> Vec<(String, CompiledFn)> {
+  let h = WasiHelpers {
+    alloc: alloc_idx,
+    string_tag,
+    string_of: base,
+    write: base + 1,
+    write_u64: base + 2,
+    write_f64: base + 3,
+    ln: base + 4,
+    exp: base + 5,
+    sin_kernel: base + 6,
+    env_match: base + 7,
+  };
+  let mut fns = vec![
+    ("__wasi_string_of".to_string(), build_string_of(string_tag)),
+    ("__wasi_write".to_string(), build_write(&h)),
+    ("__wasi_write_u64".to_string(), build_write_u64(&h)),
+    ("__wasi_write_f64".to_string(), build_write_f64(&h)),
+    ("__wasi_ln".to_string(), build_ln()),
+    ("__wasi_exp".to_string(), build_exp()),
+    ("__wasi_sin_kernel".to_string(), build_sin_kernel()),
+    ("__wasi_env_match".to_string(), build_env_match()),
+    ("__wasi_exit".to_string(), build_exit()),
+  ];
+  debug_assert_eq!(fns.len() as u32, WASI_HELPER_COUNT);
+
+  for imp in HOST_IMPORTS.iter() {
+    let f = match imp.name.as_str() {
+      "pow" => build_pow(&h),
+      "sin" => build_sin(&h, false),
+      "cos" => build_sin(&h, true),
+      "log_value" | "log_str" => build_log(&h),
+      "read_file_str" => build_read_file(&h),
+      "file_exists" => build_file_exists(&h),
+      "parse_json" => build_nil(imp.arity()),
+      "current_time" => build_current_time(&h),
+      "get_env" => build_get_env(&h),
+      other => panic!("no WASI implementation for host import `{other}`"),
+    };
+    fns.push((imp.key(), f));
+  }
+  fns
+}
+
+/// Build `_start`, the WASI command entry: runs the init function and
+/// discards its result.
+pub(super) fn build_start_fn(init_fn_idx: u32, gc_reset_idx: u32) -> CompiledFn {
+  CompiledFn {
+    export_name: Some("_start".to_string()),
+    params: vec![],
+    results: vec![],
+    locals: vec![],
+    instructions: vec![Instruction::Call(gc_reset_idx), Instruction::Call(init_fn_idx), Instruction::Drop],
+  }
+}
+
+/// `dst = __rt_alloc(size, string)` where `size` is already on the stack.
+fn emit_scratch(b: &mut RuntimeFnBuilder, h: &WasiHelpers, dst: u32) {
+  b.emit_all([
+    Instruction::I32Const(h.string_tag),
+    Instruction::Call(h.alloc),
+    Instruction::LocalSet(dst),
+  ]);
+}
+
+/// Allocate a heap string of `len_local` bytes into `dst`, with its byte length set.
+fn emit_string_alloc(b: &mut RuntimeFnBuilder, h: &WasiHelpers, len_local: u32, dst: u32) {
+  b.emit_all([
+    Instruction::LocalGet(len_local),
+    Instruction::I32Const(7),
+    Instruction::I32Add,
+    Instruction::I32Const(-8),
+    Instruction::I32And,
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+  ]);
+  emit_scratch(b, h, dst);
+  b.emit_all([
+    Instruction::LocalGet(dst),
+    Instruction::LocalGet(len_local),
+    Instruction::F64ConvertI32U,
+    Instruction::F64Store(mem_arg_f64(0)),
+  ]);
+}
+
+/// Push the byte pointer and byte length of the heap string in `s`.
+fn emit_string_bytes(b: &mut RuntimeFnBuilder, s: u32) {
+  b.emit_all([
+    Instruction::LocalGet(s),
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+    Instruction::LocalGet(s),
+    Instruction::F64Load(mem_arg_f64(0)),
+    Instruction::I32TruncF64U,
+  ]);
+}
+
+/// Write a short ASCII literal (at most 8 bytes) to `fd`.
+fn emit_write_ascii(b: &mut RuntimeFnBuilder, h: &WasiHelpers, fd: Instruction<'static>, scratch: u32, text: &str) {
+  assert!(text.len() <= 8, "ASCII literal too long: {text}");
+  let mut bytes = [0u8; 8];
+  bytes[..text.len()].copy_from_slice(text.as_bytes());
+  b.emit(Instruction::I32Const(8));
+  emit_scratch(b, h, scratch);
+  b.emit_all([
+    Instruction::LocalGet(scratch),
+    Instruction::I64Const(i64::from_le_bytes(bytes)),
+    Instruction::I64Store(mem_arg_i64(0)),
+    fd,
+    Instruction::LocalGet(scratch),
+    Instruction::I32Const(text.len() as i32),
+    Instruction::Call(h.write),
+  ]);
+}
+
+/// MemArg for i64 load/store (8-byte aligned, memory 0).
+fn mem_arg_i64(offset: u64) -> wasm_encoder::MemArg {
+  mem_arg_f64(offset)
+}
+
+/// `(v: f64) → i32` — logical pointer of `v` when it is a heap string
+/// (literal or allocated), otherwise 0.
+fn build_string_of(string_tag: i32) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // v
+  let ptr = b.alloc_i32();
+  // integer, lowest logical pointer <= v < heap_ptr
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(0),
+    Instruction::F64Trunc,
+    Instruction::F64Eq,
+    Instruction::LocalGet(0),
+    f64_const((HEAP_BASE + 8) as f64),
+    Instruction::F64Ge,
+    Instruction::I32And,
+    Instruction::LocalGet(0),
+    Instruction::GlobalGet(HEAP_PTR_GLOBAL),
+    Instruction::F64ConvertI32U,
+    Instruction::F64Lt,
+    Instruction::I32And,
+    Instruction::I32Eqz,
+    Instruction::If(BlockType::Empty),
+    Instruction::I32Const(0),
+    Instruction::Return,
+    Instruction::End,
+  ]);
+  // magic and `string` tag in the object header
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::I32TruncF64U,
+    Instruction::LocalTee(ptr),
+    Instruction::I32Const(8),
+    Instruction::I32Sub,
+    Instruction::I32Load(mem_arg_i32(0)),
+    Instruction::I32Const(HEAP_MAGIC),
+    Instruction::I32Eq,
+    Instruction::LocalGet(ptr),
+    Instruction::I32Const(4),
+    Instruction::I32Sub,
+    Instruction::I32Load(mem_arg_i32(0)),
+    Instruction::I32Const(string_tag),
+    Instruction::I32Eq,
+    Instruction::I32And,
+    Instruction::If(BlockType::Result(ValType::I32)),
+    Instruction::LocalGet(ptr),
+    Instruction::Else,
+    Instruction::I32Const(0),
+    Instruction::End,
+  ]);
+  b.finish(vec![ValType::F64], vec![ValType::I32])
+}
+
+/// `(fd, ptr, len: i32) → ()` — write all bytes, retrying partial writes.
+/// Errors are ignored, like a closed stdout in the JS host.
+fn build_write(h: &WasiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(3); // fd, ptr, len
+  let iov = b.alloc_i32();
+  // iov = [buf, len, nwritten]
+  b.emit(Instruction::I32Const(16));
+  emit_scratch(&mut b, h, iov);
+  b.emit_all([
+    Instruction::Block(BlockType::Empty),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(2),
+    Instruction::I32Const(0),
+    Instruction::I32LeS,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(iov),
+    Instruction::LocalGet(1),
+    Instruction::I32Store(mem_arg_i32(0)),
+    Instruction::LocalGet(iov),
+    Instruction::LocalGet(2),
+    Instruction::I32Store(mem_arg_i32(4)),
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(iov),
+    Instruction::I32Const(1),
+    Instruction::LocalGet(iov),
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+    Instruction::Call(WASI_FD_WRITE),
+    Instruction::BrIf(1),
+    Instruction::LocalGet(iov),
+    Instruction::I32Load(mem_arg_i32(8)),
+    Instruction::I32Eqz,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(1),
+    Instruction::LocalGet(iov),
+    Instruction::I32Load(mem_arg_i32(8)),
+    Instruction::I32Add,
+    Instruction::LocalSet(1),
+    Instruction::LocalGet(2),
+    Instruction::LocalGet(iov),
+    Instruction::I32Load(mem_arg_i32(8)),
+    Instruction::I32Sub,
+    Instruction::LocalSet(2),
+    Instruction::Br(0),
+    Instruction::End,
+    Instruction::End,
+  ]);
+  b.finish(vec![ValType::I32; 3], vec![])
+}
+
+/// `(fd: i32, n: i64) → ()` — write `n` as unsigned decimal digits.
+fn build_write_u64(h: &WasiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(2); // fd, n
+  let buf = b.alloc_i32();
+  let pos = b.alloc_i32();
+  b.emit(Instruction::I32Const(24));
+  emit_scratch(&mut b, h, buf);
+  b.emit_all([
+    Instruction::I32Const(24),
+    Instruction::LocalSet(pos),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(pos),
+    Instruction::I32Const(1),
+    Instruction::I32Sub,
+    Instruction::LocalSet(pos),
+    Instruction::LocalGet(buf),
+    Instruction::LocalGet(pos),
+    Instruction::I32Add,
+    Instruction::LocalGet(1),
+    Instruction::I64Const(10),
+    Instruction::I64RemU,
+    Instruction::I32WrapI64,
+    Instruction::I32Const(b'0' as i32),
+    Instruction::I32Add,
+    Instruction::I32Store8(mem_arg_byte(0)),
+    Instruction::LocalGet(1),
+    Instruction::I64Const(10),
+    Instruction::I64DivU,
+    Instruction::LocalTee(1),
+    Instruction::I64Const(0),
+    Instruction::I64Ne,
+    Instruction::BrIf(0),
+    Instruction::End,
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(buf),
+    Instruction::LocalGet(pos),
+    Instruction::I32Add,
+    Instruction::I32Const(24),
+    Instruction::LocalGet(pos),
+    Instruction::I32Sub,
+    Instruction::Call(h.write),
+  ]);
+  b.finish(vec![ValType::I32, ValType::I64], vec![])
+}
+
+/// `(fd: i32, v: f64) → ()` — write a number the way the JS host prints it
+/// for common values: integers without a fraction, up to `FRACTION_DIGITS`
+/// fractional digits, `NaN`/`Infinity`, and `1.5e+20` style for huge values.
+fn build_write_f64(h: &WasiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(2); // fd, v
+  let abs = b.alloc_f64();
+  let int_part = b.alloc_f64();
+  let frac = b.alloc_i64();
+  let exponent = b.alloc_i32();
+  let buf = b.alloc_i32();
+  let len = b.alloc_i32();
+  let scratch = b.alloc_i32();
+
+  // NaN
+  b.emit_all([
+    Instruction::LocalGet(1),
+    Instruction::LocalGet(1),
+    Instruction::F64Ne,
+    Instruction::If(BlockType::Empty),
+  ]);
+  emit_write_ascii(&mut b, h, Instruction::LocalGet(0), scratch, "NaN");
+  b.emit_all([Instruction::Return, Instruction::End]);
+
+  // sign
+  b.emit_all([
+    Instruction::LocalGet(1),
+    f64_const(0.0),
+    Instruction::F64Lt,
+    Instruction::If(BlockType::Empty),
+  ]);
+  emit_write_ascii(&mut b, h, Instruction::LocalGet(0), scratch, "-");
+  b.emit_all([
+    Instruction::End,
+    Instruction::LocalGet(1),
+    Instruction::F64Abs,
+    Instruction::LocalTee(abs),
+    f64_const(f64::INFINITY),
+    Instruction::F64Eq,
+    Instruction::If(BlockType::Empty),
+  ]);
+  emit_write_ascii(&mut b, h, Instruction::LocalGet(0), scratch, "Infinity");
+  b.emit_all([Instruction::Return, Instruction::End]);
+
+  // huge: scale into [1, 10) and print `<mantissa>e+<exponent>`
+  b.emit_all([
+    Instruction::LocalGet(abs),
+    f64_const(EXPONENT_FORM_MIN),
+    Instruction::F64Ge,
+    Instruction::If(BlockType::Empty),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(abs),
+    f64_const(10.0),
+    Instruction::F64Div,
+    Instruction::LocalSet(abs),
+    Instruction::LocalGet(exponent),
+    Instruction::I32Const(1),
+    Instruction::I32Add,
+    Instruction::LocalSet(exponent),
+    Instruction::LocalGet(abs),
+    f64_const(10.0),
+    Instruction::F64Ge,
+    Instruction::BrIf(0),
+    Instruction::End,
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(abs),
+    Instruction::Call(h.write_f64),
+  ]);
+  emit_write_ascii(&mut b, h, Instruction::LocalGet(0), scratch, "e+");
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(exponent),
+    Instruction::I64ExtendI32U,
+    Instruction::Call(h.write_u64),
+    Instruction::Return,
+    Instruction::End,
+  ]);
+
+  // integer part, then rounded fraction digits
+  let scale = 10f64.powi(FRACTION_DIGITS);
+  b.emit_all([
+    Instruction::LocalGet(abs),
+    Instruction::F64Floor,
+    Instruction::LocalSet(int_part),
+    Instruction::LocalGet(abs),
+    Instruction::LocalGet(int_part),
+    Instruction::F64Sub,
+    f64_const(scale),
+    Instruction::F64Mul,
+    Instruction::F64Nearest,
+    Instruction::I64TruncSatF64U,
+    Instruction::LocalSet(frac),
+    Instruction::LocalGet(frac),
+    Instruction::I64Const(scale as i64),
+    Instruction::I64GeU,
+    Instruction::If(BlockType::Empty),
+    Instruction::LocalGet(int_part),
+    f64_const(1.0),
+    Instruction::F64Add,
+    Instruction::LocalSet(int_part),
+    Instruction::I64Const(0),
+    Instruction::LocalSet(frac),
+    Instruction::End,
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(int_part),
+    Instruction::I64TruncSatF64U,
+    Instruction::Call(h.write_u64),
+    Instruction::LocalGet(frac),
+    Instruction::I64Eqz,
+    Instruction::If(BlockType::Empty),
+    Instruction::Return,
+    Instruction::End,
+  ]);
+  b.emit(Instruction::I32Const(16));
+  emit_scratch(&mut b, h, buf);
+  b.emit_all([
+    Instruction::LocalGet(buf),
+    Instruction::I32Const(b'.' as i32),
+    Instruction::I32Store8(mem_arg_byte(0)),
+    Instruction::I32Const(FRACTION_DIGITS),
+    Instruction::LocalSet(len),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(buf),
+    Instruction::LocalGet(len),
+    Instruction::I32Add,
+    Instruction::LocalGet(frac),
+    Instruction::I64Const(10),
+    Instruction::I64RemU,
+    Instruction::I32WrapI64,
+    Instruction::I32Const(b'0' as i32),
+    Instruction::I32Add,
+    Instruction::I32Store8(mem_arg_byte(0)),
+    Instruction::LocalGet(frac),
+    Instruction::I64Const(10),
+    Instruction::I64DivU,
+    Instruction::LocalSet(frac),
+    Instruction::LocalGet(len),
+    Instruction::I32Const(1),
+    Instruction::I32Sub,
+    Instruction::LocalTee(len),
+    Instruction::BrIf(0),
+    Instruction::End,
+    // trim trailing zeros; the fraction is non-zero so a digit remains
+    Instruction::I32Const(FRACTION_DIGITS + 1),
+    Instruction::LocalSet(len),
+    Instruction::Block(BlockType::Empty),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(buf),
+    Instruction::LocalGet(len),
+    Instruction::I32Add,
+    Instruction::I32Const(1),
+    Instruction::I32Sub,
+    Instruction::I32Load8U(mem_arg_byte(0)),
+    Instruction::I32Const(b'0' as i32),
+    Instruction::I32Ne,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(len),
+    Instruction::I32Const(1),
+    Instruction::I32Sub,
+    Instruction::LocalSet(len),
+    Instruction::Br(0),
+    Instruction::End,
+    Instruction::End,
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(buf),
+    Instruction::LocalGet(len),
+    Instruction::Call(h.write),
+  ]);
+  b.finish(vec![ValType::I32, ValType::F64], vec![])
+}
+
+const LN2_HI: f64 = 6.931_471_803_691_238e-1;
+const LN2_LO: f64 = 1.908_214_929_270_587_7e-10;
+
+/// Push `acc = ((c[n-1]·z + c[n-2])·z + …)·z + c[0]`, with `z` in a local.
+fn emit_horner(b: &mut RuntimeFnBuilder, coeffs: &[f64], z: u32) {
+  let (last, rest) = coeffs.split_last().expect("polynomial coefficients");
+  b.emit(f64_const(*last));
+  for c in rest.iter().rev() {
+    b.emit(Instruction::LocalGet(z));
+    b.emit(Instruction::F64Mul);
+    b.emit(f64_const(*c));
+    b.emit(Instruction::F64Add);
+  }
+}
+
+/// `(x: f64) → f64` — natural logarithm.
+///
+/// `x = m·2^e` with `m` in `[√2/2, √2)`, then `ln m = 2·atanh(s)` for
+/// `s = (m-1)/(m+1)`, summed as an odd series in `s`.
+fn build_ln() -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // x
+  let e = b.alloc_i32();
+  let bits = b.alloc_i64();
+  let m = b.alloc_f64();
+  let s = b.alloc_f64();
+  let z = b.alloc_f64();
+  b.emit_all([
+    // x <= 0 or NaN: -Infinity for zero, NaN otherwise
+    Instruction::LocalGet(0),
+    f64_const(0.0),
+    Instruction::F64Gt,
+    Instruction::I32Eqz,
+    Instruction::If(BlockType::Empty),
+    f64_const(f64::NEG_INFINITY),
+    f64_const(f64::NAN),
+    Instruction::LocalGet(0),
+    f64_const(0.0),
+    Instruction::F64Eq,
+    Instruction::Select,
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(0),
+    f64_const(f64::INFINITY),
+    Instruction::F64Eq,
+    Instruction::If(BlockType::Empty),
+    Instruction::LocalGet(0),
+    Instruction::Return,
+    Instruction::End,
+    // subnormals: scale up by 2^54
+    Instruction::LocalGet(0),
+    f64_const(f64::MIN_POSITIVE),
+    Instruction::F64Lt,
+    Instruction::If(BlockType::Empty),
+    Instruction::LocalGet(0),
+    f64_const(18014398509481984.0),
+    Instruction::F64Mul,
+    Instruction::LocalSet(0),
+    Instruction::I32Const(-54),
+    Instruction::LocalSet(e),
+    Instruction::End,
+    Instruction::LocalGet(0),
+    Instruction::I64ReinterpretF64,
+    Instruction::LocalTee(bits),
+    Instruction::I64Const(52),
+    Instruction::I64ShrU,
+    Instruction::I32WrapI64,
+    Instruction::I32Const(1023),
+    Instruction::I32Sub,
+    Instruction::LocalGet(e),
+    Instruction::I32Add,
+    Instruction::LocalSet(e),
+    Instruction::LocalGet(bits),
+    Instruction::I64Const(0x000F_FFFF_FFFF_FFFF),
+    Instruction::I64And,
+    Instruction::I64Const(0x3FF0_0000_0000_0000),
+    Instruction::I64Or,
+    Instruction::F64ReinterpretI64,
+    Instruction::LocalTee(m),
+    f64_const(std::f64::consts::SQRT_2),
+    Instruction::F64Gt,
+    Instruction::If(BlockType::Empty),
+    Instruction::LocalGet(m),
+    f64_const(0.5),
+    Instruction::F64Mul,
+    Instruction::LocalSet(m),
+    Instruction::LocalGet(e),
+    Instruction::I32Const(1),
+    Instruction::I32Add,
+    Instruction::LocalSet(e),
+    Instruction::End,
+    Instruction::LocalGet(m),
+    f64_const(1.0),
+    Instruction::F64Sub,
+    Instruction::LocalGet(m),
+    f64_const(1.0),
+    Instruction::F64Add,
+    Instruction::F64Div,
+    Instruction::LocalTee(s),
+    Instruction::LocalGet(s),
+    Instruction::F64Mul,
+    Instruction::LocalSet(z),
+    // e·ln2_hi + (2·s·P(z) + e·ln2_lo)
+    Instruction::LocalGet(e),
+    Instruction::F64ConvertI32S,
+    f64_const(LN2_HI),
+    Instruction::F64Mul,
+    f64_const(2.0),
+    Instruction::LocalGet(s),
+    Instruction::F64Mul,
+  ]);
+  // |s| < 0.172, so z^21 is far below an ulp
+  let coeffs: Vec<f64> = (0..22).map(|k| 1.0 / (2 * k + 1) as f64).collect();
+  emit_horner(&mut b, &coeffs, z);
+  b.emit_all([
+    Instruction::F64Mul,
+    Instruction::LocalGet(e),
+    Instruction::F64ConvertI32S,
+    f64_const(LN2_LO),
+    Instruction::F64Mul,
+    Instruction::F64Add,
+    Instruction::F64Add,
+  ]);
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
+
+/// Push `2^k` for an i32 `k` in `[-1022, 1023]`, built from its exponent bits.
+fn emit_pow2(b: &mut RuntimeFnBuilder, k: u32) {
+  b.emit_all([
+    Instruction::LocalGet(k),
+    Instruction::I32Const(1023),
+    Instruction::I32Add,
+    Instruction::I64ExtendI32U,
+    Instruction::I64Const(52),
+    Instruction::I64Shl,
+    Instruction::F64ReinterpretI64,
+  ]);
+}
+
+/// `(x: f64) → f64` — `e^x` as `2^k · e^r` with `|r| <= ln2/2`.
+fn build_exp() -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // x
+  let k = b.alloc_f64();
+  let r = b.alloc_f64();
+  let k1 = b.alloc_i32();
+  let k2 = b.alloc_i32();
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(0),
+    Instruction::F64Ne,
+    Instruction::If(BlockType::Empty),
+    Instruction::LocalGet(0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(0),
+    f64_const(709.782_712_893_384),
+    Instruction::F64Gt,
+    Instruction::If(BlockType::Empty),
+    f64_const(f64::INFINITY),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(0),
+    f64_const(-745.133_219_101_941_1),
+    Instruction::F64Lt,
+    Instruction::If(BlockType::Empty),
+    f64_const(0.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(0),
+    f64_const(std::f64::consts::LOG2_E),
+    Instruction::F64Mul,
+    Instruction::F64Nearest,
+    Instruction::LocalSet(k),
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(k),
+    f64_const(LN2_HI),
+    Instruction::F64Mul,
+    Instruction::F64Sub,
+    Instruction::LocalGet(k),
+    f64_const(LN2_LO),
+    Instruction::F64Mul,
+    Instruction::F64Sub,
+    Instruction::LocalSet(r),
+    // split k so each half stays a normal power of two
+    Instruction::LocalGet(k),
+    Instruction::I32TruncF64S,
+    Instruction::LocalTee(k1),
+    Instruction::LocalGet(k1),
+    Instruction::I32Const(2),
+    Instruction::I32DivS,
+    Instruction::LocalTee(k2),
+    Instruction::I32Sub,
+    Instruction::LocalSet(k1),
+  ]);
+  // |r| <= 0.347, so r^18/18! is far below an ulp
+  let mut coeffs = vec![1.0f64];
+  for n in 1..19 {
+    coeffs.push(coeffs[n - 1] / n as f64);
+  }
+  emit_horner(&mut b, &coeffs, r);
+  emit_pow2(&mut b, k1);
+  b.emit(Instruction::F64Mul);
+  emit_pow2(&mut b, k2);
+  b.emit(Instruction::F64Mul);
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
+
+/// `(r: f64) → f64` — `sin r` for `r` in `[-π/2, π/2]`.
+fn build_sin_kernel() -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // r
+  let z = b.alloc_f64();
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(0),
+    Instruction::F64Mul,
+    Instruction::LocalSet(z),
+    Instruction::LocalGet(0),
+  ]);
+  // (π/2)^23/23! is far below an ulp
+  let mut coeffs = vec![1.0f64];
+  for k in 1..12 {
+    let n = (2 * k) as f64;
+    coeffs.push(-coeffs[k - 1] / (n * (n + 1.0)));
+  }
+  emit_horner(&mut b, &coeffs, z);
+  b.emit(Instruction::F64Mul);
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
+
+const TWO_PI_HI: f64 = std::f64::consts::TAU;
+const TWO_PI_LO: f64 = 2.449_293_598_294_706_4e-16;
+
+/// `math/sin` and `math/cos`: reduce `x` into `[-π, π]`, then fold onto the
+/// kernel's range (`cos r = sin(π/2 - |r|)`).
+fn build_sin(h: &WasiHelpers, cosine: bool) -> CompiledFn {
+  use std::f64::consts::{FRAC_1_PI, FRAC_PI_2, PI};
+  let mut b = RuntimeFnBuilder::new(1); // x
+  let n = b.alloc_f64();
+  let r = b.alloc_f64();
+  b.emit_all([
+    // Infinity/NaN
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(0),
+    Instruction::F64Sub,
+    f64_const(0.0),
+    Instruction::F64Ne,
+    Instruction::If(BlockType::Empty),
+    f64_const(f64::NAN),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(0),
+    f64_const(FRAC_1_PI * 0.5),
+    Instruction::F64Mul,
+    Instruction::F64Nearest,
+    Instruction::LocalSet(n),
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(n),
+    f64_const(TWO_PI_HI),
+    Instruction::F64Mul,
+    Instruction::F64Sub,
+    Instruction::LocalGet(n),
+    f64_const(TWO_PI_LO),
+    Instruction::F64Mul,
+    Instruction::F64Sub,
+    Instruction::LocalSet(r),
+  ]);
+  if cosine {
+    b.emit_all([
+      f64_const(FRAC_PI_2),
+      Instruction::LocalGet(r),
+      Instruction::F64Abs,
+      Instruction::F64Sub,
+    ]);
+  } else {
+    b.emit_all([
+      Instruction::LocalGet(r),
+      f64_const(FRAC_PI_2),
+      Instruction::F64Gt,
+      Instruction::If(BlockType::Empty),
+      f64_const(PI),
+      Instruction::LocalGet(r),
+      Instruction::F64Sub,
+      Instruction::LocalSet(r),
+      Instruction::End,
+      Instruction::LocalGet(r),
+      f64_const(-FRAC_PI_2),
+      Instruction::F64Lt,
+      Instruction::If(BlockType::Empty),
+      f64_const(-PI),
+      Instruction::LocalGet(r),
+      Instruction::F64Sub,
+      Instruction::LocalSet(r),
+      Instruction::End,
+      Instruction::LocalGet(r),
+    ]);
+  }
+  b.emit(Instruction::Call(h.sin_kernel));
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
+
+/// `math/pow`: exact repeated squaring for integer exponents, `exp(b·ln a)`
+/// otherwise.
+fn build_pow(h: &WasiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(2); // a, b
+  let n = b.alloc_i64();
+  let base = b.alloc_f64();
+  let acc = b.alloc_f64();
+  let abs_b = b.alloc_f64();
+  b.emit_all([
+    Instruction::LocalGet(1),
+    f64_const(0.0),
+    Instruction::F64Eq,
+    Instruction::If(BlockType::Empty),
+    f64_const(1.0),
+    Instruction::Return,
+    Instruction::End,
+    // NaN operands
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(0),
+    Instruction::F64Ne,
+    Instruction::LocalGet(1),
+    Instruction::LocalGet(1),
+    Instruction::F64Ne,
+    Instruction::I32Or,
+    Instruction::If(BlockType::Empty),
+    f64_const(f64::NAN),
+    Instruction::Return,
+    Instruction::End,
+    // integer exponent below 2^53
+    Instruction::LocalGet(1),
+    Instruction::F64Abs,
+    Instruction::LocalTee(abs_b),
+    Instruction::LocalGet(abs_b),
+    Instruction::F64Trunc,
+    Instruction::F64Eq,
+    Instruction::LocalGet(abs_b),
+    f64_const(9007199254740992.0),
+    Instruction::F64Lt,
+    Instruction::I32And,
+    Instruction::If(BlockType::Empty),
+    Instruction::LocalGet(abs_b),
+    Instruction::I64TruncF64U,
+    Instruction::LocalSet(n),
+    Instruction::LocalGet(0),
+    Instruction::LocalSet(base),
+    f64_const(1.0),
+    Instruction::LocalSet(acc),
+    Instruction::Block(BlockType::Empty),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(n),
+    Instruction::I64Const(1),
+    Instruction::I64And,
+    Instruction::I32WrapI64,
+    Instruction::If(BlockType::Empty),
+    Instruction::LocalGet(acc),
+    Instruction::LocalGet(base),
+    Instruction::F64Mul,
+    Instruction::LocalSet(acc),
+    Instruction::End,
+    Instruction::LocalGet(n),
+    Instruction::I64Const(1),
+    Instruction::I64ShrU,
+    Instruction::LocalTee(n),
+    Instruction::I64Eqz,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(base),
+    Instruction::LocalGet(base),
+    Instruction::F64Mul,
+    Instruction::LocalSet(base),
+    Instruction::Br(0),
+    Instruction::End,
+    Instruction::End,
+    f64_const(1.0),
+    Instruction::LocalGet(acc),
+    Instruction::F64Div,
+    Instruction::LocalGet(acc),
+    Instruction::LocalGet(1),
+    f64_const(0.0),
+    Instruction::F64Lt,
+    Instruction::Select,
+    Instruction::Return,
+    Instruction::End,
+    // fractional exponent
+    Instruction::LocalGet(0),
+    f64_const(0.0),
+    Instruction::F64Lt,
+    Instruction::If(BlockType::Empty),
+    f64_const(f64::NAN),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(0),
+    f64_const(0.0),
+    Instruction::F64Eq,
+    Instruction::If(BlockType::Empty),
+    f64_const(0.0),
+    f64_const(f64::INFINITY),
+    Instruction::LocalGet(1),
+    f64_const(0.0),
+    Instruction::F64Gt,
+    Instruction::Select,
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(1),
+    Instruction::LocalGet(0),
+    Instruction::Call(h.ln),
+    Instruction::F64Mul,
+    Instruction::Call(h.exp),
+  ]);
+  b.finish(vec![ValType::F64; 2], vec![ValType::F64])
+}
+
+/// `io/log_value` and `io/log_str`: one line on stdout, strings verbatim and
+/// anything else as a number.
+fn build_log(h: &WasiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // v
+  let s = b.alloc_i32();
+  let scratch = b.alloc_i32();
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::Call(h.string_of),
+    Instruction::LocalTee(s),
+    Instruction::If(BlockType::Empty),
+    Instruction::I32Const(WASI_STDOUT),
+  ]);
+  emit_string_bytes(&mut b, s);
+  b.emit_all([
+    Instruction::Call(h.write),
+    Instruction::Else,
+    Instruction::I32Const(WASI_STDOUT),
+    Instruction::LocalGet(0),
+    Instruction::Call(h.write_f64),
+    Instruction::End,
+  ]);
+  emit_write_ascii(&mut b, h, Instruction::I32Const(WASI_STDOUT), scratch, "\n");
+  b.emit(f64_const(0.0));
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
+
+/// `io/read_file_str`: read a whole file relative to the preopened directory,
+/// nil when it cannot be opened.
+fn build_read_file(h: &WasiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // path
+  let path = b.alloc_i32();
+  let scratch = b.alloc_i32();
+  let fd = b.alloc_i32();
+  let size = b.alloc_i32();
+  let out = b.alloc_i32();
+  let done = b.alloc_i32();
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::Call(h.string_of),
+    Instruction::LocalTee(path),
+    Instruction::I32Eqz,
+    Instruction::If(BlockType::Empty),
+    f64_const(0.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::I32Const(WASI_FILESTAT_BYTES + 8),
+  ]);
+  emit_scratch(&mut b, h, scratch);
+  b.emit(Instruction::I32Const(WASI_PREOPEN_FD));
+  b.emit(Instruction::I32Const(WASI_LOOKUP_SYMLINK_FOLLOW));
+  emit_string_bytes(&mut b, path);
+  b.emit_all([
+    Instruction::I32Const(0), // oflags
+    Instruction::I64Const(WASI_RIGHT_FD_READ | WASI_RIGHT_FD_FILESTAT_GET),
+    Instruction::I64Const(0),
+    Instruction::I32Const(0), // fdflags
+    Instruction::LocalGet(scratch),
+    Instruction::Call(WASI_PATH_OPEN),
+    Instruction::If(BlockType::Empty),
+    f64_const(0.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(scratch),
+    Instruction::I32Load(mem_arg_i32(0)),
+    Instruction::LocalTee(fd),
+    Instruction::LocalGet(scratch),
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+    Instruction::Call(WASI_FD_FILESTAT_GET),
+    Instruction::If(BlockType::Empty),
+    Instruction::LocalGet(fd),
+    Instruction::Call(WASI_FD_CLOSE),
+    Instruction::Drop,
+    f64_const(0.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(scratch),
+    Instruction::I64Load(mem_arg_i64(8 + WASI_FILESTAT_SIZE)),
+    Instruction::I32WrapI64,
+    Instruction::LocalSet(size),
+  ]);
+  emit_string_alloc(&mut b, h, size, out);
+  b.emit_all([
+    // scratch is reused as iov = [buf, len, nread]
+    Instruction::Block(BlockType::Empty),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(done),
+    Instruction::LocalGet(size),
+    Instruction::I32GeU,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(scratch),
+    Instruction::LocalGet(out),
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+    Instruction::LocalGet(done),
+    Instruction::I32Add,
+    Instruction::I32Store(mem_arg_i32(0)),
+    Instruction::LocalGet(scratch),
+    Instruction::LocalGet(size),
+    Instruction::LocalGet(done),
+    Instruction::I32Sub,
+    Instruction::I32Store(mem_arg_i32(4)),
+    Instruction::LocalGet(fd),
+    Instruction::LocalGet(scratch),
+    Instruction::I32Const(1),
+    Instruction::LocalGet(scratch),
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+    Instruction::Call(WASI_FD_READ),
+    Instruction::BrIf(1),
+    Instruction::LocalGet(scratch),
+    Instruction::I32Load(mem_arg_i32(8)),
+    Instruction::I32Eqz,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(done),
+    Instruction::LocalGet(scratch),
+    Instruction::I32Load(mem_arg_i32(8)),
+    Instruction::I32Add,
+    Instruction::LocalSet(done),
+    Instruction::Br(0),
+    Instruction::End,
+    Instruction::End,
+    Instruction::LocalGet(fd),
+    Instruction::Call(WASI_FD_CLOSE),
+    Instruction::Drop,
+    // a file that shrank while reading keeps only the bytes read
+    Instruction::LocalGet(out),
+    Instruction::LocalGet(done),
+    Instruction::F64ConvertI32U,
+    Instruction::F64Store(mem_arg_f64(0)),
+    Instruction::LocalGet(out),
+    Instruction::F64ConvertI32U,
+  ]);
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
+
+/// `io/file_exists`: 1.0 when the path resolves under the preopened directory.
+fn build_file_exists(h: &WasiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // path
+  let path = b.alloc_i32();
+  let scratch = b.alloc_i32();
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::Call(h.string_of),
+    Instruction::LocalTee(path),
+    Instruction::I32Eqz,
+    Instruction::If(BlockType::Empty),
+    f64_const(0.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::I32Const(WASI_FILESTAT_BYTES),
+  ]);
+  emit_scratch(&mut b, h, scratch);
+  b.emit(Instruction::I32Const(WASI_PREOPEN_FD));
+  b.emit(Instruction::I32Const(WASI_LOOKUP_SYMLINK_FOLLOW));
+  emit_string_bytes(&mut b, path);
+  b.emit_all([
+    Instruction::LocalGet(scratch),
+    Instruction::Call(WASI_PATH_FILESTAT_GET),
+    Instruction::I32Eqz,
+    Instruction::F64ConvertI32U,
+  ]);
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
+
+/// A shim with no WASI counterpart: ignores its arguments and returns nil.
+fn build_nil(arity: usize) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(arity as u32);
+  b.emit(f64_const(0.0));
+  b.finish(vec![ValType::F64; arity], vec![ValType::F64])
+}
+
+/// `io/current_time`: milliseconds on the monotonic clock.
+fn build_current_time(h: &WasiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(0);
+  let scratch = b.alloc_i32();
+  b.emit(Instruction::I32Const(8));
+  emit_scratch(&mut b, h, scratch);
+  b.emit_all([
+    Instruction::I32Const(WASI_CLOCK_MONOTONIC),
+    Instruction::I64Const(1000),
+    Instruction::LocalGet(scratch),
+    Instruction::Call(WASI_CLOCK_TIME_GET),
+    Instruction::If(BlockType::Empty),
+    f64_const(0.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(scratch),
+    Instruction::I64Load(mem_arg_i64(0)),
+    Instruction::F64ConvertI64U,
+    f64_const(1e6),
+    Instruction::F64Div,
+  ]);
+  b.finish(vec![], vec![ValType::F64])
+}
+
+/// `(entry, key, key_len: i32) → i32` — whether the NUL-terminated environ
+/// entry starts with `key=`.
+fn build_env_match() -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(3); // entry, key, key_len
+  let j = b.alloc_i32();
+  b.emit_all([
+    Instruction::Block(BlockType::Empty),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(j),
+    Instruction::LocalGet(2),
+    Instruction::I32GeU,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(j),
+    Instruction::I32Add,
+    Instruction::I32Load8U(mem_arg_byte(0)),
+    Instruction::LocalGet(1),
+    Instruction::LocalGet(j),
+    Instruction::I32Add,
+    Instruction::I32Load8U(mem_arg_byte(0)),
+    Instruction::I32Ne,
+    Instruction::If(BlockType::Empty),
+    Instruction::I32Const(0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(j),
+    Instruction::I32Const(1),
+    Instruction::I32Add,
+    Instruction::LocalSet(j),
+    Instruction::Br(0),
+    Instruction::End,
+    Instruction::End,
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(2),
+    Instruction::I32Add,
+    Instruction::I32Load8U(mem_arg_byte(0)),
+    Instruction::I32Const(b'=' as i32),
+    Instruction::I32Eq,
+  ]);
+  b.finish(vec![ValType::I32; 3], vec![ValType::I32])
+}
+
+/// `io/get_env`: the value of an environment variable as a string, nil when unset.
+fn build_get_env(h: &WasiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // key
+  let key = b.alloc_i32();
+  let key_len = b.alloc_i32();
+  let sizes = b.alloc_i32();
+  let count = b.alloc_i32();
+  let ptrs = b.alloc_i32();
+  let buf = b.alloc_i32();
+  let i = b.alloc_i32();
+  let value = b.alloc_i32();
+  let value_len = b.alloc_i32();
+  let out = b.alloc_i32();
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::Call(h.string_of),
+    Instruction::LocalTee(key),
+    Instruction::I32Eqz,
+    Instruction::If(BlockType::Empty),
+    f64_const(0.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(key),
+    Instruction::F64Load(mem_arg_f64(0)),
+    Instruction::I32TruncF64U,
+    Instruction::LocalSet(key_len),
+    Instruction::I32Const(8),
+  ]);
+  emit_scratch(&mut b, h, sizes);
+  b.emit_all([
+    Instruction::LocalGet(sizes),
+    Instruction::LocalGet(sizes),
+    Instruction::I32Const(4),
+    Instruction::I32Add,
+    Instruction::Call(WASI_ENVIRON_SIZES_GET),
+    Instruction::If(BlockType::Empty),
+    f64_const(0.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(sizes),
+    Instruction::I32Load(mem_arg_i32(0)),
+    Instruction::LocalTee(count),
+    Instruction::I32Const(4),
+    Instruction::I32Mul,
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+  ]);
+  emit_scratch(&mut b, h, ptrs);
+  b.emit_all([
+    Instruction::LocalGet(sizes),
+    Instruction::I32Load(mem_arg_i32(4)),
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+  ]);
+  emit_scratch(&mut b, h, buf);
+  b.emit_all([
+    Instruction::LocalGet(ptrs),
+    Instruction::LocalGet(buf),
+    Instruction::Call(WASI_ENVIRON_GET),
+    Instruction::If(BlockType::Empty),
+    f64_const(0.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::Block(BlockType::Empty),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(i),
+    Instruction::LocalGet(count),
+    Instruction::I32GeU,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(ptrs),
+    Instruction::LocalGet(i),
+    Instruction::I32Const(4),
+    Instruction::I32Mul,
+    Instruction::I32Add,
+    Instruction::I32Load(mem_arg_i32(0)),
+    Instruction::LocalTee(value),
+    Instruction::LocalGet(key),
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+    Instruction::LocalGet(key_len),
+    Instruction::Call(h.env_match),
+    Instruction::If(BlockType::Empty),
+    // value starts after `key=` and runs to the NUL terminator
+    Instruction::LocalGet(value),
+    Instruction::LocalGet(key_len),
+    Instruction::I32Add,
+    Instruction::I32Const(1),
+    Instruction::I32Add,
+    Instruction::LocalSet(value),
+    Instruction::Block(BlockType::Empty),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(value),
+    Instruction::LocalGet(value_len),
+    Instruction::I32Add,
+    Instruction::I32Load8U(mem_arg_byte(0)),
+    Instruction::I32Eqz,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(value_len),
+    Instruction::I32Const(1),
+    Instruction::I32Add,
+    Instruction::LocalSet(value_len),
+    Instruction::Br(0),
+    Instruction::End,
+    Instruction::End,
+  ]);
+  emit_string_alloc(&mut b, h, value_len, out);
+  b.emit_all([
+    Instruction::LocalGet(out),
+    Instruction::I32Const(8),
+    Instruction::I32Add,
+    Instruction::LocalGet(value),
+    Instruction::LocalGet(value_len),
+    Instruction::MemoryCopy { src_mem: 0, dst_mem: 0 },
+    Instruction::LocalGet(out),
+    Instruction::F64ConvertI32U,
+    Instruction::Return,
+    Instruction::End,
+    Instruction::LocalGet(i),
+    Instruction::I32Const(1),
+    Instruction::I32Add,
+    Instruction::LocalSet(i),
+    Instruction::Br(0),
+    Instruction::End,
+    Instruction::End,
+    f64_const(0.0),
+  ]);
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
+
+/// `(code: f64) → f64` — `quit!` under WASI: exit the process with `code`.
+fn build_exit() -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(1); // code
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::I32TruncSatF64S,
+    Instruction::Call(WASI_PROC_EXIT),
+    Instruction::Unreachable,
+  ]);
+  b.finish(vec![ValType::F64], vec![ValType::F64])
+}
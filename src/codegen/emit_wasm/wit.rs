@@ -0,0 +1,778 @@
+use super::runtime::RuntimeFnBuilder;
+use super::*;
+use std::sync::Arc;
+use wasm_encoder::BlockType;
+
+// ---------------------------------------------------------------------------
+// `--wit`: component-model interface for `defwasm-export` definitions
+// ---------------------------------------------------------------------------
+//
+// Inside the module every value is an f64 and strings, lists and records are
+// heap pointers. With `--wit` the schemas of `defwasm-export` definitions are
+// turned into a WIT interface, written next to the module as `program.wit`:
+//
+// ```wit
+// package calcit:app-main;
+//
+// interface exports {
+//   record vec2 { x: f64, y: f64 }
+//   scale: func(v: vec2, k: f64) -> vec2;
+// }
+//
+// world program {
+//   export exports;
+// }
+// ```
+//
+// and every export gets an adapter following the canonical ABI, exported as
+// `calcit:app-main/exports#scale`. Adapters lift the flattened core params
+// into heap values, call the definition, and lower its result, through a
+// return area when it flattens to more than one core value. `cabi_realloc`
+// hands out host buffers from the heap. The module can then be wrapped with
+// `wasm-tools component embed program.wit program.wasm` and
+// `wasm-tools component new`.
+//
+// | schema             | WIT         | core params / memory layout         |
+// | ------------------ | ----------- | ----------------------------------- |
+// | `'Number`          | `f64`       | `f64`                               |
+// | `'Bool`            | `bool`      | `i32` / 1 byte                      |
+// | `'String`          | `string`    | `i32 ptr, i32 len` (UTF-8)          |
+// | `:: 'List T`       | `list<T>`   | `i32 ptr, i32 len` of packed `T`s    |
+// | struct with typed fields | `record` | fields in order, each aligned   |
+
+/// Canonical ABI limit on flattened params before they move to memory.
+const MAX_FLAT_PARAMS: usize = 16;
+const WIT_INTERFACE: &str = "exports";
+const WIT_WORLD: &str = "program";
+
+const WIT_KEYWORDS: &[&str] = &[
+  "as",
+  "async",
+  "bool",
+  "borrow",
+  "char",
+  "constructor",
+  "enum",
+  "export",
+  "f32",
+  "f64",
+  "flags",
+  "from",
+  "func",
+  "future",
+  "import",
+  "include",
+  "interface",
+  "list",
+  "option",
+  "own",
+  "package",
+  "record",
+  "resource",
+  "result",
+  "s16",
+  "s32",
+  "s64",
+  "s8",
+  "static",
+  "stream",
+  "string",
+  "tuple",
+  "type",
+  "u16",
+  "u32",
+  "u64",
+  "u8",
+  "use",
+  "variant",
+  "with",
+  "world",
+];
+
+/// A value type that can cross the component boundary.
+#[derive(Clone, Debug)]
+enum WitType {
+  F64,
+  Bool,
+  String,
+  List(Box<WitType>),
+  Record(Arc<WitRecord>),
+}
+
+#[derive(Debug)]
+struct WitRecord {
+  name: String,
+  /// Struct name, used as the heap tag of lifted values.
+  struct_name: String,
+  fields: Vec<(String, WitType)>,
+}
+
+struct WitExport {
+  /// Qualified `ns/def` of the exported definition.
+  def: String,
+  name: String,
+  params: Vec<(String, WitType)>,
+  result: WitType,
+}
+
+/// The WIT interface of a module's `defwasm-export` definitions.
+pub(super) struct WitInterface {
+  package: String,
+  records: Vec<Arc<WitRecord>>,
+  exports: Vec<WitExport>,
+}
+
+impl WitType {
+  fn render(&self) -> String {
+    match self {
+      WitType::F64 => "f64".into(),
+      WitType::Bool => "bool".into(),
+      WitType::String => "string".into(),
+      WitType::List(item) => format!("list<{}>", item.render()),
+      WitType::Record(record) => wit_ident(&record.name),
+    }
+  }
+
+  /// Flattened core value types.
+  fn flat(&self) -> Vec<ValType> {
+    match self {
+      WitType::F64 => vec![ValType::F64],
+      WitType::Bool => vec![ValType::I32],
+      WitType::String | WitType::List(_) => vec![ValType::I32, ValType::I32],
+      WitType::Record(record) => record.fields.iter().flat_map(|(_, ty)| ty.flat()).collect(),
+    }
+  }
+
+  fn align(&self) -> u32 {
+    match self {
+      WitType::F64 => 8,
+      WitType::Bool => 1,
+      WitType::String | WitType::List(_) => 4,
+      WitType::Record(record) => record.fields.iter().map(|(_, ty)| ty.align()).max().unwrap_or(1),
+    }
+  }
+
+  fn size(&self) -> u32 {
+    match self {
+      WitType::F64 | WitType::String | WitType::List(_) => 8,
+      WitType::Bool => 1,
+      WitType::Record(record) => {
+        let end = record.field_offsets().last().map_or(0, |(offset, ty)| offset + ty.size());
+        align_to(end, self.align())
+      }
+    }
+  }
+}
+
+impl WitRecord {
+  fn field_offsets(&self) -> Vec<(u32, &WitType)> {
+    let mut offset = 0;
+    self
+      .fields
+      .iter()
+      .map(|(_, ty)| {
+        offset = align_to(offset, ty.align());
+        let field = (offset, ty);
+        offset += ty.size();
+        field
+      })
+      .collect()
+  }
+}
+
+fn align_to(n: u32, align: u32) -> u32 {
+  n.div_ceil(align) * align
+}
+
+/// Kebab-case WIT name for a Calcit name: `WasmVec2` → `wasm-vec2`, `ok?` → `ok`.
+fn wit_name(raw: &str) -> Result<String, String> {
+  let mut lowered = String::new();
+  let mut prev_lower = false;
+  for c in raw.chars() {
+    if c.is_ascii_uppercase() && prev_lower {
+      lowered.push('-');
+    }
+    lowered.push(if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' });
+    prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+  }
+  let name = lowered.split('-').filter(|w| !w.is_empty()).collect::<Vec<_>>().join("-");
+  if name.split('-').all(|w| w.starts_with(|c: char| c.is_ascii_lowercase())) {
+    Ok(name)
+  } else {
+    Err(format!("`{raw}` has no valid WIT name (got `{name}`)"))
+  }
+}
+
+/// A WIT name as written in the interface, escaping keywords with `%`.
+fn wit_ident(name: &str) -> String {
+  if WIT_KEYWORDS.contains(&name) {
+    format!("%{name}")
+  } else {
+    name.to_string()
+  }
+}
+
+/// Collect the WIT interface from the schemas of all `defwasm-export` definitions.
+pub(super) fn collect_wit_interface(
+  program_data: &program::ProgramCompiledData,
+  ns_order: &[&str],
+  init_ns: &str,
+) -> Result<WitInterface, String> {
+  let mut interface = WitInterface {
+    package: format!("calcit:{}", wit_name(init_ns)?),
+    records: vec![],
+    exports: vec![],
+  };
+  for &ns in ns_order {
+    let Some(file_info) = program_data.get(ns) else {
+      continue;
+    };
+    let mut defs: Vec<_> = file_info.defs.iter().collect();
+    defs.sort_by(|a, b| a.0.cmp(b.0));
+    for (def_name, compiled) in defs {
+      if !is_wasm_export_def(&compiled.preprocessed_code) {
+        continue;
+      }
+      let export =
+        wit_export_of(ns, def_name, compiled, &mut interface.records).map_err(|e| format!("[wasm] WIT export {ns}/{def_name}: {e}"))?;
+      if interface.exports.iter().any(|other| other.name == export.name) {
+        return Err(format!(
+          "[wasm] WIT export {ns}/{def_name}: name `{}` is already exported",
+          export.name
+        ));
+      }
+      interface.exports.push(export);
+    }
+  }
+  Ok(interface)
+}
+
+fn wit_export_of(
+  ns: &str,
+  def_name: &str,
+  compiled: &program::CompiledDef,
+  records: &mut Vec<Arc<WitRecord>>,
+) -> Result<WitExport, String> {
+  let CalcitTypeAnnotation::Fn(signature) = compiled.schema.as_ref() else {
+    return Err("needs a `:: 'Fn` schema with typed args and return".into());
+  };
+  if signature.rest_type.is_some() || !signature.generics.is_empty() {
+    return Err("rest args and generics are not supported".into());
+  }
+  let (args, _) = extract_fn_parts(&compiled.preprocessed_code)?;
+  let arg_names: Vec<String> = match &args {
+    CalcitFnArgs::Args(idxs) => idxs.iter().map(|idx| CalcitLocal::read_name(*idx)).collect(),
+    CalcitFnArgs::MarkedArgs(_) => return Err("optional and rest args are not supported".into()),
+  };
+  if arg_names.len() != signature.arg_types.len() {
+    return Err(format!(
+      "schema declares {} args, definition takes {}",
+      signature.arg_types.len(),
+      arg_names.len()
+    ));
+  }
+  let mut params = vec![];
+  for (name, ty) in arg_names.iter().zip(&signature.arg_types) {
+    params.push((wit_name(name)?, wit_type_of(ty, ns, records)?));
+  }
+  let flat_count: usize = params.iter().map(|(_, ty)| ty.flat().len()).sum();
+  if flat_count > MAX_FLAT_PARAMS {
+    return Err(format!(
+      "params flatten to {flat_count} core values, at most {MAX_FLAT_PARAMS} are supported"
+    ));
+  }
+  Ok(WitExport {
+    def: format!("{ns}/{def_name}"),
+    name: wit_name(def_name)?,
+    params,
+    result: wit_type_of(&signature.return_type, ns, records)?,
+  })
+}
+
+fn wit_type_of(ty: &CalcitTypeAnnotation, ns: &str, records: &mut Vec<Arc<WitRecord>>) -> Result<WitType, String> {
+  match ty {
+    CalcitTypeAnnotation::Number => Ok(WitType::F64),
+    CalcitTypeAnnotation::Bool => Ok(WitType::Bool),
+    CalcitTypeAnnotation::String => Ok(WitType::String),
+    CalcitTypeAnnotation::List(item) => Ok(WitType::List(Box::new(wit_type_of(item, ns, records)?))),
+    _ => {
+      let struct_def = resolve_schema_struct(ty, ns).ok_or_else(|| format!("type `{}` has no WIT mapping", ty.describe()))?;
+      let struct_name = struct_def.name.to_string();
+      if let Some(record) = records.iter().find(|r| r.struct_name == struct_name) {
+        return Ok(WitType::Record(record.clone()));
+      }
+      if struct_def.fields.is_empty() || !struct_def.generics.is_empty() {
+        return Err(format!("struct {struct_name} needs at least one field and no generics"));
+      }
+      let mut fields = vec![];
+      for (field, field_ty) in struct_def.fields.iter().zip(struct_def.field_types.iter()) {
+        let field_ty = wit_type_of(field_ty, ns, records).map_err(|e| format!("field {struct_name}.{field}: {e}"))?;
+        fields.push((wit_name(field.ref_str())?, field_ty));
+      }
+      let record = Arc::new(WitRecord {
+        name: wit_name(&struct_name)?,
+        struct_name,
+        fields,
+      });
+      records.push(record.clone());
+      Ok(WitType::Record(record))
+    }
+  }
+}
+
+/// Struct named by a schema, looking bare names up in the export's namespace first.
+fn resolve_schema_struct(ty: &CalcitTypeAnnotation, ns: &str) -> Option<CalcitStructDef> {
+  if let CalcitTypeAnnotation::TypeRef(name, args) = ty
+    && !name.contains('/')
+  {
+    let qualified = CalcitTypeAnnotation::TypeRef(format!("{ns}/{}", name.trim_start_matches('\'')).into(), args.clone());
+    if let Some(found) = qualified.resolve_to_struct() {
+      return Some(found);
+    }
+  }
+  ty.resolve_to_struct()
+}
+
+impl WitInterface {
+  pub(super) fn is_empty(&self) -> bool {
+    self.exports.is_empty()
+  }
+
+  /// Struct names lifted by adapters; they need heap tags even when no
+  /// compiled code mentions them.
+  pub(super) fn struct_names(&self) -> impl Iterator<Item = &str> {
+    self.records.iter().map(|r| r.struct_name.as_str())
+  }
+
+  /// Render the interface as a `.wit` document.
+  pub(super) fn render(&self) -> String {
+    let mut out = String::new();
+    out.push_str("// Generated by cr-wasm from the schemas of `defwasm-export` definitions.\n");
+    out.push_str(&format!("package {};\n\ninterface {WIT_INTERFACE} {{\n", self.package));
+    for record in &self.records {
+      out.push_str(&format!("  record {} {{\n", wit_ident(&record.name)));
+      for (name, ty) in &record.fields {
+        out.push_str(&format!("    {}: {},\n", wit_ident(name), ty.render()));
+      }
+      out.push_str("  }\n\n");
+    }
+    for export in &self.exports {
+      let params: Vec<String> = export
+        .params
+        .iter()
+        .map(|(name, ty)| format!("{}: {}", wit_ident(name), ty.render()))
+        .collect();
+      out.push_str(&format!(
+        "  {}: func({}) -> {};\n",
+        wit_ident(&export.name),
+        params.join(", "),
+        export.result.render()
+      ));
+    }
+    out.push_str(&format!("}}\n\nworld {WIT_WORLD} {{\n  export {WIT_INTERFACE};\n}}\n"));
+    out
+  }
+
+  /// Build `cabi_realloc` and one canonical ABI adapter per export.
+  pub(super) fn build_adapters(&self, env: &WasmCompileEnv) -> Result<Vec<CompiledFn>, String> {
+    let rt = |name: &str| *env.runtime_fn_index.get(name).expect("runtime helper");
+    let tag = |name: &str| -> Result<i32, String> {
+      env
+        .tag_index
+        .get(name)
+        .map(|id| *id as i32)
+        .ok_or_else(|| format!("[wasm] missing heap tag `{name}`"))
+    };
+    let abi = AbiHelpers {
+      alloc: rt("__rt_alloc"),
+      gc_reset: rt("__rt_gc_reset"),
+      str_new: rt("__str_new"),
+      string_tag: tag("string")?,
+      list_tag: tag("list")?,
+      struct_tag: tag("struct")?,
+      struct_name_tags: self
+        .records
+        .iter()
+        .map(|r| Ok((r.struct_name.clone(), tag(&r.struct_name)?)))
+        .collect::<Result<_, String>>()?,
+    };
+    let mut fns = vec![build_cabi_realloc(&abi)];
+    for export in &self.exports {
+      let fn_idx = *env
+        .fn_index
+        .get(&export.def)
+        .ok_or_else(|| format!("[wasm] WIT export {} was not compiled", export.def))?;
+      let mut func = build_adapter(&abi, export, fn_idx);
+      func.export_name = Some(format!("{}/{WIT_INTERFACE}#{}", self.package, export.name));
+      fns.push(func);
+    }
+    Ok(fns)
+  }
+}
+
+struct AbiHelpers {
+  alloc: u32,
+  gc_reset: u32,
+  str_new: u32,
+  string_tag: i32,
+  list_tag: i32,
+  struct_tag: i32,
+  struct_name_tags: HashMap<String, i32>,
+}
+
+impl AbiHelpers {
+  /// `dst = __rt_alloc(size, tag)` for a size already on the stack.
+  fn alloc_into(&self, b: &mut RuntimeFnBuilder, tag: i32, dst: u32) {
+    b.emit_all([
+      Instruction::I32Const(tag),
+      Instruction::Call(self.alloc),
+      Instruction::LocalSet(dst),
+    ]);
+  }
+}
+
+/// `cabi_realloc(old_ptr, old_size, align, new_size) → ptr` — host buffers
+/// are unscanned heap blocks, garbage once the call that received them returns.
+fn build_cabi_realloc(abi: &AbiHelpers) -> CompiledFn {
+  let mut b = RuntimeFnBuilder::new(4); // old_ptr, old_size, align, new_size
+  let ptr = b.alloc_i32();
+  b.emit_all([
+    Instruction::LocalGet(3),
+    Instruction::I32Const(1),
+    Instruction::LocalGet(3),
+    Instruction::I32Const(1),
+    Instruction::I32GeU,
+    Instruction::Select,
+  ]);
+  abi.alloc_into(&mut b, abi.string_tag, ptr);
+  b.emit_all([
+    Instruction::LocalGet(0),
+    Instruction::If(BlockType::Empty),
+    Instruction::LocalGet(ptr),
+    Instruction::LocalGet(0),
+    Instruction::LocalGet(1),
+    Instruction::LocalGet(3),
+    Instruction::LocalGet(1),
+    Instruction::LocalGet(3),
+    Instruction::I32LtU,
+    Instruction::Select,
+    Instruction::MemoryCopy { src_mem: 0, dst_mem: 0 },
+    Instruction::End,
+    Instruction::LocalGet(ptr),
+  ]);
+  let mut func = b.finish(vec![ValType::I32; 4], vec![ValType::I32]);
+  func.export_name = Some("cabi_realloc".to_string());
+  func
+}
+
+fn build_adapter(abi: &AbiHelpers, export: &WitExport, fn_idx: u32) -> CompiledFn {
+  let core_params: Vec<ValType> = export.params.iter().flat_map(|(_, ty)| ty.flat()).collect();
+  let mut b = RuntimeFnBuilder::new(core_params.len() as u32);
+  b.emit(Instruction::Call(abi.gc_reset));
+
+  // lift params, in order, onto the stack
+  let mut next_param = 0;
+  for (_, ty) in &export.params {
+    lift_flat(&mut b, abi, ty, &mut next_param);
+  }
+  b.emit(Instruction::Call(fn_idx));
+
+  let result = b.alloc_f64();
+  b.emit(Instruction::LocalSet(result));
+  let core_results = export.result.flat();
+  if core_results.len() == 1 {
+    lower_flat(&mut b, &export.result, result);
+    return b.finish(core_params, core_results);
+  }
+  let area = b.alloc_i32();
+  b.emit(Instruction::I32Const(export.result.size() as i32));
+  abi.alloc_into(&mut b, abi.string_tag, area);
+  store_value(&mut b, abi, &export.result, result, area, 0);
+  b.emit(Instruction::LocalGet(area));
+  b.finish(core_params, vec![ValType::I32])
+}
+
+/// Push the heap value for `ty` built from consecutive flat params.
+fn lift_flat(b: &mut RuntimeFnBuilder, abi: &AbiHelpers, ty: &WitType, next_param: &mut u32) {
+  match ty {
+    WitType::F64 => {
+      b.emit(Instruction::LocalGet(*next_param));
+      *next_param += 1;
+    }
+    WitType::Bool => {
+      b.emit_all([
+        Instruction::LocalGet(*next_param),
+        Instruction::I32Const(0),
+        Instruction::I32Ne,
+        Instruction::F64ConvertI32U,
+      ]);
+      *next_param += 1;
+    }
+    WitType::String => {
+      b.emit_all([
+        Instruction::LocalGet(*next_param),
+        Instruction::LocalGet(*next_param + 1),
+        Instruction::Call(abi.str_new),
+      ]);
+      *next_param += 2;
+    }
+    WitType::List(item) => {
+      lift_list(b, abi, item, *next_param, *next_param + 1);
+      *next_param += 2;
+    }
+    WitType::Record(record) => {
+      let obj = alloc_struct(b, abi, record);
+      for (i, (_, field_ty)) in record.fields.iter().enumerate() {
+        b.emit(Instruction::LocalGet(obj));
+        lift_flat(b, abi, field_ty, next_param);
+        b.emit(Instruction::F64Store(mem_arg_f64(((2 + i) * 8) as u64)));
+      }
+      b.emit_all([Instruction::LocalGet(obj), Instruction::F64ConvertI32U]);
+    }
+  }
+}
+
+/// Allocate a struct object for `record` with its header written; returns the pointer local.
+fn alloc_struct(b: &mut RuntimeFnBuilder, abi: &AbiHelpers, record: &WitRecord) -> u32 {
+  let obj = b.alloc_i32();
+  let field_count = record.fields.len();
+  b.emit(Instruction::I32Const(((2 + field_count) * 8) as i32));
+  abi.alloc_into(b, abi.struct_tag, obj);
+  b.emit_all([
+    Instruction::LocalGet(obj),
+    f64_const(field_count as f64),
+    Instruction::F64Store(mem_arg_f64(0)),
+    Instruction::LocalGet(obj),
+    f64_const(abi.struct_name_tags[&record.struct_name] as f64),
+    Instruction::F64Store(mem_arg_f64(8)),
+  ]);
+  obj
+}
+
+/// Push a list value built from `len` packed `item`s at `ptr` (both i32 locals).
+fn lift_list(b: &mut RuntimeFnBuilder, abi: &AbiHelpers, item: &WitType, ptr: u32, len: u32) {
+  let list = b.alloc_i32();
+  let i = b.alloc_i32();
+  let addr = b.alloc_i32();
+  b.emit_all([
+    Instruction::LocalGet(len),
+    Instruction::I32Const(1),
+    Instruction::I32Add,
+    Instruction::I32Const(8),
+    Instruction::I32Mul,
+  ]);
+  abi.alloc_into(b, abi.list_tag, list);
+  b.emit_all([
+    Instruction::LocalGet(list),
+    Instruction::LocalGet(len),
+    Instruction::F64ConvertI32U,
+    Instruction::F64Store(mem_arg_f64(0)),
+    Instruction::I32Const(0),
+    Instruction::LocalSet(i),
+    Instruction::Block(BlockType::Empty),
+    Instruction::Loop(BlockType::Empty),
+    Instruction::LocalGet(i),
+    Instruction::LocalGet(len),
+    Instruction::I32GeU,
+    Instruction::BrIf(1),
+    Instruction::LocalGet(ptr),
+    Instruction::LocalGet(i),
+    Instruction::I32Const(item.size() as i32),
+    Instruction::I32Mul,
+    Instruction::I32Add,
+    Instruction::LocalSet(addr),
+    // list + 8 + i * 8
+    Instruction::LocalGet(list),
+    Instruction::LocalGet(i),
+    Instruction::I32Const(8),
+    Instruction::I32Mul,
+    Instruction::I32Add,
+  ]);
+  load_value(b, abi, item, addr, 0);
+  b.emit_all([
+    Instruction::F64Store(mem_arg_f64(8)),
+    Instruction::LocalGet(i),
+    Instruction::I32Const(1),
+    Instruction::I32Add,
+    Instruction::LocalSet(i),
+    Instruction::Br(0),
+    Instruction::End,
+    Instruction::End,
+    Instruction::LocalGet(list),
+    Instruction::F64ConvertI32U,
+  ]);
+}
+
+/// Push the heap value for `ty` stored in host memory at `addr + offset`.
+fn load_value(b: &mut RuntimeFnBuilder, abi: &AbiHelpers, ty: &WitType, addr: u32, offset: u32) {
+  let at = |b: &mut RuntimeFnBuilder, extra: u32| {
+    b.emit_all([
+      Instruction::LocalGet(addr),
+      Instruction::I32Const((offset + extra) as i32),
+      Instruction::I32Add,
+    ]);
+  };
+  match ty {
+    WitType::F64 => {
+      at(b, 0);
+      b.emit(Instruction::F64Load(mem_arg_f64(0)));
+    }
+    WitType::Bool => {
+      at(b, 0);
+      b.emit_all([
+        Instruction::I32Load8U(mem_arg_byte(0)),
+        Instruction::I32Const(0),
+        Instruction::I32Ne,
+        Instruction::F64ConvertI32U,
+      ]);
+    }
+    WitType::String => {
+      at(b, 0);
+      b.emit(Instruction::I32Load(mem_arg_i32(0)));
+      at(b, 4);
+      b.emit_all([Instruction::I32Load(mem_arg_i32(0)), Instruction::Call(abi.str_new)]);
+    }
+    WitType::List(item) => {
+      let ptr = b.alloc_i32();
+      let len = b.alloc_i32();
+      at(b, 0);
+      b.emit_all([Instruction::I32Load(mem_arg_i32(0)), Instruction::LocalSet(ptr)]);
+      at(b, 4);
+      b.emit_all([Instruction::I32Load(mem_arg_i32(0)), Instruction::LocalSet(len)]);
+      lift_list(b, abi, item, ptr, len);
+    }
+    WitType::Record(record) => {
+      let obj = alloc_struct(b, abi, record);
+      for (i, (field_offset, field_ty)) in record.field_offsets().into_iter().enumerate() {
+        b.emit(Instruction::LocalGet(obj));
+        load_value(b, abi, field_ty, addr, offset + field_offset);
+        b.emit(Instruction::F64Store(mem_arg_f64(((2 + i) * 8) as u64)));
+      }
+      b.emit_all([Instruction::LocalGet(obj), Instruction::F64ConvertI32U]);
+    }
+  }
+}
+
+/// Push the single flat core value of `ty` for the heap value in `value`.
+fn lower_flat(b: &mut RuntimeFnBuilder, ty: &WitType, value: u32) {
+  match ty {
+    WitType::F64 => b.emit(Instruction::LocalGet(value)),
+    WitType::Bool => b.emit_all([Instruction::LocalGet(value), f64_const(0.0), Instruction::F64Ne]),
+    WitType::Record(record) => {
+      // a single-field record flattens to its field
+      let field = b.alloc_f64();
+      b.emit_all([
+        Instruction::LocalGet(value),
+        Instruction::I32TruncF64U,
+        Instruction::F64Load(mem_arg_f64(16)),
+        Instruction::LocalSet(field),
+      ]);
+      lower_flat(b, &record.fields[0].1, field);
+    }
+    WitType::String | WitType::List(_) => unreachable!("strings and lists flatten to two values"),
+  }
+}
+
+/// Store the heap value in `value` into host memory at `addr + offset`.
+fn store_value(b: &mut RuntimeFnBuilder, abi: &AbiHelpers, ty: &WitType, value: u32, addr: u32, offset: u32) {
+  match ty {
+    WitType::F64 => b.emit_all([
+      Instruction::LocalGet(addr),
+      Instruction::LocalGet(value),
+      Instruction::F64Store(mem_arg_f64(offset as u64)),
+    ]),
+    WitType::Bool => b.emit_all([
+      Instruction::LocalGet(addr),
+      Instruction::LocalGet(value),
+      f64_const(0.0),
+      Instruction::F64Ne,
+      Instruction::I32Store8(mem_arg_byte(offset as u64)),
+    ]),
+    WitType::String => {
+      // bytes stay in the heap string; the host copies them before the next call
+      b.emit_all([
+        Instruction::LocalGet(addr),
+        Instruction::LocalGet(value),
+        Instruction::I32TruncF64U,
+        Instruction::I32Const(8),
+        Instruction::I32Add,
+        Instruction::I32Store(mem_arg_i32(offset as u64)),
+        Instruction::LocalGet(addr),
+        Instruction::LocalGet(value),
+        Instruction::I32TruncF64U,
+        Instruction::F64Load(mem_arg_f64(0)),
+        Instruction::I32TruncF64U,
+        Instruction::I32Store(mem_arg_i32(offset as u64 + 4)),
+      ]);
+    }
+    WitType::List(item) => {
+      let list = b.alloc_i32();
+      let len = b.alloc_i32();
+      let items = b.alloc_i32();
+      let i = b.alloc_i32();
+      let elem = b.alloc_f64();
+      let elem_addr = b.alloc_i32();
+      b.emit_all([
+        Instruction::LocalGet(value),
+        Instruction::I32TruncF64U,
+        Instruction::LocalTee(list),
+        Instruction::F64Load(mem_arg_f64(0)),
+        Instruction::I32TruncF64U,
+        Instruction::LocalTee(len),
+        Instruction::I32Const(item.size() as i32),
+        Instruction::I32Mul,
+      ]);
+      abi.alloc_into(b, abi.string_tag, items);
+      b.emit_all([
+        Instruction::Block(BlockType::Empty),
+        Instruction::Loop(BlockType::Empty),
+        Instruction::LocalGet(i),
+        Instruction::LocalGet(len),
+        Instruction::I32GeU,
+        Instruction::BrIf(1),
+        Instruction::LocalGet(list),
+        Instruction::LocalGet(i),
+        Instruction::I32Const(8),
+        Instruction::I32Mul,
+        Instruction::I32Add,
+        Instruction::F64Load(mem_arg_f64(8)),
+        Instruction::LocalSet(elem),
+        Instruction::LocalGet(items),
+        Instruction::LocalGet(i),
+        Instruction::I32Const(item.size() as i32),
+        Instruction::I32Mul,
+        Instruction::I32Add,
+        Instruction::LocalSet(elem_addr),
+      ]);
+      store_value(b, abi, item, elem, elem_addr, 0);
+      b.emit_all([
+        Instruction::LocalGet(i),
+        Instruction::I32Const(1),
+        Instruction::I32Add,
+        Instruction::LocalSet(i),
+        Instruction::Br(0),
+        Instruction::End,
+        Instruction::End,
+        Instruction::LocalGet(addr),
+        Instruction::LocalGet(items),
+        Instruction::I32Store(mem_arg_i32(offset as u64)),
+        Instruction::LocalGet(addr),
+        Instruction::LocalGet(len),
+        Instruction::I32Store(mem_arg_i32(offset as u64 + 4)),
+      ]);
+    }
+    WitType::Record(record) => {
+      let obj = b.alloc_i32();
+      b.emit_all([Instruction::LocalGet(value), Instruction::I32TruncF64U, Instruction::LocalSet(obj)]);
+      for (i, (field_offset, field_ty)) in record.field_offsets().into_iter().enumerate() {
+        let field = b.alloc_f64();
+        b.emit_all([
+          Instruction::LocalGet(obj),
+          Instruction::F64Load(mem_arg_f64(((2 + i) * 8) as u64)),
+          Instruction::LocalSet(field),
+        ]);
+        store_value(b, abi, field_ty, field, addr, offset + field_offset);
+      }
+    }
+  }
+}
@@ -2,16 +2,50 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use cirru_edn::{Edn, EdnListView, format};
 
+use crate::builtins::{self, effects::CliRunningMode};
+use crate::calcit::data_shape::DataShapeGraph;
 use crate::calcit::{
   Calcit, CalcitArgLabel, CalcitEnumDef, CalcitEnumValue, CalcitFnArgs, CalcitImpl, CalcitImport, CalcitLocal, CalcitStructDef,
-  CalcitStructValue, CalcitTypeAnnotation, ImportInfo, MethodKind,
+  CalcitStructValue, CalcitSyntax, CalcitTypeAnnotation, ImportInfo, MethodKind,
 };
 use crate::program;
 
+pub mod binary;
+mod load;
+
+pub use load::{LoadedIr, load_ir, load_ir_data};
+
+/// Version of the `program-ir.cirru` layout. Bump it whenever the shape of the
+/// dumped code changes, so `calcit run-ir` rejects files it would misread.
+pub const IR_SCHEMA_VERSION: u32 = 2;
+
+/// File format written by [`emit_ir`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IrFormat {
+  /// pretty-printed Cirru EDN in `program-ir.cirru`
+  #[default]
+  Cirru,
+  /// MessagePack in `program-ir.msgpack`, see [`binary`] for the layout
+  Msgpack,
+}
+
+impl FromStr for IrFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "cirru" => Ok(Self::Cirru),
+      "msgpack" => Ok(Self::Msgpack),
+      _ => Err(format!("unknown IR format `{s}`, expected `cirru` or `msgpack`")),
+    }
+  }
+}
+
 thread_local! {
   static TYPE_INFO_STACK: RefCell<Vec<(Arc<str>, Arc<str>)>> = const { RefCell::new(vec![]) };
 }
@@ -64,11 +98,17 @@ impl From<IrDataFile> for Edn {
 struct IrDataConfig {
   init_fn: String,
   reload_fn: String,
+  /// mode macros were expanded with, read back by `calcit run-ir`
+  running_mode: CliRunningMode,
 }
 
 impl From<IrDataConfig> for Edn {
   fn from(x: IrDataConfig) -> Edn {
-    Edn::map_from_iter([(Edn::tag("init-fn"), x.init_fn.into()), (Edn::tag("reload-fn"), x.reload_fn.into())])
+    Edn::map_from_iter([
+      (Edn::tag("init-fn"), x.init_fn.into()),
+      (Edn::tag("reload-fn"), x.reload_fn.into()),
+      (Edn::tag("running-mode"), Edn::tag(x.running_mode.as_str())),
+    ])
   }
 }
 
@@ -80,11 +120,16 @@ pub struct IrData {
 
 impl From<IrData> for Edn {
   fn from(x: IrData) -> Edn {
-    Edn::map_from_iter([(Edn::tag("configs"), x.configs.into()), (Edn::tag("files"), x.files.into())])
+    Edn::map_from_iter([
+      (Edn::tag("schema-version"), Edn::Number(IR_SCHEMA_VERSION as f64)),
+      (Edn::tag("calcit-version"), Edn::str(crate::cli_args::CALCIT_VERSION)),
+      (Edn::tag("configs"), x.configs.into()),
+      (Edn::tag("files"), x.files.into()),
+    ])
   }
 }
 
-pub fn emit_ir(init_fn: &str, reload_fn: &str, emit_path: &str) -> Result<(), String> {
+pub fn emit_ir(init_fn: &str, reload_fn: &str, emit_path: &str, ir_format: IrFormat) -> Result<(), String> {
   let program_data = program::clone_compiled_program_snapshot()?;
 
   let mut files: HashMap<Arc<str>, IrDataFile> = HashMap::new();
@@ -103,13 +148,17 @@ pub fn emit_ir(init_fn: &str, reload_fn: &str, emit_path: &str) -> Result<(), St
     configs: IrDataConfig {
       init_fn: init_fn.to_owned(),
       reload_fn: reload_fn.to_owned(),
+      running_mode: builtins::effects::cli_running_mode(),
     },
     files,
   };
 
-  let content = match format(&data.into(), true) {
-    Ok(v) => v,
-    Err(e) => return Err(format!("failed {e}")),
+  let (file_name, content) = match ir_format {
+    IrFormat::Cirru => match format(&data.into(), true) {
+      Ok(v) => ("program-ir.cirru", v.into_bytes()),
+      Err(e) => return Err(format!("failed {e}")),
+    },
+    IrFormat::Msgpack => ("program-ir.msgpack", binary::encode_binary_ir(&data.into())?),
   };
 
   let code_emit_path = Path::new(emit_path);
@@ -117,9 +166,9 @@ pub fn emit_ir(init_fn: &str, reload_fn: &str, emit_path: &str) -> Result<(), St
     let _ = fs::create_dir(code_emit_path);
   }
 
-  let js_file_path = code_emit_path.join("program-ir.cirru");
-  let _ = fs::write(&js_file_path, content);
-  println!("wrote to: {}", js_file_path.to_str().expect("extract path"));
+  let ir_file_path = code_emit_path.join(file_name);
+  let _ = fs::write(&ir_file_path, content);
+  println!("wrote to: {}", ir_file_path.to_str().expect("extract path"));
 
   Ok(())
 }
@@ -245,6 +294,17 @@ pub(crate) fn dump_code(code: &Calcit) -> Edn {
       xs.traverse(&mut |x| {
         ys.push(dump_code(x));
       });
+      // decoders compiled by preprocessing are runtime handles; without one the
+      // syntax rebuilds its decoder from the type form when it runs, so names in
+      // the form are qualified as there are no ns imports to resolve them from
+      if matches!(
+        xs.first(),
+        Some(Calcit::Syntax(CalcitSyntax::ParseCirruEdnAs | CalcitSyntax::DecodeMapAs, _))
+      ) && let (Some(type_form), Some(decoder)) = (xs.get(2), xs.get(3).and_then(DataShapeGraph::from_calcit_handle))
+      {
+        ys.truncate(2);
+        ys.push(dump_code(&qualify_type_form(type_form, &decoder.nominal_paths())));
+      }
       Edn::from(ys)
     }
     Calcit::Enum(enum_value) => dump_enum_value_code(enum_value),
@@ -258,7 +318,11 @@ pub(crate) fn dump_code(code: &Calcit) -> Edn {
         (Edn::tag("behavior"), Edn::Str((kind.to_string()).into())),
         (Edn::tag("method"), Edn::Str(method.to_owned())),
       ];
-      if let MethodKind::Invoke(t) = kind
+      if let MethodKind::Invoke(t)
+      | MethodKind::ExternalAccess(t)
+      | MethodKind::ExternalGet(t)
+      | MethodKind::ExternalSet(t)
+      | MethodKind::ExternalInvoke(t) = kind
         && !matches!(**t, CalcitTypeAnnotation::Dynamic)
       {
         entries.push((Edn::tag("receiver-type"), dump_type_annotation(t.as_ref())));
@@ -352,6 +416,22 @@ fn dump_type_annotation(type_info: &CalcitTypeAnnotation) -> Edn {
   type_info.to_type_edn()
 }
 
+/// Replace names of nominal types in a type form with `ns/def` paths.
+fn qualify_type_form(form: &Calcit, paths: &[(Arc<str>, Arc<str>)]) -> Calcit {
+  match form {
+    Calcit::Symbol { sym, info, location } => match paths.iter().filter(|(_, def)| def == sym).collect::<Vec<_>>()[..] {
+      [(ns, def)] => Calcit::Symbol {
+        sym: Arc::from(format!("{ns}/{def}")),
+        info: info.to_owned(),
+        location: location.to_owned(),
+      },
+      _ => form.to_owned(),
+    },
+    Calcit::List(xs) => Calcit::from(xs.iter().map(|x| qualify_type_form(x, paths)).collect::<Vec<_>>()),
+    _ => form.to_owned(),
+  }
+}
+
 fn dump_enum_value_code(enum_value: &CalcitEnumValue) -> Edn {
   let mut entries = enum_value_metadata_entries(enum_value);
   let mut values = EdnListView::default();
@@ -0,0 +1,506 @@
+//! Binary form of the IR, written by `calcit ir --format msgpack` to
+//! `program-ir.msgpack` and read back by `calcit run-ir`.
+//!
+//! The file is one MessagePack map with string keys:
+//!
+//! | key              | value                                                   |
+//! |------------------|---------------------------------------------------------|
+//! | `format`         | always `"calcit-ir"`                                    |
+//! | `format-version` | [`IR_BINARY_FORMAT_VERSION`], the encoding below        |
+//! | `schema-version` | [`IR_SCHEMA_VERSION`](super::IR_SCHEMA_VERSION), the node shapes |
+//! | `calcit-version` | version of the calcit that wrote the file               |
+//! | `content-hash`   | `"md5:<hex>"` of the encoded bytes of `program`         |
+//! | `program`        | the same data as `program-ir.cirru`, encoded as below   |
+//!
+//! The header fields come first, so tools can check versions before decoding
+//! the program. EDN values in `program` map to MessagePack as:
+//!
+//! - nil, booleans, strings, lists and maps map to their MessagePack types;
+//! - numbers are integers when they are whole and within ±2^53, otherwise float 64;
+//! - a tag is ext type 1 and a symbol ext type 2, both holding the UTF-8 name
+//!   without the leading `:` or `'`;
+//! - a quoted Cirru tree is ext type 3, holding a MessagePack value where a
+//!   leaf is a string and an expression is an array;
+//! - a set is ext type 4, holding an array of its items;
+//! - an enum value is ext type 5, holding `[variant, type-name or nil, [values...]]`.
+//!
+//! Map entries and set items are sorted by their encoded bytes, so the same
+//! program always encodes to the same bytes and the same hash.
+
+use cirru_edn::{Edn, EdnEnumView, EdnListView, EdnMapView, EdnSetView, EdnTag};
+use cirru_parser::Cirru;
+use md5::{Digest, Md5};
+use rmp::Marker;
+use rmp::encode;
+
+use super::IR_SCHEMA_VERSION;
+
+/// Version of the binary encoding. Bump it when the layout of the file or the
+/// mapping of EDN values changes; changes to node shapes bump the schema version.
+pub const IR_BINARY_FORMAT_VERSION: u32 = 1;
+
+const FORMAT_NAME: &str = "calcit-ir";
+
+const EXT_TAG: i8 = 1;
+const EXT_SYMBOL: i8 = 2;
+const EXT_QUOTE: i8 = 3;
+const EXT_SET: i8 = 4;
+const EXT_ENUM: i8 = 5;
+
+/// Largest magnitude at which every whole `f64` is exactly representable.
+const MAX_SAFE_INTEGER: f64 = 9007199254740992.0;
+
+/// A decoded binary IR file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryIr {
+  pub format_version: u32,
+  pub schema_version: u32,
+  pub calcit_version: String,
+  /// `md5:<hex>` of the encoded program, already checked against the content.
+  pub content_hash: String,
+  /// Same data as `program-ir.cirru`.
+  pub program: Edn,
+}
+
+/// Whether `bytes` look like a binary IR file rather than Cirru text.
+pub fn is_binary_ir(bytes: &[u8]) -> bool {
+  matches!(
+    bytes.first().map(|b| Marker::from_u8(*b)),
+    Some(Marker::FixMap(_) | Marker::Map16 | Marker::Map32)
+  )
+}
+
+/// Encode IR data, as built for `program-ir.cirru`, into the binary form.
+pub fn encode_binary_ir(program: &Edn) -> Result<Vec<u8>, String> {
+  let mut payload = vec![];
+  write_edn(&mut payload, program)?;
+
+  let mut buf = vec![];
+  encoded(encode::write_map_len(&mut buf, 6))?;
+  write_str(&mut buf, "format")?;
+  write_str(&mut buf, FORMAT_NAME)?;
+  write_str(&mut buf, "format-version")?;
+  encoded(encode::write_uint(&mut buf, IR_BINARY_FORMAT_VERSION as u64))?;
+  write_str(&mut buf, "schema-version")?;
+  encoded(encode::write_uint(&mut buf, IR_SCHEMA_VERSION as u64))?;
+  write_str(&mut buf, "calcit-version")?;
+  write_str(&mut buf, crate::cli_args::CALCIT_VERSION)?;
+  write_str(&mut buf, "content-hash")?;
+  write_str(&mut buf, &content_hash(&payload))?;
+  write_str(&mut buf, "program")?;
+  buf.extend_from_slice(&payload);
+  Ok(buf)
+}
+
+/// Decode a binary IR file, checking the format version and the content hash.
+/// The schema version is returned as found; [`super::load_ir_data`] checks it.
+pub fn decode_binary_ir(bytes: &[u8]) -> Result<BinaryIr, String> {
+  let mut rd = Reader { bytes, pos: 0 };
+  let len = match rd.marker()? {
+    Marker::FixMap(n) => n as usize,
+    Marker::Map16 => rd.uint(2)? as usize,
+    Marker::Map32 => rd.uint(4)? as usize,
+    _ => return Err("binary IR should start with a header map".to_owned()),
+  };
+
+  let mut format = None;
+  let mut format_version = None;
+  let mut schema_version = None;
+  let mut calcit_version = None;
+  let mut content_hash_field = None;
+  let mut program = None;
+  for _ in 0..len {
+    let key = match rd.value()? {
+      Edn::Str(s) => s,
+      a => return Err(format!("binary IR header key should be a string, got: {a}")),
+    };
+    match &*key {
+      "program" => {
+        // versions come first, so an unsupported file is rejected before its program is decoded
+        if format_version != Some(IR_BINARY_FORMAT_VERSION) {
+          break;
+        }
+        let start = rd.pos;
+        let value = rd.value()?;
+        program = Some((value, content_hash(&bytes[start..rd.pos])));
+      }
+      _ => {
+        let value = rd.value()?;
+        match &*key {
+          "format" => format = Some(value),
+          "format-version" => format_version = as_u32(&value),
+          "schema-version" => schema_version = as_u32(&value),
+          "calcit-version" => calcit_version = Some(value),
+          "content-hash" => content_hash_field = Some(value),
+          // later format versions may add fields
+          _ => {}
+        }
+      }
+    }
+  }
+
+  if format != Some(Edn::str(FORMAT_NAME)) {
+    return Err(format!("not a binary IR file, expected format \"{FORMAT_NAME}\""));
+  }
+  match format_version {
+    Some(IR_BINARY_FORMAT_VERSION) => {}
+    Some(v) => {
+      return Err(format!(
+        "binary IR format version {v} is not supported, this calcit reads version {IR_BINARY_FORMAT_VERSION}; re-emit it with `calcit ir --format msgpack`"
+      ));
+    }
+    None => return Err("binary IR has no format-version".to_owned()),
+  }
+  let Some((program, actual_hash)) = program else {
+    return Err("binary IR has no program".to_owned());
+  };
+  let content_hash = match content_hash_field {
+    Some(Edn::Str(s)) => s.to_string(),
+    _ => return Err("binary IR has no content-hash".to_owned()),
+  };
+  if content_hash != actual_hash {
+    return Err(format!(
+      "binary IR content hash mismatch, the header says {content_hash} but the program hashes to {actual_hash}; the file is corrupted or was edited"
+    ));
+  }
+
+  Ok(BinaryIr {
+    format_version: IR_BINARY_FORMAT_VERSION,
+    schema_version: schema_version.ok_or("binary IR has no schema-version")?,
+    calcit_version: match calcit_version {
+      Some(Edn::Str(s)) => s.to_string(),
+      _ => String::new(),
+    },
+    content_hash,
+    program,
+  })
+}
+
+fn content_hash(payload: &[u8]) -> String {
+  let mut hasher = Md5::new();
+  hasher.update(payload);
+  format!("md5:{}", hex::encode(hasher.finalize()))
+}
+
+fn as_u32(x: &Edn) -> Option<u32> {
+  match x {
+    Edn::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= u32::MAX as f64 => Some(*n as u32),
+    _ => None,
+  }
+}
+
+fn encoded<T, E: std::fmt::Display>(result: Result<T, E>) -> Result<(), String> {
+  result.map(|_| ()).map_err(|e| format!("failed to encode binary IR: {e}"))
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) -> Result<(), String> {
+  encoded(encode::write_str(buf, s))
+}
+
+fn write_ext(buf: &mut Vec<u8>, ty: i8, data: &[u8]) -> Result<(), String> {
+  encoded(encode::write_ext_meta(buf, data.len() as u32, ty))?;
+  buf.extend_from_slice(data);
+  Ok(())
+}
+
+/// Encode each item separately and sort, for a stable order of hash-based collections.
+fn sorted_encodings<'a>(xs: impl Iterator<Item = &'a Edn>) -> Result<Vec<Vec<u8>>, String> {
+  let mut ys = xs
+    .map(|x| {
+      let mut item = vec![];
+      write_edn(&mut item, x)?;
+      Ok(item)
+    })
+    .collect::<Result<Vec<_>, String>>()?;
+  ys.sort();
+  Ok(ys)
+}
+
+fn write_edn(buf: &mut Vec<u8>, x: &Edn) -> Result<(), String> {
+  match x {
+    Edn::Nil => encoded(encode::write_nil(buf)),
+    Edn::Bool(b) => encoded(encode::write_bool(buf, *b)),
+    Edn::Number(n) => {
+      if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER && !(*n == 0.0 && n.is_sign_negative()) {
+        encoded(encode::write_sint(buf, *n as i64))
+      } else {
+        encoded(encode::write_f64(buf, *n))
+      }
+    }
+    Edn::Str(s) => write_str(buf, s),
+    Edn::Tag(t) => write_ext(buf, EXT_TAG, t.ref_str().as_bytes()),
+    Edn::Symbol(s) => write_ext(buf, EXT_SYMBOL, s.as_bytes()),
+    Edn::Quote(code) => {
+      let mut data = vec![];
+      write_cirru(&mut data, code)?;
+      write_ext(buf, EXT_QUOTE, &data)
+    }
+    Edn::List(EdnListView(xs)) => {
+      encoded(encode::write_array_len(buf, xs.len() as u32))?;
+      xs.iter().try_for_each(|x| write_edn(buf, x))
+    }
+    Edn::Map(EdnMapView(xs)) => {
+      let mut pairs = xs
+        .iter()
+        .map(|(k, v)| {
+          let mut key = vec![];
+          write_edn(&mut key, k)?;
+          Ok((key, v))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+      pairs.sort_by(|a, b| a.0.cmp(&b.0));
+      encoded(encode::write_map_len(buf, pairs.len() as u32))?;
+      for (key, v) in pairs {
+        buf.extend_from_slice(&key);
+        write_edn(buf, v)?;
+      }
+      Ok(())
+    }
+    Edn::Set(EdnSetView(xs)) => {
+      let items = sorted_encodings(xs.iter())?;
+      let mut data = vec![];
+      encoded(encode::write_array_len(&mut data, items.len() as u32))?;
+      items.iter().for_each(|item| data.extend_from_slice(item));
+      write_ext(buf, EXT_SET, &data)
+    }
+    Edn::Enum(EdnEnumView { variant, type_name, extra }) => {
+      let mut data = vec![];
+      encoded(encode::write_array_len(&mut data, 3))?;
+      write_str(&mut data, variant)?;
+      match type_name {
+        Some(name) => write_str(&mut data, name)?,
+        None => encoded(encode::write_nil(&mut data))?,
+      }
+      write_edn(&mut data, &Edn::List(EdnListView(extra.to_owned())))?;
+      write_ext(buf, EXT_ENUM, &data)
+    }
+    a => Err(format!("binary IR cannot encode value: {a}")),
+  }
+}
+
+fn write_cirru(buf: &mut Vec<u8>, code: &Cirru) -> Result<(), String> {
+  match code {
+    Cirru::Leaf(s) => write_str(buf, s),
+    Cirru::List(xs) => {
+      encoded(encode::write_array_len(buf, xs.len() as u32))?;
+      xs.iter().try_for_each(|x| write_cirru(buf, x))
+    }
+  }
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+    let end = self.pos.checked_add(n).filter(|end| *end <= self.bytes.len());
+    let Some(end) = end else {
+      return Err(format!("binary IR ends unexpectedly at byte {}", self.pos));
+    };
+    let data = &self.bytes[self.pos..end];
+    self.pos = end;
+    Ok(data)
+  }
+
+  fn marker(&mut self) -> Result<Marker, String> {
+    Ok(Marker::from_u8(self.take(1)?[0]))
+  }
+
+  /// Big-endian unsigned integer of `n` bytes.
+  fn uint(&mut self, n: usize) -> Result<u64, String> {
+    Ok(self.take(n)?.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+  }
+
+  /// Big-endian signed integer of `n` bytes.
+  fn sint(&mut self, n: usize) -> Result<i64, String> {
+    let shift = 64 - 8 * n as u32;
+    Ok(((self.uint(n)? << shift) as i64) >> shift)
+  }
+
+  fn str(&mut self, n: usize) -> Result<&'a str, String> {
+    let at = self.pos;
+    std::str::from_utf8(self.take(n)?).map_err(|e| format!("invalid UTF-8 in binary IR at byte {at}: {e}"))
+  }
+
+  fn value(&mut self) -> Result<Edn, String> {
+    let at = self.pos;
+    match self.marker()? {
+      Marker::Null => Ok(Edn::Nil),
+      Marker::True => Ok(Edn::Bool(true)),
+      Marker::False => Ok(Edn::Bool(false)),
+      Marker::FixPos(n) => Ok(Edn::Number(n as f64)),
+      Marker::FixNeg(n) => Ok(Edn::Number(n as f64)),
+      Marker::U8 => Ok(Edn::Number(self.uint(1)? as f64)),
+      Marker::U16 => Ok(Edn::Number(self.uint(2)? as f64)),
+      Marker::U32 => Ok(Edn::Number(self.uint(4)? as f64)),
+      Marker::U64 => Ok(Edn::Number(self.uint(8)? as f64)),
+      Marker::I8 => Ok(Edn::Number(self.sint(1)? as f64)),
+      Marker::I16 => Ok(Edn::Number(self.sint(2)? as f64)),
+      Marker::I32 => Ok(Edn::Number(self.sint(4)? as f64)),
+      Marker::I64 => Ok(Edn::Number(self.sint(8)? as f64)),
+      Marker::F32 => Ok(Edn::Number(f32::from_bits(self.uint(4)? as u32) as f64)),
+      Marker::F64 => Ok(Edn::Number(f64::from_bits(self.uint(8)?))),
+      Marker::FixStr(n) => Ok(Edn::str(self.str(n as usize)?)),
+      Marker::Str8 => {
+        let n = self.uint(1)? as usize;
+        Ok(Edn::str(self.str(n)?))
+      }
+      Marker::Str16 => {
+        let n = self.uint(2)? as usize;
+        Ok(Edn::str(self.str(n)?))
+      }
+      Marker::Str32 => {
+        let n = self.uint(4)? as usize;
+        Ok(Edn::str(self.str(n)?))
+      }
+      Marker::FixArray(n) => self.list(n as usize),
+      Marker::Array16 => {
+        let n = self.uint(2)? as usize;
+        self.list(n)
+      }
+      Marker::Array32 => {
+        let n = self.uint(4)? as usize;
+        self.list(n)
+      }
+      Marker::FixMap(n) => self.map(n as usize),
+      Marker::Map16 => {
+        let n = self.uint(2)? as usize;
+        self.map(n)
+      }
+      Marker::Map32 => {
+        let n = self.uint(4)? as usize;
+        self.map(n)
+      }
+      marker @ (Marker::FixExt1
+      | Marker::FixExt2
+      | Marker::FixExt4
+      | Marker::FixExt8
+      | Marker::FixExt16
+      | Marker::Ext8
+      | Marker::Ext16
+      | Marker::Ext32) => {
+        let n = match marker {
+          Marker::FixExt1 => 1,
+          Marker::FixExt2 => 2,
+          Marker::FixExt4 => 4,
+          Marker::FixExt8 => 8,
+          Marker::FixExt16 => 16,
+          Marker::Ext8 => self.uint(1)? as usize,
+          Marker::Ext16 => self.uint(2)? as usize,
+          _ => self.uint(4)? as usize,
+        };
+        let ty = self.sint(1)? as i8;
+        let data = self.take(n)?;
+        ext_value(ty, data).map_err(|e| format!("{e} at byte {at}"))
+      }
+      marker => Err(format!("unexpected {marker:?} in binary IR at byte {at}")),
+    }
+  }
+
+  fn list(&mut self, n: usize) -> Result<Edn, String> {
+    let xs = (0..n).map(|_| self.value()).collect::<Result<Vec<_>, _>>()?;
+    Ok(Edn::List(EdnListView(xs)))
+  }
+
+  fn map(&mut self, n: usize) -> Result<Edn, String> {
+    let pairs = (0..n)
+      .map(|_| Ok((self.value()?, self.value()?)))
+      .collect::<Result<Vec<_>, String>>()?;
+    Ok(Edn::map_from_iter(pairs))
+  }
+}
+
+fn edn_to_cirru(x: Edn) -> Result<Cirru, String> {
+  match x {
+    Edn::Str(s) => Ok(Cirru::Leaf(s)),
+    Edn::List(xs) => xs.0.into_iter().map(edn_to_cirru).collect::<Result<_, _>>().map(Cirru::List),
+    a => Err(format!("quoted code should hold strings and arrays, got: {a}")),
+  }
+}
+
+fn ext_value(ty: i8, data: &[u8]) -> Result<Edn, String> {
+  let mut rd = Reader { bytes: data, pos: 0 };
+  let value = match ty {
+    EXT_TAG => Edn::Tag(EdnTag::new(rd.str(data.len())?)),
+    EXT_SYMBOL => Edn::sym(rd.str(data.len())?),
+    EXT_QUOTE => Edn::Quote(edn_to_cirru(rd.value()?)?),
+    EXT_SET => match rd.value()? {
+      Edn::List(xs) => Edn::Set(EdnSetView(xs.0.into_iter().collect())),
+      a => return Err(format!("set should hold an array, got: {a}")),
+    },
+    EXT_ENUM => match rd.value()? {
+      Edn::List(EdnListView(xs)) => match &xs[..] {
+        [Edn::Str(variant), type_name, Edn::List(extra)] => Edn::Enum(EdnEnumView {
+          variant: variant.to_owned(),
+          type_name: match type_name {
+            Edn::Str(name) => Some(name.to_owned()),
+            _ => None,
+          },
+          extra: extra.0.to_owned(),
+        }),
+        _ => return Err("enum should hold [variant, type-name, values]".to_owned()),
+      },
+      a => return Err(format!("enum should hold an array, got: {a}")),
+    },
+    _ => return Err(format!("unknown ext type {ty} in binary IR")),
+  };
+  if rd.pos != data.len() {
+    return Err(format!("trailing bytes in ext type {ty}"));
+  }
+  Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sample() -> Edn {
+    Edn::map_from_iter([
+      (Edn::tag("schema-version"), Edn::Number(IR_SCHEMA_VERSION as f64)),
+      (
+        Edn::tag("files"),
+        Edn::map_from_iter([(
+          Edn::str("app.main"),
+          Edn::from(vec![
+            Edn::Nil,
+            Edn::Bool(true),
+            Edn::Number(-3.0),
+            Edn::Number(1.5),
+            Edn::Number(1e300),
+            Edn::Number(-0.0),
+            Edn::str("|hello"),
+            Edn::tag("kind"),
+            Edn::sym("Number"),
+            Edn::Quote(Cirru::List(vec![Cirru::leaf("+"), Cirru::leaf("1"), Cirru::List(vec![])])),
+            Edn::Set(EdnSetView([Edn::tag("a"), Edn::tag("b")].into_iter().collect())),
+            Edn::enum_value("List", vec![Edn::sym("String")]),
+          ]),
+        )]),
+      ),
+    ])
+  }
+
+  #[test]
+  fn round_trips_ir_data() {
+    let bytes = encode_binary_ir(&sample()).expect("encode");
+    assert!(is_binary_ir(&bytes));
+    let decoded = decode_binary_ir(&bytes).expect("decode");
+    assert_eq!(decoded.program, sample());
+    assert_eq!(decoded.schema_version, IR_SCHEMA_VERSION);
+    assert_eq!(decoded.format_version, IR_BINARY_FORMAT_VERSION);
+    assert!(decoded.content_hash.starts_with("md5:"));
+    // hash-based collections are written in a stable order
+    assert_eq!(encode_binary_ir(&sample()).expect("encode again"), bytes);
+  }
+
+  #[test]
+  fn rejects_corrupted_content() {
+    let mut bytes = encode_binary_ir(&sample()).expect("encode");
+    let at = bytes.windows(5).position(|w| w == b"hello").expect("find string");
+    bytes[at] = b'j';
+    assert!(decode_binary_ir(&bytes).unwrap_err().contains("hash mismatch"));
+    assert!(decode_binary_ir(&bytes[..bytes.len() / 2]).is_err());
+  }
+}
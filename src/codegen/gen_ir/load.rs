@@ -0,0 +1,513 @@
+//! Read `program-ir.cirru` back into the compiled program, so a program can run
+//! from its IR without the source snapshot and without preprocessing.
+//!
+//! Every definition in the file becomes a compiled definition, exactly as if
+//! preprocessing had stored it. Values the IR cannot carry (compiled data
+//! shapes, struct and enum definitions, macros) are not fatal: the definition
+//! containing them is replaced with a `raise` of the reason, so it only fails
+//! when it is actually evaluated.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use cirru_edn::{Edn, EdnMapView};
+
+use super::IR_SCHEMA_VERSION;
+use super::binary::{decode_binary_ir, is_binary_ir};
+use crate::calcit::{
+  self, Calcit, CalcitArgLabel, CalcitEnumValue, CalcitFn, CalcitFnArgs, CalcitFnDefRef, CalcitFnUsageMeta, CalcitImport, CalcitList,
+  CalcitLocal, CalcitProc, CalcitScope, CalcitSymbolInfo, CalcitSyntax, CalcitTypeAnnotation, DYNAMIC_TYPE, ImportInfo, MethodKind,
+  RawCodeType,
+};
+use crate::program;
+
+/// Entry points and contents of a loaded IR file.
+#[derive(Debug)]
+pub struct LoadedIr {
+  pub init_fn: Arc<str>,
+  pub reload_fn: Arc<str>,
+  /// Number of definitions stored into the compiled program.
+  pub def_count: usize,
+  /// Definitions that could not be loaded, with the reason; they raise when evaluated.
+  pub skipped: Vec<(String, String)>,
+}
+
+/// Load `program-ir.cirru`, or its binary form `program-ir.msgpack`, from `path`
+/// into the compiled program.
+pub fn load_ir(path: &Path) -> Result<LoadedIr, String> {
+  let content = fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+  let data = if is_binary_ir(&content) {
+    decode_binary_ir(&content)
+      .map_err(|e| format!("failed to decode {}: {e}", path.display()))?
+      .program
+  } else {
+    let content = String::from_utf8(content).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    cirru_edn::parse(&content).map_err(|e| format!("failed to parse {}: {e}", path.display()))?
+  };
+  load_ir_data(&data)
+}
+
+/// Load parsed IR data into the compiled program.
+pub fn load_ir_data(data: &Edn) -> Result<LoadedIr, String> {
+  let Edn::Map(root) = data else {
+    return Err("IR should be a map with :schema-version, :configs and :files".to_owned());
+  };
+  match root.tag_get("schema-version") {
+    Some(Edn::Number(n)) if *n == IR_SCHEMA_VERSION as f64 => {}
+    Some(Edn::Number(n)) => {
+      return Err(format!(
+        "IR schema version {n} is not supported, this calcit reads version {IR_SCHEMA_VERSION}; re-emit it with `calcit ir`"
+      ));
+    }
+    _ => return Err("IR has no :schema-version, it was emitted by an older calcit; re-emit it with `calcit ir`".to_owned()),
+  }
+
+  let configs = expect_map(root.tag_get("configs"), "configs")?;
+  let init_fn = expect_str(configs.tag_get("init-fn"), "configs init-fn")?;
+  let reload_fn = expect_str(configs.tag_get("reload-fn"), "configs reload-fn")?;
+  // macros already took their `&get-calcit-running-mode` branches; eval-only
+  // code such as `macroexpand` needs the source snapshot, so only `:ir` runs here
+  match configs.tag_get("running-mode") {
+    Some(Edn::Tag(t)) if t.ref_str() == "ir" => {}
+    Some(Edn::Tag(t)) => {
+      return Err(format!(
+        "IR was expanded in :{} mode, `calcit run-ir` only runs IR emitted by `calcit ir`",
+        t.ref_str()
+      ));
+    }
+    Some(a) => return Err(format!("IR configs running-mode should be a tag, got: {a}")),
+    None => return Err("IR is missing configs running-mode; re-emit it with `calcit ir`".to_owned()),
+  }
+
+  // sorted, so def ids are assigned in a stable order
+  let mut defs: BTreeMap<(Arc<str>, Arc<str>), &Edn> = BTreeMap::new();
+  for (ns, file) in &expect_map(root.tag_get("files"), "files")?.0 {
+    let ns = edn_str(ns).ok_or_else(|| format!("IR namespace should be a string, got: {ns}"))?;
+    let file_defs = expect_map(
+      expect_map(Some(file), &format!("file {ns}"))?.tag_get("defs"),
+      &format!("defs of {ns}"),
+    )?;
+    for (def, code) in &file_defs.0 {
+      let def = edn_str(def).ok_or_else(|| format!("IR definition name in {ns} should be a string, got: {def}"))?;
+      defs.insert((ns.to_owned(), def), code);
+    }
+  }
+
+  program::register_type_lookups();
+
+  // ids first, so imports and deps between definitions resolve in any order
+  for (ns, def) in defs.keys() {
+    program::ensure_def_id(ns, def);
+  }
+
+  let mut skipped = vec![];
+  for ((ns, def), code) in &defs {
+    let code = match load_code(code, ns) {
+      Ok(code) => code,
+      Err(reason) => {
+        let message = format!("{ns}/{def} could not be loaded from IR: {reason}");
+        skipped.push((format!("{ns}/{def}"), reason));
+        Calcit::from(CalcitList::from(
+          &[Calcit::Proc(CalcitProc::Raise), Calcit::Str(message.into())][..],
+        ))
+      }
+    };
+    program::store_compiled_output(
+      ns,
+      def,
+      program::CompiledDefPayload {
+        version_id: 0,
+        deps: program::collect_compiled_deps(&code),
+        preprocessed_code: code.to_owned(),
+        codegen_form: code,
+        type_summary: None,
+        source_code: None,
+        schema: DYNAMIC_TYPE.clone(),
+        doc: Arc::from(""),
+        examples: vec![],
+      },
+    );
+  }
+
+  Ok(LoadedIr {
+    init_fn,
+    reload_fn,
+    def_count: defs.len(),
+    skipped,
+  })
+}
+
+fn edn_str(x: &Edn) -> Option<Arc<str>> {
+  match x {
+    Edn::Str(s) | Edn::Symbol(s) => Some(s.to_owned()),
+    _ => None,
+  }
+}
+
+fn expect_map<'a>(x: Option<&'a Edn>, name: &str) -> Result<&'a EdnMapView, String> {
+  match x {
+    Some(Edn::Map(m)) => Ok(m),
+    Some(a) => Err(format!("IR {name} should be a map, got: {a}")),
+    None => Err(format!("IR is missing {name}")),
+  }
+}
+
+fn expect_str(x: Option<&Edn>, name: &str) -> Result<Arc<str>, String> {
+  match x {
+    Some(a) => edn_str(a).ok_or_else(|| format!("IR {name} should be a string, got: {a}")),
+    None => Err(format!("IR is missing {name}")),
+  }
+}
+
+fn field_str(m: &EdnMapView, key: &str, kind: &str) -> Result<Arc<str>, String> {
+  expect_str(m.tag_get(key), &format!("{key} of {kind}"))
+}
+
+fn load_type(x: Option<&Edn>) -> Arc<CalcitTypeAnnotation> {
+  match x {
+    None | Some(Edn::Nil) => DYNAMIC_TYPE.clone(),
+    Some(form) => CalcitTypeAnnotation::parse_type_annotation_from_edn(form),
+  }
+}
+
+fn load_items(x: Option<&Edn>, file_ns: &str) -> Result<Vec<Calcit>, String> {
+  match x {
+    Some(Edn::List(xs)) => xs.0.iter().map(|x| load_code(x, file_ns)).collect(),
+    None | Some(Edn::Nil) => Ok(vec![]),
+    Some(a) => Err(format!("expected a list of code, got: {a}")),
+  }
+}
+
+/// Inverse of [`super::dump_code`]. `file_ns` is the namespace of the definition,
+/// which syntax nodes record as their namespace.
+pub(crate) fn load_code(x: &Edn, file_ns: &str) -> Result<Calcit, String> {
+  match x {
+    Edn::Nil => Ok(Calcit::Nil),
+    Edn::Bool(b) => Ok(Calcit::Bool(*b)),
+    Edn::Number(n) => Ok(Calcit::Number(*n)),
+    Edn::Str(s) => Ok(Calcit::Str(s.to_owned())),
+    Edn::Tag(t) => Ok(Calcit::Tag(t.to_owned())),
+    Edn::List(xs) => {
+      let ys = xs.0.iter().map(|y| load_code(y, file_ns)).collect::<Result<Vec<_>, _>>()?;
+      Ok(Calcit::from(CalcitList::from(&ys[..])))
+    }
+    Edn::Map(m) => load_node(m, file_ns),
+    a => Err(format!("unexpected IR data: {a}")),
+  }
+}
+
+fn load_node(m: &EdnMapView, file_ns: &str) -> Result<Calcit, String> {
+  let kind = match m.tag_get("kind") {
+    Some(Edn::Tag(t)) => t.ref_str().to_owned(),
+    _ => return Err(format!("IR node has no :kind: {}", Edn::Map(m.to_owned()))),
+  };
+  match kind.as_str() {
+    "symbol" => Ok(Calcit::Symbol {
+      sym: field_str(m, "val", "symbol")?,
+      info: Arc::new(CalcitSymbolInfo {
+        at_ns: field_str(m, "ns", "symbol")?,
+        at_def: field_str(m, "at-def", "symbol")?,
+      }),
+      location: match m.tag_get("location") {
+        Some(Edn::List(xs)) => Some(Arc::new(
+          xs.0
+            .iter()
+            .map(|x| match x {
+              Edn::Number(n) => Ok(*n as u16),
+              a => Err(format!("symbol location should hold numbers, got: {a}")),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        )),
+        _ => None,
+      },
+    }),
+    "local" => {
+      let sym = field_str(m, "val", "local")?;
+      let info = expect_map(m.tag_get("info"), "info of local")?;
+      Ok(Calcit::Local(CalcitLocal {
+        // indexes are per process, the name is what identifies a local
+        idx: CalcitLocal::track_sym(&sym),
+        sym,
+        info: Arc::new(CalcitSymbolInfo {
+          at_ns: field_str(info, "ns", "local info")?,
+          at_def: field_str(info, "at-def", "local info")?,
+        }),
+        location: None,
+        type_info: load_type(m.tag_get("type-info")),
+      }))
+    }
+    "import" => {
+      let ns = field_str(m, "ns", "import")?;
+      let def = field_str(m, "def", "import")?;
+      let info = load_import_info(expect_map(m.tag_get("info"), "info of import")?)?;
+      Ok(Calcit::Import(CalcitImport {
+        def_id: program::lookup_def_id(&ns, &def).map(|id| id.0),
+        ns,
+        def,
+        info: Arc::new(info),
+      }))
+    }
+    "registered" => Ok(Calcit::Registered(field_str(m, "alias", "registered")?)),
+    "proc" => {
+      let name = field_str(m, "name", "proc")?;
+      CalcitProc::from_str(&name)
+        .map(Calcit::Proc)
+        .map_err(|_| format!("unknown proc `{name}`, the IR was emitted by a different calcit version"))
+    }
+    "syntax" => {
+      let name = field_str(m, "name", "syntax")?;
+      CalcitSyntax::from_str(&name)
+        .map(|syntax| Calcit::Syntax(syntax, Arc::from(file_ns)))
+        .map_err(|_| format!("unknown syntax `{name}`, the IR was emitted by a different calcit version"))
+    }
+    "method" => {
+      let name = field_str(m, "method", "method")?;
+      let receiver = || load_type(m.tag_get("receiver-type"));
+      let behavior = field_str(m, "behavior", "method")?;
+      let method_kind = match &*behavior {
+        "invoke" => MethodKind::Invoke(receiver()),
+        "invoke-native" => MethodKind::InvokeNative,
+        "invoke-native-optional" => MethodKind::InvokeNativeOptional,
+        "tag-access" => MethodKind::TagAccess,
+        "external-access" => MethodKind::ExternalAccess(receiver()),
+        "external-get" => MethodKind::ExternalGet(receiver()),
+        "external-set" => MethodKind::ExternalSet(receiver()),
+        "external-invoke" => MethodKind::ExternalInvoke(receiver()),
+        "access" => MethodKind::Access,
+        "access-optional" => MethodKind::AccessOptional,
+        _ => return Err(format!("unknown method behavior `{behavior}`")),
+      };
+      Ok(Calcit::Method(name, method_kind))
+    }
+    "fn" => load_fn(m, file_ns),
+    "map" => {
+      let mut ys = rpds::HashTrieMap::new_sync();
+      for pair in load_items(m.tag_get("pairs"), file_ns)? {
+        match pair {
+          Calcit::List(kv) if kv.len() == 2 => ys.insert_mut(kv[0].to_owned(), kv[1].to_owned()),
+          a => return Err(format!("map entry should be a pair, got: {a}")),
+        }
+      }
+      Ok(Calcit::Map(ys))
+    }
+    "set" => {
+      let mut ys = rpds::HashTrieSet::new_sync();
+      for item in load_items(m.tag_get("items"), file_ns)? {
+        ys.insert_mut(item);
+      }
+      Ok(Calcit::Set(ys))
+    }
+    "tuple" => {
+      let tag = field_str(m, "tag", "tuple")?;
+      let tag = match tag.strip_prefix('\'') {
+        // enum-shaped type annotations, as built by the type parser
+        Some(name) => Calcit::Symbol {
+          sym: Arc::from(name),
+          info: Arc::new(CalcitSymbolInfo {
+            at_ns: Arc::from(calcit::CORE_NS),
+            at_def: Arc::from("type-annotation"),
+          }),
+          location: None,
+        },
+        None => Calcit::tag(tag.trim_start_matches(':')),
+      };
+      Ok(Calcit::Enum(CalcitEnumValue {
+        tag: Arc::new(tag),
+        extra: load_items(m.tag_get("values"), file_ns)?,
+        sum_type: None,
+      }))
+    }
+    "raw-code" => Ok(Calcit::RawCode(RawCodeType::Js, field_str(m, "code", "raw-code")?)),
+    "cirru-quote" => match m.tag_get("code") {
+      Some(Edn::Quote(code)) => Ok(Calcit::CirruQuote(code.to_owned())),
+      _ => Err("cirru-quote node has no quoted code".to_owned()),
+    },
+    "any-ref" => Err("contains an opaque runtime handle, such as the data shape compiled for `parse-cirru-edn-as`".to_owned()),
+    "macro" | "record" | "struct" | "enum" | "impl" => Err(format!("{kind} values are not supported in IR yet")),
+    _ => Err(format!("unknown IR node kind `{kind}`")),
+  }
+}
+
+fn load_import_info(m: &EdnMapView) -> Result<ImportInfo, String> {
+  let kind = match m.tag_get("kind") {
+    Some(Edn::Tag(t)) => t.ref_str().to_owned(),
+    _ => return Err("import info has no :kind".to_owned()),
+  };
+  let at_ns = || field_str(m, "at-ns", "import info");
+  let at_def = || field_str(m, "at-def", "import info");
+  match kind.as_str() {
+    "as" => Ok(ImportInfo::NsAs {
+      alias: field_str(m, "alias", "import info")?,
+      at_ns: at_ns()?,
+      at_def: at_def()?,
+    }),
+    "js-default" => Ok(ImportInfo::JsDefault {
+      alias: field_str(m, "alias", "import info")?,
+      at_ns: at_ns()?,
+      at_def: at_def()?,
+    }),
+    "refer" => Ok(ImportInfo::NsReferDef {
+      at_ns: at_ns()?,
+      at_def: at_def()?,
+    }),
+    "same-file" => Ok(ImportInfo::SameFile { at_def: at_def()? }),
+    "core" => Ok(ImportInfo::Core { at_ns: at_ns()? }),
+    _ => Err(format!("unknown import kind `{kind}`")),
+  }
+}
+
+/// Function values are top-level definitions inlined by preprocessing, so
+/// their scope is empty.
+fn load_fn(m: &EdnMapView, file_ns: &str) -> Result<Calcit, String> {
+  let name = field_str(m, "name", "fn")?;
+  let def_ns = field_str(m, "ns", "fn")?;
+  let labels: Vec<Arc<str>> = match m.tag_get("args") {
+    Some(Edn::List(xs)) => xs.0.iter().map(|x| expect_str(Some(x), "fn arg")).collect::<Result<_, _>>()?,
+    _ => vec![],
+  };
+  let args = if labels.iter().any(|label| matches!(&**label, "?" | "&")) {
+    CalcitFnArgs::MarkedArgs(
+      labels
+        .iter()
+        .map(|label| match &**label {
+          "?" => CalcitArgLabel::OptionalMark,
+          "&" => CalcitArgLabel::RestMark,
+          _ => CalcitArgLabel::Idx(CalcitLocal::track_sym(label)),
+        })
+        .collect(),
+    )
+  } else {
+    CalcitFnArgs::Args(labels.iter().map(CalcitLocal::track_sym).collect())
+  };
+  let arg_types = match m.tag_get("arg-types") {
+    Some(Edn::List(xs)) if xs.0.len() == args.param_len() => xs.0.iter().map(|x| load_type(Some(x))).collect(),
+    _ => args.empty_arg_types(),
+  };
+  Ok(Calcit::Fn {
+    id: calcit::gen_core_id(),
+    info: Arc::new(CalcitFn {
+      def_ref: Some(CalcitFnDefRef {
+        def_ns: def_ns.to_owned(),
+        def_name: name.to_owned(),
+        coord: None,
+        is_defn: true,
+        is_macro_gen: name.contains('%'),
+      }),
+      name,
+      def_ns,
+      usage: CalcitFnUsageMeta::default(),
+      scope: Arc::new(CalcitScope::default()),
+      args: Arc::new(args),
+      body: load_items(m.tag_get("code"), file_ns)?,
+      generics: Arc::new(vec![]),
+      where_bounds: Arc::new(vec![]),
+      return_type: load_type(m.tag_get("return-type")),
+      arg_types,
+      rest_type: None,
+    }),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codegen::gen_ir::dump_code;
+
+  fn local(name: &str) -> Calcit {
+    let sym: Arc<str> = Arc::from(name);
+    Calcit::Local(CalcitLocal {
+      idx: CalcitLocal::track_sym(&sym),
+      sym,
+      info: Arc::new(CalcitSymbolInfo {
+        at_ns: Arc::from("tests.ir"),
+        at_def: Arc::from("demo"),
+      }),
+      location: None,
+      type_info: DYNAMIC_TYPE.clone(),
+    })
+  }
+
+  #[test]
+  fn loads_dumped_code_back() {
+    let code = Calcit::from(CalcitList::from(
+      &[
+        Calcit::Syntax(CalcitSyntax::Defn, Arc::from("tests.ir")),
+        Calcit::Symbol {
+          sym: Arc::from("demo"),
+          info: Arc::new(CalcitSymbolInfo {
+            at_ns: Arc::from("tests.ir"),
+            at_def: Arc::from("demo"),
+          }),
+          location: Some(Arc::new(vec![1])),
+        },
+        Calcit::from(CalcitList::from(&[local("x")][..])),
+        Calcit::from(CalcitList::from(
+          &[
+            Calcit::Proc(CalcitProc::NativeStrConcat),
+            local("x"),
+            Calcit::Str(Arc::from("!")),
+            Calcit::tag("done"),
+            Calcit::Method(Arc::from("show"), MethodKind::InvokeNative),
+            Calcit::Registered(Arc::from("println")),
+          ][..],
+        )),
+      ][..],
+    ));
+    assert_eq!(load_code(&dump_code(&code), "tests.ir"), Ok(code));
+  }
+
+  #[test]
+  fn rejects_missing_or_newer_schema_versions() {
+    let ir = |version: Option<f64>| {
+      let mut entries = vec![
+        (
+          Edn::tag("configs"),
+          Edn::map_from_iter([
+            (Edn::tag("init-fn"), Edn::str("a.main/main!")),
+            (Edn::tag("reload-fn"), Edn::str("a.main/reload!")),
+            (Edn::tag("running-mode"), Edn::tag("ir")),
+          ]),
+        ),
+        (Edn::tag("files"), Edn::map_from_iter(Vec::<(Edn, Edn)>::new())),
+      ];
+      if let Some(v) = version {
+        entries.push((Edn::tag("schema-version"), Edn::Number(v)));
+      }
+      Edn::map_from_iter(entries)
+    };
+    assert!(load_ir_data(&ir(None)).unwrap_err().contains("no :schema-version"));
+    assert!(
+      load_ir_data(&ir(Some(IR_SCHEMA_VERSION as f64 + 1.0)))
+        .unwrap_err()
+        .contains("not supported")
+    );
+    let loaded = load_ir_data(&ir(Some(IR_SCHEMA_VERSION as f64))).expect("load empty IR");
+    assert_eq!(&*loaded.init_fn, "a.main/main!");
+    assert_eq!(loaded.def_count, 0);
+  }
+
+  #[test]
+  fn rejects_ir_expanded_in_other_running_modes() {
+    let ir = |mode: Option<&str>| {
+      let mut configs = vec![
+        (Edn::tag("init-fn"), Edn::str("a.main/main!")),
+        (Edn::tag("reload-fn"), Edn::str("a.main/reload!")),
+      ];
+      if let Some(mode) = mode {
+        configs.push((Edn::tag("running-mode"), Edn::tag(mode)));
+      }
+      Edn::map_from_iter([
+        (Edn::tag("schema-version"), Edn::Number(IR_SCHEMA_VERSION as f64)),
+        (Edn::tag("configs"), Edn::map_from_iter(configs)),
+        (Edn::tag("files"), Edn::map_from_iter(Vec::<(Edn, Edn)>::new())),
+      ])
+    };
+    assert!(load_ir_data(&ir(Some("eval"))).unwrap_err().contains("expanded in :eval mode"));
+    assert!(load_ir_data(&ir(Some("js"))).unwrap_err().contains("expanded in :js mode"));
+    assert!(load_ir_data(&ir(None)).unwrap_err().contains("missing configs running-mode"));
+    assert!(load_ir_data(&ir(Some("ir"))).is_ok());
+  }
+}
@@ -8,6 +8,8 @@ use crate::{
 pub mod cirru;
 pub mod edn;
 pub(crate) mod edn_decode;
+pub mod ffi_manifest;
+pub mod ffi_raw;
 
 fn where_bounds_to_calcit_form(bounds: &[crate::calcit::CalcitGenericBound], ns: &str, at_def: &str) -> Option<Calcit> {
   if bounds.is_empty() {
@@ -0,0 +1,176 @@
+//! Typed manifests for native dylib FFI.
+//!
+//! A manifest lists the methods a dylib exports, each described with the same
+//! function schema syntax as `CodeEntry :schema`:
+//!
+//! ```cirru.no-check
+//! {} $ :methods $ {}
+//!   |read_file $ {} (:args $ [] 'String) (:return 'String)
+//!   |add_duration $ {} (:args $ [] 'Number 'Number 'Tag) (:return 'Number)
+//! ```
+//!
+//! Libraries export it through an optional `ffi_manifest() -> Edn` symbol, which the
+//! runtime checks at load time. Definitions wrapping `&call-dylib-edn` may carry the
+//! same map as `:ffi $ {} (:backend :dylib) (:methods ...)` for preprocess checks.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use cirru_edn::Edn;
+
+use crate::calcit::{Calcit, CalcitFnTypeAnnotation, CalcitTypeAnnotation, value_matches_type_annotation};
+
+#[derive(Debug, Clone, Default)]
+pub struct FfiManifest {
+  pub methods: BTreeMap<Arc<str>, CalcitFnTypeAnnotation>,
+}
+
+fn field<'a>(data: &'a Edn, key: &str) -> Option<&'a Edn> {
+  match data {
+    Edn::Map(map) => map.tag_get(key),
+    Edn::Struct(record) => record.pairs.iter().find(|(k, _)| k.ref_str() == key).map(|(_, v)| v),
+    _ => None,
+  }
+}
+
+fn method_name(key: &Edn) -> Option<Arc<str>> {
+  match key {
+    Edn::Str(s) | Edn::Symbol(s) => Some(s.to_owned()),
+    Edn::Tag(t) => Some(Arc::from(t.ref_str())),
+    _ => None,
+  }
+}
+
+impl FfiManifest {
+  /// Parse a manifest map with a `:methods` field.
+  pub fn parse(data: &Edn) -> Result<Self, String> {
+    let Some(methods) = field(data, "methods") else {
+      return Err(format!("FFI manifest expected a `:methods` map, got: {data}"));
+    };
+    let Edn::Map(methods) = methods else {
+      return Err(format!("FFI manifest `:methods` expected a map, got: {methods}"));
+    };
+    let mut manifest = FfiManifest::default();
+    for (key, schema) in methods.0.iter() {
+      let Some(name) = method_name(key) else {
+        return Err(format!("FFI manifest method name expected a string, got: {key}"));
+      };
+      let Some(signature) = CalcitTypeAnnotation::parse_fn_schema_from_edn(schema) else {
+        return Err(format!("FFI manifest method `{name}` expected a function schema, got: {schema}"));
+      };
+      manifest.methods.insert(name, signature);
+    }
+    Ok(manifest)
+  }
+
+  /// Read a manifest from `CodeEntry :ffi` metadata. Returns `None` unless the
+  /// metadata declares `:backend :dylib` with `:methods`.
+  pub fn from_ffi_metadata(ffi: &Edn) -> Option<Result<Self, String>> {
+    let is_dylib = match field(ffi, "backend")? {
+      Edn::Tag(t) => t.ref_str() == "dylib",
+      Edn::Str(s) | Edn::Symbol(s) => s.trim_start_matches(':') == "dylib",
+      _ => false,
+    };
+    if !is_dylib || field(ffi, "methods").is_none() {
+      return None;
+    }
+    Some(Self::parse(ffi))
+  }
+
+  pub fn method(&self, name: &str) -> Option<&CalcitFnTypeAnnotation> {
+    self.methods.get(name)
+  }
+
+  /// Describe the arity mismatch of a call with `count` arguments, if any.
+  pub fn arity_error(signature: &CalcitFnTypeAnnotation, count: usize) -> Option<String> {
+    let fixed = signature.arg_types.len();
+    match &signature.rest_type {
+      Some(_) if count < fixed => Some(format!("expects at least {fixed} args, got {count}")),
+      None if count != fixed => Some(format!("expects {fixed} args, got {count}")),
+      _ => None,
+    }
+  }
+
+  /// Expected type of the argument at `idx`, taking `:rest` into account.
+  pub fn arg_type(signature: &CalcitFnTypeAnnotation, idx: usize) -> Option<&Arc<CalcitTypeAnnotation>> {
+    signature.arg_types.get(idx).or(signature.rest_type.as_ref())
+  }
+
+  /// Check runtime arguments of a call before they are passed to the dylib.
+  pub fn check_args(&self, method: &str, args: &[Calcit]) -> Result<(), String> {
+    let Some(signature) = self.method(method) else {
+      let known = self.methods.keys().map(|k| k.as_ref()).collect::<Vec<_>>().join(" ");
+      return Err(format!("method `{method}` is not declared in FFI manifest, known methods: {known}"));
+    };
+    if let Some(message) = Self::arity_error(signature, args.len()) {
+      return Err(format!("method `{method}` {message}"));
+    }
+    for (idx, arg) in args.iter().enumerate() {
+      if let Some(expected) = Self::arg_type(signature, idx)
+        && !value_matches_type_annotation(arg, expected)
+      {
+        return Err(format!(
+          "method `{method}` expects arg {} to be {}, got: {arg}",
+          idx + 1,
+          expected.to_brief_string()
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  /// Check the value returned from a dylib call against the declared `:return` type.
+  pub fn check_return(&self, method: &str, ret: &Calcit) -> Result<(), String> {
+    if let Some(signature) = self.method(method)
+      && !value_matches_type_annotation(ret, &signature.return_type)
+    {
+      return Err(format!(
+        "method `{method}` declares return type {}, got: {ret}",
+        signature.return_type.to_brief_string()
+      ));
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sample() -> FfiManifest {
+    let data = cirru_edn::parse(
+      "{} $ :methods $ {}\n  |read_file $ {} (:args $ [] 'String) (:return 'String)\n  |sum $ {} (:args $ [] 'Number) (:rest 'Number) (:return 'Number)",
+    )
+    .expect("parse manifest");
+    FfiManifest::parse(&data).expect("valid manifest")
+  }
+
+  #[test]
+  fn checks_args_and_return() {
+    let manifest = sample();
+    assert!(manifest.check_args("read_file", &[Calcit::Str("a.txt".into())]).is_ok());
+    assert!(manifest.check_args("read_file", &[Calcit::Number(1.0)]).is_err());
+    assert!(manifest.check_args("read_file", &[]).is_err());
+    assert!(manifest.check_args("write_file", &[]).is_err());
+    assert!(
+      manifest
+        .check_args("sum", &[Calcit::Number(1.0), Calcit::Number(2.0), Calcit::Number(3.0)])
+        .is_ok()
+    );
+    assert!(manifest.check_args("sum", &[Calcit::Number(1.0), Calcit::Nil]).is_err());
+    assert!(manifest.check_return("read_file", &Calcit::Str("x".into())).is_ok());
+    assert!(manifest.check_return("sum", &Calcit::Nil).is_err());
+  }
+
+  #[test]
+  fn reads_dylib_ffi_metadata_only() {
+    let js = cirru_edn::parse("{} (:backend :js) (:target :browser)").expect("parse js ffi");
+    assert!(FfiManifest::from_ffi_metadata(&js).is_none());
+    let dylib =
+      cirru_edn::parse("{} (:backend :dylib) $ :methods $ {} $ |ping $ {} (:args $ []) (:return 'Bool)").expect("parse dylib ffi");
+    let manifest = FfiManifest::from_ffi_metadata(&dylib).expect("dylib metadata").expect("valid");
+    assert!(manifest.method("ping").is_some());
+    let broken = cirru_edn::parse("{} (:backend :dylib) (:methods 1)").expect("parse broken ffi");
+    assert!(FfiManifest::from_ffi_metadata(&broken).expect("dylib metadata").is_err());
+  }
+}
@@ -0,0 +1,177 @@
+//! Raw calling convention of dylib FFI, available since ABI `0.1.0`.
+//!
+//! `&call-dylib-raw` calls a symbol of type [EdnFfiRaw]. Arguments are passed as
+//! [FfiRawArg] records instead of being converted to EDN one by one:
+//!
+//! - a `Buffer` is borrowed as a pointer and length of its bytes, without copying,
+//! - a list containing only numbers is flattened once into a contiguous `f64` slice,
+//! - any other value is converted to [Edn] and passed by reference.
+//!
+//! Pointers are only valid during the call, natives must copy data they want to keep.
+
+use cirru_edn::Edn;
+
+use crate::calcit::Calcit;
+use crate::data::edn::calcit_to_edn;
+
+/// FFI protocol type of the raw calling convention
+pub type EdnFfiRaw = fn(args: &[FfiRawArg]) -> Result<Edn, String>;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FfiRawKind {
+  /// `ptr` points to an [Edn] value, `len` is 0
+  Edn = 0,
+  /// `ptr` points to `len` bytes
+  Bytes = 1,
+  /// `ptr` points to `len` values of `f64`
+  Numbers = 2,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FfiRawArg {
+  pub kind: FfiRawKind,
+  pub ptr: *const u8,
+  pub len: usize,
+}
+
+impl FfiRawArg {
+  /// # Safety
+  /// `self` must come from a live raw call, the data is borrowed from the caller.
+  pub unsafe fn as_bytes(&self) -> Option<&[u8]> {
+    match self.kind {
+      FfiRawKind::Bytes if self.len == 0 => Some(&[]),
+      FfiRawKind::Bytes => Some(unsafe { std::slice::from_raw_parts(self.ptr, self.len) }),
+      _ => None,
+    }
+  }
+
+  /// # Safety
+  /// `self` must come from a live raw call, the data is borrowed from the caller.
+  pub unsafe fn as_numbers(&self) -> Option<&[f64]> {
+    match self.kind {
+      FfiRawKind::Numbers if self.len == 0 => Some(&[]),
+      FfiRawKind::Numbers => Some(unsafe { std::slice::from_raw_parts(self.ptr as *const f64, self.len) }),
+      _ => None,
+    }
+  }
+
+  /// # Safety
+  /// `self` must come from a live raw call, the data is borrowed from the caller.
+  pub unsafe fn as_edn(&self) -> Option<&Edn> {
+    match self.kind {
+      FfiRawKind::Edn => Some(unsafe { &*(self.ptr as *const Edn) }),
+      _ => None,
+    }
+  }
+}
+
+enum FfiRawHeld<'a> {
+  Bytes(&'a [u8]),
+  Numbers(Vec<f64>),
+  Edn(Edn),
+}
+
+/// Arguments prepared for a raw call, borrowing buffers from the Calcit values.
+pub struct FfiRawArgs<'a> {
+  held: Vec<FfiRawHeld<'a>>,
+}
+
+fn numbers_of(x: &Calcit) -> Option<Vec<f64>> {
+  let Calcit::List(xs) = x else {
+    return None;
+  };
+  let mut ys = Vec::with_capacity(xs.len());
+  for item in xs.iter() {
+    match item {
+      Calcit::Number(n) => ys.push(*n),
+      _ => return None,
+    }
+  }
+  Some(ys)
+}
+
+impl<'a> FfiRawArgs<'a> {
+  pub fn from_calcit(xs: &'a [Calcit]) -> Result<Self, String> {
+    let mut held = Vec::with_capacity(xs.len());
+    for x in xs {
+      held.push(match x {
+        Calcit::Buffer(buf) => FfiRawHeld::Bytes(buf),
+        _ => match numbers_of(x) {
+          Some(ys) => FfiRawHeld::Numbers(ys),
+          None => FfiRawHeld::Edn(calcit_to_edn(x)?),
+        },
+      });
+    }
+    Ok(FfiRawArgs { held })
+  }
+
+  /// records pointing into `self`, valid as long as `self` is alive
+  pub fn to_raw(&self) -> Vec<FfiRawArg> {
+    self
+      .held
+      .iter()
+      .map(|x| match x {
+        FfiRawHeld::Bytes(buf) => FfiRawArg {
+          kind: FfiRawKind::Bytes,
+          ptr: buf.as_ptr(),
+          len: buf.len(),
+        },
+        FfiRawHeld::Numbers(ys) => FfiRawArg {
+          kind: FfiRawKind::Numbers,
+          ptr: ys.as_ptr() as *const u8,
+          len: ys.len(),
+        },
+        FfiRawHeld::Edn(v) => FfiRawArg {
+          kind: FfiRawKind::Edn,
+          ptr: v as *const Edn as *const u8,
+          len: 0,
+        },
+      })
+      .collect()
+  }
+
+  /// brief description for `--trace-ffi`, without dumping payloads
+  pub fn describe(&self) -> String {
+    self
+      .held
+      .iter()
+      .map(|x| match x {
+        FfiRawHeld::Bytes(buf) => format!("bytes[{}]", buf.len()),
+        FfiRawHeld::Numbers(ys) => format!("f64[{}]", ys.len()),
+        FfiRawHeld::Edn(_) => "edn".to_owned(),
+      })
+      .collect::<Vec<_>>()
+      .join(" ")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::calcit::CalcitList;
+
+  #[test]
+  fn borrows_buffers_and_flattens_number_lists() {
+    let xs = vec![
+      Calcit::Buffer(vec![1, 2, 3]),
+      Calcit::from(CalcitList::from(&[Calcit::Number(1.5), Calcit::Number(2.5)][..])),
+      Calcit::from(CalcitList::from(&[Calcit::Number(1.0), Calcit::Str("a".into())][..])),
+      Calcit::Str("x".into()),
+    ];
+    let args = FfiRawArgs::from_calcit(&xs).expect("prepare raw args");
+    let raw = args.to_raw();
+    assert_eq!(args.describe(), "bytes[3] f64[2] edn edn");
+
+    let Calcit::Buffer(buf) = &xs[0] else { unreachable!() };
+    assert_eq!(raw[0].ptr, buf.as_ptr());
+    unsafe {
+      assert_eq!(raw[0].as_bytes(), Some(&[1u8, 2, 3][..]));
+      assert_eq!(raw[1].as_numbers(), Some(&[1.5, 2.5][..]));
+      assert!(raw[1].as_bytes().is_none());
+      assert!(matches!(raw[2].as_edn(), Some(Edn::List(_))));
+      assert_eq!(raw[3].as_edn(), Some(&Edn::str("x")));
+    }
+  }
+}
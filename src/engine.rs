@@ -0,0 +1,227 @@
+//! Embedding API, run Calcit programs inside a host Rust application.
+//!
+//! ```no_run
+//! use calcit::{Calcit, Engine, builtins::RegisteredProcDescriptor};
+//!
+//! fn host_add(xs: Vec<Calcit>, _stack: &calcit::call_stack::CallStackList) -> Result<Calcit, calcit::CalcitErr> {
+//!   let sum = xs.iter().filter_map(|x| if let Calcit::Number(n) = x { Some(*n) } else { None }).sum();
+//!   Ok(Calcit::Number(sum))
+//! }
+//!
+//! let engine = Engine::builder()
+//!   .snapshot_path("calcit.cirru")
+//!   .proc("host-add", host_add, RegisteredProcDescriptor::default())
+//!   .build()?;
+//! let total: f64 = engine.call_with("app.main", "total", &[Calcit::Number(1.0)])?;
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! Every engine owns a [RuntimeContext] with its program data and registered procs,
+//! entered by the calling thread for the duration of a call and released when the engine
+//! is dropped. Engines run in parallel
+//! on different threads, calls into one engine are serialized. Calls are not reentrant,
+//! a host proc must not call back into the same engine.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::builtins::{self, FnType, RegisteredProcDescriptor};
+use crate::calcit::{self, Calcit, CalcitErr, CalcitErrKind, LocatedWarning};
+use crate::call_stack::CallStackList;
+use crate::data::edn::{calcit_to_edn, edn_to_calcit};
+use crate::program;
+use crate::runtime_context::RuntimeContext;
+use crate::util::string::{extract_ns_def, strip_shebang};
+use crate::{ProgramEntries, load_core_snapshot, load_module, merge_module_files, project_module_folder, runner, snapshot};
+
+enum SnapshotSource {
+  Path(PathBuf),
+  Text(String),
+}
+
+pub struct EngineBuilder {
+  source: Option<SnapshotSource>,
+  entry: Option<String>,
+  procs: Vec<(String, FnType, RegisteredProcDescriptor)>,
+}
+
+pub struct Engine {
+  entries: ProgramEntries,
+  context: Arc<RuntimeContext>,
+  /// serializes calls, preprocessing of one program is not meant to run concurrently
+  calls: Mutex<()>,
+}
+
+impl EngineBuilder {
+  /// load the snapshot from a file, modules of its entry are resolved like the CLI does
+  pub fn snapshot_path(mut self, path: impl Into<PathBuf>) -> Self {
+    self.source = Some(SnapshotSource::Path(path.into()));
+    self
+  }
+
+  /// load the snapshot from Cirru EDN text, modules are resolved from the current directory
+  pub fn snapshot_str(mut self, content: impl Into<String>) -> Self {
+    self.source = Some(SnapshotSource::Text(content.into()));
+    self
+  }
+
+  /// select an entry of the snapshot, defaults to `:default`
+  pub fn entry(mut self, name: impl Into<String>) -> Self {
+    self.entry = Some(name.into());
+    self
+  }
+
+  /// register a host proc, visible to this engine only
+  pub fn proc(mut self, name: impl Into<String>, f: FnType, descriptor: RegisteredProcDescriptor) -> Self {
+    self.procs.push((name.into(), f, descriptor));
+    self
+  }
+
+  pub fn build(self) -> Result<Engine, String> {
+    let (content, path, base_dir) = match self.source {
+      Some(SnapshotSource::Path(path)) => {
+        let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read snapshot {}: {e}", path.display()))?;
+        let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        (content, path.to_string_lossy().to_string(), base_dir)
+      }
+      Some(SnapshotSource::Text(content)) => (content, String::from("calcit-embedded://snapshot.cirru"), PathBuf::from(".")),
+      None => return Err(String::from("Engine requires a snapshot, use `snapshot_path` or `snapshot_str`")),
+    };
+    let mut content = content;
+    strip_shebang(&mut content);
+    let data = cirru_edn::parse(&content).map_err(|e| format!("Failed to parse snapshot {path}: {e}"))?;
+    let mut snapshot = snapshot::load_snapshot_data(&data, &path)?;
+    let project_namespaces: HashSet<String> = snapshot.files.keys().cloned().collect();
+    snapshot.select_entry(self.entry.as_deref())?;
+
+    let module_folder = project_module_folder(&base_dir);
+    for module_path in snapshot.active_entry()?.modules.clone() {
+      let module_data = load_module(&module_path, &base_dir, &module_folder)?;
+      merge_module_files(&mut snapshot, &module_data, &module_path)?;
+    }
+    for (namespace, file) in load_core_snapshot()?.files {
+      snapshot.files.entry(namespace).or_insert(file);
+    }
+
+    let entry = snapshot.active_entry()?;
+    let (init_ns, init_def) = extract_ns_def(&entry.init_fn)?;
+    let (reload_ns, reload_def) = extract_ns_def(&entry.reload_fn)?;
+    let entries = ProgramEntries {
+      init_fn: Arc::from(entry.init_fn.as_str()),
+      init_ns: init_ns.into(),
+      init_def: init_def.into(),
+      reload_fn: Arc::from(entry.reload_fn.as_str()),
+      reload_ns: reload_ns.into(),
+      reload_def: reload_def.into(),
+    };
+
+    let context = RuntimeContext::new();
+    let engine = Engine {
+      entries,
+      context: context.clone(),
+      calls: Mutex::new(()),
+    };
+    {
+      let _entered = context.enter();
+      runner::preprocess::set_project_namespaces(&project_namespaces);
+      for (name, f, descriptor) in self.procs {
+        if builtins::is_registered_proc(&name) {
+          return Err(format!("Engine proc `{name}` registered twice"));
+        }
+        builtins::register_import_proc_with_descriptor(&name, f, descriptor);
+      }
+      let code = program::extract_program_data(&snapshot)?;
+      *program::PROGRAM_CODE_DATA.write().expect("open program data") = code;
+      let check_warnings = LocatedWarning::default_list();
+      runner::preprocess::ensure_ns_def_compiled(
+        calcit::CORE_NS,
+        calcit::BUILTIN_IMPLS_ENTRY,
+        &std::cell::RefCell::new(check_warnings),
+        &CallStackList::default(),
+      )
+      .map_err(|e| e.msg)?;
+    }
+    Ok(engine)
+  }
+}
+
+impl Engine {
+  pub fn builder() -> EngineBuilder {
+    EngineBuilder {
+      source: None,
+      entry: None,
+      procs: vec![],
+    }
+  }
+
+  /// entries of the selected snapshot entry
+  pub fn entries(&self) -> &ProgramEntries {
+    &self.entries
+  }
+
+  fn with_context<T>(&self, f: impl FnOnce() -> Result<T, CalcitErr>) -> Result<T, CalcitErr> {
+    let _call = self
+      .calls
+      .lock()
+      .map_err(|_| CalcitErr::use_str(CalcitErrKind::Unexpected, "Engine state poisoned"))?;
+    let _entered = self.context.enter();
+    f()
+  }
+
+  /// evaluate a definition, preprocessing it first. Preprocess warnings are returned as an error.
+  pub fn eval_def(&self, ns: &str, def: &str) -> Result<Calcit, CalcitErr> {
+    self.with_context(|| evaluate_def(ns, def))
+  }
+
+  /// call a function definition with arguments
+  pub fn call(&self, ns: &str, def: &str, args: &[Calcit]) -> Result<Calcit, CalcitErr> {
+    self.with_context(|| match evaluate_def(ns, def)? {
+      Calcit::Fn { info, .. } => runner::run_fn(args, &info, &CallStackList::default()),
+      value => CalcitErr::err_str(CalcitErrKind::Type, format!("expected function at {ns}/{def}, got: {value}")),
+    })
+  }
+
+  /// call a function definition and convert its result into a serde type
+  pub fn call_with<T: DeserializeOwned>(&self, ns: &str, def: &str, args: &[Calcit]) -> Result<T, String> {
+    let ret = self.call(ns, def, args).map_err(|e| e.msg)?;
+    from_calcit(&ret)
+  }
+
+  /// call the `:init-fn` of the selected entry
+  pub fn run_init(&self) -> Result<Calcit, CalcitErr> {
+    self.call(&self.entries.init_ns, &self.entries.init_def, &[])
+  }
+}
+
+fn evaluate_def(ns: &str, def: &str) -> Result<Calcit, CalcitErr> {
+  let check_warnings = std::cell::RefCell::new(LocatedWarning::default_list());
+  runner::preprocess::ensure_ns_def_compiled(ns, def, &check_warnings, &CallStackList::default()).map_err(|failure| {
+    let headline = failure.headline();
+    CalcitErr::use_str(failure.kind, headline)
+  })?;
+  let warnings = check_warnings.into_inner();
+  if !warnings.is_empty() {
+    let mut err = CalcitErr::use_str(
+      CalcitErrKind::Unexpected,
+      format!("Found {} warnings in {ns}/{def}, runner blocked", warnings.len()),
+    );
+    err.warnings = Box::new(warnings);
+    return Err(err);
+  }
+  runner::evaluate_symbol_from_program(def, ns, None, &CallStackList::default())
+}
+
+/// convert a serde value into Calcit data, going through Cirru EDN
+pub fn to_calcit<T: Serialize>(value: &T) -> Result<Calcit, String> {
+  Ok(edn_to_calcit(&cirru_edn::to_edn(value)?, &Calcit::Nil))
+}
+
+/// convert Calcit data into a serde value, going through Cirru EDN
+pub fn from_calcit<T: DeserializeOwned>(value: &Calcit) -> Result<T, String> {
+  cirru_edn::from_edn(calcit_to_edn(value)?)
+}
@@ -10,10 +10,12 @@ pub mod codegen;
 pub mod def_diff;
 pub mod detailed_snapshot;
 pub mod effects_graph;
+pub mod engine;
 pub mod program;
 pub mod program_diff;
 pub mod project_state;
 pub mod runner;
+pub mod runtime_context;
 pub mod snapshot;
 pub mod util;
 
@@ -31,11 +33,26 @@ pub use calcit::{
   SyntaxTypeSignature,
 };
 
+pub use engine::{Engine, EngineBuilder};
+
 use crate::util::string::strip_shebang;
 
 pub const DEFAULT_SNAPSHOT_FILE: &str = "calcit.cirru";
 pub const LEGACY_SNAPSHOT_FILE: &str = "compact.cirru";
-pub const FFI_ABI_VERSION: &str = "0.0.9";
+/// ABI version of native dylibs, `0.1.0` adds the raw calling convention of `&call-dylib-raw`
+pub const FFI_ABI_VERSION: &str = "0.1.0";
+/// older dylibs speaking only the EDN calling convention, still accepted
+pub const FFI_LEGACY_ABI_VERSION: &str = "0.0.9";
+
+/// whether a dylib reporting `version` from `abi_version()` can be loaded
+pub fn is_compatible_ffi_abi(version: &str) -> bool {
+  version == FFI_ABI_VERSION || version == FFI_LEGACY_ABI_VERSION
+}
+
+/// whether a dylib reporting `version` accepts [data::ffi_raw::FfiRawArg] arguments
+pub fn ffi_abi_supports_raw(version: &str) -> bool {
+  version == FFI_ABI_VERSION
+}
 
 static QUIET_TOOL_OUTPUT: AtomicBool = AtomicBool::new(false);
 
@@ -1,13 +1,11 @@
 mod entry_book;
 
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
 
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use std::sync::LazyLock;
-use std::sync::RwLock;
 
 use cirru_parser::Cirru;
 
@@ -18,15 +16,16 @@ use crate::calcit::{
 use crate::call_stack::CallStackList;
 use crate::data::{cirru::code_to_calcit, data_to_calcit};
 use crate::runner;
+use crate::runtime_context::{ContextRwLock, ContextSlot};
 use crate::snapshot;
 use crate::snapshot::Snapshot;
 use crate::util::string::extract_pkg_from_ns;
 
 pub use entry_book::EntryBook;
 
-static ACTIVE_FEATURE_POLICY: LazyLock<RwLock<HashMap<String, snapshot::FeaturePolicy>>> =
-  LazyLock::new(|| RwLock::new(HashMap::new()));
-static ACTIVE_TARGET: LazyLock<RwLock<Option<snapshot::SnapshotTarget>>> = LazyLock::new(|| RwLock::new(None));
+static ACTIVE_FEATURE_POLICY: ContextSlot<ContextRwLock<HashMap<String, snapshot::FeaturePolicy>>> =
+  ContextSlot::new(|ctx| &ctx.feature_policy);
+static ACTIVE_TARGET: ContextSlot<ContextRwLock<Option<snapshot::SnapshotTarget>>> = ContextSlot::new(|ctx| &ctx.target);
 
 pub fn configure_entry_feature_policy(policies: &HashMap<String, snapshot::FeaturePolicy>) {
   let mut active = ACTIVE_FEATURE_POLICY.write().expect("write active feature policy");
@@ -166,7 +165,7 @@ impl CompiledFileData {
 pub type CompiledProgram = HashMap<Arc<str>, CompiledFileData>;
 
 #[derive(Debug, Default)]
-struct ProgramDefIdIndex {
+pub(crate) struct ProgramDefIdIndex {
   next_id: u32,
   by_ns: HashMap<Arc<str>, HashMap<Arc<str>, DefId>>,
 }
@@ -204,12 +203,20 @@ pub enum ImportRule {
 pub type ProgramCodeData = HashMap<Arc<str>, ProgramFileData>;
 
 /// runtime values keyed by stable DefId, used by normal runtime lookup paths
-static PROGRAM_RUNTIME_DATA_STATE: LazyLock<RwLock<ProgramRuntimeData>> = LazyLock::new(|| RwLock::new(vec![]));
+static PROGRAM_RUNTIME_DATA_STATE: ContextSlot<ContextRwLock<ProgramRuntimeData>> = ContextSlot::new(|ctx| &ctx.program_runtime);
 /// preprocessed / compiled definitions for codegen and future runtime boundary split
-static PROGRAM_COMPILED_DATA_STATE: LazyLock<RwLock<ProgramCompiledData>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+static PROGRAM_COMPILED_DATA_STATE: ContextSlot<ContextRwLock<ProgramCompiledData>> = ContextSlot::new(|ctx| &ctx.program_compiled);
 /// raw code information before program running
-pub static PROGRAM_CODE_DATA: LazyLock<RwLock<ProgramCodeData>> = LazyLock::new(|| RwLock::new(HashMap::new()));
-static PROGRAM_DEF_ID_INDEX: LazyLock<RwLock<ProgramDefIdIndex>> = LazyLock::new(|| RwLock::new(ProgramDefIdIndex::default()));
+pub static PROGRAM_CODE_DATA: ContextSlot<ContextRwLock<ProgramCodeData>> = ContextSlot::new(|ctx| &ctx.program_code);
+static PROGRAM_DEF_ID_INDEX: ContextSlot<ContextRwLock<ProgramDefIdIndex>> = ContextSlot::new(|ctx| &ctx.program_def_ids);
+/// `snapshot::definition_revision` of ns forms and definitions
+static PROGRAM_REVISIONS: ContextSlot<ContextRwLock<HashMap<Arc<str>, FileRevisions>>> = ContextSlot::new(|ctx| &ctx.program_revisions);
+
+#[derive(Debug, Default)]
+pub(crate) struct FileRevisions {
+  ns: Option<Arc<str>>,
+  defs: HashMap<Arc<str>, Arc<str>>,
+}
 
 fn ensure_runtime_capacity(runtime: &mut ProgramRuntimeData, def_id: DefId) {
   let idx = def_id.0 as usize;
@@ -239,6 +246,67 @@ pub fn lookup_def_id(ns: &str, def: &str) -> Option<DefId> {
   index.by_ns.get(ns).and_then(|defs| defs.get(def)).copied()
 }
 
+/// Revision of the source definition, as computed by `snapshot::definition_revision`.
+/// Definitions patched in by watch mode only carry code, so their revision
+/// covers the code alone.
+pub fn lookup_def_revision(ns: &str, def: &str) -> Option<Arc<str>> {
+  let revisions = PROGRAM_REVISIONS.read().expect("read program revisions");
+  revisions.get(ns).and_then(|file| file.defs.get(def)).cloned()
+}
+
+/// Revision of the `ns` form of a namespace, which holds its imports.
+pub fn lookup_ns_revision(ns: &str) -> Option<Arc<str>> {
+  let revisions = PROGRAM_REVISIONS.read().expect("read program revisions");
+  revisions.get(ns).and_then(|file| file.ns.clone())
+}
+
+fn register_file_revisions(ns: &Arc<str>, file: &snapshot::FileInSnapShot) -> Result<(), String> {
+  let mut defs: HashMap<Arc<str>, Arc<str>> = HashMap::with_capacity(file.defs.len());
+  for (def, entry) in &file.defs {
+    defs.insert(def.as_str().into(), snapshot::definition_revision(entry)?.into());
+  }
+  // only the code of an ns form matters, same as for ns forms patched by watch mode
+  let ns_revision = snapshot::definition_revision(&snapshot::CodeEntry::from_code(file.ns.code.to_owned()))?.into();
+  let mut revisions = PROGRAM_REVISIONS.write().expect("write program revisions");
+  revisions.insert(
+    ns.to_owned(),
+    FileRevisions {
+      ns: Some(ns_revision),
+      defs,
+    },
+  );
+  Ok(())
+}
+
+/// `def` of `None` stands for the `ns` form.
+fn register_code_revision(ns: &Arc<str>, def: Option<&str>, code: &Cirru) -> Result<(), String> {
+  let revision: Arc<str> = snapshot::definition_revision(&snapshot::CodeEntry::from_code(code.to_owned()))?.into();
+  let mut revisions = PROGRAM_REVISIONS.write().expect("write program revisions");
+  let file = revisions.entry(ns.to_owned()).or_default();
+  match def {
+    Some(def) => {
+      file.defs.insert(def.into(), revision);
+    }
+    None => file.ns = Some(revision),
+  }
+  Ok(())
+}
+
+/// `def` of `None` removes the whole namespace.
+fn remove_revision(ns: &str, def: Option<&str>) {
+  let mut revisions = PROGRAM_REVISIONS.write().expect("write program revisions");
+  match def {
+    Some(def) => {
+      if let Some(file) = revisions.get_mut(ns) {
+        file.defs.remove(def);
+      }
+    }
+    None => {
+      revisions.remove(ns);
+    }
+  }
+}
+
 fn collect_ns_def_ids(ns: &str) -> Vec<DefId> {
   let index = PROGRAM_DEF_ID_INDEX.read().expect("read program def id index");
   index.by_ns.get(ns).map(|defs| defs.values().copied().collect()).unwrap_or_default()
@@ -329,6 +397,23 @@ pub fn lookup_runtime_ready(ns: &str, def: &str) -> Option<Calcit> {
   lookup_def_id(ns, def).and_then(lookup_runtime_ready_by_id)
 }
 
+/// Runtime value of a definition for resolving type references. Definitions
+/// loaded from IR have no source code to read a type from, so they are
+/// evaluated on demand instead.
+pub fn lookup_type_def_value(ns: &str, def: &str) -> Option<Calcit> {
+  lookup_runtime_ready(ns, def).or_else(|| {
+    if !with_compiled_def(ns, def, |compiled| compiled.source_code.is_none())? {
+      return None;
+    }
+    runner::eval_symbol_from_program(def, ns, &CallStackList::default()).ok().flatten()
+  })
+}
+
+/// Let type annotations resolve definitions of the program through this module.
+pub fn register_type_lookups() {
+  calcit::register_program_lookups(lookup_type_def_value, lookup_def_code, lookup_def_schema);
+}
+
 pub fn mark_runtime_def_resolving(ns: &str, def: &str) {
   let def_id = ensure_def_id(ns, def);
   write_runtime_cell(def_id, RuntimeCell::Resolving);
@@ -864,7 +949,7 @@ fn extract_file_data(file: &snapshot::FileInSnapShot, ns: Arc<str>) -> Result<Pr
 pub fn extract_program_data(s: &Snapshot) -> Result<ProgramCodeData, String> {
   // Register the program lookup functions in type_annotation so it can resolve
   // imported type definitions without a circular module dependency.
-  calcit::register_program_lookups(lookup_runtime_ready, lookup_def_code, lookup_def_schema);
+  register_type_lookups();
   calcit::clear_type_slots();
 
   let entry = s.active_entry()?;
@@ -892,8 +977,10 @@ pub fn extract_program_data(s: &Snapshot) -> Result<ProgramCodeData, String> {
   let mut xs: ProgramCodeData = HashMap::with_capacity(s.files.len());
 
   for (ns, file) in &s.files {
-    let file_info = extract_file_data(file, ns.to_owned().into())?;
-    xs.insert(ns.to_owned().into(), file_info);
+    let ns: Arc<str> = ns.to_owned().into();
+    let file_info = extract_file_data(file, ns.to_owned())?;
+    register_file_revisions(&ns, file)?;
+    xs.insert(ns, file_info);
   }
 
   register_program_def_ids(&xs);
@@ -910,6 +997,12 @@ pub fn has_def_code(ns: &str, def: &str) -> bool {
   }
 }
 
+/// Whether `ns/def` is defined, by source code or, in a program loaded from IR,
+/// by compiled code alone.
+pub fn has_def(ns: &str, def: &str) -> bool {
+  has_def_code(ns, def) || with_compiled_def(ns, def, |_| ()).is_some()
+}
+
 /// List all def names in a source-code namespace.
 pub fn list_source_def_names(ns: &str) -> Vec<Arc<str>> {
   let program_code = PROGRAM_CODE_DATA.read().expect("read program code");
@@ -1122,6 +1215,7 @@ pub fn apply_code_changes(changes: &snapshot::ChangesDict) -> Result<(), String>
     for def in file_info.defs.keys() {
       let _ = register_program_def_id(ns, def);
     }
+    register_file_revisions(ns, file)?;
     program_code.insert(ns.to_owned(), file_info);
     remove_compiled_ns(ns);
   }
@@ -1129,18 +1223,21 @@ pub fn apply_code_changes(changes: &snapshot::ChangesDict) -> Result<(), String>
     clear_runtime_ns(ns);
     program_code.remove(ns);
     remove_compiled_ns(ns);
+    remove_revision(ns, None);
   }
   for (ns, info) in &changes.changed {
     // println!("handling ns: {:?} {}", ns, program_code.contains_key(ns));
     let file = program_code.get_mut(ns).ok_or_else(|| format!("can not load ns: {ns}"))?;
     if let Some(v) = &info.ns {
       file.import_map = extract_import_map(v, ns)?;
+      register_code_revision(ns, None, v)?;
     }
     for (def, code) in &info.added_defs {
       let _ = register_program_def_id(ns, def);
       clear_runtime_value(ensure_def_id(ns, def));
       remove_compiled_def(ns, def);
       let calcit_code = code_to_calcit(code, ns, def, coord0.to_owned())?;
+      register_code_revision(ns, Some(def), code)?;
       let entry = ProgramDefEntry {
         code: calcit_code,
         schema: DYNAMIC_TYPE.clone(),
@@ -1156,12 +1253,14 @@ pub fn apply_code_changes(changes: &snapshot::ChangesDict) -> Result<(), String>
       }
       file.defs.remove(def.as_str());
       remove_compiled_def(ns, def);
+      remove_revision(ns, Some(def));
     }
     for (def, code) in &info.changed_defs {
       let def_id = register_program_def_id(ns, def);
       clear_runtime_value(def_id);
       remove_compiled_def(ns, def);
       let calcit_code = code_to_calcit(code, ns, def, coord0.to_owned())?;
+      register_code_revision(ns, Some(def), code)?;
       let (schema, doc, examples, ffi) = match file.defs.get(def.as_str()) {
         Some(existing) => (
           existing.schema.clone(),
@@ -4,10 +4,8 @@ use crate::calcit::{CalcitImport, CalcitStructDef, CalcitSyntax, ImportInfo};
 use crate::call_stack::CallStackList;
 use crate::data::cirru::code_to_calcit;
 use crate::run_program_with_docs;
+use crate::runtime_context::{EnteredContext, RuntimeContext};
 use cirru_edn::EdnTag;
-use std::sync::{LazyLock, Mutex};
-
-static PROGRAM_TEST_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
 
 fn cirru_leaf(value: &str) -> Cirru {
   Cirru::Leaf(value.into())
@@ -23,7 +21,7 @@ fn import_rule(source: &str, kind: &str, target: Cirru) -> Cirru {
 
 #[test]
 fn strict_edn_decoder_nominals_are_compiled_dependencies() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let ns: Arc<str> = Arc::from("tests.strict-edn-dependencies");
@@ -181,8 +179,9 @@ fn namespace_validation_accepts_legacy_colon_ns_form() {
   assert!(imports.is_empty());
 }
 
-fn lock_program_test_state() -> std::sync::MutexGuard<'static, ()> {
-  PROGRAM_TEST_LOCK.lock().unwrap_or_else(|err| err.into_inner())
+/// a fresh runtime context for the calling test, left when the guard is dropped
+fn enter_test_context() -> EnteredContext {
+  RuntimeContext::new().enter()
 }
 
 fn reset_program_test_state() {
@@ -208,9 +207,25 @@ fn compiled_def_for_test(def_id: DefId, deps: Vec<DefId>) -> CompiledDef {
   }
 }
 
+/// compiled file of `(name, def id, dep ids)` function definitions, shared with tests of other modules
+pub(crate) fn compiled_file_for_test(defs: &[(&str, u32, &[u32])]) -> CompiledFileData {
+  CompiledFileData {
+    defs: defs
+      .iter()
+      .map(|(name, id, deps)| {
+        let compiled = CompiledDef {
+          kind: CompiledDefKind::Fn,
+          ..compiled_def_for_test(DefId(*id), deps.iter().map(|dep| DefId(*dep)).collect())
+        };
+        (Arc::from(*name), compiled)
+      })
+      .collect(),
+  }
+}
+
 #[test]
 fn snapshot_fallback_preserves_dependency_metadata() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let dep_id = register_program_def_id("dep.ns", "value");
@@ -230,7 +245,7 @@ fn snapshot_fallback_preserves_dependency_metadata() {
 
 #[test]
 fn write_runtime_ready_normalizes_thunk_into_lazy_cell() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let thunk_ns = "tests.runtime";
@@ -262,7 +277,7 @@ fn write_runtime_ready_normalizes_thunk_into_lazy_cell() {
 
 #[test]
 fn write_runtime_ready_attaches_trait_definition_identity() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let trait_value = crate::calcit::CalcitTrait::new_runtime(cirru_edn::EdnTag::new("Show"), vec![], vec![]);
@@ -276,7 +291,7 @@ fn write_runtime_ready_attaches_trait_definition_identity() {
 
 #[test]
 fn clear_runtime_caches_for_changes_clears_transitive_dependents() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let def_a = ensure_def_id("app.main", "a");
@@ -332,7 +347,7 @@ fn clear_runtime_caches_for_changes_clears_transitive_dependents() {
 
 #[test]
 fn clear_runtime_caches_for_changes_expands_namespace_header_invalidation() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let main_a = ensure_def_id("app.main", "a");
@@ -397,7 +412,7 @@ fn clear_runtime_caches_for_changes_expands_namespace_header_invalidation() {
 
 #[test]
 fn clear_runtime_caches_for_reload_clears_selected_packages_and_dependents() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let app_main = ensure_def_id("app.main", "entry");
@@ -465,7 +480,7 @@ fn clear_runtime_caches_for_reload_clears_selected_packages_and_dependents() {
 
 #[test]
 fn clear_runtime_caches_for_reload_with_reload_libs_clears_all_namespaces() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let app_main = ensure_def_id("app.main", "entry");
@@ -502,7 +517,7 @@ fn clear_runtime_caches_for_reload_with_reload_libs_clears_all_namespaces() {
 
 #[test]
 fn snapshot_rebuilds_changed_source_backed_def_after_reload_changes() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let old_code = code_to_calcit(&Cirru::Leaf(Arc::from("1")), "app.reload", "demo", vec![]).expect("build initial source-backed code");
@@ -573,7 +588,7 @@ fn snapshot_rebuilds_changed_source_backed_def_after_reload_changes() {
 
 #[test]
 fn removed_source_def_changes_still_invalidate_transitive_dependents() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let shared_code =
@@ -704,7 +719,7 @@ fn removed_source_def_changes_still_invalidate_transitive_dependents() {
 
 #[test]
 fn snapshot_prefers_source_backed_compiled_def_even_with_warnings() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let warn_code =
@@ -743,7 +758,7 @@ fn snapshot_prefers_source_backed_compiled_def_even_with_warnings() {
 
 #[test]
 fn snapshot_skips_empty_namespace_when_source_backed_rebuild_fails() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let failing_code = code_to_calcit(
@@ -785,7 +800,7 @@ fn snapshot_skips_empty_namespace_when_source_backed_rebuild_fails() {
 
 #[test]
 fn snapshot_skips_unreferenced_runtime_only_defs() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let _ = ensure_def_id("app.runtime", "unused");
@@ -797,7 +812,7 @@ fn snapshot_skips_unreferenced_runtime_only_defs() {
 
 #[test]
 fn snapshot_keeps_referenced_runtime_only_defs() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let runtime_def = ensure_def_id("app.runtime", "shared");
@@ -827,7 +842,7 @@ fn snapshot_keeps_referenced_runtime_only_defs() {
 
 #[test]
 fn snapshot_skips_unserializable_referenced_runtime_only_defs() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let runtime_def = ensure_def_id("app.runtime", "shared-atom");
@@ -856,7 +871,7 @@ fn snapshot_skips_unserializable_referenced_runtime_only_defs() {
 
 #[test]
 fn lookup_codegen_type_hint_prefers_compiled_schema_over_runtime_value() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let schema = Arc::new(CalcitTypeAnnotation::String);
@@ -883,7 +898,7 @@ fn lookup_codegen_type_hint_prefers_compiled_schema_over_runtime_value() {
 
 #[test]
 fn lookup_codegen_type_hint_falls_back_to_runtime_value() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let _ = ensure_def_id("app.codegen", "runtime-only");
@@ -895,7 +910,7 @@ fn lookup_codegen_type_hint_falls_back_to_runtime_value() {
 
 #[test]
 fn lenient_compiled_fallback_backfills_runtime_cache() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let _ = ensure_def_id("app.compiled", "callable");
@@ -944,7 +959,7 @@ fn lenient_compiled_fallback_backfills_runtime_cache() {
 
 #[test]
 fn preprocess_ns_def_materializes_compiled_function_with_runtime_backfill() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let fn_code = code_to_calcit(
@@ -994,7 +1009,7 @@ fn preprocess_ns_def_materializes_compiled_function_with_runtime_backfill() {
 
 #[test]
 fn lazy_runtime_resolution_seeds_from_compiled_when_runtime_slot_is_missing() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let def_id = ensure_def_id("app.preprocess", "lazy-value");
@@ -1029,7 +1044,7 @@ fn lazy_runtime_resolution_seeds_from_compiled_when_runtime_slot_is_missing() {
 
 #[test]
 fn run_program_compiles_then_executes_with_runtime_backfill() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   let fn_code = code_to_calcit(
@@ -1074,7 +1089,7 @@ fn run_program_compiles_then_executes_with_runtime_backfill() {
 
 #[test]
 fn runtime_resolve_mode_handles_resolving_cell_differently() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   mark_runtime_def_resolving("app.runtime", "pending");
@@ -1100,7 +1115,7 @@ fn runtime_resolve_mode_handles_resolving_cell_differently() {
 
 #[test]
 fn runtime_resolve_mode_handles_errored_cell_differently() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   mark_runtime_def_errored("app.runtime", "broken", Arc::from("boom"));
@@ -1120,7 +1135,7 @@ fn runtime_resolve_mode_handles_errored_cell_differently() {
 
 #[test]
 fn compiled_executable_code_only_exposes_executable_kinds() {
-  let _guard = lock_program_test_state();
+  let _context = enter_test_context();
   reset_program_test_state();
 
   store_compiled_output(
@@ -1,4 +1,5 @@
 pub mod preprocess;
+pub mod sandbox;
 pub mod track;
 
 use std::cell::RefCell;
@@ -150,6 +151,7 @@ pub fn evaluate_expr(expr: &Calcit, scope: &CalcitScope, file_ns: &str, call_sta
       Some(x) => {
         // println!("eval expr: {}", expr.lisp_str());
         // println!("eval expr x: {}", x);
+        sandbox::tick(call_stack)?;
 
         if x.is_expr_evaluated() {
           call_expr(x, xs, scope, file_ns, call_stack, false)
@@ -1,7 +1,9 @@
+mod optimize;
 mod type_checking;
 mod type_inference;
 mod type_rewriting;
 
+use crate::runtime_context::{ContextRwLock, ContextSlot};
 use crate::{
   builtins::{self, is_js_syntax_procs, is_proc_name, is_registered_proc},
   calcit::{
@@ -11,12 +13,14 @@ use crate::{
     brief_type_of_value, pop_type_slot_override, push_type_slot_override, register_type_slot,
   },
   call_stack::{CallStackList, StackKind},
-  codegen, program, runner,
+  codegen,
+  data::ffi_manifest::FfiManifest,
+  program, runner,
 };
 
 use type_checking::{
   CallTypeCheckInfo, check_core_fn_arg_types, check_function_return_type, check_local_fn_call_arg_types, check_proc_arg_types,
-  check_user_fn_arg_types, detect_return_type_hint_from_processed_body,
+  check_trait_impl_conformance, check_user_fn_arg_types, detect_return_type_hint_from_processed_body,
 };
 pub use type_inference::infer_static_type_from_expr;
 use type_inference::{
@@ -30,8 +34,8 @@ use type_rewriting::{
 
 use std::cell::Cell;
 use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, LazyLock, RwLock};
 use std::{cell::RefCell, vec};
 
 use cirru_edn::EdnTag;
@@ -40,9 +44,10 @@ use strum::ParseError;
 
 pub(crate) type ScopeTypes = HashMap<Arc<str>, Arc<CalcitTypeAnnotation>>;
 
-static WARN_DYN_METHOD: AtomicBool = AtomicBool::new(false);
-static VERBOSE_PREPROCESS: AtomicBool = AtomicBool::new(false);
-static PROJECT_NAMESPACES: LazyLock<RwLock<HashSet<Arc<str>>>> = LazyLock::new(|| RwLock::new(HashSet::new()));
+static WARN_DYN_METHOD: ContextSlot<AtomicBool> = ContextSlot::new(|ctx| &ctx.warn_dyn_method);
+static VERBOSE_PREPROCESS: ContextSlot<AtomicBool> = ContextSlot::new(|ctx| &ctx.verbose_preprocess);
+static OPTIMIZE: ContextSlot<AtomicBool> = ContextSlot::new(|ctx| &ctx.optimize);
+static PROJECT_NAMESPACES: ContextSlot<ContextRwLock<HashSet<Arc<str>>>> = ContextSlot::new(|ctx| &ctx.project_namespaces);
 
 pub fn set_project_namespaces(namespaces: &HashSet<String>) {
   let mut target = PROJECT_NAMESPACES.write().expect("write project namespaces");
@@ -125,6 +130,15 @@ fn with_preprocess_compile_guard<T>(ns: &str, def: &str, f: impl FnOnce() -> Res
   result.map(Some)
 }
 
+/// Toggle the optimizing pass applied to preprocessed code before it is stored.
+pub fn set_optimize(enabled: bool) {
+  OPTIMIZE.store(enabled, Ordering::SeqCst);
+}
+
+pub fn optimize_enabled() -> bool {
+  OPTIMIZE.load(Ordering::Relaxed)
+}
+
 pub fn set_warn_dyn_method(enabled: bool) {
   WARN_DYN_METHOD.store(enabled, Ordering::SeqCst);
 }
@@ -282,9 +296,23 @@ impl<'a> PreprocessContext<'a> {
 }
 
 fn store_preprocessed_compiled_output(ns: &str, def: &str, source_code: &Calcit, resolved_code: &Calcit) {
-  let preprocessed_code = resolved_code.to_owned();
-  let codegen_form = resolved_code.to_owned();
-  let deps = program::collect_compiled_deps(&codegen_form);
+  let (preprocessed_code, deps) = if optimize_enabled() {
+    let (optimized, inlined) = optimize::optimize_def(ns, def, resolved_code);
+    // Inlined and folded-away references still invalidate this def on reload.
+    let mut deps = program::collect_compiled_deps(resolved_code);
+    deps.extend(program::collect_compiled_deps(&optimized));
+    deps.extend(
+      inlined
+        .iter()
+        .filter_map(|(dep_ns, dep_def)| program::lookup_def_id(dep_ns, dep_def)),
+    );
+    deps.sort();
+    deps.dedup();
+    (optimized, deps)
+  } else {
+    (resolved_code.to_owned(), program::collect_compiled_deps(resolved_code))
+  };
+  let codegen_form = preprocessed_code.to_owned();
   let type_summary = calcit::CalcitTypeAnnotation::summarize_code(source_code).map(Arc::from);
   program::store_compiled_output(
     ns,
@@ -635,7 +663,8 @@ fn parse_trait_method_specs_from_source<'a>(items: impl Iterator<Item = &'a Calc
     let Calcit::List(entry) = item else {
       return None;
     };
-    if entry.len() != 2 {
+    // a third item is the default body, which does not change the signature
+    if entry.len() != 2 && entry.len() != 3 {
       return None;
     }
 
@@ -664,8 +693,40 @@ fn parse_trait_new_source(items: &CalcitList) -> Option<CalcitTrait> {
 
 fn parse_deftrait_source(items: &CalcitList) -> Option<CalcitTrait> {
   let name = parse_trait_name_from_source(items.get(1)?)?;
-  let (methods, method_types, member_kinds) = parse_trait_method_specs_from_source(items.iter().skip(2))?;
-  Some(CalcitTrait::new_with_member_kinds(name, methods, method_types, Some(member_kinds)))
+  let mut requires: Vec<Arc<CalcitTrait>> = vec![];
+  let mut method_specs: Vec<&Calcit> = vec![];
+  let mut entries = items.iter().skip(2);
+  while let Some(item) = entries.next() {
+    if matches!(item, Calcit::Tag(tag) if tag.ref_str() == "requires") {
+      requires.push(Arc::new(parse_required_trait_reference(entries.next()?)?));
+    } else {
+      method_specs.push(item);
+    }
+  }
+  let (methods, method_types, member_kinds) = parse_trait_method_specs_from_source(method_specs.into_iter())?;
+  Some(CalcitTrait::new_with_member_kinds(name, methods, method_types, Some(member_kinds)).with_requires(requires))
+}
+
+/// `:requires Eq` in trait source only names the supertrait. Keep a qualified
+/// reference so `trait_list_from_type` can load its members on demand.
+fn parse_required_trait_reference(form: &Calcit) -> Option<CalcitTrait> {
+  let Calcit::Symbol { sym, info, .. } = form else {
+    return match form {
+      Calcit::Import(CalcitImport { ns, def, .. }) => Some(CalcitTrait::new_reference(&format!("{ns}/{def}"))),
+      _ => None,
+    };
+  };
+  if sym.contains('/') {
+    return Some(CalcitTrait::new_reference(sym));
+  }
+  let target_ns = if program::has_def_code(&info.at_ns, sym) {
+    info.at_ns.to_owned()
+  } else if let Some(target_ns) = program::lookup_def_target_in_import(&info.at_ns, sym) {
+    target_ns
+  } else {
+    Arc::from(calcit::CORE_NS)
+  };
+  Some(CalcitTrait::new_reference(&format!("{target_ns}/{sym}")))
 }
 
 fn resolve_where_bound_type_for_body(bound: &crate::calcit::CalcitGenericBound, file_ns: &str) -> Option<Arc<CalcitTypeAnnotation>> {
@@ -721,6 +782,9 @@ where
       map_type_refs_for_body(key.clone(), resolve_type_ref),
       map_type_refs_for_body(value.clone(), resolve_type_ref),
     )),
+    CalcitTypeAnnotation::MapShape(shape) => Arc::new(CalcitTypeAnnotation::MapShape(Arc::new(
+      shape.map_types(|ty| map_type_refs_for_body(ty.clone(), resolve_type_ref)),
+    ))),
     CalcitTypeAnnotation::Set(inner) => Arc::new(CalcitTypeAnnotation::Set(map_type_refs_for_body(inner.clone(), resolve_type_ref))),
     CalcitTypeAnnotation::Ref(inner) => Arc::new(CalcitTypeAnnotation::Ref(map_type_refs_for_body(inner.clone(), resolve_type_ref))),
     CalcitTypeAnnotation::Optional(inner) => Arc::new(CalcitTypeAnnotation::Optional(map_type_refs_for_body(
@@ -1472,6 +1536,10 @@ fn preprocess_list_call(
       // Process arguments with type-aware preprocessing for Fn-typed params.
       // When the expected param type is Fn(...), set EXPECTED_FN_TYPE so that
       // preprocess_defn can inject arg types into anonymous fn params' scope_types.
+      // Generics bound by earlier arguments are substituted into later callback
+      // types, so `(option:fold opt on-none (fn (x) ...))` (what `if-let` and
+      // `when-let` expand to) sees the payload type of `opt` for `x`.
+      let mut generic_bindings: HashMap<Arc<str>, Arc<CalcitTypeAnnotation>> = HashMap::new();
       for (arg_idx, a) in args.iter().enumerate() {
         if let Calcit::Syntax(CalcitSyntax::ArgSpread, _) = a {
           has_spread = true;
@@ -1482,7 +1550,14 @@ fn preprocess_list_call(
         // Set expected fn type hint if this arg position has a Fn-typed param
         let expected_fn = if arg_idx < info.arg_types.len() {
           if let CalcitTypeAnnotation::Fn(fn_annot) = info.arg_types[arg_idx].as_ref() {
-            Some(fn_annot.clone())
+            if generic_bindings.is_empty() {
+              Some(fn_annot.clone())
+            } else {
+              match info.arg_types[arg_idx].substitute_type_vars(&generic_bindings).as_ref() {
+                CalcitTypeAnnotation::Fn(substituted) => Some(substituted.clone()),
+                _ => Some(fn_annot.clone()),
+              }
+            }
           } else {
             None
           }
@@ -1513,6 +1588,17 @@ fn preprocess_list_call(
 
         let form = result?;
 
+        if !info.generics.is_empty()
+          && let Some(expected_type) = info.arg_types.get(arg_idx)
+          && expected_type.contains_type_var()
+          && let Some(actual_type) = resolve_type_value(&form, scope_types)
+        {
+          let mut candidate = generic_bindings.clone();
+          if actual_type.as_ref().matches_with_bindings(expected_type.as_ref(), &mut candidate) {
+            generic_bindings = candidate;
+          }
+        }
+
         ys = ys.push(form);
       }
       if !has_spread {
@@ -1962,10 +2048,12 @@ fn preprocess_list_call(
           require_js_ffi_feature_for_operation(call_head, file_ns, def_name.as_ref(), check_warnings, call_stack)?;
           warn_on_nullable_js_ffi_dereference(call_head, &processed_args, scope_types, file_ns, def_name.as_ref(), check_warnings);
           warn_on_untyped_js_ffi_field_access(call_head, &processed_args, scope_types, file_ns, def_name.as_ref(), check_warnings);
+          warn_on_dylib_ffi_call(call_head, &processed_args, scope_types, file_ns, def_name.as_ref(), check_warnings);
           warn_on_nominal_enum_legacy_absence_use(call_head, &processed_args, scope_types, file_ns, def_name.as_ref(), check_warnings);
           warn_on_legacy_js_nullish_predicate(call_head, &processed_args, scope_types, file_ns, def_name.as_ref(), check_warnings);
           warn_on_dynamic_trait_call(call_head, &processed_args, scope_types, file_ns, def_name.as_ref(), check_warnings);
           warn_on_method_name_conflict(call_head, &processed_args, scope_types, file_ns, def_name.as_ref(), check_warnings);
+          check_trait_impl_conformance(call_head, &processed_args, scope_types, file_ns, def_name.as_ref(), check_warnings);
         }
 
         // Check Proc argument types if available
@@ -3823,6 +3911,97 @@ fn warn_on_untyped_js_ffi_field_access(
   );
 }
 
+/// Check `&call-dylib-edn` and `&call-dylib-raw` call sites against the method manifest copied into the
+/// enclosing definition's `:ffi $ {} (:backend :dylib) (:methods ...)` metadata.
+fn warn_on_dylib_ffi_call(
+  head: &Calcit,
+  args: &CalcitList,
+  scope_types: &ScopeTypes,
+  file_ns: &str,
+  def_name: &str,
+  check_warnings: &RefCell<Vec<LocatedWarning>>,
+) {
+  if !matches!(head, Calcit::Registered(name) if matches!(name.as_ref(), "&call-dylib-edn" | "&call-dylib-raw")) {
+    return;
+  }
+  let Some(ffi) = program::lookup_def_ffi(file_ns, def_name) else {
+    return;
+  };
+  let Some(manifest) = FfiManifest::from_ffi_metadata(&ffi) else {
+    return;
+  };
+  let location = args
+    .get(1)
+    .and_then(Calcit::get_location)
+    .or_else(|| args.first().and_then(Calcit::get_location));
+  let manifest = match manifest {
+    Ok(manifest) => manifest,
+    Err(e) => {
+      gen_check_warning_code_at(
+        format!("[Warn] invalid dylib `:ffi` metadata in {file_ns}/{def_name}: {e}"),
+        "W_DYLIB_FFI_MANIFEST",
+        file_ns,
+        location,
+        check_warnings,
+      );
+      return;
+    }
+  };
+  // a dynamic method name can only be checked at runtime against the exported manifest
+  let Some(Calcit::Str(method)) = args.get(1) else {
+    return;
+  };
+  let Some(signature) = manifest.method(method) else {
+    let known = manifest.methods.keys().map(|k| k.as_ref()).collect::<Vec<_>>().join(" ");
+    gen_check_warning_code_at(
+      format!("[Warn] dylib method `{method}` is not declared in `:ffi :methods` of {file_ns}/{def_name}, known methods: {known}"),
+      "W_DYLIB_FFI_UNKNOWN_METHOD",
+      file_ns,
+      location,
+      check_warnings,
+    );
+    return;
+  };
+  let call_args = args.iter().skip(2).collect::<Vec<_>>();
+  if call_args
+    .iter()
+    .any(|arg| matches!(arg, Calcit::Syntax(CalcitSyntax::ArgSpread, _)))
+  {
+    return;
+  }
+  if let Some(message) = FfiManifest::arity_error(signature, call_args.len()) {
+    gen_check_warning_code_at(
+      format!("[Warn] dylib method `{method}` {message}, at {file_ns}/{def_name}"),
+      "W_DYLIB_FFI_ARITY",
+      file_ns,
+      location,
+      check_warnings,
+    );
+    return;
+  }
+  for (idx, arg) in call_args.iter().enumerate() {
+    let Some(expected) = FfiManifest::arg_type(signature, idx) else {
+      continue;
+    };
+    if let Some(actual) = resolve_type_value(arg, scope_types)
+      && !actual.as_ref().matches_with_bindings(expected.as_ref(), &mut HashMap::new())
+    {
+      gen_check_warning_code_at(
+        format!(
+          "[Warn] dylib method `{method}` expects arg {} to be {}, got {}, at {file_ns}/{def_name}",
+          idx + 1,
+          expected.to_brief_string(),
+          actual.to_brief_string()
+        ),
+        "W_DYLIB_FFI_ARG_TYPE",
+        file_ns,
+        arg.get_location().or_else(|| location.clone()),
+        check_warnings,
+      );
+    }
+  }
+}
+
 fn warn_on_legacy_js_nullish_predicate(
   head: &Calcit,
   args: &CalcitList,
@@ -3969,6 +4148,8 @@ fn warn_on_nominal_enum_legacy_absence_use(
   let Some(operation) = canonical_absence_operation_name(head) else {
     return;
   };
+  // `enum?` is deliberately absent: a nominal enum satisfies it, and it is the
+  // guard `tag-match` expands to before reading the variant.
   if !matches!(
     operation,
     "nil?"
@@ -3988,7 +4169,6 @@ fn warn_on_nominal_enum_legacy_absence_use(
       | "ref?"
       | "macro?"
       | "syntax?"
-      | "enum?"
       | "struct?"
       | "get"
       | "nth"
@@ -4054,8 +4234,8 @@ fn warn_on_nominal_enum_legacy_absence_use(
     }
     "=" | "&=" => "compare values of the same nominal enum, or pattern-match before comparing a payload",
     "&compare" if enum_name == "Option" => "unwrap or pattern-match the Option before comparing its payload",
-    "list?" | "map?" | "set?" | "struct?" | "enum?" | "struct-def?" | "enum-def?" | "tag?" | "number?" | "string?" | "keyword?"
-    | "symbol?" | "fn?" | "bool?" | "buffer?" | "cirru-quote?" | "ref?" | "macro?" | "syntax?" => {
+    "list?" | "map?" | "set?" | "struct?" | "struct-def?" | "enum-def?" | "tag?" | "number?" | "string?" | "keyword?" | "symbol?"
+    | "fn?" | "bool?" | "buffer?" | "cirru-quote?" | "ref?" | "macro?" | "syntax?" => {
       "pattern-match the nominal enum before applying a payload type predicate"
     }
     _ if enum_name == "Option" => "use `tag-match`, `option:unwrap-or`, or an Option method to access the payload",
@@ -4325,6 +4505,11 @@ fn try_specialize_polymorphic_call(
   // Get receiver (first argument) and its type
   let receiver = processed_args.first()?;
   let receiver_type = resolve_type_value(receiver, scope_types)?;
+  // Map shapes only refine value types; dispatch them like ordinary maps.
+  let receiver_type = match receiver_type.as_ref() {
+    T::MapShape(shape) => shape.as_plain_map(),
+    _ => receiver_type,
+  };
 
   // --- Type predicate folding: when the receiver's static type is known, we
   // can fold `(list? x)` / `(map? x)` / ... to a literal Bool. This removes
@@ -4591,6 +4776,7 @@ fn validate_method_call(
   if impl_values
     .iter()
     .any(|struct_def| struct_def.fields().iter().any(|field| field.ref_str() == method_str))
+    || find_trait_default_method_type(type_value.as_ref(), &impl_values, method_str).is_some()
   {
     return Ok(()); // Method found, validation passed
   }
@@ -4794,13 +4980,52 @@ fn resolve_core_impls(symbol: &str) -> Option<Vec<Arc<CalcitImpl>>> {
 
 fn trait_list_from_type(type_value: &CalcitTypeAnnotation) -> Option<Vec<Arc<CalcitTrait>>> {
   match type_value {
-    CalcitTypeAnnotation::Trait(trait_def) => Some(vec![trait_def.to_owned()]),
-    CalcitTypeAnnotation::TraitSet(traits) => Some(traits.as_ref().to_owned()),
+    CalcitTypeAnnotation::Trait(trait_def) => Some(with_required_traits(std::slice::from_ref(trait_def))),
+    CalcitTypeAnnotation::TraitSet(traits) => Some(with_required_traits(traits)),
     CalcitTypeAnnotation::Optional(inner) => trait_list_from_type(inner.as_ref()),
     _ => None,
   }
 }
 
+/// A value bounded by a trait also satisfies that trait's supertraits. They
+/// are placed before the trait itself so its own members keep precedence.
+fn with_required_traits(traits: &[Arc<CalcitTrait>]) -> Vec<Arc<CalcitTrait>> {
+  fn visit(trait_def: &Arc<CalcitTrait>, seen: &mut HashSet<String>, output: &mut Vec<Arc<CalcitTrait>>) {
+    for required in trait_def.requires.iter() {
+      let resolved = resolve_required_trait(required);
+      let key = resolved
+        .definition_ref
+        .as_deref()
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| resolved.name.to_string());
+      if seen.insert(key) {
+        visit(&resolved, seen, output);
+        output.push(resolved);
+      }
+    }
+  }
+  let mut seen = HashSet::new();
+  let mut output = vec![];
+  for trait_def in traits {
+    visit(trait_def, &mut seen, &mut output);
+    output.push(trait_def.to_owned());
+  }
+  output
+}
+
+fn resolve_required_trait(required: &Arc<CalcitTrait>) -> Arc<CalcitTrait> {
+  if !required.methods.is_empty() {
+    return required.to_owned();
+  }
+  let Some((ns, def)) = required.definition_ref.as_deref().and_then(|def_ref| def_ref.rsplit_once('/')) else {
+    return required.to_owned();
+  };
+  program::lookup_def_code(ns, def)
+    .and_then(|code| resolve_trait_def_from_source_code(&code))
+    .map(|trait_def| Arc::new(trait_def.with_definition_ref(ns, def)))
+    .unwrap_or_else(|| required.to_owned())
+}
+
 pub(crate) fn trait_is_external_object(trait_def: &CalcitTrait) -> bool {
   let Some(def_ref) = trait_def.definition_ref.as_deref() else {
     return false;
@@ -4865,6 +5090,7 @@ fn annotation_dynamic_weight(type_value: &CalcitTypeAnnotation) -> usize {
     | CalcitTypeAnnotation::Optional(inner)
     | CalcitTypeAnnotation::Variadic(inner) => annotation_dynamic_weight(inner),
     CalcitTypeAnnotation::Map(key, value) => annotation_dynamic_weight(key) + annotation_dynamic_weight(value),
+    CalcitTypeAnnotation::MapShape(shape) => shape.all_value_types().map(|ty| annotation_dynamic_weight(ty)).sum(),
     CalcitTypeAnnotation::Fn(signature) => {
       signature.arg_types.iter().map(|arg| annotation_dynamic_weight(arg)).sum::<usize>()
         + signature.rest_type.as_ref().map_or(0, |rest| annotation_dynamic_weight(rest))
@@ -4926,7 +5152,7 @@ fn core_impl_list_symbol_from_type_annotation(type_value: &CalcitTypeAnnotation)
   match type_value {
     CalcitTypeAnnotation::List(_) => Some("&core-list-impls"),
     CalcitTypeAnnotation::String => Some("&core-string-impls"),
-    CalcitTypeAnnotation::Map(_, _) => Some("&core-map-impls"),
+    CalcitTypeAnnotation::Map(_, _) | CalcitTypeAnnotation::MapShape(_) => Some("&core-map-impls"),
     CalcitTypeAnnotation::Set(_) => Some("&core-set-impls"),
     CalcitTypeAnnotation::Number => Some("&core-number-impls"),
     CalcitTypeAnnotation::DynFn | CalcitTypeAnnotation::Fn(_) => Some("&core-fn-impls"),
@@ -4974,10 +5200,11 @@ pub fn static_method_descriptors(type_value: &CalcitTypeAnnotation) -> Option<Ve
     } else {
       Box::new(impls.iter())
     };
+    let ordered_impls = ordered_impls.collect::<Vec<_>>();
     let mut seen = HashSet::new();
     let mut methods = vec![];
 
-    for imp in ordered_impls {
+    for imp in ordered_impls.iter() {
       let origin = imp.trait_name().unwrap_or_else(|| imp.name()).ref_str().to_owned();
       for field in imp.fields().iter() {
         let name = format!(".{}", field.ref_str());
@@ -4989,6 +5216,18 @@ pub fn static_method_descriptors(type_value: &CalcitTypeAnnotation) -> Option<Ve
         }
       }
     }
+    // trait defaults only fill methods that no impl provides explicitly
+    for origin in ordered_impls.iter().filter_map(|imp| imp.origin()) {
+      for (method, default_impl) in origin.methods.iter().zip(origin.defaults.iter()) {
+        let name = format!(".{}", method.ref_str());
+        if default_impl.is_some() && seen.insert(name.clone()) {
+          methods.push(StaticMethodDescriptor {
+            name,
+            origin: origin.name.ref_str().to_owned(),
+          });
+        }
+      }
+    }
     return Some(methods);
   }
 
@@ -5020,6 +5259,25 @@ fn find_method_entry<'a>(impls: &'a [Arc<CalcitImpl>], name: &str, last_wins: bo
   None
 }
 
+/// Signature of a trait default body reachable through `impls`, consulted only
+/// after no impl provides the method explicitly.
+fn find_trait_default_method_type(
+  type_ref: &CalcitTypeAnnotation,
+  impls: &[Arc<CalcitImpl>],
+  name: &str,
+) -> Option<Arc<CalcitTypeAnnotation>> {
+  let last_wins = core_impl_list_symbol_from_type_annotation(type_ref).is_none();
+  let ordered_impls: Box<dyn Iterator<Item = &Arc<CalcitImpl>>> = if last_wins {
+    Box::new(impls.iter().rev())
+  } else {
+    Box::new(impls.iter())
+  };
+  ordered_impls.filter_map(|imp| imp.origin()).find_map(|origin| {
+    origin.get_default(name)?;
+    origin.method_types.get(origin.method_index(name)?).cloned()
+  })
+}
+
 fn find_method_entry_for_type<'a>(type_ref: &CalcitTypeAnnotation, impls: &'a [Arc<CalcitImpl>], name: &str) -> Option<&'a Calcit> {
   // builtin impl lists are ordered by priority in calcit-core
   let last_wins = core_impl_list_symbol_from_type_annotation(type_ref).is_none();
@@ -5130,12 +5388,34 @@ fn extract_predicate_bindings(cond_form: &Calcit, scope_types: &ScopeTypes) -> P
     true_binding: None,
     false_binding: None,
   };
+  // A bare local used as a condition is truthy only when it is present, so a
+  // legacy `Optional<T>` can be used as `T` in the true branch. The false branch
+  // is not narrowed: a present `false` also takes it.
+  if let Calcit::Local(local) = cond_form {
+    let true_binding = scope_types.get(&local.sym).and_then(|current| match current.as_ref() {
+      CalcitTypeAnnotation::Optional(inner) => Some((local.sym.clone(), inner.clone())),
+      _ => None,
+    });
+    return PredicateNarrowing {
+      true_binding,
+      false_binding: None,
+    };
+  }
   let Calcit::List(items) = cond_form else {
     return empty;
   };
   if items.len() != 2 {
     return empty;
   }
+  // `when-not` and `if-not` expand to `(if (not cond) ...)`; swapping the
+  // branches keeps the narrowing of the inner predicate.
+  if is_not_call_head(items.first()) {
+    let inner = extract_predicate_bindings(&items[1], scope_types);
+    return PredicateNarrowing {
+      true_binding: inner.false_binding,
+      false_binding: inner.true_binding,
+    };
+  }
   let Some(pred_name) = (match items.first() {
     Some(Calcit::Symbol { sym, .. }) => Some(sym.as_ref()),
     Some(Calcit::Import(CalcitImport { def, .. })) => Some(def.as_ref()),
@@ -5236,6 +5516,81 @@ fn extract_predicate_bindings(cond_form: &Calcit, scope_types: &ScopeTypes) -> P
   }
 }
 
+fn is_not_call_head(head: Option<&Calcit>) -> bool {
+  match head {
+    Some(Calcit::Proc(CalcitProc::Not)) => true,
+    Some(Calcit::Symbol { sym, .. }) => sym.as_ref() == "not",
+    Some(Calcit::Import(CalcitImport { ns, def, .. })) => ns.as_ref() == calcit::CORE_NS && def.as_ref() == "not",
+    _ => false,
+  }
+}
+
+/// Whether evaluating a preprocessed form always raises, so code after it in the
+/// same body only runs when the form was not taken.
+fn is_diverging_form(form: &Calcit) -> bool {
+  let Calcit::List(items) = form else {
+    return false;
+  };
+  match items.first() {
+    Some(Calcit::Proc(CalcitProc::Raise)) => true,
+    Some(Calcit::Symbol { sym, .. }) => sym.as_ref() == "raise",
+    Some(Calcit::Import(CalcitImport { ns, def, .. })) => ns.as_ref() == calcit::CORE_NS && def.as_ref() == "raise",
+    Some(Calcit::Syntax(CalcitSyntax::CoreLet, _)) => items.len() > 2 && is_diverging_form(&items[items.len() - 1]),
+    Some(Calcit::Syntax(CalcitSyntax::If, _)) => items.len() == 4 && is_diverging_form(&items[2]) && is_diverging_form(&items[3]),
+    _ => false,
+  }
+}
+
+/// Narrowings that hold for the statements following `form` in a `&let` or
+/// `defn` body. Calcit has no early return, so the guards recognized here are
+/// a branch that raises (`(when (nil? x) (raise ...))`, `assert`) and a
+/// `non-nil!` call on a local.
+fn narrowings_after_statement(form: &Calcit, scope_types: &ScopeTypes) -> Vec<(Arc<str>, Arc<CalcitTypeAnnotation>)> {
+  let Calcit::List(items) = form else {
+    return vec![];
+  };
+  match items.first() {
+    Some(Calcit::Syntax(CalcitSyntax::If, _)) if items.len() >= 3 => {
+      let narrowing = extract_predicate_bindings(&items[1], scope_types);
+      let then_diverges = is_diverging_form(&items[2]);
+      let else_diverges = items.get(3).is_some_and(is_diverging_form);
+      if then_diverges && !else_diverges {
+        narrowing.false_binding.into_iter().collect()
+      } else if else_diverges && !then_diverges {
+        narrowing.true_binding.into_iter().collect()
+      } else {
+        vec![]
+      }
+    }
+    // `assert` and multi-statement `when` wrap their guards in `(&let () ...)`.
+    Some(Calcit::Syntax(CalcitSyntax::CoreLet, _)) if matches!(items.get(1), Some(Calcit::List(binding)) if binding.is_empty()) => {
+      let mut nested_types = scope_types.clone();
+      let mut collected = vec![];
+      for statement in items.iter().skip(2) {
+        for (sym, narrowed) in narrowings_after_statement(statement, &nested_types) {
+          nested_types.insert(sym.clone(), narrowed.clone());
+          collected.push((sym, narrowed));
+        }
+      }
+      collected
+    }
+    Some(Calcit::Import(CalcitImport { ns, def, .. })) if ns.as_ref() == calcit::CORE_NS && def.as_ref() == "non-nil!" => {
+      match items.get(1) {
+        Some(Calcit::Local(local)) => scope_types
+          .get(&local.sym)
+          .and_then(|current| match current.as_ref() {
+            CalcitTypeAnnotation::Optional(inner) => Some((local.sym.clone(), inner.clone())),
+            _ => None,
+          })
+          .into_iter()
+          .collect(),
+        _ => vec![],
+      }
+    }
+    _ => vec![],
+  }
+}
+
 fn resolve_enum_type_for_match(
   type_ref: &CalcitTypeAnnotation,
   file_ns: &str,
@@ -5715,6 +6070,9 @@ pub fn preprocess_defn(
           return Ok(());
         }
         let form = preprocess_expr(a, &body_defs, &mut body_types, ctx.file_ns, ctx.check_warnings, ctx.call_stack)?;
+        for (sym, narrowed) in narrowings_after_statement(&form, &body_types) {
+          body_types.insert(sym, narrowed);
+        }
         processed_body.push(form.clone());
         xs = xs.push_right(form);
         Ok(())
@@ -5875,6 +6233,9 @@ pub fn preprocess_core_let(
       return Ok(());
     }
     let form = preprocess_expr(a, &body_defs, &mut body_types, ctx.file_ns, ctx.check_warnings, ctx.call_stack)?;
+    for (sym, narrowed) in narrowings_after_statement(&form, &body_types) {
+      body_types.insert(sym, narrowed);
+    }
     xs.push(form);
     Ok(())
   })?;
@@ -6429,6 +6790,7 @@ fn contains_legacy_optional(annotation: &CalcitTypeAnnotation) -> bool {
     | CalcitTypeAnnotation::Variadic(inner)
     | CalcitTypeAnnotation::JsNullish(inner) => contains_legacy_optional(inner),
     CalcitTypeAnnotation::Map(key, value) => contains_legacy_optional(key) || contains_legacy_optional(value),
+    CalcitTypeAnnotation::MapShape(shape) => shape.all_value_types().any(|ty| contains_legacy_optional(ty)),
     CalcitTypeAnnotation::Fn(info) => {
       info.arg_types.iter().any(|item| contains_legacy_optional(item))
         || info.rest_type.as_ref().is_some_and(|item| contains_legacy_optional(item))
@@ -6530,13 +6892,12 @@ mod tests {
     CalcitStructValue, ImportInfo,
   };
   use crate::data::cirru::code_to_calcit;
+  use crate::runtime_context::{EnteredContext, RuntimeContext};
   use cirru_parser::Cirru;
-  use std::sync::{LazyLock, Mutex};
 
-  static PREPROCESS_TEST_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
-
-  fn lock_preprocess_test_state() -> std::sync::MutexGuard<'static, ()> {
-    PREPROCESS_TEST_LOCK.lock().unwrap_or_else(|err| err.into_inner())
+  /// a fresh runtime context for the calling test, left when the guard is dropped
+  fn enter_test_context() -> EnteredContext {
+    RuntimeContext::new().enter()
   }
 
   #[test]
@@ -6656,6 +7017,128 @@ mod tests {
     ));
   }
 
+  #[test]
+  fn nil_narrowing_follows_negation_truthiness_and_raising_guards() {
+    let sym: Arc<str> = Arc::from("found");
+    let optional_number = Arc::new(CalcitTypeAnnotation::Optional(Arc::new(CalcitTypeAnnotation::Number)));
+    let local = Calcit::Local(CalcitLocal {
+      idx: CalcitLocal::track_sym(&sym),
+      sym: sym.clone(),
+      info: Arc::new(CalcitSymbolInfo {
+        at_ns: Arc::from("tests.narrowing"),
+        at_def: Arc::from("demo"),
+      }),
+      location: None,
+      type_info: optional_number.clone(),
+    });
+    let mut scope_types = ScopeTypes::new();
+    scope_types.insert(sym.clone(), optional_number);
+    let is_number = |binding: &Option<(Arc<str>, Arc<CalcitTypeAnnotation>)>| matches!(binding, Some((name, inferred)) if name.as_ref() == "found" && matches!(inferred.as_ref(), CalcitTypeAnnotation::Number));
+    let syntax = |name: CalcitSyntax| Calcit::Syntax(name, Arc::from(calcit::CORE_NS));
+    let raise = Calcit::from(vec![Calcit::Proc(CalcitProc::Raise), Calcit::Str("missing".into())]);
+    let nil_check = Calcit::from(vec![Calcit::Proc(CalcitProc::NilQuestion), local.clone()]);
+
+    assert!(is_number(&extract_predicate_bindings(&local, &scope_types).true_binding));
+    let negated = Calcit::from(vec![Calcit::Proc(CalcitProc::Not), nil_check.clone()]);
+    assert!(is_number(&extract_predicate_bindings(&negated, &scope_types).true_binding));
+
+    // `(when (nil? found) (raise ...))` leaves `found` present for later statements.
+    let guard = Calcit::from(vec![syntax(CalcitSyntax::If), nil_check.clone(), raise.clone()]);
+    assert!(is_number(&narrowings_after_statement(&guard, &scope_types).pop()));
+
+    // `assert` expands to `(&let () ... (if cond nil (&let () ... (raise ...))))`.
+    let failure = Calcit::from(vec![syntax(CalcitSyntax::CoreLet), Calcit::from(CalcitList::default()), raise]);
+    let assertion = Calcit::from(vec![
+      syntax(CalcitSyntax::CoreLet),
+      Calcit::from(CalcitList::default()),
+      Calcit::from(vec![syntax(CalcitSyntax::If), negated, Calcit::Nil, failure]),
+    ]);
+    assert!(is_number(&narrowings_after_statement(&assertion, &scope_types).pop()));
+
+    // A branch that does not raise proves nothing about the rest of the body.
+    let plain = Calcit::from(vec![syntax(CalcitSyntax::If), nil_check, Calcit::Nil]);
+    assert!(narrowings_after_statement(&plain, &scope_types).is_empty());
+  }
+
+  #[test]
+  fn non_nil_statements_and_raising_js_guards_narrow_later_uses() {
+    let info = Arc::new(CalcitSymbolInfo {
+      at_ns: Arc::from("tests.narrowing"),
+      at_def: Arc::from("demo"),
+    });
+    // Locals carry no inline type, so later uses read the narrowed scope type like preprocessed code does.
+    let local = |name: &str| {
+      let sym: Arc<str> = Arc::from(name);
+      Calcit::Local(CalcitLocal {
+        idx: CalcitLocal::track_sym(&sym),
+        sym,
+        info: info.clone(),
+        location: None,
+        type_info: calcit::DYNAMIC_TYPE.clone(),
+      })
+    };
+    let mut scope_types = ScopeTypes::new();
+    scope_types.insert(
+      Arc::from("found"),
+      Arc::new(CalcitTypeAnnotation::Optional(Arc::new(CalcitTypeAnnotation::Number))),
+    );
+    scope_types.insert(
+      Arc::from("host"),
+      Arc::new(CalcitTypeAnnotation::JsNullish(Arc::new(CalcitTypeAnnotation::JsObject))),
+    );
+
+    let non_nil = Calcit::from(vec![
+      Calcit::Import(CalcitImport {
+        ns: Arc::from(calcit::CORE_NS),
+        def: Arc::from("non-nil!"),
+        info: Arc::new(ImportInfo::Core {
+          at_ns: Arc::from("tests.narrowing"),
+        }),
+        def_id: None,
+      }),
+      local("found"),
+    ]);
+    assert!(matches!(
+      narrowings_after_statement(&non_nil, &scope_types).as_slice(),
+      [(name, inferred)] if name.as_ref() == "found" && matches!(inferred.as_ref(), CalcitTypeAnnotation::Number)
+    ));
+    let mut present_types = scope_types.clone();
+    present_types.insert(Arc::from("found"), Arc::new(CalcitTypeAnnotation::Number));
+    assert!(narrowings_after_statement(&non_nil, &present_types).is_empty());
+
+    let read_title = |scope_types: &ScopeTypes| {
+      let warnings = RefCell::new(vec![]);
+      warn_on_nullable_js_ffi_dereference(
+        &Calcit::Method(Arc::from("title"), calcit::MethodKind::Access),
+        &CalcitList::from(vec![local("host")].as_slice()),
+        scope_types,
+        "tests.narrowing",
+        "demo",
+        &warnings,
+      );
+      warnings.into_inner()
+    };
+    assert_eq!(read_title(&scope_types).len(), 1);
+
+    // `(when (js-nullish? host) (raise ...))` leaves the dereference unflagged.
+    let guard = Calcit::from(vec![
+      Calcit::Syntax(CalcitSyntax::If, Arc::from(calcit::CORE_NS)),
+      Calcit::from(vec![
+        Calcit::Symbol {
+          sym: Arc::from("js-nullish?"),
+          info: info.clone(),
+          location: None,
+        },
+        local("host"),
+      ]),
+      Calcit::from(vec![Calcit::Proc(CalcitProc::Raise), Calcit::Str("no host".into())]),
+    ]);
+    for (sym, narrowed) in narrowings_after_statement(&guard, &scope_types) {
+      scope_types.insert(sym, narrowed);
+    }
+    assert!(read_title(&scope_types).is_empty());
+  }
+
   #[test]
   fn nominal_options_warn_on_legacy_nil_and_enum_operations() {
     let core_head = |operation: &str| {
@@ -6692,6 +7175,20 @@ mod tests {
       assert_eq!(warnings.borrow()[0].code(), Some("W_NOMINAL_ENUM_LEGACY_USE"));
     }
 
+    let enum_check_warnings = RefCell::new(vec![]);
+    warn_on_nominal_enum_legacy_absence_use(
+      &core_head("enum?"),
+      &args,
+      &ScopeTypes::new(),
+      "tests.option-migration",
+      "demo",
+      &enum_check_warnings,
+    );
+    assert!(
+      enum_check_warnings.borrow().is_empty(),
+      "`enum?` holds for every nominal enum and guards `tag-match`"
+    );
+
     let method_warnings = RefCell::new(vec![]);
     warn_on_nominal_enum_legacy_absence_use(
       &Calcit::Method(Arc::from("count"), calcit::MethodKind::Invoke(calcit::DYNAMIC_TYPE.clone())),
@@ -7085,6 +7582,53 @@ mod tests {
     );
   }
 
+  #[test]
+  fn static_method_descriptors_include_derived_impls() {
+    let not_eq = Arc::new(CalcitFn {
+      name: Arc::from("MyEq.not-eq?"),
+      def_ns: Arc::from("tests.derive"),
+      def_ref: None,
+      usage: calcit::CalcitFnUsageMeta::default(),
+      scope: Arc::new(calcit::CalcitScope::default()),
+      args: Arc::new(calcit::CalcitFnArgs::Args(vec![])),
+      body: vec![Calcit::Nil],
+      generics: Arc::new(vec![]),
+      where_bounds: Arc::new(vec![]),
+      return_type: Arc::new(CalcitTypeAnnotation::Bool),
+      arg_types: vec![],
+      rest_type: None,
+    });
+    let eq_trait = CalcitTrait::new(
+      EdnTag::new("MyEq"),
+      vec![EdnTag::new("eq?"), EdnTag::new("not-eq?")],
+      vec![Arc::new(CalcitTypeAnnotation::Dynamic), Arc::new(CalcitTypeAnnotation::Dynamic)],
+    )
+    .with_defaults(vec![None, Some(not_eq)]);
+    let derived = CalcitImpl::derived_stand_in(Arc::new(eq_trait));
+    assert_eq!(derived.fields.as_ref(), &vec![EdnTag::new("eq?")]);
+
+    let type_value = CalcitTypeAnnotation::Struct(
+      Arc::new(CalcitStructDef {
+        name: EdnTag::new("Point"),
+        fields: Arc::new(vec![]),
+        field_types: Arc::new(vec![]),
+        generics: Arc::new(vec![]),
+        where_bounds: Arc::new(vec![]),
+        impls: vec![Arc::new(derived)],
+      }),
+      Arc::new(vec![]),
+    );
+    let names = static_method_descriptors(&type_value)
+      .expect("derived impls should resolve")
+      .into_iter()
+      .map(|method| (method.name, method.origin))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      vec![(".eq?".to_owned(), "MyEq".to_owned()), (".not-eq?".to_owned(), "MyEq".to_owned())]
+    );
+  }
+
   #[test]
   fn passes_assert_type_through_preprocess() {
     let expr = Cirru::List(vec![Cirru::leaf("assert-type"), Cirru::leaf("x"), Cirru::leaf(":fn")]);
@@ -7232,7 +7776,7 @@ mod tests {
 
   #[test]
   fn warns_on_dynamic_postfix_method_when_enabled() {
-    let _lock = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let _warn_guard = WarnDynMethodGuard::new(true);
     let expr = Cirru::List(vec![Cirru::leaf("receiver"), Cirru::leaf(".show")]);
     let code = code_to_calcit(&expr, "tests.dynamic-postfix", "main", vec![]).expect("parse cirru");
@@ -7449,7 +7993,7 @@ mod tests {
 
   #[test]
   fn assert_type_direct_def_resolution_rejects_visible_values() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
 
     program::PROGRAM_CODE_DATA.write().expect("open program code").insert(
       Arc::from("tests.assert"),
@@ -7518,7 +8062,7 @@ mod tests {
 
   #[test]
   fn lookup_trait_for_preprocess_reads_source_backed_trait_without_runtime_value() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
 
     let trait_code = code_to_calcit(
       &Cirru::List(vec![
@@ -7563,6 +8107,81 @@ mod tests {
     assert!(trait_def.has_method("show"));
   }
 
+  #[test]
+  fn trait_bound_exposes_methods_of_required_source_traits() {
+    let _context = enter_test_context();
+
+    let parse_def = |def: &str, cirru: Cirru| code_to_calcit(&cirru, "tests.required-trait", def, vec![]).expect("parse trait def");
+    let base_code = parse_def(
+      "MyBase",
+      Cirru::List(vec![
+        Cirru::leaf("deftrait"),
+        Cirru::leaf("MyBase"),
+        Cirru::List(vec![Cirru::leaf(".same?"), Cirru::leaf(":fn")]),
+      ]),
+    );
+    let derived_code = parse_def(
+      "MyDerived",
+      Cirru::List(vec![
+        Cirru::leaf("deftrait"),
+        Cirru::leaf("MyDerived"),
+        Cirru::leaf(":requires"),
+        Cirru::leaf("MyBase"),
+        Cirru::List(vec![Cirru::leaf(".rank"), Cirru::leaf(":fn")]),
+        Cirru::List(vec![
+          Cirru::leaf(".outranks?"),
+          Cirru::leaf(":fn"),
+          Cirru::List(vec![
+            Cirru::leaf("fn"),
+            Cirru::List(vec![Cirru::leaf("a"), Cirru::leaf("b")]),
+            Cirru::leaf("true"),
+          ]),
+        ]),
+      ]),
+    );
+    let entry = |code: Calcit| program::ProgramDefEntry {
+      code,
+      schema: calcit::DYNAMIC_TYPE.clone(),
+      doc: Arc::from(""),
+      examples: vec![],
+      ffi: None,
+    };
+
+    let mut program_code = program::PROGRAM_CODE_DATA.write().expect("open program code");
+    program_code.insert(
+      Arc::from("tests.required-trait"),
+      program::ProgramFileData {
+        import_map: HashMap::new(),
+        defs: HashMap::from([
+          (Arc::from("MyBase"), entry(base_code)),
+          (Arc::from("MyDerived"), entry(derived_code.clone())),
+        ]),
+      },
+    );
+    drop(program_code);
+
+    let derived = resolve_trait_def_from_source_code(&derived_code).expect("trait with :requires and a default should parse");
+    assert!(derived.has_method("outranks?"));
+    assert_eq!(
+      derived.requires.iter().map(|t| t.definition_ref.as_deref()).collect::<Vec<_>>(),
+      vec![Some("tests.required-trait/MyBase")]
+    );
+
+    let names = static_method_descriptors(&CalcitTypeAnnotation::Trait(Arc::new(derived)))
+      .expect("trait bound methods should resolve")
+      .into_iter()
+      .map(|method| (method.name, method.origin))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      vec![
+        (".rank".to_owned(), "MyDerived".to_owned()),
+        (".outranks?".to_owned(), "MyDerived".to_owned()),
+        (".same?".to_owned(), "MyBase".to_owned()),
+      ]
+    );
+  }
+
   fn seed_external_field_trait(writable: bool) -> Arc<CalcitTrait> {
     let ns = "tests.external-field";
     let def = "HostElement";
@@ -7677,7 +8296,7 @@ mod tests {
 
   #[test]
   fn js_get_infers_external_trait_field_payload() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let receiver = external_field_test_receiver(seed_external_field_trait(true));
     let expression = Calcit::from(vec![
       external_field_test_symbol("js-get"),
@@ -7706,7 +8325,7 @@ mod tests {
 
   #[test]
   fn typed_js_field_rewrite_obeys_js_ffi_capability_policy() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let _feature_policy = JsFfiFeaturePolicyGuard::require();
     let _codegen_mode = CodegenModeGuard::enabled();
     let receiver = external_field_test_receiver(seed_external_field_trait(true));
@@ -7735,7 +8354,7 @@ mod tests {
 
   #[test]
   fn unlowered_js_field_operations_still_require_js_ffi_capability() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let _feature_policy = JsFfiFeaturePolicyGuard::require();
     let _codegen_mode = CodegenModeGuard::enabled();
 
@@ -7762,7 +8381,7 @@ mod tests {
 
   #[test]
   fn js_set_checks_external_trait_static_fields() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let receiver = external_field_test_receiver(seed_external_field_trait(true));
     let head = external_field_test_symbol("js-set");
 
@@ -7819,7 +8438,7 @@ mod tests {
 
   #[test]
   fn js_set_requires_external_field_writable_metadata() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let _feature_policy = JsFfiFeaturePolicyGuard::warn();
     let receiver = external_field_test_receiver(seed_external_field_trait(false));
     let warnings = RefCell::new(vec![]);
@@ -7840,7 +8459,7 @@ mod tests {
 
   #[test]
   fn js_set_error_policy_rejects_readonly_external_field() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let _feature_policy = JsFfiFeaturePolicyGuard::require();
     let receiver = external_field_test_receiver(seed_external_field_trait(false));
     let error = check_typed_js_field_operation(
@@ -7860,7 +8479,7 @@ mod tests {
 
   #[test]
   fn js_ffi_error_policy_rejects_unmarked_host_operations() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let _feature_policy = JsFfiFeaturePolicyGuard::require();
     let _codegen_mode = CodegenModeGuard::enabled();
     let warnings = RefCell::new(vec![]);
@@ -7879,6 +8498,47 @@ mod tests {
     assert!(warnings.borrow().is_empty());
   }
 
+  #[test]
+  fn checks_dylib_ffi_call_sites_against_manifest_metadata() {
+    let _context = enter_test_context();
+    let (ns, def) = ("tests.dylib-ffi", "read-file");
+    let ffi = cirru_edn::parse("{} (:backend :dylib) $ :methods $ {} $ |read_file $ {} (:args $ [] 'String) (:return 'String)")
+      .expect("parse dylib ffi");
+    program::PROGRAM_CODE_DATA.write().expect("open program code").insert(
+      Arc::from(ns),
+      program::ProgramFileData {
+        import_map: HashMap::new(),
+        defs: HashMap::from([(
+          Arc::from(def),
+          program::ProgramDefEntry {
+            code: Calcit::Nil,
+            schema: calcit::DYNAMIC_TYPE.clone(),
+            doc: Arc::from(""),
+            examples: vec![],
+            ffi: Some(ffi),
+          },
+        )]),
+      },
+    );
+    let head = Calcit::Registered(Arc::from("&call-dylib-edn"));
+    let check = |method: &str, args: &[Calcit]| {
+      let warnings = RefCell::new(vec![]);
+      let mut xs = vec![Calcit::Str(Arc::from("libdemo.so")), Calcit::Str(Arc::from(method))];
+      xs.extend_from_slice(args);
+      warn_on_dylib_ffi_call(&head, &CalcitList::from(xs.as_slice()), &ScopeTypes::new(), ns, def, &warnings);
+      warnings.borrow().iter().map(|w| w.code().map(str::to_owned)).collect::<Vec<_>>()
+    };
+
+    assert!(check("read_file", &[Calcit::Str(Arc::from("a.txt"))]).is_empty());
+    assert_eq!(
+      check("read_file", &[Calcit::Number(1.0)]),
+      vec![Some("W_DYLIB_FFI_ARG_TYPE".to_owned())]
+    );
+    assert_eq!(check("read_file", &[]), vec![Some("W_DYLIB_FFI_ARITY".to_owned())]);
+    assert_eq!(check("write_file", &[]), vec![Some("W_DYLIB_FFI_UNKNOWN_METHOD".to_owned())]);
+    program::PROGRAM_CODE_DATA.write().expect("open program code").remove(ns);
+  }
+
   fn untyped_js_ffi_test_receiver() -> Calcit {
     let sym: Arc<str> = Arc::from("host");
     Calcit::Local(CalcitLocal {
@@ -7895,7 +8555,7 @@ mod tests {
 
   #[test]
   fn warns_on_untyped_js_ffi_field_access_when_enabled() {
-    let _lock = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let _warn_guard = WarnDynMethodGuard::new(true);
     let receiver = untyped_js_ffi_test_receiver();
     let head = Calcit::Method(Arc::from("value"), calcit::MethodKind::Access);
@@ -7915,7 +8575,7 @@ mod tests {
 
   #[test]
   fn untyped_js_ffi_field_access_warning_is_opt_in_and_scoped() {
-    let _lock = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let receiver = untyped_js_ffi_test_receiver();
     let head = Calcit::Method(Arc::from("value"), calcit::MethodKind::Access);
 
@@ -7961,7 +8621,7 @@ mod tests {
 
   #[test]
   fn named_function_schema_types_are_visible_inside_the_body() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
 
     let ns = "tests.named-schema-body";
     let def = "add-one";
@@ -8025,7 +8685,7 @@ mod tests {
 
   #[test]
   fn infers_imported_generic_return_type_from_compiled_function_without_runtime_ready() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
 
     let ns = "tests.generic-infer";
     let def = "identity";
@@ -8107,7 +8767,7 @@ mod tests {
 
   #[test]
   fn ensure_ns_def_compiled_refreshes_source_backed_output_even_when_runtime_is_ready() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
 
     let ns = "tests.runtime-shortcut";
     let def = "value";
@@ -8143,7 +8803,7 @@ mod tests {
 
   #[test]
   fn ensure_ns_def_compiled_handles_recursive_source_with_compile_guard() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
 
     let ns = "tests.recursive-compile";
     let def = "loop";
@@ -8238,7 +8898,7 @@ mod tests {
 
   #[test]
   fn nested_struct_field_type_inherits_the_declaring_namespace() {
-    let _guard = lock_preprocess_test_state();
+    let _context = enter_test_context();
     calcit::register_program_lookups(program::lookup_runtime_ready, program::lookup_def_code, program::lookup_def_schema);
 
     let ns = "tests.nested-struct-owner";
@@ -9544,7 +10204,7 @@ mod tests {
 
   #[test]
   fn warns_on_trait_impl_method_tag_syntax() {
-    let _lock = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let _warn_guard = WarnDynMethodGuard::new(true);
 
     let expr = Cirru::List(vec![
@@ -10857,7 +11517,7 @@ mod tests {
 
   #[test]
   fn warns_on_dynamic_trait_call() {
-    let _lock = lock_preprocess_test_state();
+    let _context = enter_test_context();
     let _guard = WarnDynMethodGuard::new(true);
 
     let expr = Cirru::List(vec![Cirru::leaf(".greet"), Cirru::leaf("user")]);
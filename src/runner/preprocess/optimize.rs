@@ -0,0 +1,548 @@
+//! Optimizing pass over preprocessed code.
+//!
+//! Runs on the resolved form of each definition after type rewriting, right
+//! before it is stored, so the interpreter and every backend see the same
+//! optimized code and `calcit ir` shows it. The pass is conservative: it only
+//! rewrites shapes whose evaluation it fully understands and leaves every other
+//! syntax form (quote, match, macros, ...) untouched.
+//!
+//! - constant folding: pure procs whose arguments are all literals are
+//!   evaluated with the runtime implementation of the proc;
+//! - literal propagation: `&let` bindings of literals are substituted into the
+//!   body and the binding is dropped once unused;
+//! - dead branch elimination: `if` with a literal condition keeps one branch;
+//! - let-sinking: a pure `&let` binding used by only one branch of the `if`
+//!   that forms its body moves into that branch;
+//! - inlining: calls to small, non-recursive `defn`s of the same namespace
+//!   become `&let` bindings of the parameters around the callee body.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::{
+  builtins,
+  calcit::{self, Calcit, CalcitList, CalcitProc, CalcitSyntax},
+  call_stack::CallStackList,
+  program,
+};
+
+/// Largest callee body, in nodes, that gets inlined.
+const INLINE_NODE_LIMIT: usize = 24;
+/// Inlined bodies are optimized again and may inline further calls; stop at this depth.
+const INLINE_DEPTH_LIMIT: usize = 4;
+
+/// Definitions inlined while optimizing one definition, as `(ns, def)`.
+pub(super) type InlinedDefs = HashSet<(Arc<str>, Arc<str>)>;
+
+/// Optimize the resolved code of `ns/def`. Returns the optimized form and the
+/// definitions that were inlined into it, which stay dependencies of `ns/def`.
+pub(super) fn optimize_def(ns: &str, def: &str, code: &Calcit) -> (Calcit, InlinedDefs) {
+  let mut optimizer = Optimizer {
+    ns,
+    def,
+    inline_depth: 0,
+    inlined: HashSet::new(),
+  };
+  let optimized = optimizer.optimize_top(code);
+  (optimized, optimizer.inlined)
+}
+
+struct Optimizer<'a> {
+  ns: &'a str,
+  def: &'a str,
+  inline_depth: usize,
+  inlined: InlinedDefs,
+}
+
+impl Optimizer<'_> {
+  fn optimize_top(&mut self, code: &Calcit) -> Calcit {
+    match code {
+      Calcit::List(xs) if matches!(xs.first(), Some(Calcit::Syntax(CalcitSyntax::DefWasmExport, _))) => self.optimize_defn(xs),
+      _ => self.optimize_expr(code),
+    }
+  }
+
+  fn optimize_expr(&mut self, expr: &Calcit) -> Calcit {
+    let Calcit::List(xs) = expr else {
+      return expr.to_owned();
+    };
+    match xs.first() {
+      None => expr.to_owned(),
+      Some(Calcit::Syntax(CalcitSyntax::If, _)) => self.optimize_if(xs),
+      Some(Calcit::Syntax(CalcitSyntax::CoreLet, _)) => self.optimize_let(xs),
+      Some(Calcit::Syntax(CalcitSyntax::Defn, _)) => self.optimize_defn(xs),
+      Some(Calcit::Syntax(CalcitSyntax::Try, _)) => map_items(xs, 1, |x| self.optimize_expr(x)),
+      Some(Calcit::Syntax(..)) => expr.to_owned(),
+      Some(Calcit::Proc(proc)) => {
+        let proc = *proc;
+        let call = map_items(xs, 1, |x| self.optimize_expr(x));
+        fold_proc_call(proc, &call).unwrap_or(call)
+      }
+      Some(Calcit::Import(import)) => {
+        let import = import.to_owned();
+        let call = map_items(xs, 1, |x| self.optimize_expr(x));
+        match self.try_inline(&import, &call) {
+          Some(inlined) => inlined,
+          None => call,
+        }
+      }
+      Some(_) => map_items(xs, 0, |x| self.optimize_expr(x)),
+    }
+  }
+
+  /// `(defn name (args...) body...)`: only the body is code.
+  fn optimize_defn(&mut self, xs: &CalcitList) -> Calcit {
+    map_items(xs, 3, |x| self.optimize_expr(x))
+  }
+
+  fn optimize_if(&mut self, xs: &CalcitList) -> Calcit {
+    let form = map_items(xs, 1, |x| self.optimize_expr(x));
+    let Calcit::List(ys) = &form else { unreachable!("if stays a list") };
+    if ys.len() < 3 || ys.len() > 4 {
+      return form;
+    }
+    match ys.get(1).and_then(literal_truthiness) {
+      Some(true) => ys[2].to_owned(),
+      Some(false) => ys.get(3).cloned().unwrap_or(Calcit::Nil),
+      None => form,
+    }
+  }
+
+  fn optimize_let(&mut self, xs: &CalcitList) -> Calcit {
+    let Some(Calcit::List(pair)) = xs.get(1) else {
+      return xs.into();
+    };
+    let (Some(Calcit::Local(local)), Some(value)) = (pair.get(0), pair.get(1)) else {
+      return xs.into();
+    };
+    if pair.len() != 2 {
+      return xs.into();
+    }
+    let idx = local.idx;
+    let value = self.optimize_expr(value);
+    let mut body: Vec<Calcit> = xs.iter().skip(2).cloned().collect();
+    if is_propagated_literal(&value) {
+      body = body.iter().map(|x| substitute_local(x, idx, &value)).collect();
+    }
+    let body: Vec<Calcit> = body.iter().map(|x| self.optimize_expr(x)).collect();
+
+    if is_pure(&value) && body.len() == 1 {
+      let used = mentions_local(&body[0], idx);
+      if !used {
+        return body.into_iter().next().expect("single body item");
+      }
+      if let Some(sunk) = sink_let_into_if(&xs[0], local, &value, &body[0]) {
+        return sunk;
+      }
+    }
+
+    let binding = Calcit::from(CalcitList::from(&[Calcit::Local(local.to_owned()), value][..]));
+    let mut ys = vec![xs[0].to_owned(), binding];
+    ys.extend(body);
+    Calcit::from(CalcitList::from(&ys[..]))
+  }
+
+  /// Replace a call to a small `defn` of this namespace with its body, binding
+  /// each parameter to the matching argument with `&let`.
+  fn try_inline(&mut self, import: &calcit::CalcitImport, call: &Calcit) -> Option<Calcit> {
+    if self.inline_depth >= INLINE_DEPTH_LIMIT || &*import.ns != self.ns || self.ns == calcit::CORE_NS || &*import.def == self.def {
+      return None;
+    }
+    let Calcit::List(call) = call else { return None };
+    let compiled = program::lookup_compiled_def(&import.ns, &import.def)?;
+    let inlined = inline_defn_call(
+      &compiled.codegen_form,
+      &import.ns,
+      &import.def,
+      &call.iter().skip(1).cloned().collect::<Vec<_>>(),
+    )?;
+    self.inlined.insert((import.ns.to_owned(), import.def.to_owned()));
+    self.inline_depth += 1;
+    let optimized = self.optimize_expr(&inlined);
+    self.inline_depth -= 1;
+    Some(optimized)
+  }
+}
+
+/// Rebuild `xs` with items from `start` on mapped through `f`.
+fn map_items(xs: &CalcitList, start: usize, mut f: impl FnMut(&Calcit) -> Calcit) -> Calcit {
+  let ys: Vec<Calcit> = xs
+    .iter()
+    .enumerate()
+    .map(|(i, x)| if i < start { x.to_owned() } else { f(x) })
+    .collect();
+  Calcit::from(CalcitList::from(&ys[..]))
+}
+
+fn is_literal(x: &Calcit) -> bool {
+  matches!(
+    x,
+    Calcit::Nil | Calcit::Bool(_) | Calcit::Number(_) | Calcit::Str(_) | Calcit::Tag(_)
+  )
+}
+
+/// Literals worth copying into every use of a `&let` binding.
+fn is_propagated_literal(x: &Calcit) -> bool {
+  match x {
+    Calcit::Str(s) => s.len() <= 32,
+    _ => is_literal(x),
+  }
+}
+
+/// Truthiness of a literal condition: only `nil` and `false` are falsy.
+fn literal_truthiness(x: &Calcit) -> Option<bool> {
+  match x {
+    Calcit::Nil | Calcit::Bool(false) => Some(false),
+    _ if is_literal(x) => Some(true),
+    _ => None,
+  }
+}
+
+/// Procs without side effects whose result only depends on their arguments.
+fn is_foldable_proc(proc: CalcitProc) -> bool {
+  use CalcitProc::*;
+  matches!(
+    proc,
+    NativeAdd
+      | NativeMinus
+      | NativeMultiply
+      | NativeDivide
+      | NativeNumberRem
+      | NativeNumberFract
+      | NativeLessThan
+      | NativeGreaterThan
+      | NativeEquals
+      | Identical
+      | Not
+      | Floor
+      | Ceil
+      | Round
+      | Sqrt
+      | Pow
+      | NativeStrConcat
+      | NativeStrCount
+  )
+}
+
+/// Evaluate a pure proc call on literal arguments with the runtime implementation.
+/// Errors and non-finite results are left for runtime.
+fn fold_proc_call(proc: CalcitProc, call: &Calcit) -> Option<Calcit> {
+  let Calcit::List(xs) = call else { return None };
+  if !is_foldable_proc(proc) {
+    return None;
+  }
+  let args: Vec<Calcit> = xs.iter().skip(1).cloned().collect();
+  if !args.iter().all(is_literal) {
+    return None;
+  }
+  match builtins::handle_proc(proc, &args, &CallStackList::default()) {
+    Ok(Calcit::Number(n)) if n.is_finite() => Some(Calcit::Number(n)),
+    Ok(value @ (Calcit::Bool(_) | Calcit::Str(_) | Calcit::Nil)) => Some(value),
+    _ => None,
+  }
+}
+
+/// Expressions that can be dropped or moved without changing behavior.
+fn is_pure(x: &Calcit) -> bool {
+  match x {
+    Calcit::Local(_) => true,
+    Calcit::List(xs) => match xs.first() {
+      Some(Calcit::Proc(proc)) => is_foldable_proc(*proc) && xs.iter().skip(1).all(is_pure),
+      _ => false,
+    },
+    _ => is_literal(x),
+  }
+}
+
+/// Whether `x` may refer to the local `idx` bound outside of it. Rebinding by
+/// `&let` and `defn` parameters is respected; other forms are searched entirely.
+fn mentions_local(x: &Calcit, idx: u16) -> bool {
+  match x {
+    Calcit::Local(local) => local.idx == idx,
+    Calcit::Symbol { sym, .. } => calcit::CalcitLocal::track_sym(sym) == idx,
+    Calcit::List(xs) => match xs.first() {
+      Some(Calcit::Syntax(CalcitSyntax::CoreLet, _)) => match xs.get(1) {
+        Some(Calcit::List(pair)) if pair.len() == 2 => match (pair.get(0), pair.get(1)) {
+          (Some(Calcit::Local(local)), Some(value)) => {
+            mentions_local(value, idx) || (local.idx != idx && xs.iter().skip(2).any(|y| mentions_local(y, idx)))
+          }
+          _ => xs.iter().any(|y| mentions_local(y, idx)),
+        },
+        _ => xs.iter().any(|y| mentions_local(y, idx)),
+      },
+      Some(Calcit::Syntax(CalcitSyntax::Defn, _)) => {
+        !xs.get(2).is_some_and(|args| mentions_local(args, idx)) && xs.iter().skip(3).any(|y| mentions_local(y, idx))
+      }
+      _ => xs.iter().any(|y| mentions_local(y, idx)),
+    },
+    _ => false,
+  }
+}
+
+/// Substitute the literal `value` for the local `idx` in expression `x`,
+/// stopping where `idx` is rebound and leaving unknown syntax forms alone.
+fn substitute_local(x: &Calcit, idx: u16, value: &Calcit) -> Calcit {
+  match x {
+    Calcit::Local(local) if local.idx == idx => value.to_owned(),
+    Calcit::List(xs) => match xs.first() {
+      Some(Calcit::Syntax(CalcitSyntax::CoreLet, _)) => {
+        let Some(Calcit::List(pair)) = xs.get(1) else { return x.to_owned() };
+        let (Some(Calcit::Local(local)), Some(bound)) = (pair.get(0), pair.get(1)) else {
+          return x.to_owned();
+        };
+        let binding = Calcit::from(CalcitList::from(
+          &[Calcit::Local(local.to_owned()), substitute_local(bound, idx, value)][..],
+        ));
+        let shadowed = local.idx == idx;
+        let ys: Vec<Calcit> = xs
+          .iter()
+          .enumerate()
+          .map(|(i, y)| match i {
+            0 => y.to_owned(),
+            1 => binding.to_owned(),
+            _ if shadowed => y.to_owned(),
+            _ => substitute_local(y, idx, value),
+          })
+          .collect();
+        Calcit::from(CalcitList::from(&ys[..]))
+      }
+      Some(Calcit::Syntax(CalcitSyntax::Defn, _)) => {
+        if xs.get(2).is_some_and(|args| mentions_local(args, idx)) {
+          x.to_owned()
+        } else {
+          map_items(xs, 3, |y| substitute_local(y, idx, value))
+        }
+      }
+      Some(Calcit::Syntax(CalcitSyntax::If | CalcitSyntax::Try, _)) => map_items(xs, 1, |y| substitute_local(y, idx, value)),
+      Some(Calcit::Syntax(..)) => x.to_owned(),
+      _ => map_items(xs, 0, |y| substitute_local(y, idx, value)),
+    },
+    _ => x.to_owned(),
+  }
+}
+
+/// `(&let (x v) (if c a b))` where only one branch uses `x` becomes
+/// `(if c (&let (x v) a) b)`, so `v` is only computed when needed.
+fn sink_let_into_if(let_head: &Calcit, local: &calcit::CalcitLocal, value: &Calcit, body: &Calcit) -> Option<Calcit> {
+  let Calcit::List(xs) = body else { return None };
+  if !matches!(xs.first(), Some(Calcit::Syntax(CalcitSyntax::If, _))) || xs.len() < 3 || xs.len() > 4 {
+    return None;
+  }
+  let idx = local.idx;
+  if mentions_local(&xs[1], idx) {
+    return None;
+  }
+  let users: Vec<usize> = (2..xs.len()).filter(|i| mentions_local(&xs[*i], idx)).collect();
+  let [branch] = users[..] else { return None };
+  let binding = Calcit::from(CalcitList::from(&[Calcit::Local(local.to_owned()), value.to_owned()][..]));
+  let wrapped = Calcit::from(CalcitList::from(&[let_head.to_owned(), binding, xs[branch].to_owned()][..]));
+  let ys: Vec<Calcit> = xs
+    .iter()
+    .enumerate()
+    .map(|(i, y)| if i == branch { wrapped.to_owned() } else { y.to_owned() })
+    .collect();
+  Some(Calcit::from(CalcitList::from(&ys[..])))
+}
+
+fn count_nodes(x: &Calcit) -> usize {
+  match x {
+    Calcit::List(xs) => 1 + xs.iter().map(count_nodes).sum::<usize>(),
+    _ => 1,
+  }
+}
+
+/// Callee bodies that can be evaluated in the caller's scope: calls, `if` and
+/// `&let` only, no `recur`, no reference back to the callee, no unresolved symbols.
+fn is_inlinable_body(x: &Calcit, ns: &str, def: &str) -> bool {
+  match x {
+    Calcit::Symbol { .. } => false,
+    Calcit::Proc(CalcitProc::Recur) => false,
+    Calcit::Import(import) => !(&*import.ns == ns && &*import.def == def),
+    Calcit::Syntax(syntax, _) => matches!(syntax, CalcitSyntax::If | CalcitSyntax::CoreLet),
+    Calcit::List(xs) => xs.iter().all(|y| is_inlinable_body(y, ns, def)),
+    _ => true,
+  }
+}
+
+/// Inline a call of `defn_form`, the compiled `ns/def`, on `args`. Returns
+/// `None` when the definition is not a small single-expression `defn` with
+/// plain parameters matching the call, or when an argument mentions a
+/// parameter bound before it, which the nested `&let`s would capture.
+fn inline_defn_call(defn_form: &Calcit, ns: &str, def: &str, args: &[Calcit]) -> Option<Calcit> {
+  let Calcit::List(xs) = defn_form else { return None };
+  let (Some(Calcit::Syntax(CalcitSyntax::Defn, _)), Some(Calcit::List(params)), 4) = (xs.first(), xs.get(2), xs.len()) else {
+    return None;
+  };
+  let body = &xs[3];
+  if params.len() != args.len() || count_nodes(body) > INLINE_NODE_LIMIT || !is_inlinable_body(body, ns, def) {
+    return None;
+  }
+  let mut locals = Vec::with_capacity(params.len());
+  for param in params.iter() {
+    let Calcit::Local(local) = param else { return None };
+    locals.push(local.to_owned());
+  }
+  for (i, arg) in args.iter().enumerate() {
+    if locals[..i].iter().any(|local| mentions_local(arg, local.idx)) {
+      return None;
+    }
+  }
+
+  let let_head = Calcit::Syntax(CalcitSyntax::CoreLet, Arc::from(calcit::CORE_NS));
+  let mut acc = body.to_owned();
+  for (local, arg) in locals.into_iter().zip(args).rev() {
+    let binding = Calcit::from(CalcitList::from(&[Calcit::Local(local), arg.to_owned()][..]));
+    acc = Calcit::from(CalcitList::from(&[let_head.to_owned(), binding, acc][..]));
+  }
+  Some(acc)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::calcit::{CalcitLocal, CalcitSymbolInfo, DYNAMIC_TYPE};
+
+  fn local(name: &str) -> Calcit {
+    let sym: Arc<str> = Arc::from(name);
+    Calcit::Local(CalcitLocal {
+      idx: CalcitLocal::track_sym(&sym),
+      sym,
+      info: Arc::new(CalcitSymbolInfo {
+        at_ns: Arc::from("tests.optimize"),
+        at_def: Arc::from("demo"),
+      }),
+      location: None,
+      type_info: DYNAMIC_TYPE.clone(),
+    })
+  }
+
+  fn list(xs: Vec<Calcit>) -> Calcit {
+    Calcit::from(CalcitList::from(&xs[..]))
+  }
+
+  fn syntax(s: CalcitSyntax) -> Calcit {
+    Calcit::Syntax(s, Arc::from(calcit::CORE_NS))
+  }
+
+  fn let_form(name: &str, value: Calcit, body: Calcit) -> Calcit {
+    list(vec![syntax(CalcitSyntax::CoreLet), list(vec![local(name), value]), body])
+  }
+
+  fn optimize(code: &Calcit) -> Calcit {
+    optimize_def("tests.optimize", "demo", code).0
+  }
+
+  #[test]
+  fn folds_constant_arithmetic_and_concat() {
+    let sum = list(vec![
+      Calcit::Proc(CalcitProc::NativeAdd),
+      Calcit::Number(1.0),
+      list(vec![
+        Calcit::Proc(CalcitProc::NativeMultiply),
+        Calcit::Number(2.0),
+        Calcit::Number(3.0),
+      ]),
+    ]);
+    assert_eq!(optimize(&sum), Calcit::Number(7.0));
+
+    let concat = list(vec![
+      Calcit::Proc(CalcitProc::NativeStrConcat),
+      Calcit::Str(Arc::from("a")),
+      Calcit::Number(1.0),
+    ]);
+    assert_eq!(optimize(&concat), Calcit::Str(Arc::from("a1")));
+
+    // division by zero is left for runtime
+    let div = list(vec![
+      Calcit::Proc(CalcitProc::NativeDivide),
+      Calcit::Number(1.0),
+      Calcit::Number(0.0),
+    ]);
+    assert_eq!(optimize(&div), div);
+  }
+
+  #[test]
+  fn drops_branches_with_literal_conditions() {
+    let cond = list(vec![
+      Calcit::Proc(CalcitProc::NativeLessThan),
+      Calcit::Number(1.0),
+      Calcit::Number(2.0),
+    ]);
+    let form = list(vec![syntax(CalcitSyntax::If), cond, local("a"), local("b")]);
+    assert_eq!(optimize(&form), local("a"));
+
+    let form = list(vec![syntax(CalcitSyntax::If), Calcit::Nil, local("a")]);
+    assert_eq!(optimize(&form), Calcit::Nil);
+
+    let form = list(vec![syntax(CalcitSyntax::If), Calcit::Str(Arc::from("x")), local("a"), local("b")]);
+    assert_eq!(optimize(&form), local("a"));
+  }
+
+  #[test]
+  fn propagates_literal_bindings_until_shadowed() {
+    let add = |x: Calcit, y: Calcit| list(vec![Calcit::Proc(CalcitProc::NativeAdd), x, y]);
+    let form = let_form("a", Calcit::Number(1.0), add(local("a"), Calcit::Number(2.0)));
+    assert_eq!(optimize(&form), Calcit::Number(3.0));
+
+    let inner = let_form("a", local("n"), add(local("a"), Calcit::Number(1.0)));
+    let form = let_form("a", Calcit::Number(1.0), inner.to_owned());
+    assert_eq!(optimize(&form), inner);
+  }
+
+  #[test]
+  fn sinks_bindings_into_the_only_branch_using_them() {
+    let sum = list(vec![Calcit::Proc(CalcitProc::NativeAdd), local("n"), Calcit::Number(1.0)]);
+    let branch = list(vec![Calcit::Proc(CalcitProc::NativeMultiply), local("x"), local("x")]);
+    let form = let_form(
+      "x",
+      sum.to_owned(),
+      list(vec![syntax(CalcitSyntax::If), local("c"), branch.to_owned(), Calcit::Nil]),
+    );
+    let expected = list(vec![syntax(CalcitSyntax::If), local("c"), let_form("x", sum, branch), Calcit::Nil]);
+    assert_eq!(optimize(&form), expected);
+
+    // calls may have effects, so they stay where they were
+    let effect = list(vec![Calcit::Proc(CalcitProc::GenerateId), local("n")]);
+    let form = let_form(
+      "x",
+      effect,
+      list(vec![syntax(CalcitSyntax::If), local("c"), local("x"), Calcit::Nil]),
+    );
+    assert_eq!(optimize(&form), form);
+  }
+
+  #[test]
+  fn inlines_small_defn_calls_with_let_bindings() {
+    let body = list(vec![Calcit::Proc(CalcitProc::NativeMultiply), local("x"), Calcit::Number(2.0)]);
+    let defn = list(vec![
+      syntax(CalcitSyntax::Defn),
+      Calcit::Str(Arc::from("twice")),
+      list(vec![local("x")]),
+      body.to_owned(),
+    ]);
+    let inlined = inline_defn_call(&defn, "tests.optimize", "twice", &[local("n")]).expect("inline twice");
+    assert_eq!(inlined, let_form("x", local("n"), body));
+
+    // arity mismatch and recursion are not inlined
+    assert_eq!(inline_defn_call(&defn, "tests.optimize", "twice", &[]), None);
+    let recursive = list(vec![
+      syntax(CalcitSyntax::Defn),
+      Calcit::Str(Arc::from("again")),
+      list(vec![local("x")]),
+      list(vec![Calcit::Proc(CalcitProc::Recur), local("x")]),
+    ]);
+    assert_eq!(inline_defn_call(&recursive, "tests.optimize", "again", &[local("n")]), None);
+  }
+
+  #[test]
+  fn avoids_capturing_earlier_parameters() {
+    let defn = list(vec![
+      syntax(CalcitSyntax::Defn),
+      Calcit::Str(Arc::from("pair")),
+      list(vec![local("x"), local("y")]),
+      list(vec![Calcit::Proc(CalcitProc::NativeAdd), local("x"), local("y")]),
+    ]);
+    assert_eq!(inline_defn_call(&defn, "tests.optimize", "pair", &[local("y"), local("x")]), None);
+    assert!(inline_defn_call(&defn, "tests.optimize", "pair", &[local("x"), local("n")]).is_some());
+  }
+}
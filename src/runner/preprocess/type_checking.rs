@@ -10,16 +10,21 @@
 //! - `check_local_fn_call_arg_types` — check local fn variable call arguments
 //! - `check_user_fn_arg_types` — check user-defined fn call arguments
 //! - `check_function_return_type` — check fn body return type vs declaration
+//! - `check_trait_impl_conformance` — check `defimpl` members vs `deftrait` signatures
 //! - `detect_return_type_hint_from_processed_body` — extract hint-fn return type
 
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use super::type_inference::infer_struct_field_type;
+use cirru_edn::EdnTag;
+
+use super::type_inference::{
+  extract_impl_field_name, extract_impl_pair, infer_preprocessed_function_parameters, infer_struct_field_type, resolve_impl_origin,
+};
 use crate::calcit::{
-  self, Calcit, CalcitFn, CalcitGenericBound, CalcitList, CalcitLocal, CalcitProc, CalcitSyntax, CalcitTypeAnnotation, LocatedWarning,
-  NodeLocation,
+  self, Calcit, CalcitFn, CalcitGenericBound, CalcitList, CalcitLocal, CalcitProc, CalcitSyntax, CalcitTrait, CalcitTraitMemberKind,
+  CalcitTypeAnnotation, LocatedWarning, NodeLocation,
 };
 use crate::program;
 
@@ -591,11 +596,223 @@ pub(crate) fn check_function_return_type(
   }
 }
 
+/// Callable shape of one `defimpl` member, as far as preprocessing can see it.
+struct ImplMethodSignature {
+  arg_types: Vec<Arc<CalcitTypeAnnotation>>,
+  rest_type: Option<Arc<CalcitTypeAnnotation>>,
+  return_type: Arc<CalcitTypeAnnotation>,
+  location: Option<NodeLocation>,
+}
+
+fn resolve_impl_method_signature(value: &Calcit, scope_types: &ScopeTypes) -> Option<ImplMethodSignature> {
+  // An inline `fn` has been preprocessed into `(defn name (params) ...body)`. Its parameters carry
+  // their hinted types, and an undeclared return type is inferred from the last body form.
+  if let Calcit::List(items) = value
+    && matches!(items.first(), Some(Calcit::Syntax(CalcitSyntax::Defn, _)))
+  {
+    let (arg_types, rest_type) = infer_preprocessed_function_parameters(items.get(2));
+    let body = items.iter().skip(3).cloned().collect::<Vec<_>>();
+    let declared_return = detect_return_type_hint_from_processed_body(&body);
+    let return_type = if matches!(declared_return.as_ref(), CalcitTypeAnnotation::Dynamic) {
+      body
+        .iter()
+        .rfind(|form| CalcitTypeAnnotation::extract_return_type_from_hint_form(form).is_none())
+        .and_then(|last| resolve_type_value(last, scope_types))
+        .unwrap_or_else(|| calcit::DYNAMIC_TYPE.clone())
+    } else {
+      declared_return
+    };
+    return Some(ImplMethodSignature {
+      arg_types,
+      rest_type,
+      return_type,
+      location: impl_member_location(value),
+    });
+  }
+
+  match resolve_type_value(value, scope_types)?.as_ref() {
+    CalcitTypeAnnotation::Fn(fn_annot) => Some(ImplMethodSignature {
+      arg_types: fn_annot.arg_types.clone(),
+      rest_type: fn_annot.rest_type.clone(),
+      return_type: fn_annot.return_type.clone(),
+      location: impl_member_location(value),
+    }),
+    _ => None,
+  }
+}
+
+/// Source location of a `defimpl` member value; an inline `fn` has no location of its own, so
+/// its first parameter (or generated name) stands in for it.
+fn impl_member_location(value: &Calcit) -> Option<NodeLocation> {
+  match value {
+    Calcit::List(items) if matches!(items.first(), Some(Calcit::Syntax(CalcitSyntax::Defn, _))) => match items.get(2) {
+      Some(Calcit::List(params)) => params.iter().find_map(Calcit::get_location),
+      _ => None,
+    }
+    .or_else(|| items.get(1).and_then(Calcit::get_location)),
+    _ => value.get_location(),
+  }
+}
+
+fn describe_trait_impl_signature_mismatch(
+  signature: &ImplMethodSignature,
+  expected: &calcit::CalcitFnTypeAnnotation,
+) -> Option<String> {
+  let expected_arity = expected.arg_types.len();
+  let actual_arity = signature.arg_types.len();
+  let arity_mismatch = match (&signature.rest_type, &expected.rest_type) {
+    (None, None) => actual_arity != expected_arity,
+    (None, Some(_)) => true,
+    (Some(_), _) => actual_arity > expected_arity,
+  };
+  if arity_mismatch {
+    let expected_text = if expected.rest_type.is_some() {
+      format!("{expected_arity} argument(s) and a rest argument")
+    } else {
+      format!("{expected_arity} argument(s)")
+    };
+    let actual_text = if signature.rest_type.is_some() {
+      format!("{actual_arity} argument(s) and a rest argument")
+    } else {
+      format!("{actual_arity} argument(s)")
+    };
+    return Some(format!("expects {expected_text}, but the implementation takes {actual_text}"));
+  }
+
+  // Same direction as `CalcitFnTypeAnnotation::matches_signature`: trait arguments must be accepted
+  // by the implementation, and the implementation's return value must satisfy the trait.
+  let mut bindings = HashMap::new();
+  for (idx, expected_arg) in expected.arg_types.iter().enumerate() {
+    let Some(actual_arg) = signature.arg_types.get(idx).or(signature.rest_type.as_ref()) else {
+      continue;
+    };
+    if !expected_arg.matches_with_bindings(actual_arg, &mut bindings) {
+      return Some(format!(
+        "argument {} expects type `{}`, but the implementation declares `{}`",
+        idx + 1,
+        expected_arg.to_brief_string(),
+        actual_arg.to_brief_string()
+      ));
+    }
+  }
+
+  if matches!(
+    signature.return_type.as_ref(),
+    CalcitTypeAnnotation::Dynamic | CalcitTypeAnnotation::DynFn
+  ) {
+    return None;
+  }
+  if !signature
+    .return_type
+    .matches_with_bindings(expected.return_type.as_ref(), &mut bindings)
+  {
+    return Some(format!(
+      "returns type `{}`, but the implementation returns `{}`",
+      expected.return_type.substitute_type_vars(&bindings).to_brief_string(),
+      signature.return_type.to_brief_string()
+    ));
+  }
+  None
+}
+
+/// Check an `&impl::new` call (what `defimpl` expands to) against the `deftrait` it names:
+/// every trait member must be provided, every provided method must be declared by the trait, and
+/// method arity, argument types and return types must fit the declared signatures. The runtime
+/// repeats the member check when the impl is created; doing it here reports the source location
+/// and also covers return types that are only known by inference.
+pub(crate) fn check_trait_impl_conformance(
+  head: &Calcit,
+  args: &CalcitList,
+  scope_types: &ScopeTypes,
+  file_ns: &str,
+  def_name: &str,
+  check_warnings: &RefCell<Vec<LocatedWarning>>,
+) {
+  if !matches!(head, Calcit::Proc(CalcitProc::NativeImplNew)) {
+    return;
+  }
+  let Some((_, Some(trait_def))) = args.first().and_then(|origin| resolve_impl_origin(origin, scope_types)) else {
+    return;
+  };
+  let trait_def: &CalcitTrait = trait_def.as_ref();
+
+  let mut members: Vec<(EdnTag, &Calcit)> = Vec::with_capacity(args.len().saturating_sub(1));
+  for item in args.iter().skip(1) {
+    // Malformed pairs are reported by `&impl::new` itself.
+    let Some((field, value)) = extract_impl_pair(item) else {
+      return;
+    };
+    let Some(name) = extract_impl_field_name(field) else {
+      return;
+    };
+    members.push((name, value));
+  }
+
+  let def_location = NodeLocation::new(Arc::from(file_ns), Arc::from(def_name), Arc::from(vec![]));
+  let trait_name = trait_def.name.ref_str();
+
+  let missing = trait_def
+    .methods
+    .iter()
+    .filter(|method| !members.iter().any(|(name, _)| name == *method) && trait_def.get_default(method.ref_str()).is_none())
+    .map(|method| format!(".{}", method.ref_str()))
+    .collect::<Vec<_>>();
+  if !missing.is_empty() {
+    gen_check_warning_code_at(
+      format!(
+        "[Warn] impl of trait `{trait_name}` in {file_ns}/{def_name} is missing {}: {}",
+        if missing.len() == 1 { "method" } else { "methods" },
+        missing.join(" ")
+      ),
+      "W_TRAIT_IMPL_MISSING_METHOD",
+      file_ns,
+      Some(def_location.clone()),
+      check_warnings,
+    );
+  }
+
+  for (name, value) in &members {
+    let Some(method_idx) = trait_def.methods.iter().position(|method| method == name) else {
+      gen_check_warning_code_at(
+        format!(
+          "[Warn] impl of trait `{trait_name}` in {file_ns}/{def_name} defines `.{}`, which the trait does not declare",
+          name.ref_str()
+        ),
+        "W_TRAIT_IMPL_UNKNOWN_METHOD",
+        file_ns,
+        impl_member_location(value).or_else(|| Some(def_location.clone())),
+        check_warnings,
+      );
+      continue;
+    };
+    if trait_def.member_kinds.get(method_idx) != Some(&CalcitTraitMemberKind::Method) {
+      continue;
+    }
+    let Some(CalcitTypeAnnotation::Fn(expected)) = trait_def.method_types.get(method_idx).map(|ty| ty.as_ref()) else {
+      continue;
+    };
+    let Some(signature) = resolve_impl_method_signature(value, scope_types) else {
+      continue;
+    };
+    if let Some(detail) = describe_trait_impl_signature_mismatch(&signature, expected) {
+      gen_check_warning_code_at(
+        format!(
+          "[Warn] `.{}` in impl of trait `{trait_name}` ({file_ns}/{def_name}) does not match the trait signature: {detail}",
+          name.ref_str()
+        ),
+        "W_TRAIT_IMPL_SIGNATURE_MISMATCH",
+        file_ns,
+        signature.location.or_else(|| Some(def_location.clone())),
+        check_warnings,
+      );
+    }
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
   use crate::calcit::{CalcitLocal, CalcitStructDef, CalcitSymbolInfo};
-  use cirru_edn::EdnTag;
 
   fn make_local(name: &str, type_info: Arc<CalcitTypeAnnotation>) -> Calcit {
     let sym: Arc<str> = Arc::from(name);
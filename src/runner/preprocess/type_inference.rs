@@ -19,8 +19,9 @@ use crate::calcit::type_annotation::code_resolves_to_nominal_type_def;
 use crate::{
   builtins,
   calcit::{
-    self, Calcit, CalcitEnumDef, CalcitFnTypeAnnotation, CalcitImpl, CalcitImport, CalcitList, CalcitProc, CalcitStructDef,
-    CalcitStructValue, CalcitSyntax, CalcitTrait, CalcitTypeAnnotation, ImportInfo, SchemaKind, resolve_type_slot,
+    self, Calcit, CalcitEnumDef, CalcitFn, CalcitFnArgs, CalcitFnTypeAnnotation, CalcitFnUsageMeta, CalcitImpl, CalcitImport,
+    CalcitList, CalcitMapShape, CalcitProc, CalcitScope, CalcitStructDef, CalcitStructValue, CalcitSyntax, CalcitTrait,
+    CalcitTypeAnnotation, ImportInfo, SchemaKind, resolve_type_slot,
   },
   call_stack::CallStackList,
   program, runner,
@@ -28,9 +29,9 @@ use crate::{
 use cirru_edn::EdnTag;
 
 use super::{
-  ScopeTypes, find_method_entry_for_type, find_trait_field_type, find_trait_method_type, get_impls_from_type,
-  resolve_local_type_refs_for_body, resolve_namespace_type_refs_for_body, resolve_trait_def_from_source_code, tag_annotation,
-  trait_is_external_object, trait_list_from_type,
+  ScopeTypes, find_method_entry_for_type, find_trait_default_method_type, find_trait_field_type, find_trait_method_type,
+  get_impls_from_type, resolve_local_type_refs_for_body, resolve_namespace_type_refs_for_body, resolve_trait_def_from_source_code,
+  tag_annotation, trait_is_external_object, trait_list_from_type,
 };
 
 // ---------------------------------------------------------------------------
@@ -192,6 +193,22 @@ pub(crate) fn infer_return_type_from_compiled_callable(
   {
     return Some(inferred);
   }
+  if ns == calcit::CORE_NS
+    && matches!(def, "assoc" | "dissoc")
+    && let Some(receiver_type) = call_expr.get(1).and_then(|receiver| resolve_type_value(receiver, scope_types))
+    && let CalcitTypeAnnotation::MapShape(shape) = receiver_type.as_ref()
+  {
+    let args = call_expr.iter().skip(2).collect::<Vec<_>>();
+    return Some(infer_map_shape_update_type(shape, def == "assoc", &args, scope_types));
+  }
+  if ns == calcit::CORE_NS
+    && def == "non-nil!"
+    && let Some(receiver_type) = call_expr.get(1).and_then(|receiver| resolve_type_value(receiver, scope_types))
+    && let CalcitTypeAnnotation::Optional(inner) = receiver_type.as_ref()
+  {
+    // `non-nil!` raises on nil, so its result is the payload of a legacy optional.
+    return Some(inner.clone());
+  }
   if ns == calcit::CORE_NS
     && def == "update"
     && let Some(receiver_type) = call_expr.get(1).and_then(|receiver| resolve_type_value(receiver, scope_types))
@@ -214,6 +231,12 @@ pub(crate) fn infer_return_type_from_compiled_callable(
   {
     return Some(inferred);
   }
+  if ns == calcit::CORE_NS
+    && matches!(def, "&derive-struct-impl" | "&derive-enum-impl")
+    && let Some(impl_def) = infer_derived_impl_value(call_expr)
+  {
+    return Some(Arc::new(CalcitTypeAnnotation::Custom(Arc::new(Calcit::Impl(impl_def)))));
+  }
 
   let is_core_enum_constructor = ns == calcit::CORE_NS && matches!(def, "%some" | "%none" | "%ok" | "%err");
 
@@ -400,6 +423,10 @@ fn infer_lookup_payload_type_from_type(
   match base_type {
     CalcitTypeAnnotation::List(element_type) => Some(element_type.clone()),
     CalcitTypeAnnotation::Map(_, value_type) => Some(value_type.clone()),
+    CalcitTypeAnnotation::MapShape(shape) => Some(match key_arg {
+      Some(Calcit::Tag(key)) => shape.key_type(key.ref_str()),
+      _ => calcit::DYNAMIC_TYPE.clone(),
+    }),
     CalcitTypeAnnotation::String => Some(tag_annotation("string")),
     CalcitTypeAnnotation::EnumValue(_) | CalcitTypeAnnotation::AnonymousEnum => Some(calcit::DYNAMIC_TYPE.clone()),
     CalcitTypeAnnotation::StructValue(_) | CalcitTypeAnnotation::Struct(_, _) | CalcitTypeAnnotation::TypeRef(_, _) => {
@@ -416,6 +443,57 @@ fn infer_lookup_payload_type_from_type(
   }
 }
 
+/// Value type of `(&map:get shape :key)`. Optional keys may be missing, so they are nullable;
+/// unlisted keys read from the open row, and always yield nil on a closed shape.
+fn infer_map_shape_get_type(shape: &CalcitMapShape, key_arg: Option<&Calcit>) -> Arc<CalcitTypeAnnotation> {
+  let Some(Calcit::Tag(key)) = key_arg else {
+    return calcit::DYNAMIC_TYPE.clone();
+  };
+  let value_type = shape.key_type(key.ref_str());
+  match (shape.field(key.ref_str()), &shape.rest) {
+    // required keys are always present, unlisted keys of a closed shape never are
+    (Some((_, true)), _) | (None, None) => value_type,
+    _ => wrap_optional_type(value_type),
+  }
+}
+
+/// Refine a map shape through `assoc`/`dissoc` with literal tag keys. A non-literal key makes the
+/// result an ordinary map, since any listed field may have been replaced or removed.
+fn infer_map_shape_update_type(
+  shape: &CalcitMapShape,
+  is_assoc: bool,
+  args: &[&Calcit],
+  scope_types: &ScopeTypes,
+) -> Arc<CalcitTypeAnnotation> {
+  let plain_map = || {
+    Arc::new(CalcitTypeAnnotation::Map(
+      calcit::DYNAMIC_TYPE.clone(),
+      calcit::DYNAMIC_TYPE.clone(),
+    ))
+  };
+  let mut next = shape.to_owned();
+  if is_assoc {
+    if !args.len().is_multiple_of(2) {
+      return plain_map();
+    }
+    for pair in args.chunks(2) {
+      let Calcit::Tag(key) = pair[0] else {
+        return plain_map();
+      };
+      let value_type = resolve_type_value(pair[1], scope_types).unwrap_or_else(|| calcit::DYNAMIC_TYPE.clone());
+      next = next.with_field(key.to_owned(), value_type);
+    }
+  } else {
+    for key in args {
+      let Calcit::Tag(key) = key else {
+        return plain_map();
+      };
+      next = next.without_field(key);
+    }
+  }
+  Arc::new(CalcitTypeAnnotation::MapShape(Arc::new(next)))
+}
+
 fn wrap_optional_type(inner: Arc<CalcitTypeAnnotation>) -> Arc<CalcitTypeAnnotation> {
   match inner.as_ref() {
     CalcitTypeAnnotation::Optional(_) => inner,
@@ -670,8 +748,10 @@ pub(crate) fn infer_type_from_expr(expr: &Calcit, scope_types: &ScopeTypes) -> O
             find_trait_method_type(&traits, method_name).map(|(_, method_type)| method_type.clone())?
           } else {
             let impls = get_impls_from_type(receiver_type.as_ref())?;
-            let method = find_method_entry_for_type(receiver_type.as_ref(), &impls, method_name)?;
-            infer_type_from_expr(method, scope_types)?
+            match find_method_entry_for_type(receiver_type.as_ref(), &impls, method_name) {
+              Some(method) => infer_type_from_expr(method, scope_types)?,
+              None => find_trait_default_method_type(receiver_type.as_ref(), &impls, method_name)?,
+            }
           };
           let CalcitTypeAnnotation::Fn(info) = method_type.as_ref() else {
             return Some(calcit::DYNAMIC_TYPE.clone());
@@ -790,7 +870,7 @@ fn infer_preprocessed_function_type(xs: &CalcitList) -> Arc<CalcitTypeAnnotation
   })))
 }
 
-fn infer_preprocessed_function_parameters(
+pub(super) fn infer_preprocessed_function_parameters(
   params: Option<&Calcit>,
 ) -> (Vec<Arc<CalcitTypeAnnotation>>, Option<Arc<CalcitTypeAnnotation>>) {
   let Some(Calcit::List(params)) = params else {
@@ -821,7 +901,7 @@ fn infer_preprocessed_function_parameters(
   (fixed, rest_type)
 }
 
-fn resolve_impl_origin(value: &Calcit, scope_types: &ScopeTypes) -> Option<(EdnTag, Option<Arc<CalcitTrait>>)> {
+pub(super) fn resolve_impl_origin(value: &Calcit, scope_types: &ScopeTypes) -> Option<(EdnTag, Option<Arc<CalcitTrait>>)> {
   let resolved_trait = match value {
     Calcit::Trait(trait_def) => Some(Arc::new(trait_def.to_owned())),
     Calcit::Import(import) => match resolve_program_value_for_preprocess(&import.ns, &import.def, import.def_id) {
@@ -861,7 +941,7 @@ fn is_list_constructor(value: &Calcit) -> bool {
     || matches!(value, Calcit::Symbol { sym, .. } if sym.as_ref() == "[]")
 }
 
-fn extract_impl_pair(value: &Calcit) -> Option<(&Calcit, &Calcit)> {
+pub(super) fn extract_impl_pair(value: &Calcit) -> Option<(&Calcit, &Calcit)> {
   match value {
     Calcit::Enum(enum_value) if enum_value.extra.len() == 1 => Some((enum_value.tag.as_ref(), enum_value.extra.first()?)),
     Calcit::List(items) if items.len() == 2 => Some((items.first()?, items.get(1)?)),
@@ -877,7 +957,7 @@ fn extract_impl_pair(value: &Calcit) -> Option<(&Calcit, &Calcit)> {
   }
 }
 
-fn extract_impl_field_name(value: &Calcit) -> Option<EdnTag> {
+pub(super) fn extract_impl_field_name(value: &Calcit) -> Option<EdnTag> {
   match value {
     Calcit::Method(name, _) | Calcit::Str(name) | Calcit::Symbol { sym: name, .. } => Some(EdnTag(name.to_owned())),
     Calcit::Tag(name) => Some(name.to_owned()),
@@ -908,11 +988,71 @@ fn normalize_static_metadata_form(value: &Calcit) -> Calcit {
 }
 
 fn infer_trait_value(xs: &CalcitList) -> Option<CalcitTrait> {
-  let args = xs.iter().skip(1).map(normalize_static_metadata_form).collect::<Vec<_>>();
-  match builtins::meta::trait_new(&args).ok()? {
-    Calcit::Trait(trait_def) => Some(trait_def),
-    _ => None,
+  // Default bodies and required traits are runtime values; strip them so the
+  // builtin only sees `(method type)` specs, then record which defaults exist.
+  let mut args = xs.iter().skip(1).take(2).map(normalize_static_metadata_form).collect::<Vec<_>>();
+  let mut default_forms = vec![];
+  if let Some(Calcit::List(specs)) = args.get(1) {
+    let mut signatures = Vec::with_capacity(specs.len());
+    for spec in specs.iter() {
+      match spec {
+        Calcit::List(entry) if entry.len() == 3 => {
+          default_forms.push(entry.get(2).cloned());
+          signatures.push(Calcit::List(Arc::new(entry.butlast().ok()?)));
+        }
+        other => {
+          default_forms.push(None);
+          signatures.push(other.to_owned());
+        }
+      }
+    }
+    args[1] = Calcit::from(signatures);
   }
+  let Calcit::Trait(trait_def) = builtins::meta::trait_new(&args).ok()? else {
+    return None;
+  };
+  if default_forms.iter().all(Option::is_none) {
+    return Some(trait_def);
+  }
+  let defaults = default_forms
+    .into_iter()
+    .zip(trait_def.methods.iter().zip(trait_def.method_types.iter()))
+    .map(|(form, (method, method_type))| form.map(|form| static_default_stand_in(&trait_def.name, method, method_type, form)))
+    .collect();
+  Some(trait_def.with_defaults(defaults))
+}
+
+/// Preprocessing only needs to know that a default body exists and which
+/// signature it has; the callable itself appears once the trait is evaluated.
+fn static_default_stand_in(
+  trait_name: &EdnTag,
+  method: &EdnTag,
+  method_type: &Arc<CalcitTypeAnnotation>,
+  form: Calcit,
+) -> Arc<CalcitFn> {
+  let (generics, arg_types, return_type, rest_type) = match method_type.as_ref() {
+    CalcitTypeAnnotation::Fn(info) => (
+      info.generics.to_owned(),
+      info.arg_types.to_owned(),
+      info.return_type.to_owned(),
+      info.rest_type.to_owned(),
+    ),
+    _ => (Arc::new(vec![]), vec![], calcit::DYNAMIC_TYPE.clone(), None),
+  };
+  Arc::new(CalcitFn {
+    name: Arc::from(format!("{trait_name}.{}", method.ref_str())),
+    def_ns: Arc::from(calcit::GEN_NS),
+    def_ref: None,
+    usage: CalcitFnUsageMeta::default(),
+    scope: Arc::new(CalcitScope::default()),
+    args: Arc::new(CalcitFnArgs::Args(vec![])),
+    body: vec![form],
+    generics,
+    where_bounds: Arc::new(vec![]),
+    return_type,
+    arg_types,
+    rest_type,
+  })
 }
 
 /// Synthesize the concrete metadata type produced by `&impl::new` without
@@ -939,6 +1079,18 @@ fn infer_impl_value(xs: &CalcitList, scope_types: &ScopeTypes) -> Option<CalcitI
   })
 }
 
+/// `:derive` clauses expand into `(&derive-struct-impl :Trait ...)` calls whose
+/// impl provides exactly the required methods of that core trait.
+fn infer_derived_impl_value(xs: &CalcitList) -> Option<CalcitImpl> {
+  let Some(Calcit::Tag(trait_tag)) = xs.get(1) else {
+    return None;
+  };
+  match resolve_program_value_for_preprocess(calcit::CORE_NS, trait_tag.ref_str(), None)? {
+    Calcit::Trait(trait_def) => Some(CalcitImpl::derived_stand_in(Arc::new(trait_def))),
+    _ => None,
+  }
+}
+
 fn resolve_impl_annotation(value: &Calcit, scope_types: &ScopeTypes) -> Option<Arc<CalcitImpl>> {
   let inferred = resolve_type_value(value, scope_types)?;
   match inferred.as_ref() {
@@ -1120,9 +1272,25 @@ fn infer_proc_call_return_type(proc: &CalcitProc, xs: &CalcitList, scope_types:
   if matches!(proc, CalcitProc::NativeMapGet)
     && let Some(first_arg) = xs.get(1)
     && let Some(type_value) = resolve_type_value(first_arg, scope_types)
-    && let CalcitTypeAnnotation::Map(_key_type, val_type) = type_value.as_ref()
   {
-    return Some(val_type.clone());
+    match type_value.as_ref() {
+      CalcitTypeAnnotation::Map(_key_type, val_type) => return Some(val_type.clone()),
+      CalcitTypeAnnotation::MapShape(shape) => return Some(infer_map_shape_get_type(shape, xs.get(2))),
+      _ => {}
+    }
+  }
+  if matches!(proc, CalcitProc::NativeMapAssoc | CalcitProc::NativeMapDissoc)
+    && let Some(first_arg) = xs.get(1)
+    && let Some(type_value) = resolve_type_value(first_arg, scope_types)
+    && let CalcitTypeAnnotation::MapShape(shape) = type_value.as_ref()
+  {
+    let args = xs.iter().skip(2).collect::<Vec<_>>();
+    return Some(infer_map_shape_update_type(
+      shape,
+      matches!(proc, CalcitProc::NativeMapAssoc),
+      &args,
+      scope_types,
+    ));
   }
   if matches!(
     proc,
@@ -1919,6 +2087,56 @@ mod tests {
     );
   }
 
+  #[test]
+  fn map_shape_lookups_and_updates_refine_literal_tag_keys() {
+    let string_type = Arc::new(CalcitTypeAnnotation::String);
+    let shape = CalcitMapShape::new(
+      vec![(EdnTag::from("name"), string_type.clone())],
+      vec![(EdnTag::from("email"), string_type.clone())],
+      None,
+    );
+    let person = local("person", Arc::new(CalcitTypeAnnotation::MapShape(Arc::new(shape))));
+    let scope = ScopeTypes::new();
+
+    let get_name = proc_call(CalcitProc::NativeMapGet, vec![person.clone(), Calcit::tag("name")]);
+    assert_eq!(infer_type_from_expr(&get_name, &scope), Some(string_type.clone()));
+    let get_email = proc_call(CalcitProc::NativeMapGet, vec![person.clone(), Calcit::tag("email")]);
+    assert!(matches!(
+      infer_type_from_expr(&get_email, &scope).as_deref(),
+      Some(CalcitTypeAnnotation::Optional(inner)) if inner.as_ref() == string_type.as_ref()
+    ));
+    let get_unknown = proc_call(CalcitProc::NativeMapGet, vec![person.clone(), Calcit::tag("phone")]);
+    assert_eq!(
+      infer_type_from_expr(&get_unknown, &scope).as_deref(),
+      Some(&CalcitTypeAnnotation::Unit)
+    );
+
+    let assoc = proc_call(
+      CalcitProc::NativeMapAssoc,
+      vec![person.clone(), Calcit::tag("age"), Calcit::Number(3.0)],
+    );
+    let Some(CalcitTypeAnnotation::MapShape(assoced)) = infer_type_from_expr(&assoc, &scope).as_deref().cloned() else {
+      panic!("assoc with a literal tag should keep the shape");
+    };
+    assert!(matches!(assoced.field("age"), Some((ty, true)) if ty.as_ref() == &CalcitTypeAnnotation::Number));
+
+    let dissoc_call = CalcitList::from(&[symbol("dissoc"), person.clone(), Calcit::tag("name")] as &[Calcit]);
+    let Some(CalcitTypeAnnotation::MapShape(dissoced)) =
+      infer_return_type_from_compiled_callable(calcit::CORE_NS, "dissoc", &dissoc_call, &scope)
+        .as_deref()
+        .cloned()
+    else {
+      panic!("dissoc with a literal tag should keep the shape");
+    };
+    assert!(dissoced.field("name").is_none());
+
+    let dynamic_key = proc_call(CalcitProc::NativeMapAssoc, vec![person, symbol("k"), Calcit::Number(3.0)]);
+    assert!(matches!(
+      infer_type_from_expr(&dynamic_key, &scope).as_deref(),
+      Some(CalcitTypeAnnotation::Map(_, _))
+    ));
+  }
+
   #[test]
   fn struct_preserving_operations_keep_nominal_type_for_required_field_access() {
     let mut struct_def = CalcitStructDef::from_fields(EdnTag::from("User"), vec![EdnTag::from("name")]);
@@ -0,0 +1,454 @@
+//! Capability-restricted execution, enabled by `calcit --sandbox`.
+//!
+//! A sandbox denies effects unless the policy allows them:
+//!
+//! - `read-file` and `read-dir` need a path under one of the `fs_read` roots,
+//! - `write-file` needs a path under one of the `fs_write` roots,
+//! - `get-env` needs `env`,
+//! - registered procs tagged `:interop` need `ffi`, `:net` needs `net`, `:env` needs `env`,
+//!   and `:file` procs are denied since their paths can not be checked,
+//! - other registered procs tagged `:io` are denied unless they are `:log` output, no
+//!   capability grants timers like `async-sleep` or signal handlers like `on-control-c`,
+//! - `quit!` is always denied, a snippet must not stop the host process.
+//!
+//! Budgets are checked in the interpreter loop, once per evaluated call expression.
+//! Once a budget is exceeded every following step fails too, so `try` can not swallow it.
+//!
+//! The memory budget needs `CountingAlloc` as the global allocator, which the `calcit`
+//! binary installs. It adds each allocation to a counter of the allocating thread, the
+//! counter is moved into the sandbox of the thread's context every `CHECK_INTERVAL` steps
+//! and whenever the thread enters or leaves a context. The budget so covers net bytes
+//! allocated by all threads inside the sandboxed context, including the timer and FFI
+//! callback threads that enter it. Memory of a dylib's own allocator is not seen.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicIsize, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use cirru_edn::EdnTag;
+
+use crate::builtins::RegisteredProcDescriptor;
+use crate::calcit::{CalcitErr, CalcitErrKind};
+use crate::call_stack::CallStackList;
+use crate::runtime_context::ContextSlot;
+
+/// steps between checks of the clock and the heap size
+const CHECK_INTERVAL: u64 = 1024;
+
+static SANDBOX: ContextSlot<OnceLock<Sandbox>> = ContextSlot::new(|ctx| &ctx.sandbox);
+
+/// sandboxes alive in the process, lets [tick] skip the context lookup when there are none
+static INSTALLED_SANDBOXES: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+  /// net heap bytes allocated by this thread and not yet moved into a sandbox
+  static HEAP_DELTA: Cell<isize> = const { Cell::new(0) };
+}
+
+fn count_heap(bytes: isize) {
+  let _ = HEAP_DELTA.try_with(|delta| delta.set(delta.get() + bytes));
+}
+
+/// Global allocator counting heap bytes of sandboxes, required by memory budgets.
+pub struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let ptr = unsafe { System.alloc(layout) };
+    if !ptr.is_null() {
+      count_heap(layout.size() as isize);
+    }
+    ptr
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    unsafe { System.dealloc(ptr, layout) };
+    count_heap(-(layout.size() as isize));
+  }
+
+  unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+    let ptr = unsafe { System.alloc_zeroed(layout) };
+    if !ptr.is_null() {
+      count_heap(layout.size() as isize);
+    }
+    ptr
+  }
+
+  unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    let next = unsafe { System.realloc(ptr, layout, new_size) };
+    if !next.is_null() {
+      count_heap(new_size as isize - layout.size() as isize);
+    }
+    next
+  }
+}
+
+/// move heap bytes counted on this thread into the sandbox of the current context,
+/// called when the thread enters or leaves a context
+pub(crate) fn settle_heap() {
+  let delta = HEAP_DELTA.with(|delta| delta.replace(0));
+  if delta != 0
+    && INSTALLED_SANDBOXES.load(Ordering::Relaxed) > 0
+    && let Some(sandbox) = SANDBOX.current().get()
+  {
+    sandbox.heap.fetch_add(delta, Ordering::Relaxed);
+  }
+}
+
+/// whether allocations of this thread reach [CountingAlloc]
+fn counting_alloc_installed() -> bool {
+  let before = HEAP_DELTA.with(Cell::get);
+  let boxed = std::hint::black_box(Box::new(0u64));
+  let counted = HEAP_DELTA.with(Cell::get) > before;
+  drop(boxed);
+  counted
+}
+
+/// Capabilities and budgets of a sandbox, everything not listed is denied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SandboxPolicy {
+  pub fs_read: Vec<PathBuf>,
+  pub fs_write: Vec<PathBuf>,
+  pub env: bool,
+  pub ffi: bool,
+  pub net: bool,
+  /// wall-clock time since the sandbox is installed
+  pub timeout: Option<Duration>,
+  /// net heap bytes allocated in the sandboxed context, needs [CountingAlloc]
+  pub memory_budget: Option<usize>,
+  /// evaluated call expressions
+  pub step_budget: Option<u64>,
+}
+
+pub struct Sandbox {
+  policy: SandboxPolicy,
+  read_roots: Vec<PathBuf>,
+  write_roots: Vec<PathBuf>,
+  started: Instant,
+  steps: AtomicU64,
+  /// net heap bytes counted by [CountingAlloc] for this sandbox
+  heap: AtomicIsize,
+  exceeded: OnceLock<String>,
+}
+
+fn canonical_roots(paths: &[PathBuf], capability: &str) -> Result<Vec<PathBuf>, String> {
+  paths
+    .iter()
+    .map(|path| {
+      path
+        .canonicalize()
+        .map_err(|e| format!("sandbox {capability} path {} is not accessible: {e}", path.display()))
+    })
+    .collect()
+}
+
+/// absolute path with symlinks of existing ancestors resolved, `None` for paths escaping with `..`
+fn resolve_path(path: &Path) -> Option<PathBuf> {
+  let absolute = if path.is_absolute() {
+    path.to_path_buf()
+  } else {
+    std::env::current_dir().ok()?.join(path)
+  };
+  let mut existing = absolute.as_path();
+  let mut rest = vec![];
+  loop {
+    if let Ok(resolved) = existing.canonicalize() {
+      return Some(rest.iter().rev().fold(resolved, |acc, part| acc.join(part)));
+    }
+    rest.push(existing.file_name()?.to_owned());
+    existing = existing.parent()?;
+  }
+}
+
+/// install a sandbox into the current runtime context, it stays for the lifetime of the context
+pub fn install(policy: SandboxPolicy) -> Result<(), String> {
+  if policy.memory_budget.is_some() && !counting_alloc_installed() {
+    return Err(String::from(
+      "sandbox memory budget requires `calcit::runner::sandbox::CountingAlloc` as the global allocator",
+    ));
+  }
+  let sandbox = Sandbox {
+    read_roots: canonical_roots(&policy.fs_read, "fs-read")?,
+    write_roots: canonical_roots(&policy.fs_write, "fs-write")?,
+    policy,
+    started: Instant::now(),
+    steps: AtomicU64::new(0),
+    heap: AtomicIsize::new(0),
+    exceeded: OnceLock::new(),
+  };
+  // paired with the decrement when a sandbox drops, including one rejected below
+  INSTALLED_SANDBOXES.fetch_add(1, Ordering::Relaxed);
+  SANDBOX.with(|slot| {
+    slot
+      .set(sandbox)
+      .map_err(|_| String::from("sandbox is already installed in this runtime context"))
+  })?;
+  // allocations of this thread before the sandbox are not charged to it
+  HEAP_DELTA.with(|delta| delta.set(0));
+  Ok(())
+}
+
+pub fn is_active() -> bool {
+  SANDBOX.current().get().is_some()
+}
+
+/// steps evaluated in the sandbox of the current context
+pub fn steps() -> Option<u64> {
+  SANDBOX.current().get().map(|sandbox| sandbox.steps.load(Ordering::Relaxed))
+}
+
+/// heap bytes counted for the sandbox of the current context
+pub fn heap_bytes() -> Option<usize> {
+  SANDBOX.current().get().map(Sandbox::heap_bytes)
+}
+
+fn denied(msg: String) -> CalcitErr {
+  CalcitErr::use_str(CalcitErrKind::Sandbox, msg)
+}
+
+/// count one step of the interpreter loop and check budgets
+#[inline]
+pub(crate) fn tick(call_stack: &CallStackList) -> Result<(), CalcitErr> {
+  if INSTALLED_SANDBOXES.load(Ordering::Relaxed) == 0 {
+    return Ok(());
+  }
+  match SANDBOX.current().get() {
+    None => Ok(()),
+    Some(sandbox) => sandbox.tick(call_stack),
+  }
+}
+
+impl Drop for Sandbox {
+  fn drop(&mut self) {
+    INSTALLED_SANDBOXES.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+impl Sandbox {
+  fn tick(&self, call_stack: &CallStackList) -> Result<(), CalcitErr> {
+    if let Some(msg) = self.exceeded.get() {
+      return Err(CalcitErr::use_msg_stack(CalcitErrKind::Sandbox, msg.to_owned(), call_stack));
+    }
+    let steps = self.steps.fetch_add(1, Ordering::Relaxed) + 1;
+    let exceeded = if let Some(max) = self.policy.step_budget
+      && steps > max
+    {
+      Some(format!("sandbox step budget of {max} exhausted"))
+    } else if steps.is_multiple_of(CHECK_INTERVAL) {
+      self.check_clock_and_heap()
+    } else {
+      None
+    };
+    match exceeded {
+      Some(msg) => {
+        let msg = self.exceeded.get_or_init(|| msg);
+        Err(CalcitErr::use_msg_stack(CalcitErrKind::Sandbox, msg.to_owned(), call_stack))
+      }
+      None => Ok(()),
+    }
+  }
+
+  fn heap_bytes(&self) -> usize {
+    self.heap.load(Ordering::Relaxed).max(0) as usize
+  }
+
+  fn check_clock_and_heap(&self) -> Option<String> {
+    self.heap.fetch_add(HEAP_DELTA.with(|delta| delta.replace(0)), Ordering::Relaxed);
+    if let Some(timeout) = self.policy.timeout
+      && self.started.elapsed() > timeout
+    {
+      return Some(format!("sandbox timeout of {}ms exceeded", timeout.as_millis()));
+    }
+    if let Some(budget) = self.policy.memory_budget
+      && self.heap_bytes() > budget
+    {
+      return Some(format!("sandbox memory budget of {budget} bytes exceeded"));
+    }
+    None
+  }
+
+  fn check_path(&self, roots: &[PathBuf], path: &str, capability: &str) -> Result<(), CalcitErr> {
+    let allowed = resolve_path(Path::new(path)).is_some_and(|resolved| roots.iter().any(|root| resolved.starts_with(root)));
+    if allowed {
+      Ok(())
+    } else {
+      Err(denied(format!(
+        "sandbox denies {capability} of `{path}`, allow it with --allow-{capability}"
+      )))
+    }
+  }
+}
+
+pub(crate) fn check_read(path: &str) -> Result<(), CalcitErr> {
+  match SANDBOX.current().get() {
+    None => Ok(()),
+    Some(sandbox) => sandbox.check_path(&sandbox.read_roots, path, "read"),
+  }
+}
+
+pub(crate) fn check_write(path: &str) -> Result<(), CalcitErr> {
+  match SANDBOX.current().get() {
+    None => Ok(()),
+    Some(sandbox) => sandbox.check_path(&sandbox.write_roots, path, "write"),
+  }
+}
+
+pub(crate) fn check_env(name: &str) -> Result<(), CalcitErr> {
+  match SANDBOX.current().get() {
+    Some(sandbox) if !sandbox.policy.env => Err(denied(format!(
+      "sandbox denies reading env variable `{name}`, allow it with --allow-env"
+    ))),
+    _ => Ok(()),
+  }
+}
+
+pub(crate) fn check_quit() -> Result<(), CalcitErr> {
+  if is_active() {
+    Err(denied(String::from("sandbox denies quit!")))
+  } else {
+    Ok(())
+  }
+}
+
+/// check a registered proc against the capabilities of its `:tags`
+pub(crate) fn check_registered_proc(alias: &str, descriptor: &RegisteredProcDescriptor) -> Result<(), CalcitErr> {
+  let slot = SANDBOX.current();
+  let Some(sandbox) = slot.get() else {
+    return Ok(());
+  };
+  let policy = &sandbox.policy;
+  let capabilities = [
+    ("interop", policy.ffi, "--allow-ffi"),
+    ("net", policy.net, "--allow-net"),
+    ("env", policy.env, "--allow-env"),
+  ];
+  for (tag, allowed, flag) in capabilities {
+    if !allowed && descriptor.tags.contains(&EdnTag::new(tag)) {
+      return Err(denied(format!("sandbox denies `{alias}` tagged :{tag}, allow it with {flag}")));
+    }
+  }
+  if descriptor.tags.contains(&EdnTag::new("file")) {
+    return Err(denied(format!(
+      "sandbox denies `{alias}` tagged :file, its paths can not be checked"
+    )));
+  }
+  let granted = descriptor.tags.contains(&EdnTag::new("log"))
+    || capabilities
+      .iter()
+      .any(|(tag, allowed, _)| *allowed && descriptor.tags.contains(&EdnTag::new(*tag)));
+  if !granted && descriptor.tags.contains(&EdnTag::new("io")) {
+    return Err(denied(format!("sandbox denies `{alias}` tagged :io, no capability grants it")));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::builtins::proc_tags;
+  use crate::runtime_context::RuntimeContext;
+
+  fn sandboxed(policy: SandboxPolicy) -> crate::runtime_context::EnteredContext {
+    let entered = RuntimeContext::new().enter();
+    install(policy).expect("install sandbox");
+    entered
+  }
+
+  #[test]
+  fn checks_paths_env_and_proc_tags() {
+    let dir = std::env::temp_dir().join(format!("calcit-sandbox-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("data")).expect("create dirs");
+    let _entered = sandboxed(SandboxPolicy {
+      fs_read: vec![dir.clone()],
+      fs_write: vec![dir.join("data")],
+      ..Default::default()
+    });
+    let inside = dir.join("a.txt");
+    assert!(check_read(&inside.to_string_lossy()).is_ok());
+    assert!(check_write(&inside.to_string_lossy()).is_err());
+    assert!(check_write(&dir.join("data/new/b.txt").to_string_lossy()).is_ok());
+    assert!(check_write(&dir.join("data/../a.txt").to_string_lossy()).is_err());
+    assert!(check_read("/etc/passwd").is_err());
+    assert_eq!(check_env("HOME").expect_err("env").kind, CalcitErrKind::Sandbox);
+    assert!(check_quit().is_err());
+
+    let ffi = RegisteredProcDescriptor {
+      tags: proc_tags(["interop", "io"]),
+      ..Default::default()
+    };
+    let log = RegisteredProcDescriptor {
+      tags: proc_tags(["log", "io"]),
+      ..Default::default()
+    };
+    let timer = RegisteredProcDescriptor {
+      tags: proc_tags(["io"]),
+      ..Default::default()
+    };
+    let signal = RegisteredProcDescriptor {
+      tags: proc_tags(["control", "io"]),
+      ..Default::default()
+    };
+    assert!(check_registered_proc("&call-dylib-edn", &ffi).is_err());
+    assert!(check_registered_proc("println", &log).is_ok());
+    assert!(check_registered_proc("async-sleep", &timer).is_err());
+    assert!(check_registered_proc("on-control-c", &signal).is_err());
+    std::fs::remove_dir_all(&dir).expect("clean dirs");
+  }
+
+  #[test]
+  fn step_budget_stays_exhausted() {
+    let _entered = sandboxed(SandboxPolicy {
+      step_budget: Some(3),
+      ..Default::default()
+    });
+    let stack = CallStackList::default();
+    for _ in 0..3 {
+      assert!(tick(&stack).is_ok());
+    }
+    assert!(tick(&stack).is_err());
+    assert!(tick(&stack).is_err());
+    assert_eq!(steps(), Some(4));
+  }
+
+  #[global_allocator]
+  static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+  #[test]
+  fn memory_budget_counts_allocations_of_its_context() {
+    let _entered = sandboxed(SandboxPolicy {
+      memory_budget: Some(1 << 20),
+      ..Default::default()
+    });
+    let stack = CallStackList::default();
+    assert!(tick(&stack).is_ok());
+    let kept = std::hint::black_box(vec![1u8; 2 << 20]);
+    let elsewhere = {
+      let _other = RuntimeContext::new().enter();
+      std::hint::black_box(vec![1u8; 8 << 20])
+    };
+    let ctx = crate::runtime_context::current();
+    let thread_bytes = std::thread::spawn(move || {
+      let _entered = ctx.enter();
+      std::hint::black_box(vec![1u8; 1 << 20])
+    })
+    .join()
+    .expect("join");
+    settle_heap();
+    let counted = heap_bytes().expect("sandbox heap");
+    assert!((3 << 20..8 << 20).contains(&counted), "counted {counted} bytes");
+    assert!((0..CHECK_INTERVAL).any(|_| tick(&stack).is_err()));
+    drop((kept, elsewhere, thread_bytes));
+  }
+
+  #[test]
+  fn checks_nothing_outside_of_sandbox() {
+    let _entered = RuntimeContext::new().enter();
+    assert!(!is_active());
+    assert!(check_read("/etc/passwd").is_ok());
+    assert!(check_quit().is_ok());
+    assert!(tick(&CallStackList::default()).is_ok());
+  }
+}
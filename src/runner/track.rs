@@ -2,6 +2,8 @@ use std::sync::atomic;
 use std::sync::atomic::AtomicUsize;
 use std::{thread, time};
 
+/// pending tasks of the whole process, kept out of runtime contexts since tasks are
+/// released from timer and FFI threads that enter no context
 static TASK_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 pub fn exit_when_cleared() {
@@ -0,0 +1,349 @@
+//! Runtime state of one Calcit program.
+//!
+//! Program data, registered procs, refs, id and gensym counters, the running mode, codegen mode,
+//! preprocess and codegen switches, JS codegen caches, the stack tracking switch and the
+//! sandbox live in a [RuntimeContext]. Each thread reads the context it has entered,
+//! threads that never enter one share the process default context, which is what the CLI uses.
+//! Threads spawned while running a program (timers, FFI callbacks, file watchers) capture
+//! [current] and enter it, so they keep running against the program that started them.
+//!
+//! The slots formerly kept as statics are [ContextSlot] handles with the same names,
+//! `PROGRAM_CODE_DATA.read()` resolves to the current context of the calling thread.
+//! Entering a context is cheap, so an embedder or a test may run several programs side
+//! by side, each thread inside its own context:
+//!
+//! ```
+//! use calcit::runtime_context::RuntimeContext;
+//!
+//! let ctx = RuntimeContext::new();
+//! let _entered = ctx.enter();
+//! assert!(calcit::program::PROGRAM_CODE_DATA.read().unwrap().is_empty());
+//! ```
+//!
+//! A context is dropped with the last [Arc] pointing at it. The guard of [RuntimeContext::enter]
+//! holds one, and so do references handed out by a slot. Locked fields are shared through
+//! their own [Arc], lock guards of a slot own a handle of the field they lock, so a lock taken
+//! on `PROGRAM_CODE_DATA` stays valid after the thread leaves the context.
+//!
+//! Some state stays process-wide on purpose:
+//! - the pending task counter in `runner::track`, tasks are released from threads that never
+//!   enter a context and the CLI waits for all of them before exiting,
+//! - the interned local names in `calcit::local`, an append-only table shared by all programs,
+//! - the type lookup hooks in `calcit::type_annotation`, function pointers that read the
+//!   current context when called,
+//! - the process start instant behind `cpu-time`, and the quiet output switch of the CLI,
+//! - thread-locals of the preprocessor and type checker, which are scoped to one call.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, LockResult, OnceLock};
+
+use parking_lot::{ArcMutexGuard, ArcRwLockReadGuard, ArcRwLockWriteGuard, Mutex, RawMutex, RawRwLock, RwLock};
+
+use crate::builtins::effects::CliRunningMode;
+use crate::builtins::refs::RefListeners;
+use crate::builtins::{FnType, RegisteredProcDescriptor};
+use crate::call_stack::CalcitStack;
+use crate::codegen::emit_js::DefCodeCache;
+use crate::program::{FileRevisions, ProgramCodeData, ProgramCompiledData, ProgramDefIdIndex, ProgramRuntimeData};
+use crate::runner::sandbox::{self, Sandbox};
+use crate::snapshot;
+
+/// lock on a field of a [RuntimeContext], shared so that guards can own a handle of it
+pub type ContextRwLock<T> = Arc<RwLock<T>>;
+/// mutex on a field of a [RuntimeContext], shared so that guards can own a handle of it
+pub type ContextMutex<T> = Arc<Mutex<T>>;
+
+pub struct RuntimeContext {
+  pub(crate) program_code: ContextRwLock<ProgramCodeData>,
+  pub(crate) program_compiled: ContextRwLock<ProgramCompiledData>,
+  pub(crate) program_runtime: ContextRwLock<ProgramRuntimeData>,
+  pub(crate) program_def_ids: ContextRwLock<ProgramDefIdIndex>,
+  pub(crate) program_revisions: ContextRwLock<HashMap<Arc<str>, FileRevisions>>,
+  pub(crate) feature_policy: ContextRwLock<HashMap<String, snapshot::FeaturePolicy>>,
+  pub(crate) target: ContextRwLock<Option<snapshot::SnapshotTarget>>,
+  pub(crate) imported_procs: ContextRwLock<HashMap<Arc<str>, FnType>>,
+  pub(crate) imported_proc_descriptors: ContextRwLock<HashMap<Arc<str>, RegisteredProcDescriptor>>,
+  pub(crate) warned_registered_procs: ContextRwLock<HashSet<Arc<str>>>,
+  pub(crate) project_namespaces: ContextRwLock<HashSet<Arc<str>>>,
+  pub(crate) refs: ContextMutex<RefListeners>,
+  pub(crate) ns_symbols: ContextMutex<HashMap<Arc<str>, usize>>,
+  pub(crate) js_symbol_index: AtomicUsize,
+  pub(crate) fn_ids: AtomicUsize,
+  pub(crate) atom_ids: AtomicUsize,
+  pub(crate) trait_ids: AtomicU64,
+  pub(crate) running_mode: ContextRwLock<CliRunningMode>,
+  pub(crate) track_stack: AtomicBool,
+  pub(crate) codegen_mode: AtomicBool,
+  pub(crate) skip_arity_check: AtomicBool,
+  pub(crate) warn_dyn_method: AtomicBool,
+  pub(crate) verbose_preprocess: AtomicBool,
+  pub(crate) optimize: AtomicBool,
+  pub(crate) js_first_compilation: AtomicBool,
+  pub(crate) js_previous_program: ContextRwLock<HashMap<Arc<str>, HashSet<Arc<str>>>>,
+  pub(crate) js_def_cache: ContextMutex<Option<DefCodeCache>>,
+  pub(crate) js_gen_stack: ContextMutex<rpds::ListSync<CalcitStack>>,
+  pub(crate) sandbox: OnceLock<Sandbox>,
+}
+
+impl Default for RuntimeContext {
+  fn default() -> Self {
+    RuntimeContext {
+      program_code: Arc::new(RwLock::new(HashMap::new())),
+      program_compiled: Arc::new(RwLock::new(HashMap::new())),
+      program_runtime: Arc::new(RwLock::new(vec![])),
+      program_def_ids: Arc::new(RwLock::new(ProgramDefIdIndex::default())),
+      program_revisions: Arc::new(RwLock::new(HashMap::new())),
+      feature_policy: Arc::new(RwLock::new(HashMap::new())),
+      target: Arc::new(RwLock::new(None)),
+      imported_procs: Arc::new(RwLock::new(HashMap::new())),
+      imported_proc_descriptors: Arc::new(RwLock::new(HashMap::new())),
+      warned_registered_procs: Arc::new(RwLock::new(HashSet::new())),
+      project_namespaces: Arc::new(RwLock::new(HashSet::new())),
+      refs: Arc::new(Mutex::new(HashMap::new())),
+      ns_symbols: Arc::new(Mutex::new(HashMap::new())),
+      js_symbol_index: AtomicUsize::new(0),
+      fn_ids: AtomicUsize::new(0),
+      atom_ids: AtomicUsize::new(0),
+      trait_ids: AtomicU64::new(1),
+      running_mode: Arc::new(RwLock::new(CliRunningMode::Eval)),
+      track_stack: AtomicBool::new(true),
+      codegen_mode: AtomicBool::new(true),
+      skip_arity_check: AtomicBool::new(false),
+      warn_dyn_method: AtomicBool::new(false),
+      verbose_preprocess: AtomicBool::new(false),
+      optimize: AtomicBool::new(false),
+      js_first_compilation: AtomicBool::new(true),
+      js_previous_program: Arc::new(RwLock::new(HashMap::new())),
+      js_def_cache: Arc::new(Mutex::new(None)),
+      js_gen_stack: Arc::new(Mutex::new(rpds::List::new_sync())),
+      sandbox: OnceLock::new(),
+    }
+  }
+}
+
+static DEFAULT_CONTEXT: LazyLock<Arc<RuntimeContext>> = LazyLock::new(Arc::default);
+
+thread_local! {
+  static CURRENT_CONTEXT: RefCell<Option<Arc<RuntimeContext>>> = const { RefCell::new(None) };
+}
+
+/// context entered by the calling thread, or the process default context
+pub fn current() -> Arc<RuntimeContext> {
+  CURRENT_CONTEXT
+    .with(|cell| cell.borrow().clone())
+    .unwrap_or_else(|| DEFAULT_CONTEXT.clone())
+}
+
+fn current_ptr() -> *const RuntimeContext {
+  CURRENT_CONTEXT
+    .with(|cell| cell.borrow().as_ref().map(Arc::as_ptr))
+    .unwrap_or_else(|| Arc::as_ptr(&DEFAULT_CONTEXT))
+}
+
+impl RuntimeContext {
+  /// allocate an empty context, it is dropped along with its last handle
+  pub fn new() -> Arc<RuntimeContext> {
+    Arc::default()
+  }
+
+  /// make `self` the current context of this thread until the guard is dropped,
+  /// the thread holds a handle of the context meanwhile
+  pub fn enter(self: &Arc<Self>) -> EnteredContext {
+    sandbox::settle_heap();
+    let previous = CURRENT_CONTEXT.with(|cell| cell.replace(Some(self.clone())));
+    EnteredContext {
+      previous,
+      _not_send: PhantomData,
+    }
+  }
+
+  /// whether `self` is the current context of this thread
+  pub fn is_current(&self) -> bool {
+    std::ptr::eq(self, current_ptr())
+  }
+
+  /// drop program data, procs, refs and JS codegen caches, leaving an empty context
+  pub fn clear(&self) {
+    *self.program_code.write() = HashMap::new();
+    *self.program_compiled.write() = HashMap::new();
+    *self.program_runtime.write() = vec![];
+    *self.program_def_ids.write() = ProgramDefIdIndex::default();
+    *self.program_revisions.write() = HashMap::new();
+    *self.feature_policy.write() = HashMap::new();
+    *self.target.write() = None;
+    *self.imported_procs.write() = HashMap::new();
+    *self.imported_proc_descriptors.write() = HashMap::new();
+    *self.warned_registered_procs.write() = HashSet::new();
+    *self.project_namespaces.write() = HashSet::new();
+    *self.refs.lock() = HashMap::new();
+    *self.ns_symbols.lock() = HashMap::new();
+    *self.js_previous_program.write() = HashMap::new();
+    *self.js_def_cache.lock() = None;
+    *self.js_gen_stack.lock() = rpds::List::new_sync();
+    self.js_first_compilation.store(true, Ordering::SeqCst);
+  }
+}
+
+/// restores the previously entered context of this thread on drop
+#[must_use = "the context is left when the guard is dropped"]
+pub struct EnteredContext {
+  previous: Option<Arc<RuntimeContext>>,
+  /// the guard restores a thread-local, it has to be dropped on the thread that entered
+  _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for EnteredContext {
+  fn drop(&mut self) {
+    sandbox::settle_heap();
+    let previous = self.previous.take();
+    CURRENT_CONTEXT.with(|cell| *cell.borrow_mut() = previous);
+  }
+}
+
+/// Named slot of the current [RuntimeContext]. Accessors resolve the context entered by
+/// the calling thread, references keep that context alive and lock guards the locked field.
+pub struct ContextSlot<T: 'static> {
+  pick: fn(&RuntimeContext) -> &T,
+}
+
+impl<T> ContextSlot<T> {
+  pub(crate) const fn new(pick: fn(&RuntimeContext) -> &T) -> Self {
+    ContextSlot { pick }
+  }
+
+  /// the field in the current context
+  pub fn current(&self) -> SlotRef<T> {
+    SlotRef {
+      context: current(),
+      pick: self.pick,
+    }
+  }
+
+  /// call `f` with the field in the current context
+  pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+    f((self.pick)(&current()))
+  }
+}
+
+// parking_lot locks are not poisoned, the results are always `Ok` and keep call sites
+// written against `std::sync` locks unchanged
+impl<T> ContextSlot<ContextRwLock<T>> {
+  pub fn read(&self) -> LockResult<ArcRwLockReadGuard<RawRwLock, T>> {
+    Ok(self.with(RwLock::read_arc))
+  }
+
+  pub fn write(&self) -> LockResult<ArcRwLockWriteGuard<RawRwLock, T>> {
+    Ok(self.with(RwLock::write_arc))
+  }
+}
+
+impl<T> ContextSlot<ContextMutex<T>> {
+  pub fn lock(&self) -> LockResult<ArcMutexGuard<RawMutex, T>> {
+    Ok(self.with(Mutex::lock_arc))
+  }
+}
+
+impl ContextSlot<AtomicBool> {
+  pub fn load(&self, order: Ordering) -> bool {
+    self.with(|flag| flag.load(order))
+  }
+
+  pub fn store(&self, value: bool, order: Ordering) {
+    self.with(|flag| flag.store(value, order))
+  }
+}
+
+impl ContextSlot<AtomicUsize> {
+  pub fn load(&self, order: Ordering) -> usize {
+    self.with(|counter| counter.load(order))
+  }
+
+  pub fn store(&self, value: usize, order: Ordering) {
+    self.with(|counter| counter.store(value, order))
+  }
+
+  pub fn fetch_add(&self, value: usize, order: Ordering) -> usize {
+    self.with(|counter| counter.fetch_add(value, order))
+  }
+
+  pub fn swap(&self, value: usize, order: Ordering) -> usize {
+    self.with(|counter| counter.swap(value, order))
+  }
+}
+
+impl ContextSlot<AtomicU64> {
+  pub fn fetch_add(&self, value: u64, order: Ordering) -> u64 {
+    self.with(|counter| counter.fetch_add(value, order))
+  }
+}
+
+/// field of a context, holding a handle of the context
+pub struct SlotRef<T: 'static> {
+  context: Arc<RuntimeContext>,
+  pick: fn(&RuntimeContext) -> &T,
+}
+
+impl<T> Deref for SlotRef<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    (self.pick)(&self.context)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn entered_contexts_nest_and_restore() {
+    let outer = RuntimeContext::new();
+    let inner = RuntimeContext::new();
+    assert!(Arc::ptr_eq(&current(), &DEFAULT_CONTEXT));
+    {
+      let _outer = outer.enter();
+      assert!(outer.is_current());
+      {
+        let _inner = inner.enter();
+        assert!(inner.is_current());
+      }
+      assert!(outer.is_current());
+    }
+    assert!(!outer.is_current());
+    assert_eq!(Arc::strong_count(&outer), 1, "leaving releases the handle of the thread");
+
+    let handle = std::thread::spawn(move || {
+      let _entered = inner.enter();
+      inner.imported_procs.read().len()
+    });
+    assert_eq!(handle.join().expect("join"), 0);
+  }
+
+  #[test]
+  fn slot_guards_keep_their_context_alive() {
+    let guard = {
+      let ctx = RuntimeContext::new();
+      let _entered = ctx.enter();
+      let file = crate::program::ProgramFileData {
+        import_map: HashMap::new(),
+        defs: HashMap::new(),
+      };
+      crate::program::PROGRAM_CODE_DATA
+        .write()
+        .expect("write program")
+        .insert("app.main".into(), file);
+      crate::program::PROGRAM_CODE_DATA.read().expect("read program")
+    };
+    assert!(guard.contains_key("app.main"));
+    assert!(
+      !crate::program::PROGRAM_CODE_DATA
+        .read()
+        .expect("read program")
+        .contains_key("app.main")
+    );
+  }
+}
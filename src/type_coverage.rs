@@ -260,6 +260,11 @@ fn count_annotation_positions(annotation: &CalcitTypeAnnotation) -> (usize, usiz
       add(key);
       add(value);
     }
+    CalcitTypeAnnotation::MapShape(shape) => {
+      for ty in shape.all_value_types() {
+        add(ty);
+      }
+    }
     CalcitTypeAnnotation::Fn(fn_annot) => {
       for arg in &fn_annot.arg_types {
         add(arg);
@@ -510,6 +515,16 @@ fn scan_schema_dynamic_annotation(
       scan_schema_dynamic_annotation(key, &format!("{path}.key"), &key_detail, occurrences);
       scan_schema_dynamic_annotation(value, &format!("{path}.value"), &value_detail, occurrences);
     }
+    CalcitTypeAnnotation::MapShape(shape) => {
+      let field_detail = extend_schema_dynamic_detail(detail, "map-field");
+      for (key, ty) in shape.required.iter().chain(shape.optional.iter()) {
+        scan_schema_dynamic_annotation(ty, &format!("{path}.{key}"), &field_detail, occurrences);
+      }
+      if let Some(rest) = &shape.rest {
+        let rest_detail = extend_schema_dynamic_detail(detail, "map-rest");
+        scan_schema_dynamic_annotation(rest, &format!("{path}.rest"), &rest_detail, occurrences);
+      }
+    }
     CalcitTypeAnnotation::Fn(fn_annot) => {
       for (idx, arg) in fn_annot.arg_types.iter().enumerate() {
         let arg_detail = extend_schema_dynamic_detail(detail, "fn-arg");
@@ -182,22 +182,47 @@ const trimDataDefinitionEntries = (entries: CalcitValue[]): CalcitValue[] => {
   return entries.slice(start);
 };
 
-export let _$n_trait_$o__$o_new = function (name: CalcitValue, methods: CalcitValue): CalcitTrait {
-  if (arguments.length !== 2) throw new Error("&trait::new expected 2 arguments");
+export let _$n_trait_$o__$o_new = function (name: CalcitValue, methods: CalcitValue, requires?: CalcitValue): CalcitTrait {
+  if (arguments.length !== 2 && arguments.length !== 3) throw new Error("&trait::new expected 2 or 3 arguments");
   const items = list_items(methods);
   const methodNames: CalcitValue[] = [];
   const methodTypes: CalcitValue[] = [];
+  const defaults: CalcitValue[] = [];
   for (let entry of items) {
     const pair = list_items(entry);
-    if (pair.length !== 2) {
-      throw new Error(`&trait::new expects (method type) pairs, got: ${toString(entry, true)}`);
+    if (pair.length !== 2 && pair.length !== 3) {
+      throw new Error(`&trait::new expects (method type) pairs or (method type default) triples, got: ${toString(entry, true)}`);
+    }
+    const defaultImpl = pair.length === 3 ? pair[2] : null;
+    if (defaultImpl != null && typeof defaultImpl !== "function") {
+      throw new Error(`&trait::new expects default of ${toString(pair[0], true)} to be a function, got: ${toString(defaultImpl, true)}`);
     }
     methodNames.push(pair[0]);
     methodTypes.push(pair[1]);
+    defaults.push(defaultImpl);
+  }
+  const requiredTraits = requires == null ? [] : list_items(requires);
+  for (let item of requiredTraits) {
+    if (!(item instanceof CalcitTrait)) {
+      throw new Error(`&trait::new expects required traits, got: ${toString(item, true)}`);
+    }
   }
-  return new CalcitTrait(name, methodNames, methodTypes);
+  return new CalcitTrait(name, methodNames, methodTypes, defaults, requiredTraits as CalcitTrait[]);
 };
 
+/** every trait impl on a type needs impls of the traits its origin requires */
+function check_required_traits(impls: CalcitImpl[], procName: string): void {
+  for (let impl of impls) {
+    const origin = impl?.origin;
+    if (origin == null) continue;
+    const missing = origin.requires.filter((required) => !impls.some((candidate) => candidate?.origin === required));
+    if (missing.length > 0) {
+      const names = missing.map((required) => required.name.toString()).join(" ");
+      throw new Error(`${procName}: trait ${origin.name.toString()} requires impls of ${names}, attach them together or before it`);
+    }
+  }
+}
+
 export let _$n_assert_traits = function (value: CalcitValue, traitDef: CalcitValue): CalcitValue {
   if (arguments.length !== 2) throw new Error("&assert-traits expected 2 arguments");
   if (!(traitDef instanceof CalcitTrait)) {
@@ -226,7 +251,7 @@ export let _$n_assert_traits = function (value: CalcitValue, traitDef: CalcitVal
       }`
     );
   }
-  const missing = traitDef.methods.filter((method) => selected.getOrNil(method) == null);
+  const missing = traitDef.methods.filter((method) => selected.getOrNil(method) == null && traitDef.getDefault(method.value) == null);
   if (missing.length > 0) {
     throw new Error(
       `assert-traits failed: impl ${selected.name.toString()} for trait ${traitDef.name.toString()} is incomplete. Missing: ${missing.join(
@@ -333,7 +358,9 @@ export let _$n_impl_$o__$o_new = (name: CalcitValue, ...pairs: CalcitValue[]): C
   const fields = entries.map((entry) => entry.tag);
   const values = entries.map((entry) => entry.value);
   if (origin != null) {
-    const missing = origin.methods.filter((method) => !fields.some((field) => field.value === method.value));
+    const missing = origin.methods.filter(
+      (method) => !fields.some((field) => field.value === method.value) && origin.getDefault(method.value) == null
+    );
     const unexpected = fields.filter((field) => !origin.methods.some((method) => method.value === field.value));
     if (missing.length > 0 || unexpected.length > 0) {
       const details: string[] = [];
@@ -427,6 +454,7 @@ export let defatom = (path: string, x: CalcitValue): CalcitValue => {
 };
 
 export { atom } from "./js-ref.mjs";
+export type { CalcitRef } from "./js-ref.mjs";
 
 export let peekDefatom = (path: string): CalcitRef => {
   return refsRegistry.get(path);
@@ -622,7 +650,9 @@ export let _$n_enum_$o_impl_traits = function (x: CalcitValue, ...traits: Calcit
       new CalcitStructValue(newTag("_"), [tagName], [anyTypes], new CalcitStructDef(newTag("_"), [tagName], [anyTypes]))
     );
   }
-  return new CalcitEnumValue(x.tag, x.extra, proto.withImpls(impls));
+  const nextProto = proto.withImpls(impls);
+  check_required_traits(nextProto.impls, "&enum:impl-traits");
+  return new CalcitEnumValue(x.tag, x.extra, nextProto);
 };
 
 export let _$n_enum_$o_definition = function (x: CalcitEnumValue) {
@@ -762,6 +792,7 @@ export let _$n_struct_$o_impl_traits = function (xs: CalcitValue, ...traits: Cal
   if (!(xs instanceof CalcitStructValue)) throw new Error("&struct:impl-traits expected a struct value");
   const impls = traits.map((trait) => coerce_impl(trait, "&struct:impl-traits"));
   const nextStruct = new CalcitStructDef(xs.name, xs.fields, xs.structRef.fieldTypes, xs.structRef.impls.concat(impls));
+  check_required_traits(nextStruct.impls, "&struct:impl-traits");
   return new CalcitStructValue(xs.name, xs.fields, xs.values, nextStruct);
 };
 
@@ -770,14 +801,18 @@ export let _$n_struct_def_$o_impl_traits = function (xs: CalcitValue, ...traits:
   if (!(xs instanceof CalcitStructDef)) throw new Error("&struct-def:impl-traits expected a struct definition");
   const addedImpls = traits.map((trait) => coerce_impl(trait, "&struct-def:impl-traits"));
   const baseImpls = xs.impls ?? [];
-  return new CalcitStructDef(xs.name, xs.fields, xs.fieldTypes, baseImpls.concat(addedImpls));
+  const nextImpls = baseImpls.concat(addedImpls);
+  check_required_traits(nextImpls, "&struct-def:impl-traits");
+  return new CalcitStructDef(xs.name, xs.fields, xs.fieldTypes, nextImpls);
 };
 
 export let _$n_enum_def_$o_impl_traits = function (xs: CalcitValue, ...traits: CalcitValue[]) {
   if (traits.length < 1) throw new Error("&enum-def:impl-traits takes 2+ arguments");
   const addedImpls = traits.map((trait) => coerce_impl(trait, "&enum-def:impl-traits"));
   if (xs instanceof CalcitEnumDef) {
-    return xs.withImpls(addedImpls);
+    const next = xs.withImpls(addedImpls);
+    check_required_traits(next.impls, "&enum-def:impl-traits");
+    return next;
   }
   throw new Error("&enum-def:impl-traits expected an enum definition");
 };
@@ -2345,6 +2380,16 @@ function lookup_impls(obj: CalcitValue): [CalcitImpl[], string] {
   return [impls, tag];
 }
 
+function find_trait_default(impls: CalcitImpl[], name: string, reverse: boolean): CalcitValue {
+  let idx = reverse ? impls.length - 1 : 0;
+  while (reverse ? idx >= 0 : idx < impls.length) {
+    const defaultImpl = impls[idx]?.origin?.getDefault(name);
+    if (defaultImpl != null) return defaultImpl;
+    idx += reverse ? -1 : 1;
+  }
+  return null;
+}
+
 export function invoke_method(p: string, obj: CalcitValue, ...args: CalcitValue[]) {
   let pair = lookup_impls(obj);
   if (pair == null) {
@@ -2369,6 +2414,11 @@ export function invoke_method(p: string, obj: CalcitValue, ...args: CalcitValue[
     }
     idx += reverse ? -1 : 1;
   }
+  // explicit impl entries always win, trait defaults only fill the gaps
+  const defaultImpl = find_trait_default(impls, p, reverse);
+  if (defaultImpl != null) {
+    return (defaultImpl as Function)(obj, ...args);
+  }
   throw new Error(`No method '.${p}' for '${tag}' object '${obj}'.`);
 }
 
@@ -2402,6 +2452,22 @@ export function _$n_methods_of(obj: CalcitValue): CalcitSliceList {
     }
     idx += reverse ? -1 : 1;
   }
+  // trait defaults come after every explicit entry, matching dispatch
+  idx = reverse ? impls.length - 1 : 0;
+  while (reverse ? idx >= 0 : idx < impls.length) {
+    const origin = impls[idx]?.origin;
+    if (origin != null) {
+      for (let k = 0; k < origin.methods.length; k++) {
+        let rawName = origin.methods[k].value;
+        let name = "." + rawName;
+        if (origin.defaults[k] != null && !seen.has(name)) {
+          seen.add(name);
+          ys.push(invoke_method_closure(rawName));
+        }
+      }
+    }
+    idx += reverse ? -1 : 1;
+  }
   return new CalcitSliceList(ys);
 }
 
@@ -2485,7 +2551,7 @@ export function _$n_trait_call(traitDef: CalcitValue, method: CalcitValue, obj:
   while (reverse ? idx >= 0 : idx < impls.length) {
     const impl = impls[idx];
     if (impl != null && impl.origin === traitDef) {
-      const fn = impl.getOrNil(methodName);
+      const fn = impl.getOrNil(methodName) ?? traitDef.getDefault(methodName);
       if (fn != null) {
         if (typeof fn !== "function") {
           throw new Error(`&trait-call: method :${methodName} for trait ${traitDef.name.toString()} is not a function: ${toString(fn, true)}`);
@@ -5,11 +5,22 @@ export class CalcitTrait {
   name: CalcitTag;
   methods: CalcitTag[];
   methodTypes: CalcitValue[];
+  /** default method bodies, parallel to `methods`, `null` when absent */
+  defaults: CalcitValue[];
+  /** traits that every implementor of this trait has to implement as well */
+  requires: CalcitTrait[];
 
-  constructor(name: CalcitValue, methods: CalcitValue[], methodTypes: CalcitValue[]) {
+  constructor(name: CalcitValue, methods: CalcitValue[], methodTypes: CalcitValue[], defaults?: CalcitValue[], requires?: CalcitTrait[]) {
     this.name = castTag(name);
     this.methods = methods.map(castTag);
     this.methodTypes = methodTypes;
+    this.defaults = defaults ?? methods.map(() => null);
+    this.requires = requires ?? [];
+  }
+
+  getDefault(name: string): CalcitValue {
+    const idx = this.methods.findIndex((method) => method.value === name);
+    return idx >= 0 ? this.defaults[idx] : null;
   }
 
   toString(disableJsDataWarning: boolean = false): string {